@@ -1,5 +1,6 @@
-use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
 use include_dir::{include_dir, Dir};
+use std::hint::black_box;
 
 use hanekawa_bencode;
 
@@ -17,6 +18,23 @@ pub fn parse_torrents(c: &mut Criterion) {
                 hanekawa_bencode::parse(black_box(sample.contents())).unwrap();
             })
         });
+
+        // What borrowing saves, mostly the copy of `pieces`.
+        group.bench_function("parse(borrowed)", |b| {
+            b.iter(|| {
+                hanekawa_bencode::parse(black_box(sample.contents()))
+                    .unwrap()
+                    .into_value()
+            })
+        });
+        group.bench_function("parse(owned)", |b| {
+            b.iter(|| {
+                hanekawa_bencode::parse(black_box(sample.contents()))
+                    .unwrap()
+                    .into_value()
+                    .into_owned()
+            })
+        });
     }
 }
 
@@ -1,11 +1,12 @@
 #![no_main]
 use libfuzzer_sys::fuzz_target;
 
-use hanekawa_bencode::{Value, parse, encode};
+use hanekawa_bencode::{Value, Limits, parse_with_limits, encode};
 
 fuzz_target!(|input: Value<&[u8]>| {
     let expected = Ok(input.clone().into_elements());
     let encoded = encode(&input);
-    let parsed_val = parse(&encoded);
+    let limits = Limits { max_depth: usize::MAX, ..Limits::default() };
+    let parsed_val = parse_with_limits(&encoded, limits);
     assert_eq!(expected, parsed_val);
 });
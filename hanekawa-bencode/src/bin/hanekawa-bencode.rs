@@ -0,0 +1,85 @@
+use hanekawa_bencode::{encode, from_json, parse, parse_raw_dict, to_json, Value};
+
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::io::{Read, Write};
+
+const USAGE: &str = "usage: hanekawa-bencode <inspect|encode|hash> [file]";
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let command = args.next();
+    let result = read(args.next()).and_then(|input| match command.as_deref() {
+        Some("inspect") => inspect(&input),
+        Some("encode") => encode_json(&input),
+        Some("hash") => hash(&input),
+        _ => Err(USAGE.to_string()),
+    });
+
+    if let Err(e) = result {
+        eprintln!("{e}");
+        std::process::exit(1);
+    }
+}
+
+// From stdin when there is no file, or it is `-`.
+fn read(path: Option<String>) -> Result<Vec<u8>, String> {
+    match path.as_deref() {
+        None | Some("-") => {
+            let mut input = vec![];
+            std::io::stdin()
+                .read_to_end(&mut input)
+                .map_err(|e| format!("cannot read stdin: {e}"))?;
+            Ok(input)
+        }
+        Some(path) => std::fs::read(path).map_err(|e| format!("cannot read {path}: {e}")),
+    }
+}
+
+fn write(output: &[u8]) -> Result<(), String> {
+    std::io::stdout()
+        .write_all(output)
+        .map_err(|e| format!("cannot write stdout: {e}"))
+}
+
+fn inspect(input: &[u8]) -> Result<(), String> {
+    let value = parse(input).map_err(|e| e.to_string())?.into_value();
+    let mut json = serde_json::to_string_pretty(&to_json(&value)).unwrap();
+    json.push('\n');
+    write(json.as_bytes())
+}
+
+fn encode_json(input: &[u8]) -> Result<(), String> {
+    let json = serde_json::from_slice(input).map_err(|e| format!("not JSON: {e}"))?;
+    write(&encode(&from_json(&json).map_err(|e| e.to_string())?))
+}
+
+// The v1 info hash, then the v2 one, of whichever the torrent is. Hashed as
+// the info dictionary is in the file, as clients do. This is what metainfo
+// in hanekawa-common does, which cannot be used from here as it uses this.
+fn hash(input: &[u8]) -> Result<(), String> {
+    let entries = parse_raw_dict(input).map_err(|e| e.to_string())?;
+    let raw_info = entries
+        .iter()
+        .find(|(k, _)| *k == b"info")
+        .map(|(_, v)| *v)
+        .ok_or("no info dictionary")?;
+    let info = parse(raw_info).map_err(|e| e.to_string())?.into_value();
+
+    let v1 = info.get("pieces").and_then(Value::as_bytes).is_some();
+    let v2 = info.get("meta version").and_then(Value::as_int) == Some(2);
+    if !v1 && !v2 {
+        return Err("neither a v1 nor a v2 torrent".to_string());
+    }
+
+    let mut output = String::new();
+    if v1 {
+        output.push_str(&hex::encode(Sha1::digest(raw_info)));
+        output.push('\n');
+    }
+    if v2 {
+        output.push_str(&hex::encode(&Sha256::digest(raw_info)[..20]));
+        output.push('\n');
+    }
+    write(output.as_bytes())
+}
@@ -0,0 +1,459 @@
+use crate::{Element, Elements};
+
+use super::Parser;
+
+use serde::de::{self, IntoDeserializer};
+
+struct Deserializer<'de> {
+    elements: std::vec::IntoIter<Element<&'de [u8]>>,
+}
+
+impl<'de> Deserializer<'de> {
+    fn new(elements: Elements<&'de [u8]>) -> Self {
+        Self {
+            elements: elements.into_parts().into_iter(),
+        }
+    }
+
+    fn next(&mut self) -> Result<Element<&'de [u8]>, Error> {
+        self.elements.next().ok_or(Error::UnexpectedEnd)
+    }
+
+    fn next_bytes(&mut self) -> Result<&'de [u8], Error> {
+        match self.next()? {
+            Element::Bytes(bs) => Ok(bs),
+            _ => Err(Error::ExpectedBytes),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Syntax(crate::Error),
+    UnexpectedEnd,
+    ExpectedBytes,
+    UnsupportedType(String),
+    TrailingElements,
+    Other(String),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Syntax(e) => f.write_fmt(format_args!("invalid bencode: {}", e)),
+            Self::UnexpectedEnd => f.write_str("unexpected end of input"),
+            Self::ExpectedBytes => f.write_str("expected a string"),
+            Self::UnsupportedType(t) => f.write_fmt(format_args!("unsupported type: {}", t)),
+            Self::TrailingElements => f.write_str("container has more elements than expected"),
+            Self::Other(s) => f.write_fmt(format_args!("other error: {}", s)),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl de::Error for Error {
+    fn custom<T>(msg: T) -> Self
+    where
+        T: std::fmt::Display,
+    {
+        Self::Other(msg.to_string())
+    }
+}
+
+fn unsupported_type<T>(which: &str) -> Result<T, Error> {
+    Err(Error::UnsupportedType(which.to_string()))
+}
+
+impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        match self.next()? {
+            Element::Int(i) => visitor.visit_i64(i),
+            Element::Bytes(bs) => visitor.visit_borrowed_bytes(bs),
+            Element::ListBegin(ct) => {
+                let mut access = Access { de: self, left: ct };
+                let value = visitor.visit_seq(&mut access)?;
+                access.finish(value)
+            }
+            Element::DictBegin(ct) => {
+                let mut access = Access { de: self, left: ct };
+                let value = visitor.visit_map(&mut access)?;
+                access.finish(value)
+            }
+        }
+    }
+
+    // Bencode has no booleans, but 0 and 1 are the usual stand-ins.
+    fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        match self.next()? {
+            Element::Int(0) => visitor.visit_bool(false),
+            Element::Int(1) => visitor.visit_bool(true),
+            _ => unsupported_type("bool"),
+        }
+    }
+
+    fn deserialize_str<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        let bs = self.next_bytes()?;
+        match std::str::from_utf8(bs) {
+            Ok(s) => visitor.visit_borrowed_str(s),
+            Err(_) => visitor.visit_borrowed_bytes(bs),
+        }
+    }
+
+    fn deserialize_string<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_char<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_identifier<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_borrowed_bytes(self.next_bytes()?)
+    }
+
+    fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.deserialize_bytes(visitor)
+    }
+
+    // There is no null, so a present value is always `Some`.
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_unit<V>(self, _visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        unsupported_type("unit")
+    }
+
+    fn deserialize_unit_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    // Unit variants are plain strings, others are single-key dictionaries.
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        match self.next()? {
+            Element::Bytes(bs) => match std::str::from_utf8(bs) {
+                Ok(s) => visitor.visit_enum(s.into_deserializer()),
+                Err(_) => Err(Error::Other("enum variant is not utf-8".to_string())),
+            },
+            Element::DictBegin(1) => visitor.visit_enum(Enum { de: self }),
+            _ => unsupported_type("enum"),
+        }
+    }
+
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        self.deserialize_any(visitor)
+    }
+
+    serde::forward_to_deserialize_any! {
+        i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64
+        seq tuple tuple_struct map struct
+    }
+}
+
+struct Access<'a, 'de> {
+    de: &'a mut Deserializer<'de>,
+    left: usize,
+}
+
+impl<'a, 'de> Access<'a, 'de> {
+    // A visitor that stops early would leave elements behind and misalign
+    // everything after them, so reject it instead.
+    fn finish<T>(self, value: T) -> Result<T, Error> {
+        if self.left == 0 {
+            Ok(value)
+        } else {
+            Err(Error::TrailingElements)
+        }
+    }
+}
+
+impl<'a, 'de> de::SeqAccess<'de> for Access<'a, 'de> {
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error>
+    where
+        T: de::DeserializeSeed<'de>,
+    {
+        if self.left == 0 {
+            return Ok(None);
+        }
+        self.left -= 1;
+
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.left)
+    }
+}
+
+impl<'a, 'de> de::MapAccess<'de> for Access<'a, 'de> {
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error>
+    where
+        K: de::DeserializeSeed<'de>,
+    {
+        if self.left == 0 {
+            return Ok(None);
+        }
+        self.left -= 1;
+
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::DeserializeSeed<'de>,
+    {
+        seed.deserialize(&mut *self.de)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.left)
+    }
+}
+
+struct Enum<'a, 'de> {
+    de: &'a mut Deserializer<'de>,
+}
+
+impl<'a, 'de> de::EnumAccess<'de> for Enum<'a, 'de> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self::Variant), Self::Error>
+    where
+        V: de::DeserializeSeed<'de>,
+    {
+        let variant = seed.deserialize(&mut *self.de)?;
+        Ok((variant, self))
+    }
+}
+
+impl<'a, 'de> de::VariantAccess<'de> for Enum<'a, 'de> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        unsupported_type("unit variant in a dictionary")
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, Self::Error>
+    where
+        T: de::DeserializeSeed<'de>,
+    {
+        seed.deserialize(self.de)
+    }
+
+    fn tuple_variant<V>(self, _len: usize, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        de::Deserializer::deserialize_seq(self.de, visitor)
+    }
+
+    fn struct_variant<V>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: de::Visitor<'de>,
+    {
+        de::Deserializer::deserialize_map(self.de, visitor)
+    }
+}
+
+pub fn from_bytes<'de, T: de::Deserialize<'de>>(input: &'de [u8]) -> Result<T, Error> {
+    let elements = Parser::new(input).parse().map_err(Error::Syntax)?;
+    let mut deserializer = Deserializer::new(elements);
+
+    T::deserialize(&mut deserializer)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use include_dir::{include_dir, Dir};
+    use serde::Deserialize;
+    use std::collections::HashMap;
+
+    static TORRENT_SAMPLES_DIR: Dir<'_> = include_dir!("$CARGO_MANIFEST_DIR/benches/samples/");
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Announce<'a> {
+        interval: u32,
+        #[serde(rename = "min interval")]
+        min_interval: Option<u32>,
+        #[serde(borrow, with = "serde_bytes")]
+        peers: &'a [u8],
+        warning: Option<String>,
+    }
+
+    #[test]
+    fn deserializes_structs() {
+        let announce: Announce =
+            from_bytes(b"d8:intervali1800e12:min intervali900e5:peers6:\x7f\x00\x00\x01\x1a\xe1e")
+                .unwrap();
+
+        assert_eq!(
+            Announce {
+                interval: 1800,
+                min_interval: Some(900),
+                peers: &[127, 0, 0, 1, 0x1a, 0xe1],
+                warning: None,
+            },
+            announce
+        );
+    }
+
+    #[test]
+    fn skips_unknown_keys() {
+        let announce: Announce =
+            from_bytes(b"d5:extrald1:ai1eee8:intervali60e5:peers0:7:warning2:hie").unwrap();
+
+        assert_eq!(60, announce.interval);
+        assert_eq!(Some("hi".to_string()), announce.warning);
+    }
+
+    #[test]
+    fn deserializes_collections() {
+        let map: HashMap<String, Vec<i64>> = from_bytes(b"d1:ali1ei2ee1:blee").unwrap();
+
+        assert_eq!(vec![1, 2], map["a"]);
+        assert!(map["b"].is_empty());
+    }
+
+    #[test]
+    fn deserializes_enums() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        #[serde(rename_all = "lowercase")]
+        enum Event {
+            Started,
+            Stopped(u32),
+        }
+
+        assert_eq!(Event::Started, from_bytes(b"7:started").unwrap());
+        assert_eq!(Event::Stopped(3), from_bytes(b"d7:stoppedi3ee").unwrap());
+    }
+
+    #[test]
+    fn rejects_out_of_range_ints() {
+        assert!(from_bytes::<u8>(b"i256e").is_err());
+        assert!(from_bytes::<u32>(b"i-1e").is_err());
+        assert!(from_bytes::<u32>(b"i4294967296e").is_err());
+        assert_eq!(
+            u32::MAX as u64 + 1,
+            from_bytes::<u64>(b"i4294967296e").unwrap()
+        );
+    }
+
+    #[test]
+    fn rejects_short_tuples() {
+        assert!(matches!(
+            from_bytes::<(i64, i64)>(b"li1ei2ei3ee"),
+            Err(Error::TrailingElements)
+        ));
+    }
+
+    #[test]
+    fn rejects_invalid_bencode() {
+        assert!(matches!(
+            from_bytes::<Vec<i64>>(b"li1e"),
+            Err(Error::Syntax(_))
+        ));
+    }
+
+    #[derive(Deserialize)]
+    struct Torrent<'a> {
+        #[serde(borrow)]
+        info: Info<'a>,
+    }
+
+    #[derive(Deserialize)]
+    struct Info<'a> {
+        name: String,
+        #[serde(rename = "piece length")]
+        piece_length: u64,
+        #[serde(borrow, with = "serde_bytes")]
+        pieces: &'a [u8],
+    }
+
+    #[test]
+    fn deserializes_torrents() {
+        for sample in TORRENT_SAMPLES_DIR.files() {
+            let torrent: Torrent = from_bytes(sample.contents()).unwrap();
+
+            assert!(!torrent.info.name.is_empty());
+            assert!(torrent.info.piece_length > 0);
+            assert_eq!(0, torrent.info.pieces.len() % 20);
+        }
+    }
+}
@@ -1,19 +1,73 @@
+pub mod de;
+
 use super::{Element, Elements, Error};
 
-pub fn parse(input: &[u8]) -> Result<Elements<&[u8]>, ()> {
-    let parser = Parser::new(input);
-    parser.parse().map_err(|_| ())
+use std::cmp::Ordering;
+
+// How much input is parsed before giving up, so that what comes from
+// untrusted peers can be held to less than a local file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    pub max_depth: usize,
+    pub max_string_len: usize,
+    pub max_total_len: usize,
+    // Dict keys must be unique and in order, as BEP 3 has them, rather than
+    // taken as they come.
+    pub strict: bool,
+}
+
+impl Default for Limits {
+    // Far deeper than any torrent or request nests, and shallow enough that
+    // parsing cannot run out of stack.
+    fn default() -> Self {
+        Self {
+            max_depth: 64,
+            max_string_len: usize::MAX,
+            max_total_len: usize::MAX,
+            strict: false,
+        }
+    }
+}
+
+pub fn parse(input: &[u8]) -> Result<Elements<&[u8]>, Error> {
+    parse_with_limits(input, Limits::default())
+}
+
+pub fn parse_with_limits(input: &[u8], limits: Limits) -> Result<Elements<&[u8]>, Error> {
+    let parser = Parser::new(input).with_limits(limits);
+    parser.parse()
+}
+
+// Keys, and values as they were written.
+pub type RawEntries<'a> = Vec<(&'a [u8], &'a [u8])>;
+
+pub fn parse_raw_dict(input: &[u8]) -> Result<RawEntries<'_>, Error> {
+    Parser::new(input).parse_raw_dict()
 }
 
 pub struct Parser<'a> {
     input: &'a [u8],
+    len: usize,
+    depth: usize,
+    limits: Limits,
     elements: Vec<Element<&'a [u8]>>,
 }
 
 impl<'a> Parser<'a> {
     pub fn new(input: &'a [u8]) -> Self {
         let elements = Vec::with_capacity(10);
-        Self { input, elements }
+        Self {
+            input,
+            len: input.len(),
+            depth: 0,
+            limits: Limits::default(),
+            elements,
+        }
+    }
+
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
     }
 
     #[inline(always)]
@@ -21,6 +75,11 @@ impl<'a> Parser<'a> {
         self.input.len() == 0
     }
 
+    #[inline(always)]
+    fn offset(&self) -> usize {
+        self.len - self.input.len()
+    }
+
     #[inline(always)]
     fn peek(&self) -> Option<u8> {
         self.input.get(0).copied()
@@ -35,84 +94,105 @@ impl<'a> Parser<'a> {
                 self.input = tail;
                 Ok(head)
             }
-            _ => Err(Error::ExpectedFar(b)),
+            _ => Err(Error::UnexpectedEof { offset: self.len }),
         }
     }
 
     #[inline(always)]
     fn take_n(&mut self, n: usize) -> Result<&'a [u8], Error> {
-        let (head, tail) = self.input.split_at(n);
-        if head.len() == n {
-            self.input = tail;
-            Ok(head)
-        } else {
-            Err(Error::UnexpectedEnd(format!("take_n: {}", n)))
-        }
-    }
-
-    #[inline(always)]
-    fn bump(&mut self) -> Result<u8, Error> {
-        match self.input.split_first() {
-            Some((t, tail)) => {
+        match self.input.split_at_checked(n) {
+            Some((head, tail)) => {
                 self.input = tail;
-                Ok(*t)
+                Ok(head)
             }
-            _ => Err(Error::UnexpectedEnd("bump".to_string())),
+            _ => Err(Error::UnexpectedEof { offset: self.len }),
         }
     }
 
     #[inline(always)]
     fn bump_assert(&mut self) {
-        let result = self.bump();
-        debug_assert!(result.is_ok());
+        debug_assert!(!self.is_done());
+        self.input = &self.input[1..];
     }
 
     #[inline(always)]
-    fn parse_raw_int<T: lexical::FromLexical>(input: &[u8]) -> Result<T, Error> {
-        lexical::parse(input).map_err(|_| Error::InvalidInt(input.to_vec()))
+    fn parse_raw_int<T: lexical::FromLexical>(input: &[u8]) -> Option<T> {
+        lexical::parse(input).ok()
     }
 
     fn parse_string(&mut self) -> Result<(), Error> {
-        let len = self.take_until(b':')?;
-        self.bump_assert();
-        let len_num = Self::parse_raw_int(len)?;
-        let str = self.take_n(len_num)?;
+        let str = self.take_string()?;
 
         self.elements.push(Element::Bytes(str));
 
         Ok(())
     }
 
-    fn parse_dict(&mut self) -> Result<(), Error> {
+    fn take_string(&mut self) -> Result<&'a [u8], Error> {
+        let offset = self.offset();
+        let len = self.take_until(b':')?;
         self.bump_assert();
+        let len_num: usize =
+            Self::parse_raw_int(len).ok_or(Error::InvalidStringLength { offset })?;
+        if len_num > self.limits.max_string_len {
+            Err(Error::StringTooLong { offset })?;
+        }
+        self.take_n(len_num)
+    }
+
+    fn parse_dict(&mut self) -> Result<(), Error> {
+        self.begin_nested()?;
 
         let header_idx = self.elements.len();
         self.elements.push(Element::DictBegin(0));
 
         let mut ct = 0;
+        let mut last = None;
 
-        while self.peek() != Some(b'e') {
-            self.parse_string()?;
+        while let Some(key) = self.dict_key(&mut last)? {
+            self.elements.push(Element::Bytes(key));
             self.parse_value()?;
             ct += 1;
         }
 
         self.bump_assert();
+        self.depth -= 1;
 
         self.elements[header_idx] = Element::DictBegin(ct);
 
         Ok(())
     }
 
+    // None at the end of the dict.
+    fn dict_key(&mut self, last: &mut Option<&'a [u8]>) -> Result<Option<&'a [u8]>, Error> {
+        let offset = self.offset();
+        let key = match self.peek() {
+            Some(b'e') => return Ok(None),
+            Some(b'0'..=b'9') => self.take_string()?,
+            _ => Err(self.unexpected())?,
+        };
+        if self.limits.strict {
+            match last.map(|last| key.cmp(last)) {
+                Some(Ordering::Equal) => Err(Error::DuplicateKey { offset })?,
+                Some(Ordering::Less) => Err(Error::UnsortedKey { offset })?,
+                _ => *last = Some(key),
+            }
+        }
+
+        Ok(Some(key))
+    }
+
     fn parse_int(&mut self) -> Result<(), Error> {
         self.bump_assert();
 
+        let offset = self.offset();
         let num = self.take_until(b'e')?;
         // Reject leading -0 and leading 0, but not 0 itself.
         if num.starts_with(&[b'-', b'0']) || (num.starts_with(&[b'0']) && num.len() != 1) {
-            Err(Error::InvalidInt(num.to_vec()))?;
+            Err(Error::InvalidInteger { offset })?;
         }
-        let num = lexical::parse(&num).unwrap();
+        // Out of range of an i64, or not a number at all.
+        let num = Self::parse_raw_int(num).ok_or(Error::InvalidInteger { offset })?;
 
         self.bump_assert();
 
@@ -122,7 +202,7 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_list(&mut self) -> Result<(), Error> {
-        self.bump_assert();
+        self.begin_nested()?;
 
         let header_idx = self.elements.len();
 
@@ -136,28 +216,93 @@ impl<'a> Parser<'a> {
         }
 
         self.bump_assert();
+        self.depth -= 1;
 
         self.elements[header_idx] = Element::ListBegin(ct);
 
         Ok(())
     }
 
+    fn begin_nested(&mut self) -> Result<(), Error> {
+        if self.depth >= self.limits.max_depth {
+            Err(Error::DepthLimitExceeded {
+                offset: self.offset(),
+            })?;
+        }
+        self.depth += 1;
+        self.bump_assert();
+
+        Ok(())
+    }
+
+    fn unexpected(&self) -> Error {
+        let offset = self.offset();
+        match self.peek() {
+            Some(byte) => Error::UnexpectedByte { byte, offset },
+            None => Error::UnexpectedEof { offset },
+        }
+    }
+
     fn parse_value(&mut self) -> Result<(), Error> {
         match self.peek() {
             Some(b'd') => self.parse_dict(),
             Some(b'i') => self.parse_int(),
             Some(b'l') => self.parse_list(),
             Some(b'0'..=b'9') => self.parse_string(),
-            _ => Err(Error::UnexpectedEnd("parse_value".to_string())),
+            _ => Err(self.unexpected()),
         }
     }
 
     pub fn parse(mut self) -> Result<Elements<&'a [u8]>, Error> {
+        self.check_len()?;
         self.parse_value()?;
-        if self.is_done() {
-            Ok(Elements::from_parts(self.elements))
-        } else {
-            Err(Error::Trailing)
+        self.check_done()?;
+
+        Ok(Elements::from_parts(self.elements))
+    }
+
+    // The entries of a dict, each value as the bytes it was parsed from, for
+    // what is hashed as written rather than as it would be encoded again.
+    pub fn parse_raw_dict(mut self) -> Result<RawEntries<'a>, Error> {
+        self.check_len()?;
+        if self.peek() != Some(b'd') {
+            Err(self.unexpected())?;
+        }
+        self.begin_nested()?;
+
+        let mut entries = Vec::new();
+        let mut last = None;
+
+        while let Some(key) = self.dict_key(&mut last)? {
+            let value = self.input;
+            let start = self.offset();
+            self.parse_value()?;
+            self.elements.clear();
+            entries.push((key, &value[..self.offset() - start]));
+        }
+
+        self.bump_assert();
+        self.depth -= 1;
+        self.check_done()?;
+
+        Ok(entries)
+    }
+
+    fn check_len(&self) -> Result<(), Error> {
+        match self.len > self.limits.max_total_len {
+            true => Err(Error::InputTooLong {
+                offset: self.limits.max_total_len,
+            }),
+            false => Ok(()),
+        }
+    }
+
+    fn check_done(&self) -> Result<(), Error> {
+        match self.is_done() {
+            true => Ok(()),
+            false => Err(Error::TrailingData {
+                offset: self.offset(),
+            }),
         }
     }
 }
@@ -195,6 +340,190 @@ mod test {
             parse("i-0e".as_bytes()).is_err(),
             "negative zero is invalid"
         );
+        for invalid in [
+            "i9223372036854775808e",
+            "i-9223372036854775809e",
+            "i99999999999999999999e",
+            "ie",
+            "i-e",
+            "i1x2e",
+        ] {
+            assert_eq!(
+                Err(Error::InvalidInteger { offset: 1 }),
+                parse(invalid.as_bytes()),
+                "{invalid}"
+            );
+        }
+    }
+
+    #[test]
+    fn says_where_and_why_it_stopped() {
+        for (enc, error) in [
+            ("i03e", Error::InvalidInteger { offset: 1 }),
+            ("li1ei-0ee", Error::InvalidInteger { offset: 5 }),
+            ("4:sp", Error::UnexpectedEof { offset: 4 }),
+            ("i12", Error::UnexpectedEof { offset: 3 }),
+            ("l4:spam", Error::UnexpectedEof { offset: 7 }),
+            ("", Error::UnexpectedEof { offset: 0 }),
+            (
+                "d3:fooe",
+                Error::UnexpectedByte {
+                    byte: b'e',
+                    offset: 6,
+                },
+            ),
+            (
+                "di1ei2ee",
+                Error::UnexpectedByte {
+                    byte: b'i',
+                    offset: 1,
+                },
+            ),
+            (
+                "x",
+                Error::UnexpectedByte {
+                    byte: b'x',
+                    offset: 0,
+                },
+            ),
+            ("4x:spam", Error::InvalidStringLength { offset: 0 }),
+            ("i1ei2e", Error::TrailingData { offset: 3 }),
+            ("4:spam\n", Error::TrailingData { offset: 6 }),
+        ] {
+            assert_eq!(Err(error), parse(enc.as_bytes()), "{enc:?}");
+        }
+    }
+
+    const MAX_DEPTH: usize = 64;
+
+    #[test]
+    fn limits_nesting() {
+        let nested = |depth| format!("{}{}", "l".repeat(depth), "e".repeat(depth));
+        assert!(parse(nested(MAX_DEPTH).as_bytes()).is_ok());
+        assert_eq!(
+            Err(Error::DepthLimitExceeded { offset: MAX_DEPTH }),
+            parse(nested(MAX_DEPTH + 1).as_bytes())
+        );
+        assert_eq!(
+            Err(Error::DepthLimitExceeded {
+                offset: 4 * MAX_DEPTH
+            }),
+            parse("d1:a".repeat(MAX_DEPTH + 1).as_bytes())
+        );
+
+        // What would once run out of stack.
+        assert_eq!(
+            Err(Error::DepthLimitExceeded { offset: MAX_DEPTH }),
+            parse(nested(100_000).as_bytes())
+        );
+        let limits = Limits {
+            max_depth: 2,
+            ..Limits::default()
+        };
+        assert!(parse_with_limits(b"llee", limits).is_ok());
+        assert_eq!(
+            Err(Error::DepthLimitExceeded { offset: 2 }),
+            parse_with_limits(b"llleee", limits)
+        );
+    }
+
+    #[test]
+    fn limits_lengths() {
+        // Declared, but never sent.
+        let enc = "l4:spam8388608:eggs";
+        assert_eq!(
+            Err(Error::UnexpectedEof { offset: enc.len() }),
+            parse(enc.as_bytes())
+        );
+        let limits = Limits {
+            max_string_len: 1 << 20,
+            max_total_len: 32,
+            ..Limits::default()
+        };
+        assert_eq!(
+            Err(Error::StringTooLong { offset: 7 }),
+            parse_with_limits(enc.as_bytes(), limits)
+        );
+        assert!(parse_with_limits(b"7:1048576", limits).is_ok());
+        assert_eq!(
+            Err(Error::InputTooLong { offset: 32 }),
+            parse_with_limits("4:spam".repeat(6).as_bytes(), limits)
+        );
+    }
+
+    #[test]
+    fn strictly_wants_keys_in_order() {
+        let strict = Limits {
+            strict: true,
+            ..Limits::default()
+        };
+        for (enc, error) in [
+            ("d3:fooi1e3:fooi2ee", Error::DuplicateKey { offset: 9 }),
+            ("d1:bi1e1:ai2ee", Error::UnsortedKey { offset: 7 }),
+            // A key repeated further on is out of order by then.
+            ("d1:ai1e1:bi2e1:ai3ee", Error::UnsortedKey { offset: 13 }),
+            (
+                "d1:ad1:bi1e1:ai2ee1:bi3ee",
+                Error::UnsortedKey { offset: 11 },
+            ),
+        ] {
+            assert!(parse(enc.as_bytes()).is_ok(), "{enc:?}");
+            assert_eq!(
+                Err(error),
+                parse_with_limits(enc.as_bytes(), strict),
+                "{enc:?}"
+            );
+        }
+
+        // Order is by raw bytes, and only among keys of the same dict.
+        assert!(parse_with_limits(b"d1:Ai1e1:ai2e2:aai3ee", strict).is_ok());
+        assert!(parse_with_limits(b"d1:bd1:ai1ee1:cd1:ai2eee", strict).is_ok());
+    }
+
+    #[test]
+    fn keeps_values_as_written() {
+        assert_eq!(
+            Ok(vec![
+                (&b"info"[..], &b"d1:bi1e1:ai2ee"[..]),
+                (b"announce", b"3:foo"),
+                (b"info", b"le"),
+            ]),
+            parse_raw_dict(b"d4:infod1:bi1e1:ai2ee8:announce3:foo4:infolee")
+        );
+        assert_eq!(Ok(vec![]), parse_raw_dict(b"de"));
+        assert_eq!(
+            Err(Error::UnexpectedByte {
+                byte: b'l',
+                offset: 0
+            }),
+            parse_raw_dict(b"le")
+        );
+        assert_eq!(
+            Err(Error::UnexpectedEof { offset: 14 }),
+            parse_raw_dict(b"d4:infod1:ai1e")
+        );
+        assert_eq!(
+            Err(Error::TrailingData { offset: 8 }),
+            parse_raw_dict(b"d1:ai1eei1e")
+        );
+    }
+
+    #[test]
+    fn parses_64_bit_ints() {
+        for (enc, int) in [
+            ("i4294967296e", u32::MAX as i64 + 1),
+            ("i9223372036854775807e", i64::MAX),
+            ("i-9223372036854775808e", i64::MIN),
+        ] {
+            assert_eq!(
+                Elements::from_parts(vec![Element::Int(int)]),
+                parse(enc.as_bytes()).unwrap()
+            );
+            assert_eq!(
+                enc.as_bytes(),
+                crate::encode(&crate::Value::<&[u8]>::Int(int))
+            );
+        }
     }
 
     #[test]
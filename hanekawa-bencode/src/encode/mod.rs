@@ -1,7 +1,9 @@
 pub mod ser;
+pub mod stream;
 
 use super::{Map, Value};
 use bytes::{BufMut, BytesMut};
+use std::io::{self, Write};
 
 fn encode_string<B: AsRef<[u8]> + Ord>(bytes: B, buf: &mut BytesMut) {
     use lexical::{FormattedSize, ToLexical};
@@ -16,7 +18,7 @@ fn encode_string<B: AsRef<[u8]> + Ord>(bytes: B, buf: &mut BytesMut) {
 
 fn encode_integer(i: i64, buf: &mut BytesMut) {
     use lexical::{FormattedSize, ToLexical};
-    let mut digits = [0; usize::FORMATTED_SIZE_DECIMAL];
+    let mut digits = [0; i64::FORMATTED_SIZE_DECIMAL];
 
     buf.put_u8(b'i');
     buf.put_slice(i.to_lexical(&mut digits));
@@ -73,5 +75,70 @@ pub fn encode<B: AsRef<[u8]> + Ord>(value: &Value<B>) -> Vec<u8> {
     let mut buf = BytesMut::new();
     encode_value(value, &mut buf);
 
-    buf.to_vec()
+    buf.into()
+}
+
+// Without holding more of it than an integer at a time, so strings go to `w`
+// as they are. Best given a buffered writer.
+pub fn encode_to<B: AsRef<[u8]> + Ord, W: Write>(value: &Value<B>, w: &mut W) -> io::Result<()> {
+    match value {
+        Value::Bytes(b) => write_string(b.as_ref(), w),
+        Value::Int(i) => write_integer(*i, w),
+        Value::List(vs) => {
+            w.write_all(b"l")?;
+            for v in vs {
+                encode_to(v, w)?;
+            }
+            w.write_all(b"e")
+        }
+        Value::Dict(vs) => {
+            w.write_all(b"d")?;
+            for (k, v) in vs {
+                write_string(k.as_ref(), w)?;
+                encode_to(v, w)?;
+            }
+            w.write_all(b"e")
+        }
+    }
+}
+
+fn write_string<W: Write>(bytes: &[u8], w: &mut W) -> io::Result<()> {
+    use lexical::{FormattedSize, ToLexical};
+    let mut digits = [0; usize::FORMATTED_SIZE_DECIMAL];
+
+    w.write_all(bytes.len().to_lexical(&mut digits))?;
+    w.write_all(b":")?;
+    w.write_all(bytes)
+}
+
+fn write_integer<W: Write>(i: i64, w: &mut W) -> io::Result<()> {
+    use lexical::{FormattedSize, ToLexical};
+    let mut digits = [0; i64::FORMATTED_SIZE_DECIMAL];
+
+    w.write_all(b"i")?;
+    w.write_all(i.to_lexical(&mut digits))?;
+    w.write_all(b"e")
+}
+
+// Exactly what `encode` would write, without writing it.
+pub fn encoded_len<B: AsRef<[u8]> + Ord>(value: &Value<B>) -> usize {
+    match value {
+        Value::Bytes(b) => string_len(b.as_ref()),
+        Value::Int(i) => 2 + (*i < 0) as usize + digits(i.unsigned_abs()),
+        Value::List(vs) => 2 + vs.iter().map(encoded_len).sum::<usize>(),
+        Value::Dict(vs) => {
+            2 + vs
+                .into_iter()
+                .map(|(k, v)| string_len(k.as_ref()) + encoded_len(v))
+                .sum::<usize>()
+        }
+    }
+}
+
+fn string_len(bytes: &[u8]) -> usize {
+    digits(bytes.len() as u64) + 1 + bytes.len()
+}
+
+fn digits(n: u64) -> usize {
+    n.checked_ilog10().unwrap_or(0) as usize + 1
 }
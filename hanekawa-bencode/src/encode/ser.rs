@@ -1,6 +1,6 @@
 use crate::{Element, Elements};
 
-use std::cell::RefCell;
+use std::{cell::RefCell, ops::Range};
 
 use super::{
     encode_dict_begin, encode_dict_end, encode_integer, encode_list_begin, encode_list_end,
@@ -13,14 +13,60 @@ use serde::ser::{self, Impossible};
 struct Serializer {
     buf: BytesMut,
     writing_map_key: bool,
+    // Of the dicts being written, innermost last.
+    entries: Vec<Entry>,
+}
+
+// Where in the buffer a dict entry is, and the key's bytes.
+struct Entry {
+    key: Range<usize>,
+    entry: Range<usize>,
 }
 
 impl Serializer {
-    fn new() -> Self {
+    fn new(buf: BytesMut, entries: Vec<Entry>) -> Self {
         Self {
-            buf: BytesMut::new(),
+            buf,
             writing_map_key: false,
+            entries,
+        }
+    }
+
+    // Values that write nothing, like `None`, leave their key out too.
+    fn end_entry(&mut self, start: usize, key: Range<usize>) {
+        if self.buf.len() == key.end {
+            self.buf.truncate(start);
+            return;
+        }
+
+        let entry = start..self.buf.len();
+        self.entries.push(Entry { key, entry });
+    }
+
+    // BEP 3: keys must appear in sorted order, as raw strings. Most dicts are
+    // written that way already, the rest are put in order in place.
+    fn end_dict(&mut self, first: usize) {
+        let Self { buf, entries, .. } = self;
+        let dict = &mut entries[first..];
+        let sorted = dict
+            .windows(2)
+            .all(|w| buf[w[0].key.clone()] <= buf[w[1].key.clone()]);
+
+        if !sorted {
+            let start = dict[0].entry.start;
+            let written = buf[start..].to_vec();
+            let at = |range: &Range<usize>| range.start - start..range.end - start;
+            dict.sort_by(|a, b| written[at(&a.key)].cmp(&written[at(&b.key)]));
+
+            let mut pos = start;
+            for entry in dict.iter() {
+                let len = entry.entry.len();
+                buf[pos..pos + len].copy_from_slice(&written[at(&entry.entry)]);
+                pos += len;
+            }
         }
+
+        entries.truncate(first);
     }
 
     fn reject_if_writing_map_key(&self) -> Result<(), Error> {
@@ -125,8 +171,11 @@ impl<'a> ser::Serializer for &'a mut Serializer {
         self.serialize_i64(v as i64)
     }
 
-    fn serialize_u64(self, _v: u64) -> Result<Self::Ok, Self::Error> {
-        unsupported_element(Some("u64"))
+    fn serialize_u64(self, v: u64) -> Result<Self::Ok, Self::Error> {
+        match i64::try_from(v) {
+            Ok(v) => self.serialize_i64(v),
+            Err(_) => unsupported_element(Some("u64 above i64::MAX")),
+        }
     }
 
     fn serialize_f32(self, _v: f32) -> Result<Self::Ok, Self::Error> {
@@ -295,12 +344,14 @@ impl<'a> ser::SerializeSeq for SeqSerializer<'a> {
 
 struct StructSerializer<'a> {
     serializer: &'a mut Serializer,
+    first: usize,
 }
 
 impl<'a> StructSerializer<'a> {
     fn new(serializer: &'a mut Serializer) -> Self {
         encode_dict_begin(&mut serializer.buf);
-        Self { serializer }
+        let first = serializer.entries.len();
+        Self { serializer, first }
     }
 }
 
@@ -319,13 +370,17 @@ impl<'a> ser::SerializeStruct for StructSerializer<'a> {
     {
         use serde::Serialize;
 
+        let start = self.serializer.buf.len();
         key.serialize(&mut *self.serializer)?;
+        let end = self.serializer.buf.len();
         value.serialize(&mut *self.serializer)?;
+        self.serializer.end_entry(start, end - key.len()..end);
 
         Ok(())
     }
 
     fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.serializer.end_dict(self.first);
         encode_dict_end(&mut self.serializer.buf);
 
         Ok(())
@@ -334,12 +389,20 @@ impl<'a> ser::SerializeStruct for StructSerializer<'a> {
 
 struct MapSerializer<'a> {
     serializer: &'a mut Serializer,
+    first: usize,
+    // Where the entry whose value comes next starts, and its key.
+    key: (usize, Range<usize>),
 }
 
 impl<'a> MapSerializer<'a> {
     fn new(serializer: &'a mut Serializer) -> Self {
         encode_dict_begin(&mut serializer.buf);
-        Self { serializer }
+        let first = serializer.entries.len();
+        Self {
+            serializer,
+            first,
+            key: (0, 0..0),
+        }
     }
 }
 
@@ -355,10 +418,17 @@ impl<'a> ser::SerializeMap for MapSerializer<'a> {
         let current = self.serializer.writing_map_key;
         self.serializer.writing_map_key = true;
 
+        let start = self.serializer.buf.len();
         key.serialize(&mut *self.serializer)?;
+        let end = self.serializer.buf.len();
 
         self.serializer.writing_map_key = current;
 
+        // Past the length, keys being strings.
+        let buf = &self.serializer.buf[start..end];
+        let colon = buf.iter().position(|b| *b == b':').unwrap_or_default();
+        self.key = (start, start + colon + 1..end);
+
         Ok(())
     }
 
@@ -367,11 +437,14 @@ impl<'a> ser::SerializeMap for MapSerializer<'a> {
         T: serde::Serialize,
     {
         value.serialize(&mut *self.serializer)?;
+        let (start, key) = std::mem::replace(&mut self.key, (0, 0..0));
+        self.serializer.end_entry(start, key);
 
         Ok(())
     }
 
     fn end(self) -> Result<Self::Ok, Self::Error> {
+        self.serializer.end_dict(self.first);
         encode_dict_end(&mut self.serializer.buf);
 
         Ok(())
@@ -465,17 +538,69 @@ impl<B: AsRef<[u8]> + Ord> serde::Serialize for Elements<B> {
     }
 }
 
+// Most tracker responses fit, so they take one allocation rather than one for
+// every time the buffer is outgrown.
+const INITIAL_CAPACITY: usize = 1024;
+
 pub fn to_bytes<T: serde::Serialize>(value: &T) -> Result<Bytes, Error> {
-    let mut serializer = Serializer::new();
-    value.serialize(&mut serializer)?;
+    let mut buf = BytesMut::with_capacity(INITIAL_CAPACITY);
+    to_writer(value, &mut buf)?;
+
+    Ok(buf.freeze())
+}
+
+// Appends to `buf`, which is left as it was if encoding fails.
+pub fn to_writer<T: serde::Serialize>(value: &T, buf: &mut BytesMut) -> Result<(), Error> {
+    write(value, buf, &mut Vec::new())
+}
+
+fn write<T: serde::Serialize>(
+    value: &T,
+    buf: &mut BytesMut,
+    entries: &mut Vec<Entry>,
+) -> Result<(), Error> {
+    let start = buf.len();
+    let mut serializer = Serializer::new(std::mem::take(buf), std::mem::take(entries));
+    let result = value.serialize(&mut serializer);
+
+    *buf = serializer.buf;
+    *entries = serializer.entries;
+    entries.clear();
+    if result.is_err() {
+        buf.truncate(start);
+    }
+    result
+}
+
+// Encodes value after value into the same buffer, splitting each off
+// without a copy. Its space is reused once they have all been dropped, so
+// encoding many small values rarely allocates.
+pub struct Encoder {
+    buf: BytesMut,
+    entries: Vec<Entry>,
+}
+
+impl Encoder {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            buf: BytesMut::with_capacity(capacity),
+            entries: Vec::new(),
+        }
+    }
 
-    Ok(serializer.buf.into())
+    pub fn encode<T: serde::Serialize>(&mut self, value: &T) -> Result<Bytes, Error> {
+        write(value, &mut self.buf, &mut self.entries)?;
+
+        Ok(self.buf.split().freeze())
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
     use include_dir::{include_dir, Dir};
+    use proptest::prelude::*;
+    use serde::Serialize;
 
     static TORRENT_SAMPLES_DIR: Dir<'_> = include_dir!("$CARGO_MANIFEST_DIR/benches/samples/");
 
@@ -492,4 +617,139 @@ mod test {
             assert_eq!(sample.contents(), encoded.unwrap());
         }
     }
+
+    fn value() -> impl Strategy<Value = Value<Vec<u8>>> {
+        let leaf = prop_oneof![
+            any::<Vec<u8>>().prop_map(Value::Bytes),
+            any::<i64>().prop_map(Value::Int),
+        ];
+        leaf.prop_recursive(4, 64, 8, |inner| {
+            prop_oneof![
+                prop::collection::vec(inner.clone(), 0..8).prop_map(Value::List),
+                prop::collection::btree_map(any::<Vec<u8>>(), inner, 0..8)
+                    .prop_map(|entries| Value::Dict(entries.into_iter().collect())),
+            ]
+        })
+    }
+
+    proptest! {
+        // Against both encoders as they were, each into an empty buffer.
+        #[test]
+        fn encodes_as_before(value in value()) {
+            let mut before = BytesMut::new();
+            super::super::encode_value(&value, &mut before);
+            prop_assert_eq!(&before.to_vec(), &crate::encode(&value));
+
+            let mut before = Serializer::new(BytesMut::new(), Vec::new());
+            value.serialize(&mut before).unwrap();
+            prop_assert_eq!(before.buf.freeze(), to_bytes(&value).unwrap());
+        }
+
+        #[test]
+        fn streams_and_counts_what_it_encodes(value in value()) {
+            let encoded = crate::encode(&value);
+            prop_assert_eq!(encoded.len(), crate::encoded_len(&value));
+
+            let mut streamed = Vec::new();
+            crate::encode_to(&value, &mut streamed).unwrap();
+            prop_assert_eq!(encoded, streamed);
+        }
+    }
+
+    #[test]
+    fn encodes_64_bit_ints() {
+        assert_eq!(
+            &b"i4294967296e"[..],
+            to_bytes(&(u32::MAX as u64 + 1)).unwrap()
+        );
+        assert_eq!(&b"i-9223372036854775808e"[..], to_bytes(&i64::MIN).unwrap());
+        assert_eq!(
+            &b"i9223372036854775807e"[..],
+            to_bytes(&(i64::MAX as u64)).unwrap()
+        );
+        assert!(to_bytes(&u64::MAX).is_err());
+    }
+
+    #[test]
+    fn writes_dicts_in_key_order() {
+        #[derive(Serialize)]
+        struct Stats {
+            complete: u32,
+            incomplete: u32,
+        }
+
+        #[derive(Serialize)]
+        #[serde(untagged)]
+        enum Peers {
+            Compact(#[serde(with = "serde_bytes")] Vec<u8>),
+            Long(Vec<Stats>),
+        }
+
+        #[derive(Serialize)]
+        struct Response {
+            peers: Peers,
+            interval: u32,
+            #[serde(flatten)]
+            stats: Option<Stats>,
+            #[serde(rename = "warning message")]
+            warning: Option<String>,
+        }
+
+        let response = Response {
+            peers: Peers::Long(vec![Stats {
+                incomplete: 2,
+                complete: 1,
+            }]),
+            interval: 1800,
+            stats: Some(Stats {
+                complete: 3,
+                incomplete: 4,
+            }),
+            warning: None,
+        };
+        assert_eq!(
+            &b"d8:completei3e10:incompletei4e8:intervali1800e5:peersld8:completei1e10:incompletei2eeee"[..],
+            to_bytes(&response).unwrap()
+        );
+
+        let response = Response {
+            peers: Peers::Compact(vec![b'a'; 6]),
+            interval: 1800,
+            stats: None,
+            warning: Some("spam".to_string()),
+        };
+        assert_eq!(
+            &b"d8:intervali1800e5:peers6:aaaaaa15:warning message4:spame"[..],
+            to_bytes(&response).unwrap()
+        );
+
+        let map = std::collections::HashMap::from([
+            ("b", Some(2)),
+            ("a", Some(1)),
+            ("ab", None),
+            ("c", Some(3)),
+        ]);
+        assert_eq!(&b"d1:ai1e1:bi2e1:ci3ee"[..], to_bytes(&map).unwrap());
+    }
+
+    #[test]
+    fn encoder_reuses_its_buffer() {
+        let mut encoder = Encoder::with_capacity(64);
+        let first = encoder.encode(&"spam").unwrap();
+        let second = encoder.encode(&vec![1, 2, 3]).unwrap();
+        assert_eq!(&b"4:spam"[..], first);
+        assert_eq!(&b"li1ei2ei3ee"[..], second);
+
+        // Space freed up is taken again, rather than allocated anew.
+        let start = first.as_ptr() as usize;
+        drop((first, second));
+        for _ in 0..10 {
+            let encoded = encoder.encode(&"x".repeat(20)).unwrap();
+            assert!((start..start + 64).contains(&(encoded.as_ptr() as usize)));
+        }
+
+        // And the buffer is left alone by values that cannot be encoded.
+        assert!(encoder.encode(&1.5).is_err());
+        assert_eq!(&b"4:eggs"[..], encoder.encode(&"eggs").unwrap());
+    }
 }
@@ -0,0 +1,234 @@
+use std::io::{self, Write};
+
+use super::{write_integer, write_string};
+
+#[derive(Debug)]
+pub enum StreamError {
+    Io(io::Error),
+    // Not after the key before it, or the same.
+    UnsortedKey(Vec<u8>),
+    ExpectedKey,
+    UnexpectedKey,
+    ExpectedValue,
+    NothingToEnd,
+    Finished,
+    Unfinished,
+}
+
+impl std::fmt::Display for StreamError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => f.write_fmt(format_args!("cannot write: {}", e)),
+            Self::UnsortedKey(k) => f.write_fmt(format_args!(
+                "key {:?} out of order",
+                String::from_utf8_lossy(k)
+            )),
+            Self::ExpectedKey => f.write_str("expected a key"),
+            Self::UnexpectedKey => f.write_str("key outside of a dict, or without a value"),
+            Self::ExpectedValue => f.write_str("key without a value"),
+            Self::NothingToEnd => f.write_str("no list or dict to end"),
+            Self::Finished => f.write_str("value after the end"),
+            Self::Unfinished => f.write_str("list or dict never ended"),
+        }
+    }
+}
+
+impl std::error::Error for StreamError {}
+
+impl From<io::Error> for StreamError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+enum Frame {
+    List,
+    Dict {
+        last: Option<Vec<u8>>,
+        value_next: bool,
+    },
+}
+
+// Writes a value as it is given, piece by piece, rather than from a `Value`
+// built up front. Keys must come in order, as BEP 3 has them.
+pub struct StreamEncoder<W> {
+    w: W,
+    frames: Vec<Frame>,
+    written: bool,
+}
+
+impl<W: Write> StreamEncoder<W> {
+    pub fn new(w: W) -> Self {
+        Self {
+            w,
+            frames: Vec::new(),
+            written: false,
+        }
+    }
+
+    pub fn begin_dict(&mut self) -> Result<(), StreamError> {
+        self.begin(
+            b"d",
+            Frame::Dict {
+                last: None,
+                value_next: false,
+            },
+        )
+    }
+
+    pub fn begin_list(&mut self) -> Result<(), StreamError> {
+        self.begin(b"l", Frame::List)
+    }
+
+    pub fn key(&mut self, key: &[u8]) -> Result<(), StreamError> {
+        let Some(Frame::Dict { last, value_next }) = self.frames.last_mut() else {
+            return Err(StreamError::UnexpectedKey);
+        };
+        if *value_next {
+            return Err(StreamError::UnexpectedKey);
+        }
+        if last.as_deref().is_some_and(|last| key <= last) {
+            return Err(StreamError::UnsortedKey(key.to_vec()));
+        }
+
+        write_string(key, &mut self.w)?;
+        let last = last.get_or_insert_with(Vec::new);
+        last.clear();
+        last.extend_from_slice(key);
+        *value_next = true;
+
+        Ok(())
+    }
+
+    pub fn int(&mut self, i: i64) -> Result<(), StreamError> {
+        self.value()?;
+        write_integer(i, &mut self.w)?;
+        self.ended();
+
+        Ok(())
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) -> Result<(), StreamError> {
+        self.value()?;
+        write_string(bytes, &mut self.w)?;
+        self.ended();
+
+        Ok(())
+    }
+
+    pub fn end(&mut self) -> Result<(), StreamError> {
+        match self.frames.last() {
+            None => return Err(StreamError::NothingToEnd),
+            Some(Frame::Dict {
+                value_next: true, ..
+            }) => return Err(StreamError::ExpectedValue),
+            Some(_) => {}
+        }
+
+        self.w.write_all(b"e")?;
+        self.frames.pop();
+        self.ended();
+
+        Ok(())
+    }
+
+    // Once the value is whole.
+    pub fn finish(self) -> Result<W, StreamError> {
+        match self.written && self.frames.is_empty() {
+            true => Ok(self.w),
+            false => Err(StreamError::Unfinished),
+        }
+    }
+
+    fn begin(&mut self, tag: &[u8], frame: Frame) -> Result<(), StreamError> {
+        self.value()?;
+        self.w.write_all(tag)?;
+        self.frames.push(frame);
+
+        Ok(())
+    }
+
+    // Where a value may go next.
+    fn value(&mut self) -> Result<(), StreamError> {
+        match self.frames.last_mut() {
+            None if self.written => Err(StreamError::Finished),
+            Some(Frame::Dict { value_next, .. }) => match std::mem::take(value_next) {
+                true => Ok(()),
+                false => Err(StreamError::ExpectedKey),
+            },
+            _ => Ok(()),
+        }
+    }
+
+    fn ended(&mut self) {
+        self.written |= self.frames.is_empty();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn streams_a_scrape() {
+        let mut encoder = StreamEncoder::new(Vec::new());
+        encoder.begin_dict().unwrap();
+        encoder.key(b"files").unwrap();
+        encoder.begin_dict().unwrap();
+        for info_hash in [[0xaa; 20], [0xbb; 20]] {
+            encoder.key(&info_hash).unwrap();
+            encoder.begin_dict().unwrap();
+            for (key, n) in [("complete", 1), ("downloaded", 2), ("incomplete", 3)] {
+                encoder.key(key.as_bytes()).unwrap();
+                encoder.int(n).unwrap();
+            }
+            encoder.end().unwrap();
+        }
+        encoder.end().unwrap();
+        encoder.key(b"flags").unwrap();
+        encoder.begin_list().unwrap();
+        encoder.bytes(b"spam").unwrap();
+        encoder.end().unwrap();
+        encoder.end().unwrap();
+
+        let stats = "d8:completei1e10:downloadedi2e10:incompletei3ee";
+        let mut expected = b"d5:filesd20:".to_vec();
+        expected.extend_from_slice(&[0xaa; 20]);
+        expected.extend_from_slice(stats.as_bytes());
+        expected.extend_from_slice(b"20:");
+        expected.extend_from_slice(&[0xbb; 20]);
+        expected.extend_from_slice(stats.as_bytes());
+        expected.extend_from_slice(b"e5:flagsl4:spamee");
+        assert_eq!(expected, encoder.finish().unwrap());
+    }
+
+    #[test]
+    fn checks_what_comes_when() {
+        let mut encoder = StreamEncoder::new(Vec::new());
+        encoder.begin_dict().unwrap();
+        assert!(matches!(encoder.int(1), Err(StreamError::ExpectedKey)));
+        encoder.key(b"b").unwrap();
+        assert!(matches!(encoder.key(b"c"), Err(StreamError::UnexpectedKey)));
+        assert!(matches!(encoder.end(), Err(StreamError::ExpectedValue)));
+        encoder.int(1).unwrap();
+        assert!(matches!(encoder.key(b"a"), Err(StreamError::UnsortedKey(k)) if k == b"a"));
+        assert!(matches!(
+            encoder.key(b"b"),
+            Err(StreamError::UnsortedKey(_))
+        ));
+        encoder.key(b"c").unwrap();
+        encoder.begin_list().unwrap();
+        assert!(matches!(encoder.key(b"d"), Err(StreamError::UnexpectedKey)));
+        encoder.end().unwrap();
+        encoder.end().unwrap();
+        assert!(matches!(encoder.end(), Err(StreamError::NothingToEnd)));
+        assert!(matches!(encoder.bytes(b""), Err(StreamError::Finished)));
+        assert_eq!(b"d1:bi1e1:clee".to_vec(), encoder.finish().unwrap());
+
+        let mut encoder = StreamEncoder::new(Vec::new());
+        encoder.begin_list().unwrap();
+        assert!(matches!(encoder.finish(), Err(StreamError::Unfinished)));
+        let encoder = StreamEncoder::new(Vec::new());
+        assert!(matches!(encoder.finish(), Err(StreamError::Unfinished)));
+    }
+}
@@ -0,0 +1,180 @@
+use crate::{Map, Value};
+
+use serde_json::{Number, Value as Json};
+
+// Strings that are not UTF-8 become `{"$hex": "…"}`. Dicts become objects
+// unless a key is not UTF-8 or starts with `$`, as a marker would, or keys
+// are not in order, in which case they are `{"$dict": [[key, value], …]}`
+// as they were written. Either way they read back as they were.
+const HEX: &str = "$hex";
+const DICT: &str = "$dict";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    // Bencode only has integers.
+    Float(Number),
+    IntegerOutOfRange(Number),
+    // Null or a boolean, which bencode has nothing for.
+    Unsupported(&'static str),
+    InvalidHex(String),
+    // An entry of a `$dict` that is not a key and a value.
+    InvalidEntry,
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Float(n) => f.write_fmt(format_args!("{n} is not an integer")),
+            Self::IntegerOutOfRange(n) => f.write_fmt(format_args!("{n} does not fit in 64 bits")),
+            Self::Unsupported(kind) => f.write_fmt(format_args!("bencode has no {kind}")),
+            Self::InvalidHex(s) => f.write_fmt(format_args!("{s:?} is not hex")),
+            Self::InvalidEntry => f.write_str("dict entries are [key, value] pairs"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+pub fn to_json<B: AsRef<[u8]> + Ord>(value: &Value<B>) -> Json {
+    match value {
+        Value::Bytes(bs) => string(bs.as_ref()),
+        Value::Int(i) => (*i).into(),
+        Value::List(vs) => vs.iter().map(to_json).collect(),
+        Value::Dict(m) => {
+            let keys = m
+                .into_iter()
+                .map(|(k, _)| std::str::from_utf8(k.as_ref()).ok())
+                .collect::<Option<Vec<_>>>();
+
+            match keys {
+                Some(keys)
+                    if !keys.iter().any(|k| k.starts_with('$'))
+                        && keys.windows(2).all(|pair| pair[0] < pair[1]) =>
+                {
+                    keys.into_iter()
+                        .zip(m)
+                        .map(|(k, (_, v))| (k.to_string(), to_json(v)))
+                        .collect::<serde_json::Map<_, _>>()
+                        .into()
+                }
+                _ => {
+                    let entries = m
+                        .into_iter()
+                        .map(|(k, v)| Json::Array(vec![string(k.as_ref()), to_json(v)]))
+                        .collect();
+                    marker(DICT, Json::Array(entries))
+                }
+            }
+        }
+    }
+}
+
+pub fn from_json(json: &Json) -> Result<Value<Vec<u8>>, Error> {
+    match json {
+        Json::Null => Err(Error::Unsupported("null")),
+        Json::Bool(_) => Err(Error::Unsupported("booleans")),
+        Json::Number(n) if n.is_f64() => Err(Error::Float(n.clone())),
+        Json::Number(n) => n
+            .as_i64()
+            .map(Value::Int)
+            .ok_or_else(|| Error::IntegerOutOfRange(n.clone())),
+        Json::String(s) => Ok(Value::Bytes(s.as_bytes().to_vec())),
+        Json::Array(vs) => vs
+            .iter()
+            .map(from_json)
+            .collect::<Result<_, _>>()
+            .map(Value::List),
+        Json::Object(o) => match o.iter().next() {
+            Some((k, Json::String(hex))) if o.len() == 1 && k == HEX => hex::decode(hex)
+                .map(Value::Bytes)
+                .map_err(|_| Error::InvalidHex(hex.clone())),
+            Some((k, Json::Array(entries))) if o.len() == 1 && k == DICT => {
+                let entries = entries
+                    .iter()
+                    .map(|entry| match entry.as_array().map(Vec::as_slice) {
+                        Some([k, v]) => match from_json(k)? {
+                            Value::Bytes(k) => Ok((k, from_json(v)?)),
+                            _ => Err(Error::InvalidEntry),
+                        },
+                        _ => Err(Error::InvalidEntry),
+                    })
+                    .collect::<Result<_, _>>()?;
+                Ok(Value::Dict(Map::from_raw(entries)))
+            }
+            // Already in key order.
+            _ => o
+                .iter()
+                .map(|(k, v)| Ok((k.as_bytes().to_vec(), from_json(v)?)))
+                .collect::<Result<_, _>>()
+                .map(Value::Dict),
+        },
+    }
+}
+
+fn string(bs: &[u8]) -> Json {
+    match std::str::from_utf8(bs) {
+        Ok(s) => s.into(),
+        Err(_) => marker(HEX, hex::encode(bs).into()),
+    }
+}
+
+fn marker(key: &str, value: Json) -> Json {
+    Json::Object([(key.to_string(), value)].into_iter().collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use serde_json::json;
+
+    #[test]
+    fn round_trips_binary_strings_and_keys() {
+        let info_hash = vec![0xaa; 20];
+        let value = Value::dict()
+            .with("$hex", Value::List(vec![Value::Int(-1)]))
+            .with("files", Value::dict().with(&info_hash, 3))
+            .with("name", Value::Bytes(b"a.txt".to_vec()))
+            .with("pieces", Value::Bytes(vec![0xff, 0x00, b'a']));
+
+        let json = to_json(&value);
+        assert_eq!(
+            json!({"$dict": [
+                ["$hex", [-1]],
+                ["files", {"$dict": [[{"$hex": "aa".repeat(20)}, 3]]}],
+                ["name", "a.txt"],
+                ["pieces", {"$hex": "ff0061"}],
+            ]}),
+            json
+        );
+        assert_eq!(value, from_json(&json).unwrap());
+
+        let plain = Value::dict().with("a", Value::List(vec![]));
+        assert_eq!(json!({"a": []}), to_json(&plain));
+        assert_eq!(plain, from_json(&to_json(&plain)).unwrap());
+    }
+
+    #[test]
+    fn takes_only_what_bencode_has() {
+        assert_eq!(
+            Value::dict().with("a", 1).with("b", 2),
+            from_json(&json!({"b": 2, "a": 1})).unwrap()
+        );
+
+        for (json, error) in [
+            (json!(1.5), "1.5 is not an integer"),
+            (
+                json!(u64::MAX),
+                "18446744073709551615 does not fit in 64 bits",
+            ),
+            (json!([null]), "bencode has no null"),
+            (json!({"$hex": "xyz"}), "\"xyz\" is not hex"),
+            (
+                json!({"$dict": [["a"]]}),
+                "dict entries are [key, value] pairs",
+            ),
+        ] {
+            assert_eq!(error, from_json(&json).unwrap_err().to_string());
+        }
+    }
+}
@@ -1,14 +1,20 @@
 mod decode;
 mod encode;
+#[cfg(feature = "json")]
+mod json;
 mod map;
 mod repr;
 
-pub use decode::parse;
-pub use encode::encode;
-pub use encode::ser::to_bytes;
+pub use decode::de::{from_bytes, Error as DecodeError};
+pub use decode::{parse, parse_raw_dict, parse_with_limits, Limits, RawEntries};
+pub use encode::ser::{to_bytes, to_writer, Encoder};
+pub use encode::stream::{StreamEncoder, StreamError};
+pub use encode::{encode, encode_to, encoded_len};
+#[cfg(feature = "json")]
+pub use json::{from_json, to_json, Error as JsonError};
 
 pub use map::Map;
-pub use repr::{Element, Elements, Error, Value};
+pub use repr::{Element, Elements, Error, TypeError, Value};
 
 #[cfg(test)]
 mod test {
@@ -125,4 +131,96 @@ mod test {
         dict.insert("key".as_bytes(), Value::Bytes("value".as_bytes()));
         assert_eq!(Value::Dict(dict), value);
     }
+
+    #[test]
+    fn borrows_from_the_input() {
+        let data = "d6:valuesl4:spami127ee3:key5:valuee".as_bytes();
+        let value = parse(data).unwrap().into_value();
+        let Value::Dict(dict) = &value else {
+            panic!("not a dict");
+        };
+        let (key, _) = dict.into_iter().next().unwrap();
+        assert!(data.as_ptr_range().contains(&key.as_ptr()));
+
+        let owned = value.clone().into_owned();
+        assert_eq!(encode(&value), encode(&owned));
+        assert_eq!(to_bytes(&value).unwrap(), to_bytes(&owned).unwrap());
+    }
+
+    #[test]
+    fn reads_values_without_matching() {
+        let data =
+            "d8:announce3:foo13:announce-listll3:fooel3:baree4:infod5:filesld6:lengthi7eeeee";
+        let torrent = parse(data.as_bytes()).unwrap().into_value();
+
+        assert_eq!(Some("foo"), torrent.get("announce").and_then(Value::as_str));
+        let tiers = torrent.get("announce-list").and_then(Value::as_list);
+        assert_eq!(
+            Some(&b"bar"[..]),
+            tiers.and_then(|tiers| tiers[1].as_list()?[0].as_bytes())
+        );
+        let files = torrent.lookup(&["info", "files"]).and_then(Value::as_list);
+        assert_eq!(Some(7), files.and_then(|f| f[0].get("length")?.as_int()));
+
+        assert_eq!(None, torrent.lookup(&["info", "name"]));
+        assert_eq!(None, torrent.lookup(&["announce", "foo"]));
+        assert_eq!(Some(&torrent), torrent.lookup(&[]));
+
+        let length = torrent.lookup(&["info", "files"]).unwrap();
+        assert_eq!(
+            Err(TypeError {
+                expected: "an integer"
+            }),
+            i64::try_from(length)
+        );
+        assert_eq!(Ok("foo"), torrent.get("announce").unwrap().try_into());
+    }
+
+    #[test]
+    fn builds_values() {
+        let peers = Value::dict().with("ip", "192.0.2.1").with("port", 6881);
+        let value = Value::dict()
+            .with("interval", 1800)
+            .with("peers", vec![peers])
+            .with("peers6", b"".to_vec());
+        assert_eq!(
+            &b"d8:intervali1800e5:peersld2:ip9:192.0.2.14:porti6881eee6:peers60:e"[..],
+            encode(&value)
+        );
+
+        let borrowed: Value<&[u8]> = vec![Value::from("spam"), 3.into()].into();
+        assert_eq!(b"l4:spami3ee".to_vec(), encode(&borrowed));
+    }
+
+    #[test]
+    fn round_trips_binary_strings() {
+        // A compact peer at 192.168.255.254:65535, and an info hash; neither
+        // is UTF-8.
+        let peer = [0xc0, 0xa8, 0xff, 0xfe, 0xff, 0xff];
+        let info_hash: Vec<u8> = (0xec..=0xff).collect();
+
+        let mut files = Map::new();
+        files.insert(&info_hash[..], Value::Int(1));
+        let mut dict = Map::new();
+        dict.insert("files".as_bytes(), Value::Dict(files));
+        dict.insert("peers".as_bytes(), Value::Bytes(&peer[..]));
+        let value = Value::Dict(dict);
+
+        let encoded = encode(&value);
+        assert!(encoded.starts_with(b"d5:filesd20:\xec\xed"));
+        assert_eq!(value, parse(&encoded).unwrap().into_value());
+        assert_eq!(encoded, to_bytes(&value).unwrap());
+
+        #[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+        struct Response {
+            #[serde(with = "serde_bytes")]
+            peers: Vec<u8>,
+        }
+        let response = Response {
+            peers: peer.to_vec(),
+        };
+        let encoded = to_bytes(&response).unwrap();
+        assert_eq!(&b"d5:peers6:\xc0\xa8\xff\xfe\xff\xffe"[..], encoded);
+        assert_eq!(response, from_bytes::<Response>(&encoded).unwrap());
+    }
 }
@@ -47,14 +47,227 @@ impl<B: Ord> Value<B> {
     }
 }
 
+impl Value<&[u8]> {
+    // Parsed values borrow from the input; this copies them out of it.
+    pub fn into_owned(self) -> Value<Vec<u8>> {
+        match self {
+            Value::Bytes(bs) => Value::Bytes(bs.to_vec()),
+            Value::Int(i) => Value::Int(i),
+            Value::List(vs) => Value::List(vs.into_iter().map(Value::into_owned).collect()),
+            Value::Dict(m) => Value::Dict(
+                m.into_iter()
+                    .map(|(k, v)| (k.to_vec(), v.into_owned()))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl<B: Ord + AsRef<[u8]>> Value<B> {
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Value::Bytes(bs) => Some(bs.as_ref()),
+            _ => None,
+        }
+    }
+
+    // None as well for strings that are not UTF-8.
+    pub fn as_str(&self) -> Option<&str> {
+        std::str::from_utf8(self.as_bytes()?).ok()
+    }
+
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            Value::Int(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    pub fn as_list(&self) -> Option<&[Self]> {
+        match self {
+            Value::List(vs) => Some(vs),
+            _ => None,
+        }
+    }
+
+    pub fn as_dict(&self) -> Option<&Map<B, Self>> {
+        match self {
+            Value::Dict(m) => Some(m),
+            _ => None,
+        }
+    }
+
+    // The first entry under `key`, if this is a dict.
+    pub fn get(&self, key: impl AsRef<[u8]>) -> Option<&Self> {
+        self.as_dict()?
+            .into_iter()
+            .find(|(k, _)| k.as_ref() == key.as_ref())
+            .map(|(_, v)| v)
+    }
+
+    // Through nested dicts, as `get` on each in turn.
+    pub fn lookup(&self, path: &[&str]) -> Option<&Self> {
+        path.iter().try_fold(self, |value, key| value.get(key))
+    }
+}
+
+impl Value<Vec<u8>> {
+    pub fn dict() -> Self {
+        Value::Dict(Map::new())
+    }
+
+    // Adds an entry to a dict, and leaves anything else as it is.
+    pub fn with(mut self, key: impl AsRef<[u8]>, value: impl Into<Self>) -> Self {
+        debug_assert!(matches!(self, Value::Dict(_)), "not a dict");
+        if let Value::Dict(m) = &mut self {
+            m.insert(key.as_ref().to_vec(), value.into());
+        }
+        self
+    }
+}
+
+impl<B: Ord> From<i64> for Value<B> {
+    fn from(i: i64) -> Self {
+        Value::Int(i)
+    }
+}
+
+impl<B: Ord> From<Vec<Value<B>>> for Value<B> {
+    fn from(vs: Vec<Value<B>>) -> Self {
+        Value::List(vs)
+    }
+}
+
+impl<B: Ord> From<Map<B, Value<B>>> for Value<B> {
+    fn from(m: Map<B, Value<B>>) -> Self {
+        Value::Dict(m)
+    }
+}
+
+impl<'a> From<&'a str> for Value<&'a [u8]> {
+    fn from(s: &'a str) -> Self {
+        Value::Bytes(s.as_bytes())
+    }
+}
+
+impl<'a> From<&'a [u8]> for Value<&'a [u8]> {
+    fn from(bs: &'a [u8]) -> Self {
+        Value::Bytes(bs)
+    }
+}
+
+impl From<&str> for Value<Vec<u8>> {
+    fn from(s: &str) -> Self {
+        Value::Bytes(s.as_bytes().to_vec())
+    }
+}
+
+impl From<Vec<u8>> for Value<Vec<u8>> {
+    fn from(bs: Vec<u8>) -> Self {
+        Value::Bytes(bs)
+    }
+}
+
+// What a value was taken for, but is not.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TypeError {
+    pub expected: &'static str,
+}
+
+impl std::fmt::Display for TypeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!("expected {}", self.expected))
+    }
+}
+
+impl std::error::Error for TypeError {}
+
+impl<B: Ord + AsRef<[u8]>> TryFrom<&Value<B>> for i64 {
+    type Error = TypeError;
+
+    fn try_from(value: &Value<B>) -> Result<Self, Self::Error> {
+        value.as_int().ok_or(TypeError {
+            expected: "an integer",
+        })
+    }
+}
+
+impl<'a, B: Ord + AsRef<[u8]>> TryFrom<&'a Value<B>> for &'a [u8] {
+    type Error = TypeError;
+
+    fn try_from(value: &'a Value<B>) -> Result<Self, Self::Error> {
+        value.as_bytes().ok_or(TypeError {
+            expected: "a string",
+        })
+    }
+}
+
+impl<'a, B: Ord + AsRef<[u8]>> TryFrom<&'a Value<B>> for &'a str {
+    type Error = TypeError;
+
+    fn try_from(value: &'a Value<B>) -> Result<Self, Self::Error> {
+        value.as_str().ok_or(TypeError {
+            expected: "a UTF-8 string",
+        })
+    }
+}
+
+// Each with the offset of the byte parsing stopped at.
 #[derive(Debug, PartialEq, Eq)]
 pub enum Error {
-    UnexpectedEnd(String),
-    ExpectedFar(u8),
-    InvalidInt(Vec<u8>),
-    Trailing,
+    UnexpectedEof { offset: usize },
+    UnexpectedByte { byte: u8, offset: usize },
+    InvalidInteger { offset: usize },
+    InvalidStringLength { offset: usize },
+    DepthLimitExceeded { offset: usize },
+    StringTooLong { offset: usize },
+    InputTooLong { offset: usize },
+    DuplicateKey { offset: usize },
+    UnsortedKey { offset: usize },
+    TrailingData { offset: usize },
 }
 
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnexpectedEof { offset } => {
+                f.write_fmt(format_args!("unexpected end of input at byte {}", offset))
+            }
+            Self::UnexpectedByte { byte, offset } => f.write_fmt(format_args!(
+                "unexpected {:?} at byte {}",
+                char::from(*byte),
+                offset
+            )),
+            Self::InvalidInteger { offset } => {
+                f.write_fmt(format_args!("invalid integer at byte {}", offset))
+            }
+            Self::InvalidStringLength { offset } => {
+                f.write_fmt(format_args!("invalid string length at byte {}", offset))
+            }
+            Self::DepthLimitExceeded { offset } => {
+                f.write_fmt(format_args!("nested too deeply at byte {}", offset))
+            }
+            Self::StringTooLong { offset } => {
+                f.write_fmt(format_args!("string too long at byte {}", offset))
+            }
+            Self::InputTooLong { offset } => {
+                f.write_fmt(format_args!("input too long past byte {}", offset))
+            }
+            Self::DuplicateKey { offset } => {
+                f.write_fmt(format_args!("duplicate key at byte {}", offset))
+            }
+            Self::UnsortedKey { offset } => {
+                f.write_fmt(format_args!("key out of order at byte {}", offset))
+            }
+            Self::TrailingData { offset } => {
+                f.write_fmt(format_args!("trailing data at byte {}", offset))
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum Element<B> {
     DictBegin(usize),
@@ -72,6 +285,10 @@ impl<B> Elements<B> {
     pub(crate) fn from_parts(elements: Vec<Element<B>>) -> Self {
         Self { elements }
     }
+
+    pub(crate) fn into_parts(self) -> Vec<Element<B>> {
+        self.elements
+    }
 }
 
 impl<'a, B> IntoIterator for &'a Elements<B> {
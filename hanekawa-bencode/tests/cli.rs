@@ -0,0 +1,58 @@
+#![cfg(feature = "cli")]
+
+use std::{
+    io::Write,
+    process::{Command, Output, Stdio},
+};
+
+const TORRENT: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/../hanekawa-common/fixtures/single-file.torrent"
+);
+
+fn run(args: &[&str], stdin: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_hanekawa-bencode"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn hashes_and_inspects_torrents() {
+    let output = run(&["hash", TORRENT], b"");
+    assert!(output.status.success());
+    assert_eq!(
+        &b"1d9b3d2093102580772a6d3f1b666e965e4a2075\n"[..],
+        output.stdout
+    );
+
+    let output = run(&["inspect", TORRENT], b"");
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!("numbers.txt", json["info"]["name"]);
+    assert!(json["info"]["pieces"]["$hex"].is_string());
+
+    // And back again, byte for byte.
+    let output = run(&["encode"], &output.stdout);
+    assert_eq!(std::fs::read(TORRENT).unwrap(), output.stdout);
+}
+
+#[test]
+fn says_what_went_wrong() {
+    let output = run(&["inspect", "-"], b"d1:a");
+    assert!(!output.status.success());
+    assert!(!output.stderr.is_empty());
+
+    let output = run(&["encode"], b"{\"a\": true}");
+    assert_eq!(
+        "bencode has no booleans\n",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let output = run(&["decode"], b"");
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("usage:"));
+}
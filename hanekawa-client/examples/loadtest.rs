@@ -0,0 +1,55 @@
+// Simulates swarms announcing to one or more trackers, ours or anyone's, and
+// reports the achieved request rate, latencies and errors. HTTP and UDP
+// trackers can be mixed, peers are spread over them in turn:
+//
+//     cargo run --release -p hanekawa-client --example loadtest -- \
+//         http://127.0.0.1:8001/announce udp://127.0.0.1:8002 \
+//         --peers 1000 --torrents 10 --announces 5 --rate 500
+//
+// Other options are `--seeders` and `--completion`, the shares of peers
+// joining as seeders and of leechers completing before they leave.
+
+use hanekawa_client::{loadtest, HttpTrackerClient, TrackerClient, UdpTrackerClient};
+
+fn usage() -> ! {
+    eprintln!(
+        "usage: loadtest <tracker url>... [--peers N] [--torrents N] [--announces N] \
+         [--rate PER_SECOND] [--seeders SHARE] [--completion SHARE]"
+    );
+    std::process::exit(2)
+}
+
+fn value<T: std::str::FromStr>(args: &mut impl Iterator<Item = String>) -> T {
+    args.next()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or_else(|| usage())
+}
+
+#[tokio::main]
+async fn main() {
+    let mut scenario = loadtest::Scenario::default();
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--peers" => scenario.peers = value(&mut args),
+            "--torrents" => scenario.torrents = value(&mut args),
+            "--announces" => scenario.announces_per_peer = value(&mut args),
+            "--rate" => scenario.rate = Some(value(&mut args)),
+            "--seeders" => scenario.seeders = value(&mut args),
+            "--completion" => scenario.completion = value(&mut args),
+            url if !url.starts_with("--") => scenario.trackers.push(url.to_string()),
+            _ => usage(),
+        }
+    }
+    if scenario.trackers.is_empty() {
+        usage();
+    }
+
+    // A lost UDP packet is counted as a timeout, instead of being sent again
+    // within the deadline.
+    let udp = UdpTrackerClient::builder().max_retransmissions(0).build();
+    let client = TrackerClient::new(HttpTrackerClient::new().unwrap(), udp);
+
+    println!("{}", loadtest::run(client, &scenario).await);
+}
@@ -0,0 +1,1016 @@
+use crate::clock::{Clock, TokioClock};
+use crate::observer::Observer;
+use crate::proto::{AnnounceParams, AnnounceResponse, Peer};
+use crate::retry::Retry;
+use crate::tiers::Tiers;
+use crate::{ClientError, TrackerClient, TrackerTransport};
+
+use hanekawa_common::{magnet::MagnetLink, types::Event};
+
+use rand::Rng;
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::{
+    sync::{oneshot, watch, Notify},
+    task::JoinHandle,
+};
+
+// Keeps a tracker answering with a zero interval from being hammered.
+const MIN_DELAY: Duration = Duration::from_secs(1);
+// Whatever the client's own timeouts, so shutdown never waits long on a dead
+// tracker.
+const STOPPED_TIMEOUT: Duration = Duration::from_secs(5);
+
+struct Counters {
+    uploaded: AtomicU64,
+    downloaded: AtomicU64,
+    left: AtomicU64,
+    left_changed: Notify,
+}
+
+// Transfer counters reported on every announce, shared with the caller.
+#[derive(Clone)]
+pub struct TransferStats {
+    inner: Arc<Counters>,
+}
+
+impl TransferStats {
+    fn new(params: &AnnounceParams) -> Self {
+        Self {
+            inner: Arc::new(Counters {
+                uploaded: AtomicU64::new(params.uploaded),
+                downloaded: AtomicU64::new(params.downloaded),
+                left: AtomicU64::new(params.left),
+                left_changed: Notify::new(),
+            }),
+        }
+    }
+
+    pub fn add_uploaded(&self, bytes: u64) {
+        self.inner.uploaded.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn add_downloaded(&self, bytes: u64) {
+        self.inner.downloaded.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn set_left(&self, bytes: u64) {
+        self.inner.left.store(bytes, Ordering::Relaxed);
+        self.inner.left_changed.notify_one();
+    }
+
+    pub fn uploaded(&self) -> u64 {
+        self.inner.uploaded.load(Ordering::Relaxed)
+    }
+
+    pub fn downloaded(&self) -> u64 {
+        self.inner.downloaded.load(Ordering::Relaxed)
+    }
+
+    pub fn left(&self) -> u64 {
+        self.inner.left.load(Ordering::Relaxed)
+    }
+}
+
+// Swarm size as reported by the last successful announce.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SwarmStats {
+    pub seeders: Option<u32>,
+    pub leechers: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Strategy {
+    // BEP 12: the first tracker to answer, in tier order.
+    FirstAnswer,
+    // Every tracker at once, with their peers merged.
+    AllTrackers,
+}
+
+pub struct Announcer {
+    client: Arc<dyn TrackerTransport>,
+    tiers: Tiers,
+    strategy: Strategy,
+    params: AnnounceParams,
+    stats: TransferStats,
+    peers: watch::Sender<Vec<Peer>>,
+    warning: watch::Sender<Option<String>>,
+    swarm: watch::Sender<SwarmStats>,
+    clock: Arc<dyn Clock>,
+    observer: Observer,
+    // Of the tracker that answered last.
+    tier: Option<usize>,
+}
+
+impl Announcer {
+    pub fn spawn(
+        client: impl TrackerTransport + 'static,
+        url: impl Into<String>,
+        params: AnnounceParams,
+    ) -> AnnouncerHandle {
+        Self::spawn_with_clock(
+            Arc::new(client),
+            Tiers::single(url.into()),
+            params,
+            Arc::new(TokioClock),
+        )
+    }
+
+    pub(crate) fn spawn_with_clock(
+        client: Arc<dyn TrackerTransport>,
+        tiers: Tiers,
+        params: AnnounceParams,
+        clock: Arc<dyn Clock>,
+    ) -> AnnouncerHandle {
+        Self::spawn_with(client, tiers, Strategy::FirstAnswer, params, clock)
+    }
+
+    pub(crate) fn spawn_with(
+        client: Arc<dyn TrackerTransport>,
+        tiers: Tiers,
+        strategy: Strategy,
+        params: AnnounceParams,
+        clock: Arc<dyn Clock>,
+    ) -> AnnouncerHandle {
+        let stats = TransferStats::new(&params);
+        let (peers, peers_rx) = watch::channel(vec![]);
+        let (warning, warning_rx) = watch::channel(None);
+        let (swarm, swarm_rx) = watch::channel(SwarmStats::default());
+        let (shutdown, shutdown_rx) = oneshot::channel();
+
+        let announcer = Self {
+            observer: Observer::new(client.observer()),
+            client,
+            tiers,
+            strategy,
+            params,
+            stats: stats.clone(),
+            peers,
+            warning,
+            swarm,
+            clock,
+            tier: None,
+        };
+
+        AnnouncerHandle {
+            stats,
+            peers: peers_rx,
+            warning: warning_rx,
+            swarm: swarm_rx,
+            shutdown,
+            task: tokio::spawn(announcer.run(shutdown_rx)),
+        }
+    }
+
+    // Tries every tracker in order until one answers, and returns the errors
+    // of all of them otherwise.
+    async fn announce(&mut self, event: Event) -> Result<AnnounceResponse, Vec<ClientError>> {
+        let mut params = self.params.clone();
+        params.event = event;
+        params.uploaded = self.stats.uploaded();
+        params.downloaded = self.stats.downloaded();
+        params.left = self.stats.left();
+
+        if self.strategy == Strategy::AllTrackers {
+            return self.announce_to_all(params).await;
+        }
+
+        let mut errors = vec![];
+        for position in self.tiers.positions() {
+            let url = self.tiers.url(position);
+            match self.observed(url, params.clone()).await {
+                Ok(response) => {
+                    if let Ok(peers) = response.peers() {
+                        self.observer.notify(|o| o.peers_received(url, peers.len()));
+                        self.peers.send_replace(peers);
+                    }
+                    if let Some(from) = self.tier.replace(position.0) {
+                        if from != position.0 {
+                            self.observer.notify(|o| o.tier_switched(from, position.0));
+                        }
+                    }
+                    self.tiers.promote(position);
+                    self.publish(&response);
+
+                    return Ok(response);
+                }
+                Err(e) => errors.push(e),
+            }
+        }
+
+        Err(errors)
+    }
+
+    // The first tracker in order that answered sets the interval and swarm
+    // stats, peers come from all of them.
+    async fn announce_to_all(
+        &mut self,
+        params: AnnounceParams,
+    ) -> Result<AnnounceResponse, Vec<ClientError>> {
+        let results =
+            futures::future::join_all(self.tiers.positions().into_iter().map(|position| {
+                let url = self.tiers.url(position);
+                async {
+                    let result = self.observed(url, params.clone()).await;
+                    if let Ok(Ok(peers)) = result.as_ref().map(AnnounceResponse::peers) {
+                        self.observer.notify(|o| o.peers_received(url, peers.len()));
+                    }
+                    result
+                }
+            }))
+            .await;
+
+        let mut first = None;
+        let mut peers: Vec<Peer> = vec![];
+        let mut errors = vec![];
+        for result in results {
+            match result {
+                Ok(response) => {
+                    for peer in response.peers().unwrap_or_default() {
+                        if !peers.iter().any(|p| p.addr == peer.addr) {
+                            peers.push(peer);
+                        }
+                    }
+                    first.get_or_insert(response);
+                }
+                Err(e) => errors.push(e),
+            }
+        }
+
+        let response = first.ok_or(errors)?;
+        self.peers.send_replace(peers);
+        self.publish(&response);
+
+        Ok(response)
+    }
+
+    // Announces to a single tracker, and tells the observer how it went.
+    async fn observed(
+        &self,
+        url: &str,
+        params: AnnounceParams,
+    ) -> Result<AnnounceResponse, ClientError> {
+        self.observer
+            .notify(|o| o.request_started(url, &params.event));
+        let started = self.clock.now();
+
+        let result = self.client.announce(url, params).await;
+
+        let elapsed = self.clock.now().saturating_duration_since(started);
+        self.observer
+            .notify(|o| o.request_finished(url, elapsed, result.as_ref()));
+
+        result
+    }
+
+    fn publish(&self, response: &AnnounceResponse) {
+        self.warning.send_replace(response.warning_message.clone());
+        self.swarm.send_replace(SwarmStats {
+            seeders: response.complete,
+            leechers: response.incomplete,
+        });
+    }
+
+    // The soonest retry any of the trackers allows, if there is one.
+    fn retry(&self, errors: Vec<ClientError>, failures: u32) -> Result<Duration, ClientError> {
+        let policy = self.client.retry_policy();
+
+        let delay = errors
+            .iter()
+            .filter_map(|e| match policy.retry(e, failures) {
+                Retry::After(delay) => Some(delay),
+                Retry::Never => None,
+            })
+            .min();
+
+        delay.ok_or_else(|| {
+            let e = errors.into_iter().last().unwrap_or(ClientError::NoTrackers);
+            ClientError::Terminal(Box::new(e))
+        })
+    }
+
+    fn interval(response: &AnnounceResponse) -> Duration {
+        // Up to 10% either way, so clients started together drift apart.
+        let interval = u64::from(response.interval) * 1000;
+        let jitter = interval / 10;
+        let delay = rand::thread_rng().gen_range(interval - jitter..=interval + jitter);
+
+        let min_interval = u64::from(response.min_interval.unwrap_or(0)) * 1000;
+
+        Duration::from_millis(delay.max(min_interval)).max(MIN_DELAY)
+    }
+
+    async fn run(mut self, mut shutdown: oneshot::Receiver<()>) -> Result<(), ClientError> {
+        // Seeders from the start never complete, whatever happens before the
+        // first announce.
+        let mut completed = self.params.left == 0;
+        let mut event = Event::Started;
+        let mut failures = 0;
+
+        'announce: loop {
+            // An announce left hanging must not hold shutdown up, so it is
+            // dropped, and with it whatever sockets it had open.
+            let result = tokio::select! {
+                _ = &mut shutdown => break 'announce,
+                result = self.announce(event.clone()) => result,
+            };
+
+            let delay = match result {
+                Ok(response) => {
+                    failures = 0;
+                    event = Event::Interval;
+                    Self::interval(&response)
+                }
+                // A failed announce is repeated with the same event.
+                Err(errors) => {
+                    let delay = self.retry(errors, failures)?;
+                    failures += 1;
+                    self.observer.notify(|o| o.retry_scheduled(delay, failures));
+                    delay
+                }
+            };
+
+            let mut sleep = self.clock.sleep(delay);
+            loop {
+                tokio::select! {
+                    // Also taken when the handle is dropped.
+                    _ = &mut shutdown => break 'announce,
+                    _ = &mut sleep => break,
+                    _ = self.stats.inner.left_changed.notified(), if !completed => {
+                        if self.stats.left() == 0 {
+                            completed = true;
+                            event = Event::Completed;
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+
+        let _ = tokio::time::timeout(STOPPED_TIMEOUT, self.announce(Event::Stopped)).await;
+
+        Ok(())
+    }
+}
+
+// BEP 12: Multitracker Metadata Extension
+pub struct MultiTrackerAnnouncer;
+
+impl MultiTrackerAnnouncer {
+    // Takes the tiers of a torrent's `announce-list`.
+    pub fn spawn(
+        client: impl TrackerTransport + 'static,
+        announce_list: Vec<Vec<String>>,
+        params: AnnounceParams,
+    ) -> AnnouncerHandle {
+        Announcer::spawn_with_clock(
+            Arc::new(client),
+            Tiers::new(announce_list),
+            params,
+            Arc::new(TokioClock),
+        )
+    }
+
+    // Announces the link's info hash to all of its trackers at once, rather
+    // than to the first that answers, since magnet links carry no tiers.
+    // Trackers no client here speaks are left out.
+    pub fn from_magnet(
+        client: impl TrackerTransport + 'static,
+        magnet: &MagnetLink,
+        mut params: AnnounceParams,
+    ) -> Result<AnnouncerHandle, ClientError> {
+        let (trackers, unsupported): (Vec<_>, Vec<_>) = magnet
+            .trackers
+            .iter()
+            .cloned()
+            .partition(|url| TrackerClient::supports(url));
+
+        if trackers.is_empty() {
+            return Err(unsupported
+                .into_iter()
+                .next()
+                .map_or(ClientError::NoTrackers, ClientError::UnsupportedScheme));
+        }
+
+        params.info_hash = magnet.info_hash.clone();
+        Ok(Announcer::spawn_with(
+            Arc::new(client),
+            Tiers::new(trackers.into_iter().map(|url| vec![url]).collect()),
+            Strategy::AllTrackers,
+            params,
+            Arc::new(TokioClock),
+        ))
+    }
+}
+
+// Dropping the handle stops the announcer too, but without waiting for the
+// final `stopped` announce.
+pub struct AnnouncerHandle {
+    stats: TransferStats,
+    peers: watch::Receiver<Vec<Peer>>,
+    warning: watch::Receiver<Option<String>>,
+    swarm: watch::Receiver<SwarmStats>,
+    shutdown: oneshot::Sender<()>,
+    task: JoinHandle<Result<(), ClientError>>,
+}
+
+impl AnnouncerHandle {
+    pub fn stats(&self) -> &TransferStats {
+        &self.stats
+    }
+
+    // Updated with the peers from every successful announce.
+    pub fn peers(&self) -> watch::Receiver<Vec<Peer>> {
+        self.peers.clone()
+    }
+
+    // The warning message of the last successful announce, if it had one.
+    pub fn warning(&self) -> watch::Receiver<Option<String>> {
+        self.warning.clone()
+    }
+
+    pub fn swarm(&self) -> watch::Receiver<SwarmStats> {
+        self.swarm.clone()
+    }
+
+    // The announcer stops by itself once retrying cannot help.
+    pub fn is_finished(&self) -> bool {
+        self.task.is_finished()
+    }
+
+    // Returns the terminal error if the announcer had already given up.
+    pub async fn shutdown(self) -> Result<(), ClientError> {
+        let _ = self.shutdown.send(());
+        self.task.await.unwrap_or(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::{proto::RetryIn, AnnounceObserver, HttpTrackerClient, RetryPolicy};
+    use axum::{
+        extract::{RawQuery, State},
+        http::{header::RETRY_AFTER, HeaderMap, StatusCode},
+        routing::get,
+        Router,
+    };
+    use hanekawa::http_tracker::proto::AnnounceRequest;
+    use hanekawa_common::types::{InfoHash, PeerId};
+    use std::{collections::VecDeque, future::Future, pin::Pin, sync::Mutex};
+    use tokio::sync::mpsc;
+
+    const OK: &[u8] = b"d8:intervali1800e12:min intervali1750e5:peers6:\x7f\x00\x00\x01\x1a\xe1e";
+
+    // Replies are taken from the script in order, and are OK once it runs out.
+    #[derive(Default)]
+    struct Tracker {
+        script: VecDeque<(StatusCode, HeaderMap, &'static [u8])>,
+        seen: Vec<(Event, u64)>,
+    }
+
+    type SharedTracker = Arc<Mutex<Tracker>>;
+
+    async fn announce(
+        State(tracker): State<SharedTracker>,
+        RawQuery(query): RawQuery,
+    ) -> (StatusCode, HeaderMap, &'static [u8]) {
+        let request: AnnounceRequest =
+            hanekawa_percent_encode::from_query_string(&query.unwrap_or_default()).unwrap();
+
+        let mut tracker = tracker.lock().unwrap();
+        tracker.seen.push((request.event, request.left));
+        tracker
+            .script
+            .pop_front()
+            .unwrap_or((StatusCode::OK, HeaderMap::new(), OK))
+    }
+
+    async fn spawn_tracker(
+        script: Vec<(StatusCode, HeaderMap, &'static [u8])>,
+    ) -> (String, SharedTracker) {
+        let tracker = Arc::new(Mutex::new(Tracker {
+            script: script.into(),
+            seen: vec![],
+        }));
+        let app = Router::new()
+            .route("/announce", get(announce))
+            .with_state(tracker.clone());
+
+        let server =
+            axum::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(app.into_make_service());
+        let addr = server.local_addr();
+        tokio::spawn(server);
+
+        (format!("http://{addr}/announce"), tracker)
+    }
+
+    fn seen(tracker: &SharedTracker) -> Vec<(Event, u64)> {
+        tracker.lock().unwrap().seen.clone()
+    }
+
+    // Hands every sleep to the test, which decides when it is over.
+    struct MockClock {
+        sleeps: mpsc::UnboundedSender<(Duration, oneshot::Sender<()>)>,
+    }
+
+    impl Clock for MockClock {
+        fn sleep(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+            let (wake, woken) = oneshot::channel();
+            let _ = self.sleeps.send((duration, wake));
+
+            Box::pin(async move {
+                let _ = woken.await;
+            })
+        }
+    }
+
+    fn params() -> AnnounceParams {
+        AnnounceParams {
+            info_hash: InfoHash(vec![0xaa; 20]),
+            peer_id: PeerId(b"-HK0100-123456789012".to_vec()),
+            port: 6881,
+            uploaded: 0,
+            downloaded: 0,
+            left: 100,
+            event: Event::Started,
+            num_want: None,
+            compact: true,
+            key: None,
+            ip: None,
+        }
+    }
+
+    fn spawn(
+        url: String,
+    ) -> (
+        AnnouncerHandle,
+        mpsc::UnboundedReceiver<(Duration, oneshot::Sender<()>)>,
+    ) {
+        spawn_tiers(Tiers::single(url))
+    }
+
+    fn spawn_tiers(
+        tiers: Tiers,
+    ) -> (
+        AnnouncerHandle,
+        mpsc::UnboundedReceiver<(Duration, oneshot::Sender<()>)>,
+    ) {
+        spawn_client(tiers, client())
+    }
+
+    fn client() -> crate::http::HttpTrackerClientBuilder {
+        HttpTrackerClient::builder().retry_policy(RetryPolicy {
+            initial_backoff: Duration::from_secs(10),
+            max_backoff: Duration::from_secs(30),
+        })
+    }
+
+    fn spawn_client(
+        tiers: Tiers,
+        client: crate::http::HttpTrackerClientBuilder,
+    ) -> (
+        AnnouncerHandle,
+        mpsc::UnboundedReceiver<(Duration, oneshot::Sender<()>)>,
+    ) {
+        let (sleeps, sleeps_rx) = mpsc::unbounded_channel();
+        let handle = Announcer::spawn_with_clock(
+            Arc::new(client.build().unwrap()),
+            tiers,
+            params(),
+            Arc::new(MockClock { sleeps }),
+        );
+
+        (handle, sleeps_rx)
+    }
+
+    fn assert_interval(delay: Duration) {
+        // 1800s with 10% jitter, but never below the 1750s min interval.
+        assert!(
+            delay >= Duration::from_secs(1750) && delay <= Duration::from_secs(1980),
+            "unexpected delay {delay:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn announces_over_the_torrent_lifetime() {
+        let (url, tracker) = spawn_tracker(vec![]).await;
+        let (handle, mut sleeps) = spawn(url);
+
+        let (delay, wake) = sleeps.recv().await.unwrap();
+        assert_interval(delay);
+        assert_eq!(
+            vec![Peer {
+                peer_id: None,
+                addr: "127.0.0.1:6881".parse().unwrap(),
+            }],
+            *handle.peers().borrow()
+        );
+
+        wake.send(()).unwrap();
+        let (delay, _wake) = sleeps.recv().await.unwrap();
+        assert_interval(delay);
+
+        handle.stats().add_downloaded(100);
+        handle.stats().set_left(0);
+        let (delay, _wake) = sleeps.recv().await.unwrap();
+        assert_interval(delay);
+
+        handle.shutdown().await.unwrap();
+
+        assert_eq!(
+            vec![
+                (Event::Started, 100),
+                (Event::Interval, 100),
+                (Event::Completed, 0),
+                (Event::Stopped, 0),
+            ],
+            seen(&tracker)
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_warnings_until_the_next_announce() {
+        let warned: &[u8] = b"d8:intervali1800e15:warning message12:slow down :)5:peers0:e";
+        let (url, _) = spawn_tracker(vec![(StatusCode::OK, HeaderMap::new(), warned)]).await;
+        let (handle, mut sleeps) = spawn(url);
+
+        let (_, wake) = sleeps.recv().await.unwrap();
+        assert_eq!(Some("slow down :)"), handle.warning().borrow().as_deref());
+
+        wake.send(()).unwrap();
+        let (_, _wake) = sleeps.recv().await.unwrap();
+        assert_eq!(None, *handle.warning().borrow());
+    }
+
+    #[tokio::test]
+    async fn waits_for_left_to_reach_zero() {
+        let (url, tracker) = spawn_tracker(vec![]).await;
+        let (handle, mut sleeps) = spawn(url);
+
+        let (_, _wake) = sleeps.recv().await.unwrap();
+        handle.stats().set_left(50);
+        handle.shutdown().await.unwrap();
+
+        assert_eq!(
+            vec![(Event::Started, 100), (Event::Stopped, 50)],
+            seen(&tracker)
+        );
+    }
+
+    #[tokio::test]
+    async fn stops_when_dropped() {
+        let (url, tracker) = spawn_tracker(vec![]).await;
+        let (handle, mut sleeps) = spawn(url);
+
+        let (_, _wake) = sleeps.recv().await.unwrap();
+        drop(handle);
+
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while seen(&tracker).last() != Some(&(Event::Stopped, 100)) {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn shuts_down_in_time_when_the_tracker_never_answers() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let (handle, _sleeps) = spawn(format!(
+            "http://{}/announce",
+            listener.local_addr().unwrap()
+        ));
+
+        // The `started` announce is still waiting when shutdown is asked for.
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        let started = tokio::time::Instant::now();
+        handle.shutdown().await.unwrap();
+
+        assert_eq!(STOPPED_TIMEOUT, started.elapsed());
+    }
+
+    // Lets the announcer give up by itself, before shutdown interrupts it.
+    async fn finished(handle: &AnnouncerHandle) {
+        while !handle.is_finished() {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    fn reply(status: StatusCode, body: &'static [u8]) -> (StatusCode, HeaderMap, &'static [u8]) {
+        (status, HeaderMap::new(), body)
+    }
+
+    fn assert_between(delay: Duration, min: u64, max: u64) {
+        assert!(
+            delay >= Duration::from_secs(min) && delay <= Duration::from_secs(max),
+            "{delay:?} is not within {min}s..={max}s"
+        );
+    }
+
+    #[tokio::test]
+    async fn backs_off_on_server_errors() {
+        let (url, tracker) = spawn_tracker(vec![
+            reply(StatusCode::INTERNAL_SERVER_ERROR, b""),
+            reply(StatusCode::SERVICE_UNAVAILABLE, b""),
+            reply(StatusCode::BAD_GATEWAY, b"<html>"),
+        ])
+        .await;
+        let (handle, mut sleeps) = spawn(url);
+
+        // 10s doubling per failure with equal jitter, capped at 30s.
+        for (min, max) in [(5, 10), (10, 20), (15, 30)] {
+            let (delay, wake) = sleeps.recv().await.unwrap();
+            assert_between(delay, min, max);
+            wake.send(()).unwrap();
+        }
+
+        let (delay, _wake) = sleeps.recv().await.unwrap();
+        assert_interval(delay);
+
+        handle.shutdown().await.unwrap();
+
+        assert_eq!(
+            vec![
+                (Event::Started, 100),
+                (Event::Started, 100),
+                (Event::Started, 100),
+                (Event::Started, 100),
+                (Event::Stopped, 100),
+            ],
+            seen(&tracker)
+        );
+    }
+
+    #[tokio::test]
+    async fn backs_off_on_network_errors() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+
+        let (handle, mut sleeps) = spawn(format!("http://{addr}/announce"));
+
+        let (delay, wake) = sleeps.recv().await.unwrap();
+        assert_between(delay, 5, 10);
+        wake.send(()).unwrap();
+
+        let (delay, _wake) = sleeps.recv().await.unwrap();
+        assert_between(delay, 10, 20);
+
+        handle.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn honors_retry_in() {
+        let (url, tracker) = spawn_tracker(vec![reply(
+            StatusCode::OK,
+            b"d14:failure reason10:overloaded8:retry ini2ee",
+        )])
+        .await;
+        let (handle, mut sleeps) = spawn(url);
+
+        let (delay, wake) = sleeps.recv().await.unwrap();
+        assert_eq!(Duration::from_secs(120), delay);
+        wake.send(()).unwrap();
+
+        let (delay, _wake) = sleeps.recv().await.unwrap();
+        assert_interval(delay);
+
+        handle.shutdown().await.unwrap();
+        assert_eq!(3, seen(&tracker).len());
+    }
+
+    #[tokio::test]
+    async fn honors_retry_after() {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, "42".parse().unwrap());
+        let (url, _tracker) =
+            spawn_tracker(vec![(StatusCode::TOO_MANY_REQUESTS, headers, b"")]).await;
+        let (handle, mut sleeps) = spawn(url);
+
+        let (delay, _wake) = sleeps.recv().await.unwrap();
+        assert_eq!(Duration::from_secs(42), delay);
+
+        handle.shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn gives_up_when_told_to_never_retry() {
+        let (url, tracker) = spawn_tracker(vec![reply(
+            StatusCode::OK,
+            b"d14:failure reason10:overloaded8:retry in5:nevere",
+        )])
+        .await;
+        let (handle, mut sleeps) = spawn(url);
+        finished(&handle).await;
+
+        match handle.shutdown().await {
+            Err(ClientError::Terminal(e)) => assert!(matches!(
+                *e,
+                ClientError::Failure {
+                    retry_in: Some(RetryIn::Never),
+                    ..
+                }
+            )),
+            r => panic!("expected a terminal error, got {r:?}"),
+        }
+
+        assert!(sleeps.recv().await.is_none());
+        assert_eq!(vec![(Event::Started, 100)], seen(&tracker));
+    }
+
+    #[tokio::test]
+    async fn gives_up_on_unregistered_torrents() {
+        let (url, tracker) = spawn_tracker(vec![reply(
+            StatusCode::OK,
+            b"d14:failure reason20:Unregistered torrente",
+        )])
+        .await;
+        let (handle, _sleeps) = spawn(url);
+        finished(&handle).await;
+
+        assert!(matches!(
+            handle.shutdown().await,
+            Err(ClientError::Terminal(_))
+        ));
+        assert_eq!(vec![(Event::Started, 100)], seen(&tracker));
+    }
+
+    #[tokio::test]
+    async fn falls_back_and_promotes_across_tiers() {
+        let (flaky, flaky_tracker) =
+            spawn_tracker(vec![reply(StatusCode::INTERNAL_SERVER_ERROR, b"")]).await;
+        let (broken, broken_tracker) =
+            spawn_tracker(vec![reply(StatusCode::INTERNAL_SERVER_ERROR, b""); 10]).await;
+        let (backup, backup_tracker) = spawn_tracker(vec![]).await;
+
+        let (handle, mut sleeps) = spawn_tiers(Tiers::new(vec![vec![flaky, broken], vec![backup]]));
+
+        // Both first tier trackers fail, so the second tier is used.
+        let (_, wake) = sleeps.recv().await.unwrap();
+        assert_eq!(vec![(Event::Started, 100)], seen(&flaky_tracker));
+        assert_eq!(1, seen(&broken_tracker).len());
+        assert_eq!(vec![(Event::Started, 100)], seen(&backup_tracker));
+        wake.send(()).unwrap();
+
+        // The first tier is tried again and the recovered tracker answers.
+        let (_, wake) = sleeps.recv().await.unwrap();
+        assert_eq!(2, seen(&flaky_tracker).len());
+        let broken_announces = seen(&broken_tracker).len();
+        assert_eq!(1, seen(&backup_tracker).len());
+        wake.send(()).unwrap();
+
+        // It was promoted, so the broken tracker is no longer tried first.
+        let (_, _wake) = sleeps.recv().await.unwrap();
+        assert_eq!(3, seen(&flaky_tracker).len());
+        assert_eq!(broken_announces, seen(&broken_tracker).len());
+        assert_eq!(1, seen(&backup_tracker).len());
+
+        handle.shutdown().await.unwrap();
+        assert_eq!(Some(&(Event::Stopped, 100)), seen(&flaky_tracker).last());
+    }
+
+    #[derive(Clone, Default)]
+    struct Recorder(Arc<Mutex<Vec<String>>>);
+
+    impl Recorder {
+        fn record(&self, event: String) {
+            self.0.lock().unwrap().push(event);
+        }
+
+        fn events(&self) -> Vec<String> {
+            self.0.lock().unwrap().clone()
+        }
+    }
+
+    impl AnnounceObserver for Recorder {
+        fn request_started(&self, _url: &str, event: &Event) {
+            self.record(format!("started {event:?}"));
+        }
+
+        fn request_finished(
+            &self,
+            _url: &str,
+            _elapsed: Duration,
+            outcome: Result<&AnnounceResponse, &ClientError>,
+        ) {
+            match outcome {
+                Ok(_) => self.record("finished".to_string()),
+                Err(e) => self.record(format!("failed {:?}", e.kind())),
+            }
+        }
+
+        fn retry_scheduled(&self, _delay: Duration, failures: u32) {
+            self.record(format!("retry {failures}"));
+        }
+
+        fn tier_switched(&self, from: usize, to: usize) {
+            self.record(format!("tier {from} -> {to}"));
+        }
+
+        fn peers_received(&self, _url: &str, count: usize) {
+            self.record(format!("peers {count}"));
+        }
+    }
+
+    #[tokio::test]
+    async fn reports_to_the_observer() {
+        let (url, _) = spawn_tracker(vec![reply(StatusCode::INTERNAL_SERVER_ERROR, b"")]).await;
+        let recorder = Recorder::default();
+        let (handle, mut sleeps) =
+            spawn_client(Tiers::single(url), client().observer(recorder.clone()));
+
+        let (_, wake) = sleeps.recv().await.unwrap();
+        wake.send(()).unwrap();
+        let (_, _wake) = sleeps.recv().await.unwrap();
+        handle.shutdown().await.unwrap();
+
+        assert_eq!(
+            vec![
+                "started Started",
+                "failed Http",
+                "retry 1",
+                "started Started",
+                "finished",
+                "peers 1",
+                "started Stopped",
+                "finished",
+                "peers 1",
+            ],
+            recorder.events()
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_tier_switches() {
+        let (flaky, _) = spawn_tracker(vec![
+            reply(StatusCode::OK, OK),
+            reply(StatusCode::INTERNAL_SERVER_ERROR, b""),
+        ])
+        .await;
+        let (backup, _) = spawn_tracker(vec![]).await;
+        let recorder = Recorder::default();
+        let (handle, mut sleeps) = spawn_client(
+            Tiers::new(vec![vec![flaky], vec![backup]]),
+            client().observer(recorder.clone()),
+        );
+
+        let (_, wake) = sleeps.recv().await.unwrap();
+        wake.send(()).unwrap();
+        let (_, _wake) = sleeps.recv().await.unwrap();
+        handle.shutdown().await.unwrap();
+
+        let switches: Vec<_> = recorder
+            .events()
+            .into_iter()
+            .filter(|e| e.starts_with("tier"))
+            .collect();
+        // And back for `stopped`, once the first tier answers again.
+        assert_eq!(vec!["tier 0 -> 1", "tier 1 -> 0"], switches);
+    }
+
+    #[tokio::test]
+    async fn outlives_panicking_observers() {
+        struct Panicking;
+
+        impl AnnounceObserver for Panicking {
+            fn request_started(&self, _url: &str, _event: &Event) {
+                panic!("observer bug");
+            }
+
+            fn peers_received(&self, _url: &str, _count: usize) {
+                panic!("observer bug");
+            }
+        }
+
+        let (url, tracker) = spawn_tracker(vec![]).await;
+        let (handle, mut sleeps) = spawn_client(Tiers::single(url), client().observer(Panicking));
+
+        let (_, _wake) = sleeps.recv().await.unwrap();
+        assert_eq!(1, handle.peers().borrow().len());
+        handle.shutdown().await.unwrap();
+
+        assert_eq!(
+            vec![(Event::Started, 100), (Event::Stopped, 100)],
+            seen(&tracker)
+        );
+    }
+
+    #[tokio::test]
+    async fn gives_up_without_trackers() {
+        let (handle, _sleeps) = spawn_tiers(Tiers::new(vec![vec![]]));
+        finished(&handle).await;
+
+        match handle.shutdown().await {
+            Err(ClientError::Terminal(e)) => assert!(matches!(*e, ClientError::NoTrackers)),
+            r => panic!("expected a terminal error, got {r:?}"),
+        }
+    }
+}
@@ -0,0 +1,211 @@
+use crate::proto::RetryIn;
+
+use hanekawa_bencode::DecodeError;
+
+use std::{fmt::Display, future::Future, time::Duration};
+
+// Failure reasons that asking again will not change.
+const PERMANENT_FAILURES: &[&str] = &[
+    "unregistered",
+    "not registered",
+    "not found",
+    "not allowed",
+    "banned",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    // The tracker could not be reached, or did not answer in time.
+    Transport,
+    // The tracker answered with an HTTP error.
+    Http,
+    // The tracker answered with something that is not a valid response.
+    Protocol,
+    // The tracker refused the request and said why.
+    Tracker,
+    // The request could not be made at all.
+    Request,
+}
+
+// Warnings are not errors, they come with successful responses as
+// `warning_message`.
+#[derive(Debug)]
+pub enum ClientError {
+    Http(reqwest::Error),
+    Io(std::io::Error),
+    // No reply arrived before the operation's deadline.
+    Timeout,
+    Status(u16),
+    // HTTP 429, with the `Retry-After` delay if the tracker sent one.
+    RateLimited(Option<Duration>),
+    Redirect(String),
+    Malformed(String),
+    // The body is kept as received, for debugging.
+    Decode {
+        source: DecodeError,
+        body: Vec<u8>,
+    },
+    Failure {
+        reason: String,
+        retry_in: Option<RetryIn>,
+    },
+    InvalidUrl(String),
+    // A tracker URL of a kind no client here speaks, e.g. WebTorrent's ws://.
+    UnsupportedScheme(String),
+    ScrapeUnsupported(String),
+    // The kind of proxy the UDP client was configured with.
+    UdpOverProxy(&'static str),
+    NoTrackers,
+    // Retrying cannot help, e.g. the torrent is not registered.
+    Terminal(Box<ClientError>),
+}
+
+impl ClientError {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Self::Http(e) if e.is_builder() => ErrorKind::Request,
+            Self::Http(_) | Self::Io(_) | Self::Timeout => ErrorKind::Transport,
+            Self::Status(_) | Self::RateLimited(_) | Self::Redirect(_) => ErrorKind::Http,
+            Self::Malformed(_) | Self::Decode { .. } => ErrorKind::Protocol,
+            Self::Failure { .. } => ErrorKind::Tracker,
+            Self::InvalidUrl(_)
+            | Self::UnsupportedScheme(_)
+            | Self::ScrapeUnsupported(_)
+            | Self::UdpOverProxy(_)
+            | Self::NoTrackers => ErrorKind::Request,
+            Self::Terminal(e) => e.kind(),
+        }
+    }
+
+    // Whether asking again later could succeed.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::Http(e) => !e.is_builder(),
+            Self::Io(_) | Self::Timeout | Self::RateLimited(_) => true,
+            Self::Status(status) => *status >= 500,
+            // Often an error page from a proxy in front of the tracker.
+            Self::Malformed(_) | Self::Decode { .. } => true,
+            Self::Failure {
+                retry_in: Some(RetryIn::Never),
+                ..
+            } => false,
+            Self::Failure { reason, .. } => !is_permanent(reason),
+            Self::Redirect(_)
+            | Self::InvalidUrl(_)
+            | Self::UnsupportedScheme(_)
+            | Self::ScrapeUnsupported(_)
+            | Self::UdpOverProxy(_)
+            | Self::NoTrackers
+            | Self::Terminal(_) => false,
+        }
+    }
+
+    // The response body, if it could not be decoded.
+    pub fn body(&self) -> Option<&[u8]> {
+        match self {
+            Self::Decode { body, .. } => Some(body),
+            Self::Terminal(e) => e.body(),
+            _ => None,
+        }
+    }
+}
+
+fn is_permanent(reason: &str) -> bool {
+    let reason = reason.to_lowercase();
+    PERMANENT_FAILURES.iter().any(|p| reason.contains(p))
+}
+
+impl Display for ClientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Http(e) => f.write_fmt(format_args!("http error: {e}")),
+            Self::Io(e) => f.write_fmt(format_args!("io error: {e}")),
+            Self::Timeout => f.write_str("tracker did not respond"),
+            Self::Status(s) => f.write_fmt(format_args!("unexpected http status: {s}")),
+            Self::RateLimited(_) => f.write_str("rate limited by tracker"),
+            Self::Redirect(s) => f.write_fmt(format_args!("redirect failed: {s}")),
+            Self::Malformed(s) => f.write_fmt(format_args!("malformed response: {s}")),
+            Self::Decode { source, .. } => {
+                f.write_fmt(format_args!("malformed response: {source}"))
+            }
+            Self::Failure { reason, .. } => f.write_fmt(format_args!("tracker failure: {reason}")),
+            Self::InvalidUrl(s) => f.write_fmt(format_args!("invalid url: {s}")),
+            Self::UnsupportedScheme(s) => f.write_fmt(format_args!("unsupported tracker: {s}")),
+            Self::ScrapeUnsupported(s) => {
+                f.write_fmt(format_args!("no scrape url can be derived from {s}"))
+            }
+            Self::UdpOverProxy(kind) => f.write_fmt(format_args!(
+                "UDP not supported over this proxy type ({kind})"
+            )),
+            Self::NoTrackers => f.write_str("no trackers to announce to"),
+            Self::Terminal(e) => f.write_fmt(format_args!("giving up: {e}")),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Http(e) => Some(e),
+            Self::Io(e) => Some(e),
+            Self::Decode { source, .. } => Some(source),
+            Self::Terminal(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for ClientError {
+    fn from(value: reqwest::Error) -> Self {
+        Self::Http(value)
+    }
+}
+
+impl From<std::io::Error> for ClientError {
+    fn from(value: std::io::Error) -> Self {
+        Self::Io(value)
+    }
+}
+
+// Bounds a whole operation, retries and redirects included. Whatever the
+// operation holds, sockets included, is dropped with it.
+pub(crate) async fn deadline<T>(
+    timeout: Duration,
+    operation: impl Future<Output = Result<T, ClientError>>,
+) -> Result<T, ClientError> {
+    tokio::time::timeout(timeout, operation)
+        .await
+        .unwrap_or(Err(ClientError::Timeout))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::error::Error;
+
+    #[test]
+    fn chains_sources() {
+        let io = std::io::Error::new(std::io::ErrorKind::ConnectionRefused, "refused");
+        let e = ClientError::Terminal(Box::new(ClientError::Io(io)));
+
+        let source = e.source().unwrap();
+        assert_eq!("io error: refused", source.to_string());
+        assert_eq!("refused", source.source().unwrap().to_string());
+        assert_eq!(ErrorKind::Transport, e.kind());
+        assert!(!e.is_retryable());
+    }
+
+    #[test]
+    fn classifies_tracker_failures() {
+        let failure = |reason: &str, retry_in| ClientError::Failure {
+            reason: reason.to_string(),
+            retry_in,
+        };
+
+        assert!(failure("tracker is overloaded", None).is_retryable());
+        assert!(!failure("Unregistered torrent", None).is_retryable());
+        assert!(!failure("overloaded", Some(RetryIn::Never)).is_retryable());
+        assert_eq!(ErrorKind::Tracker, failure("banned", None).kind());
+    }
+}
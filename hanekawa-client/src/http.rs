@@ -0,0 +1,1433 @@
+use crate::key::AnnounceKey;
+use crate::proto::{AnnounceParams, AnnounceResponse, RetryIn, ScrapeResponse};
+use crate::proxy::Proxy;
+use crate::retry::RetryPolicy;
+use crate::{error::deadline, AnnounceObserver, ClientError};
+
+use hanekawa_common::types::{Event, InfoHash, PeerId};
+
+use percent_encoding::{percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use reqwest::{
+    header::{LOCATION, RETRY_AFTER},
+    redirect, StatusCode, Url,
+};
+use serde::de::DeserializeOwned;
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use url::Host;
+
+// Everything but the RFC 3986 unreserved characters is escaped, so binary
+// values like info_hash survive the trip byte-for-byte.
+const QUERY_VALUE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_MAX_REDIRECTS: usize = 5;
+const DEFAULT_USER_AGENT: &str = concat!("hanekawa/", env!("CARGO_PKG_VERSION"));
+
+pub const DEFAULT_PEER_ID_PREFIX: &str = "-HK0100-";
+
+// Public addresses, only used to find the local address routes to the
+// internet go out from. Nothing is sent to them.
+const ROUTE_PROBES: [&str; 2] = ["8.8.8.8:53", "[2001:4860:4860::8888]:53"];
+
+// What the underlying reqwest client is built from.
+#[derive(Clone)]
+struct Connection {
+    pool_idle_timeout: Option<Duration>,
+    user_agent: String,
+    proxy: Option<Proxy>,
+    local_address: Option<IpAddr>,
+}
+
+impl Connection {
+    fn client(&self) -> Result<reqwest::Client, ClientError> {
+        // Redirects are followed by hand, reqwest would drop the query string
+        // when a tracker redirects to a bare path.
+        let mut http = reqwest::Client::builder()
+            .user_agent(&self.user_agent)
+            .redirect(redirect::Policy::none());
+        if let Some(pool_idle_timeout) = self.pool_idle_timeout {
+            http = http.pool_idle_timeout(pool_idle_timeout);
+        }
+        if let Some(local_address) = self.local_address {
+            http = http.local_address(local_address);
+        }
+        // Without one, reqwest would pick a proxy up from the environment.
+        http = match &self.proxy {
+            Some(proxy) => http.proxy(proxy.to_reqwest()?),
+            None => http.no_proxy(),
+        };
+
+        Ok(http.build()?)
+    }
+}
+
+pub struct HttpTrackerClientBuilder {
+    connection: Connection,
+    announce_timeout: Duration,
+    scrape_timeout: Duration,
+    retry_policy: RetryPolicy,
+    max_redirects: usize,
+    allow_downgrade: bool,
+    peer_id: Option<PeerId>,
+    advertised: Vec<SocketAddr>,
+    detect_addresses: bool,
+    announce_key: Option<AnnounceKey>,
+    observer: Option<Arc<dyn AnnounceObserver>>,
+}
+
+impl HttpTrackerClientBuilder {
+    // For both announces and scrapes, redirects included.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.announce_timeout = timeout;
+        self.scrape_timeout = timeout;
+        self
+    }
+
+    pub fn announce_timeout(mut self, announce_timeout: Duration) -> Self {
+        self.announce_timeout = announce_timeout;
+        self
+    }
+
+    pub fn scrape_timeout(mut self, scrape_timeout: Duration) -> Self {
+        self.scrape_timeout = scrape_timeout;
+        self
+    }
+
+    pub fn max_redirects(mut self, max_redirects: usize) -> Self {
+        self.max_redirects = max_redirects;
+        self
+    }
+
+    // Whether redirects from https to http are followed.
+    pub fn allow_downgrade(mut self, allow_downgrade: bool) -> Self {
+        self.allow_downgrade = allow_downgrade;
+        self
+    }
+
+    // How long idle connections are kept for reuse by later announces.
+    pub fn pool_idle_timeout(mut self, pool_idle_timeout: Duration) -> Self {
+        self.connection.pool_idle_timeout = Some(pool_idle_timeout);
+        self
+    }
+
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.connection.user_agent = user_agent.into();
+        self
+    }
+
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.connection.proxy = Some(proxy);
+        self
+    }
+
+    // The address announces are sent from, on hosts with more than one.
+    pub fn local_address(mut self, local_address: IpAddr) -> Self {
+        self.connection.local_address = Some(local_address);
+        self
+    }
+
+    // BEP 7: an endpoint this client listens on, sent as `ipv4=` or `ipv6=`
+    // to trackers reached over the other address family. One per family.
+    pub fn advertise(mut self, endpoint: SocketAddr) -> Self {
+        self.advertised
+            .retain(|e| e.is_ipv4() != endpoint.is_ipv4());
+        self.advertised.push(endpoint);
+        self
+    }
+
+    // Advertises the host's addresses in families without an explicit
+    // endpoint, on the announced port. They are looked up once, on build.
+    pub fn detect_addresses(mut self, detect_addresses: bool) -> Self {
+        self.detect_addresses = detect_addresses;
+        self
+    }
+
+    // Sent when the announce parameters have no key of their own.
+    pub fn announce_key(mut self, announce_key: AnnounceKey) -> Self {
+        self.announce_key = Some(announce_key);
+        self
+    }
+
+    // Generated with the default prefix if not set.
+    pub fn peer_id(mut self, peer_id: PeerId) -> Self {
+        self.peer_id = Some(peer_id);
+        self
+    }
+
+    // Told about the announces of announcers using this client.
+    pub fn observer(mut self, observer: impl AnnounceObserver + 'static) -> Self {
+        self.observer = Some(Arc::new(observer));
+        self
+    }
+
+    pub fn build(self) -> Result<HttpTrackerClient, ClientError> {
+        let peer_id = self.peer_id.unwrap_or_else(|| {
+            PeerId::generate(DEFAULT_PEER_ID_PREFIX).expect("the default prefix is valid")
+        });
+
+        let mut advertised: Vec<_> = self
+            .advertised
+            .iter()
+            .map(|e| Advertised {
+                ip: e.ip(),
+                port: Some(e.port()),
+            })
+            .collect();
+        if self.detect_addresses {
+            for ip in detect_addresses() {
+                if !advertised.iter().any(|a| a.ip.is_ipv4() == ip.is_ipv4()) {
+                    advertised.push(Advertised { ip, port: None });
+                }
+            }
+        }
+
+        Ok(HttpTrackerClient {
+            http: self.connection.client()?,
+            connection: self.connection,
+            announce_timeout: self.announce_timeout,
+            scrape_timeout: self.scrape_timeout,
+            peer_id,
+            retry_policy: self.retry_policy,
+            max_redirects: self.max_redirects,
+            allow_downgrade: self.allow_downgrade,
+            advertised,
+            announce_key: self.announce_key,
+            observer: self.observer,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestInfo {
+    // Where the response finally came from, after redirects.
+    pub url: String,
+    pub redirects: usize,
+    pub elapsed: Duration,
+}
+
+// Clones share one connection pool.
+#[derive(Clone)]
+pub struct HttpTrackerClient {
+    http: reqwest::Client,
+    connection: Connection,
+    announce_timeout: Duration,
+    scrape_timeout: Duration,
+    peer_id: PeerId,
+    retry_policy: RetryPolicy,
+    max_redirects: usize,
+    allow_downgrade: bool,
+    advertised: Vec<Advertised>,
+    announce_key: Option<AnnounceKey>,
+    observer: Option<Arc<dyn AnnounceObserver>>,
+}
+
+// An address to advertise, with its own port or the announced one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Advertised {
+    ip: IpAddr,
+    port: Option<u16>,
+}
+
+impl HttpTrackerClient {
+    pub fn builder() -> HttpTrackerClientBuilder {
+        HttpTrackerClientBuilder {
+            connection: Connection {
+                pool_idle_timeout: None,
+                user_agent: DEFAULT_USER_AGENT.to_string(),
+                proxy: None,
+                local_address: None,
+            },
+            announce_timeout: DEFAULT_TIMEOUT,
+            scrape_timeout: DEFAULT_TIMEOUT,
+            retry_policy: RetryPolicy::default(),
+            max_redirects: DEFAULT_MAX_REDIRECTS,
+            allow_downgrade: false,
+            peer_id: None,
+            advertised: vec![],
+            detect_addresses: false,
+            announce_key: None,
+            observer: None,
+        }
+    }
+
+    pub fn new() -> Result<Self, ClientError> {
+        Self::builder().build()
+    }
+
+    // The same client going through another proxy, or none, e.g. for the
+    // trackers of a single torrent. It gets a connection pool of its own.
+    pub fn with_proxy(&self, proxy: Option<Proxy>) -> Result<Self, ClientError> {
+        let mut client = self.clone();
+        client.connection.proxy = proxy;
+        client.http = client.connection.client()?;
+
+        Ok(client)
+    }
+
+    pub fn proxy(&self) -> Option<&Proxy> {
+        self.connection.proxy.as_ref()
+    }
+
+    pub fn retry_policy(&self) -> &RetryPolicy {
+        &self.retry_policy
+    }
+
+    pub fn observer(&self) -> Option<Arc<dyn AnnounceObserver>> {
+        self.observer.clone()
+    }
+
+    // The same for every announce, and shared by clones.
+    pub fn peer_id(&self) -> &PeerId {
+        &self.peer_id
+    }
+
+    pub async fn announce(
+        &self,
+        url: &str,
+        params: AnnounceParams,
+    ) -> Result<AnnounceResponse, ClientError> {
+        Ok(self.announce_with_info(url, params).await?.0)
+    }
+
+    pub async fn announce_with_info(
+        &self,
+        url: &str,
+        mut params: AnnounceParams,
+    ) -> Result<(AnnounceResponse, RequestInfo), ClientError> {
+        if let (None, Some(key)) = (&params.key, &self.announce_key) {
+            params.key = Some(key.for_tracker(url));
+        }
+
+        let mut full = announce_url(url, &params);
+        push_advertised(&mut full, url, &self.advertised, params.port);
+
+        deadline(self.announce_timeout, self.get(&full)).await
+    }
+
+    // BEP 48: Tracker Protocol Extension: Scrape
+    pub async fn scrape(
+        &self,
+        announce_url: &str,
+        info_hashes: &[InfoHash],
+    ) -> Result<ScrapeResponse, ClientError> {
+        Ok(self.scrape_with_info(announce_url, info_hashes).await?.0)
+    }
+
+    pub async fn scrape_with_info(
+        &self,
+        announce_url: &str,
+        info_hashes: &[InfoHash],
+    ) -> Result<(ScrapeResponse, RequestInfo), ClientError> {
+        let mut url = scrape_url(announce_url)?;
+        for info_hash in info_hashes {
+            push_param(&mut url, "info_hash", &info_hash.0);
+        }
+
+        deadline(self.scrape_timeout, self.get(&url)).await
+    }
+
+    async fn get<T: DeserializeOwned>(&self, url: &str) -> Result<(T, RequestInfo), ClientError> {
+        let started = Instant::now();
+
+        let mut url =
+            Url::parse(url).map_err(|e| ClientError::InvalidUrl(format!("{url}: {e}")))?;
+        let query = url.query().map(ToString::to_string);
+
+        let mut redirects = 0;
+        let response = loop {
+            let response = self.http.get(url.clone()).send().await?;
+            let location = match response.headers().get(LOCATION) {
+                Some(location) if response.status().is_redirection() => location,
+                _ => break response,
+            };
+
+            if redirects == self.max_redirects {
+                return Err(ClientError::Redirect(format!(
+                    "more than {} redirects",
+                    self.max_redirects
+                )));
+            }
+
+            let location = location
+                .to_str()
+                .map_err(|_| ClientError::Redirect("location is not ascii".to_string()))?;
+            url = redirect_target(&url, location, query.as_deref(), self.allow_downgrade)?;
+            redirects += 1;
+        };
+
+        let status = response.status();
+        // Only the delay-seconds form, HTTP dates fall back to backing off.
+        let retry_after = response
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse().ok())
+            .map(Duration::from_secs);
+        let body = response.bytes().await?;
+
+        // Trackers may pair a failure reason with an error status, so the
+        // body takes precedence over the status code.
+        let response = match decode(&body) {
+            Err(e @ ClientError::Failure { .. }) => Err(e),
+            _ if status == StatusCode::TOO_MANY_REQUESTS => {
+                Err(ClientError::RateLimited(retry_after))
+            }
+            _ if !status.is_success() => Err(ClientError::Status(status.as_u16())),
+            result => result,
+        }?;
+
+        let info = RequestInfo {
+            url: url.to_string(),
+            redirects,
+            elapsed: started.elapsed(),
+        };
+
+        Ok((response, info))
+    }
+}
+
+fn redirect_target(
+    from: &Url,
+    location: &str,
+    query: Option<&str>,
+    allow_downgrade: bool,
+) -> Result<Url, ClientError> {
+    let mut to = from
+        .join(location)
+        .map_err(|e| ClientError::Redirect(format!("invalid location {location}: {e}")))?;
+
+    match (from.scheme(), to.scheme()) {
+        ("https", "http") if !allow_downgrade => {
+            return Err(ClientError::Redirect(format!(
+                "refusing to downgrade to {to}"
+            )))
+        }
+        (_, "http" | "https") => {}
+        (_, scheme) => {
+            return Err(ClientError::Redirect(format!(
+                "unsupported scheme {scheme}"
+            )))
+        }
+    }
+
+    // The announce parameters still have to reach a redirect to a bare path.
+    if to.query().is_none() {
+        to.set_query(query);
+    }
+
+    Ok(to)
+}
+
+fn push_param(url: &mut String, key: &str, value: &[u8]) {
+    url.push(if url.contains('?') { '&' } else { '?' });
+    url.push_str(key);
+    url.push('=');
+    url.extend(percent_encode(value, QUERY_VALUE));
+}
+
+fn announce_url(base: &str, params: &AnnounceParams) -> String {
+    let mut url = base.to_string();
+
+    push_param(&mut url, "info_hash", &params.info_hash.0);
+    push_param(&mut url, "peer_id", &params.peer_id.0);
+    push_param(&mut url, "port", params.port.to_string().as_bytes());
+    push_param(&mut url, "uploaded", params.uploaded.to_string().as_bytes());
+    push_param(
+        &mut url,
+        "downloaded",
+        params.downloaded.to_string().as_bytes(),
+    );
+    push_param(&mut url, "left", params.left.to_string().as_bytes());
+    push_param(
+        &mut url,
+        "compact",
+        if params.compact { b"1" } else { b"0" },
+    );
+
+    if params.event != Event::Interval {
+        push_param(&mut url, "event", params.event.to_string().as_bytes());
+    }
+
+    if let Some(num_want) = params.num_want {
+        push_param(&mut url, "numwant", num_want.to_string().as_bytes());
+    }
+
+    if let Some(key) = &params.key {
+        push_param(&mut url, "key", key.as_bytes());
+    }
+
+    if let Some(ip) = params.ip {
+        push_param(&mut url, "ip", ip.to_string().as_bytes());
+    }
+
+    url
+}
+
+// Only addresses in a family the tracker is known not to be reached over are
+// sent. For host names that is up to the resolver, so all of them are, and
+// the tracker ignores the one it sees anyway.
+fn push_advertised(url: &mut String, tracker: &str, advertised: &[Advertised], port: u16) {
+    let ipv4 = Url::parse(tracker).ok().and_then(|u| match u.host() {
+        Some(Host::Ipv4(_)) => Some(true),
+        Some(Host::Ipv6(_)) => Some(false),
+        _ => None,
+    });
+
+    for a in advertised.iter().filter(|a| Some(a.ip.is_ipv4()) != ipv4) {
+        let port = a.port.unwrap_or(port);
+        match a.ip {
+            IpAddr::V4(ip) => push_param(url, "ipv4", format!("{ip}:{port}").as_bytes()),
+            IpAddr::V6(ip) => push_param(url, "ipv6", format!("[{ip}]:{port}").as_bytes()),
+        }
+    }
+}
+
+fn detect_addresses() -> Vec<IpAddr> {
+    ROUTE_PROBES
+        .iter()
+        .filter_map(|probe| {
+            let probe: SocketAddr = probe.parse().unwrap();
+            let local: SocketAddr = if probe.is_ipv4() {
+                "0.0.0.0:0".parse().unwrap()
+            } else {
+                "[::]:0".parse().unwrap()
+            };
+
+            // Connecting a UDP socket only picks a route.
+            let socket = std::net::UdpSocket::bind(local).ok()?;
+            socket.connect(probe).ok()?;
+            let ip = socket.local_addr().ok()?.ip();
+
+            (!ip.is_loopback() && !ip.is_unspecified()).then_some(ip)
+        })
+        .collect()
+}
+
+// By convention the scrape URL is the announce URL with the `announce` at the
+// start of the final path segment replaced by `scrape`.
+fn scrape_url(announce_url: &str) -> Result<String, ClientError> {
+    let unsupported = || ClientError::ScrapeUnsupported(announce_url.to_string());
+
+    let (path, query) = match announce_url.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (announce_url, None),
+    };
+
+    // Only look at the path, never at the scheme or host.
+    let authority = path.find("://").map(|i| i + 3).unwrap_or(0);
+    let (base, segment) = path
+        .rsplit_once('/')
+        .filter(|(base, _)| base.len() >= authority)
+        .ok_or_else(unsupported)?;
+    let rest = segment.strip_prefix("announce").ok_or_else(unsupported)?;
+
+    let mut url = format!("{base}/scrape{rest}");
+    if let Some(query) = query {
+        url.push('?');
+        url.push_str(query);
+    }
+
+    Ok(url)
+}
+
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum Body<T> {
+    Failure {
+        #[serde(rename = "failure reason")]
+        failure_reason: String,
+        #[serde(rename = "retry in")]
+        retry_in: Option<RawRetryIn>,
+    },
+    Success(T),
+}
+
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum RawRetryIn {
+    Minutes(u64),
+    Text(String),
+}
+
+impl RawRetryIn {
+    // BEP 31 gives the delay in minutes, anything but "never" is ignored.
+    fn into_retry_in(self) -> Option<RetryIn> {
+        match self {
+            Self::Minutes(m) => Some(RetryIn::After(Duration::from_secs(m * 60))),
+            Self::Text(s) if s == "never" => Some(RetryIn::Never),
+            Self::Text(_) => None,
+        }
+    }
+}
+
+fn decode<T: DeserializeOwned>(body: &[u8]) -> Result<T, ClientError> {
+    match hanekawa_bencode::from_bytes(body) {
+        Ok(Body::Failure {
+            failure_reason,
+            retry_in,
+        }) => Err(ClientError::Failure {
+            reason: failure_reason,
+            retry_in: retry_in.and_then(RawRetryIn::into_retry_in),
+        }),
+        Ok(Body::Success(response)) => Ok(response),
+        // An untagged mismatch only says that no variant matched, the
+        // success type itself gives a more useful error.
+        Err(e) => Err(ClientError::Decode {
+            source: hanekawa_bencode::from_bytes::<T>(body).err().unwrap_or(e),
+            body: body.to_vec(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::proto::{DictPeer, Peer, PeerList, ScrapeFile};
+    use hanekawa_common::types::{InfoHash, PeerId};
+    use std::net::SocketAddr;
+
+    fn params() -> AnnounceParams {
+        let mut info_hash = vec![0x00, 0xff, b' ', b'+', b'%', b'~'];
+        info_hash.resize(20, 0xab);
+
+        AnnounceParams {
+            info_hash: InfoHash(info_hash),
+            peer_id: PeerId(b"-HK0100-123456789012".to_vec()),
+            port: 6881,
+            uploaded: 1,
+            downloaded: 2,
+            left: 3,
+            event: Event::Started,
+            num_want: Some(25),
+            compact: true,
+            key: Some("a1b2".to_string()),
+            ip: None,
+        }
+    }
+
+    #[test]
+    fn percent_encodes_binary_parameters() {
+        let url = announce_url("http://tracker.test/announce", &params());
+
+        assert_eq!(
+            "http://tracker.test/announce\
+            ?info_hash=%00%FF%20%2B%25~%AB%AB%AB%AB%AB%AB%AB%AB%AB%AB%AB%AB%AB%AB\
+            &peer_id=-HK0100-123456789012\
+            &port=6881&uploaded=1&downloaded=2&left=3&compact=1\
+            &event=started&numwant=25&key=a1b2",
+            url
+        );
+    }
+
+    #[test]
+    fn appends_to_existing_query_string() {
+        let url = announce_url("http://tracker.test/announce.php?passkey=abc", &params());
+
+        assert!(url.starts_with("http://tracker.test/announce.php?passkey=abc&info_hash="));
+    }
+
+    #[test]
+    fn advertises_addresses_of_the_other_family() {
+        let advertised = [
+            Advertised {
+                ip: "2001:db8::1".parse().unwrap(),
+                port: Some(51413),
+            },
+            Advertised {
+                ip: "192.0.2.1".parse().unwrap(),
+                port: None,
+            },
+        ];
+
+        for (tracker, expected) in [
+            (
+                "http://192.0.2.9/announce",
+                "?ipv6=%5B2001%3Adb8%3A%3A1%5D%3A51413",
+            ),
+            ("http://[2001:db8::9]/announce", "?ipv4=192.0.2.1%3A6881"),
+            (
+                "http://tracker.test/announce",
+                "?ipv6=%5B2001%3Adb8%3A%3A1%5D%3A51413&ipv4=192.0.2.1%3A6881",
+            ),
+        ] {
+            let mut query = String::new();
+            push_advertised(&mut query, tracker, &advertised, 6881);
+
+            assert_eq!(expected, query, "{tracker}");
+        }
+    }
+
+    #[test]
+    fn omits_event_for_regular_announces() {
+        let mut params = params();
+        params.event = Event::Interval;
+
+        assert!(!announce_url("http://tracker.test/announce", &params).contains("event="));
+    }
+
+    // Captured from an opentracker instance.
+    const OPENTRACKER_COMPACT: &[u8] = b"d8:completei4e10:downloadedi12e10:incompletei1e8:intervali1800e12:min intervali900e5:peers12:\x0a\x00\x00\x01\x1a\xe1\xc0\xa8\x01\x02\xc8\xd5e";
+
+    #[test]
+    fn parses_compact_response() {
+        let response = decode::<AnnounceResponse>(OPENTRACKER_COMPACT).unwrap();
+
+        assert_eq!(
+            AnnounceResponse {
+                interval: 1800,
+                min_interval: Some(900),
+                complete: Some(4),
+                incomplete: Some(1),
+                warning_message: None,
+                tracker_id: None,
+                peers: PeerList::Compact(vec![10, 0, 0, 1, 0x1a, 0xe1, 192, 168, 1, 2, 0xc8, 0xd5]),
+                peers6: None,
+            },
+            response
+        );
+    }
+
+    #[test]
+    fn parses_dictionary_response() {
+        let body = b"d8:intervali900e5:peersld2:ip8:10.0.0.17:peer id20:-TR2940-abcdefghijkl4:porti51413eed2:ip3:::14:porti6881eeee";
+
+        let response = decode::<AnnounceResponse>(body).unwrap();
+
+        assert_eq!(
+            PeerList::Dict(vec![
+                DictPeer {
+                    peer_id: Some(PeerId(b"-TR2940-abcdefghijkl".to_vec())),
+                    ip: "10.0.0.1".to_string(),
+                    port: 51413,
+                },
+                DictPeer {
+                    peer_id: None,
+                    ip: "::1".to_string(),
+                    port: 6881,
+                }
+            ]),
+            response.peers
+        );
+    }
+
+    #[test]
+    fn extracts_failure_reason() {
+        let body = b"d14:failure reason17:torrent not founde";
+
+        match decode::<AnnounceResponse>(body) {
+            Err(ClientError::Failure { reason, .. }) => assert_eq!("torrent not found", reason),
+            r => panic!("expected a tracker failure, got {r:?}"),
+        }
+        match decode::<ScrapeResponse>(body) {
+            Err(ClientError::Failure { reason, .. }) => assert_eq!("torrent not found", reason),
+            r => panic!("expected a tracker failure, got {r:?}"),
+        }
+    }
+
+    #[test]
+    fn rejects_responses_without_interval() {
+        match decode::<AnnounceResponse>(b"d5:peers0:e") {
+            Err(e @ ClientError::Decode { .. }) => {
+                assert_eq!(
+                    "malformed response: other error: missing field `interval`",
+                    e.to_string()
+                );
+                assert_eq!(Some(&b"d5:peers0:e"[..]), e.body());
+            }
+            r => panic!("expected a malformed response, got {r:?}"),
+        }
+    }
+
+    // Responses captured from public trackers, trimmed to a few peers.
+    const CORPUS: &[(&str, &[u8])] = &[
+        ("opentracker", OPENTRACKER_COMPACT),
+        ("opentrackr", OPENTRACKR_COMPACT),
+        (
+            "chihaya",
+            b"d8:completei0e10:incompletei1e8:intervali1800e12:min intervali900e5:peers0:6:peers60:e",
+        ),
+        (
+            "qbittorrent",
+            b"d8:intervali1800e5:peers6:\x7f\x00\x00\x01\x1a\xe110:tracker id8:\xde\xad\xbe\xef\x00\x01\x02\x03e",
+        ),
+        (
+            "hefur",
+            b"d8:completei2e10:incompletei3e8:intervali600e5:peers0:15:warning message25:announcing too frequentlye",
+        ),
+        ("minimal", b"d8:intervali30ee"),
+    ];
+
+    #[test]
+    fn decodes_captured_responses() {
+        for (name, body) in CORPUS {
+            let response = decode::<AnnounceResponse>(body)
+                .unwrap_or_else(|e| panic!("failed to decode {name}: {e}"));
+            assert!(response.interval > 0, "{name}");
+            response
+                .peers()
+                .unwrap_or_else(|e| panic!("invalid peers from {name}: {e}"));
+        }
+    }
+
+    #[test]
+    fn decodes_optional_keys() {
+        let qbittorrent = decode::<AnnounceResponse>(CORPUS[3].1).unwrap();
+        assert_eq!(
+            Some(vec![0xde, 0xad, 0xbe, 0xef, 0, 1, 2, 3]),
+            qbittorrent.tracker_id
+        );
+
+        let hefur = decode::<AnnounceResponse>(CORPUS[4].1).unwrap();
+        assert_eq!(
+            Some("announcing too frequently".to_string()),
+            hefur.warning_message
+        );
+
+        let minimal = decode::<AnnounceResponse>(CORPUS[5].1).unwrap();
+        assert_eq!(PeerList::Compact(vec![]), minimal.peers);
+        assert_eq!(None, minimal.peers6);
+        assert_eq!(None, minimal.complete);
+    }
+
+    #[test]
+    fn normalizes_compact_peers() {
+        let response = decode::<AnnounceResponse>(OPENTRACKER_COMPACT).unwrap();
+
+        assert_eq!(
+            vec![
+                Peer {
+                    peer_id: None,
+                    addr: "10.0.0.1:6881".parse().unwrap(),
+                },
+                Peer {
+                    peer_id: None,
+                    addr: "192.168.1.2:51413".parse().unwrap(),
+                },
+            ],
+            response.peers().unwrap()
+        );
+    }
+
+    // Captured from opentrackr.org, which always sends `peers6`.
+    const OPENTRACKR_COMPACT: &[u8] = b"d8:completei1e10:incompletei0e8:intervali1940e12:min intervali970e5:peers6:\x5d\xb8\xd8\x22\xc8\xd56:peers618:\x2a\x01\x04\xf8\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x01\x1a\xe1e";
+
+    #[test]
+    fn normalizes_ipv6_peers() {
+        let response = decode::<AnnounceResponse>(OPENTRACKR_COMPACT).unwrap();
+
+        assert_eq!(
+            vec![
+                "93.184.216.34:51413".parse::<SocketAddr>().unwrap(),
+                "[2a01:4f8::1]:6881".parse().unwrap(),
+            ],
+            response
+                .peers()
+                .unwrap()
+                .into_iter()
+                .map(|p| p.addr)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn normalizes_dictionary_peers() {
+        let body = b"d8:intervali900e5:peersld2:ip8:10.0.0.17:peer id20:-TR2940-abcdefghijkl4:porti51413eed2:ip16:peer.example.org4:porti1eed2:ip3:::14:porti6881eed2:ip13:[2001:db8::1]4:porti6881eeee";
+
+        let peers = decode::<AnnounceResponse>(body).unwrap().peers().unwrap();
+
+        assert_eq!(
+            vec![
+                Peer {
+                    peer_id: Some(PeerId(b"-TR2940-abcdefghijkl".to_vec())),
+                    addr: "10.0.0.1:51413".parse().unwrap(),
+                },
+                Peer {
+                    peer_id: None,
+                    addr: "[::1]:6881".parse().unwrap(),
+                },
+                Peer {
+                    peer_id: None,
+                    addr: "[2001:db8::1]:6881".parse().unwrap(),
+                },
+            ],
+            peers
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_compact_peers() {
+        let body = b"d8:intervali900e5:peers6:\x0a\x00\x00\x01\x1a\xe16:peers617:\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x01\x1ae";
+
+        match decode::<AnnounceResponse>(body).unwrap().peers() {
+            Err(ClientError::Malformed(e)) => {
+                assert_eq!("compact peers6 is 17 bytes, not a multiple of 18", e)
+            }
+            r => panic!("expected a malformed response, got {r:?}"),
+        }
+    }
+
+    #[test]
+    fn keeps_one_peer_id_per_client() {
+        let client = HttpTrackerClient::new().unwrap();
+
+        assert_eq!(client.peer_id(), client.clone().peer_id());
+        assert_eq!("HK", client.peer_id().client().unwrap().id);
+        assert_ne!(
+            client.peer_id(),
+            HttpTrackerClient::new().unwrap().peer_id()
+        );
+
+        let peer_id = PeerId::generate("-XX1234-").unwrap();
+        let client = HttpTrackerClient::builder()
+            .peer_id(peer_id.clone())
+            .build()
+            .unwrap();
+        assert_eq!(&peer_id, client.peer_id());
+    }
+
+    #[test]
+    fn refuses_redirect_downgrades() {
+        let from = Url::parse("https://tracker.test/announce?info_hash=%00").unwrap();
+
+        assert!(matches!(
+            redirect_target(&from, "http://tracker.test/announce", None, false),
+            Err(ClientError::Redirect(_))
+        ));
+        assert!(matches!(
+            redirect_target(&from, "udp://tracker.test:6969", None, true),
+            Err(ClientError::Redirect(_))
+        ));
+        assert_eq!(
+            "http://tracker.test/a.php?info_hash=%00",
+            redirect_target(
+                &from,
+                "http://tracker.test/a.php",
+                Some("info_hash=%00"),
+                true
+            )
+            .unwrap()
+            .as_str()
+        );
+    }
+
+    mod roundtrip {
+        use super::*;
+
+        use hanekawa::http_tracker::{
+            encode_peers, proto::AnnounceResponse as ServerAnnounceResponse,
+        };
+        use hanekawa_common::types::{Peer as ServerPeer, PeerSource};
+        use proptest::prelude::*;
+        use std::net::IpAddr;
+
+        fn server_peer() -> impl Strategy<Value = ServerPeer> {
+            (
+                prop::collection::vec(any::<u8>(), 20),
+                any::<IpAddr>(),
+                any::<u16>(),
+            )
+                .prop_map(|(peer_id, ip, port)| ServerPeer {
+                    peer_id: PeerId(peer_id),
+                    ip,
+                    port,
+                    connectable: None,
+                    seeding: false,
+                    source: PeerSource::Announce,
+                })
+        }
+
+        fn encode(peers: Vec<ServerPeer>, is_compact: bool) -> Vec<u8> {
+            let (peers, peers6) = encode_peers(peers, is_compact, true);
+            let response = ServerAnnounceResponse {
+                interval: 60,
+                min_interval: 30,
+                peers,
+                peers6,
+                stats: None,
+                warnings: vec![],
+            };
+
+            hanekawa_bencode::to_bytes(&response).unwrap().to_vec()
+        }
+
+        // The client returns IPv4 peers first, like the server splits them.
+        fn expected(peers: &[ServerPeer], with_peer_id: bool) -> Vec<Peer> {
+            let (v4, v6): (Vec<_>, Vec<_>) = peers.iter().partition(|p| p.ip.is_ipv4());
+            v4.into_iter()
+                .chain(v6)
+                .map(|p| Peer {
+                    peer_id: with_peer_id.then(|| p.peer_id.clone()),
+                    addr: SocketAddr::new(p.ip, p.port),
+                })
+                .collect()
+        }
+
+        proptest! {
+            #[test]
+            fn compact_peers(peers in prop::collection::vec(server_peer(), 0..50)) {
+                let response = decode::<AnnounceResponse>(&encode(peers.clone(), true)).unwrap();
+
+                prop_assert_eq!(expected(&peers, false), response.peers().unwrap());
+            }
+
+            #[test]
+            fn dictionary_peers(peers in prop::collection::vec(server_peer(), 0..50)) {
+                let response = decode::<AnnounceResponse>(&encode(peers.clone(), false)).unwrap();
+
+                prop_assert_eq!(expected(&peers, true), response.peers().unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn derives_scrape_url() {
+        assert_eq!(
+            "http://tracker.test/scrape",
+            scrape_url("http://tracker.test/announce").unwrap()
+        );
+        assert_eq!(
+            "http://tracker.test/x/scrape.php?passkey=abc",
+            scrape_url("http://tracker.test/x/announce.php?passkey=abc").unwrap()
+        );
+    }
+
+    #[test]
+    fn rejects_unconventional_announce_urls() {
+        for url in [
+            "http://tracker.test/a",
+            "http://tracker.test/announce/x",
+            "tracker",
+        ] {
+            assert!(matches!(
+                scrape_url(url),
+                Err(ClientError::ScrapeUnsupported(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn parses_scrape_response() {
+        let body = b"d5:filesd\
+            20:aaaaaaaaaaaaaaaaaaaad8:completei5e10:downloadedi50e10:incompletei10ee\
+            20:\x00\x01\x02\x03\x04\x05\x06\x07\x08\x09\x0a\x0b\x0c\x0d\x0e\x0f\x10\x11\x12\x13\
+            d8:completei0e10:downloadedi0e10:incompletei1e4:name3:fooe\
+            20:\xff\xff\xff\xff\xff\xff\xff\xff\xff\xff\xff\xff\xff\xff\xff\xff\xff\xff\xff\xff\
+            d8:completei1e10:downloadedi2e10:incompletei3ee\
+            e5:flagsd20:min_request_intervali3600eee";
+
+        let response = decode::<ScrapeResponse>(body).unwrap();
+
+        assert_eq!(3, response.files.len());
+        assert_eq!(Some(3600), response.min_request_interval);
+        assert_eq!(
+            ScrapeFile {
+                complete: 5,
+                downloaded: 50,
+                incomplete: 10,
+                name: None,
+            },
+            response.files[&InfoHash(vec![b'a'; 20])]
+        );
+        assert_eq!(
+            Some("foo".to_string()),
+            response.files[&InfoHash((0..20).collect())].name
+        );
+        assert_eq!(3, response.files[&InfoHash(vec![0xff; 20])].incomplete);
+    }
+
+    mod server {
+        use super::*;
+
+        use crate::ErrorKind;
+        use axum::{
+            extract::{ConnectInfo, RawQuery, State},
+            http::{header, HeaderMap, StatusCode},
+            response::IntoResponse,
+            routing::get,
+            Router,
+        };
+        use flate2::{write::GzEncoder, Compression};
+        use hanekawa::http_tracker::proto::{
+            AnnounceRequest as ServerAnnounceRequest, AnnounceResponse as ServerAnnounceResponse,
+            PeerData, ScrapeRequest as ServerScrapeRequest, ScrapeResponse as ServerScrapeResponse,
+        };
+        use hanekawa_common::types::PeerStatistics;
+        use std::{
+            collections::HashSet,
+            io::Write,
+            net::SocketAddr,
+            sync::{Arc, Mutex},
+        };
+
+        // Client addresses seen, one per connection.
+        type Connections = Arc<Mutex<HashSet<SocketAddr>>>;
+
+        // Decodes the query with the same deserializer the tracker uses and
+        // answers with the tracker's own response encoding.
+        async fn announce(
+            State(connections): State<Connections>,
+            ConnectInfo(addr): ConnectInfo<SocketAddr>,
+            RawQuery(query): RawQuery,
+        ) -> Vec<u8> {
+            connections.lock().unwrap().insert(addr);
+
+            let query = query.unwrap_or_default();
+            let request: ServerAnnounceRequest =
+                match hanekawa_percent_encode::from_query_string(&query) {
+                    Ok(r) => r,
+                    Err(e) => {
+                        return format!("d14:failure reason{}:{}e", e.to_string().len(), e)
+                            .into_bytes()
+                    }
+                };
+
+            assert_eq!(params().info_hash, request.info_hash);
+            assert_eq!(params().peer_id, request.peer_id);
+            assert_eq!(Event::Started, request.event);
+
+            let response = ServerAnnounceResponse {
+                interval: 60,
+                min_interval: 30,
+                peers: PeerData::Compact(vec![127, 0, 0, 1, 0x1a, 0xe1].into()),
+                peers6: PeerData::Compact(Default::default()),
+                stats: Some(PeerStatistics {
+                    complete: 1,
+                    downloaded: 1,
+                    incomplete: request.left.min(1) as u32,
+                }),
+                warnings: vec![],
+            };
+
+            hanekawa_bencode::to_bytes(&response).unwrap().to_vec()
+        }
+
+        async fn scrape(RawQuery(query): RawQuery) -> Vec<u8> {
+            let query = query.unwrap_or_default();
+            let request: ServerScrapeRequest =
+                hanekawa_percent_encode::from_query_string(&query).unwrap();
+
+            let files = request
+                .info_hash
+                .into_iter()
+                .enumerate()
+                .map(|(i, info_hash)| {
+                    let stats = PeerStatistics {
+                        complete: i as u32,
+                        downloaded: 0,
+                        incomplete: 1,
+                    };
+                    (info_hash, stats)
+                })
+                .collect();
+
+            hanekawa_bencode::to_bytes(&ServerScrapeResponse { files })
+                .unwrap()
+                .to_vec()
+        }
+
+        async fn failure() -> (StatusCode, &'static [u8]) {
+            (
+                StatusCode::FORBIDDEN,
+                b"d14:failure reason21:info hash not allowede",
+            )
+        }
+
+        // Answers with the User-Agent as the failure reason.
+        async fn user_agent(headers: HeaderMap) -> Vec<u8> {
+            let agent = headers
+                .get(header::USER_AGENT)
+                .map(|v| v.to_str().unwrap().to_string())
+                .unwrap_or_default();
+
+            format!("d14:failure reason{}:{}e", agent.len(), agent).into_bytes()
+        }
+
+        fn redirect(status: StatusCode, location: &'static str) -> impl IntoResponse {
+            (status, [(header::LOCATION, location)])
+        }
+
+        async fn gzipped() -> impl IntoResponse {
+            let body = b"d8:intervali900e5:peersld2:ip8:10.0.0.17:peer id20:-TR2940-abcdefghijkl4:porti51413eeee";
+            let mut encoder = GzEncoder::new(vec![], Compression::default());
+            encoder.write_all(body).unwrap();
+
+            (
+                [(header::CONTENT_ENCODING, "gzip")],
+                encoder.finish().unwrap(),
+            )
+        }
+
+        async fn spawn() -> (String, Connections) {
+            let connections = Connections::default();
+            let app = Router::new()
+                .route("/announce", get(announce))
+                .route("/scrape", get(scrape))
+                .route("/denied/announce", get(failure))
+                .route(
+                    "/old/announce",
+                    get(|| async { redirect(StatusCode::MOVED_PERMANENTLY, "/moved") }),
+                )
+                .route(
+                    "/moved",
+                    get(|| async { redirect(StatusCode::FOUND, "/announce.php") }),
+                )
+                .route("/announce.php", get(announce))
+                .route(
+                    "/loop/announce",
+                    get(|| async { redirect(StatusCode::MOVED_PERMANENTLY, "/loop/announce") }),
+                )
+                .route("/gzip/announce", get(gzipped))
+                .route(
+                    "/broken/announce",
+                    get(|| async { StatusCode::INTERNAL_SERVER_ERROR }),
+                )
+                .route(
+                    "/gone/announce",
+                    get(|| async { (StatusCode::NOT_FOUND, "<h1>Not Found</h1>") }),
+                )
+                .route("/garbage/announce", get(|| async { "<h1>Welcome</h1>" }))
+                .route("/agent/announce", get(user_agent))
+                .with_state(connections.clone());
+
+            let server = axum::Server::bind(&"127.0.0.1:0".parse().unwrap())
+                .serve(app.into_make_service_with_connect_info::<SocketAddr>());
+            let addr = server.local_addr();
+            tokio::spawn(server);
+
+            (format!("http://{addr}"), connections)
+        }
+
+        #[tokio::test]
+        async fn announces_to_tracker() {
+            let (base, _) = spawn().await;
+            let client = HttpTrackerClient::new().unwrap();
+
+            let response = client
+                .announce(&format!("{base}/announce"), params())
+                .await
+                .unwrap();
+
+            assert_eq!(60, response.interval);
+            assert_eq!(Some(1), response.complete);
+            assert_eq!(Some(1), response.incomplete);
+            assert_eq!(
+                PeerList::Compact(vec![127, 0, 0, 1, 0x1a, 0xe1]),
+                response.peers
+            );
+            assert_eq!(Some(PeerList::Compact(vec![])), response.peers6);
+        }
+
+        #[tokio::test]
+        async fn surfaces_failure_reason() {
+            let (base, _) = spawn().await;
+            let client = HttpTrackerClient::new().unwrap();
+
+            let result = client
+                .announce(&format!("{base}/denied/announce"), params())
+                .await;
+
+            match result {
+                Err(ClientError::Failure { reason, .. }) => {
+                    assert_eq!("info hash not allowed", reason)
+                }
+                _ => panic!("expected a tracker failure"),
+            }
+        }
+
+        #[tokio::test]
+        async fn scrapes_multiple_info_hashes() {
+            let (base, _) = spawn().await;
+            let client = HttpTrackerClient::new().unwrap();
+
+            let info_hashes = [
+                InfoHash(vec![0x00; 20]),
+                InfoHash(vec![b'%'; 20]),
+                params().info_hash,
+            ];
+
+            let response = client
+                .scrape(&format!("{base}/announce"), &info_hashes)
+                .await
+                .unwrap();
+
+            assert_eq!(3, response.files.len());
+            for (i, info_hash) in info_hashes.iter().enumerate() {
+                assert_eq!(i as u32, response.files[info_hash].complete);
+            }
+            assert_eq!(None, response.min_request_interval);
+        }
+
+        #[tokio::test]
+        async fn identifies_itself() {
+            let (base, _) = spawn().await;
+            let url = format!("{base}/agent/announce");
+
+            for (client, expected) in [
+                (HttpTrackerClient::new().unwrap(), DEFAULT_USER_AGENT),
+                (
+                    HttpTrackerClient::builder()
+                        .user_agent("qBittorrent/4.6.2")
+                        .build()
+                        .unwrap(),
+                    "qBittorrent/4.6.2",
+                ),
+            ] {
+                for _ in 0..2 {
+                    match client.announce(&url, params()).await {
+                        Err(ClientError::Failure { reason, .. }) => assert_eq!(expected, reason),
+                        r => panic!("expected the user agent, got {r:?}"),
+                    }
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn classifies_failures() {
+            let (base, _) = spawn().await;
+            let client = HttpTrackerClient::new().unwrap();
+
+            // Nothing listens there once the listener is dropped.
+            let closed = std::net::TcpListener::bind("127.0.0.1:0")
+                .unwrap()
+                .local_addr()
+                .unwrap();
+
+            for (url, kind, retryable) in [
+                (
+                    format!("http://{closed}/announce"),
+                    ErrorKind::Transport,
+                    true,
+                ),
+                (format!("{base}/broken/announce"), ErrorKind::Http, true),
+                (format!("{base}/gone/announce"), ErrorKind::Http, false),
+                (
+                    format!("{base}/garbage/announce"),
+                    ErrorKind::Protocol,
+                    true,
+                ),
+                (format!("{base}/denied/announce"), ErrorKind::Tracker, false),
+                ("tracker.example".to_string(), ErrorKind::Request, false),
+            ] {
+                let e = client.announce(&url, params()).await.unwrap_err();
+                assert_eq!(
+                    (kind, retryable),
+                    (e.kind(), e.is_retryable()),
+                    "{url}: {e}"
+                );
+            }
+
+            let e = client
+                .announce(&format!("{base}/garbage/announce"), params())
+                .await
+                .unwrap_err();
+            assert_eq!(Some(&b"<h1>Welcome</h1>"[..]), e.body());
+        }
+
+        #[tokio::test]
+        async fn follows_redirects_preserving_the_query() {
+            let (base, _) = spawn().await;
+            let client = HttpTrackerClient::new().unwrap();
+
+            let (response, info) = client
+                .announce_with_info(&format!("{base}/old/announce"), params())
+                .await
+                .unwrap();
+
+            assert_eq!(60, response.interval);
+            assert_eq!(2, info.redirects);
+            assert!(info
+                .url
+                .starts_with(&format!("{base}/announce.php?info_hash=%00%FF")));
+        }
+
+        #[tokio::test]
+        async fn gives_up_on_redirect_loops() {
+            let (base, _) = spawn().await;
+            let client = HttpTrackerClient::builder()
+                .max_redirects(3)
+                .build()
+                .unwrap();
+
+            match client
+                .announce(&format!("{base}/loop/announce"), params())
+                .await
+            {
+                Err(ClientError::Redirect(e)) => assert_eq!("more than 3 redirects", e),
+                r => panic!("expected a redirect error, got {r:?}"),
+            }
+        }
+
+        #[tokio::test]
+        async fn decompresses_gzipped_responses() {
+            let (base, _) = spawn().await;
+            let client = HttpTrackerClient::new().unwrap();
+
+            let response = client
+                .announce(&format!("{base}/gzip/announce"), params())
+                .await
+                .unwrap();
+
+            assert_eq!(900, response.interval);
+            assert_eq!(
+                vec!["10.0.0.1:51413".parse::<SocketAddr>().unwrap()],
+                response
+                    .peers()
+                    .unwrap()
+                    .into_iter()
+                    .map(|p| p.addr)
+                    .collect::<Vec<_>>()
+            );
+        }
+
+        #[tokio::test]
+        async fn reuses_connections() {
+            let (base, connections) = spawn().await;
+            let client = HttpTrackerClient::new().unwrap();
+            let clone = client.clone();
+
+            for client in [&client, &clone, &client] {
+                client
+                    .announce(&format!("{base}/announce"), params())
+                    .await
+                    .unwrap();
+            }
+
+            assert_eq!(1, connections.lock().unwrap().len());
+        }
+
+        #[tokio::test(start_paused = true)]
+        async fn gives_up_on_trackers_that_never_answer() {
+            // Connections are accepted by the kernel and then left alone.
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            let base = format!("http://{}", listener.local_addr().unwrap());
+            let client = HttpTrackerClient::builder()
+                .scrape_timeout(Duration::from_secs(2))
+                .build()
+                .unwrap();
+
+            let started = tokio::time::Instant::now();
+            let announce = client.announce(&format!("{base}/announce"), params()).await;
+            assert!(matches!(announce, Err(ClientError::Timeout)));
+            assert_eq!(Duration::from_secs(10), started.elapsed());
+
+            let started = tokio::time::Instant::now();
+            let scrape = client.scrape(&format!("{base}/announce"), &[]).await;
+            assert!(matches!(scrape, Err(ClientError::Timeout)));
+            assert_eq!(Duration::from_secs(2), started.elapsed());
+        }
+    }
+}
@@ -0,0 +1,303 @@
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+use url::Url;
+
+// The scope of the key shared by all trackers.
+const GLOBAL: &str = "*";
+
+// The `key` announce parameter, which lets trackers recognise a client whose
+// address changed. It only helps if it survives restarts, so it can be kept in
+// a state file, one `<scope> <key>` line per key.
+#[derive(Debug, Clone)]
+pub struct AnnounceKey {
+    path: Option<PathBuf>,
+    per_tracker: bool,
+    // By tracker, or GLOBAL. Shared by clones.
+    keys: Arc<Mutex<BTreeMap<String, u32>>>,
+}
+
+impl AnnounceKey {
+    // A fresh key, forgotten when the process exits.
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            per_tracker: false,
+            keys: Arc::new(Mutex::new(BTreeMap::from([(
+                GLOBAL.to_string(),
+                rand::random(),
+            )]))),
+        }
+    }
+
+    // Missing files are created, and unreadable contents replaced by new
+    // keys. Fails if the file cannot be written.
+    pub fn load_or_generate(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+
+        let keys = match fs::read_to_string(path) {
+            Ok(contents) => parse(&contents).unwrap_or_else(|| {
+                tracing::warn!(path = %path.display(), "corrupted announce key file, regenerating");
+                BTreeMap::new()
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(e),
+        };
+
+        let key = Self {
+            path: Some(path.to_path_buf()),
+            per_tracker: false,
+            keys: Arc::new(Mutex::new(keys)),
+        };
+        key.get_or_insert(GLOBAL.to_string()).map_err(|(_, e)| e)?;
+
+        Ok(key)
+    }
+
+    // Every tracker gets a key of its own, so they cannot tell they are
+    // talking to the same client.
+    pub fn per_tracker(mut self) -> Self {
+        self.per_tracker = true;
+        self
+    }
+
+    // As sent to the tracker: 8 hex digits, which also fit UDP's 32 bits.
+    pub fn for_tracker(&self, url: &str) -> String {
+        format!("{:08x}", self.value(url))
+    }
+
+    pub(crate) fn value(&self, url: &str) -> u32 {
+        let scope = if self.per_tracker {
+            scope(url)
+        } else {
+            GLOBAL.to_string()
+        };
+
+        // The key is still used for this session if it cannot be saved.
+        self.get_or_insert(scope).unwrap_or_else(|(key, e)| {
+            tracing::warn!(error = %e, "failed to save announce key");
+            key
+        })
+    }
+
+    fn get_or_insert(&self, scope: String) -> Result<u32, (u32, io::Error)> {
+        let mut keys = self.keys.lock().unwrap();
+        if let Some(key) = keys.get(&scope) {
+            return Ok(*key);
+        }
+
+        let key = rand::random();
+        keys.insert(scope, key);
+
+        match &self.path {
+            Some(path) => save(path, &keys).map(|_| key).map_err(|e| (key, e)),
+            None => Ok(key),
+        }
+    }
+}
+
+// Trackers are told apart by host and port, so every announce URL of a
+// tracker shares its key.
+fn scope(url: &str) -> String {
+    match Url::parse(url) {
+        Ok(u) => match (u.host_str(), u.port_or_known_default()) {
+            (Some(host), Some(port)) => format!("{host}:{port}"),
+            (Some(host), None) => host.to_string(),
+            _ => url.to_string(),
+        },
+        Err(_) => url.to_string(),
+    }
+}
+
+fn parse(contents: &str) -> Option<BTreeMap<String, u32>> {
+    contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let (scope, key) = line.split_once(' ')?;
+            if key.len() != 8 {
+                return None;
+            }
+
+            Some((scope.to_string(), u32::from_str_radix(key, 16).ok()?))
+        })
+        .collect()
+}
+
+// Written next to the file and renamed over it, so a crash never leaves half
+// a key. Only the owner can read it.
+fn save(path: &Path, keys: &BTreeMap<String, u32>) -> io::Result<()> {
+    use std::io::Write;
+
+    let contents: String = keys
+        .iter()
+        .map(|(scope, key)| format!("{scope} {key:08x}\n"))
+        .collect();
+
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    let mut file = options.open(&tmp)?;
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()?;
+
+    fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::{proto::AnnounceParams, HttpTrackerClient, UdpTrackerClient};
+    use axum::{
+        extract::{RawQuery, State},
+        routing::get,
+        Router,
+    };
+    use hanekawa_common::types::{Event, InfoHash, PeerId};
+
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new() -> Self {
+            let name = format!("hanekawa-key-{:016x}", rand::random::<u64>());
+            Self(std::env::temp_dir().join(name))
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn keeps_the_key_across_loads() {
+        let file = TempFile::new();
+
+        let key = AnnounceKey::load_or_generate(&file.0).unwrap();
+        let again = AnnounceKey::load_or_generate(&file.0).unwrap();
+        let url = "http://tracker.test/announce";
+
+        assert_eq!(key.for_tracker(url), again.for_tracker(url));
+        assert_eq!(
+            format!("* {}\n", key.for_tracker(url)),
+            fs::read_to_string(&file.0).unwrap()
+        );
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&file.0).unwrap().permissions().mode();
+            assert_eq!(0o600, mode & 0o777);
+        }
+    }
+
+    #[test]
+    fn regenerates_corrupted_files() {
+        let file = TempFile::new();
+        fs::write(&file.0, "* not-hex!\n").unwrap();
+
+        let key = AnnounceKey::load_or_generate(&file.0).unwrap();
+
+        let contents = fs::read_to_string(&file.0).unwrap();
+        assert_eq!(
+            Some(key.value("udp://tracker.test:6969")),
+            parse(&contents).unwrap().get(GLOBAL).copied()
+        );
+    }
+
+    #[test]
+    fn keeps_a_key_per_tracker() {
+        let file = TempFile::new();
+
+        let key = AnnounceKey::load_or_generate(&file.0)
+            .unwrap()
+            .per_tracker();
+        let a = key.for_tracker("http://a.test/announce");
+        let b = key.for_tracker("udp://b.test:6969");
+        assert_ne!(a, b);
+        assert_eq!(a, key.for_tracker("http://a.test:80/announce.php"));
+
+        let again = AnnounceKey::load_or_generate(&file.0)
+            .unwrap()
+            .per_tracker();
+        assert_eq!(b, again.for_tracker("udp://b.test:6969/announce"));
+        assert_eq!(
+            3,
+            parse(&fs::read_to_string(&file.0).unwrap()).unwrap().len()
+        );
+    }
+
+    #[tokio::test]
+    async fn sends_the_same_key_after_a_restart() {
+        type Seen = Arc<Mutex<Vec<String>>>;
+
+        let seen = Seen::default();
+        let app = Router::new()
+            .route(
+                "/announce",
+                get(
+                    |State(seen): State<Seen>, RawQuery(query): RawQuery| async move {
+                        let query = query.unwrap_or_default();
+                        let key = query.split('&').find_map(|p| p.strip_prefix("key="));
+                        seen.lock()
+                            .unwrap()
+                            .push(key.unwrap_or_default().to_string());
+
+                        &b"d8:intervali1800e5:peers0:e"[..]
+                    },
+                ),
+            )
+            .with_state(seen.clone());
+        let server =
+            axum::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(app.into_make_service());
+        let url = format!("http://{}/announce", server.local_addr());
+        tokio::spawn(server);
+
+        let params = AnnounceParams {
+            info_hash: InfoHash(vec![0xaa; 20]),
+            peer_id: PeerId(b"-HK0100-123456789012".to_vec()),
+            port: 6881,
+            uploaded: 0,
+            downloaded: 0,
+            left: 100,
+            event: Event::Started,
+            num_want: None,
+            compact: true,
+            key: None,
+            ip: None,
+        };
+
+        let file = TempFile::new();
+        for _ in 0..2 {
+            let client = HttpTrackerClient::builder()
+                .announce_key(AnnounceKey::load_or_generate(&file.0).unwrap())
+                .build()
+                .unwrap();
+            client.announce(&url, params.clone()).await.unwrap();
+        }
+
+        let seen = seen.lock().unwrap();
+        assert_eq!(2, seen.len());
+        assert_eq!(8, seen[0].len());
+        assert_eq!(seen[0], seen[1]);
+
+        // The UDP client sends the same one.
+        let udp = UdpTrackerClient::builder()
+            .announce_key(AnnounceKey::load_or_generate(&file.0).unwrap())
+            .build();
+        assert_eq!(
+            u32::from_str_radix(&seen[0], 16).unwrap(),
+            udp.key_for("udp://tracker.test:6969")
+        );
+    }
+}
@@ -0,0 +1,25 @@
+pub mod announcer;
+mod clock;
+mod error;
+pub mod http;
+mod key;
+pub mod loadtest;
+pub mod observer;
+pub mod proto;
+pub mod proxy;
+pub mod retry;
+pub mod session;
+mod tiers;
+pub mod transport;
+pub mod udp;
+
+pub use announcer::{Announcer, AnnouncerHandle, MultiTrackerAnnouncer, SwarmStats, TransferStats};
+pub use error::{ClientError, ErrorKind};
+pub use http::{HttpTrackerClient, RequestInfo, DEFAULT_PEER_ID_PREFIX};
+pub use key::AnnounceKey;
+pub use observer::AnnounceObserver;
+pub use proxy::Proxy;
+pub use retry::RetryPolicy;
+pub use session::TorrentSession;
+pub use transport::{TrackerClient, TrackerTransport};
+pub use udp::UdpTrackerClient;
@@ -0,0 +1,565 @@
+use crate::proto::AnnounceParams;
+use crate::{ErrorKind, TrackerTransport, DEFAULT_PEER_ID_PREFIX};
+
+use hanekawa_common::types::{Event, InfoHash, PeerId};
+
+use rand::Rng;
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::time::Interval;
+
+// Simulated swarms, announcing to any tracker. Every peer joins with
+// `started`, announces regularly, may send `completed` along the way, and
+// leaves with `stopped`. Tracker intervals are ignored, the pace is the
+// scenario's.
+#[derive(Debug, Clone)]
+pub struct Scenario {
+    // Peers are spread over these in turn, so a run can mix HTTP and UDP.
+    pub trackers: Vec<String>,
+    pub torrents: usize,
+    // In total, spread evenly over the torrents.
+    pub peers: usize,
+    // From `started` to `stopped`, so at least 2. Leechers only complete
+    // with 3 or more.
+    pub announces_per_peer: usize,
+    // Over all peers, in announces per second. `None` sends as fast as the
+    // tracker answers.
+    pub rate: Option<f64>,
+    // The share of peers joining as seeders.
+    pub seeders: f64,
+    // The share of leechers finishing their download before they leave.
+    pub completion: f64,
+    pub torrent_size: u64,
+}
+
+impl Default for Scenario {
+    fn default() -> Self {
+        Self {
+            trackers: vec![],
+            torrents: 1,
+            peers: 100,
+            announces_per_peer: 4,
+            rate: None,
+            seeders: 0.2,
+            completion: 0.8,
+            torrent_size: 1 << 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EventCounts {
+    pub started: u64,
+    pub completed: u64,
+    pub stopped: u64,
+    pub interval: u64,
+}
+
+impl EventCounts {
+    pub fn total(&self) -> u64 {
+        self.started + self.completed + self.stopped + self.interval
+    }
+
+    pub(crate) fn add(&mut self, event: &Event) {
+        match event {
+            Event::Started => self.started += 1,
+            Event::Completed => self.completed += 1,
+            Event::Stopped => self.stopped += 1,
+            Event::Interval => self.interval += 1,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub elapsed: Duration,
+    // Successful announces, by the event they carried.
+    pub announces: EventCounts,
+    pub errors: HashMap<ErrorKind, u64>,
+    // Of successful announces, sorted.
+    latencies: Vec<Duration>,
+}
+
+impl Report {
+    pub fn requests(&self) -> u64 {
+        self.announces.total() + self.errors.values().sum::<u64>()
+    }
+
+    // Achieved, in requests per second.
+    pub fn rate(&self) -> f64 {
+        self.requests() as f64 / self.elapsed.as_secs_f64()
+    }
+
+    // Nearest rank, `None` without any successful announce.
+    pub fn latency(&self, percentile: f64) -> Option<Duration> {
+        let rank = (percentile / 100.0 * self.latencies.len() as f64).ceil() as usize;
+        self.latencies
+            .get(rank.clamp(1, self.latencies.len().max(1)) - 1)
+            .copied()
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{} requests in {:.2?} ({:.1}/s)",
+            self.requests(),
+            self.elapsed,
+            self.rate()
+        )?;
+
+        let a = &self.announces;
+        writeln!(
+            f,
+            "announces: {} started, {} completed, {} stopped, {} regular",
+            a.started, a.completed, a.stopped, a.interval
+        )?;
+
+        if let Some(max) = self.latencies.last() {
+            let p = |percentile| self.latency(percentile).unwrap_or_default();
+            writeln!(
+                f,
+                "latency: p50 {:.2?}, p90 {:.2?}, p99 {:.2?}, max {:.2?}",
+                p(50.0),
+                p(90.0),
+                p(99.0),
+                max
+            )?;
+        }
+
+        let mut errors: Vec<_> = self
+            .errors
+            .iter()
+            .map(|(kind, count)| format!("{count} {}", kind_name(*kind)))
+            .collect();
+        errors.sort();
+        if errors.is_empty() {
+            write!(f, "errors: none")
+        } else {
+            write!(f, "errors: {}", errors.join(", "))
+        }
+    }
+}
+
+fn kind_name(kind: ErrorKind) -> &'static str {
+    match kind {
+        ErrorKind::Transport => "transport",
+        ErrorKind::Http => "http",
+        ErrorKind::Protocol => "protocol",
+        ErrorKind::Tracker => "tracker",
+        ErrorKind::Request => "request",
+    }
+}
+
+#[derive(Default)]
+struct Tally {
+    announces: EventCounts,
+    errors: HashMap<ErrorKind, u64>,
+    latencies: Vec<Duration>,
+}
+
+struct SimulatedPeer {
+    tracker: String,
+    info_hash: InfoHash,
+    peer_id: PeerId,
+    port: u16,
+    // (event, downloaded) for every announce.
+    announces: Vec<(Event, u64)>,
+}
+
+fn plan(scenario: &Scenario, rng: &mut impl Rng) -> Vec<SimulatedPeer> {
+    let info_hashes: Vec<_> = (0..scenario.torrents.max(1))
+        .map(|_| InfoHash(rng.gen::<[u8; 20]>().to_vec()))
+        .collect();
+    let n = scenario.announces_per_peer.max(2);
+    let size = scenario.torrent_size;
+
+    (0..scenario.peers)
+        .map(|i| {
+            let mut announces = vec![(Event::Interval, 0); n];
+
+            if rng.gen_bool(scenario.seeders.clamp(0.0, 1.0)) {
+                announces.iter_mut().for_each(|a| a.1 = size);
+            } else if n >= 3 && rng.gen_bool(scenario.completion.clamp(0.0, 1.0)) {
+                let done = rng.gen_range(1..n - 1);
+                announces[done].0 = Event::Completed;
+                for (j, a) in announces.iter_mut().enumerate() {
+                    a.1 = size * j.min(done) as u64 / done as u64;
+                }
+            } else {
+                for (j, a) in announces.iter_mut().enumerate() {
+                    a.1 = size * j as u64 / n as u64;
+                }
+            }
+
+            announces[0].0 = Event::Started;
+            announces[n - 1].0 = Event::Stopped;
+
+            SimulatedPeer {
+                tracker: scenario.trackers[i % scenario.trackers.len()].clone(),
+                info_hash: info_hashes[i % info_hashes.len()].clone(),
+                peer_id: PeerId::generate(DEFAULT_PEER_ID_PREFIX).unwrap(),
+                port: 1024 + (i % 64512) as u16,
+                announces,
+            }
+        })
+        .collect()
+}
+
+// Runs the scenario to the end, every peer announcing concurrently.
+pub async fn run(client: impl TrackerTransport + 'static, scenario: &Scenario) -> Report {
+    if scenario.trackers.is_empty() {
+        return Report {
+            elapsed: Duration::ZERO,
+            announces: EventCounts::default(),
+            errors: HashMap::new(),
+            latencies: vec![],
+        };
+    }
+
+    let peers = plan(scenario, &mut rand::thread_rng());
+    let client: Arc<dyn TrackerTransport> = Arc::new(client);
+    let tally = Arc::new(Mutex::new(Tally::default()));
+    let pace = scenario
+        .rate
+        .filter(|r| *r > 0.0)
+        .map(|r| Arc::new(tokio::sync::Mutex::new(pacer(r))));
+    let size = scenario.torrent_size;
+
+    let started = Instant::now();
+    let tasks: Vec<_> = peers
+        .into_iter()
+        .map(|peer| {
+            let (client, tally, pace) = (client.clone(), tally.clone(), pace.clone());
+            tokio::spawn(async move {
+                for (event, downloaded) in peer.announces {
+                    if let Some(pace) = &pace {
+                        pace.lock().await.tick().await;
+                    }
+
+                    let params = AnnounceParams {
+                        info_hash: peer.info_hash.clone(),
+                        peer_id: peer.peer_id.clone(),
+                        port: peer.port,
+                        uploaded: 0,
+                        downloaded,
+                        left: size - downloaded,
+                        event: event.clone(),
+                        num_want: None,
+                        compact: true,
+                        key: None,
+                        ip: None,
+                    };
+
+                    let sent = Instant::now();
+                    let result = client.announce(&peer.tracker, params).await;
+                    let latency = sent.elapsed();
+
+                    let mut tally = tally.lock().unwrap();
+                    match result {
+                        Ok(_) => {
+                            tally.announces.add(&event);
+                            tally.latencies.push(latency);
+                        }
+                        Err(e) => *tally.errors.entry(e.kind()).or_default() += 1,
+                    }
+                }
+            })
+        })
+        .collect();
+
+    for task in tasks {
+        task.await.unwrap();
+    }
+    let elapsed = started.elapsed();
+
+    let mut tally = std::mem::take(&mut *tally.lock().unwrap());
+    tally.latencies.sort();
+
+    Report {
+        elapsed,
+        announces: tally.announces,
+        errors: tally.errors,
+        latencies: tally.latencies,
+    }
+}
+
+fn pacer(rate: f64) -> Interval {
+    let mut interval = tokio::time::interval(Duration::from_secs_f64(1.0 / rate));
+    // A slow tracker makes the run longer rather than burstier.
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    interval
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::{HttpTrackerClient, TrackerClient, UdpTrackerClient};
+    use axum::{
+        extract::{ConnectInfo, RawQuery, State},
+        routing::get,
+        Router,
+    };
+    use bytes::BytesMut;
+    use hanekawa::{
+        http_tracker::{proto::AnnounceRequest, HttpTrackerService},
+        udp_tracker::{
+            proto::{Request, Response},
+            UdpTrackerService,
+        },
+    };
+    use hanekawa_common::{
+        audit::AuditLog,
+        ban::BanList,
+        maintenance::Maintenance,
+        offense::Offenders,
+        passkey::Passkeys,
+        peer_limit::PeerLimits,
+        repository::{
+            memory::MemoryStore,
+            peer::{
+                GetPeerStatistics, GetPeers, GetSwarmDetail, IterSwarms, PeerRepository,
+                PurgeSwarm, SetConnectable, UpdatePeerAnnounce,
+            },
+            Error,
+        },
+        task::{Task, TaskQueue},
+        torrent_policy::TorrentPolicy,
+        types::{Peer, PeerStatistics, SwarmMember, SwarmSummary},
+        Config, InvalidRequestConfig, Services,
+    };
+    use std::{
+        collections::HashSet,
+        net::{Ipv4Addr, SocketAddr},
+    };
+    use tokio::net::UdpSocket;
+
+    // Every announce the trackers accepted.
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<UpdatePeerAnnounce>>);
+
+    #[async_trait::async_trait]
+    impl PeerRepository for Recorder {
+        async fn update_peer_announce(&self, cmd: &UpdatePeerAnnounce) -> Result<(), Error> {
+            self.0.lock().unwrap().push(cmd.clone());
+            Ok(())
+        }
+
+        async fn get_peers(&self, _cmd: GetPeers<'_>) -> Result<Vec<Peer>, Error> {
+            Ok(vec![])
+        }
+
+        async fn get_peer_statistics(
+            &self,
+            _cmd: GetPeerStatistics<'_>,
+        ) -> Result<HashMap<InfoHash, PeerStatistics>, Error> {
+            Ok(HashMap::new())
+        }
+
+        async fn iter_swarms(&self, _cmd: IterSwarms) -> Result<Vec<SwarmSummary>, Error> {
+            Ok(vec![])
+        }
+
+        async fn get_swarm_detail(
+            &self,
+            _cmd: GetSwarmDetail<'_>,
+        ) -> Result<Vec<SwarmMember>, Error> {
+            Ok(vec![])
+        }
+
+        async fn purge_swarm(&self, _cmd: PurgeSwarm<'_>) -> Result<(), Error> {
+            Ok(())
+        }
+
+        async fn set_connectable(&self, _cmd: SetConnectable) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    // Runs tasks right away, against the recorder.
+    struct InlineQueue(Services);
+
+    #[async_trait::async_trait]
+    impl TaskQueue for InlineQueue {
+        async fn enqueue(&self, task: &dyn Task) -> Option<()> {
+            task.execute(&self.0).await
+        }
+    }
+
+    struct DiscardingQueue;
+
+    #[async_trait::async_trait]
+    impl TaskQueue for DiscardingQueue {
+        async fn enqueue(&self, _task: &dyn Task) -> Option<()> {
+            Some(())
+        }
+    }
+
+    fn config() -> Config {
+        Config {
+            database_pool_size: 1,
+            bind_ip: Ipv4Addr::LOCALHOST,
+            http_bind_port: 0,
+            udp_bind_port: 0,
+            interval_jitter_percent: 0,
+            peer_activity_timeout: Some(3600),
+            ..Config::default()
+        }
+    }
+
+    fn services(recorder: Arc<Recorder>) -> Services {
+        let info_hashes = Arc::new(MemoryStore::new());
+        let bans = BanList::in_memory();
+        let audit = AuditLog::in_memory();
+        let inner = Services {
+            peer_repository: recorder.clone(),
+            info_hash_repository: info_hashes.clone(),
+            task_queue: Arc::new(DiscardingQueue),
+            bans: bans.clone(),
+            audit: audit.clone(),
+            prober: None,
+            federation: None,
+            offenders: Offenders::new(&InvalidRequestConfig::default()),
+            maintenance: Maintenance::default(),
+            passkeys: Passkeys::in_memory(),
+            torrent_policy: TorrentPolicy::open(),
+            peer_limits: PeerLimits::unlimited(),
+            clock: hanekawa_common::system_clock(),
+        };
+
+        Services {
+            peer_repository: recorder,
+            info_hash_repository: info_hashes,
+            task_queue: Arc::new(InlineQueue(inner)),
+            bans,
+            audit,
+            prober: None,
+            federation: None,
+            offenders: Offenders::new(&InvalidRequestConfig::default()),
+            maintenance: Maintenance::default(),
+            passkeys: Passkeys::in_memory(),
+            torrent_policy: TorrentPolicy::open(),
+            peer_limits: PeerLimits::unlimited(),
+            clock: hanekawa_common::system_clock(),
+        }
+    }
+
+    async fn spawn_http(services: Services) -> String {
+        let tracker = HttpTrackerService::new(&config(), services);
+        let app = Router::new()
+            .route(
+                "/announce",
+                get(
+                    |State(tracker): State<HttpTrackerService>,
+                     ConnectInfo(from): ConnectInfo<SocketAddr>,
+                     RawQuery(query): RawQuery| async move {
+                        let request: AnnounceRequest =
+                            hanekawa_percent_encode::from_query_string(&query.unwrap_or_default())
+                                .unwrap();
+                        let response = tracker.announce(request, from.ip()).await.unwrap();
+
+                        hanekawa_bencode::to_bytes(&response).unwrap()
+                    },
+                ),
+            )
+            .with_state(tracker);
+
+        let server = axum::Server::bind(&"127.0.0.1:0".parse().unwrap())
+            .serve(app.into_make_service_with_connect_info::<SocketAddr>());
+        let addr = server.local_addr();
+        tokio::spawn(server);
+
+        format!("http://{addr}/announce")
+    }
+
+    async fn spawn_udp(services: Services) -> String {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        let tracker = UdpTrackerService::new(&config(), services);
+
+        tokio::spawn(async move {
+            let mut buf = [0; 1500];
+            loop {
+                let (len, from) = socket.recv_from(&mut buf).await.unwrap();
+                let response = match hanekawa_udp::parse_request(&buf[..len]).unwrap() {
+                    Request::Connect(r) => Response::Connect(tracker.connect(r, from)),
+                    Request::Announce(r) => {
+                        Response::Announce(tracker.announce(r, from).await.unwrap())
+                    }
+                    Request::Scrape(r) => Response::Scrape(tracker.scrape(r, from).await.unwrap()),
+                };
+
+                let mut reply = BytesMut::new();
+                hanekawa_udp::encode_response(&response, &mut reply);
+                socket.send_to(&reply, from).await.unwrap();
+            }
+        });
+
+        format!("udp://{addr}/announce")
+    }
+
+    #[tokio::test]
+    async fn reports_what_the_tracker_saw() {
+        let recorder = Arc::new(Recorder::default());
+        let scenario = Scenario {
+            trackers: vec![
+                spawn_http(services(recorder.clone())).await,
+                spawn_udp(services(recorder.clone())).await,
+            ],
+            torrents: 2,
+            peers: 10,
+            announces_per_peer: 4,
+            rate: Some(200.0),
+            ..Default::default()
+        };
+
+        let client = TrackerClient::new(HttpTrackerClient::new().unwrap(), UdpTrackerClient::new());
+        let report = run(client, &scenario).await;
+
+        assert!(report.errors.is_empty(), "{report}");
+        assert_eq!(40, report.requests());
+        assert_eq!(10, report.announces.started);
+        assert_eq!(10, report.announces.stopped);
+        assert!(report.latency(50.0) <= report.latency(99.0));
+
+        let seen = recorder.0.lock().unwrap();
+        let mut server = EventCounts::default();
+        seen.iter().for_each(|a| server.add(&a.event));
+        assert_eq!(report.announces, server);
+
+        let torrents: HashSet<_> = seen.iter().map(|a| &a.info_hash).collect();
+        let peers: HashSet<_> = seen.iter().map(|a| &a.peer_id).collect();
+        assert_eq!((2, 10), (torrents.len(), peers.len()));
+
+        // Completing leechers have nothing left from then on.
+        for a in seen.iter().filter(|a| a.event == Event::Completed) {
+            assert_eq!(0, a.left);
+        }
+    }
+
+    #[test]
+    fn takes_nearest_rank_percentiles() {
+        let report = Report {
+            elapsed: Duration::from_secs(2),
+            announces: EventCounts {
+                interval: 10,
+                ..Default::default()
+            },
+            errors: HashMap::from([(ErrorKind::Transport, 2)]),
+            latencies: (1..=10).map(Duration::from_millis).collect(),
+        };
+
+        assert_eq!(6.0, report.rate());
+        assert_eq!(Some(Duration::from_millis(5)), report.latency(50.0));
+        assert_eq!(Some(Duration::from_millis(9)), report.latency(90.0));
+        assert_eq!(Some(Duration::from_millis(10)), report.latency(99.0));
+        assert_eq!(Some(Duration::from_millis(1)), report.latency(0.0));
+        assert!(report.to_string().ends_with("errors: 2 transport"));
+    }
+}
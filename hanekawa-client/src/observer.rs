@@ -0,0 +1,99 @@
+use crate::proto::AnnounceResponse;
+use crate::ClientError;
+
+use hanekawa_common::types::Event;
+
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::Arc,
+    time::Duration,
+};
+
+// What an announcer does, for embedders to feed into their own metrics or
+// logs. Callbacks run on the announcer's task in between requests, so they
+// must return quickly. One that panics is ignored rather than taking the
+// announcer down with it.
+pub trait AnnounceObserver: Send + Sync {
+    fn request_started(&self, _url: &str, _event: &Event) {}
+
+    fn request_finished(
+        &self,
+        _url: &str,
+        _elapsed: Duration,
+        _outcome: Result<&AnnounceResponse, &ClientError>,
+    ) {
+    }
+
+    // Every tracker failed, `failures` times in a row now.
+    fn retry_scheduled(&self, _delay: Duration, _failures: u32) {}
+
+    // A tracker in another tier than the last one answered.
+    fn tier_switched(&self, _from: usize, _to: usize) {}
+
+    fn peers_received(&self, _url: &str, _count: usize) {}
+}
+
+// The announcer's handle on the client's observer, if it has one.
+#[derive(Clone, Default)]
+pub(crate) struct Observer(Option<Arc<dyn AnnounceObserver>>);
+
+impl Observer {
+    pub(crate) fn new(observer: Option<Arc<dyn AnnounceObserver>>) -> Self {
+        Self(observer)
+    }
+
+    pub(crate) fn notify(&self, callback: impl FnOnce(&dyn AnnounceObserver)) {
+        if let Some(observer) = &self.0 {
+            let _ = panic::catch_unwind(AssertUnwindSafe(|| callback(&**observer)));
+        }
+    }
+}
+
+// Feeds announces into the `metrics` facade, for whichever recorder the
+// embedder installed:
+//
+//  - hanekawa_client_announces_total, by `outcome`
+//  - hanekawa_client_announce_duration_seconds
+//  - hanekawa_client_retries_total
+//  - hanekawa_client_tier_switches_total
+//  - hanekawa_client_peers_received
+#[cfg(feature = "metrics")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MetricsObserver;
+
+#[cfg(feature = "metrics")]
+impl AnnounceObserver for MetricsObserver {
+    fn request_finished(
+        &self,
+        _url: &str,
+        elapsed: Duration,
+        outcome: Result<&AnnounceResponse, &ClientError>,
+    ) {
+        use crate::ErrorKind;
+
+        let outcome = match outcome.map_err(|e| e.kind()) {
+            Ok(_) => "success",
+            Err(ErrorKind::Transport) => "transport",
+            Err(ErrorKind::Http) => "http",
+            Err(ErrorKind::Protocol) => "protocol",
+            Err(ErrorKind::Tracker) => "tracker",
+            Err(ErrorKind::Request) => "request",
+        };
+
+        metrics::counter!("hanekawa_client_announces_total", "outcome" => outcome).increment(1);
+        metrics::histogram!("hanekawa_client_announce_duration_seconds")
+            .record(elapsed.as_secs_f64());
+    }
+
+    fn retry_scheduled(&self, _delay: Duration, _failures: u32) {
+        metrics::counter!("hanekawa_client_retries_total").increment(1);
+    }
+
+    fn tier_switched(&self, _from: usize, _to: usize) {
+        metrics::counter!("hanekawa_client_tier_switches_total").increment(1);
+    }
+
+    fn peers_received(&self, _url: &str, count: usize) {
+        metrics::histogram!("hanekawa_client_peers_received").record(count as f64);
+    }
+}
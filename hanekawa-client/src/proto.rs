@@ -0,0 +1,180 @@
+use crate::ClientError;
+
+use hanekawa_common::types::{Event, InfoHash, PeerId};
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::Duration,
+};
+
+const IPV4_PEER_SIZE: usize = 6;
+const IPV6_PEER_SIZE: usize = 18;
+
+#[derive(Debug, Clone)]
+pub struct AnnounceParams {
+    pub info_hash: InfoHash,
+    pub peer_id: PeerId,
+    pub port: u16,
+    pub uploaded: u64,
+    pub downloaded: u64,
+    pub left: u64,
+    pub event: Event,
+    pub num_want: Option<u32>,
+    pub compact: bool,
+    pub key: Option<String>,
+    // The peer's address, for a tracker trusting us to say, which otherwise
+    // takes the one the request came from.
+    pub ip: Option<IpAddr>,
+}
+
+// BEP 31: Failure Retry Extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryIn {
+    After(Duration),
+    Never,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+pub struct DictPeer {
+    #[serde(rename = "peer id")]
+    pub peer_id: Option<PeerId>,
+    pub ip: String,
+    pub port: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+#[serde(untagged)]
+pub enum PeerList {
+    Compact(#[serde(with = "serde_bytes")] Vec<u8>),
+    Dict(Vec<DictPeer>),
+}
+
+impl Default for PeerList {
+    fn default() -> Self {
+        Self::Compact(vec![])
+    }
+}
+
+// A peer in either response shape. Compact entries carry no peer id.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Peer {
+    pub peer_id: Option<PeerId>,
+    pub addr: SocketAddr,
+}
+
+impl PeerList {
+    fn to_peers(&self, key: &str, entry_size: usize) -> Result<Vec<Peer>, ClientError> {
+        match self {
+            Self::Compact(bs) => {
+                if bs.len() % entry_size != 0 {
+                    return Err(ClientError::Malformed(format!(
+                        "compact {key} is {} bytes, not a multiple of {entry_size}",
+                        bs.len()
+                    )));
+                }
+
+                Ok(bs
+                    .chunks_exact(entry_size)
+                    .map(|entry| {
+                        let (ip, port) = entry.split_at(entry_size - 2);
+                        let ip = match <[u8; 4]>::try_from(ip) {
+                            Ok(octets) => IpAddr::V4(Ipv4Addr::from(octets)),
+                            Err(_) => IpAddr::V6(Ipv6Addr::from(
+                                <[u8; 16]>::try_from(ip).expect("entry size is 6 or 18"),
+                            )),
+                        };
+                        let port = u16::from_be_bytes([port[0], port[1]]);
+
+                        Peer {
+                            peer_id: None,
+                            addr: SocketAddr::new(ip, port),
+                        }
+                    })
+                    .collect())
+            }
+            // Peers given by DNS name are skipped, resolving them is up to the caller.
+            Self::Dict(peers) => Ok(peers
+                .iter()
+                .filter_map(|peer| {
+                    let ip = match peer.ip.strip_prefix('[') {
+                        Some(ip) => ip.strip_suffix(']')?,
+                        None => &peer.ip,
+                    };
+                    let ip = ip.parse::<IpAddr>().ok()?;
+
+                    Some(Peer {
+                        peer_id: peer.peer_id.clone(),
+                        addr: SocketAddr::new(ip, peer.port),
+                    })
+                })
+                .collect()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+pub struct AnnounceResponse {
+    pub interval: u32,
+    #[serde(rename = "min interval")]
+    pub min_interval: Option<u32>,
+    pub complete: Option<u32>,
+    pub incomplete: Option<u32>,
+    #[serde(rename = "warning message")]
+    pub warning_message: Option<String>,
+    #[serde(rename = "tracker id", default, with = "serde_bytes")]
+    pub tracker_id: Option<Vec<u8>>,
+    #[serde(default)]
+    pub peers: PeerList,
+    pub peers6: Option<PeerList>,
+}
+
+impl AnnounceResponse {
+    // All peers from `peers` and `peers6`, in that order.
+    pub fn peers(&self) -> Result<Vec<Peer>, ClientError> {
+        let mut peers = self.peers.to_peers("peers", IPV4_PEER_SIZE)?;
+        if let Some(peers6) = &self.peers6 {
+            peers.extend(peers6.to_peers("peers6", IPV6_PEER_SIZE)?);
+        }
+
+        Ok(peers)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(default)]
+pub struct ScrapeFile {
+    pub complete: u32,
+    pub downloaded: u32,
+    pub incomplete: u32,
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
+#[serde(from = "ScrapeBody")]
+pub struct ScrapeResponse {
+    pub files: HashMap<InfoHash, ScrapeFile>,
+    // The `flags.min_request_interval` hint, in seconds.
+    pub min_request_interval: Option<u32>,
+}
+
+#[derive(serde::Deserialize)]
+struct ScrapeBody {
+    #[serde(default)]
+    files: HashMap<InfoHash, ScrapeFile>,
+    flags: Option<ScrapeFlags>,
+}
+
+#[derive(serde::Deserialize)]
+struct ScrapeFlags {
+    min_request_interval: Option<u32>,
+}
+
+impl From<ScrapeBody> for ScrapeResponse {
+    fn from(body: ScrapeBody) -> Self {
+        Self {
+            files: body.files,
+            min_request_interval: body.flags.and_then(|f| f.min_request_interval),
+        }
+    }
+}
@@ -0,0 +1,378 @@
+use crate::ClientError;
+
+use url::Url;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Proxy {
+    // http trackers are requested through the proxy, https ones are tunneled
+    // with CONNECT.
+    Http {
+        url: String,
+        credentials: Option<Credentials>,
+    },
+    // Tracker host names are resolved by the proxy, so lookups do not leak.
+    Socks5 {
+        addr: String,
+        credentials: Option<Credentials>,
+    },
+}
+
+impl Proxy {
+    pub fn http(url: impl Into<String>) -> Self {
+        Self::Http {
+            url: url.into(),
+            credentials: None,
+        }
+    }
+
+    // `addr` is the proxy's host:port.
+    pub fn socks5(addr: impl Into<String>) -> Self {
+        Self::Socks5 {
+            addr: addr.into(),
+            credentials: None,
+        }
+    }
+
+    pub fn with_credentials(
+        mut self,
+        username: impl Into<String>,
+        password: impl Into<String>,
+    ) -> Self {
+        let new = Some(Credentials {
+            username: username.into(),
+            password: password.into(),
+        });
+
+        match &mut self {
+            Self::Http { credentials, .. } | Self::Socks5 { credentials, .. } => *credentials = new,
+        }
+
+        self
+    }
+
+    pub(crate) fn kind(&self) -> &'static str {
+        match self {
+            Self::Http { .. } => "http",
+            Self::Socks5 { .. } => "socks5",
+        }
+    }
+
+    pub(crate) fn to_reqwest(&self) -> Result<reqwest::Proxy, ClientError> {
+        let invalid = |url: &str, e: &dyn std::fmt::Display| {
+            ClientError::InvalidUrl(format!("proxy {url}: {e}"))
+        };
+
+        match self {
+            Self::Http { url, credentials } => {
+                let proxy = reqwest::Proxy::all(url.as_str()).map_err(|e| invalid(url, &e))?;
+
+                Ok(match credentials {
+                    Some(c) => proxy.basic_auth(&c.username, &c.password),
+                    None => proxy,
+                })
+            }
+            Self::Socks5 { addr, credentials } => {
+                // socks5h rather than socks5, for remote name resolution.
+                let mut url =
+                    Url::parse(&format!("socks5h://{addr}")).map_err(|e| invalid(addr, &e))?;
+                if let Some(c) = credentials {
+                    let _ = url.set_username(&c.username);
+                    let _ = url.set_password(Some(&c.password));
+                }
+
+                reqwest::Proxy::all(url.as_str()).map_err(|e| invalid(addr, &e))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::{
+        proto::AnnounceParams, ErrorKind, HttpTrackerClient, TrackerClient, TrackerTransport,
+        UdpTrackerClient,
+    };
+    use axum::{
+        extract::{ConnectInfo, State},
+        routing::get,
+        Router,
+    };
+    use hanekawa_common::types::{Event, InfoHash, PeerId};
+    use std::{
+        net::{Ipv4Addr, SocketAddr},
+        sync::{Arc, Mutex},
+    };
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+
+    const OK: &[u8] = b"d8:intervali1800e5:peers0:e";
+
+    fn params() -> AnnounceParams {
+        AnnounceParams {
+            info_hash: InfoHash(vec![0xaa; 20]),
+            peer_id: PeerId(b"-HK0100-123456789012".to_vec()),
+            port: 6881,
+            uploaded: 0,
+            downloaded: 0,
+            left: 100,
+            event: Event::Started,
+            num_want: None,
+            compact: true,
+            key: None,
+            ip: None,
+        }
+    }
+
+    type Seen = Arc<Mutex<Vec<SocketAddr>>>;
+
+    // Returns the port and the addresses connections came from.
+    async fn spawn_tracker() -> (u16, Seen) {
+        let seen = Seen::default();
+        let app = Router::new()
+            .route(
+                "/announce",
+                get(
+                    |State(seen): State<Seen>, ConnectInfo(addr): ConnectInfo<SocketAddr>| async move {
+                        seen.lock().unwrap().push(addr);
+                        OK
+                    },
+                ),
+            )
+            .with_state(seen.clone());
+
+        let server = axum::Server::bind(&"127.0.0.1:0".parse().unwrap())
+            .serve(app.into_make_service_with_connect_info::<SocketAddr>());
+        let port = server.local_addr().port();
+        tokio::spawn(server);
+
+        (port, seen)
+    }
+
+    #[derive(Default)]
+    struct Socks5 {
+        credentials: Option<(&'static str, &'static str)>,
+        // Targets as the client asked for them.
+        targets: Mutex<Vec<String>>,
+        // Local addresses of the proxy's own connections to the targets.
+        outbound: Mutex<Vec<SocketAddr>>,
+    }
+
+    // A SOCKS5 proxy that only knows CONNECT, and resolves every name to
+    // localhost.
+    async fn spawn_socks5(
+        credentials: Option<(&'static str, &'static str)>,
+    ) -> (String, Arc<Socks5>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let proxy = Arc::new(Socks5 {
+            credentials,
+            ..Default::default()
+        });
+
+        let state = proxy.clone();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                tokio::spawn(socks5_session(stream, state.clone()));
+            }
+        });
+
+        (addr.to_string(), proxy)
+    }
+
+    async fn read_string(stream: &mut TcpStream) -> String {
+        let len = stream.read_u8().await.unwrap();
+        let mut buf = vec![0; len as usize];
+        stream.read_exact(&mut buf).await.unwrap();
+
+        String::from_utf8(buf).unwrap()
+    }
+
+    async fn socks5_session(mut client: TcpStream, proxy: Arc<Socks5>) {
+        assert_eq!(5, client.read_u8().await.unwrap());
+        let mut methods = vec![0; client.read_u8().await.unwrap() as usize];
+        client.read_exact(&mut methods).await.unwrap();
+
+        let method = if proxy.credentials.is_some() { 2 } else { 0 };
+        if !methods.contains(&method) {
+            client.write_all(&[5, 0xff]).await.unwrap();
+            return;
+        }
+        client.write_all(&[5, method]).await.unwrap();
+
+        if let Some((username, password)) = proxy.credentials {
+            assert_eq!(1, client.read_u8().await.unwrap());
+            let accepted = read_string(&mut client).await == username
+                && read_string(&mut client).await == password;
+
+            client
+                .write_all(&[1, if accepted { 0 } else { 1 }])
+                .await
+                .unwrap();
+            if !accepted {
+                return;
+            }
+        }
+
+        let mut request = [0; 4];
+        client.read_exact(&mut request).await.unwrap();
+        assert_eq!([5, 1, 0], request[..3]);
+        let host = match request[3] {
+            1 => Ipv4Addr::from(client.read_u32().await.unwrap()).to_string(),
+            3 => read_string(&mut client).await,
+            atyp => panic!("unexpected address type {atyp}"),
+        };
+        let port = client.read_u16().await.unwrap();
+        proxy.targets.lock().unwrap().push(format!("{host}:{port}"));
+
+        let mut upstream = TcpStream::connect((Ipv4Addr::LOCALHOST, port))
+            .await
+            .unwrap();
+        proxy
+            .outbound
+            .lock()
+            .unwrap()
+            .push(upstream.local_addr().unwrap());
+
+        client
+            .write_all(&[5, 0, 0, 1, 0, 0, 0, 0, 0, 0])
+            .await
+            .unwrap();
+        let _ = tokio::io::copy_bidirectional(&mut client, &mut upstream).await;
+    }
+
+    #[tokio::test]
+    async fn announces_through_socks5_resolving_names_remotely() {
+        let (port, seen) = spawn_tracker().await;
+        let (addr, proxy) = spawn_socks5(None).await;
+
+        let client = HttpTrackerClient::builder()
+            .proxy(Proxy::socks5(addr))
+            .build()
+            .unwrap();
+
+        // Only the proxy can resolve this name.
+        client
+            .announce(&format!("http://tracker.invalid:{port}/announce"), params())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            vec![format!("tracker.invalid:{port}")],
+            *proxy.targets.lock().unwrap()
+        );
+        assert_eq!(*proxy.outbound.lock().unwrap(), *seen.lock().unwrap());
+
+        // Overridden for a single tracker, which is then contacted directly.
+        client
+            .with_proxy(None)
+            .unwrap()
+            .announce(&format!("http://127.0.0.1:{port}/announce"), params())
+            .await
+            .unwrap();
+
+        assert_eq!(1, proxy.targets.lock().unwrap().len());
+        assert_eq!(2, seen.lock().unwrap().len());
+    }
+
+    #[tokio::test]
+    async fn authenticates_to_socks5() {
+        let (port, seen) = spawn_tracker().await;
+        let (addr, proxy) = spawn_socks5(Some(("user", "hunter2"))).await;
+        let url = format!("http://127.0.0.1:{port}/announce");
+
+        let client = HttpTrackerClient::builder()
+            .proxy(Proxy::socks5(&addr).with_credentials("user", "hunter2"))
+            .build()
+            .unwrap();
+        client.announce(&url, params()).await.unwrap();
+
+        assert_eq!(*proxy.outbound.lock().unwrap(), *seen.lock().unwrap());
+
+        for proxy in [
+            Proxy::socks5(&addr).with_credentials("user", "wrong"),
+            Proxy::socks5(&addr),
+        ] {
+            let e = client
+                .with_proxy(Some(proxy))
+                .unwrap()
+                .announce(&url, params())
+                .await
+                .unwrap_err();
+            assert_eq!(ErrorKind::Transport, e.kind(), "{e}");
+        }
+        assert_eq!(1, seen.lock().unwrap().len());
+    }
+
+    #[tokio::test]
+    async fn sends_requests_to_http_proxies() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // Answers with the request head as the failure reason.
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+
+            let mut head = vec![];
+            while !head.ends_with(b"\r\n\r\n") {
+                head.push(stream.read_u8().await.unwrap());
+            }
+
+            let body = [
+                format!("d14:failure reason{}:", head.len()).as_bytes(),
+                &head,
+                b"e",
+            ]
+            .concat();
+            let response = format!("HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n", body.len());
+            stream.write_all(response.as_bytes()).await.unwrap();
+            stream.write_all(&body).await.unwrap();
+        });
+
+        let client = HttpTrackerClient::builder()
+            .proxy(Proxy::http(format!("http://{addr}")).with_credentials("user", "pass"))
+            .build()
+            .unwrap();
+
+        let head = match client
+            .announce("http://tracker.invalid/announce", params())
+            .await
+        {
+            Err(crate::ClientError::Failure { reason, .. }) => reason.to_lowercase(),
+            r => panic!("expected the request head, got {r:?}"),
+        };
+
+        assert!(head.starts_with("get http://tracker.invalid/announce?info_hash="));
+        assert!(head.contains("\r\nproxy-authorization: basic dxnlcjpwyxnz\r\n"));
+    }
+
+    #[tokio::test]
+    async fn refuses_udp_through_proxies() {
+        let http = HttpTrackerClient::builder()
+            .proxy(Proxy::socks5("127.0.0.1:1080"))
+            .build()
+            .unwrap();
+        let client = TrackerClient::new(http, UdpTrackerClient::new());
+
+        let e = client
+            .announce("udp://127.0.0.1:6969/announce", params())
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            "UDP not supported over this proxy type (socks5)",
+            e.to_string()
+        );
+        assert!(!e.is_retryable());
+    }
+}
@@ -0,0 +1,126 @@
+use crate::proto::RetryIn;
+use crate::ClientError;
+
+use rand::Rng;
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_secs(15),
+            max_backoff: Duration::from_secs(30 * 60),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Retry {
+    After(Duration),
+    Never,
+}
+
+impl RetryPolicy {
+    // `attempt` counts the failures before this one, starting at 0.
+    pub fn retry(&self, error: &ClientError, attempt: u32) -> Retry {
+        match error {
+            ClientError::Failure {
+                retry_in: Some(RetryIn::After(delay)),
+                ..
+            } => Retry::After(*delay),
+            ClientError::RateLimited(Some(delay)) => Retry::After(*delay),
+            e if !e.is_retryable() => Retry::Never,
+            _ => Retry::After(self.backoff(attempt)),
+        }
+    }
+
+    fn backoff(&self, attempt: u32) -> Duration {
+        let delay = self
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_backoff);
+
+        // Keep at least half of the delay so retries still back off.
+        let half = delay / 2;
+        half + rand::thread_rng().gen_range(Duration::ZERO..=half)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            initial_backoff: Duration::from_secs(10),
+            max_backoff: Duration::from_secs(60),
+        }
+    }
+
+    fn failure(reason: &str, retry_in: Option<RetryIn>) -> ClientError {
+        ClientError::Failure {
+            reason: reason.to_string(),
+            retry_in,
+        }
+    }
+
+    #[test]
+    fn backs_off_exponentially_up_to_the_cap() {
+        for (attempt, max) in [(0, 10), (1, 20), (2, 40), (3, 60), (20, 60), (u32::MAX, 60)] {
+            let Retry::After(delay) = policy().retry(&ClientError::Status(503), attempt) else {
+                panic!("expected a retry");
+            };
+
+            let max = Duration::from_secs(max);
+            assert!(
+                delay >= max / 2 && delay <= max,
+                "attempt {attempt}: {delay:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn honors_retry_in() {
+        assert_eq!(
+            Retry::After(Duration::from_secs(120)),
+            policy().retry(
+                &failure("overloaded", Some(RetryIn::After(Duration::from_secs(120)))),
+                5
+            )
+        );
+        assert_eq!(
+            Retry::Never,
+            policy().retry(&failure("overloaded", Some(RetryIn::Never)), 0)
+        );
+    }
+
+    #[test]
+    fn gives_up_on_permanent_failures() {
+        assert_eq!(
+            Retry::Never,
+            policy().retry(&failure("Unregistered torrent", None), 0)
+        );
+        assert_eq!(Retry::Never, policy().retry(&ClientError::Status(404), 0));
+        assert!(matches!(
+            policy().retry(&failure("tracker is overloaded", None), 0),
+            Retry::After(_)
+        ));
+    }
+
+    #[test]
+    fn honors_retry_after() {
+        assert_eq!(
+            Retry::After(Duration::from_secs(42)),
+            policy().retry(&ClientError::RateLimited(Some(Duration::from_secs(42))), 0)
+        );
+        assert!(matches!(
+            policy().retry(&ClientError::RateLimited(None), 0),
+            Retry::After(_)
+        ));
+    }
+}
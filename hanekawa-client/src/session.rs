@@ -0,0 +1,229 @@
+use crate::announcer::{Announcer, AnnouncerHandle, SwarmStats};
+use crate::clock::{Clock, TokioClock};
+use crate::proto::{AnnounceParams, Peer};
+use crate::tiers::Tiers;
+use crate::{ClientError, TrackerTransport};
+
+use std::sync::Arc;
+use tokio::sync::watch;
+
+// A torrent's announces over its lifetime, driven by its progress: `started`
+// first, `completed` once when nothing is left, `stopped` on close and
+// regular announces in between. Torrents that start out complete never
+// announce `completed`.
+pub struct TorrentSession {
+    handle: AnnouncerHandle,
+}
+
+impl TorrentSession {
+    // The event in `params` is ignored, the session picks its own.
+    pub fn start(
+        client: impl TrackerTransport + 'static,
+        announce_list: Vec<Vec<String>>,
+        params: AnnounceParams,
+    ) -> Self {
+        Self::start_with_clock(
+            Arc::new(client),
+            Tiers::new(announce_list),
+            params,
+            Arc::new(TokioClock),
+        )
+    }
+
+    pub(crate) fn start_with_clock(
+        client: Arc<dyn TrackerTransport>,
+        tiers: Tiers,
+        params: AnnounceParams,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        Self {
+            handle: Announcer::spawn_with_clock(client, tiers, params, clock),
+        }
+    }
+
+    pub fn add_downloaded(&self, bytes: u64) {
+        self.handle.stats().add_downloaded(bytes);
+    }
+
+    pub fn add_uploaded(&self, bytes: u64) {
+        self.handle.stats().add_uploaded(bytes);
+    }
+
+    // Reaching 0 announces `completed` right away.
+    pub fn set_left(&self, bytes: u64) {
+        self.handle.stats().set_left(bytes);
+    }
+
+    // As of the last successful announce.
+    pub fn swarm(&self) -> SwarmStats {
+        self.handle.swarm().borrow().clone()
+    }
+
+    pub fn swarm_updates(&self) -> watch::Receiver<SwarmStats> {
+        self.handle.swarm()
+    }
+
+    pub fn peers(&self) -> watch::Receiver<Vec<Peer>> {
+        self.handle.peers()
+    }
+
+    // Sends `stopped`, and returns the terminal error if announcing had
+    // already been given up on.
+    pub async fn close(self) -> Result<(), ClientError> {
+        self.handle.shutdown().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::HttpTrackerClient;
+    use axum::{
+        extract::{RawQuery, State},
+        routing::get,
+        Router,
+    };
+    use hanekawa::http_tracker::proto::AnnounceRequest;
+    use hanekawa_common::types::{Event, InfoHash, PeerId};
+    use std::{future::Future, pin::Pin, sync::Mutex, time::Duration};
+    use tokio::sync::{mpsc, oneshot};
+
+    const OK: &[u8] = b"d8:completei3e10:incompletei7e8:intervali1800e5:peers0:e";
+
+    type Seen = Arc<Mutex<Vec<(Event, u64, u64)>>>;
+
+    async fn spawn_tracker() -> (String, Seen) {
+        let seen = Seen::default();
+        let app = Router::new()
+            .route(
+                "/announce",
+                get(
+                    |State(seen): State<Seen>, RawQuery(query): RawQuery| async move {
+                        let request: AnnounceRequest =
+                            hanekawa_percent_encode::from_query_string(&query.unwrap_or_default())
+                                .unwrap();
+
+                        seen.lock().unwrap().push((
+                            request.event,
+                            request.downloaded,
+                            request.left,
+                        ));
+                        OK
+                    },
+                ),
+            )
+            .with_state(seen.clone());
+
+        let server =
+            axum::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(app.into_make_service());
+        let addr = server.local_addr();
+        tokio::spawn(server);
+
+        (format!("http://{addr}/announce"), seen)
+    }
+
+    // Hands every sleep to the test, which decides when it is over.
+    struct MockClock {
+        sleeps: mpsc::UnboundedSender<oneshot::Sender<()>>,
+    }
+
+    impl Clock for MockClock {
+        fn sleep(&self, _duration: Duration) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+            let (wake, woken) = oneshot::channel();
+            let _ = self.sleeps.send(wake);
+
+            Box::pin(async move {
+                let _ = woken.await;
+            })
+        }
+    }
+
+    async fn start(
+        left: u64,
+    ) -> (
+        TorrentSession,
+        Seen,
+        mpsc::UnboundedReceiver<oneshot::Sender<()>>,
+    ) {
+        let (url, seen) = spawn_tracker().await;
+        let (sleeps, sleeps_rx) = mpsc::unbounded_channel();
+
+        let params = AnnounceParams {
+            info_hash: InfoHash(vec![0xaa; 20]),
+            peer_id: PeerId(b"-HK0100-123456789012".to_vec()),
+            port: 6881,
+            uploaded: 0,
+            downloaded: 0,
+            left,
+            event: Event::Interval,
+            num_want: None,
+            compact: true,
+            key: None,
+            ip: None,
+        };
+        let session = TorrentSession::start_with_clock(
+            Arc::new(HttpTrackerClient::new().unwrap()),
+            Tiers::single(url),
+            params,
+            Arc::new(MockClock { sleeps }),
+        );
+
+        (session, seen, sleeps_rx)
+    }
+
+    #[tokio::test]
+    async fn announces_a_download_to_completion() {
+        let (session, seen, mut sleeps) = start(300).await;
+
+        let wake = sleeps.recv().await.unwrap();
+        assert_eq!(
+            SwarmStats {
+                seeders: Some(3),
+                leechers: Some(7),
+            },
+            session.swarm()
+        );
+
+        session.add_downloaded(100);
+        session.set_left(200);
+        wake.send(()).unwrap();
+        let _wake = sleeps.recv().await.unwrap();
+
+        // Pieces that fail to verify can make `left` go back up.
+        session.add_downloaded(200);
+        session.set_left(0);
+        let wake = sleeps.recv().await.unwrap();
+        session.set_left(50);
+        wake.send(()).unwrap();
+        let _wake = sleeps.recv().await.unwrap();
+        session.set_left(0);
+
+        session.close().await.unwrap();
+
+        assert_eq!(
+            vec![
+                (Event::Started, 0, 300),
+                (Event::Interval, 100, 200),
+                (Event::Completed, 300, 0),
+                (Event::Interval, 300, 50),
+                (Event::Stopped, 300, 0),
+            ],
+            *seen.lock().unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn seeders_never_complete() {
+        let (session, seen, mut sleeps) = start(0).await;
+
+        let _wake = sleeps.recv().await.unwrap();
+        session.set_left(0);
+        session.close().await.unwrap();
+
+        assert_eq!(
+            vec![(Event::Started, 0, 0), (Event::Stopped, 0, 0)],
+            *seen.lock().unwrap()
+        );
+    }
+}
@@ -0,0 +1,108 @@
+use rand::seq::SliceRandom;
+
+// BEP 12: Multitracker Metadata Extension
+//
+// Trackers grouped in tiers, each kept in the order it should be tried.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Tiers {
+    tiers: Vec<Vec<String>>,
+}
+
+impl Tiers {
+    // Each tier is shuffled once, later rounds only reorder by promotion.
+    pub(crate) fn new(announce_list: Vec<Vec<String>>) -> Self {
+        let mut rng = rand::thread_rng();
+
+        let tiers = announce_list
+            .into_iter()
+            .filter(|tier| !tier.is_empty())
+            .map(|mut tier| {
+                tier.shuffle(&mut rng);
+                tier
+            })
+            .collect();
+
+        Self { tiers }
+    }
+
+    pub(crate) fn single(url: String) -> Self {
+        Self {
+            tiers: vec![vec![url]],
+        }
+    }
+
+    // Every tracker as (tier, index), in the order to try them.
+    pub(crate) fn positions(&self) -> Vec<(usize, usize)> {
+        self.tiers
+            .iter()
+            .enumerate()
+            .flat_map(|(tier, urls)| (0..urls.len()).map(move |index| (tier, index)))
+            .collect()
+    }
+
+    pub(crate) fn url(&self, (tier, index): (usize, usize)) -> &str {
+        &self.tiers[tier][index]
+    }
+
+    // Moves a tracker that answered to the front of its tier.
+    pub(crate) fn promote(&mut self, (tier, index): (usize, usize)) {
+        let url = self.tiers[tier].remove(index);
+        self.tiers[tier].insert(0, url);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn tiers(tiers: &[&[&str]]) -> Vec<Vec<String>> {
+        tiers
+            .iter()
+            .map(|tier| tier.iter().map(ToString::to_string).collect())
+            .collect()
+    }
+
+    #[test]
+    fn shuffles_within_tiers_only() {
+        let list = tiers(&[&["a", "b", "c"], &[], &["d", "e"]]);
+
+        let mut shuffled = Tiers::new(list).tiers;
+        for tier in &mut shuffled {
+            tier.sort();
+        }
+
+        assert_eq!(tiers(&[&["a", "b", "c"], &["d", "e"]]), shuffled);
+    }
+
+    #[test]
+    fn tries_tiers_in_order() {
+        let tiers = Tiers {
+            tiers: tiers(&[&["a", "b"], &["c"]]),
+        };
+
+        let urls: Vec<_> = tiers
+            .positions()
+            .into_iter()
+            .map(|p| tiers.url(p))
+            .collect();
+
+        assert_eq!(vec!["a", "b", "c"], urls);
+    }
+
+    #[test]
+    fn promotes_within_the_tier() {
+        let mut tiers = Tiers {
+            tiers: tiers(&[&["a", "b", "c"], &["d", "e"]]),
+        };
+
+        tiers.promote((0, 2));
+        tiers.promote((1, 1));
+
+        assert_eq!(
+            Tiers {
+                tiers: self::tiers(&[&["c", "a", "b"], &["e", "d"]])
+            },
+            tiers
+        );
+    }
+}
@@ -0,0 +1,148 @@
+use crate::proto::{AnnounceParams, AnnounceResponse, ScrapeResponse};
+use crate::{AnnounceObserver, ClientError, HttpTrackerClient, RetryPolicy, UdpTrackerClient};
+
+use hanekawa_common::types::InfoHash;
+
+use std::sync::Arc;
+
+// What the announcer needs from a tracker, whichever protocol it speaks.
+#[async_trait::async_trait]
+pub trait TrackerTransport: Send + Sync {
+    async fn announce(
+        &self,
+        url: &str,
+        params: AnnounceParams,
+    ) -> Result<AnnounceResponse, ClientError>;
+
+    async fn scrape(
+        &self,
+        url: &str,
+        info_hashes: &[InfoHash],
+    ) -> Result<ScrapeResponse, ClientError>;
+
+    fn retry_policy(&self) -> &RetryPolicy;
+
+    // Told what announcers using this transport do.
+    fn observer(&self) -> Option<Arc<dyn AnnounceObserver>> {
+        None
+    }
+}
+
+#[async_trait::async_trait]
+impl TrackerTransport for HttpTrackerClient {
+    async fn announce(
+        &self,
+        url: &str,
+        params: AnnounceParams,
+    ) -> Result<AnnounceResponse, ClientError> {
+        HttpTrackerClient::announce(self, url, params).await
+    }
+
+    async fn scrape(
+        &self,
+        url: &str,
+        info_hashes: &[InfoHash],
+    ) -> Result<ScrapeResponse, ClientError> {
+        HttpTrackerClient::scrape(self, url, info_hashes).await
+    }
+
+    fn retry_policy(&self) -> &RetryPolicy {
+        HttpTrackerClient::retry_policy(self)
+    }
+
+    fn observer(&self) -> Option<Arc<dyn AnnounceObserver>> {
+        HttpTrackerClient::observer(self)
+    }
+}
+
+#[async_trait::async_trait]
+impl TrackerTransport for UdpTrackerClient {
+    async fn announce(
+        &self,
+        url: &str,
+        params: AnnounceParams,
+    ) -> Result<AnnounceResponse, ClientError> {
+        UdpTrackerClient::announce(self, url, params).await
+    }
+
+    async fn scrape(
+        &self,
+        url: &str,
+        info_hashes: &[InfoHash],
+    ) -> Result<ScrapeResponse, ClientError> {
+        UdpTrackerClient::scrape(self, url, info_hashes).await
+    }
+
+    fn retry_policy(&self) -> &RetryPolicy {
+        UdpTrackerClient::retry_policy(self)
+    }
+
+    fn observer(&self) -> Option<Arc<dyn AnnounceObserver>> {
+        UdpTrackerClient::observer(self)
+    }
+}
+
+// Picks the client by url scheme, for announce lists mixing both kinds of
+// trackers.
+#[derive(Clone)]
+pub struct TrackerClient {
+    http: HttpTrackerClient,
+    udp: UdpTrackerClient,
+}
+
+impl TrackerClient {
+    // A proxy set on the HTTP client applies to the UDP one too, so UDP
+    // trackers fail instead of being contacted directly.
+    pub fn new(http: HttpTrackerClient, udp: UdpTrackerClient) -> Self {
+        let udp = match (http.proxy(), udp.proxy()) {
+            (Some(proxy), None) => udp.with_proxy(Some(proxy.clone())),
+            _ => udp,
+        };
+
+        Self { http, udp }
+    }
+
+    fn transport(&self, url: &str) -> Result<&dyn TrackerTransport, ClientError> {
+        match url.split_once("://").map(|(scheme, _)| scheme) {
+            Some("udp") => Ok(&self.udp),
+            Some("http" | "https") => Ok(&self.http),
+            _ => Err(ClientError::UnsupportedScheme(url.to_string())),
+        }
+    }
+
+    pub(crate) fn supports(url: &str) -> bool {
+        matches!(
+            url.split_once("://").map(|(scheme, _)| scheme),
+            Some("udp" | "http" | "https")
+        )
+    }
+}
+
+#[async_trait::async_trait]
+impl TrackerTransport for TrackerClient {
+    async fn announce(
+        &self,
+        url: &str,
+        params: AnnounceParams,
+    ) -> Result<AnnounceResponse, ClientError> {
+        self.transport(url)?.announce(url, params).await
+    }
+
+    async fn scrape(
+        &self,
+        url: &str,
+        info_hashes: &[InfoHash],
+    ) -> Result<ScrapeResponse, ClientError> {
+        self.transport(url)?.scrape(url, info_hashes).await
+    }
+
+    // Failures of either protocol are retried by the HTTP client's policy.
+    fn retry_policy(&self) -> &RetryPolicy {
+        self.http.retry_policy()
+    }
+
+    // The HTTP client's, falling back to the UDP one's.
+    fn observer(&self) -> Option<Arc<dyn AnnounceObserver>> {
+        self.http.observer().or_else(|| self.udp.observer())
+    }
+}
@@ -0,0 +1,1037 @@
+use crate::clock::{Clock, TokioClock};
+use crate::key::AnnounceKey;
+use crate::proto::{AnnounceParams, AnnounceResponse, PeerList, ScrapeFile, ScrapeResponse};
+use crate::proxy::Proxy;
+use crate::retry::RetryPolicy;
+use crate::{error::deadline, AnnounceObserver, ClientError};
+
+use hanekawa_common::types::{Event, InfoHash};
+
+use std::{
+    collections::HashMap,
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::net::UdpSocket;
+use url::{Host, Url};
+
+// BEP 15: UDP Tracker Protocol for BitTorrent
+
+const PROTOCOL_ID: i64 = 0x41727101980;
+
+const CONNECT: i32 = 0;
+const ANNOUNCE: i32 = 1;
+const SCRAPE: i32 = 2;
+const ERROR: i32 = 3;
+
+// A request is retransmitted after 15 * 2^n seconds, for n up to 8.
+const BASE_TIMEOUT: Duration = Duration::from_secs(15);
+const DEFAULT_MAX_RETRANSMISSIONS: u32 = 8;
+
+// For a whole announce or scrape, so by default a request is only sent once
+// more if its connect had to be.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(15);
+
+// Trackers accept a connection id for two minutes, but clients are only
+// meant to use it for one so it cannot expire in flight.
+const CONNECTION_ID_LIFETIME: Duration = Duration::from_secs(60);
+
+// The most info hashes that fit in one scrape packet.
+const MAX_SCRAPE_HASHES: usize = 74;
+
+const MAX_PACKET_SIZE: usize = 65_536;
+
+#[async_trait::async_trait]
+pub(crate) trait Socket: Send + Sync {
+    async fn send(&self, packet: &[u8]) -> io::Result<()>;
+    async fn recv(&self, buf: &mut [u8]) -> io::Result<usize>;
+}
+
+#[async_trait::async_trait]
+impl Socket for UdpSocket {
+    async fn send(&self, packet: &[u8]) -> io::Result<()> {
+        UdpSocket::send(self, packet).await.map(|_| ())
+    }
+
+    async fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        UdpSocket::recv(self, buf).await
+    }
+}
+
+#[async_trait::async_trait]
+pub(crate) trait Network: Send + Sync {
+    async fn connect(&self, addr: SocketAddr) -> io::Result<Box<dyn Socket>>;
+}
+
+struct TokioNetwork;
+
+#[async_trait::async_trait]
+impl Network for TokioNetwork {
+    async fn connect(&self, addr: SocketAddr) -> io::Result<Box<dyn Socket>> {
+        let local = match addr {
+            SocketAddr::V4(_) => SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+            SocketAddr::V6(_) => SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)),
+        };
+
+        let socket = UdpSocket::bind(local).await?;
+        socket.connect(addr).await?;
+
+        Ok(Box::new(socket))
+    }
+}
+
+pub struct UdpTrackerClientBuilder {
+    retry_policy: RetryPolicy,
+    max_retransmissions: u32,
+    announce_timeout: Duration,
+    scrape_timeout: Duration,
+    proxy: Option<Proxy>,
+    announce_key: Option<AnnounceKey>,
+    observer: Option<Arc<dyn AnnounceObserver>>,
+    network: Arc<dyn Network>,
+    clock: Arc<dyn Clock>,
+}
+
+impl UdpTrackerClientBuilder {
+    // Per request, so the longest wait for a reply is 15 * (2^(n + 1) - 1)
+    // seconds.
+    pub fn max_retransmissions(mut self, max_retransmissions: u32) -> Self {
+        self.max_retransmissions = max_retransmissions;
+        self
+    }
+
+    // For both announces and scrapes, with every retransmission and the
+    // connect before them. Raise it to wait out more retransmissions.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.announce_timeout = timeout;
+        self.scrape_timeout = timeout;
+        self
+    }
+
+    pub fn announce_timeout(mut self, announce_timeout: Duration) -> Self {
+        self.announce_timeout = announce_timeout;
+        self
+    }
+
+    pub fn scrape_timeout(mut self, scrape_timeout: Duration) -> Self {
+        self.scrape_timeout = scrape_timeout;
+        self
+    }
+
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    // Requests fail while a proxy is set, rather than going around it.
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    // Sent when the announce parameters have no key of their own. A fresh
+    // random one is used if not set.
+    pub fn announce_key(mut self, announce_key: AnnounceKey) -> Self {
+        self.announce_key = Some(announce_key);
+        self
+    }
+
+    // Told about the announces of announcers using this client.
+    pub fn observer(mut self, observer: impl AnnounceObserver + 'static) -> Self {
+        self.observer = Some(Arc::new(observer));
+        self
+    }
+
+    #[cfg(test)]
+    pub(crate) fn network(mut self, network: Arc<dyn Network>) -> Self {
+        self.network = network;
+        self
+    }
+
+    #[cfg(test)]
+    pub(crate) fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn build(self) -> UdpTrackerClient {
+        UdpTrackerClient {
+            retry_policy: self.retry_policy,
+            max_retransmissions: self.max_retransmissions,
+            announce_timeout: self.announce_timeout,
+            scrape_timeout: self.scrape_timeout,
+            proxy: self.proxy,
+            network: self.network,
+            clock: self.clock,
+            key: self.announce_key.unwrap_or_else(AnnounceKey::in_memory),
+            observer: self.observer,
+            connections: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+}
+
+// Clones share the cache of connection ids.
+#[derive(Clone)]
+pub struct UdpTrackerClient {
+    retry_policy: RetryPolicy,
+    max_retransmissions: u32,
+    announce_timeout: Duration,
+    scrape_timeout: Duration,
+    proxy: Option<Proxy>,
+    network: Arc<dyn Network>,
+    clock: Arc<dyn Clock>,
+    // Used when the announce parameters have no key of their own.
+    key: AnnounceKey,
+    observer: Option<Arc<dyn AnnounceObserver>>,
+    connections: Arc<Mutex<HashMap<SocketAddr, (i64, Instant)>>>,
+}
+
+impl Default for UdpTrackerClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UdpTrackerClient {
+    pub fn builder() -> UdpTrackerClientBuilder {
+        UdpTrackerClientBuilder {
+            retry_policy: RetryPolicy::default(),
+            max_retransmissions: DEFAULT_MAX_RETRANSMISSIONS,
+            announce_timeout: DEFAULT_TIMEOUT,
+            scrape_timeout: DEFAULT_TIMEOUT,
+            proxy: None,
+            announce_key: None,
+            observer: None,
+            network: Arc::new(TokioNetwork),
+            clock: Arc::new(TokioClock),
+        }
+    }
+
+    pub fn new() -> Self {
+        Self::builder().build()
+    }
+
+    pub fn retry_policy(&self) -> &RetryPolicy {
+        &self.retry_policy
+    }
+
+    pub fn observer(&self) -> Option<Arc<dyn AnnounceObserver>> {
+        self.observer.clone()
+    }
+
+    pub(crate) fn key_for(&self, url: &str) -> u32 {
+        self.key.value(url)
+    }
+
+    pub fn with_proxy(&self, proxy: Option<Proxy>) -> Self {
+        Self {
+            proxy,
+            ..self.clone()
+        }
+    }
+
+    pub fn proxy(&self) -> Option<&Proxy> {
+        self.proxy.as_ref()
+    }
+
+    // No proxy type we support can carry UDP.
+    fn check_proxy(&self) -> Result<(), ClientError> {
+        match &self.proxy {
+            Some(proxy) => Err(ClientError::UdpOverProxy(proxy.kind())),
+            None => Ok(()),
+        }
+    }
+
+    pub async fn announce(
+        &self,
+        url: &str,
+        params: AnnounceParams,
+    ) -> Result<AnnounceResponse, ClientError> {
+        deadline(self.announce_timeout, self.announce_unbounded(url, params)).await
+    }
+
+    async fn announce_unbounded(
+        &self,
+        url: &str,
+        params: AnnounceParams,
+    ) -> Result<AnnounceResponse, ClientError> {
+        self.check_proxy()?;
+        let addr = resolve(url).await?;
+        let socket = self.network.connect(addr).await?;
+
+        let key = params
+            .key
+            .as_deref()
+            .and_then(|k| u32::from_str_radix(k, 16).ok())
+            .unwrap_or_else(|| self.key_for(url));
+
+        let mut body = Vec::with_capacity(82);
+        body.extend_from_slice(&params.info_hash.0);
+        body.extend_from_slice(&params.peer_id.0);
+        body.extend_from_slice(&(params.downloaded as i64).to_be_bytes());
+        body.extend_from_slice(&(params.left as i64).to_be_bytes());
+        body.extend_from_slice(&(params.uploaded as i64).to_be_bytes());
+        body.extend_from_slice(&event(&params.event).to_be_bytes());
+        // Zero for the address the packet came from. There is no room for
+        // an IPv6 one.
+        let ip = match params.ip {
+            Some(IpAddr::V4(ip)) => ip.octets(),
+            _ => [0; 4],
+        };
+        body.extend_from_slice(&ip);
+        body.extend_from_slice(&key.to_be_bytes());
+        let num_want = params
+            .num_want
+            .map_or(-1, |n| n.min(i32::MAX as u32) as i32);
+        body.extend_from_slice(&num_want.to_be_bytes());
+        body.extend_from_slice(&params.port.to_be_bytes());
+
+        let reply = self.request(&*socket, addr, ANNOUNCE, &body).await?;
+        parse_announce(&reply, addr)
+    }
+
+    pub async fn scrape(
+        &self,
+        url: &str,
+        info_hashes: &[InfoHash],
+    ) -> Result<ScrapeResponse, ClientError> {
+        deadline(self.scrape_timeout, self.scrape_unbounded(url, info_hashes)).await
+    }
+
+    async fn scrape_unbounded(
+        &self,
+        url: &str,
+        info_hashes: &[InfoHash],
+    ) -> Result<ScrapeResponse, ClientError> {
+        self.check_proxy()?;
+        let addr = resolve(url).await?;
+        let socket = self.network.connect(addr).await?;
+
+        let mut files = HashMap::new();
+        for chunk in info_hashes.chunks(MAX_SCRAPE_HASHES) {
+            let body: Vec<u8> = chunk.iter().flat_map(|ih| ih.0.iter().copied()).collect();
+            let reply = self.request(&*socket, addr, SCRAPE, &body).await?;
+
+            if reply.len() < chunk.len() * 12 {
+                return Err(ClientError::Malformed(format!(
+                    "scrape reply has {} bytes for {} info hashes",
+                    reply.len(),
+                    chunk.len()
+                )));
+            }
+
+            for (info_hash, entry) in chunk.iter().zip(reply.chunks_exact(12)) {
+                files.insert(
+                    info_hash.clone(),
+                    ScrapeFile {
+                        complete: read_i32(entry, 0).max(0) as u32,
+                        downloaded: read_i32(entry, 4).max(0) as u32,
+                        incomplete: read_i32(entry, 8).max(0) as u32,
+                        name: None,
+                    },
+                );
+            }
+        }
+
+        Ok(ScrapeResponse {
+            files,
+            min_request_interval: None,
+        })
+    }
+
+    async fn connection_id(
+        &self,
+        socket: &dyn Socket,
+        addr: SocketAddr,
+    ) -> Result<i64, ClientError> {
+        let now = self.clock.now();
+        if let Some((connection_id, issued)) = self.connections.lock().unwrap().get(&addr) {
+            if now.duration_since(*issued) < CONNECTION_ID_LIFETIME {
+                return Ok(*connection_id);
+            }
+        }
+
+        let transaction_id = rand::random();
+
+        let mut packet = Vec::with_capacity(16);
+        packet.extend_from_slice(&PROTOCOL_ID.to_be_bytes());
+        packet.extend_from_slice(&CONNECT.to_be_bytes());
+        packet.extend_from_slice(&i32::to_be_bytes(transaction_id));
+
+        for n in 0..=self.max_retransmissions {
+            socket.send(&packet).await?;
+
+            if let Some(reply) = self.wait(socket, transaction_id, CONNECT, n).await? {
+                if reply.len() < 8 {
+                    return Err(ClientError::Malformed(
+                        "connect reply is missing the connection id".to_string(),
+                    ));
+                }
+
+                let connection_id = i64::from_be_bytes(reply[..8].try_into().unwrap());
+                self.connections
+                    .lock()
+                    .unwrap()
+                    .insert(addr, (connection_id, self.clock.now()));
+
+                return Ok(connection_id);
+            }
+        }
+
+        Err(ClientError::Timeout)
+    }
+
+    // Sends `body` under a connection id until the tracker replies, and
+    // returns the reply without its header.
+    async fn request(
+        &self,
+        socket: &dyn Socket,
+        addr: SocketAddr,
+        action: i32,
+        body: &[u8],
+    ) -> Result<Vec<u8>, ClientError> {
+        let transaction_id = rand::random();
+
+        for n in 0..=self.max_retransmissions {
+            // Asked again on every transmission, the id may expire while we wait.
+            let connection_id = self.connection_id(socket, addr).await?;
+
+            let mut packet = Vec::with_capacity(16 + body.len());
+            packet.extend_from_slice(&connection_id.to_be_bytes());
+            packet.extend_from_slice(&action.to_be_bytes());
+            packet.extend_from_slice(&i32::to_be_bytes(transaction_id));
+            packet.extend_from_slice(body);
+
+            socket.send(&packet).await?;
+
+            if let Some(reply) = self.wait(socket, transaction_id, action, n).await? {
+                return Ok(reply);
+            }
+        }
+
+        Err(ClientError::Timeout)
+    }
+
+    // Waits out the timeout of the nth transmission for a reply to it.
+    async fn wait(
+        &self,
+        socket: &dyn Socket,
+        transaction_id: i32,
+        action: i32,
+        n: u32,
+    ) -> Result<Option<Vec<u8>>, ClientError> {
+        let timeout = BASE_TIMEOUT.saturating_mul(2u32.saturating_pow(n));
+        let mut sleep = self.clock.sleep(timeout);
+        let mut buf = vec![0; MAX_PACKET_SIZE];
+
+        loop {
+            tokio::select! {
+                _ = &mut sleep => return Ok(None),
+                len = socket.recv(&mut buf) => {
+                    let reply = &buf[..len?];
+
+                    // Anything else is a late reply to an earlier request, or not
+                    // from the tracker at all.
+                    if reply.len() < 8 || read_i32(reply, 4) != transaction_id {
+                        continue;
+                    }
+
+                    return match read_i32(reply, 0) {
+                        a if a == action => Ok(Some(reply[8..].to_vec())),
+                        ERROR => Err(ClientError::Failure {
+                            reason: String::from_utf8_lossy(&reply[8..]).into_owned(),
+                            retry_in: None,
+                        }),
+                        a => Err(ClientError::Malformed(format!(
+                            "expected action {action}, got {a}"
+                        ))),
+                    };
+                }
+            }
+        }
+    }
+}
+
+async fn resolve(url: &str) -> Result<SocketAddr, ClientError> {
+    let invalid = |reason: &str| ClientError::InvalidUrl(format!("{url}: {reason}"));
+
+    let parsed = Url::parse(url).map_err(|e| invalid(&e.to_string()))?;
+    if parsed.scheme() != "udp" {
+        return Err(invalid("not a udp url"));
+    }
+
+    let port = parsed.port().ok_or_else(|| invalid("missing port"))?;
+    let ip = match parsed.host() {
+        Some(Host::Ipv4(ip)) => IpAddr::V4(ip),
+        Some(Host::Ipv6(ip)) => IpAddr::V6(ip),
+        Some(Host::Domain(host)) => {
+            return tokio::net::lookup_host((host, port))
+                .await?
+                .next()
+                .ok_or_else(|| invalid("host did not resolve"));
+        }
+        None => return Err(invalid("missing host")),
+    };
+
+    Ok(SocketAddr::new(ip, port))
+}
+
+fn event(event: &Event) -> i32 {
+    match event {
+        Event::Interval => 0,
+        Event::Completed => 1,
+        Event::Started => 2,
+        Event::Stopped => 3,
+    }
+}
+
+fn read_i32(bs: &[u8], at: usize) -> i32 {
+    i32::from_be_bytes(bs[at..at + 4].try_into().unwrap())
+}
+
+// Peers are of the same address family as the tracker, so they are put in
+// `peers` or `peers6` to match.
+fn parse_announce(reply: &[u8], tracker: SocketAddr) -> Result<AnnounceResponse, ClientError> {
+    if reply.len() < 12 {
+        return Err(ClientError::Malformed(format!(
+            "announce reply is {} bytes, at least 12 expected",
+            reply.len()
+        )));
+    }
+
+    let peers = PeerList::Compact(reply[12..].to_vec());
+    let (peers, peers6) = match tracker {
+        SocketAddr::V4(_) => (peers, None),
+        SocketAddr::V6(_) => (PeerList::default(), Some(peers)),
+    };
+
+    Ok(AnnounceResponse {
+        interval: read_i32(reply, 0).max(0) as u32,
+        min_interval: None,
+        complete: Some(read_i32(reply, 8).max(0) as u32),
+        incomplete: Some(read_i32(reply, 4).max(0) as u32),
+        warning_message: None,
+        tracker_id: None,
+        peers,
+        peers6,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::{HttpTrackerClient, MultiTrackerAnnouncer, TrackerClient};
+    use hanekawa_common::types::PeerId;
+    use std::{collections::VecDeque, future::Future, pin::Pin};
+    use tokio::sync::{mpsc, oneshot};
+
+    fn params() -> AnnounceParams {
+        AnnounceParams {
+            info_hash: InfoHash(vec![0xaa; 20]),
+            peer_id: PeerId(b"-HK0100-123456789012".to_vec()),
+            port: 6881,
+            uploaded: 0,
+            downloaded: 0,
+            left: 100,
+            event: Event::Started,
+            num_want: None,
+            compact: true,
+            key: None,
+            ip: None,
+        }
+    }
+
+    mod tracker {
+        use super::*;
+
+        use bytes::BytesMut;
+        use hanekawa::udp_tracker::{
+            proto::{Request, Response},
+            UdpTrackerService,
+        };
+        use hanekawa_common::{
+            audit::AuditLog,
+            ban::BanList,
+            maintenance::Maintenance,
+            offense::Offenders,
+            passkey::Passkeys,
+            peer_limit::PeerLimits,
+            repository::{
+                memory::MemoryStore,
+                peer::{PeerRepository, UpdatePeerAnnounce},
+            },
+            task::{Task, TaskQueue},
+            torrent_policy::TorrentPolicy,
+            types::{Peer, PeerSource},
+            Config, InvalidRequestConfig, Services,
+        };
+
+        fn peer(id: u8, endpoint: &str) -> Peer {
+            let endpoint: SocketAddr = endpoint.parse().unwrap();
+            Peer {
+                peer_id: PeerId(vec![id; 20]),
+                ip: endpoint.ip(),
+                port: endpoint.port(),
+                connectable: None,
+                seeding: false,
+                source: PeerSource::Announce,
+            }
+        }
+
+        // Two leechers, one of each address family, on the announced torrent,
+        // and three seeders that completed and a leecher on the 42nd scraped.
+        async fn store() -> Arc<MemoryStore> {
+            let store = Arc::new(MemoryStore::new());
+            let now = hanekawa_common::system_clock()();
+            for peer in [peer(1, "10.0.0.1:6881"), peer(2, "[2001:db8::1]:51413")] {
+                store.seed(&params().info_hash, peer, 100, now);
+            }
+
+            let info_hash = InfoHash(vec![42; 20]);
+            for id in 3..6 {
+                let cmd = UpdatePeerAnnounce {
+                    info_hash: info_hash.clone(),
+                    peer_id: PeerId(vec![id; 20]),
+                    ip: "10.0.0.2".parse().unwrap(),
+                    port: 6881 + u16::from(id),
+                    uploaded: 0,
+                    downloaded: 0,
+                    left: 0,
+                    event: Event::Completed,
+                    update_timestamp: now,
+                    other_endpoint: None,
+                    transport: None,
+                    user_id: None,
+                    key: None,
+                };
+                store.update_peer_announce(&cmd).await.unwrap();
+            }
+            store.seed(&info_hash, peer(6, "10.0.0.3:6881"), 100, now);
+
+            store
+        }
+
+        struct DiscardingQueue;
+
+        #[async_trait::async_trait]
+        impl TaskQueue for DiscardingQueue {
+            async fn enqueue(&self, _task: &dyn Task) -> Option<()> {
+                Some(())
+            }
+        }
+
+        fn config() -> Config {
+            Config {
+                database_pool_size: 1,
+                bind_ip: Ipv4Addr::LOCALHOST,
+                http_bind_port: 0,
+                udp_bind_port: 0,
+                interval_jitter_percent: 0,
+                peer_activity_timeout: Some(3600),
+                ..Config::default()
+            }
+        }
+
+        pub(super) async fn spawn(bind: &str) -> String {
+            let socket = UdpSocket::bind(bind).await.unwrap();
+            let addr = socket.local_addr().unwrap();
+
+            let store = store().await;
+            let services = Services {
+                peer_repository: store.clone(),
+                info_hash_repository: store,
+                task_queue: Arc::new(DiscardingQueue),
+                bans: BanList::in_memory(),
+                audit: AuditLog::in_memory(),
+                prober: None,
+                federation: None,
+                offenders: Offenders::new(&InvalidRequestConfig::default()),
+                maintenance: Maintenance::default(),
+                passkeys: Passkeys::in_memory(),
+                torrent_policy: TorrentPolicy::open(),
+                peer_limits: PeerLimits::unlimited(),
+                clock: hanekawa_common::system_clock(),
+            };
+            let tracker = UdpTrackerService::new(&config(), services);
+
+            tokio::spawn(async move {
+                let mut buf = [0; 1500];
+                loop {
+                    let (len, from) = socket.recv_from(&mut buf).await.unwrap();
+                    let response = match hanekawa_udp::parse_request(&buf[..len]).unwrap() {
+                        Request::Connect(r) => Response::Connect(tracker.connect(r, from)),
+                        Request::Announce(r) => {
+                            Response::Announce(tracker.announce(r, from).await.unwrap())
+                        }
+                        Request::Scrape(r) => {
+                            Response::Scrape(tracker.scrape(r, from).await.unwrap())
+                        }
+                    };
+
+                    let mut reply = BytesMut::new();
+                    hanekawa_udp::encode_response(&response, &mut reply);
+                    socket.send_to(&reply, from).await.unwrap();
+                }
+            });
+
+            format!("udp://{addr}/announce")
+        }
+    }
+
+    #[tokio::test]
+    async fn announces_to_our_tracker() {
+        let client = UdpTrackerClient::new();
+
+        for (bind, peer) in [
+            ("127.0.0.1:0", "10.0.0.1:6881"),
+            ("[::1]:0", "[2001:db8::1]:51413"),
+        ] {
+            let url = tracker::spawn(bind).await;
+            let response = client.announce(&url, params()).await.unwrap();
+
+            assert_eq!(1800, response.interval);
+            assert_eq!(Some(2), response.incomplete);
+            assert_eq!(
+                vec![peer.parse::<SocketAddr>().unwrap()],
+                response
+                    .peers()
+                    .unwrap()
+                    .into_iter()
+                    .map(|p| p.addr)
+                    .collect::<Vec<_>>()
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn scrapes_our_tracker() {
+        let url = tracker::spawn("127.0.0.1:0").await;
+
+        let info_hashes: Vec<_> = (0..100).map(|i| InfoHash(vec![i; 20])).collect();
+        let response = UdpTrackerClient::new()
+            .scrape(&url, &info_hashes)
+            .await
+            .unwrap();
+
+        assert_eq!(100, response.files.len());
+        assert_eq!(
+            ScrapeFile {
+                complete: 3,
+                downloaded: 3,
+                incomplete: 1,
+                name: None,
+            },
+            response.files[&InfoHash(vec![42; 20])]
+        );
+    }
+
+    #[tokio::test]
+    async fn plugs_into_the_announcer() {
+        let url = tracker::spawn("127.0.0.1:0").await;
+        let client = TrackerClient::new(HttpTrackerClient::new().unwrap(), UdpTrackerClient::new());
+
+        let handle = MultiTrackerAnnouncer::spawn(client, vec![vec![url]], params());
+        let mut peers = handle.peers();
+        peers.changed().await.unwrap();
+
+        assert_eq!(
+            "10.0.0.1:6881".parse::<SocketAddr>().unwrap(),
+            peers.borrow()[0].addr
+        );
+        handle.shutdown().await.unwrap();
+    }
+
+    #[derive(Debug, Clone, Copy)]
+    enum Reply {
+        Drop,
+        Answer,
+        // An answer with another transaction id.
+        Stale,
+        Error,
+    }
+
+    // Replies are taken from the script in order, and are answers once it
+    // runs out.
+    struct MockTracker {
+        script: Mutex<VecDeque<Reply>>,
+        actions: Mutex<Vec<i32>>,
+    }
+
+    impl MockTracker {
+        fn new(script: Vec<Reply>) -> Arc<Self> {
+            Arc::new(Self {
+                script: Mutex::new(script.into()),
+                actions: Mutex::new(vec![]),
+            })
+        }
+
+        fn actions(&self) -> Vec<i32> {
+            self.actions.lock().unwrap().clone()
+        }
+
+        fn reply(&self, packet: &[u8]) -> Option<Vec<u8>> {
+            let action = read_i32(packet, 8);
+            let transaction_id = read_i32(packet, 12);
+            self.actions.lock().unwrap().push(action);
+
+            let reply = self
+                .script
+                .lock()
+                .unwrap()
+                .pop_front()
+                .unwrap_or(Reply::Answer);
+
+            let transaction_id = match reply {
+                Reply::Drop => return None,
+                Reply::Stale => transaction_id.wrapping_add(1),
+                _ => transaction_id,
+            };
+
+            let mut out = vec![];
+            let mut put = |v: i32| out.extend_from_slice(&v.to_be_bytes());
+            if let Reply::Error = reply {
+                put(ERROR);
+                put(transaction_id);
+                out.extend_from_slice(b"not today");
+                return Some(out);
+            }
+
+            put(action);
+            put(transaction_id);
+            match action {
+                CONNECT => out.extend_from_slice(&0x1234_i64.to_be_bytes()),
+                ANNOUNCE => {
+                    for v in [1800, 1, 2] {
+                        out.extend_from_slice(&i32::to_be_bytes(v));
+                    }
+                    out.extend_from_slice(&[10, 0, 0, 1, 0x1a, 0xe1]);
+                }
+                _ => {
+                    for _ in 0..(packet.len() - 16) / 20 {
+                        for v in [2, 5, 1] {
+                            out.extend_from_slice(&i32::to_be_bytes(v));
+                        }
+                    }
+                }
+            }
+
+            Some(out)
+        }
+    }
+
+    struct MockSocket {
+        tracker: Arc<MockTracker>,
+        replies_tx: mpsc::UnboundedSender<Vec<u8>>,
+        replies: tokio::sync::Mutex<mpsc::UnboundedReceiver<Vec<u8>>>,
+    }
+
+    #[async_trait::async_trait]
+    impl Socket for MockSocket {
+        async fn send(&self, packet: &[u8]) -> io::Result<()> {
+            if let Some(reply) = self.tracker.reply(packet) {
+                let _ = self.replies_tx.send(reply);
+            }
+            Ok(())
+        }
+
+        async fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+            let reply = self.replies.lock().await.recv().await.unwrap();
+            buf[..reply.len()].copy_from_slice(&reply);
+            Ok(reply.len())
+        }
+    }
+
+    struct MockNetwork(Arc<MockTracker>);
+
+    #[async_trait::async_trait]
+    impl Network for MockNetwork {
+        async fn connect(&self, _addr: SocketAddr) -> io::Result<Box<dyn Socket>> {
+            let (replies_tx, replies) = mpsc::unbounded_channel();
+
+            Ok(Box::new(MockSocket {
+                tracker: self.0.clone(),
+                replies_tx,
+                replies: tokio::sync::Mutex::new(replies),
+            }))
+        }
+    }
+
+    // Hands every sleep to the test, which decides when it is over.
+    struct MockClock {
+        sleeps: mpsc::UnboundedSender<(Duration, oneshot::Sender<()>)>,
+        now: Mutex<Instant>,
+    }
+
+    impl MockClock {
+        fn advance(&self, by: Duration) {
+            *self.now.lock().unwrap() += by;
+        }
+    }
+
+    impl Clock for MockClock {
+        fn sleep(&self, duration: Duration) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+            let (wake, woken) = oneshot::channel();
+            let _ = self.sleeps.send((duration, wake));
+
+            Box::pin(async move {
+                let _ = woken.await;
+            })
+        }
+
+        fn now(&self) -> Instant {
+            *self.now.lock().unwrap()
+        }
+    }
+
+    const URL: &str = "udp://192.0.2.1:1337/announce";
+
+    type Sleeps = mpsc::UnboundedReceiver<(Duration, oneshot::Sender<()>)>;
+
+    fn mock(
+        script: Vec<Reply>,
+        max_retransmissions: u32,
+    ) -> (UdpTrackerClient, Arc<MockTracker>, Arc<MockClock>, Sleeps) {
+        let tracker = MockTracker::new(script);
+        let (sleeps, sleeps_rx) = mpsc::unbounded_channel();
+        let clock = Arc::new(MockClock {
+            sleeps,
+            now: Mutex::new(Instant::now()),
+        });
+
+        let client = UdpTrackerClient::builder()
+            .max_retransmissions(max_retransmissions)
+            .network(Arc::new(MockNetwork(tracker.clone())))
+            .clock(clock.clone())
+            .build();
+
+        (client, tracker, clock, sleeps_rx)
+    }
+
+    #[tokio::test]
+    async fn retransmits_on_the_bep_15_schedule() {
+        use Reply::*;
+
+        let (client, tracker, _, mut sleeps) = mock(vec![Drop, Drop, Answer, Drop, Answer], 8);
+        let announce = tokio::spawn(async move { client.announce(URL, params()).await });
+
+        // The timeout starts over for the announce once connected.
+        let mut pending = vec![];
+        for (secs, answered) in [
+            (15, false),
+            (30, false),
+            (60, true),
+            (15, false),
+            (30, true),
+        ] {
+            let (delay, wake) = sleeps.recv().await.unwrap();
+            assert_eq!(Duration::from_secs(secs), delay);
+            if answered {
+                pending.push(wake);
+            } else {
+                let _ = wake.send(());
+            }
+        }
+
+        let response = announce.await.unwrap().unwrap();
+        assert_eq!(Some(2), response.complete);
+        assert_eq!(vec![0, 0, 0, 1, 1], tracker.actions());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn bounds_the_whole_exchange() {
+        let tracker = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let url = format!("udp://{}", tracker.local_addr().unwrap());
+        let client = UdpTrackerClient::builder()
+            .scrape_timeout(Duration::from_secs(5))
+            .build();
+
+        // Well short of the retransmission schedule's limit.
+        let started = tokio::time::Instant::now();
+        let announce = client.announce(&url, params()).await;
+        assert!(matches!(announce, Err(ClientError::Timeout)));
+        assert_eq!(Duration::from_secs(15), started.elapsed());
+
+        let started = tokio::time::Instant::now();
+        let scrape = client.scrape(&url, &[InfoHash(vec![0xaa; 20])]).await;
+        assert!(matches!(scrape, Err(ClientError::Timeout)));
+        assert_eq!(Duration::from_secs(5), started.elapsed());
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_the_last_retransmission() {
+        use Reply::*;
+
+        let (client, tracker, _, mut sleeps) = mock(vec![Drop, Drop, Drop], 2);
+        let announce = tokio::spawn(async move { client.announce(URL, params()).await });
+
+        for secs in [15, 30, 60] {
+            let (delay, wake) = sleeps.recv().await.unwrap();
+            assert_eq!(Duration::from_secs(secs), delay);
+            let _ = wake.send(());
+        }
+
+        assert!(matches!(announce.await.unwrap(), Err(ClientError::Timeout)));
+        assert_eq!(vec![0, 0, 0], tracker.actions());
+    }
+
+    #[tokio::test]
+    async fn reuses_connection_ids_until_they_expire() {
+        let (client, tracker, clock, _sleeps) = mock(vec![], 8);
+
+        client.announce(URL, params()).await.unwrap();
+        clock.advance(Duration::from_secs(59));
+        client.scrape(URL, &[InfoHash(vec![1; 20])]).await.unwrap();
+        clock.advance(Duration::from_secs(2));
+        client.announce(URL, params()).await.unwrap();
+
+        assert_eq!(vec![0, 1, 2, 0, 1], tracker.actions());
+    }
+
+    #[tokio::test]
+    async fn ignores_replies_to_other_transactions() {
+        use Reply::*;
+
+        let (client, tracker, _, mut sleeps) = mock(vec![Answer, Stale], 8);
+        let announce = tokio::spawn(async move { client.announce(URL, params()).await });
+
+        let (_, _connected) = sleeps.recv().await.unwrap();
+        let (delay, wake) = sleeps.recv().await.unwrap();
+        assert_eq!(Duration::from_secs(15), delay);
+        let _ = wake.send(());
+
+        announce.await.unwrap().unwrap();
+        assert_eq!(vec![0, 1, 1], tracker.actions());
+    }
+
+    #[tokio::test]
+    async fn surfaces_error_packets() {
+        use Reply::*;
+
+        let (client, _, _, _sleeps) = mock(vec![Answer, Error], 8);
+
+        match client.announce(URL, params()).await {
+            Err(ClientError::Failure { reason, .. }) => assert_eq!("not today", reason),
+            r => panic!("expected a failure, got {:?}", r.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn rejects_non_udp_urls() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+
+        for url in [
+            "http://tracker.example/announce",
+            "udp://tracker.example/announce",
+        ] {
+            assert!(matches!(
+                rt.block_on(resolve(url)),
+                Err(ClientError::InvalidUrl(_))
+            ));
+        }
+    }
+}
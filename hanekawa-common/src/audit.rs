@@ -0,0 +1,95 @@
+use crate::{
+    repository::{
+        audit::{AppendAudit, AuditRepository, GetAudit, PurgeAudit},
+        Error,
+    },
+    types::AuditRecord,
+};
+
+use std::sync::{Arc, Mutex};
+use time::OffsetDateTime;
+
+// Where the admin API records what it changed, and who asked.
+#[derive(Clone)]
+pub struct AuditLog(Arc<dyn AuditRepository>);
+
+impl AuditLog {
+    pub fn new(repository: Arc<dyn AuditRepository>) -> Self {
+        Self(repository)
+    }
+
+    // Records are forgotten when the process exits.
+    pub fn in_memory() -> Self {
+        Self(Arc::new(MemoryAudit::default()))
+    }
+
+    pub async fn record(
+        &self,
+        token_id: Option<&str>,
+        action: &str,
+        target: &str,
+        outcome: &str,
+    ) -> Result<(), Error> {
+        self.0
+            .append_audit(AppendAudit {
+                timestamp: OffsetDateTime::now_utc(),
+                token_id,
+                action,
+                target,
+                outcome,
+            })
+            .await
+    }
+
+    // Newest first.
+    pub async fn records(&self, cmd: GetAudit) -> Result<Vec<AuditRecord>, Error> {
+        self.0.get_audit(cmd).await
+    }
+
+    // Returns how many records were older than `before`.
+    pub async fn purge(&self, before: OffsetDateTime) -> Result<u64, Error> {
+        self.0.purge_audit(PurgeAudit { before }).await
+    }
+}
+
+#[derive(Default)]
+struct MemoryAudit(Mutex<Vec<AuditRecord>>);
+
+#[async_trait::async_trait]
+impl AuditRepository for MemoryAudit {
+    async fn append_audit(&self, cmd: AppendAudit<'_>) -> Result<(), Error> {
+        let mut records = self.0.lock().unwrap();
+        let id = records.len() as u64 + 1;
+
+        records.push(AuditRecord {
+            id,
+            timestamp: cmd.timestamp,
+            token_id: cmd.token_id.map(String::from),
+            action: cmd.action.to_string(),
+            target: cmd.target.to_string(),
+            outcome: cmd.outcome.to_string(),
+        });
+
+        Ok(())
+    }
+
+    async fn get_audit(&self, cmd: GetAudit) -> Result<Vec<AuditRecord>, Error> {
+        let records = self.0.lock().unwrap();
+
+        Ok(records
+            .iter()
+            .rev()
+            .filter(|r| cmd.before.is_none_or(|before| r.id < before))
+            .take(cmd.limit)
+            .cloned()
+            .collect())
+    }
+
+    async fn purge_audit(&self, cmd: PurgeAudit) -> Result<u64, Error> {
+        let mut records = self.0.lock().unwrap();
+        let before = records.len();
+        records.retain(|r| r.timestamp >= cmd.before);
+
+        Ok((before - records.len()) as u64)
+    }
+}
@@ -0,0 +1,293 @@
+use crate::{
+    repository::{
+        ban::{AddBan, BanRepository, GetBans, RemoveBan},
+        Error,
+    },
+    types::{Ban, BanTarget, Cidr, PeerId},
+};
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, RwLock,
+    },
+};
+use time::{Duration, OffsetDateTime};
+
+type Clock = Arc<dyn Fn() -> OffsetDateTime + Send + Sync>;
+
+// What announces and scrapes are checked against. Lookups only take a read
+// lock and never touch storage; changes are written through to the repository
+// first, if there is one, and are only seen by this process until the next
+// `load`.
+#[derive(Clone)]
+pub struct BanList {
+    repository: Option<Arc<dyn BanRepository>>,
+    clock: Clock,
+    index: Arc<RwLock<Index>>,
+    // Ids of bans only kept in memory.
+    next_id: Arc<AtomicU64>,
+}
+
+// Who is asking, as far as the tracker can tell.
+#[derive(Debug, Clone, Copy)]
+pub struct BanCheck<'a> {
+    pub ip: IpAddr,
+    pub peer_id: Option<&'a PeerId>,
+    pub passkey: Option<&'a str>,
+}
+
+#[derive(Default)]
+struct Index {
+    bans: BTreeMap<u64, Ban>,
+    // Ban ids by target. An address block has to be compared with every
+    // address, but there are few of them.
+    ips: HashMap<IpAddr, Vec<u64>>,
+    cidrs: Vec<(Cidr, u64)>,
+    peer_ids: HashMap<PeerId, Vec<u64>>,
+    passkeys: HashMap<String, Vec<u64>>,
+}
+
+impl Index {
+    fn new(bans: impl IntoIterator<Item = Ban>) -> Self {
+        let mut index = Self::default();
+
+        for ban in bans {
+            let id = ban.id;
+            match &ban.target {
+                BanTarget::Ip(ip) => index.ips.entry(*ip).or_default().push(id),
+                BanTarget::Cidr(cidr) => index.cidrs.push((*cidr, id)),
+                BanTarget::PeerId(peer_id) => {
+                    index.peer_ids.entry(peer_id.clone()).or_default().push(id)
+                }
+                BanTarget::Passkey(passkey) => {
+                    index.passkeys.entry(passkey.clone()).or_default().push(id)
+                }
+            }
+            index.bans.insert(id, ban);
+        }
+
+        index
+    }
+
+    // Rebuilt on every change, which is rare, without the bans that expired
+    // in the meantime.
+    fn rebuild(&mut self, now: OffsetDateTime, change: impl FnOnce(&mut BTreeMap<u64, Ban>)) {
+        let mut bans = std::mem::take(&mut self.bans);
+        change(&mut bans);
+        *self = Self::new(bans.into_values().filter(|ban| ban.is_active(now)));
+    }
+}
+
+impl BanList {
+    // Bans are forgotten when the process exits.
+    pub fn in_memory() -> Self {
+        Self {
+            repository: None,
+            clock: Arc::new(OffsetDateTime::now_utc),
+            index: Default::default(),
+            next_id: Arc::new(AtomicU64::new(1)),
+        }
+    }
+
+    pub async fn load(repository: Arc<dyn BanRepository>) -> Result<Self, Error> {
+        let bans = repository
+            .get_bans(GetBans {
+                active_at: OffsetDateTime::now_utc(),
+            })
+            .await?;
+
+        Ok(Self {
+            repository: Some(repository),
+            clock: Arc::new(OffsetDateTime::now_utc),
+            index: Arc::new(RwLock::new(Index::new(bans))),
+            next_id: Arc::new(AtomicU64::new(1)),
+        })
+    }
+
+    // For tests, to expire bans without waiting.
+    pub fn with_clock(
+        mut self,
+        clock: impl Fn() -> OffsetDateTime + Send + Sync + 'static,
+    ) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    // Banned for good, if `expires_in` is not set.
+    pub async fn add(
+        &self,
+        target: BanTarget,
+        reason: String,
+        expires_in: Option<Duration>,
+    ) -> Result<Ban, Error> {
+        let created = (self.clock)();
+        let expires = expires_in.map(|d| created + d);
+
+        let id = match &self.repository {
+            Some(repository) => {
+                repository
+                    .add_ban(AddBan {
+                        target: &target,
+                        reason: &reason,
+                        created,
+                        expires,
+                    })
+                    .await?
+            }
+            None => self.next_id.fetch_add(1, Ordering::Relaxed),
+        };
+
+        let ban = Ban {
+            id,
+            target,
+            reason,
+            created,
+            expires,
+        };
+        self.write(created, |bans| {
+            bans.insert(id, ban.clone());
+        });
+
+        Ok(ban)
+    }
+
+    // Whether there was an active ban with that id.
+    pub async fn remove(&self, id: u64) -> Result<bool, Error> {
+        if let Some(repository) = &self.repository {
+            repository.remove_ban(RemoveBan { id }).await?;
+        }
+
+        let now = (self.clock)();
+        let mut removed = None;
+        self.write(now, |bans| removed = bans.remove(&id));
+
+        Ok(removed.is_some_and(|ban| ban.is_active(now)))
+    }
+
+    // Oldest first.
+    pub fn active(&self) -> Vec<Ban> {
+        let now = (self.clock)();
+
+        self.read()
+            .bans
+            .values()
+            .filter(|ban| ban.is_active(now))
+            .cloned()
+            .collect()
+    }
+
+    pub fn find(&self, check: BanCheck<'_>) -> Option<Ban> {
+        let now = (self.clock)();
+        let index = self.read();
+
+        let ids = index.ips.get(&check.ip).into_iter().flatten();
+        let ids = ids.chain(
+            index
+                .cidrs
+                .iter()
+                .filter(|(cidr, _)| cidr.contains(check.ip))
+                .map(|(_, id)| id),
+        );
+        let ids = ids.chain(
+            check
+                .peer_id
+                .and_then(|peer_id| index.peer_ids.get(peer_id))
+                .into_iter()
+                .flatten(),
+        );
+        let mut ids = ids.chain(
+            check
+                .passkey
+                .and_then(|passkey| index.passkeys.get(passkey))
+                .into_iter()
+                .flatten(),
+        );
+
+        ids.find_map(|id| index.bans.get(id).filter(|ban| ban.is_active(now)))
+            .cloned()
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, Index> {
+        self.index.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write(&self, now: OffsetDateTime, change: impl FnOnce(&mut BTreeMap<u64, Ban>)) {
+        let mut index = self.index.write().unwrap_or_else(|e| e.into_inner());
+        index.rebuild(now, change);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use futures::executor::block_on;
+    use std::sync::Mutex;
+
+    fn check(bans: &BanList, ip: [u8; 4], peer_id: &PeerId, passkey: Option<&str>) -> Option<u64> {
+        let check = BanCheck {
+            ip: IpAddr::from(ip),
+            peer_id: Some(peer_id),
+            passkey,
+        };
+
+        bans.find(check).map(|ban| ban.id)
+    }
+
+    #[test]
+    fn finds_bans_by_any_target() {
+        let bans = BanList::in_memory();
+        let peer_id = PeerId(vec![b'a'; 20]);
+        let other = PeerId(vec![b'b'; 20]);
+
+        let targets = [
+            BanTarget::Ip(IpAddr::from([192, 0, 2, 1])),
+            BanTarget::Cidr("198.51.100.0/24".parse().unwrap()),
+            BanTarget::PeerId(peer_id.clone()),
+            BanTarget::Passkey("secret".to_string()),
+        ];
+        for target in targets {
+            block_on(bans.add(target, "abuse".to_string(), None)).unwrap();
+        }
+
+        assert_eq!(Some(1), check(&bans, [192, 0, 2, 1], &other, None));
+        assert_eq!(Some(2), check(&bans, [198, 51, 100, 7], &other, None));
+        assert_eq!(Some(3), check(&bans, [203, 0, 113, 1], &peer_id, None));
+        assert_eq!(
+            Some(4),
+            check(&bans, [203, 0, 113, 1], &other, Some("secret"))
+        );
+        assert_eq!(None, check(&bans, [203, 0, 113, 1], &other, Some("other")));
+
+        assert!(block_on(bans.remove(2)).unwrap());
+        assert!(!block_on(bans.remove(2)).unwrap());
+        assert_eq!(None, check(&bans, [198, 51, 100, 7], &other, None));
+        assert_eq!(
+            vec![1, 3, 4],
+            bans.active().iter().map(|b| b.id).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn expires_bans() {
+        let now = Arc::new(Mutex::new(OffsetDateTime::now_utc()));
+        let clock = now.clone();
+        let bans = BanList::in_memory().with_clock(move || *clock.lock().unwrap());
+
+        let ip = IpAddr::from([192, 0, 2, 1]);
+        let check = BanCheck {
+            ip,
+            peer_id: None,
+            passkey: None,
+        };
+        block_on(bans.add(BanTarget::Ip(ip), "abuse".to_string(), Some(Duration::HOUR))).unwrap();
+        assert!(bans.find(check).is_some());
+
+        *now.lock().unwrap() += Duration::HOUR;
+        assert!(bans.find(check).is_none());
+        assert!(bans.active().is_empty());
+    }
+}
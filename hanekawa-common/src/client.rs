@@ -0,0 +1,327 @@
+use crate::{types::PeerId, ClientPolicyConfig, ClientRule};
+
+use std::fmt::Display;
+
+// Shadow-style ids start with one of these, followed by up to five version
+// digits, padded with dashes.
+const SHADOW_CLIENTS: &[(u8, &str)] = &[
+    (b'A', "ABC"),
+    (b'O', "Osprey"),
+    (b'Q', "BTQueue"),
+    (b'R', "Tribler"),
+    (b'S', "Shadow"),
+    (b'T', "BitTornado"),
+    (b'U', "UPnP NAT Bit Torrent"),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Family {
+    QBittorrent,
+    Transmission,
+    // libtorrent-rasterbar, and clients built on it that keep its prefix.
+    Libtorrent,
+    // On libtorrent by rakshasa, which has its own prefix.
+    RTorrent,
+    Deluge,
+    UTorrent,
+    BitTorrent,
+    BitComet,
+    // Other Azureus-style ids, by their two letter code.
+    Azureus(String),
+    // By its letter, one of `SHADOW_CLIENTS`.
+    Shadow(u8),
+    // The first bytes of the id, escaped where not printable.
+    Unknown(String),
+}
+
+impl Display for Family {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::QBittorrent => f.write_str("qBittorrent"),
+            Self::Transmission => f.write_str("Transmission"),
+            Self::Libtorrent => f.write_str("libtorrent"),
+            Self::RTorrent => f.write_str("rTorrent"),
+            Self::Deluge => f.write_str("Deluge"),
+            Self::UTorrent => f.write_str("uTorrent"),
+            Self::BitTorrent => f.write_str("BitTorrent"),
+            Self::BitComet => f.write_str("BitComet"),
+            Self::Azureus(code) => f.write_str(code),
+            Self::Shadow(letter) => {
+                let name = SHADOW_CLIENTS
+                    .iter()
+                    .find(|(l, _)| l == letter)
+                    .map_or("Shadow-style", |(_, name)| name);
+                f.write_str(name)
+            }
+            Self::Unknown(_) => f.write_str("unknown"),
+        }
+    }
+}
+
+// Components as the id has them, `4.6.5` for `-qB4650-`. Trailing zeros
+// are left out, so that `3` and `3.0.0` are the same version.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Version(Vec<u8>);
+
+impl Version {
+    pub fn new(mut components: Vec<u8>) -> Self {
+        while components.last() == Some(&0) {
+            components.pop();
+        }
+        Self(components)
+    }
+}
+
+impl std::str::FromStr for Version {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split('.')
+            .map(str::parse)
+            .collect::<Result<_, _>>()
+            .map(Self::new)
+            .map_err(|_| format!("invalid version: {s}"))
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Version {
+    fn deserialize<D: serde::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        <String as serde::Deserialize>::deserialize(d)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+// At least a major and a minor version, `5.0` for `-qB5000-`.
+impl Display for Version {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut components = self.0.clone();
+        components.resize(components.len().max(2), 0);
+        let components = components.iter().map(u8::to_string).collect::<Vec<_>>();
+        f.write_str(&components.join("."))
+    }
+}
+
+// What a peer id says the client is. Clients may say anything, so this is
+// only as true as they are.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ClientFingerprint {
+    pub family: Family,
+    pub version: Option<Version>,
+}
+
+impl ClientFingerprint {
+    pub fn of(peer_id: &PeerId) -> Self {
+        let id = &peer_id.0;
+        if let Some(client) = peer_id.client() {
+            let version = client.version.bytes().map(digit).collect();
+            return Self {
+                family: azureus(client.id),
+                version: Some(Version::new(version)),
+            };
+        }
+
+        shadow(id)
+            .or_else(|| mainline(id))
+            .or_else(|| bitcomet(id))
+            .unwrap_or_else(|| Self {
+                family: Family::Unknown(id[..id.len().min(8)].escape_ascii().to_string()),
+                version: None,
+            })
+    }
+}
+
+impl Display for ClientFingerprint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.version {
+            Some(version) => f.write_fmt(format_args!("{} {version}", self.family)),
+            None => self.family.fmt(f),
+        }
+    }
+}
+
+impl ClientPolicyConfig {
+    // Denied clients are refused, and so are all those not allowed if any
+    // are.
+    pub fn admits(&self, client: &ClientFingerprint) -> bool {
+        let matching = |rule: &ClientRule| rule.matches(client);
+        !self.deny.iter().any(matching)
+            && (self.allow.is_empty() || self.allow.iter().any(matching))
+    }
+}
+
+impl ClientRule {
+    // Versions are only compared when the id has one.
+    pub fn matches(&self, client: &ClientFingerprint) -> bool {
+        let older = match (&self.below, &client.version) {
+            (None, _) => true,
+            (Some(below), Some(version)) => version < below,
+            (Some(_), None) => false,
+        };
+
+        older && self.client.eq_ignore_ascii_case(&client.family.to_string())
+    }
+}
+
+fn azureus(code: String) -> Family {
+    match code.as_str() {
+        "qB" => Family::QBittorrent,
+        "TR" => Family::Transmission,
+        "LT" => Family::Libtorrent,
+        "lt" => Family::RTorrent,
+        "DE" => Family::Deluge,
+        // On Windows, macOS and the web.
+        "UT" | "UM" | "UW" => Family::UTorrent,
+        "BT" => Family::BitTorrent,
+        "BC" => Family::BitComet,
+        _ => Family::Azureus(code),
+    }
+}
+
+// Base 36, either case, as Azureus-style ids go past 9 with letters.
+fn digit(b: u8) -> u8 {
+    match b {
+        b'0'..=b'9' => b - b'0',
+        _ => b.to_ascii_uppercase() - b'A' + 10,
+    }
+}
+
+// `S58B-----…`, whose digits go on past `Z` with `a`-`z` and `.`.
+fn shadow(id: &[u8]) -> Option<ClientFingerprint> {
+    let (&letter, rest) = id.split_first()?;
+    if id.len() != 20 || !SHADOW_CLIENTS.iter().any(|(l, _)| *l == letter) {
+        return None;
+    }
+
+    let digits = &rest[..5];
+    let len = digits.iter().take_while(|b| **b != b'-').count();
+    let (version, padding) = digits.split_at(len);
+    // Without padding they would be taken for random bytes too often.
+    let padded = !padding.is_empty() || rest[5..8] == *b"---";
+    if len == 0 || !padded || padding.iter().any(|b| *b != b'-') {
+        return None;
+    }
+
+    let version = version
+        .iter()
+        .map(|b| match b {
+            b'0'..=b'9' => Some(b - b'0'),
+            b'A'..=b'Z' => Some(b - b'A' + 10),
+            b'a'..=b'z' => Some(b - b'a' + 36),
+            b'.' => Some(62),
+            _ => None,
+        })
+        .collect::<Option<_>>()?;
+    Some(ClientFingerprint {
+        family: Family::Shadow(letter),
+        version: Some(Version::new(version)),
+    })
+}
+
+// The BitTorrent client before it took Azureus-style ids, `M7-2-2--…` or
+// `M4-20-8-…`.
+fn mainline(id: &[u8]) -> Option<ClientFingerprint> {
+    let prefix = std::str::from_utf8(id.get(..8)?.strip_prefix(b"M")?).ok()?;
+    let version = prefix
+        .trim_end_matches('-')
+        .split('-')
+        .map(|n| {
+            n.parse()
+                .ok()
+                .filter(|_| n.bytes().all(|b| b.is_ascii_digit()))
+        })
+        .collect::<Option<Vec<_>>>()
+        .filter(|version| version.len() == 3)?;
+
+    Some(ClientFingerprint {
+        family: Family::BitTorrent,
+        version: Some(Version::new(version)),
+    })
+}
+
+// `exbc` and the major and minor version as bytes, of old BitComets.
+fn bitcomet(id: &[u8]) -> Option<ClientFingerprint> {
+    match id {
+        [b'e', b'x', b'b', b'c', major, minor, ..] => Some(ClientFingerprint {
+            family: Family::BitComet,
+            version: Some(Version::new(vec![*major, *minor])),
+        }),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn id(prefix: &str) -> PeerId {
+        let mut id = prefix.as_bytes().to_vec();
+        id.resize(20, b'x');
+        PeerId(id)
+    }
+
+    #[test]
+    fn fingerprints_real_world_ids() {
+        for (prefix, expected) in [
+            ("-qB4650-", "qBittorrent 4.6.5"),
+            ("-TR2940-", "Transmission 2.9.4"),
+            ("-TR4060-", "Transmission 4.0.6"),
+            ("-lt0D80-", "rTorrent 0.13.8"),
+            ("-LT2090-", "libtorrent 2.0.9"),
+            ("-DE13F0-", "Deluge 1.3.15"),
+            ("-UT3550-", "uTorrent 3.5.5"),
+            ("-UM1870-", "uTorrent 1.8.7"),
+            ("-qB5000-", "qBittorrent 5.0"),
+            ("-AZ5770-", "AZ 5.7.7"),
+            ("M7-2-2--", "BitTorrent 7.2.2"),
+            ("M4-20-8-", "BitTorrent 4.20.8"),
+            ("S58B-----", "Shadow 5.8.11"),
+            ("T03I--00", "BitTornado 0.3.18"),
+            ("exbc\x00\x3a", "BitComet 0.58"),
+        ] {
+            let peer_id = id(prefix);
+            assert_eq!(expected, ClientFingerprint::of(&peer_id).to_string());
+        }
+
+        for prefix in ["\x01\x02abcdef", "-qB465-x", "Sabcdefg", "M7-2-x--"] {
+            let family = ClientFingerprint::of(&id(prefix)).family;
+            assert!(matches!(family, Family::Unknown(_)), "{prefix:?}");
+        }
+        assert_eq!(
+            Family::Unknown("\\x01\\x02abcdef".to_string()),
+            ClientFingerprint::of(&id("\x01\x02abcdef")).family
+        );
+    }
+
+    #[test]
+    fn refuses_denied_and_unlisted_clients() {
+        let rule = |client: &str, below: Option<&str>| ClientRule {
+            client: client.to_string(),
+            below: below.map(|v| v.parse().unwrap()),
+        };
+        let of = |prefix: &str| ClientFingerprint::of(&id(prefix));
+
+        let policy = ClientPolicyConfig {
+            allow: vec![],
+            deny: vec![rule("utorrent", Some("3")), rule("unknown", None)],
+        };
+        assert!(!policy.admits(&of("-UT2210-")));
+        assert!(policy.admits(&of("-UT3000-")));
+        assert!(policy.admits(&of("-UT3550-")));
+        assert!(!policy.admits(&of("\x01\x02abcdef")));
+        assert!(policy.admits(&of("-qB4650-")));
+
+        let policy = ClientPolicyConfig {
+            allow: vec![rule("qBittorrent", None), rule("Transmission", Some("4"))],
+            deny: vec![rule("qbittorrent", Some("4.3.10"))],
+        };
+        assert!(policy.admits(&of("-qB4650-")));
+        assert!(!policy.admits(&of("-qB4390-")));
+        assert!(policy.admits(&of("-TR2940-")));
+        assert!(!policy.admits(&of("-TR4060-")));
+        assert!(!policy.admits(&of("-DE13F0-")));
+
+        assert!(ClientPolicyConfig::default().admits(&of("\x01\x02abcdef")));
+        assert!("3.x".parse::<Version>().is_err());
+    }
+}
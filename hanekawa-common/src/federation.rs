@@ -0,0 +1,29 @@
+use crate::{
+    repository::peer::UpdatePeerAnnounce,
+    types::{InfoHash, Peer},
+};
+
+use std::collections::HashSet;
+
+// Passes announces on to upstream trackers, and hands out the peers they
+// returned.
+pub trait Federation: Send + Sync {
+    // Returns at once. Forwarding happens in the background, for the
+    // torrents the federation is configured for.
+    fn forward(&self, announce: &UpdatePeerAnnounce);
+
+    // Those upstreams returned recently, tagged with which one.
+    fn peers(&self, info_hash: &InfoHash) -> Vec<Peer>;
+}
+
+// Upstream peers are added after local ones, unless already among them.
+pub fn merge(mut local: Vec<Peer>, upstream: Vec<Peer>) -> Vec<Peer> {
+    let mut known: HashSet<_> = local.iter().map(|p| (p.ip, p.port)).collect();
+    local.extend(
+        upstream
+            .into_iter()
+            .filter(|p| known.insert((p.ip, p.port))),
+    );
+
+    local
+}
@@ -1,43 +1,826 @@
+pub mod audit;
+pub mod ban;
+pub mod client;
+pub mod federation;
+pub mod magnet;
+pub mod maintenance;
+pub mod metainfo;
+pub mod offense;
+pub mod passkey;
+pub mod peer_limit;
+pub mod privacy;
+pub mod probe;
 pub mod repository;
 pub mod task;
+pub mod torrent_policy;
 pub mod types;
 
-use std::{net::Ipv4Addr, sync::Arc};
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    path::PathBuf,
+    sync::Arc,
+};
 
 #[derive(serde::Deserialize, Debug, Clone)]
 pub struct Config {
     pub database_url: String,
+    pub database_pool_size: u32,
     pub message_queue_url: String,
     pub bind_ip: Ipv4Addr,
+    // Also binds the HTTP and UDP trackers on this address, on the same
+    // ports.
+    pub bind_ipv6: Option<Ipv6Addr>,
     pub http_bind_port: u16,
+    // Serves HTTPS rather than HTTP if set.
+    pub tls: Option<TlsConfig>,
+    // Reverse proxies in front of the HTTP tracker, whose X-Forwarded-For,
+    // X-Real-IP and Forwarded headers say where requests came from.
+    #[serde(default)]
+    pub trusted_proxies: Vec<types::Cidr>,
     pub udp_bind_port: u16,
+    pub udp_socket_count: usize,
     pub peer_announce_interval: u32,
-    pub peer_activity_timeout: u32,
+    // The shortest clients are asked to keep to, sent as `min interval`.
+    pub peer_min_announce_interval: u32,
+    // Intervals are spread over this many percent either side, so clients
+    // that started together do not keep announcing together.
+    pub interval_jitter_percent: u32,
+    // Refuses regular announces that come before half of the min interval
+    // the peer was last sent.
+    pub enforce_min_interval: bool,
+    // Twice the longest interval handed out if unset.
+    pub peer_activity_timeout: Option<u32>,
+    // How long a swarm is kept once its last peer is gone, in seconds.
+    // Registered torrents are kept regardless.
+    pub empty_swarm_grace_period: u32,
+    // How often peers that stopped announcing and empty swarms are swept,
+    // in seconds.
+    pub peer_sweep_interval: u32,
+    // Requests under way at shutdown get this long to finish, in seconds,
+    // or 10 if unset.
+    pub shutdown_grace_period: Option<u32>,
+    // Where swarms kept in memory are written at shutdown, and read back
+    // from at start but for peers that timed out in between. Other stores
+    // keep theirs anyway.
+    pub snapshot_path: Option<PathBuf>,
+    pub default_num_want: u32,
+    pub max_num_want: u32,
+    pub udp_max_packet_size: usize,
     pub only_allowed_info_hashes: bool,
     pub enable_admin_api: bool,
+    // Serves the admin API on a port of its own instead of under /admin on
+    // the HTTP tracker's.
+    pub admin_bind_port: Option<u16>,
+    // Required as `Authorization: Bearer <token>` by the admin API if set,
+    // or any of `admin_tokens`, one of which it does not start without. It
+    // may do anything, and is audited as `admin`.
+    pub admin_token: Option<String>,
+    #[serde(default)]
+    pub admin_tokens: Vec<AdminToken>,
+    // Leaves addresses and peer ids out of the admin API's peer lists.
+    pub admin_redact_peers: bool,
+    // Whether the `ip`, `ipv4` and `ipv6` announce parameters are taken as
+    // the peer's addresses when others than trusted forwarders send them.
+    // Theirs always are, unless they are addresses that cannot be stored.
+    #[serde(default)]
+    pub announced_ip: AnnouncedIp,
+    // What becomes of announces for a peer from elsewhere without its key.
+    #[serde(default)]
+    pub key_mismatch: KeyMismatch,
+    // Who may fetch `/stats/torrent/<info hash>`.
+    #[serde(default)]
+    pub torrent_stats: StatsVisibility,
+    #[serde(default)]
+    pub probe: ProbeConfig,
+    #[serde(default)]
+    pub federation: FederationConfig,
+    #[serde(default)]
+    pub invalid_requests: InvalidRequestConfig,
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
+    #[serde(default)]
+    pub peer_limits: PeerLimitConfig,
+    #[serde(default)]
+    pub maintenance: MaintenanceConfig,
+    #[serde(default)]
+    pub compression: CompressionConfig,
+    #[serde(default)]
+    pub privacy: PrivacyConfig,
+    #[serde(default)]
+    pub encryption: EncryptionConfig,
+    #[serde(default)]
+    pub interval_ramp: IntervalRampConfig,
+    #[serde(default)]
+    pub interval_scale: IntervalScaleConfig,
+    #[serde(default)]
+    pub passkeys: PasskeyConfig,
+    #[serde(default)]
+    pub scrape: ScrapeConfig,
+    #[serde(default)]
+    pub compact: CompactConfig,
+    #[serde(default)]
+    pub torrent_policy: TorrentPolicyConfig,
+    // Answers announces and scrapes sent with `Accept: application/json` in
+    // JSON, for debugging. Clients only ever get bencode otherwise.
+    #[serde(default)]
+    pub json_responses: bool,
+    #[serde(default)]
+    pub logging: LoggingConfig,
+    #[serde(default)]
+    pub clients: ClientPolicyConfig,
+}
+
+// What is configured when nothing is, with nowhere to store anything or
+// queue announces to.
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            database_url: String::new(),
+            database_pool_size: 80,
+            message_queue_url: String::new(),
+            bind_ip: Ipv4Addr::UNSPECIFIED,
+            bind_ipv6: None,
+            http_bind_port: 8001,
+            tls: None,
+            trusted_proxies: vec![],
+            udp_bind_port: 8002,
+            udp_socket_count: 1,
+            peer_announce_interval: 1800,
+            peer_min_announce_interval: 900,
+            interval_jitter_percent: 10,
+            enforce_min_interval: false,
+            peer_activity_timeout: None,
+            empty_swarm_grace_period: 4 * 60 * 60,
+            peer_sweep_interval: 60,
+            shutdown_grace_period: None,
+            snapshot_path: None,
+            default_num_want: 50,
+            max_num_want: 200,
+            udp_max_packet_size: 1200,
+            only_allowed_info_hashes: false,
+            enable_admin_api: false,
+            admin_bind_port: None,
+            admin_token: None,
+            admin_tokens: vec![],
+            admin_redact_peers: false,
+            announced_ip: AnnouncedIp::default(),
+            key_mismatch: KeyMismatch::default(),
+            torrent_stats: StatsVisibility::default(),
+            probe: ProbeConfig::default(),
+            federation: FederationConfig::default(),
+            invalid_requests: InvalidRequestConfig::default(),
+            rate_limit: RateLimitConfig::default(),
+            peer_limits: PeerLimitConfig::default(),
+            maintenance: MaintenanceConfig::default(),
+            compression: CompressionConfig::default(),
+            privacy: PrivacyConfig::default(),
+            encryption: EncryptionConfig::default(),
+            interval_ramp: IntervalRampConfig::default(),
+            interval_scale: IntervalScaleConfig::default(),
+            passkeys: PasskeyConfig::default(),
+            scrape: ScrapeConfig::default(),
+            compact: CompactConfig::default(),
+            torrent_policy: TorrentPolicyConfig::default(),
+            json_responses: false,
+            logging: LoggingConfig::default(),
+            clients: ClientPolicyConfig::default(),
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct TlsConfig {
+    // PEM, the server's certificate first and then the rest of its chain.
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+// Whether announced peers take connections is checked in the background if
+// enabled, with probes spread out so the tracker does not look like a
+// scanner.
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ProbeConfig {
+    pub enabled: bool,
+    // Sends a uTP connection request too, for peers that do not take TCP.
+    pub udp: bool,
+    pub timeout_ms: u64,
+    // Probes in flight at once.
+    pub workers: usize,
+    pub per_second: u32,
+    // Before an address is probed again, on any port.
+    pub destination_interval: u64,
+    // Connectable peers handed out for every other peer, or none preferred
+    // if 0.
+    pub connectable_weight: u32,
+}
+
+impl Default for ProbeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            udp: false,
+            timeout_ms: 2000,
+            workers: 16,
+            per_second: 50,
+            destination_interval: 3600,
+            connectable_weight: 0,
+        }
+    }
+}
+
+// Announces are passed on to other trackers if any are listed, and the peers
+// they return handed out along with ours for a while.
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct FederationConfig {
+    pub upstreams: Vec<Upstream>,
+    // Hex info hashes to forward announces for, or all if empty.
+    pub info_hashes: Vec<String>,
+    // How long the peers an upstream returned are handed out, in seconds.
+    pub peer_ttl: u64,
+    pub timeout_ms: u64,
+    // Trackers forwarding announces to this one, whose `ip` parameter is
+    // taken as the peer's address.
+    pub trusted_forwarders: Vec<IpAddr>,
+}
+
+impl Default for FederationConfig {
+    fn default() -> Self {
+        Self {
+            upstreams: vec![],
+            info_hashes: vec![],
+            peer_ttl: 300,
+            timeout_ms: 5000,
+            trusted_forwarders: vec![],
+        }
+    }
+}
+
+// Clients whose requests keep failing validation are answered from a cache,
+// and then refused for a while. Either step is off if its threshold is 0.
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct InvalidRequestConfig {
+    // Over which failures are counted, in seconds.
+    pub window: u64,
+    // Failures after which the same request is answered as before.
+    pub cache_after: usize,
+    // Failures after which the address is timed out.
+    pub timeout_after: usize,
+    // How long a timeout lasts, in seconds.
+    pub timeout: u64,
+}
+
+impl Default for InvalidRequestConfig {
+    fn default() -> Self {
+        Self {
+            window: 300,
+            cache_after: 3,
+            timeout_after: 30,
+            timeout: 600,
+        }
+    }
+}
+
+// Announces and scrapes over HTTP from each address, with a token bucket
+// for each. Addresses in `exempt` are never limited.
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    pub enabled: bool,
+    pub announce: BucketConfig,
+    pub scrape: BucketConfig,
+    pub exempt: Vec<types::Cidr>,
+}
+
+#[derive(serde::Deserialize, Debug, Clone, Copy)]
+pub struct BucketConfig {
+    // Requests that may come one after the other.
+    pub burst: u32,
+    // How many more are allowed each minute after that.
+    pub per_minute: u32,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            announce: BucketConfig {
+                burst: 10,
+                per_minute: 2,
+            },
+            scrape: BucketConfig {
+                burst: 20,
+                per_minute: 10,
+            },
+            exempt: vec!["127.0.0.0/8".parse().unwrap(), "::1/128".parse().unwrap()],
+        }
+    }
+}
+
+// How much of the store announces may take up, each limit off if unset.
+// Only peers that announced within the activity timeout count, so places
+// free up as peers go quiet and are swept.
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct PeerLimitConfig {
+    // Peers kept for each torrent. New ones past it are still answered with
+    // peers.
+    pub max_per_torrent: Option<u32>,
+    pub swarm_full: SwarmFull,
+    // Peers kept for one address, across every torrent. Announces for new
+    // ones past it are refused.
+    pub max_per_ip: Option<u32>,
+    // Torrents without peers that one address may announce to, per window.
+    pub max_new_torrents_per_ip: Option<u32>,
+    // In seconds.
+    pub new_torrent_window: u64,
+}
+
+impl Default for PeerLimitConfig {
+    fn default() -> Self {
+        Self {
+            max_per_torrent: None,
+            swarm_full: SwarmFull::default(),
+            max_per_ip: None,
+            max_new_torrents_per_ip: None,
+            new_torrent_window: 60 * 60,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SwarmFull {
+    // Answered, and not kept.
+    #[default]
+    Ignore,
+    // Kept in place of the peer that announced longest ago.
+    Evict,
+}
+
+// What clients are told while the tracker is drained. It can be switched on
+// and off at runtime through the admin API or with SIGUSR2.
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct MaintenanceConfig {
+    // Whether the tracker starts in maintenance mode.
+    pub enabled: bool,
+    pub reason: String,
+    // When clients should announce again, in minutes.
+    pub retry_in: u32,
+}
+
+impl Default for MaintenanceConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            reason: "down for maintenance".to_string(),
+            retry_in: 30,
+        }
+    }
+}
+
+// Large HTTP tracker responses, like full scrapes and non-compact announces,
+// are gzip or deflate compressed for clients that accept it.
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct CompressionConfig {
+    pub enabled: bool,
+    // Smaller bodies are sent as they are, in bytes.
+    pub min_size: usize,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_size: 4096,
+        }
+    }
+}
+
+// Limits the personal data shown and kept about peers. Announces are still
+// answered with their real addresses.
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct PrivacyConfig {
+    // Shows addresses in the admin API only down to their /24 or /48.
+    pub truncate_ips: bool,
+    // Writes salted hashes instead of addresses and peer ids to the audit
+    // log, and never writes them to the database as they are, encrypting
+    // them under a key kept in memory if no `encryption` keys are set.
+    pub pseudonymize: bool,
+    // How often the salt is replaced, in seconds.
+    pub salt_rotation: u64,
+    // Audit records and announces older than this are deleted, in seconds.
+    // Kept for good if not set.
+    pub retention: Option<u64>,
+}
+
+impl Default for PrivacyConfig {
+    fn default() -> Self {
+        Self {
+            truncate_ips: false,
+            pseudonymize: false,
+            salt_rotation: 24 * 60 * 60,
+            retention: None,
+        }
+    }
+}
+
+// Peers are told to come back sooner while a swarm is forming, so its first
+// members find each other in minutes rather than an interval apart.
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct IntervalRampConfig {
+    pub enabled: bool,
+    // In seconds.
+    pub initial_interval: u32,
+    // A peer's announces to a swarm that start at the initial interval and
+    // double from there.
+    pub first_announces: u32,
+    // Swarms with fewer peers than this get an interval between the initial
+    // and the normal one by their size, and larger ones the normal one.
+    pub small_swarm: u32,
+}
+
+impl Default for IntervalRampConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            initial_interval: 60,
+            first_announces: 3,
+            small_swarm: 10,
+        }
+    }
+}
+
+// Large swarms are told to come back later than `peer_announce_interval`,
+// by so many seconds a peer over the threshold, as a few of their peers are
+// enough for anyone. The min interval grows by as much, and jitter is
+// applied after.
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct IntervalScaleConfig {
+    pub enabled: bool,
+    // Peers, seeders and leechers both.
+    pub threshold: u32,
+    pub per_peer: f64,
+    // In seconds.
+    pub max_interval: u32,
+}
+
+impl Default for IntervalScaleConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            threshold: 1000,
+            per_peer: 0.05,
+            max_interval: 3600,
+        }
+    }
+}
+
+// What `start` logs, and how. `RUST_LOG` takes the place of the filter if
+// set. Span timings are logged as each span closes, storage calls among
+// them at `debug`, for telling whether the database is what is slow.
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct LoggingConfig {
+    pub format: LogFormat,
+    // As `RUST_LOG` takes it, `info,hanekawa_storage=debug` say.
+    pub filter: String,
+    pub span_timings: bool,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            format: LogFormat::Text,
+            filter: "info".to_string(),
+            span_timings: false,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    // One line per event.
+    #[default]
+    Text,
+    // One object per line, for log collectors.
+    Json,
+}
+
+// Clients refused at announce, by what their peer ids say they are, as
+// fingerprinted in `client`.
+#[derive(serde::Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct ClientPolicyConfig {
+    // Only clients one of these matches may announce, if any are listed.
+    pub allow: Vec<ClientRule>,
+    pub deny: Vec<ClientRule>,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct ClientRule {
+    // As fingerprints name it, in any case: `utorrent`, or `unknown` for
+    // ids that say nothing.
+    pub client: String,
+    // Only versions older than this, `3` or `3.5.5`, if set.
+    pub below: Option<client::Version>,
+}
+
+// Passkeys are issued and rotated through the admin API, per user.
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct PasskeyConfig {
+    // Refuses announces and scrapes without a known passkey, and so UDP.
+    // Only then are announce URLs with a passkey in their path served.
+    pub required: bool,
+    // How long a passkey keeps working once rotated away from, in seconds.
+    pub grace_period: u64,
+    // Sent with announces made with such a passkey.
+    pub warning: String,
+}
+
+impl Default for PasskeyConfig {
+    fn default() -> Self {
+        Self {
+            required: false,
+            grace_period: 7 * 24 * 60 * 60,
+            warning: "your passkey has changed, download your torrents again".to_string(),
+        }
+    }
+}
+
+// BEP 48 scrapes over HTTP. UDP ones are bounded by the packet, and answer
+// every info hash asked for.
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ScrapeConfig {
+    // Requests asking for more are refused.
+    pub max_info_hashes: usize,
+    // Answers for info hashes without peers too, with zeros, rather than
+    // leaving them out.
+    pub include_unknown: bool,
+    // Whether scrapes without info hashes get every torrent, as indexers
+    // expect of public trackers.
+    pub full_scrape: FullScrape,
+    // Full scrapes are answered from a snapshot at most this old, in
+    // seconds.
+    pub full_scrape_refresh: u32,
+}
+
+impl Default for ScrapeConfig {
+    fn default() -> Self {
+        Self {
+            max_info_hashes: 74,
+            include_unknown: false,
+            full_scrape: FullScrape::default(),
+            full_scrape_refresh: 300,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FullScrape {
+    Allow,
+    #[default]
+    Deny,
+}
+
+// How HTTP announces are answered as to BEP 23.
+#[derive(serde::Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct CompactConfig {
+    // Compact peer lists for clients that send no `compact` parameter.
+    pub default: bool,
+    // Refuses announces asking for dictionary model peer lists, which are
+    // several times larger.
+    pub required: bool,
+}
+
+impl Default for CompactConfig {
+    fn default() -> Self {
+        Self {
+            default: true,
+            required: false,
+        }
+    }
+}
+
+// Peer ids and addresses are encrypted in the database if any keys are set.
+// The first encrypts, the others only decrypt what `hanekawa-server rekey` has
+// not yet moved to it.
+#[derive(serde::Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct EncryptionConfig {
+    pub keys: Vec<EncryptionKey>,
+    // Keys the hashes that stand in for peer ids and addresses in lookups,
+    // and is required with `keys`. Unlike them it cannot be rotated.
+    pub lookup_key: Option<String>,
+    pub lookup_keyfile: Option<PathBuf>,
+}
+
+// 32 bytes in hex, either inline or in a file of their own.
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct EncryptionKey {
+    // Stored with each row, so must never be reused for another key.
+    pub id: String,
+    pub key: Option<String>,
+    pub keyfile: Option<PathBuf>,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct Upstream {
+    pub url: String,
+    // Whether it takes the peer's address from us. Otherwise the announce is
+    // sent without it, as if from this tracker.
+    #[serde(default)]
+    pub trusted: bool,
+}
+
+// Which torrents are served, by the info hashes listed in `file`, one in hex
+// per line, and those of the .torrent files in `torrents_dir`. Both are read
+// again on SIGHUP.
+#[derive(serde::Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub struct TorrentPolicyConfig {
+    pub mode: TorrentPolicyMode,
+    pub file: Option<PathBuf>,
+    pub torrents_dir: Option<PathBuf>,
+}
+
+#[derive(serde::Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TorrentPolicyMode {
+    // Whatever the list says.
+    #[default]
+    Open,
+    // Only those listed.
+    Allowlist,
+    // All but those listed.
+    Denylist,
+}
+
+#[derive(serde::Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AnnouncedIp {
+    #[default]
+    Ignore,
+    // Only if it can be reached from the internet, as for peers behind NAT.
+    Public,
+    // Whatever it is, as on a LAN.
+    Any,
+}
+
+impl AnnouncedIp {
+    pub fn accepts(self, ip: IpAddr) -> bool {
+        match self {
+            Self::Ignore => false,
+            Self::Public => is_public(ip),
+            Self::Any => true,
+        }
+    }
+}
+
+fn is_public(ip: IpAddr) -> bool {
+    match ip.to_canonical() {
+        IpAddr::V4(ip) => {
+            let shared = ip.octets()[0] == 100 && ip.octets()[1] & 0xc0 == 64;
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                || shared)
+        }
+        IpAddr::V6(ip) => {
+            let segment = ip.segments()[0];
+            let unique_local = segment & 0xfe00 == 0xfc00;
+            let link_local = segment & 0xffc0 == 0xfe80;
+            let documentation = segment == 0x2001 && ip.segments()[1] == 0xdb8;
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                || unique_local
+                || link_local
+                || documentation)
+        }
+    }
+}
+
+#[derive(serde::Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyMismatch {
+    // Answered as if from a peer not in the swarm, and not recorded.
+    #[default]
+    Ignore,
+    // Answered with a failure reason.
+    Reject,
+}
+
+#[derive(serde::Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum StatsVisibility {
+    #[default]
+    Off,
+    // On the HTTP tracker's port.
+    Public,
+    // With the admin API, under its tokens.
+    Admin,
+}
+
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct AdminToken {
+    // Names the token in the audit log, instead of the secret.
+    pub id: String,
+    pub token: String,
+    #[serde(default)]
+    pub scope: AdminScope,
+}
+
+#[derive(serde::Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AdminScope {
+    #[default]
+    Read,
+    // Read, and change torrents, bans and the like.
+    Mutate,
 }
 
 impl Config {
+    // Peers that have not announced for this long have left their swarms,
+    // in seconds.
+    pub fn activity_timeout(&self) -> u32 {
+        let longest =
+            self.longest_interval() as u64 * (100 + self.interval_jitter_percent as u64) / 100;
+        self.peer_activity_timeout
+            .unwrap_or((2 * longest).min(u32::MAX as u64) as u32)
+    }
+
+    // Before jitter, for the largest swarms.
+    pub fn longest_interval(&self) -> u32 {
+        match self.interval_scale.enabled {
+            true => self
+                .peer_announce_interval
+                .max(self.interval_scale.max_interval),
+            false => self.peer_announce_interval,
+        }
+    }
+
+    pub fn shutdown_grace_period(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.shutdown_grace_period.unwrap_or(10) as u64)
+    }
+
     pub fn default_config() -> impl serde::Serialize {
         #[derive(serde::Serialize)]
         struct DefaultConfig {
+            pub database_pool_size: u32,
             pub bind_ip: Ipv4Addr,
             pub http_bind_port: u16,
             pub udp_bind_port: u16,
+            pub udp_socket_count: usize,
             pub peer_announce_interval: u32,
-            pub peer_activity_timeout: u32,
+            pub peer_min_announce_interval: u32,
+            pub interval_jitter_percent: u32,
+            pub enforce_min_interval: bool,
+            pub empty_swarm_grace_period: u32,
+            pub peer_sweep_interval: u32,
+            pub default_num_want: u32,
+            pub max_num_want: u32,
+            pub udp_max_packet_size: usize,
             pub only_allowed_info_hashes: bool,
             pub enable_admin_api: bool,
+            pub admin_redact_peers: bool,
         }
 
+        let cfg = Self::default();
         let defaults = DefaultConfig {
-            bind_ip: "0.0.0.0".parse().unwrap(),
-            http_bind_port: 8001,
-            udp_bind_port: 8002,
-            peer_announce_interval: 60,
-            peer_activity_timeout: 120,
-            only_allowed_info_hashes: false,
-            enable_admin_api: false,
+            database_pool_size: cfg.database_pool_size,
+            bind_ip: cfg.bind_ip,
+            http_bind_port: cfg.http_bind_port,
+            udp_bind_port: cfg.udp_bind_port,
+            udp_socket_count: cfg.udp_socket_count,
+            peer_announce_interval: cfg.peer_announce_interval,
+            peer_min_announce_interval: cfg.peer_min_announce_interval,
+            interval_jitter_percent: cfg.interval_jitter_percent,
+            enforce_min_interval: cfg.enforce_min_interval,
+            empty_swarm_grace_period: cfg.empty_swarm_grace_period,
+            peer_sweep_interval: cfg.peer_sweep_interval,
+            default_num_want: cfg.default_num_want,
+            max_num_want: cfg.max_num_want,
+            udp_max_packet_size: cfg.udp_max_packet_size,
+            only_allowed_info_hashes: cfg.only_allowed_info_hashes,
+            enable_admin_api: cfg.enable_admin_api,
+            admin_redact_peers: cfg.admin_redact_peers,
         };
 
         defaults
@@ -49,4 +832,21 @@ pub struct Services {
     pub peer_repository: Arc<dyn crate::repository::peer::PeerRepository>,
     pub info_hash_repository: Arc<dyn crate::repository::info_hash::InfoHashRepository>,
     pub task_queue: Arc<dyn crate::task::TaskQueue>,
+    pub bans: crate::ban::BanList,
+    pub audit: crate::audit::AuditLog,
+    pub prober: Option<Arc<dyn crate::probe::Prober>>,
+    pub federation: Option<Arc<dyn crate::federation::Federation>>,
+    pub offenders: crate::offense::Offenders,
+    pub maintenance: crate::maintenance::Maintenance,
+    pub passkeys: crate::passkey::Passkeys,
+    pub torrent_policy: crate::torrent_policy::TorrentPolicy,
+    pub peer_limits: crate::peer_limit::PeerLimits,
+    // What the trackers take to be now, which tests may move along.
+    pub clock: Clock,
+}
+
+pub type Clock = Arc<dyn Fn() -> time::OffsetDateTime + Send + Sync>;
+
+pub fn system_clock() -> Clock {
+    Arc::new(time::OffsetDateTime::now_utc)
 }
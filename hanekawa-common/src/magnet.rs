@@ -0,0 +1,269 @@
+use crate::types::InfoHash;
+
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use std::{fmt::Display, str::FromStr};
+
+// The RFC 3986 unreserved characters are left as they are.
+const COMPONENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+// BEP 9: Extension for Peers to Send Metadata Files
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MagnetLink {
+    pub info_hash: InfoHash,
+    pub display_name: Option<String>,
+    // `tr` parameters in order, without duplicates.
+    pub trackers: Vec<String>,
+    // BEP 19's `ws`, in order.
+    pub web_seeds: Vec<String>,
+    // Every other parameter as it came, decoded, so the link can be written
+    // out again whole.
+    pub other: Vec<(String, String)>,
+}
+
+impl MagnetLink {
+    pub fn new(info_hash: InfoHash) -> Self {
+        Self {
+            info_hash,
+            display_name: None,
+            trackers: vec![],
+            web_seeds: vec![],
+            other: vec![],
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum MagnetError {
+    NotMagnet,
+    MissingInfoHash,
+    InvalidInfoHash(String),
+    InvalidEncoding(String),
+}
+
+impl Display for MagnetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotMagnet => f.write_str("not a magnet link"),
+            Self::MissingInfoHash => f.write_str("magnet link without a btih exact topic"),
+            Self::InvalidInfoHash(s) => f.write_fmt(format_args!("invalid info hash: {s}")),
+            Self::InvalidEncoding(s) => f.write_fmt(format_args!("invalid encoding: {s}")),
+        }
+    }
+}
+
+impl std::error::Error for MagnetError {}
+
+impl FromStr for MagnetLink {
+    type Err = MagnetError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let query = s.strip_prefix("magnet:?").ok_or(MagnetError::NotMagnet)?;
+
+        let mut info_hash = None;
+        let mut link = Self::new(InfoHash(vec![]));
+        let decode = |s: &str| {
+            percent_decode_str(s)
+                .decode_utf8()
+                .map(|s| s.into_owned())
+                .map_err(|_| MagnetError::InvalidEncoding(s.to_string()))
+        };
+
+        for pair in query.split('&').filter(|p| !p.is_empty()) {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let value = decode(value)?;
+
+            match key {
+                "xt" if info_hash.is_none() && value.starts_with("urn:btih:") => {
+                    info_hash = Some(parse_btih(&value["urn:btih:".len()..])?);
+                }
+                "dn" => link.display_name = Some(value),
+                "tr" if link.trackers.contains(&value) => {}
+                "tr" => link.trackers.push(value),
+                "ws" => link.web_seeds.push(value),
+                // Including other exact topics, e.g. BEP 52's btmh.
+                _ => link.other.push((decode(key)?, value)),
+            }
+        }
+
+        link.info_hash = info_hash.ok_or(MagnetError::MissingInfoHash)?;
+        Ok(link)
+    }
+}
+
+impl Display for MagnetLink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!(
+            "magnet:?xt=urn:btih:{}",
+            self.info_hash.to_hex()
+        ))?;
+
+        let params = self
+            .display_name
+            .iter()
+            .map(|name| ("dn", name))
+            .chain(self.trackers.iter().map(|url| ("tr", url)))
+            .chain(self.web_seeds.iter().map(|url| ("ws", url)))
+            .chain(self.other.iter().map(|(key, value)| (key.as_str(), value)));
+        for (key, value) in params {
+            f.write_fmt(format_args!(
+                "&{}={}",
+                utf8_percent_encode(key, COMPONENT),
+                utf8_percent_encode(value, COMPONENT)
+            ))?;
+        }
+
+        Ok(())
+    }
+}
+
+// 40 hex digits, or 32 base32 ones in older links.
+fn parse_btih(s: &str) -> Result<InfoHash, MagnetError> {
+    let invalid = || MagnetError::InvalidInfoHash(s.to_string());
+
+    match s.len() {
+        40 => hex::decode(s).map(InfoHash).map_err(|_| invalid()),
+        32 => {
+            let mut bytes = Vec::with_capacity(20);
+            let (mut buffer, mut bits) = (0_u64, 0);
+
+            for c in s.bytes() {
+                let value = match c.to_ascii_uppercase() {
+                    c @ b'A'..=b'Z' => c - b'A',
+                    c @ b'2'..=b'7' => c - b'2' + 26,
+                    _ => return Err(invalid()),
+                };
+                buffer = buffer << 5 | u64::from(value);
+                bits += 5;
+                if bits >= 8 {
+                    bits -= 8;
+                    bytes.push((buffer >> bits) as u8);
+                }
+            }
+
+            Ok(InfoHash(bytes))
+        }
+        _ => Err(invalid()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_magnet_links() {
+        let link: MagnetLink = "magnet:?xt=urn:btih:c12fe1c06bba254a9dc9f519b335aa7c1367a88a\
+            &dn=Some%20Torrent\
+            &tr=http%3A%2F%2Ftracker.test%2Fannounce\
+            &tr=udp%3A%2F%2Ftracker.test%3A6969\
+            &tr=http%3A%2F%2Ftracker.test%2Fannounce"
+            .parse()
+            .unwrap();
+
+        assert_eq!(
+            MagnetLink {
+                info_hash: InfoHash::from_hex("c12fe1c06bba254a9dc9f519b335aa7c1367a88a"),
+                display_name: Some("Some Torrent".to_string()),
+                trackers: vec![
+                    "http://tracker.test/announce".to_string(),
+                    "udp://tracker.test:6969".to_string(),
+                ],
+                web_seeds: vec![],
+                other: vec![],
+            },
+            link
+        );
+    }
+
+    #[test]
+    fn writes_magnet_links() {
+        let mut link = MagnetLink::new(InfoHash::from_hex(
+            "c12fe1c06bba254a9dc9f519b335aa7c1367a88a",
+        ));
+        assert_eq!(
+            "magnet:?xt=urn:btih:c12fe1c06bba254a9dc9f519b335aa7c1367a88a",
+            link.to_string()
+        );
+
+        link.display_name = Some("Some Torrent & more".to_string());
+        link.trackers = vec![
+            "http://tracker.test/announce?passkey=a b".to_string(),
+            "udp://tracker.test:6969".to_string(),
+        ];
+        assert_eq!(
+            "magnet:?xt=urn:btih:c12fe1c06bba254a9dc9f519b335aa7c1367a88a\
+                &dn=Some%20Torrent%20%26%20more\
+                &tr=http%3A%2F%2Ftracker.test%2Fannounce%3Fpasskey%3Da%20b\
+                &tr=udp%3A%2F%2Ftracker.test%3A6969",
+            link.to_string()
+        );
+        assert_eq!(Ok(link.clone()), link.to_string().parse());
+    }
+
+    #[test]
+    fn keeps_what_it_does_not_know() {
+        let link = "magnet:?xt=urn:btih:C12FE1C06BBA254A9DC9F519B335AA7C1367A88A\
+            &xt=urn:btmh:1220aaaa\
+            &ws=http%3A%2F%2Fseed.test%2Fa\
+            &x.pe=192.0.2.1%3A6881\
+            &ws=http%3A%2F%2Fseed.test%2Fb\
+            &kt=a+b";
+        let parsed: MagnetLink = link.parse().unwrap();
+
+        assert_eq!(
+            InfoHash::from_hex("c12fe1c06bba254a9dc9f519b335aa7c1367a88a"),
+            parsed.info_hash
+        );
+        assert_eq!(
+            vec!["http://seed.test/a", "http://seed.test/b"],
+            parsed.web_seeds
+        );
+        assert_eq!(
+            vec![
+                ("xt".to_string(), "urn:btmh:1220aaaa".to_string()),
+                ("x.pe".to_string(), "192.0.2.1:6881".to_string()),
+                ("kt".to_string(), "a+b".to_string()),
+            ],
+            parsed.other
+        );
+        assert_eq!(Ok(parsed.clone()), parsed.to_string().parse());
+    }
+
+    #[test]
+    fn accepts_base32_info_hashes() {
+        let hex: MagnetLink = "magnet:?xt=urn:btih:c12fe1c06bba254a9dc9f519b335aa7c1367a88a"
+            .parse()
+            .unwrap();
+        let base32: MagnetLink = "magnet:?xt=urn:btih:YEX6DQDLXISUVHOJ6UM3GNNKPQJWPKEK"
+            .parse()
+            .unwrap();
+
+        assert_eq!(hex.info_hash, base32.info_hash);
+    }
+
+    #[test]
+    fn rejects_links_without_a_usable_info_hash() {
+        for (link, expected) in [
+            ("http://tracker.test", MagnetError::NotMagnet),
+            ("magnet:?dn=x", MagnetError::MissingInfoHash),
+            (
+                "magnet:?xt=urn:btih:zz",
+                MagnetError::InvalidInfoHash("zz".to_string()),
+            ),
+            (
+                "magnet:?xt=urn:btih:c12fe1c06bba254a9dc9f519b335aa7c1367a88",
+                MagnetError::InvalidInfoHash("c12fe1c06bba254a9dc9f519b335aa7c1367a88".to_string()),
+            ),
+            (
+                "magnet:?xt=urn:btih:YEX6DQDLXISUVHOJ6UM3GNNKPQJWPKEKA",
+                MagnetError::InvalidInfoHash("YEX6DQDLXISUVHOJ6UM3GNNKPQJWPKEKA".to_string()),
+            ),
+        ] {
+            assert_eq!(Err(expected), link.parse::<MagnetLink>());
+        }
+    }
+}
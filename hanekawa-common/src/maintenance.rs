@@ -0,0 +1,29 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+// Whether the tracker is being drained. While it is, announces and scrapes
+// are refused with the configured reason and told when to come back, but
+// peers may still leave. Switched at runtime, every clone sees the change.
+#[derive(Clone, Default)]
+pub struct Maintenance(Arc<AtomicBool>);
+
+impl Maintenance {
+    pub fn new(enabled: bool) -> Self {
+        Self(Arc::new(AtomicBool::new(enabled)))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    pub fn set(&self, enabled: bool) {
+        self.0.store(enabled, Ordering::Relaxed);
+    }
+
+    // Returns whether it is now enabled.
+    pub fn toggle(&self) -> bool {
+        !self.0.fetch_xor(true, Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,356 @@
+use crate::types::InfoHash;
+
+use hanekawa_bencode::Value;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::fmt::Display;
+
+// BEP 3: The BitTorrent Protocol Specification, and BEP 52's v2 and hybrid
+// torrents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Metainfo {
+    pub announce: Option<String>,
+    // BEP 12: Multitracker Metadata Extension, tier by tier.
+    pub announce_list: Vec<Vec<String>>,
+    // In seconds since the epoch.
+    pub creation_date: Option<i64>,
+    pub comment: Option<String>,
+    pub info: Info,
+    // SHA-1 of the info dictionary, for v1 and hybrid torrents.
+    pub info_hash: Option<InfoHash>,
+    // SHA-256 of it, truncated to 20 bytes as v2 clients announce it, for v2
+    // and hybrid torrents.
+    pub info_hash_v2: Option<InfoHash>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Info {
+    pub name: String,
+    pub piece_length: u64,
+    // SHA-1 of each piece, none for v2 torrents.
+    pub pieces: Vec<u8>,
+    pub files: Files,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Files {
+    // The file is named after the torrent.
+    Single { length: u64 },
+    // Under a directory named after the torrent.
+    Multiple(Vec<File>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct File {
+    pub path: Vec<String>,
+    pub length: u64,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum MetainfoError {
+    NotBencode,
+    MissingInfo,
+    InvalidInfo(&'static str),
+}
+
+impl Display for MetainfoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotBencode => f.write_str("not bencoded"),
+            Self::MissingInfo => f.write_str("no info dictionary"),
+            Self::InvalidInfo(s) => f.write_fmt(format_args!("invalid info dictionary: {s}")),
+        }
+    }
+}
+
+impl std::error::Error for MetainfoError {}
+
+impl Metainfo {
+    pub fn from_bytes(input: &[u8]) -> Result<Self, MetainfoError> {
+        let entries =
+            hanekawa_bencode::parse_raw_dict(input).map_err(|_| MetainfoError::NotBencode)?;
+        let raw = |key: &str| {
+            entries
+                .iter()
+                .find(|(k, _)| *k == key.as_bytes())
+                .map(|(_, v)| *v)
+        };
+        let value = |key: &str| Some(hanekawa_bencode::parse(raw(key)?).ok()?.into_value());
+
+        // Hashed as it is in the file, which need not be how it would be
+        // encoded again.
+        let raw_info = raw("info").ok_or(MetainfoError::MissingInfo)?;
+        let info = match value("info") {
+            Some(info @ Value::Dict(_)) => info,
+            _ => return Err(MetainfoError::MissingInfo),
+        };
+
+        let name = string(info.get("name")).ok_or(MetainfoError::InvalidInfo("missing name"))?;
+
+        let pieces = info.get("pieces").and_then(Value::as_bytes);
+        let v1 = pieces.is_some();
+        let v2 = info.get("meta version").and_then(Value::as_int) == Some(2);
+        if !v1 && !v2 {
+            return Err(MetainfoError::InvalidInfo("neither v1 nor v2"));
+        }
+
+        let length = info.get("length").and_then(Value::as_int);
+        let files = info.get("files").and_then(Value::as_list);
+        let files = match (length, files, info.get("file tree")) {
+            (Some(length), _, _) => u64::try_from(length)
+                .ok()
+                .map(|length| Files::Single { length }),
+            (_, Some(files), _) => files
+                .iter()
+                .map(file)
+                .collect::<Option<_>>()
+                .map(Files::Multiple),
+            (_, _, Some(tree)) => {
+                let mut files = Vec::new();
+                tree_files(tree, &mut Vec::new(), &mut files).map(|_| Files::Multiple(files))
+            }
+            _ => None,
+        }
+        .ok_or(MetainfoError::InvalidInfo("invalid file lengths"))?;
+
+        let piece_length = info
+            .get("piece length")
+            .and_then(Value::as_int)
+            .and_then(|length| u64::try_from(length).ok())
+            .ok_or(MetainfoError::InvalidInfo("missing piece length"))?;
+
+        let announce_list = value("announce-list")
+            .as_ref()
+            .and_then(Value::as_list)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(Value::as_list)
+            .map(|tier| tier.iter().filter_map(|url| string(Some(url))).collect())
+            .collect();
+
+        Ok(Self {
+            announce: value("announce").and_then(|announce| string(Some(&announce))),
+            announce_list,
+            creation_date: value("creation date").and_then(|date| date.as_int()),
+            comment: value("comment").and_then(|comment| string(Some(&comment))),
+            info: Info {
+                name,
+                piece_length,
+                pieces: pieces.unwrap_or_default().to_vec(),
+                files,
+            },
+            info_hash: v1.then(|| InfoHash(Sha1::digest(raw_info).to_vec())),
+            info_hash_v2: v2.then(|| InfoHash(Sha256::digest(raw_info)[..20].to_vec())),
+        })
+    }
+
+    // Both of a hybrid's, v1 first.
+    pub fn info_hashes(&self) -> impl Iterator<Item = &InfoHash> {
+        self.info_hash.iter().chain(&self.info_hash_v2)
+    }
+}
+
+impl Info {
+    // Of all files, in bytes.
+    pub fn size(&self) -> u64 {
+        match &self.files {
+            Files::Single { length } => *length,
+            Files::Multiple(files) => files.iter().map(|file| file.length).sum(),
+        }
+    }
+}
+
+fn string(value: Option<&Value<&[u8]>>) -> Option<String> {
+    Some(String::from_utf8_lossy(value?.as_bytes()?).into_owned())
+}
+
+fn length(file: &Value<&[u8]>) -> Option<u64> {
+    u64::try_from(file.get("length")?.as_int()?).ok()
+}
+
+fn file(file: &Value<&[u8]>) -> Option<File> {
+    let path = file.get("path")?.as_list()?;
+    Some(File {
+        path: path
+            .iter()
+            .map(|p| string(Some(p)))
+            .collect::<Option<_>>()?,
+        length: length(file)?,
+    })
+}
+
+// BEP 52's file tree, where a file is a dictionary under the empty key.
+fn tree_files(tree: &Value<&[u8]>, path: &mut Vec<String>, files: &mut Vec<File>) -> Option<()> {
+    // No deeper than the parser allows.
+    for (name, entry) in tree.as_dict()? {
+        if name.is_empty() {
+            files.push(File {
+                path: path.clone(),
+                length: length(entry)?,
+            });
+            continue;
+        }
+        path.push(String::from_utf8_lossy(name).into_owned());
+        tree_files(entry, path, files)?;
+        path.pop();
+    }
+
+    Some(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Keys out of order, so re-encoding the info dictionary would give
+    // another hash.
+    const V1: &[u8] = b"d8:announce21:http://tracker.test/a4:infod4:name5:a.txt6:lengthi5e\
+        12:piece lengthi16384e6:pieces20:aaaaaaaaaaaaaaaaaaaaee";
+
+    fn raw(torrent: &[u8]) -> &[u8] {
+        let start = torrent.windows(6).position(|w| w == b"4:info").unwrap() + 6;
+        &torrent[start..torrent.len() - 1]
+    }
+
+    #[test]
+    fn hashes_the_info_dictionary_as_written() {
+        let metainfo = Metainfo::from_bytes(V1).unwrap();
+
+        assert_eq!(
+            Metainfo {
+                announce: Some("http://tracker.test/a".to_string()),
+                announce_list: vec![],
+                creation_date: None,
+                comment: None,
+                info: Info {
+                    name: "a.txt".to_string(),
+                    piece_length: 16384,
+                    pieces: vec![b'a'; 20],
+                    files: Files::Single { length: 5 },
+                },
+                info_hash: Some(InfoHash(Sha1::digest(raw(V1)).to_vec())),
+                info_hash_v2: None,
+            },
+            metainfo
+        );
+    }
+
+    #[test]
+    fn reads_single_file_torrents() {
+        let torrent = include_bytes!("../fixtures/single-file.torrent");
+        let metainfo = Metainfo::from_bytes(torrent).unwrap();
+
+        assert_eq!(
+            "1d9b3d2093102580772a6d3f1b666e965e4a2075",
+            metainfo.info_hash.unwrap().to_hex()
+        );
+        assert_eq!(
+            Some("http://tracker.test/announce"),
+            metainfo.announce.as_deref()
+        );
+        assert_eq!(
+            vec![
+                vec!["http://tracker.test/announce"],
+                vec![
+                    "udp://tracker.test:6969/announce",
+                    "http://backup.test/announce"
+                ],
+            ],
+            metainfo.announce_list
+        );
+        assert_eq!(Some(1760400000), metainfo.creation_date);
+        assert_eq!(Some("Numbers, one a line"), metainfo.comment.as_deref());
+        assert_eq!("numbers.txt", metainfo.info.name);
+        assert_eq!(Files::Single { length: 48890 }, metainfo.info.files);
+        assert_eq!(48890, metainfo.info.size());
+        assert_eq!(3 * 20, metainfo.info.pieces.len());
+    }
+
+    #[test]
+    fn reads_multi_file_torrents() {
+        let torrent = include_bytes!("../fixtures/multi-file.torrent");
+        let metainfo = Metainfo::from_bytes(torrent).unwrap();
+
+        assert_eq!(
+            "01754695f7174eb85aad2a436795cfec4fb20897",
+            metainfo.info_hash.unwrap().to_hex()
+        );
+        assert_eq!(None, metainfo.info_hash_v2);
+        assert_eq!(2, metainfo.announce_list.len());
+        assert_eq!(Some(1665468170), metainfo.creation_date);
+        assert_eq!("TheVoynichManuscript", metainfo.info.name);
+        assert_eq!(524288, metainfo.info.piece_length);
+
+        let Files::Multiple(files) = &metainfo.info.files else {
+            panic!("not a multi-file torrent");
+        };
+        assert_eq!(13, files.len());
+        assert_eq!(
+            File {
+                path: vec!["Voynich_Manuscript.djvu".to_string()],
+                length: 5029507,
+            },
+            files[2]
+        );
+        assert_eq!(101420791, metainfo.info.size());
+    }
+
+    #[test]
+    fn hashes_hybrids_both_ways() {
+        let torrent = b"d4:infod9:file treed1:ad0:d6:lengthi3eee1:bd1:cd0:d6:lengthi4eeeee\
+            5:filesld6:lengthi3e4:pathl1:aeed6:lengthi4e4:pathl1:b1:ceee\
+            12:meta versioni2e4:name1:x12:piece lengthi16384e6:pieces20:aaaaaaaaaaaaaaaaaaaaee";
+        let metainfo = Metainfo::from_bytes(torrent).unwrap();
+
+        assert_eq!(7, metainfo.info.size());
+        assert_eq!(
+            vec![
+                InfoHash(Sha1::digest(raw(torrent)).to_vec()),
+                InfoHash(Sha256::digest(raw(torrent))[..20].to_vec()),
+            ],
+            metainfo.info_hashes().cloned().collect::<Vec<_>>()
+        );
+
+        // Without the v1 file list, files come from the tree.
+        let v2 = b"d4:infod9:file treed1:ad0:d6:lengthi3eee1:bd1:cd0:d6:lengthi4eeeee\
+            12:meta versioni2e4:name1:x12:piece lengthi16384eee";
+        let v2 = Metainfo::from_bytes(v2).unwrap();
+        assert_eq!(None, v2.info_hash);
+        assert_eq!(
+            Files::Multiple(vec![
+                File {
+                    path: vec!["a".to_string()],
+                    length: 3,
+                },
+                File {
+                    path: vec!["b".to_string(), "c".to_string()],
+                    length: 4,
+                },
+            ]),
+            v2.info.files
+        );
+    }
+
+    #[test]
+    fn rejects_files_without_a_usable_info_dictionary() {
+        for (torrent, expected) in [
+            (&b"d4:infod"[..], MetainfoError::NotBencode),
+            (b"d8:announce1:xe", MetainfoError::MissingInfo),
+            (b"d4:infoi1ee", MetainfoError::MissingInfo),
+            (
+                b"d4:infod6:lengthi5eee",
+                MetainfoError::InvalidInfo("missing name"),
+            ),
+            (
+                b"d4:infod6:lengthi-5e4:name1:x6:pieces0:ee",
+                MetainfoError::InvalidInfo("invalid file lengths"),
+            ),
+            (
+                b"d4:infod6:lengthi5e4:name1:x6:pieces0:ee",
+                MetainfoError::InvalidInfo("missing piece length"),
+            ),
+        ] {
+            assert_eq!(Err(expected), Metainfo::from_bytes(torrent));
+        }
+    }
+}
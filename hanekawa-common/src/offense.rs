@@ -0,0 +1,284 @@
+use crate::InvalidRequestConfig;
+
+use std::{
+    collections::{HashMap, VecDeque},
+    net::IpAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+use time::{Duration, OffsetDateTime};
+
+type Clock = Arc<dyn Fn() -> OffsetDateTime + Send + Sync>;
+
+// Addresses tracked at once. New ones past it go unnoticed until others are
+// forgiven.
+const MAX_TRACKED: usize = 1 << 16;
+
+// Clients that keep sending requests the tracker cannot make sense of. Past
+// `cache_after` failures within the window, the same request is answered with
+// what it got the last time without looking at it again, and past
+// `timeout_after` the address is refused for a while. Unlike bans, timeouts
+// are never stored and lift by themselves.
+#[derive(Clone)]
+pub struct Offenders {
+    config: InvalidRequestConfig,
+    clock: Clock,
+    records: Arc<Mutex<HashMap<IpAddr, Record>>>,
+    repeats_answered: Arc<AtomicU64>,
+    timeouts: Arc<AtomicU64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Standing {
+    Good,
+    // The response to the same invalid request before.
+    Repeated(Arc<[u8]>),
+    TimedOut { until: OffsetDateTime },
+}
+
+// Since the process started.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize)]
+pub struct OffenseCounts {
+    pub repeats_answered: u64,
+    pub timeouts: u64,
+}
+
+#[derive(Default)]
+struct Record {
+    failures: VecDeque<OffsetDateTime>,
+    // The latest invalid request's fingerprint, and its response.
+    last: Option<(u64, Arc<[u8]>)>,
+    timed_out_until: Option<OffsetDateTime>,
+}
+
+impl Record {
+    fn forget_before(&mut self, since: OffsetDateTime) {
+        while self.failures.front().is_some_and(|at| *at < since) {
+            self.failures.pop_front();
+        }
+    }
+
+    fn is_clean(&self, now: OffsetDateTime) -> bool {
+        self.failures.is_empty() && self.timed_out_until.is_none_or(|until| until <= now)
+    }
+}
+
+impl Offenders {
+    pub fn new(config: &InvalidRequestConfig) -> Self {
+        Self {
+            config: config.clone(),
+            clock: Arc::new(OffsetDateTime::now_utc),
+            records: Arc::default(),
+            repeats_answered: Arc::default(),
+            timeouts: Arc::default(),
+        }
+    }
+
+    pub fn with_clock(
+        mut self,
+        clock: impl Fn() -> OffsetDateTime + Send + Sync + 'static,
+    ) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    fn window(&self) -> Duration {
+        Duration::seconds(self.config.window as i64)
+    }
+
+    // Checked before a request is looked at, with a fingerprint that tells
+    // repeats apart. A repeat answered from the cache counts as a failure.
+    pub fn standing(&self, ip: IpAddr, fingerprint: u64) -> Standing {
+        let now = (self.clock)();
+        let mut records = self.records.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(record) = records.get_mut(&ip) else {
+            return Standing::Good;
+        };
+
+        if let Some(until) = record.timed_out_until {
+            if until > now {
+                return Standing::TimedOut { until };
+            }
+            record.timed_out_until = None;
+        }
+
+        record.forget_before(now - self.window());
+        match &record.last {
+            Some((last, response))
+                if *last == fingerprint
+                    && self.config.cache_after > 0
+                    && record.failures.len() >= self.config.cache_after =>
+            {
+                let response = response.clone();
+                self.fail(record, now);
+                self.repeats_answered.fetch_add(1, Ordering::Relaxed);
+                Standing::Repeated(response)
+            }
+            _ => Standing::Good,
+        }
+    }
+
+    pub fn invalid(&self, ip: IpAddr, fingerprint: u64, response: impl Into<Arc<[u8]>>) {
+        let now = (self.clock)();
+        let mut records = self.records.lock().unwrap_or_else(|e| e.into_inner());
+        if !records.contains_key(&ip) && records.len() >= MAX_TRACKED {
+            let since = now - self.window();
+            records.retain(|_, record| {
+                record.forget_before(since);
+                !record.is_clean(now)
+            });
+            if records.len() >= MAX_TRACKED {
+                return;
+            }
+        }
+
+        let record = records.entry(ip).or_default();
+        record.forget_before(now - self.window());
+        record.last = Some((fingerprint, response.into()));
+        self.fail(record, now);
+    }
+
+    fn fail(&self, record: &mut Record, now: OffsetDateTime) {
+        record.failures.push_back(now);
+        if self.config.timeout_after > 0 && record.failures.len() >= self.config.timeout_after {
+            record.failures.clear();
+            record.last = None;
+            record.timed_out_until = Some(now + Duration::seconds(self.config.timeout as i64));
+            self.timeouts.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    // A valid request forgives half of the failures, the oldest first, so a
+    // client that was fixed is back in good standing after a few.
+    pub fn valid(&self, ip: IpAddr) {
+        let now = (self.clock)();
+        let mut records = self.records.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(record) = records.get_mut(&ip) else {
+            return;
+        };
+
+        let forgiven = record.failures.len().div_ceil(2);
+        record.failures.drain(..forgiven);
+        if record.is_clean(now) {
+            records.remove(&ip);
+        }
+    }
+
+    // Those lifting soonest first.
+    pub fn timed_out(&self) -> Vec<(IpAddr, OffsetDateTime)> {
+        let now = (self.clock)();
+        let records = self.records.lock().unwrap_or_else(|e| e.into_inner());
+        let mut timed_out = records
+            .iter()
+            .filter_map(|(ip, record)| record.timed_out_until.map(|until| (*ip, until)))
+            .filter(|(_, until)| *until > now)
+            .collect::<Vec<_>>();
+        timed_out.sort_by_key(|(ip, until)| (*until, *ip));
+
+        timed_out
+    }
+
+    pub fn counts(&self) -> OffenseCounts {
+        OffenseCounts {
+            repeats_answered: self.repeats_answered.load(Ordering::Relaxed),
+            timeouts: self.timeouts.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn offenders() -> (Offenders, impl Fn(Duration)) {
+        let config = InvalidRequestConfig {
+            window: 60,
+            cache_after: 3,
+            timeout_after: 6,
+            timeout: 600,
+        };
+        let now = Arc::new(Mutex::new(OffsetDateTime::now_utc()));
+        let clock = now.clone();
+        let offenders = Offenders::new(&config).with_clock(move || *clock.lock().unwrap());
+
+        (offenders, move |by| *now.lock().unwrap() += by)
+    }
+
+    const RESPONSE: &[u8] = b"d14:failure reason7:invalide";
+
+    #[test]
+    fn escalates_from_cached_responses_to_a_timeout() {
+        let (offenders, advance) = offenders();
+        let ip = IpAddr::from([192, 0, 2, 1]);
+
+        for _ in 0..3 {
+            assert_eq!(Standing::Good, offenders.standing(ip, 1));
+            offenders.invalid(ip, 1, RESPONSE);
+        }
+
+        // Only the same request is answered from the cache.
+        assert_eq!(Standing::Good, offenders.standing(ip, 2));
+        assert_eq!(
+            Standing::Repeated(RESPONSE.into()),
+            offenders.standing(ip, 1)
+        );
+        assert_eq!(
+            Standing::Good,
+            offenders.standing(IpAddr::from([192, 0, 2, 2]), 1)
+        );
+
+        assert!(matches!(offenders.standing(ip, 1), Standing::Repeated(_)));
+        assert!(matches!(offenders.standing(ip, 1), Standing::Repeated(_)));
+        let Standing::TimedOut { until } = offenders.standing(ip, 1) else {
+            panic!("not timed out");
+        };
+        assert_eq!(vec![(ip, until)], offenders.timed_out());
+        assert_eq!(
+            OffenseCounts {
+                repeats_answered: 3,
+                timeouts: 1,
+            },
+            offenders.counts()
+        );
+
+        advance(Duration::minutes(10));
+        assert_eq!(Standing::Good, offenders.standing(ip, 1));
+        assert!(offenders.timed_out().is_empty());
+    }
+
+    #[test]
+    fn counts_failures_within_the_window() {
+        let (offenders, advance) = offenders();
+        let ip = IpAddr::from([192, 0, 2, 1]);
+
+        for _ in 0..5 {
+            offenders.invalid(ip, 1, RESPONSE);
+            advance(Duration::seconds(20));
+        }
+
+        // Only the latest three are within the minute.
+        assert!(matches!(offenders.standing(ip, 1), Standing::Repeated(_)));
+        offenders.invalid(ip, 1, RESPONSE);
+        assert!(offenders.timed_out().is_empty());
+    }
+
+    #[test]
+    fn valid_requests_forgive_failures_quickly() {
+        let (offenders, _) = offenders();
+        let ip = IpAddr::from([192, 0, 2, 1]);
+
+        for _ in 0..4 {
+            offenders.invalid(ip, 1, RESPONSE);
+        }
+        assert!(matches!(offenders.standing(ip, 1), Standing::Repeated(_)));
+
+        // Five failures, then two, one and none.
+        offenders.valid(ip);
+        assert_eq!(Standing::Good, offenders.standing(ip, 1));
+        offenders.valid(ip);
+        offenders.valid(ip);
+        assert!(offenders.records.lock().unwrap().is_empty());
+    }
+}
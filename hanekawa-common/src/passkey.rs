@@ -0,0 +1,154 @@
+use crate::{
+    repository::{
+        passkey::{AddPasskey, GetPasskeys, PasskeyRepository},
+        Error,
+    },
+    types::Passkey,
+};
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+use time::{Duration, OffsetDateTime};
+
+type Clock = Arc<dyn Fn() -> OffsetDateTime + Send + Sync>;
+
+// What announces are checked against when passkeys are required. As with
+// bans, lookups never touch storage, and changes are written through to the
+// repository first, if there is one.
+#[derive(Clone)]
+pub struct Passkeys {
+    repository: Option<Arc<dyn PasskeyRepository>>,
+    clock: Clock,
+    index: Arc<RwLock<HashMap<String, Passkey>>>,
+}
+
+impl Passkeys {
+    // Passkeys are forgotten when the process exits.
+    pub fn in_memory() -> Self {
+        Self {
+            repository: None,
+            clock: Arc::new(OffsetDateTime::now_utc),
+            index: Default::default(),
+        }
+    }
+
+    pub async fn load(repository: Arc<dyn PasskeyRepository>) -> Result<Self, Error> {
+        let passkeys = repository
+            .get_passkeys(GetPasskeys {
+                active_at: OffsetDateTime::now_utc(),
+            })
+            .await?;
+
+        Ok(Self {
+            repository: Some(repository),
+            clock: Arc::new(OffsetDateTime::now_utc),
+            index: Arc::new(RwLock::new(
+                passkeys
+                    .into_iter()
+                    .map(|p| (p.passkey.clone(), p))
+                    .collect(),
+            )),
+        })
+    }
+
+    pub fn with_clock(
+        mut self,
+        clock: impl Fn() -> OffsetDateTime + Send + Sync + 'static,
+    ) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    // A new passkey for the user, with the ones before it working for
+    // `grace` longer, or not at all if zero.
+    pub async fn rotate(&self, user_id: &str, grace: Duration) -> Result<Passkey, Error> {
+        let now = (self.clock)();
+        let passkey = Passkey {
+            passkey: hex::encode(rand::random::<[u8; 16]>()),
+            user_id: user_id.to_string(),
+            created: now,
+            expires: None,
+        };
+        let retire_at = now + grace;
+
+        if let Some(repository) = &self.repository {
+            repository
+                .add_passkey(AddPasskey {
+                    passkey: &passkey,
+                    retire_others_at: retire_at,
+                })
+                .await?;
+        }
+
+        let mut index = self.index.write().unwrap_or_else(|e| e.into_inner());
+        for other in index.values_mut().filter(|p| p.user_id == user_id) {
+            other.expires = Some(other.expires.map_or(retire_at, |e| e.min(retire_at)));
+        }
+        index.retain(|_, p| p.is_active(now));
+        index.insert(passkey.passkey.clone(), passkey.clone());
+
+        Ok(passkey)
+    }
+
+    // None if not one that could have been issued, unknown, or expired.
+    pub fn find(&self, passkey: &str) -> Option<Passkey> {
+        if passkey.len() != 32 || !passkey.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+
+        let now = (self.clock)();
+        let index = self.index.read().unwrap_or_else(|e| e.into_inner());
+
+        index.get(passkey).filter(|p| p.is_active(now)).cloned()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use futures::executor::block_on;
+    use std::sync::Mutex;
+
+    #[test]
+    fn keeps_rotated_passkeys_for_the_grace_period() {
+        let now = Arc::new(Mutex::new(OffsetDateTime::now_utc()));
+        let clock = now.clone();
+        let passkeys = Passkeys::in_memory().with_clock(move || *clock.lock().unwrap());
+
+        let first = block_on(passkeys.rotate("1", Duration::DAY)).unwrap();
+        assert_eq!(Some(first.clone()), passkeys.find(&first.passkey));
+        assert_eq!(None, passkeys.find("guess"));
+        assert_eq!(None, passkeys.find(&first.passkey.to_uppercase()));
+
+        let second = block_on(passkeys.rotate("1", Duration::DAY)).unwrap();
+        assert_ne!(first.passkey, second.passkey);
+        let retiring = passkeys.find(&first.passkey).unwrap();
+        assert_eq!(Some(second.created + Duration::DAY), retiring.expires);
+        assert_eq!(None, passkeys.find(&second.passkey).unwrap().expires);
+
+        // Rotating again does not extend the grace period.
+        *now.lock().unwrap() += Duration::HOUR;
+        let third = block_on(passkeys.rotate("1", Duration::DAY)).unwrap();
+        assert_eq!(retiring, passkeys.find(&first.passkey).unwrap());
+
+        *now.lock().unwrap() += Duration::DAY;
+        assert_eq!(None, passkeys.find(&first.passkey));
+        assert_eq!(None, passkeys.find(&second.passkey));
+        assert!(passkeys.find(&third.passkey).is_some());
+    }
+
+    #[test]
+    fn revokes_at_once_without_a_grace_period() {
+        let passkeys = Passkeys::in_memory();
+        let other = block_on(passkeys.rotate("2", Duration::DAY)).unwrap();
+
+        let leaked = block_on(passkeys.rotate("1", Duration::DAY)).unwrap();
+        let fresh = block_on(passkeys.rotate("1", Duration::ZERO)).unwrap();
+        assert_eq!(None, passkeys.find(&leaked.passkey));
+        assert!(passkeys.find(&fresh.passkey).is_some());
+        assert!(passkeys.find(&other.passkey).is_some());
+    }
+}
@@ -0,0 +1,197 @@
+use crate::{
+    repository::{
+        peer::{CountPeers, EvictStalestPeer, PeerRepository, UpdatePeerAnnounce},
+        Error,
+    },
+    types::Event,
+    Config, PeerLimitConfig, SwarmFull,
+};
+
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+};
+use time::{Duration, OffsetDateTime};
+
+type Clock = Arc<dyn Fn() -> OffsetDateTime + Send + Sync>;
+
+// Addresses whose new torrents are counted at once. New ones past it go
+// uncounted until older windows are over.
+const MAX_TRACKED: usize = 1 << 16;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Refusal {
+    // At most this many peers are kept for one address.
+    TooManyPeers(u32),
+    // Seconds until the address may announce to another new torrent.
+    TooManyTorrents(u32),
+    // The store could not tell how many there are.
+    Storage(Error),
+}
+
+// The new torrents an address announced to since `started`.
+struct Window {
+    started: OffsetDateTime,
+    torrents: u32,
+}
+
+// Holds announces to the config's peer limits, from what the store has.
+// New torrents are counted here, in windows that start with each address's
+// first, and are not shared between instances.
+#[derive(Clone)]
+pub struct PeerLimits {
+    config: PeerLimitConfig,
+    activity_timeout: Duration,
+    clock: Clock,
+    windows: Arc<Mutex<HashMap<IpAddr, Window>>>,
+}
+
+impl PeerLimits {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            config: config.peer_limits.clone(),
+            activity_timeout: Duration::seconds(config.activity_timeout() as i64),
+            clock: Arc::new(OffsetDateTime::now_utc),
+            windows: Arc::default(),
+        }
+    }
+
+    pub fn unlimited() -> Self {
+        Self {
+            config: PeerLimitConfig::default(),
+            activity_timeout: Duration::ZERO,
+            clock: Arc::new(OffsetDateTime::now_utc),
+            windows: Arc::default(),
+        }
+    }
+
+    pub fn with_clock(
+        mut self,
+        clock: impl Fn() -> OffsetDateTime + Send + Sync + 'static,
+    ) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    // Whether the announce is to be kept, once room is made for it if so
+    // configured. Peers already kept, and those leaving, always are.
+    pub async fn admit(
+        &self,
+        repository: &dyn PeerRepository,
+        cmd: &UpdatePeerAnnounce,
+    ) -> Result<bool, Refusal> {
+        let config = &self.config;
+        let unlimited = config.max_per_torrent.is_none()
+            && config.max_per_ip.is_none()
+            && config.max_new_torrents_per_ip.is_none();
+        if unlimited || cmd.event == Event::Stopped {
+            return Ok(true);
+        }
+
+        let active_after = (self.clock)() - self.activity_timeout;
+        let counts = repository
+            .count_peers(CountPeers {
+                info_hash: &cmd.info_hash,
+                peer_id: &cmd.peer_id,
+                ip: config.max_per_ip.map(|_| cmd.ip),
+                active_after,
+            })
+            .await
+            .map_err(Refusal::Storage)?;
+        let Some(counts) = counts.filter(|counts| !counts.known) else {
+            return Ok(true);
+        };
+
+        if let Some(max) = config.max_per_ip {
+            if counts.from_ip >= max {
+                return Err(Refusal::TooManyPeers(max));
+            }
+        }
+        if counts.in_swarm == 0 {
+            self.count_new_torrent(cmd.ip)?;
+        }
+
+        match (config.max_per_torrent, config.swarm_full) {
+            (Some(max), SwarmFull::Ignore) if counts.in_swarm >= max => Ok(false),
+            (Some(max), SwarmFull::Evict) if counts.in_swarm >= max => {
+                repository
+                    .evict_stalest_peer(EvictStalestPeer {
+                        info_hash: &cmd.info_hash,
+                        active_after,
+                    })
+                    .await
+                    .map_err(Refusal::Storage)?;
+                Ok(true)
+            }
+            _ => Ok(true),
+        }
+    }
+
+    fn count_new_torrent(&self, ip: IpAddr) -> Result<(), Refusal> {
+        let Some(max) = self.config.max_new_torrents_per_ip else {
+            return Ok(());
+        };
+
+        let now = (self.clock)();
+        let window = Duration::seconds(self.config.new_torrent_window as i64);
+        let mut windows = self.windows.lock().unwrap_or_else(|e| e.into_inner());
+        if !windows.contains_key(&ip) && windows.len() >= MAX_TRACKED {
+            windows.retain(|_, w| now - w.started < window);
+            if windows.len() >= MAX_TRACKED {
+                return Ok(());
+            }
+        }
+
+        let w = windows.entry(ip).or_insert(Window {
+            started: now,
+            torrents: 0,
+        });
+        if now - w.started >= window {
+            *w = Window {
+                started: now,
+                torrents: 0,
+            };
+        }
+        if w.torrents >= max {
+            let left = (w.started + window - now).whole_seconds().max(1);
+            return Err(Refusal::TooManyTorrents(left as u32));
+        }
+        w.torrents += 1;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn counts_new_torrents_per_address_and_window() {
+        let now = Arc::new(Mutex::new(OffsetDateTime::now_utc()));
+        let clock = now.clone();
+        let limits = PeerLimits {
+            config: PeerLimitConfig {
+                max_new_torrents_per_ip: Some(2),
+                new_torrent_window: 60,
+                ..PeerLimitConfig::default()
+            },
+            ..PeerLimits::unlimited()
+        }
+        .with_clock(move || *clock.lock().unwrap());
+        let (a, b) = (IpAddr::from([192, 0, 2, 1]), IpAddr::from([192, 0, 2, 2]));
+
+        assert_eq!(Ok(()), limits.count_new_torrent(a));
+        *now.lock().unwrap() += Duration::seconds(20);
+        assert_eq!(Ok(()), limits.count_new_torrent(a));
+        assert_eq!(
+            Err(Refusal::TooManyTorrents(40)),
+            limits.count_new_torrent(a)
+        );
+        assert_eq!(Ok(()), limits.count_new_torrent(b));
+
+        *now.lock().unwrap() += Duration::seconds(40);
+        assert_eq!(Ok(()), limits.count_new_torrent(a));
+    }
+}
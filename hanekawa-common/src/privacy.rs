@@ -0,0 +1,101 @@
+use crate::Clock;
+
+use sha2::{Digest, Sha256};
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    sync::{Arc, Mutex},
+};
+use time::{Duration, OffsetDateTime};
+
+// Down to the network it is in, a /24 or a /48, which is usually an ISP's
+// customers rather than one of them.
+pub fn truncate(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            IpAddr::V4(Ipv4Addr::new(a, b, c, 0))
+        }
+        IpAddr::V6(ip) => {
+            let [a, b, c, ..] = ip.segments();
+            IpAddr::V6(Ipv6Addr::new(a, b, c, 0, 0, 0, 0, 0))
+        }
+    }
+}
+
+// Stands in for addresses and peer ids where they only need to be told
+// apart. The salt is random and never stored, and is replaced every
+// `rotation`, so pseudonyms can neither be reversed nor linked across
+// rotations.
+#[derive(Clone)]
+pub struct Pseudonymizer {
+    rotation: Duration,
+    clock: Clock,
+    salt: Arc<Mutex<(OffsetDateTime, [u8; 32])>>,
+}
+
+impl Pseudonymizer {
+    pub fn new(rotation: std::time::Duration) -> Self {
+        let now = OffsetDateTime::now_utc();
+        Self {
+            rotation: Duration::try_from(rotation).unwrap_or(Duration::MAX),
+            clock: Arc::new(OffsetDateTime::now_utc),
+            salt: Arc::new(Mutex::new((now, rand::random()))),
+        }
+    }
+
+    pub fn with_clock(
+        mut self,
+        clock: impl Fn() -> OffsetDateTime + Send + Sync + 'static,
+    ) -> Self {
+        self.salt.lock().unwrap().0 = clock();
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    pub fn pseudonym(&self, value: &[u8]) -> String {
+        let now = (self.clock)();
+        let mut salt = self.salt.lock().unwrap_or_else(|e| e.into_inner());
+        if now - salt.0 >= self.rotation {
+            *salt = (now, rand::random());
+        }
+
+        let digest = Sha256::new()
+            .chain_update(salt.1)
+            .chain_update(value)
+            .finalize();
+        format!("anon-{}", hex::encode(&digest[..8]))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn truncates_to_the_network() {
+        assert_eq!(
+            IpAddr::from([192, 0, 2, 0]),
+            truncate(IpAddr::from([192, 0, 2, 77]))
+        );
+        assert_eq!(
+            "2001:db8:1::".parse::<IpAddr>().unwrap(),
+            truncate("2001:db8:1:2::77".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn pseudonyms_change_with_the_salt() {
+        let now = Arc::new(Mutex::new(OffsetDateTime::now_utc()));
+        let clock = now.clone();
+        let pseudonymizer = Pseudonymizer::new(std::time::Duration::from_secs(3600))
+            .with_clock(move || *clock.lock().unwrap());
+
+        let before = pseudonymizer.pseudonym(b"192.0.2.1");
+        assert_eq!(before, pseudonymizer.pseudonym(b"192.0.2.1"));
+        assert_ne!(before, pseudonymizer.pseudonym(b"192.0.2.2"));
+        assert!(!before.contains("192.0.2.1"));
+
+        *now.lock().unwrap() += Duration::HOUR;
+        assert_ne!(before, pseudonymizer.pseudonym(b"192.0.2.1"));
+    }
+}
@@ -0,0 +1,9 @@
+use std::net::SocketAddr;
+
+// Checks whether announced peers take connections, and records it with the
+// peer.
+pub trait Prober: Send + Sync {
+    // Returns at once. Whether and when the endpoint is probed is up to the
+    // prober, which may skip it to stay within its limits.
+    fn submit(&self, endpoint: SocketAddr);
+}
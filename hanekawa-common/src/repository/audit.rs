@@ -0,0 +1,37 @@
+use crate::types::AuditRecord;
+
+use super::Error;
+use time::OffsetDateTime;
+
+#[derive(Debug, Clone)]
+pub struct AppendAudit<'a> {
+    pub timestamp: OffsetDateTime,
+    pub token_id: Option<&'a str>,
+    pub action: &'a str,
+    pub target: &'a str,
+    pub outcome: &'a str,
+}
+
+#[derive(Debug, Clone)]
+pub struct GetAudit {
+    pub limit: usize,
+    // Only records older than this id, for paging.
+    pub before: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PurgeAudit {
+    pub before: OffsetDateTime,
+}
+
+// Records are only ever appended, and deleted once past their retention.
+#[async_trait::async_trait]
+pub trait AuditRepository: Send + Sync {
+    async fn append_audit(&self, cmd: AppendAudit<'_>) -> Result<(), Error>;
+
+    // Newest first.
+    async fn get_audit(&self, cmd: GetAudit) -> Result<Vec<AuditRecord>, Error>;
+
+    // Returns how many records were deleted.
+    async fn purge_audit(&self, cmd: PurgeAudit) -> Result<u64, Error>;
+}
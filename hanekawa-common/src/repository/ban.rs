@@ -0,0 +1,34 @@
+use crate::types::{Ban, BanTarget};
+
+use super::Error;
+use time::OffsetDateTime;
+
+#[derive(Debug, Clone)]
+pub struct AddBan<'a> {
+    pub target: &'a BanTarget,
+    pub reason: &'a str,
+    pub created: OffsetDateTime,
+    pub expires: Option<OffsetDateTime>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RemoveBan {
+    pub id: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct GetBans {
+    // Bans expired by then are left out.
+    pub active_at: OffsetDateTime,
+}
+
+#[async_trait::async_trait]
+pub trait BanRepository: Send + Sync {
+    // The new ban's id.
+    async fn add_ban(&self, cmd: AddBan<'_>) -> Result<u64, Error>;
+
+    // Whether there was a ban with that id.
+    async fn remove_ban(&self, cmd: RemoveBan) -> Result<bool, Error>;
+
+    async fn get_bans(&self, cmd: GetBans) -> Result<Vec<Ban>, Error>;
+}
@@ -1,4 +1,4 @@
-use crate::types::{InfoHash, InfoHashStatus, InfoHashSummary};
+use crate::types::{InfoHash, InfoHashStatus, InfoHashSummary, TorrentMetadata};
 
 use super::Error;
 
@@ -13,6 +13,13 @@ pub struct UpdateInfoHash<'a> {
     pub status: InfoHashStatus,
 }
 
+// Allows the info hash, with what its .torrent file says about it.
+#[derive(Debug, Clone)]
+pub struct RegisterTorrent<'a> {
+    pub info_hash: &'a InfoHash,
+    pub metadata: &'a TorrentMetadata,
+}
+
 #[async_trait::async_trait]
 pub trait InfoHashRepository: Send + Sync {
     async fn get_info_hash_summary(
@@ -21,4 +28,6 @@ pub trait InfoHashRepository: Send + Sync {
     ) -> Result<InfoHashSummary, Error>;
 
     async fn update_info_hash(&self, cmd: UpdateInfoHash<'_>) -> Result<(), Error>;
+
+    async fn register_torrent(&self, cmd: RegisterTorrent<'_>) -> Result<(), Error>;
 }
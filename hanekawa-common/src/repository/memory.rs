@@ -0,0 +1,897 @@
+// Swarms kept in process memory, for a single tracker that may lose them on
+// restart. Each shard of swarms has a lock of its own, so announces to
+// different torrents rarely wait on each other.
+
+use super::{
+    info_hash::{GetInfoHashSummary, InfoHashRepository, RegisterTorrent, UpdateInfoHash},
+    peer::{
+        CheckPeerKey, CountPeers, EvictStalestPeer, GetPeerStatistics, GetPeers, GetSwarmDetail,
+        IterIdleSwarms, IterSwarms, PeerCounts, PeerRepository, PurgeStalePeers, PurgeSwarm,
+        SetConnectable, UpdatePeerAnnounce,
+    },
+    Error,
+};
+use crate::types::{
+    Event, InfoHash, InfoHashStatus, InfoHashSummary, Peer, PeerId, PeerKey, PeerSource,
+    PeerStatistics, SwarmMember, SwarmSummary, TorrentMetadata, Transport,
+};
+
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    hash::{Hash, Hasher},
+    net::{IpAddr, SocketAddr},
+    sync::{Mutex, MutexGuard},
+};
+use time::OffsetDateTime;
+
+const SHARDS: usize = 64;
+
+// A peer as of its latest announce.
+#[derive(Debug, Clone)]
+pub struct StoredPeer {
+    // Where it announced from first, then its other address family's.
+    pub endpoints: Vec<Peer>,
+    pub uploaded: u64,
+    pub downloaded: u64,
+    pub left: u64,
+    pub event: Event,
+    pub transport: Option<Transport>,
+    pub announced: OffsetDateTime,
+    pub key: Option<PeerKey>,
+    // Whose passkey it last announced with.
+    pub user_id: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct Swarm {
+    pub peers: HashMap<PeerId, StoredPeer>,
+    // Those counted in `downloaded`.
+    pub completed: HashSet<PeerId>,
+    pub downloaded: u32,
+    // The latest announce, None once the swarm is purged.
+    pub last_activity: Option<OffsetDateTime>,
+}
+
+impl Swarm {
+    fn admits(&self, peer_id: &PeerId, ip: IpAddr, key: Option<&PeerKey>) -> bool {
+        self.peers.get(peer_id).is_none_or(|peer| {
+            peer.key.is_none() || peer.key.as_ref() == key || peer.endpoints[0].ip == ip
+        })
+    }
+
+    // Peers that announced after `active_after`, or all of them.
+    fn active(
+        &self,
+        active_after: Option<OffsetDateTime>,
+    ) -> impl Iterator<Item = (&PeerId, &StoredPeer)> {
+        self.peers
+            .iter()
+            .filter(move |(_, peer)| active_after.is_none_or(|after| peer.announced > after))
+    }
+
+    fn statistics(&self, active_after: OffsetDateTime) -> PeerStatistics {
+        let (mut complete, mut incomplete) = (0, 0);
+        for (_, peer) in self.active(Some(active_after)) {
+            match peer.left {
+                0 => complete += 1,
+                _ => incomplete += 1,
+            }
+        }
+
+        PeerStatistics {
+            complete,
+            downloaded: self.downloaded,
+            incomplete,
+        }
+    }
+
+    // Whether there is anything left to keep.
+    fn is_empty(&self) -> bool {
+        self.peers.is_empty() && self.downloaded == 0
+    }
+}
+
+type Swarms = HashMap<InfoHash, Swarm>;
+
+// When each peer last announced, by the address it announces from, so those
+// from an address are counted without going through every swarm.
+type ByIp = HashMap<IpAddr, HashMap<(InfoHash, PeerId), OffsetDateTime>>;
+
+pub struct MemoryStore {
+    shards: Vec<Mutex<Swarms>>,
+    info_hashes: Mutex<HashMap<InfoHash, (InfoHashStatus, Option<TorrentMetadata>)>>,
+    // Locked after the shard of the peer being kept or forgotten.
+    by_ip: Mutex<ByIp>,
+}
+
+impl Default for MemoryStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self {
+            shards: (0..SHARDS).map(|_| Mutex::default()).collect(),
+            info_hashes: Mutex::default(),
+            by_ip: Mutex::default(),
+        }
+    }
+
+    // Puts `peer` in the swarm, in place of what it was.
+    fn keep(&self, swarm: &mut Swarm, info_hash: &InfoHash, peer_id: &PeerId, peer: StoredPeer) {
+        let mut by_ip = self.by_ip.lock().unwrap_or_else(|e| e.into_inner());
+        let peers = by_ip.entry(peer.endpoints[0].ip).or_default();
+        peers.insert((info_hash.clone(), peer_id.clone()), peer.announced);
+        drop(by_ip);
+
+        if let Some(known) = swarm.peers.insert(peer_id.clone(), peer) {
+            if known.endpoints[0].ip != swarm.peers[peer_id].endpoints[0].ip {
+                self.forgotten(info_hash, peer_id, &known);
+            }
+        }
+    }
+
+    // Leaves a peer gone from its swarm out of its address's count.
+    fn forgotten(&self, info_hash: &InfoHash, peer_id: &PeerId, peer: &StoredPeer) {
+        let mut by_ip = self.by_ip.lock().unwrap_or_else(|e| e.into_inner());
+        let ip = peer.endpoints[0].ip;
+        if let Some(peers) = by_ip.get_mut(&ip) {
+            peers.remove(&(info_hash.clone(), peer_id.clone()));
+            if peers.is_empty() {
+                by_ip.remove(&ip);
+            }
+        }
+    }
+
+    fn shard(&self, info_hash: &InfoHash) -> MutexGuard<'_, Swarms> {
+        let mut hasher = DefaultHasher::new();
+        info_hash.hash(&mut hasher);
+        let shard = &self.shards[hasher.finish() as usize % SHARDS];
+        shard.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // One shard locked at a time, so a swarm is only ever seen whole.
+    fn each_shard(&self) -> impl Iterator<Item = MutexGuard<'_, Swarms>> {
+        self.shards
+            .iter()
+            .map(|shard| shard.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    // What `f` makes of the swarm, if there is one.
+    pub fn with_swarm<T>(&self, info_hash: &InfoHash, f: impl FnOnce(Option<&Swarm>) -> T) -> T {
+        f(self.shard(info_hash).get(info_hash))
+    }
+
+    // Every endpoint of the swarm's peers, active or not.
+    pub fn peers(&self, info_hash: &InfoHash) -> Vec<Peer> {
+        self.with_swarm(info_hash, |swarm| {
+            swarm
+                .map(|swarm| {
+                    let peers = swarm.peers.values();
+                    peers.flat_map(|peer| peer.endpoints.clone()).collect()
+                })
+                .unwrap_or_default()
+        })
+    }
+
+    // As if `peer` had announced at `at` with `left` to go.
+    pub fn seed(&self, info_hash: &InfoHash, peer: Peer, left: u64, at: OffsetDateTime) {
+        let mut shard = self.shard(info_hash);
+        let swarm = shard.entry(info_hash.clone()).or_default();
+        swarm.last_activity = Some(at);
+        let peer_id = peer.peer_id.clone();
+        let peer = StoredPeer {
+            endpoints: vec![peer],
+            uploaded: 0,
+            downloaded: 0,
+            left,
+            event: Event::Started,
+            transport: None,
+            announced: at,
+            key: None,
+            user_id: None,
+        };
+        self.keep(swarm, info_hash, &peer_id, peer);
+    }
+}
+
+// What a snapshot keeps of swarms and registered torrents, in bencode, which
+// has neither booleans nor nulls: fields left out are None, and flags are 0
+// or 1. Times are in nanoseconds since the epoch.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Snapshot {
+    swarms: Vec<SwarmSnapshot>,
+    torrents: Vec<TorrentSnapshot>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SwarmSnapshot {
+    info_hash: InfoHash,
+    peers: Vec<PeerSnapshot>,
+    completed: Vec<PeerId>,
+    downloaded: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_activity: Option<i64>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct PeerSnapshot {
+    peer_id: PeerId,
+    endpoints: Vec<EndpointSnapshot>,
+    uploaded: i64,
+    downloaded: i64,
+    left: i64,
+    event: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    transport: Option<String>,
+    announced: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    key: Option<PeerKey>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    user_id: Option<String>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct EndpointSnapshot {
+    ip: String,
+    port: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    connectable: Option<u8>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct TorrentSnapshot {
+    info_hash: InfoHash,
+    allowed: u8,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    size: Option<i64>,
+}
+
+fn nanos(at: OffsetDateTime) -> i64 {
+    at.unix_timestamp_nanos() as i64
+}
+
+fn from_nanos(nanos: i64) -> Option<OffsetDateTime> {
+    OffsetDateTime::from_unix_timestamp_nanos(nanos as i128).ok()
+}
+
+// Counters past what bencode holds are kept as its largest.
+fn clamped(n: u64) -> i64 {
+    n.min(i64::MAX as u64) as i64
+}
+
+impl StoredPeer {
+    fn snapshot(&self, peer_id: &PeerId) -> PeerSnapshot {
+        let endpoints = self.endpoints.iter().map(|endpoint| EndpointSnapshot {
+            ip: endpoint.ip.to_string(),
+            port: endpoint.port,
+            connectable: endpoint.connectable.map(u8::from),
+        });
+
+        PeerSnapshot {
+            peer_id: peer_id.clone(),
+            endpoints: endpoints.collect(),
+            uploaded: clamped(self.uploaded),
+            downloaded: clamped(self.downloaded),
+            left: clamped(self.left),
+            event: self.event.to_string(),
+            transport: self.transport.map(|t| t.as_str().to_string()),
+            announced: nanos(self.announced),
+            key: self.key.clone(),
+            user_id: self.user_id.clone(),
+        }
+    }
+
+    // None if any of it cannot be read back.
+    fn restore(snapshot: PeerSnapshot) -> Option<Self> {
+        let left = snapshot.left as u64;
+        let endpoints = snapshot.endpoints.into_iter().map(|endpoint| {
+            Some(Peer {
+                peer_id: snapshot.peer_id.clone(),
+                ip: endpoint.ip.parse().ok()?,
+                port: endpoint.port,
+                connectable: endpoint.connectable.map(|c| c == 1),
+                seeding: left == 0,
+                source: PeerSource::Announce,
+            })
+        });
+        let endpoints = endpoints.collect::<Option<Vec<_>>>()?;
+        if endpoints.is_empty() {
+            return None;
+        }
+        let transport = match snapshot.transport.as_deref() {
+            None => None,
+            Some("http") => Some(Transport::Http),
+            Some("udp") => Some(Transport::Udp),
+            Some(_) => return None,
+        };
+
+        Some(Self {
+            endpoints,
+            uploaded: snapshot.uploaded as u64,
+            downloaded: snapshot.downloaded as u64,
+            left,
+            event: match snapshot.event.as_str() {
+                "started" => Event::Started,
+                "completed" => Event::Completed,
+                "stopped" => Event::Stopped,
+                _ => Event::Interval,
+            },
+            transport,
+            announced: from_nanos(snapshot.announced)?,
+            key: snapshot.key,
+            user_id: snapshot.user_id,
+        })
+    }
+}
+
+impl MemoryStore {
+    // Every swarm and registered torrent, in bencode, for `restore` to read
+    // back into another store.
+    pub fn snapshot(&self) -> Vec<u8> {
+        let mut swarms = vec![];
+        for shard in self.each_shard() {
+            for (info_hash, swarm) in shard.iter() {
+                let peers = swarm.peers.iter();
+                swarms.push(SwarmSnapshot {
+                    info_hash: info_hash.clone(),
+                    peers: peers.map(|(id, peer)| peer.snapshot(id)).collect(),
+                    completed: swarm.completed.iter().cloned().collect(),
+                    downloaded: swarm.downloaded,
+                    last_activity: swarm.last_activity.map(nanos),
+                });
+            }
+        }
+
+        let info_hashes = self.info_hashes.lock().unwrap_or_else(|e| e.into_inner());
+        let torrents = info_hashes
+            .iter()
+            .map(|(info_hash, (status, metadata))| TorrentSnapshot {
+                info_hash: info_hash.clone(),
+                allowed: u8::from(*status == InfoHashStatus::ExplicitAllow),
+                name: metadata.as_ref().map(|m| m.name.clone()),
+                size: metadata.as_ref().map(|m| clamped(m.size)),
+            })
+            .collect();
+
+        let snapshot = Snapshot { swarms, torrents };
+        hanekawa_bencode::to_bytes(&snapshot).unwrap().to_vec()
+    }
+
+    // Adds what a snapshot kept, but for peers that did not announce after
+    // `active_after`, which have timed out since. Returns the peers
+    // restored.
+    pub fn restore(
+        &self,
+        bytes: &[u8],
+        active_after: OffsetDateTime,
+    ) -> Result<usize, hanekawa_bencode::DecodeError> {
+        let snapshot: Snapshot = hanekawa_bencode::from_bytes(bytes)?;
+
+        let mut restored = 0;
+        for swarm in snapshot.swarms {
+            let mut shard = self.shard(&swarm.info_hash);
+            let into = shard.entry(swarm.info_hash.clone()).or_default();
+            for peer in swarm.peers {
+                let peer_id = peer.peer_id.clone();
+                match StoredPeer::restore(peer) {
+                    Some(peer) if peer.announced > active_after => {
+                        self.keep(into, &swarm.info_hash, &peer_id, peer);
+                        restored += 1;
+                    }
+                    _ => {}
+                }
+            }
+            into.completed.extend(swarm.completed);
+            into.downloaded = into.downloaded.max(swarm.downloaded);
+            into.last_activity = into
+                .last_activity
+                .max(swarm.last_activity.and_then(from_nanos));
+        }
+
+        let mut info_hashes = self.info_hashes.lock().unwrap_or_else(|e| e.into_inner());
+        for torrent in snapshot.torrents {
+            let status = match torrent.allowed {
+                1 => InfoHashStatus::ExplicitAllow,
+                _ => InfoHashStatus::ExplicitDeny,
+            };
+            let metadata = torrent.name.map(|name| TorrentMetadata {
+                name,
+                size: torrent.size.unwrap_or_default() as u64,
+            });
+            info_hashes.insert(torrent.info_hash, (status, metadata));
+        }
+
+        Ok(restored)
+    }
+}
+
+#[async_trait::async_trait]
+impl PeerRepository for MemoryStore {
+    async fn update_peer_announce(&self, cmd: &UpdatePeerAnnounce) -> Result<(), Error> {
+        let mut shard = self.shard(&cmd.info_hash);
+        let swarm = shard.entry(cmd.info_hash.clone()).or_default();
+        if !swarm.admits(&cmd.peer_id, cmd.ip, cmd.key.as_ref()) {
+            return Ok(());
+        }
+        swarm.last_activity = Some(cmd.update_timestamp);
+
+        if cmd.event == Event::Stopped {
+            // It is still known to have completed.
+            if let Some(known) = swarm.peers.remove(&cmd.peer_id) {
+                self.forgotten(&cmd.info_hash, &cmd.peer_id, &known);
+            }
+            if swarm.is_empty() {
+                shard.remove(&cmd.info_hash);
+            }
+            return Ok(());
+        }
+        if cmd.event == Event::Completed && swarm.completed.insert(cmd.peer_id.clone()) {
+            swarm.downloaded += 1;
+        }
+
+        let known = swarm.peers.get(&cmd.peer_id);
+        let key = cmd
+            .key
+            .clone()
+            .or_else(|| known.and_then(|p| p.key.clone()));
+        // Probed endpoints stay so while the peer announces them.
+        let connectable = |ip, port| {
+            let endpoints = &known?.endpoints;
+            endpoints
+                .iter()
+                .find(|p: &&Peer| p.ip == ip && p.port == port)?
+                .connectable
+        };
+        let endpoints = std::iter::once((cmd.ip, cmd.port))
+            .chain(cmd.other_endpoint.map(|e| (e.ip(), e.port())))
+            .map(|(ip, port)| Peer {
+                peer_id: cmd.peer_id.clone(),
+                ip,
+                port,
+                connectable: connectable(ip, port),
+                seeding: cmd.left == 0,
+                source: PeerSource::Announce,
+            })
+            .collect();
+        let peer = StoredPeer {
+            endpoints,
+            uploaded: cmd.uploaded,
+            downloaded: cmd.downloaded,
+            left: cmd.left,
+            event: cmd.event.clone(),
+            transport: cmd.transport,
+            announced: cmd.update_timestamp,
+            key,
+            user_id: cmd.user_id.clone(),
+        };
+        self.keep(swarm, &cmd.info_hash, &cmd.peer_id, peer);
+
+        Ok(())
+    }
+
+    async fn check_peer_key(&self, cmd: CheckPeerKey<'_>) -> Result<bool, Error> {
+        Ok(self.with_swarm(cmd.info_hash, |swarm| {
+            swarm.is_none_or(|swarm| swarm.admits(cmd.peer_id, cmd.ip, cmd.key))
+        }))
+    }
+
+    async fn count_peers(&self, cmd: CountPeers<'_>) -> Result<Option<PeerCounts>, Error> {
+        let active_after = Some(cmd.active_after);
+        let (known, in_swarm) = self.with_swarm(cmd.info_hash, |swarm| {
+            let known = swarm.is_some_and(|swarm| swarm.peers.contains_key(cmd.peer_id));
+            let in_swarm = swarm.map_or(0, |swarm| swarm.active(active_after).count());
+            (known, in_swarm as u32)
+        });
+        let from_ip = cmd.ip.map_or(0, |ip| {
+            let by_ip = self.by_ip.lock().unwrap_or_else(|e| e.into_inner());
+            let peers = by_ip.get(&ip).into_iter().flat_map(|peers| peers.values());
+            peers.filter(|at| **at > cmd.active_after).count()
+        });
+
+        Ok(Some(PeerCounts {
+            known,
+            in_swarm,
+            from_ip: from_ip as u32,
+        }))
+    }
+
+    async fn evict_stalest_peer(&self, cmd: EvictStalestPeer<'_>) -> Result<(), Error> {
+        let mut shard = self.shard(cmd.info_hash);
+        let Some(swarm) = shard.get_mut(cmd.info_hash) else {
+            return Ok(());
+        };
+        let stalest = swarm
+            .active(Some(cmd.active_after))
+            .min_by_key(|(_, peer)| peer.announced)
+            .map(|(peer_id, _)| peer_id.clone());
+        if let Some(peer_id) = stalest {
+            let known = swarm.peers.remove(&peer_id).unwrap();
+            self.forgotten(cmd.info_hash, &peer_id, &known);
+        }
+
+        Ok(())
+    }
+
+    async fn get_peers(&self, cmd: GetPeers<'_>) -> Result<Vec<Peer>, Error> {
+        Ok(self.with_swarm(cmd.info_hash, |swarm| {
+            swarm
+                .map(|swarm| {
+                    let active = swarm.active(cmd.active_after);
+                    active.flat_map(|(_, p)| p.endpoints.clone()).collect()
+                })
+                .unwrap_or_default()
+        }))
+    }
+
+    async fn get_peer_statistics(
+        &self,
+        cmd: GetPeerStatistics<'_>,
+    ) -> Result<HashMap<InfoHash, PeerStatistics>, Error> {
+        Ok(cmd
+            .info_hashes
+            .iter()
+            .filter_map(|info_hash| {
+                let statistics = self.with_swarm(info_hash, |swarm| {
+                    swarm.map(|swarm| swarm.statistics(cmd.active_after))
+                })?;
+                Some((info_hash.clone(), statistics))
+            })
+            .collect())
+    }
+
+    async fn iter_swarms(&self, cmd: IterSwarms) -> Result<Vec<SwarmSummary>, Error> {
+        let mut swarms = vec![];
+        for shard in self.each_shard() {
+            swarms.extend(shard.iter().filter_map(|(info_hash, swarm)| {
+                let active = swarm.active(Some(cmd.active_after));
+                let last_activity = active.map(|(_, peer)| peer.announced).max()?;
+                Some(SwarmSummary {
+                    info_hash: info_hash.clone(),
+                    statistics: swarm.statistics(cmd.active_after),
+                    last_activity,
+                })
+            }));
+        }
+
+        Ok(swarms)
+    }
+
+    async fn iter_idle_swarms(
+        &self,
+        cmd: IterIdleSwarms,
+    ) -> Result<HashMap<InfoHash, OffsetDateTime>, Error> {
+        let mut idle = HashMap::new();
+        for shard in self.each_shard() {
+            idle.extend(shard.iter().filter_map(|(info_hash, swarm)| {
+                let last_activity = swarm.last_activity?;
+                let idle = swarm.active(Some(cmd.idle_since)).next().is_none();
+                idle.then(|| (info_hash.clone(), last_activity))
+            }));
+        }
+
+        Ok(idle)
+    }
+
+    async fn get_swarm_detail(&self, cmd: GetSwarmDetail<'_>) -> Result<Vec<SwarmMember>, Error> {
+        Ok(self.with_swarm(cmd.info_hash, |swarm| {
+            let Some(swarm) = swarm else {
+                return vec![];
+            };
+            swarm
+                .active(Some(cmd.active_after))
+                .map(|(peer_id, peer)| SwarmMember {
+                    peer_id: peer_id.clone(),
+                    ip: peer.endpoints[0].ip,
+                    port: peer.endpoints[0].port,
+                    other_endpoint: peer.endpoints.get(1).map(|p| SocketAddr::new(p.ip, p.port)),
+                    uploaded: peer.uploaded,
+                    downloaded: peer.downloaded,
+                    left: peer.left,
+                    event: peer.event.clone(),
+                    last_announce: peer.announced,
+                    transport: peer.transport,
+                    connectable: peer.endpoints[0].connectable,
+                })
+                .collect()
+        }))
+    }
+
+    // The swarm's completed count outlives it.
+    async fn purge_swarm(&self, cmd: PurgeSwarm<'_>) -> Result<(), Error> {
+        let mut shard = self.shard(cmd.info_hash);
+        if let Some(swarm) = shard.get_mut(cmd.info_hash) {
+            for (peer_id, peer) in swarm.peers.drain() {
+                self.forgotten(cmd.info_hash, &peer_id, &peer);
+            }
+            swarm.last_activity = None;
+            if swarm.is_empty() {
+                shard.remove(cmd.info_hash);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn purge_stale_peers(&self, cmd: PurgeStalePeers) -> Result<u64, Error> {
+        let mut purged = 0;
+        for mut shard in self.each_shard() {
+            shard.retain(|info_hash, swarm| {
+                let announcing = swarm.active(Some(cmd.inactive_since)).next().is_some();
+                let before = swarm.peers.len();
+                swarm.peers.retain(|peer_id, peer| {
+                    let keep = match announcing {
+                        true => peer.announced > cmd.inactive_since,
+                        false => peer.announced >= cmd.cutoff,
+                    };
+                    if !keep {
+                        self.forgotten(info_hash, peer_id, peer);
+                    }
+                    keep
+                });
+                purged += (before - swarm.peers.len()) as u64;
+                !swarm.is_empty()
+            });
+        }
+
+        Ok(purged)
+    }
+
+    async fn set_connectable(&self, cmd: SetConnectable) -> Result<(), Error> {
+        let (ip, port) = (cmd.endpoint.ip(), cmd.endpoint.port());
+        for mut shard in self.each_shard() {
+            let peers = shard
+                .values_mut()
+                .flat_map(|swarm| swarm.peers.values_mut());
+            for endpoint in peers.flat_map(|peer| peer.endpoints.iter_mut()) {
+                if endpoint.ip == ip && endpoint.port == port {
+                    endpoint.connectable = Some(cmd.connectable);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl InfoHashRepository for MemoryStore {
+    async fn get_info_hash_summary(
+        &self,
+        cmd: GetInfoHashSummary<'_>,
+    ) -> Result<InfoHashSummary, Error> {
+        let (status, metadata) = self
+            .info_hashes
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(cmd.info_hash)
+            .cloned()
+            .unwrap_or((InfoHashStatus::Unknown, None));
+
+        Ok(InfoHashSummary {
+            info_hash: cmd.info_hash.clone(),
+            status,
+            metadata,
+        })
+    }
+
+    async fn update_info_hash(&self, cmd: UpdateInfoHash<'_>) -> Result<(), Error> {
+        let mut info_hashes = self.info_hashes.lock().unwrap_or_else(|e| e.into_inner());
+        match cmd.status {
+            InfoHashStatus::Unknown => info_hashes.remove(cmd.info_hash),
+            status => info_hashes.insert(cmd.info_hash.clone(), (status, None)),
+        };
+
+        Ok(())
+    }
+
+    async fn register_torrent(&self, cmd: RegisterTorrent<'_>) -> Result<(), Error> {
+        self.info_hashes
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(
+                cmd.info_hash.clone(),
+                (InfoHashStatus::ExplicitAllow, Some(cmd.metadata.clone())),
+            );
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use futures::executor::block_on;
+    use std::net::Ipv4Addr;
+    use time::Duration;
+
+    fn announce(
+        info_hash: u8,
+        peer_id: u8,
+        event: Event,
+        at: OffsetDateTime,
+    ) -> UpdatePeerAnnounce {
+        UpdatePeerAnnounce {
+            info_hash: InfoHash(vec![info_hash; 20]),
+            peer_id: PeerId(vec![peer_id; 20]),
+            ip: IpAddr::V4(Ipv4Addr::new(10, 0, 0, peer_id)),
+            port: 6881,
+            uploaded: 0,
+            downloaded: 0,
+            left: 100,
+            event,
+            update_timestamp: at,
+            other_endpoint: None,
+            transport: None,
+            user_id: None,
+            key: None,
+        }
+    }
+
+    #[test]
+    fn keeps_swarms_apart() {
+        let store = MemoryStore::new();
+        let now = OffsetDateTime::now_utc();
+        for info_hash in 0..=255 {
+            let cmd = announce(info_hash, 1, Event::Started, now);
+            block_on(store.update_peer_announce(&cmd)).unwrap();
+        }
+        let cmd = announce(7, 2, Event::Started, now);
+        block_on(store.update_peer_announce(&cmd)).unwrap();
+
+        let swarms = block_on(store.iter_swarms(IterSwarms {
+            active_after: now - Duration::MINUTE,
+        }))
+        .unwrap();
+        assert_eq!(256, swarms.len());
+        let peers = block_on(store.get_peers(GetPeers {
+            info_hash: &InfoHash(vec![7; 20]),
+            active_after: None,
+        }))
+        .unwrap();
+        assert_eq!(2, peers.len());
+    }
+
+    #[test]
+    fn keeps_the_completed_count_of_purged_swarms() {
+        let store = MemoryStore::new();
+        let now = OffsetDateTime::now_utc();
+        let info_hash = InfoHash(vec![1; 20]);
+        let cmd = announce(1, 1, Event::Completed, now);
+        block_on(store.update_peer_announce(&cmd)).unwrap();
+
+        let idle_since = now + Duration::MINUTE;
+        let idle = block_on(store.iter_idle_swarms(IterIdleSwarms { idle_since })).unwrap();
+        assert_eq!(Some(&now), idle.get(&info_hash));
+        block_on(store.purge_swarm(PurgeSwarm {
+            info_hash: &info_hash,
+        }))
+        .unwrap();
+
+        let idle = block_on(store.iter_idle_swarms(IterIdleSwarms { idle_since })).unwrap();
+        assert!(idle.is_empty());
+        let stats = block_on(store.get_peer_statistics(GetPeerStatistics {
+            info_hashes: std::slice::from_ref(&info_hash),
+            active_after: now,
+        }))
+        .unwrap();
+        assert_eq!(1, stats[&info_hash].downloaded);
+        assert_eq!(0, stats[&info_hash].incomplete);
+    }
+
+    #[test]
+    fn counts_peers_from_an_address_as_they_come_and_go() {
+        let store = MemoryStore::new();
+        let now = OffsetDateTime::now_utc();
+        let ip = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+        let from_ip = |ip, active_after| {
+            let cmd = CountPeers {
+                info_hash: &InfoHash(vec![9; 20]),
+                peer_id: &PeerId(vec![9; 20]),
+                ip,
+                active_after,
+            };
+            block_on(store.count_peers(cmd)).unwrap().unwrap().from_ip
+        };
+        for info_hash in 1..=4 {
+            let cmd = announce(info_hash, 1, Event::Started, now - Duration::MINUTE);
+            block_on(store.update_peer_announce(&cmd)).unwrap();
+        }
+        assert_eq!(4, from_ip(Some(ip), now - Duration::HOUR));
+        assert_eq!(0, from_ip(None, now - Duration::HOUR));
+
+        // Stopped, moved elsewhere, or purged with its swarm, and only the
+        // latest announce of the one left counts.
+        block_on(store.update_peer_announce(&announce(1, 1, Event::Stopped, now))).unwrap();
+        let mut moved = announce(2, 1, Event::Interval, now);
+        moved.ip = IpAddr::V4(Ipv4Addr::new(10, 0, 1, 1));
+        block_on(store.update_peer_announce(&moved)).unwrap();
+        block_on(store.purge_swarm(PurgeSwarm {
+            info_hash: &InfoHash(vec![3; 20]),
+        }))
+        .unwrap();
+        block_on(store.update_peer_announce(&announce(4, 1, Event::Interval, now))).unwrap();
+        assert_eq!(1, from_ip(Some(ip), now - Duration::SECOND));
+        assert_eq!(1, from_ip(Some(moved.ip), now - Duration::SECOND));
+    }
+
+    #[test]
+    fn counts_a_peer_that_stopped_and_completed_again_once() {
+        let store = MemoryStore::new();
+        let now = OffsetDateTime::now_utc();
+        let info_hash = InfoHash(vec![1; 20]);
+        for event in [Event::Completed, Event::Stopped, Event::Completed] {
+            let cmd = announce(1, 1, event, now);
+            block_on(store.update_peer_announce(&cmd)).unwrap();
+        }
+
+        let stats = block_on(store.get_peer_statistics(GetPeerStatistics {
+            info_hashes: std::slice::from_ref(&info_hash),
+            active_after: now - Duration::MINUTE,
+        }))
+        .unwrap();
+        assert_eq!(1, stats[&info_hash].downloaded);
+    }
+
+    #[test]
+    fn restores_snapshots_but_for_timed_out_peers() {
+        let store = MemoryStore::new();
+        let now = OffsetDateTime::now_utc();
+        for cmd in [
+            announce(1, 1, Event::Completed, now),
+            announce(1, 2, Event::Started, now - Duration::HOUR),
+            announce(2, 3, Event::Started, now),
+        ] {
+            block_on(store.update_peer_announce(&cmd)).unwrap();
+        }
+        let metadata = TorrentMetadata {
+            name: "fixture.txt".to_string(),
+            size: 14,
+        };
+        let cmd = RegisterTorrent {
+            info_hash: &InfoHash(vec![3; 20]),
+            metadata: &metadata,
+        };
+        block_on(store.register_torrent(cmd)).unwrap();
+
+        let restored = MemoryStore::new();
+        let active_after = now - Duration::MINUTE;
+        assert_eq!(
+            2,
+            restored.restore(&store.snapshot(), active_after).unwrap()
+        );
+
+        for info_hash in [1, 2] {
+            let info_hash = InfoHash(vec![info_hash; 20]);
+            let cmd = GetPeers {
+                info_hash: &info_hash,
+                active_after: None,
+            };
+            let restored = block_on(restored.get_peers(cmd.clone())).unwrap();
+            let active = block_on(store.get_peers(GetPeers {
+                active_after: Some(active_after),
+                ..cmd
+            }))
+            .unwrap();
+            assert_eq!(active, restored);
+        }
+        let cmd = GetPeerStatistics {
+            info_hashes: &[InfoHash(vec![1; 20])],
+            active_after,
+        };
+        let stats = block_on(restored.get_peer_statistics(cmd)).unwrap();
+        assert_eq!(1, stats[&InfoHash(vec![1; 20])].downloaded);
+        let summary = block_on(restored.get_info_hash_summary(GetInfoHashSummary {
+            info_hash: &InfoHash(vec![3; 20]),
+        }))
+        .unwrap();
+        assert_eq!(Some(metadata), summary.metadata);
+
+        assert!(restored.restore(b"not a snapshot", active_after).is_err());
+    }
+}
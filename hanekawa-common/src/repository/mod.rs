@@ -1,5 +1,22 @@
+pub mod audit;
+pub mod ban;
 pub mod info_hash;
+pub mod memory;
+pub mod passkey;
 pub mod peer;
 
-#[derive(Debug)]
-pub enum Error {}
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    // The store could not be reached, or failed the query.
+    Backend(String),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Backend(s) => f.write_fmt(format_args!("storage failed: {s}")),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
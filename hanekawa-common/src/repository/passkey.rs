@@ -0,0 +1,25 @@
+use crate::types::Passkey;
+
+use super::Error;
+use time::OffsetDateTime;
+
+#[derive(Debug, Clone)]
+pub struct AddPasskey<'a> {
+    pub passkey: &'a Passkey,
+    // When the user's other passkeys stop working, unless they expire
+    // sooner.
+    pub retire_others_at: OffsetDateTime,
+}
+
+#[derive(Debug, Clone)]
+pub struct GetPasskeys {
+    // Passkeys expired by then are left out.
+    pub active_at: OffsetDateTime,
+}
+
+#[async_trait::async_trait]
+pub trait PasskeyRepository: Send + Sync {
+    async fn add_passkey(&self, cmd: AddPasskey<'_>) -> Result<(), Error>;
+
+    async fn get_passkeys(&self, cmd: GetPasskeys) -> Result<Vec<Passkey>, Error>;
+}
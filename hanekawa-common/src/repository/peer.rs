@@ -1,6 +1,10 @@
-use crate::types::{Event, InfoHash, Peer, PeerId, PeerStatistics};
+use crate::types::{
+    Event, InfoHash, InfoHashStatus, Peer, PeerId, PeerKey, PeerStatistics, SwarmMember,
+    SwarmSummary, Transport,
+};
+use std::cmp::{Ordering, Reverse};
 use std::collections::HashMap;
-use std::net::IpAddr;
+use std::net::{IpAddr, SocketAddr};
 
 use time::OffsetDateTime;
 
@@ -17,6 +21,57 @@ pub struct UpdatePeerAnnounce {
     pub left: u64,
     pub event: Event,
     pub update_timestamp: OffsetDateTime,
+    // BEP 7: where the peer listens in the other address family, if it said.
+    #[serde(default)]
+    pub other_endpoint: Option<SocketAddr>,
+    #[serde(default)]
+    pub transport: Option<Transport>,
+    // Whose passkey it was announced with, if passkeys are required. What
+    // it uploaded and downloaded since its last announce is theirs.
+    #[serde(default)]
+    pub user_id: Option<String>,
+    // Kept with the peer, so that only announces with it move the peer to
+    // another address or take it out of the swarm.
+    #[serde(default)]
+    pub key: Option<PeerKey>,
+}
+
+// Whether an announce from `ip` may stand for the peer: it has no key, the
+// announce has its key, or it comes from where the peer already is.
+#[derive(Debug, Clone)]
+pub struct CheckPeerKey<'a> {
+    pub info_hash: &'a InfoHash,
+    pub peer_id: &'a PeerId,
+    pub ip: IpAddr,
+    pub key: Option<&'a PeerKey>,
+}
+
+// What an announce for the peer from `ip` would add to, counting peers that
+// announced after `active_after`.
+#[derive(Debug, Clone)]
+pub struct CountPeers<'a> {
+    pub info_hash: &'a InfoHash,
+    pub peer_id: &'a PeerId,
+    // The address to count peers from, if they are to be.
+    pub ip: Option<IpAddr>,
+    pub active_after: OffsetDateTime,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PeerCounts {
+    // Whether the peer is already kept, so that it adds nothing.
+    pub known: bool,
+    pub in_swarm: u32,
+    // In any swarm, 0 unless asked for.
+    pub from_ip: u32,
+}
+
+// Of the peers that announced after `active_after`, the one that did so
+// longest ago.
+#[derive(Debug, Clone)]
+pub struct EvictStalestPeer<'a> {
+    pub info_hash: &'a InfoHash,
+    pub active_after: OffsetDateTime,
 }
 
 #[derive(Debug, Clone)]
@@ -31,12 +86,195 @@ pub struct GetPeerStatistics<'a> {
     pub active_after: OffsetDateTime,
 }
 
+// Every swarm with a peer active since `active_after`.
+#[derive(Debug, Clone)]
+pub struct IterSwarms {
+    pub active_after: OffsetDateTime,
+}
+
+// Every swarm with no peer announcing after `idle_since`.
+#[derive(Debug, Clone)]
+pub struct IterIdleSwarms {
+    pub idle_since: OffsetDateTime,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SwarmOrder {
+    // Most seeders and leechers first.
+    #[default]
+    Peers,
+    // Most recently announced first.
+    Activity,
+}
+
+// Where a swarm is in either order. Ties are broken by info hash, so no two
+// swarms are in the same place.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SwarmPosition {
+    pub peers: u32,
+    pub last_activity: OffsetDateTime,
+    pub info_hash: InfoHash,
+}
+
+impl SwarmPosition {
+    pub fn of(swarm: &SwarmSummary) -> Self {
+        Self {
+            peers: swarm.statistics.complete + swarm.statistics.incomplete,
+            last_activity: swarm.last_activity,
+            info_hash: swarm.info_hash.clone(),
+        }
+    }
+}
+
+impl SwarmOrder {
+    pub fn compare(self, a: &SwarmPosition, b: &SwarmPosition) -> Ordering {
+        match self {
+            Self::Peers => (Reverse(a.peers), Reverse(a.last_activity), &a.info_hash.0).cmp(&(
+                Reverse(b.peers),
+                Reverse(b.last_activity),
+                &b.info_hash.0,
+            )),
+            Self::Activity => (Reverse(a.last_activity), &a.info_hash.0)
+                .cmp(&(Reverse(b.last_activity), &b.info_hash.0)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SwarmFilter {
+    pub min_peers: Option<u32>,
+    // Announced to since.
+    pub active_since: Option<OffsetDateTime>,
+    // Of the info hash in lowercase hex, of any length.
+    pub info_hash_prefix: Option<String>,
+    // Left to the caller by backends that do not store it with the peers.
+    pub status: Option<InfoHashStatus>,
+}
+
+impl SwarmFilter {
+    // Everything but the status.
+    pub fn matches(&self, swarm: &SwarmSummary) -> bool {
+        let peers = swarm.statistics.complete + swarm.statistics.incomplete;
+
+        self.min_peers.is_none_or(|min| peers >= min)
+            && self
+                .active_since
+                .is_none_or(|since| swarm.last_activity >= since)
+            && self
+                .info_hash_prefix
+                .as_ref()
+                .is_none_or(|prefix| swarm.info_hash.to_hex().starts_with(prefix.as_str()))
+    }
+}
+
+// Up to `limit` of the swarms `IterSwarms` would give that match `filter`,
+// in `order` from just after `after`.
+#[derive(Debug, Clone)]
+pub struct PageSwarms<'a> {
+    pub active_after: OffsetDateTime,
+    pub filter: &'a SwarmFilter,
+    pub order: SwarmOrder,
+    pub after: Option<&'a SwarmPosition>,
+    pub limit: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct GetSwarmDetail<'a> {
+    pub info_hash: &'a InfoHash,
+    pub active_after: OffsetDateTime,
+}
+
+#[derive(Debug, Clone)]
+pub struct PurgeSwarm<'a> {
+    pub info_hash: &'a InfoHash,
+}
+
+// Every peer whose latest announce is older than `before`, in any swarm.
+#[derive(Debug, Clone)]
+pub struct PurgePeers {
+    pub before: OffsetDateTime,
+}
+
+// Peers whose latest announce is older than `inactive_since`, in swarms
+// with others still announcing, and those older than `cutoff` in any.
+#[derive(Debug, Clone)]
+pub struct PurgeStalePeers {
+    pub inactive_since: OffsetDateTime,
+    pub cutoff: OffsetDateTime,
+}
+
+// For every swarm the peer announced this endpoint in.
+#[derive(Debug, Clone)]
+pub struct SetConnectable {
+    pub endpoint: SocketAddr,
+    pub connectable: bool,
+}
+
 #[async_trait::async_trait]
 pub trait PeerRepository: Send + Sync {
     async fn update_peer_announce(&self, cmd: &UpdatePeerAnnounce) -> Result<(), Error>;
+    // Backends that keep no keys take any announce.
+    async fn check_peer_key(&self, _cmd: CheckPeerKey<'_>) -> Result<bool, Error> {
+        Ok(true)
+    }
+    // None from backends that cannot count, which leaves announces
+    // unlimited.
+    async fn count_peers(&self, _cmd: CountPeers<'_>) -> Result<Option<PeerCounts>, Error> {
+        Ok(None)
+    }
+    async fn evict_stalest_peer(&self, _cmd: EvictStalestPeer<'_>) -> Result<(), Error> {
+        Ok(())
+    }
     async fn get_peers(&self, cmd: GetPeers<'_>) -> Result<Vec<Peer>, Error>;
     async fn get_peer_statistics(
         &self,
         cmd: GetPeerStatistics<'_>,
     ) -> Result<HashMap<InfoHash, PeerStatistics>, Error>;
+    async fn iter_swarms(&self, cmd: IterSwarms) -> Result<Vec<SwarmSummary>, Error>;
+    // Goes through every swarm, for backends that cannot filter and page in
+    // their queries.
+    async fn page_swarms(&self, cmd: PageSwarms<'_>) -> Result<Vec<SwarmSummary>, Error> {
+        let swarms = self
+            .iter_swarms(IterSwarms {
+                active_after: cmd.active_after,
+            })
+            .await?;
+
+        let mut page = swarms
+            .into_iter()
+            .filter(|swarm| cmd.filter.matches(swarm))
+            .map(|swarm| (SwarmPosition::of(&swarm), swarm))
+            .filter(|(position, _)| {
+                cmd.after
+                    .is_none_or(|after| cmd.order.compare(after, position).is_lt())
+            })
+            .collect::<Vec<_>>();
+        page.sort_by(|(a, _), (b, _)| cmd.order.compare(a, b));
+        page.truncate(cmd.limit);
+
+        Ok(page.into_iter().map(|(_, swarm)| swarm).collect())
+    }
+    // Each idle swarm's latest announce. Backends that cannot tell have
+    // none, and keep every swarm.
+    async fn iter_idle_swarms(
+        &self,
+        _cmd: IterIdleSwarms,
+    ) -> Result<HashMap<InfoHash, OffsetDateTime>, Error> {
+        Ok(HashMap::new())
+    }
+    async fn get_swarm_detail(&self, cmd: GetSwarmDetail<'_>) -> Result<Vec<SwarmMember>, Error>;
+    // Forgets every peer of the swarm.
+    async fn purge_swarm(&self, cmd: PurgeSwarm<'_>) -> Result<(), Error>;
+    // Returns how many announces were deleted. Backends that keep no
+    // history have none to.
+    async fn purge_peers(&self, _cmd: PurgePeers) -> Result<u64, Error> {
+        Ok(0)
+    }
+    // Returns how many peers were forgotten. Swarms with none left
+    // announcing are kept whole until the cutoff, for the grace period to
+    // start from their last announce.
+    async fn purge_stale_peers(&self, _cmd: PurgeStalePeers) -> Result<u64, Error> {
+        Ok(0)
+    }
+    async fn set_connectable(&self, cmd: SetConnectable) -> Result<(), Error>;
 }
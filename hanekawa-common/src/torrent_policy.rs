@@ -0,0 +1,244 @@
+use crate::{
+    metainfo::{Metainfo, MetainfoError},
+    types::InfoHash,
+    TorrentPolicyConfig, TorrentPolicyMode,
+};
+
+use std::{
+    collections::HashSet,
+    io,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+#[derive(Debug)]
+pub enum PolicyError {
+    Read(PathBuf, io::Error),
+    // The line, counting from 1.
+    InvalidInfoHash(PathBuf, usize),
+    InvalidTorrent(PathBuf, MetainfoError),
+}
+
+impl std::fmt::Display for PolicyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Read(path, e) => f.write_fmt(format_args!("cannot read {}: {e}", path.display())),
+            Self::InvalidInfoHash(path, line) => f.write_fmt(format_args!(
+                "{}:{line} is not a hex-encoded info hash",
+                path.display()
+            )),
+            Self::InvalidTorrent(path, e) => {
+                f.write_fmt(format_args!("{} is not a torrent: {e}", path.display()))
+            }
+        }
+    }
+}
+
+impl std::error::Error for PolicyError {}
+
+// Which torrents announces and scrapes are answered for. The list is read
+// when loaded and again on each reload, which every clone sees.
+#[derive(Clone)]
+pub struct TorrentPolicy {
+    config: TorrentPolicyConfig,
+    listed: Arc<RwLock<HashSet<InfoHash>>>,
+}
+
+impl TorrentPolicy {
+    // Any torrent is served.
+    pub fn open() -> Self {
+        Self {
+            config: TorrentPolicyConfig::default(),
+            listed: Arc::default(),
+        }
+    }
+
+    pub fn load(config: &TorrentPolicyConfig) -> Result<Self, PolicyError> {
+        Ok(Self {
+            config: config.clone(),
+            listed: Arc::new(RwLock::new(read(config)?)),
+        })
+    }
+
+    // How many torrents are listed now. If the list cannot be read, the one
+    // before it is kept.
+    pub fn reload(&self) -> Result<usize, PolicyError> {
+        let listed = read(&self.config)?;
+        let count = listed.len();
+        *self.listed.write().unwrap_or_else(|e| e.into_inner()) = listed;
+
+        Ok(count)
+    }
+
+    pub fn allows(&self, info_hash: &InfoHash) -> bool {
+        let listed = || {
+            self.listed
+                .read()
+                .unwrap_or_else(|e| e.into_inner())
+                .contains(info_hash)
+        };
+
+        match self.config.mode {
+            TorrentPolicyMode::Open => true,
+            TorrentPolicyMode::Allowlist => listed(),
+            TorrentPolicyMode::Denylist => !listed(),
+        }
+    }
+}
+
+fn read(config: &TorrentPolicyConfig) -> Result<HashSet<InfoHash>, PolicyError> {
+    let mut listed = HashSet::new();
+    if config.mode == TorrentPolicyMode::Open {
+        return Ok(listed);
+    }
+
+    if let Some(path) = &config.file {
+        listed.extend(read_file(path)?);
+    }
+    if let Some(dir) = &config.torrents_dir {
+        listed.extend(read_dir(dir)?);
+    }
+
+    Ok(listed)
+}
+
+// Blank lines and those starting with `#` are skipped.
+fn read_file(path: &Path) -> Result<Vec<InfoHash>, PolicyError> {
+    let contents =
+        std::fs::read_to_string(path).map_err(|e| PolicyError::Read(path.to_path_buf(), e))?;
+
+    contents
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(number, line)| match hex::decode(line) {
+            Ok(bytes) if bytes.len() == 20 => Ok(InfoHash(bytes)),
+            _ => Err(PolicyError::InvalidInfoHash(path.to_path_buf(), number)),
+        })
+        .collect()
+}
+
+// Both info hashes of hybrid torrents.
+fn read_dir(dir: &Path) -> Result<Vec<InfoHash>, PolicyError> {
+    let read_error = |path: &Path| {
+        let path = path.to_path_buf();
+        move |e| PolicyError::Read(path, e)
+    };
+
+    let mut info_hashes = vec![];
+    for entry in std::fs::read_dir(dir).map_err(read_error(dir))? {
+        let path = entry.map_err(read_error(dir))?.path();
+        if path.extension().is_none_or(|ext| ext != "torrent") {
+            continue;
+        }
+
+        let bytes = std::fs::read(&path).map_err(read_error(&path))?;
+        let metainfo =
+            Metainfo::from_bytes(&bytes).map_err(|e| PolicyError::InvalidTorrent(path, e))?;
+        info_hashes.extend(metainfo.info_hashes().cloned());
+    }
+
+    Ok(info_hashes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use sha1::{Digest, Sha1};
+
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            let name = format!("hanekawa-policy-{:016x}", rand::random::<u64>());
+            let dir = std::env::temp_dir().join(name);
+            std::fs::create_dir(&dir).unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn config(mode: TorrentPolicyMode, dir: &TempDir) -> TorrentPolicyConfig {
+        TorrentPolicyConfig {
+            mode,
+            file: Some(dir.0.join("info_hashes")),
+            torrents_dir: Some(dir.0.join("torrents")),
+        }
+    }
+
+    #[test]
+    fn lists_info_hashes_and_torrents() {
+        let dir = TempDir::new();
+        let listed = InfoHash(vec![0xaa; 20]);
+        let other = InfoHash(vec![0xbb; 20]);
+        std::fs::write(
+            dir.0.join("info_hashes"),
+            format!("# published\n\n  {}\n", listed.to_hex()),
+        )
+        .unwrap();
+
+        let info = b"d6:lengthi1e4:name1:a12:piece lengthi16384e6:pieces20:aaaaaaaaaaaaaaaaaaaae";
+        let mut torrent = b"d4:info".to_vec();
+        torrent.extend_from_slice(info);
+        torrent.push(b'e');
+        std::fs::create_dir(dir.0.join("torrents")).unwrap();
+        std::fs::write(dir.0.join("torrents/a.torrent"), torrent).unwrap();
+        std::fs::write(dir.0.join("torrents/README"), "not a torrent").unwrap();
+        let from_torrent = InfoHash(Sha1::digest(info).to_vec());
+
+        let allowlist = TorrentPolicy::load(&config(TorrentPolicyMode::Allowlist, &dir)).unwrap();
+        assert!(allowlist.allows(&listed));
+        assert!(allowlist.allows(&from_torrent));
+        assert!(!allowlist.allows(&other));
+
+        let denylist = TorrentPolicy::load(&config(TorrentPolicyMode::Denylist, &dir)).unwrap();
+        assert!(!denylist.allows(&listed));
+        assert!(denylist.allows(&other));
+
+        // The list is not even read when open.
+        let open = TorrentPolicy::load(&TorrentPolicyConfig {
+            file: Some(dir.0.join("missing")),
+            ..TorrentPolicyConfig::default()
+        })
+        .unwrap();
+        assert!(open.allows(&listed) && open.allows(&other));
+        assert!(TorrentPolicy::open().allows(&other));
+    }
+
+    #[test]
+    fn reloads_or_keeps_what_it_had() {
+        let dir = TempDir::new();
+        let config = TorrentPolicyConfig {
+            torrents_dir: None,
+            ..config(TorrentPolicyMode::Allowlist, &dir)
+        };
+        let (a, b) = (InfoHash(vec![0xaa; 20]), InfoHash(vec![0xbb; 20]));
+        let path = dir.0.join("info_hashes");
+        std::fs::write(&path, a.to_hex()).unwrap();
+        let policy = TorrentPolicy::load(&config).unwrap();
+        let clone = policy.clone();
+
+        std::fs::write(&path, format!("{}\n{}\n", a.to_hex(), b.to_hex())).unwrap();
+        assert!(!clone.allows(&b));
+        assert_eq!(2, policy.reload().unwrap());
+        assert!(clone.allows(&a) && clone.allows(&b));
+
+        std::fs::write(&path, format!("{}\nabc\n", a.to_hex())).unwrap();
+        assert!(matches!(
+            policy.reload(),
+            Err(PolicyError::InvalidInfoHash(_, 2))
+        ));
+        assert!(clone.allows(&b));
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(policy.reload(), Err(PolicyError::Read(..))));
+        assert!(TorrentPolicy::load(&config).is_err());
+    }
+}
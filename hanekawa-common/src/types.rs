@@ -1,9 +1,107 @@
-use std::net::IpAddr;
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
+
+use time::OffsetDateTime;
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
 #[serde(transparent)]
 pub struct PeerId(#[serde(with = "serde_bytes")] pub Vec<u8>);
 
+// The `key` a peer announces with, which only it and the tracker know. It
+// is never shown, even in logs.
+#[derive(Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(transparent)]
+pub struct PeerKey(pub String);
+
+impl std::fmt::Debug for PeerKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("PeerKey(..)")
+    }
+}
+
+// The `-XX0000-` prefix of Azureus-style peer ids.
+const PREFIX_LEN: usize = 8;
+const PEER_ID_LEN: usize = 20;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PeerIdError {
+    InvalidPrefix(String),
+}
+
+impl std::fmt::Display for PeerIdError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidPrefix(p) => f.write_fmt(format_args!(
+                "peer id prefix {p:?} is not of the form -XX0000-"
+            )),
+        }
+    }
+}
+
+impl std::error::Error for PeerIdError {}
+
+// The client and version named by an Azureus-style peer id.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerClient {
+    pub id: String,
+    pub version: String,
+}
+
+fn parse_prefix(prefix: &[u8]) -> Option<PeerClient> {
+    match prefix {
+        [b'-', id @ .., b'-']
+            if prefix.len() == PREFIX_LEN && id.iter().all(u8::is_ascii_alphanumeric) =>
+        {
+            let (id, version) = id.split_at(2);
+
+            Some(PeerClient {
+                id: String::from_utf8_lossy(id).into_owned(),
+                version: String::from_utf8_lossy(version).into_owned(),
+            })
+        }
+        _ => None,
+    }
+}
+
+impl PeerId {
+    // An Azureus-style id, `client_prefix` followed by 12 random bytes.
+    pub fn generate(client_prefix: &str) -> Result<Self, PeerIdError> {
+        Self::generate_with(client_prefix, &mut rand::thread_rng())
+    }
+
+    pub fn generate_with(
+        client_prefix: &str,
+        rng: &mut impl rand::Rng,
+    ) -> Result<Self, PeerIdError> {
+        if parse_prefix(client_prefix.as_bytes()).is_none() {
+            return Err(PeerIdError::InvalidPrefix(client_prefix.to_string()));
+        }
+
+        let mut id = client_prefix.as_bytes().to_vec();
+        id.resize(PEER_ID_LEN, 0);
+        rng.fill(&mut id[PREFIX_LEN..]);
+
+        Ok(Self(id))
+    }
+
+    // None for ids that are not Azureus-style.
+    pub fn client(&self) -> Option<PeerClient> {
+        if self.0.len() != PEER_ID_LEN {
+            return None;
+        }
+
+        parse_prefix(&self.0[..PREFIX_LEN])
+    }
+
+    // `-qB4650-` say, of Azureus-style ids.
+    pub fn client_prefix(&self) -> Option<&str> {
+        self.client()?;
+        std::str::from_utf8(&self.0[..PREFIX_LEN]).ok()
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
 #[serde(transparent)]
 pub struct InfoHash(#[serde(with = "serde_bytes")] pub Vec<u8>);
@@ -45,21 +143,246 @@ impl ToString for Event {
     }
 }
 
-#[derive(serde::Serialize, Debug, PartialEq)]
+#[derive(serde::Serialize, Debug, Clone, PartialEq)]
 pub struct Peer {
     #[serde(rename = "peer id")]
     pub peer_id: PeerId,
     pub ip: IpAddr,
     pub port: u16,
+    // Unknown until probed, and never sent to other peers.
+    #[serde(skip)]
+    pub connectable: Option<bool>,
+    // Had nothing left as of its last announce. Peers from upstream trackers
+    // are taken for leechers, as they do not say.
+    #[serde(skip)]
+    pub seeding: bool,
+    #[serde(skip)]
+    pub source: PeerSource,
 }
 
-#[derive(Debug, Clone, serde::Serialize)]
+// Where a peer handed out was learned of.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum PeerSource {
+    #[default]
+    Announce,
+    // From the upstream tracker at this url, in federation.
+    Upstream(Arc<str>),
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize)]
 pub struct PeerStatistics {
     pub complete: u32,
     pub downloaded: u32,
     pub incomplete: u32,
 }
 
+// How a peer reached the tracker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    Http,
+    Udp,
+}
+
+impl Transport {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Http => "http",
+            Self::Udp => "udp",
+        }
+    }
+}
+
+// A swarm with its peers counted, as of its latest announce.
+#[derive(Debug, Clone)]
+pub struct SwarmSummary {
+    pub info_hash: InfoHash,
+    pub statistics: PeerStatistics,
+    pub last_activity: OffsetDateTime,
+}
+
+// A peer as of its latest announce.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SwarmMember {
+    pub peer_id: PeerId,
+    pub ip: IpAddr,
+    pub port: u16,
+    pub other_endpoint: Option<SocketAddr>,
+    pub uploaded: u64,
+    pub downloaded: u64,
+    pub left: u64,
+    pub event: Event,
+    pub last_announce: OffsetDateTime,
+    // Unknown for announces recorded before it was.
+    pub transport: Option<Transport>,
+    // Unknown until probed.
+    pub connectable: Option<bool>,
+}
+
+// An address block, e.g. `192.0.2.0/24`, with the host bits cleared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    pub fn new(addr: IpAddr, prefix: u8) -> Option<Self> {
+        let bits = if addr.is_ipv4() { 32 } else { 128 };
+        if prefix > bits {
+            return None;
+        }
+
+        let addr = match addr {
+            IpAddr::V4(a) => IpAddr::from((u32::from(a) & mask(prefix, 32) as u32).to_be_bytes()),
+            IpAddr::V6(a) => IpAddr::from((u128::from(a) & mask(prefix, 128)).to_be_bytes()),
+        };
+
+        Some(Self { addr, prefix })
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                u32::from(ip) & mask(self.prefix, 32) as u32 == u32::from(net)
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                u128::from(ip) & mask(self.prefix, 128) == u128::from(net)
+            }
+            _ => false,
+        }
+    }
+}
+
+// The top `prefix` bits of a `bits` wide address.
+fn mask(prefix: u8, bits: u32) -> u128 {
+    match u32::from(prefix) {
+        0 => 0,
+        p => (u128::MAX >> (128 - bits)) & !((1_u128 << (bits - p)) - 1),
+    }
+}
+
+impl std::str::FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid address block: {s}");
+
+        let (addr, prefix) = s.split_once('/').ok_or_else(invalid)?;
+        let addr = addr.parse().map_err(|_| invalid())?;
+        let prefix = prefix.parse().map_err(|_| invalid())?;
+
+        Self::new(addr, prefix).ok_or_else(invalid)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Cidr {
+    fn deserialize<D: serde::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        <String as serde::Deserialize>::deserialize(d)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+impl std::fmt::Display for Cidr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BanTarget {
+    Ip(IpAddr),
+    Cidr(Cidr),
+    PeerId(PeerId),
+    Passkey(String),
+}
+
+impl BanTarget {
+    // From a kind and a value as `kind()` and `value()` give them, with peer
+    // ids in hex.
+    pub fn parse(kind: &str, value: &str) -> Result<Self, String> {
+        match kind {
+            "ip" => value
+                .parse()
+                .map(Self::Ip)
+                .map_err(|_| format!("invalid ip: {value}")),
+            "cidr" => value.parse().map(Self::Cidr),
+            "peer_id" => match hex::decode(value) {
+                Ok(id) if id.len() == 20 => Ok(Self::PeerId(PeerId(id))),
+                _ => Err(format!("invalid peer id: {value}")),
+            },
+            "passkey" if !value.is_empty() => Ok(Self::Passkey(value.to_string())),
+            "passkey" => Err("empty passkey".to_string()),
+            _ => Err(format!("unknown ban target: {kind}")),
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Ip(_) => "ip",
+            Self::Cidr(_) => "cidr",
+            Self::PeerId(_) => "peer_id",
+            Self::Passkey(_) => "passkey",
+        }
+    }
+
+    pub fn value(&self) -> String {
+        match self {
+            Self::Ip(ip) => ip.to_string(),
+            Self::Cidr(cidr) => cidr.to_string(),
+            Self::PeerId(id) => hex::encode(&id.0),
+            Self::Passkey(passkey) => passkey.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ban {
+    pub id: u64,
+    pub target: BanTarget,
+    pub reason: String,
+    pub created: OffsetDateTime,
+    // Never, if not set.
+    pub expires: Option<OffsetDateTime>,
+}
+
+impl Ban {
+    pub fn is_active(&self, now: OffsetDateTime) -> bool {
+        self.expires.is_none_or(|expires| now < expires)
+    }
+}
+
+// What a user announces with on a private tracker. Those rotated away from
+// keep working until they expire.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Passkey {
+    pub passkey: String,
+    pub user_id: String,
+    pub created: OffsetDateTime,
+    // Never, if not set.
+    pub expires: Option<OffsetDateTime>,
+}
+
+impl Passkey {
+    pub fn is_active(&self, now: OffsetDateTime) -> bool {
+        self.expires.is_none_or(|expires| now < expires)
+    }
+}
+
+// A change made through the admin API.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditRecord {
+    pub id: u64,
+    pub timestamp: OffsetDateTime,
+    // Anonymous if the admin API takes no tokens.
+    pub token_id: Option<String>,
+    pub action: String,
+    pub target: String,
+    // `ok`, or what went wrong.
+    pub outcome: String,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum InfoHashStatus {
     Unknown,
@@ -71,4 +394,84 @@ pub enum InfoHashStatus {
 pub struct InfoHashSummary {
     pub info_hash: InfoHash,
     pub status: InfoHashStatus,
+    // Known only for torrents registered from their .torrent file.
+    pub metadata: Option<TorrentMetadata>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TorrentMetadata {
+    pub name: String,
+    pub size: u64,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn generated_ids_name_their_client() {
+        let id = PeerId::generate("-HK0100-").unwrap();
+
+        assert_eq!(20, id.0.len());
+        assert_eq!(
+            Some(PeerClient {
+                id: "HK".to_string(),
+                version: "0100".to_string(),
+            }),
+            id.client()
+        );
+    }
+
+    #[test]
+    fn seeded_ids_are_reproducible() {
+        let generate = |seed| PeerId::generate_with("-TR2940-", &mut StdRng::seed_from_u64(seed));
+
+        assert_eq!(generate(7), generate(7));
+        assert_ne!(generate(7), generate(8));
+    }
+
+    #[test]
+    fn rejects_malformed_prefixes() {
+        for prefix in [
+            "",
+            "-HK0100",
+            "HK0100--",
+            "-HK01000-",
+            "-HK 100-",
+            "-HK01-0-",
+        ] {
+            assert_eq!(
+                Err(PeerIdError::InvalidPrefix(prefix.to_string())),
+                PeerId::generate(prefix)
+            );
+        }
+    }
+
+    #[test]
+    fn matches_addresses_in_blocks() {
+        let v4: Cidr = "192.0.2.77/24".parse().unwrap();
+        assert_eq!("192.0.2.0/24", v4.to_string());
+        assert!(v4.contains("192.0.2.1".parse().unwrap()));
+        assert!(!v4.contains("192.0.3.1".parse().unwrap()));
+        assert!(!v4.contains("::ffff:192.0.2.1".parse().unwrap()));
+
+        let v6: Cidr = "2001:db8::/32".parse().unwrap();
+        assert!(v6.contains("2001:db8:1::1".parse().unwrap()));
+        assert!(!v6.contains("2001:db9::1".parse().unwrap()));
+
+        let everything: Cidr = "0.0.0.0/0".parse().unwrap();
+        assert!(everything.contains("203.0.113.9".parse().unwrap()));
+
+        for invalid in ["192.0.2.0", "192.0.2.0/33", "::/129", "x/8"] {
+            assert!(invalid.parse::<Cidr>().is_err(), "{invalid} parsed");
+        }
+    }
+
+    #[test]
+    fn ignores_other_id_styles() {
+        assert_eq!(None, PeerId(b"M7-2-2--abcdefghijkl".to_vec()).client());
+        assert_eq!(None, PeerId(b"-HK0100-".to_vec()).client());
+    }
 }
@@ -44,14 +44,18 @@ impl<'a> Parts<'a> {
 
         let mut map = HashMap::new();
 
-        let parts = query_string.split('&').map(|param| {
-            let (key, value) = param
-                .split_once('=')
-                .ok_or(Error::custom("missing parameter value"))?;
-            let key = percent_encoding::percent_decode_str(key).decode_utf8_lossy();
-            let value: Cow<'_, [u8]> = percent_encoding::percent_decode_str(value).into();
-            Ok((key, value))
-        });
+        // Empty parameters, as in an empty query string, are skipped.
+        let parts = query_string
+            .split('&')
+            .filter(|p| !p.is_empty())
+            .map(|param| {
+                let (key, value) = param
+                    .split_once('=')
+                    .ok_or(Error::custom("missing parameter value"))?;
+                let key = percent_encoding::percent_decode_str(key).decode_utf8_lossy();
+                let value: Cow<'_, [u8]> = percent_encoding::percent_decode_str(value).into();
+                Ok((key, value))
+            });
 
         // Propagate first error if any
         let parts: Vec<_> = parts.collect::<Result<_, _>>()?;
@@ -185,7 +189,9 @@ macro_rules! parse_from_str {
 
                 match s.parse::<$ty>() {
                     Ok(val) => val.into_deserializer().$method(visitor),
-                    Err(e) => Err(Error::custom(e))
+                    Err(e) => Err(Error::custom(format_args!(
+                        "{:?} is not a {}: {}", s, stringify!($ty), e
+                    )))
                 }
             }
         )*
@@ -362,6 +368,20 @@ pub fn from_query_string<'q, T: serde::Deserialize<'q>>(
 mod test {
     use super::*;
 
+    #[test]
+    fn accepts_empty_query_strings() {
+        #[derive(Debug, serde::Deserialize, PartialEq)]
+        struct Page {
+            offset: Option<usize>,
+        }
+
+        assert_eq!(Page { offset: None }, from_query_string("").unwrap());
+        assert_eq!(
+            Page { offset: Some(2) },
+            from_query_string("&offset=2&").unwrap()
+        );
+    }
+
     #[test]
     fn deserializes() {
         #[derive(Debug, serde::Deserialize, PartialEq)]
@@ -408,6 +428,21 @@ mod test {
         )
     }
 
+    #[test]
+    fn says_which_value_does_not_parse() {
+        #[derive(Debug, serde::Deserialize)]
+        #[allow(dead_code)]
+        struct Query {
+            left: u64,
+        }
+
+        let error = from_query_string::<Query>("left=lots").unwrap_err();
+        assert_eq!(
+            "\"lots\" is not a u64: invalid digit found in string",
+            error.to_string()
+        );
+    }
+
     #[test]
     fn treats_single_params_as_seqs_if_requested() {
         #[derive(Debug, serde::Deserialize, PartialEq)]
@@ -0,0 +1,115 @@
+// Drives UDP announces at a running tracker over loopback and reports the
+// achieved reply rate.
+//
+// Compare a server started with `HKW_UDP_SOCKET_COUNT=1` against one started
+// with e.g. `HKW_UDP_SOCKET_COUNT=4` to see how the receive path scales:
+//
+//     cargo run --release --example udp_flood -- 127.0.0.1:8002 64 10
+
+use bytes::{BufMut, BytesMut};
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tokio::net::UdpSocket;
+
+fn announce_packet(client: u32, transaction_id: i32) -> BytesMut {
+    let mut buf = BytesMut::with_capacity(98);
+
+    let mut peer_id = [0; 20];
+    peer_id[..4].copy_from_slice(&client.to_be_bytes());
+
+    buf.put_i64(0);
+    buf.put_i32(1);
+    buf.put_i32(transaction_id);
+    buf.put_slice(&[0xab; 20]);
+    buf.put_slice(&peer_id);
+    buf.put_i64(0);
+    buf.put_i64(100);
+    buf.put_i64(0);
+    buf.put_i32(0);
+    buf.put_i32(0);
+    buf.put_i32(0);
+    buf.put_i32(-1);
+    buf.put_u16(6881);
+
+    buf
+}
+
+async fn client(
+    id: u32,
+    target: SocketAddr,
+    deadline: Instant,
+    replies: Arc<AtomicU64>,
+    timeouts: Arc<AtomicU64>,
+) {
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    socket.connect(target).await.unwrap();
+
+    let mut buf = [0; 1500];
+    let mut transaction_id = 0;
+
+    while Instant::now() < deadline {
+        transaction_id += 1;
+        socket
+            .send(&announce_packet(id, transaction_id))
+            .await
+            .unwrap();
+
+        match tokio::time::timeout(Duration::from_secs(1), socket.recv(&mut buf)).await {
+            Ok(Ok(_)) => replies.fetch_add(1, Ordering::Relaxed),
+            _ => timeouts.fetch_add(1, Ordering::Relaxed),
+        };
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let mut args = std::env::args().skip(1);
+
+    let target: SocketAddr = args
+        .next()
+        .unwrap_or_else(|| "127.0.0.1:8002".to_string())
+        .parse()
+        .expect("invalid target address");
+    let clients: u32 = args
+        .next()
+        .map_or(64, |s| s.parse().expect("invalid client count"));
+    let seconds: u64 = args
+        .next()
+        .map_or(10, |s| s.parse().expect("invalid duration"));
+
+    let replies = Arc::new(AtomicU64::new(0));
+    let timeouts = Arc::new(AtomicU64::new(0));
+
+    let start = Instant::now();
+    let deadline = start + Duration::from_secs(seconds);
+
+    let handles: Vec<_> = (0..clients)
+        .map(|id| {
+            tokio::spawn(client(
+                id,
+                target,
+                deadline,
+                replies.clone(),
+                timeouts.clone(),
+            ))
+        })
+        .collect();
+
+    for handle in handles {
+        handle.await.unwrap();
+    }
+
+    let elapsed = start.elapsed().as_secs_f64();
+    let replies = replies.load(Ordering::Relaxed);
+
+    println!("clients:   {}", clients);
+    println!("replies:   {}", replies);
+    println!("timeouts:  {}", timeouts.load(Ordering::Relaxed));
+    println!("rate:      {:.0} replies/s", replies as f64 / elapsed);
+}
@@ -1,35 +1,93 @@
-use hanekawa::admin::{AdminService, Error, KnownInfoHashRequest};
-use hanekawa_common::Config;
+use hanekawa::admin::{
+    AddBanRequest, AdminService, Caller, Error, KnownInfoHashRequest, ListAuditRequest,
+    ListTorrentsRequest,
+};
+use hanekawa_common::repository::peer::{SwarmFilter, SwarmOrder};
+use hanekawa_common::{AdminScope, Config, Services, StatsVisibility};
 
 use crate::http::extractor::Query;
 
-use axum::routing::{delete, post};
+use axum::routing::{delete, get, post, put};
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
+    body::Bytes,
+    extract::{DefaultBodyLimit, Path, State},
+    http::{header::AUTHORIZATION, Method, Request, StatusCode},
+    middleware::{self, Next},
+    response::Response,
+    Extension, Json,
 };
 use axum::{response::IntoResponse, Router};
 use hanekawa_common::types::InfoHashStatus;
+use std::sync::Arc;
+use time::OffsetDateTime;
+
+// Well above the largest .torrent files in the wild.
+const MAX_TORRENT_SIZE: usize = 16 * 1024 * 1024;
 
 #[derive(Debug, serde::Deserialize)]
 struct UpdateParams {
     allowed: bool,
 }
 
+#[derive(Debug, serde::Deserialize)]
+struct ListParams {
+    // `peers` or `activity`.
+    sort: Option<String>,
+    cursor: Option<String>,
+    limit: Option<usize>,
+    min_peers: Option<u32>,
+    // Unix time.
+    active_since: Option<i64>,
+    info_hash_prefix: Option<String>,
+    // `default`, `allowed` or `denied`.
+    policy: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct AuditParams {
+    limit: Option<usize>,
+    before: Option<u64>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct BanBody {
+    kind: String,
+    value: String,
+    reason: String,
+    // In seconds.
+    expires_in: Option<u64>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct MaintenanceBody {
+    enabled: bool,
+}
+
+fn status(e: Error) -> StatusCode {
+    match e {
+        Error::NotAllowed => StatusCode::NOT_FOUND,
+        Error::InvalidInfoHash(_) => StatusCode::BAD_REQUEST,
+        Error::InvalidTorrent(_) => StatusCode::BAD_REQUEST,
+        Error::InvalidBan(_) => StatusCode::BAD_REQUEST,
+        Error::UnknownBan(_) => StatusCode::NOT_FOUND,
+        Error::InvalidCursor => StatusCode::BAD_REQUEST,
+    }
+}
+
 async fn delete_info_hash(
     Path(hex_info_hash): Path<String>,
     State(admin): State<AdminService>,
+    Extension(caller): Extension<Caller>,
 ) -> impl IntoResponse {
-    let result = admin
-        .known_info_hash_command(KnownInfoHashRequest {
-            hex_info_hash,
-            action: InfoHashStatus::Unknown,
-        })
-        .await;
+    let request = KnownInfoHashRequest {
+        hex_info_hash,
+        action: InfoHashStatus::Unknown,
+    };
+    let result = admin.known_info_hash_command(request, &caller).await;
 
     match result {
         Ok(_) => StatusCode::OK,
-        Err(Error::NotAllowed) => StatusCode::NOT_FOUND,
+        Err(e) => status(e),
     }
 }
 
@@ -37,32 +95,905 @@ async fn update_info_hash(
     Path(hex_info_hash): Path<String>,
     Query(params): Query<UpdateParams>,
     State(admin): State<AdminService>,
+    Extension(caller): Extension<Caller>,
 ) -> impl IntoResponse {
+    let request = KnownInfoHashRequest {
+        hex_info_hash,
+        action: if params.allowed {
+            InfoHashStatus::ExplicitAllow
+        } else {
+            InfoHashStatus::ExplicitDeny
+        },
+    };
+    let result = admin.known_info_hash_command(request, &caller).await;
+
+    match result {
+        Ok(_) => StatusCode::OK,
+        Err(e) => status(e),
+    }
+}
+
+async fn list_torrents(
+    Query(params): Query<ListParams>,
+    State(admin): State<AdminService>,
+) -> Response {
+    let order = match params.sort.as_deref() {
+        None | Some("peers") => SwarmOrder::Peers,
+        Some("activity") => SwarmOrder::Activity,
+        Some(_) => return StatusCode::BAD_REQUEST.into_response(),
+    };
+    let policy = match params.policy.as_deref() {
+        None => None,
+        Some("default") => Some(InfoHashStatus::Unknown),
+        Some("allowed") => Some(InfoHashStatus::ExplicitAllow),
+        Some("denied") => Some(InfoHashStatus::ExplicitDeny),
+        Some(_) => return StatusCode::BAD_REQUEST.into_response(),
+    };
+    let active_since = match params.active_since.map(OffsetDateTime::from_unix_timestamp) {
+        None => None,
+        Some(Ok(since)) => Some(since),
+        Some(Err(_)) => return StatusCode::BAD_REQUEST.into_response(),
+    };
+
     let result = admin
-        .known_info_hash_command(KnownInfoHashRequest {
-            hex_info_hash,
-            action: if params.allowed {
-                InfoHashStatus::ExplicitAllow
-            } else {
-                InfoHashStatus::ExplicitDeny
+        .list_torrents(ListTorrentsRequest {
+            order,
+            filter: SwarmFilter {
+                min_peers: params.min_peers,
+                active_since,
+                info_hash_prefix: params.info_hash_prefix,
+                status: policy,
             },
+            cursor: params.cursor,
+            limit: params.limit,
         })
         .await;
 
     match result {
+        Ok(list) => Json(list).into_response(),
+        Err(e) => status(e).into_response(),
+    }
+}
+
+async fn register_torrent(
+    Path(hex_info_hash): Path<String>,
+    State(admin): State<AdminService>,
+    Extension(caller): Extension<Caller>,
+) -> impl IntoResponse {
+    match admin.register_torrent(&hex_info_hash, &caller).await {
+        Ok(_) => StatusCode::OK,
+        Err(e) => status(e),
+    }
+}
+
+// The body is the .torrent file as is.
+async fn upload_torrent(
+    State(admin): State<AdminService>,
+    Extension(caller): Extension<Caller>,
+    torrent: Bytes,
+) -> Response {
+    match admin.upload_torrent(&torrent, &caller).await {
+        Ok(registered) => (StatusCode::CREATED, Json(registered)).into_response(),
+        Err(e) => status(e).into_response(),
+    }
+}
+
+async fn remove_torrent(
+    Path(hex_info_hash): Path<String>,
+    State(admin): State<AdminService>,
+    Extension(caller): Extension<Caller>,
+) -> impl IntoResponse {
+    match admin.remove_torrent(&hex_info_hash, &caller).await {
+        Ok(_) => StatusCode::OK,
+        Err(e) => status(e),
+    }
+}
+
+async fn list_peers(
+    Path(hex_info_hash): Path<String>,
+    State(admin): State<AdminService>,
+) -> Response {
+    match admin.list_peers(&hex_info_hash).await {
+        Ok(peers) => Json(peers).into_response(),
+        Err(e) => status(e).into_response(),
+    }
+}
+
+async fn list_clients(
+    Path(hex_info_hash): Path<String>,
+    State(admin): State<AdminService>,
+) -> Response {
+    match admin.list_clients(&hex_info_hash).await {
+        Ok(clients) => Json(clients).into_response(),
+        Err(e) => status(e).into_response(),
+    }
+}
+
+async fn add_ban(
+    State(admin): State<AdminService>,
+    Extension(caller): Extension<Caller>,
+    Json(body): Json<BanBody>,
+) -> Response {
+    let request = AddBanRequest {
+        kind: body.kind,
+        value: body.value,
+        reason: body.reason,
+        expires_in: body.expires_in.map(std::time::Duration::from_secs),
+    };
+    let result = admin.add_ban(request, &caller).await;
+
+    match result {
+        Ok(ban) => (StatusCode::CREATED, Json(ban)).into_response(),
+        Err(e) => status(e).into_response(),
+    }
+}
+
+async fn remove_ban(
+    Path(id): Path<u64>,
+    State(admin): State<AdminService>,
+    Extension(caller): Extension<Caller>,
+) -> impl IntoResponse {
+    match admin.remove_ban(id, &caller).await {
         Ok(_) => StatusCode::OK,
-        Err(Error::NotAllowed) => StatusCode::NOT_FOUND,
+        Err(e) => status(e),
+    }
+}
+
+async fn list_bans(State(admin): State<AdminService>) -> Response {
+    match admin.list_bans() {
+        Ok(bans) => Json(bans).into_response(),
+        Err(e) => status(e).into_response(),
+    }
+}
+
+async fn list_timeouts(State(admin): State<AdminService>) -> Response {
+    match admin.list_timeouts() {
+        Ok(timeouts) => Json(timeouts).into_response(),
+        Err(e) => status(e).into_response(),
+    }
+}
+
+async fn maintenance(State(admin): State<AdminService>) -> Response {
+    match admin.maintenance() {
+        Ok(maintenance) => Json(maintenance).into_response(),
+        Err(e) => status(e).into_response(),
+    }
+}
+
+async fn set_maintenance(
+    State(admin): State<AdminService>,
+    Extension(caller): Extension<Caller>,
+    Json(body): Json<MaintenanceBody>,
+) -> Response {
+    match admin.set_maintenance(body.enabled, &caller).await {
+        Ok(maintenance) => Json(maintenance).into_response(),
+        Err(e) => status(e).into_response(),
+    }
+}
+
+async fn rotate_passkey(
+    Path(user_id): Path<String>,
+    State(admin): State<AdminService>,
+    Extension(caller): Extension<Caller>,
+) -> Response {
+    match admin.rotate_passkey(&user_id, &caller).await {
+        Ok(passkey) => Json(passkey).into_response(),
+        Err(e) => status(e).into_response(),
+    }
+}
+
+async fn revoke_passkey(
+    Path(user_id): Path<String>,
+    State(admin): State<AdminService>,
+    Extension(caller): Extension<Caller>,
+) -> Response {
+    match admin.revoke_passkey(&user_id, &caller).await {
+        Ok(passkey) => Json(passkey).into_response(),
+        Err(e) => status(e).into_response(),
+    }
+}
+
+async fn list_audit(
+    Query(params): Query<AuditParams>,
+    State(admin): State<AdminService>,
+) -> Response {
+    let result = admin
+        .list_audit(ListAuditRequest {
+            limit: params.limit,
+            before: params.before,
+        })
+        .await;
+
+    match result {
+        Ok(entries) => Json(entries).into_response(),
+        Err(e) => status(e).into_response(),
+    }
+}
+
+struct Token {
+    id: String,
+    secret: String,
+    scope: AdminScope,
+}
+
+fn refuse(status: StatusCode, error: &str) -> Response {
+    (status, Json(serde_json::json!({ "error": error }))).into_response()
+}
+
+// Reading takes any token, changing anything a token with the `mutate` scope.
+// Without tokens, which the server refuses to start with, nothing changes.
+async fn authenticate<B>(
+    State(tokens): State<Arc<[Token]>>,
+    mut request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let reading = matches!(*request.method(), Method::GET | Method::HEAD);
+    if tokens.is_empty() {
+        if !reading {
+            return refuse(StatusCode::FORBIDDEN, "no token may make changes");
+        }
+        request.extensions_mut().insert(Caller::default());
+        return next.run(request).await;
+    }
+
+    let bearer = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .unwrap_or_default();
+
+    // Every token is compared in full whatever differs, so timing tells
+    // nothing about any of them.
+    let mut found = None;
+    for token in tokens.iter() {
+        let matches = bearer.len() == token.secret.len()
+            && bearer
+                .bytes()
+                .zip(token.secret.bytes())
+                .fold(0, |diff, (a, b)| diff | (a ^ b))
+                == 0;
+        if matches {
+            found = Some(token);
+        }
+    }
+
+    let Some(token) = found else {
+        return refuse(StatusCode::UNAUTHORIZED, "missing or unknown token");
+    };
+    if !reading && token.scope != AdminScope::Mutate {
+        return refuse(StatusCode::FORBIDDEN, "token may not make changes");
     }
+
+    request.extensions_mut().insert(Caller {
+        token_id: Some(token.id.clone()),
+    });
+    next.run(request).await
 }
 
-pub async fn admin<S>(cfg: &Config) -> Router<S> {
-    let storage = hanekawa_storage::Services::start(cfg).await;
+pub async fn admin<S>(cfg: &Config, services: &Services) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    let admin = AdminService::new(cfg, services);
 
-    let ir = std::sync::Arc::new(storage.info_hash);
-    let admin = AdminService::new(cfg, ir);
+    let tokens = cfg
+        .admin_token
+        .iter()
+        .map(|secret| Token {
+            id: "admin".to_string(),
+            secret: secret.clone(),
+            scope: AdminScope::Mutate,
+        })
+        .chain(cfg.admin_tokens.iter().map(|token| Token {
+            id: token.id.clone(),
+            secret: token.token.clone(),
+            scope: token.scope,
+        }))
+        .collect::<Arc<[Token]>>();
 
-    Router::new()
+    let mut router = Router::new()
         .route("/info_hashes/:info_hash", delete(delete_info_hash))
         .route("/info_hashes/:info_hash", post(update_info_hash))
-        .with_state(admin)
+        .route(
+            "/torrents",
+            get(list_torrents)
+                .post(upload_torrent)
+                .layer(DefaultBodyLimit::max(MAX_TORRENT_SIZE)),
+        )
+        .route(
+            "/torrents/:info_hash",
+            put(register_torrent).delete(remove_torrent),
+        )
+        .route("/torrents/:info_hash/peers", get(list_peers))
+        .route("/torrents/:info_hash/clients", get(list_clients))
+        .route("/bans", get(list_bans).post(add_ban))
+        .route("/bans/:id", delete(remove_ban))
+        .route("/timeouts", get(list_timeouts))
+        .route("/maintenance", get(maintenance).put(set_maintenance))
+        .route("/users/:id/passkey/rotate", post(rotate_passkey))
+        .route("/users/:id/passkey/revoke", post(revoke_passkey))
+        .route("/audit", get(list_audit))
+        .with_state(admin);
+    if cfg.enable_admin_api && cfg.torrent_stats == StatsVisibility::Admin {
+        router = router.merge(crate::stats::stats(cfg, services.clone()));
+    }
+
+    router.route_layer(middleware::from_fn_with_state(tokens, authenticate))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use axum::http::header::CONTENT_TYPE;
+    use hanekawa_common::{
+        audit::AuditLog,
+        ban::BanList,
+        maintenance::Maintenance,
+        offense::Offenders,
+        passkey::Passkeys,
+        peer_limit::PeerLimits,
+        repository::{
+            info_hash::{InfoHashRepository, UpdateInfoHash},
+            memory::MemoryStore,
+            peer::{PeerRepository, SetConnectable, UpdatePeerAnnounce},
+        },
+        task::{Task, TaskQueue},
+        torrent_policy::TorrentPolicy,
+        types::{Event, InfoHash, Peer, PeerId, PeerSource, Transport},
+        AdminToken, InvalidRequestConfig,
+    };
+    use std::{cmp::Reverse, net::Ipv4Addr};
+    use tower::ServiceExt;
+
+    fn info_hash(n: u8) -> InfoHash {
+        InfoHash(vec![n; 20])
+    }
+
+    // Three swarms of 5, 1 and 3 peers, the smallest announced last and the
+    // largest denied. Their seeders completed there.
+    async fn seeded() -> Arc<MemoryStore> {
+        let store = Arc::new(MemoryStore::new());
+        let now = OffsetDateTime::now_utc();
+        for (n, complete, incomplete, age) in [(1, 4, 1, 30), (3, 1, 2, 20)] {
+            for i in 0..complete + incomplete {
+                let seeding = i < complete;
+                let cmd = UpdatePeerAnnounce {
+                    info_hash: info_hash(n),
+                    peer_id: PeerId(vec![i; 20]),
+                    ip: Ipv4Addr::new(10, 0, n, i).into(),
+                    port: 6881,
+                    uploaded: 0,
+                    downloaded: 0,
+                    left: if seeding { 0 } else { 100 },
+                    event: if seeding {
+                        Event::Completed
+                    } else {
+                        Event::Started
+                    },
+                    update_timestamp: now - time::Duration::seconds(age),
+                    other_endpoint: None,
+                    transport: None,
+                    user_id: None,
+                    key: None,
+                };
+                store.update_peer_announce(&cmd).await.unwrap();
+            }
+        }
+
+        let cmd = UpdatePeerAnnounce {
+            info_hash: info_hash(2),
+            peer_id: PeerId(b"-qB4650-123456789012".to_vec()),
+            ip: Ipv4Addr::new(192, 0, 2, 1).into(),
+            port: 6881,
+            uploaded: 10,
+            downloaded: 20,
+            left: 30,
+            event: Event::Started,
+            update_timestamp: now - time::Duration::seconds(10),
+            other_endpoint: Some("[2001:db8::1]:6881".parse().unwrap()),
+            transport: Some(Transport::Udp),
+            user_id: None,
+            key: None,
+        };
+        store.update_peer_announce(&cmd).await.unwrap();
+        let cmd = SetConnectable {
+            endpoint: "192.0.2.1:6881".parse().unwrap(),
+            connectable: true,
+        };
+        store.set_connectable(cmd).await.unwrap();
+
+        let cmd = UpdateInfoHash {
+            info_hash: &info_hash(1),
+            status: InfoHashStatus::ExplicitDeny,
+        };
+        store.update_info_hash(cmd).await.unwrap();
+
+        store
+    }
+
+    // 10,000 swarms of 1 to 7 peers, every fifth denied, with many ties in
+    // either order.
+    const CROWD: u16 = 10_000;
+
+    fn crowded_hash(n: u16) -> InfoHash {
+        let mut info_hash = vec![0; 20];
+        info_hash[..2].copy_from_slice(&n.to_be_bytes());
+        InfoHash(info_hash)
+    }
+
+    fn crowded_number(info_hash: &InfoHash) -> u16 {
+        u16::from_be_bytes([info_hash.0[0], info_hash.0[1]])
+    }
+
+    async fn crowded() -> Arc<MemoryStore> {
+        let store = Arc::new(MemoryStore::new());
+        let now = OffsetDateTime::now_utc();
+        for n in 0..CROWD {
+            let peers = (n % 7 + 1) as u8;
+            let complete = ((n % 3) as u8).min(peers);
+            let at = now - time::Duration::seconds((n % 13) as i64);
+            for i in 0..peers {
+                let peer = Peer {
+                    peer_id: PeerId(vec![i; 20]),
+                    ip: Ipv4Addr::new(10, 0, 0, i).into(),
+                    port: 6881,
+                    connectable: None,
+                    seeding: i < complete,
+                    source: PeerSource::Announce,
+                };
+                let left = if i < complete { 0 } else { 100 };
+                store.seed(&crowded_hash(n), peer, left, at);
+            }
+
+            if n % 5 == 0 {
+                let cmd = UpdateInfoHash {
+                    info_hash: &crowded_hash(n),
+                    status: InfoHashStatus::ExplicitDeny,
+                };
+                store.update_info_hash(cmd).await.unwrap();
+            }
+        }
+
+        store
+    }
+
+    struct DiscardingQueue;
+
+    #[async_trait::async_trait]
+    impl TaskQueue for DiscardingQueue {
+        async fn enqueue(&self, _task: &dyn Task) -> Option<()> {
+            Some(())
+        }
+    }
+
+    fn config() -> Config {
+        Config {
+            enable_admin_api: true,
+            ..crate::testkit::config()
+        }
+    }
+
+    async fn app(cfg: &Config) -> Router {
+        serve(cfg, seeded().await).await
+    }
+
+    async fn serve(cfg: &Config, store: Arc<MemoryStore>) -> Router {
+        Router::new().nest("/admin", admin(cfg, &services(store)).await)
+    }
+
+    fn services(store: Arc<MemoryStore>) -> Services {
+        Services {
+            peer_repository: store.clone(),
+            info_hash_repository: store,
+            task_queue: Arc::new(DiscardingQueue),
+            bans: BanList::in_memory(),
+            audit: AuditLog::in_memory(),
+            prober: None,
+            federation: None,
+            offenders: Offenders::new(&InvalidRequestConfig::default()),
+            maintenance: Maintenance::default(),
+            passkeys: Passkeys::in_memory(),
+            torrent_policy: TorrentPolicy::open(),
+            peer_limits: PeerLimits::unlimited(),
+            clock: hanekawa_common::system_clock(),
+        }
+    }
+
+    async fn send(
+        app: &Router,
+        request: axum::http::request::Builder,
+        body: Option<serde_json::Value>,
+    ) -> (StatusCode, serde_json::Value) {
+        let request = match body {
+            Some(body) => request
+                .header(CONTENT_TYPE, "application/json")
+                .body(axum::body::Body::from(body.to_string())),
+            None => request.body(axum::body::Body::empty()),
+        };
+        let response = app.clone().oneshot(request.unwrap()).await.unwrap();
+
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap_or_default())
+    }
+
+    async fn get(cfg: &Config, uri: &str, token: Option<&str>) -> (StatusCode, serde_json::Value) {
+        let mut request = Request::get(uri);
+        if let Some(token) = token {
+            request = request.header(AUTHORIZATION, format!("Bearer {token}"));
+        }
+
+        send(&app(cfg).await, request, None).await
+    }
+
+    fn hashes(list: &serde_json::Value) -> Vec<String> {
+        list["torrents"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|t| t["info_hash"].as_str().unwrap()[..2].to_string())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn lists_torrents_in_pages() {
+        let cfg = config();
+
+        let (status, page) = get(&cfg, "/admin/torrents?limit=2", None).await;
+        assert_eq!(StatusCode::OK, status);
+        assert_eq!(vec!["01", "03"], hashes(&page));
+        assert!(page["next_cursor"].is_string());
+
+        let first = &page["torrents"][0];
+        assert_eq!(
+            serde_json::json!([4, 1, 4]),
+            serde_json::json!([first["seeders"], first["leechers"], first["snatches"]])
+        );
+        assert_eq!("denied", first["policy"]);
+        assert_eq!("default", page["torrents"][1]["policy"]);
+
+        let cursor = page["next_cursor"].as_str().unwrap();
+        let uri = format!("/admin/torrents?limit=2&cursor={cursor}");
+        let (_, page) = get(&cfg, &uri, None).await;
+        assert_eq!(vec!["02"], hashes(&page));
+        assert!(page["next_cursor"].is_null());
+
+        let uri = format!("/admin/torrents?sort=activity&cursor={cursor}");
+        let (status, _) = get(&cfg, &uri, None).await;
+        assert_eq!(StatusCode::BAD_REQUEST, status);
+
+        let (_, page) = get(&cfg, "/admin/torrents?sort=activity", None).await;
+        assert_eq!(vec!["02", "03", "01"], hashes(&page));
+
+        let (status, _) = get(&cfg, "/admin/torrents?sort=size", None).await;
+        assert_eq!(StatusCode::BAD_REQUEST, status);
+    }
+
+    #[tokio::test]
+    async fn pages_through_filtered_swarms_without_gaps() {
+        let app = serve(&config(), crowded().await).await;
+
+        let mut expected = (0..CROWD)
+            .filter(|n| n % 7 + 1 >= 4 && n % 5 != 0)
+            .map(|n| crowded_hash(n).to_hex())
+            .collect::<Vec<_>>();
+        expected.sort();
+
+        for sort in ["peers", "activity"] {
+            let mut listed = vec![];
+            let mut cursor = None::<String>;
+            loop {
+                let mut uri =
+                    format!("/admin/torrents?sort={sort}&limit=250&min_peers=4&policy=default");
+                if let Some(cursor) = &cursor {
+                    uri = format!("{uri}&cursor={cursor}");
+                }
+                let (status, page) = send(&app, Request::get(&uri), None).await;
+                assert_eq!(StatusCode::OK, status);
+
+                let torrents = page["torrents"].as_array().unwrap();
+                assert!(torrents.len() <= 250);
+                listed.extend(
+                    torrents
+                        .iter()
+                        .map(|t| t["info_hash"].as_str().unwrap().to_string()),
+                );
+
+                match page["next_cursor"].as_str() {
+                    Some(next) => cursor = Some(next.to_string()),
+                    None => break,
+                }
+            }
+
+            let in_order = listed
+                .iter()
+                .map(|hex| {
+                    let n = crowded_number(&InfoHash::from_hex(hex));
+                    match sort {
+                        "peers" => (Reverse(n % 7 + 1), n % 13, hex.clone()),
+                        _ => (Reverse(0), n % 13, hex.clone()),
+                    }
+                })
+                .collect::<Vec<_>>();
+            assert!(
+                in_order.windows(2).all(|w| w[0] < w[1]),
+                "{sort} is out of order"
+            );
+
+            listed.sort();
+            assert_eq!(expected, listed, "{sort} has gaps or duplicates");
+        }
+
+        let uri = format!(
+            "/admin/torrents?info_hash_prefix={}",
+            &crowded_hash(258).to_hex()[..4]
+        );
+        let (_, page) = send(&app, Request::get(&uri), None).await;
+        assert_eq!(vec!["01"], hashes(&page));
+    }
+
+    #[tokio::test]
+    async fn lists_swarm_members() {
+        let mut cfg = config();
+        let uri = format!("/admin/torrents/{}/peers", info_hash(2).to_hex());
+
+        let (status, peers) = get(&cfg, &uri, None).await;
+        assert_eq!(StatusCode::OK, status);
+        let peer = &peers[0];
+        assert_eq!("192.0.2.1", peer["ip"]);
+        assert_eq!("[2001:db8::1]:6881", peer["other_endpoint"]);
+        assert_eq!("qBittorrent 4.6.5", peer["client"]);
+        assert_eq!(true, peer["connectable"]);
+        assert_eq!(
+            serde_json::json!([10, 20, 30, "started", "udp"]),
+            serde_json::json!([
+                peer["uploaded"],
+                peer["downloaded"],
+                peer["left"],
+                peer["event"],
+                peer["transport"]
+            ])
+        );
+
+        cfg.admin_redact_peers = true;
+        let (_, peers) = get(&cfg, &uri, None).await;
+        let peer = &peers[0];
+        for field in ["peer_id", "ip", "port", "other_endpoint"] {
+            assert!(peer[field].is_null(), "{field} is not redacted");
+        }
+        assert_eq!("qBittorrent 4.6.5", peer["client"]);
+
+        let (status, _) = get(&cfg, "/admin/torrents/xyz/peers", None).await;
+        assert_eq!(StatusCode::BAD_REQUEST, status);
+    }
+
+    // By the tracker's clock, the seeded peers are long gone.
+    #[tokio::test]
+    async fn lists_peers_active_by_the_clock() {
+        let cfg = config();
+        let later = OffsetDateTime::now_utc() + time::Duration::hours(1);
+        let services = Services {
+            clock: Arc::new(move || later),
+            ..services(seeded().await)
+        };
+        let app = Router::new().nest("/admin", admin(&cfg, &services).await);
+        let uri = format!("/admin/torrents/{}/peers", info_hash(2).to_hex());
+
+        let (status, peers) = send(&app, Request::get(uri), None).await;
+        assert_eq!(StatusCode::OK, status);
+        assert_eq!(serde_json::json!([]), peers);
+    }
+
+    #[tokio::test]
+    async fn counts_swarm_clients() {
+        let uri = format!("/admin/torrents/{}/clients", info_hash(2).to_hex());
+
+        let (status, clients) = get(&config(), &uri, None).await;
+        assert_eq!(StatusCode::OK, status);
+        assert_eq!(
+            serde_json::json!([{ "client": "qBittorrent", "version": "4.6.5", "peers": 1 }]),
+            clients
+        );
+    }
+
+    #[tokio::test]
+    async fn protects_peer_addresses_if_configured() {
+        let mut cfg = config();
+        cfg.admin_token = Some("secret".to_string());
+        cfg.privacy.truncate_ips = true;
+        cfg.privacy.pseudonymize = true;
+        let app = app(&cfg).await;
+
+        let uri = format!("/admin/torrents/{}/peers", info_hash(2).to_hex());
+        let (_, peers) = send(&app, as_token(Request::get(&uri), "secret"), None).await;
+        assert_eq!("192.0.2.0", peers[0]["ip"]);
+        assert_eq!("[2001:db8::]:6881", peers[0]["other_endpoint"]);
+
+        // Bans still hold the address, only the audit log does not.
+        let ban = serde_json::json!({ "kind": "ip", "value": "192.0.2.1", "reason": "abuse" });
+        let request = as_token(Request::post("/admin/bans"), "secret");
+        let (_, added) = send(&app, request, Some(ban)).await;
+        assert_eq!("192.0.2.1", added["value"]);
+        let request = as_token(Request::get("/admin/audit"), "secret");
+        let (_, audit) = send(&app, request, None).await;
+        let target = audit[0]["target"].as_str().unwrap();
+        assert!(target.starts_with("ip anon-"), "{target}");
+    }
+
+    #[tokio::test]
+    async fn requires_the_token_if_configured() {
+        let mut cfg = config();
+        cfg.admin_token = Some("secret".to_string());
+
+        for (token, expected) in [
+            (None, StatusCode::UNAUTHORIZED),
+            (Some("guess"), StatusCode::UNAUTHORIZED),
+            (Some("secret"), StatusCode::OK),
+        ] {
+            let (status, _) = get(&cfg, "/admin/torrents", token).await;
+            assert_eq!(expected, status);
+        }
+
+        cfg.enable_admin_api = false;
+        let (status, _) = get(&cfg, "/admin/torrents", Some("secret")).await;
+        assert_eq!(StatusCode::NOT_FOUND, status);
+    }
+
+    #[tokio::test]
+    async fn adds_and_removes_bans() {
+        let mut cfg = config();
+        cfg.admin_token = Some("secret".to_string());
+        let app = app(&cfg).await;
+        let send = |request, body| send(&app, as_token(request, "secret"), body);
+
+        let ban = serde_json::json!({
+            "kind": "cidr",
+            "value": "192.0.2.77/24",
+            "reason": "abuse",
+            "expires_in": 3600,
+        });
+        let (status, added) = send(Request::post("/admin/bans"), Some(ban)).await;
+        assert_eq!(StatusCode::CREATED, status);
+        assert_eq!("192.0.2.0/24", added["value"]);
+        assert_eq!(
+            Some(3600),
+            added["expires"]
+                .as_i64()
+                .zip(added["created"].as_i64())
+                .map(|(expires, created)| expires - created)
+        );
+
+        let permanent = serde_json::json!({
+            "kind": "peer_id",
+            "value": "2d7142343635302d313233343536373839303132",
+            "reason": "leech",
+        });
+        send(Request::post("/admin/bans"), Some(permanent)).await;
+
+        let (_, bans) = send(Request::get("/admin/bans"), None).await;
+        assert_eq!(2, bans.as_array().unwrap().len());
+        assert!(bans[1]["expires"].is_null());
+
+        let uri = format!("/admin/bans/{}", added["id"]);
+        let (status, _) = send(Request::delete(&uri), None).await;
+        assert_eq!(StatusCode::OK, status);
+        let (status, _) = send(Request::delete(&uri), None).await;
+        assert_eq!(StatusCode::NOT_FOUND, status);
+
+        let (_, bans) = send(Request::get("/admin/bans"), None).await;
+        assert_eq!("leech", bans[0]["reason"]);
+        assert_eq!(1, bans.as_array().unwrap().len());
+
+        let invalid = serde_json::json!({ "kind": "ip", "value": "nope", "reason": "" });
+        let (status, _) = send(Request::post("/admin/bans"), Some(invalid)).await;
+        assert_eq!(StatusCode::BAD_REQUEST, status);
+    }
+
+    fn with_tokens() -> Config {
+        let mut cfg = config();
+        cfg.admin_tokens = vec![
+            AdminToken {
+                id: "ro".to_string(),
+                token: "read-secret".to_string(),
+                scope: AdminScope::Read,
+            },
+            AdminToken {
+                id: "rw".to_string(),
+                token: "write-secret".to_string(),
+                scope: AdminScope::Mutate,
+            },
+        ];
+        cfg
+    }
+
+    fn as_token(
+        request: axum::http::request::Builder,
+        token: &str,
+    ) -> axum::http::request::Builder {
+        request.header(AUTHORIZATION, format!("Bearer {token}"))
+    }
+
+    #[tokio::test]
+    async fn takes_no_changes_without_tokens() {
+        let app = app(&config()).await;
+        let ban = serde_json::json!({ "kind": "ip", "value": "192.0.2.1", "reason": "abuse" });
+
+        let (status, _) = send(&app, Request::post("/admin/bans"), Some(ban)).await;
+        assert_eq!(StatusCode::FORBIDDEN, status);
+        let (_, bans) = send(&app, Request::get("/admin/bans"), None).await;
+        assert_eq!(serde_json::json!([]), bans);
+    }
+
+    #[tokio::test]
+    async fn enforces_token_scopes() {
+        let app = app(&with_tokens()).await;
+        let ban = serde_json::json!({ "kind": "ip", "value": "192.0.2.1", "reason": "abuse" });
+
+        let (status, body) = send(&app, Request::post("/admin/bans"), Some(ban.clone())).await;
+        assert_eq!(StatusCode::UNAUTHORIZED, status);
+        assert!(body["error"].is_string());
+
+        let request = as_token(Request::post("/admin/bans"), "read-secret");
+        let (status, body) = send(&app, request, Some(ban.clone())).await;
+        assert_eq!(StatusCode::FORBIDDEN, status);
+        assert!(body["error"].is_string());
+
+        let request = as_token(Request::get("/admin/bans"), "read-secret");
+        let (status, bans) = send(&app, request, None).await;
+        assert_eq!(StatusCode::OK, status);
+        assert!(bans.as_array().unwrap().is_empty());
+
+        let request = as_token(Request::post("/admin/bans"), "write-secret");
+        let (status, _) = send(&app, request, Some(ban)).await;
+        assert_eq!(StatusCode::CREATED, status);
+    }
+
+    #[tokio::test]
+    async fn audits_changes() {
+        let app = app(&with_tokens()).await;
+
+        let ban = serde_json::json!({ "kind": "ip", "value": "192.0.2.1", "reason": "abuse" });
+        let request = as_token(Request::post("/admin/bans"), "write-secret");
+        let (_, added) = send(&app, request, Some(ban)).await;
+        let uri = format!("/admin/bans/{}", added["id"]);
+        for _ in 0..2 {
+            send(&app, as_token(Request::delete(&uri), "write-secret"), None).await;
+        }
+
+        let request = as_token(Request::get("/admin/audit"), "read-secret");
+        let (status, audit) = send(&app, request, None).await;
+        assert_eq!(StatusCode::OK, status);
+
+        let entries = audit
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| {
+                assert_eq!("rw", e["token_id"]);
+                assert!(e["timestamp"].is_i64());
+                (
+                    e["action"].as_str().unwrap(),
+                    e["target"].as_str().unwrap(),
+                    e["outcome"].as_str().unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                ("remove_ban", "ban 1", "no ban 1"),
+                ("remove_ban", "ban 1", "ok"),
+                ("add_ban", "ip 192.0.2.1", "ok"),
+            ],
+            entries
+        );
+
+        let before = audit[0]["id"].as_u64().unwrap();
+        let uri = format!("/admin/audit?limit=1&before={before}");
+        let (_, page) = send(&app, as_token(Request::get(&uri), "read-secret"), None).await;
+        assert_eq!(1, page.as_array().unwrap().len());
+        assert_eq!("ok", page[0]["outcome"]);
+        assert_eq!("remove_ban", page[0]["action"]);
+    }
 }
@@ -1,4 +1,12 @@
 #[tokio::main]
 async fn main() {
-    hanekawa_server::start().await;
+    let result = match std::env::args().nth(1).as_deref() {
+        Some("rekey") => hanekawa_server::rekey().await,
+        _ => hanekawa_server::start().await,
+    };
+
+    if let Err(e) = result {
+        eprintln!("{e}");
+        std::process::exit(1);
+    }
 }
@@ -0,0 +1,180 @@
+use hanekawa_client::{
+    proto::AnnounceParams, HttpTrackerClient, TrackerClient, TrackerTransport, UdpTrackerClient,
+};
+use hanekawa_common::{
+    federation::Federation,
+    repository::peer::UpdatePeerAnnounce,
+    types::{InfoHash, Peer, PeerId, PeerSource},
+    Config, Upstream,
+};
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::{
+    sync::{mpsc, Semaphore},
+    task::JoinHandle,
+};
+use tokio_util::sync::CancellationToken;
+
+// Announces waiting to be forwarded. New ones are dropped while it is full.
+const QUEUE_SIZE: usize = 1024;
+// Announces in flight at once, to all upstreams together.
+const MAX_IN_FLIGHT: usize = 64;
+
+// What each upstream last returned for each torrent, and when.
+type Returned = HashMap<InfoHash, HashMap<Arc<str>, (Vec<Peer>, Instant)>>;
+
+// Forwards announces to the configured upstream trackers in the background,
+// and keeps the peers they return to be handed out until they expire. A slow
+// or failing upstream only loses its own announces.
+pub struct UpstreamFederation {
+    queue: mpsc::Sender<UpdatePeerAnnounce>,
+    // Those forwarded, or all if none.
+    info_hashes: HashSet<InfoHash>,
+    ttl: Duration,
+    returned: Arc<Mutex<Returned>>,
+}
+
+impl UpstreamFederation {
+    // Forwards until `kt` is cancelled.
+    pub fn start(cfg: &Config, kt: CancellationToken) -> (Arc<Self>, JoinHandle<()>) {
+        let (queue, announces) = mpsc::channel(QUEUE_SIZE);
+        let federation = Arc::new(Self {
+            queue,
+            info_hashes: cfg
+                .federation
+                .info_hashes
+                .iter()
+                .map(InfoHash::from_hex)
+                .collect(),
+            ttl: Duration::from_secs(cfg.federation.peer_ttl),
+            returned: Arc::default(),
+        });
+
+        let task = tokio::spawn(run(cfg.clone(), announces, federation.returned.clone(), kt));
+
+        (federation, task)
+    }
+}
+
+impl Federation for UpstreamFederation {
+    fn forward(&self, announce: &UpdatePeerAnnounce) {
+        if self.info_hashes.is_empty() || self.info_hashes.contains(&announce.info_hash) {
+            let _ = self.queue.try_send(announce.clone());
+        }
+    }
+
+    fn peers(&self, info_hash: &InfoHash) -> Vec<Peer> {
+        let returned = self.returned.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(upstreams) = returned.get(info_hash) else {
+            return vec![];
+        };
+
+        upstreams
+            .values()
+            .filter(|(_, at)| at.elapsed() < self.ttl)
+            .flat_map(|(peers, _)| peers.iter().cloned())
+            .collect()
+    }
+}
+
+async fn run(
+    cfg: Config,
+    mut announces: mpsc::Receiver<UpdatePeerAnnounce>,
+    returned: Arc<Mutex<Returned>>,
+    kt: CancellationToken,
+) {
+    let timeout = Duration::from_millis(cfg.federation.timeout_ms);
+    let http = HttpTrackerClient::builder()
+        .timeout(timeout)
+        .build()
+        .unwrap();
+    let udp = UdpTrackerClient::builder().timeout(timeout).build();
+    let client = TrackerClient::new(http, udp);
+
+    let in_flight = Arc::new(Semaphore::new(MAX_IN_FLIGHT));
+    let ttl = Duration::from_secs(cfg.federation.peer_ttl);
+    let mut prune = tokio::time::interval(ttl.max(Duration::from_secs(1)));
+
+    loop {
+        let announce = tokio::select! {
+            _ = kt.cancelled() => break,
+            _ = prune.tick() => {
+                let mut returned = returned.lock().unwrap_or_else(|e| e.into_inner());
+                returned.retain(|_, upstreams| {
+                    upstreams.retain(|_, (_, at)| at.elapsed() < ttl);
+                    !upstreams.is_empty()
+                });
+                continue;
+            }
+            announce = announces.recv() => match announce {
+                Some(announce) => announce,
+                None => break,
+            },
+        };
+
+        for upstream in &cfg.federation.upstreams {
+            let permit = tokio::select! {
+                _ = kt.cancelled() => return,
+                permit = in_flight.clone().acquire_owned() => permit.unwrap(),
+            };
+
+            let client = client.clone();
+            let params = params(&announce, upstream, cfg.default_num_want);
+            let info_hash = announce.info_hash.clone();
+            let url: Arc<str> = upstream.url.as_str().into();
+            let returned = returned.clone();
+            tokio::spawn(async move {
+                let peers = match client.announce(&url, params).await {
+                    Ok(response) => response.peers(),
+                    Err(e) => Err(e),
+                };
+                drop(permit);
+
+                match peers {
+                    Ok(peers) => {
+                        let peers = peers
+                            .into_iter()
+                            .map(|p| Peer {
+                                peer_id: p.peer_id.unwrap_or(PeerId(vec![0; 20])),
+                                ip: p.addr.ip(),
+                                port: p.addr.port(),
+                                connectable: None,
+                                seeding: false,
+                                source: PeerSource::Upstream(url.clone()),
+                            })
+                            .collect();
+
+                        let mut returned = returned.lock().unwrap_or_else(|e| e.into_inner());
+                        returned
+                            .entry(info_hash)
+                            .or_default()
+                            .insert(url, (peers, Instant::now()));
+                    }
+                    Err(e) => tracing::debug!("forwarding to {url} failed: {e}"),
+                }
+            });
+        }
+    }
+}
+
+// Only an upstream trusting this tracker is told the peer's address, the
+// others take this tracker's.
+fn params(announce: &UpdatePeerAnnounce, upstream: &Upstream, num_want: u32) -> AnnounceParams {
+    AnnounceParams {
+        info_hash: announce.info_hash.clone(),
+        peer_id: announce.peer_id.clone(),
+        port: announce.port,
+        uploaded: announce.uploaded,
+        downloaded: announce.downloaded,
+        left: announce.left,
+        event: announce.event.clone(),
+        num_want: Some(num_want),
+        compact: false,
+        key: None,
+        ip: upstream.trusted.then_some(announce.ip),
+    }
+}
@@ -0,0 +1,20 @@
+use hanekawa_common::{maintenance::Maintenance, Services};
+
+use axum::{extract::State, http::StatusCode, routing::get, Json, Router};
+
+// Unready while in maintenance, so load balancers stop sending clients.
+async fn status(State(maintenance): State<Maintenance>) -> (StatusCode, Json<serde_json::Value>) {
+    match maintenance.is_enabled() {
+        true => (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(serde_json::json!({ "status": "maintenance" })),
+        ),
+        false => (StatusCode::OK, Json(serde_json::json!({ "status": "ok" }))),
+    }
+}
+
+pub fn health<S>(services: &Services) -> Router<S> {
+    Router::new()
+        .route("/health", get(status))
+        .with_state(services.maintenance.clone())
+}
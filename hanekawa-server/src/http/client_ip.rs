@@ -0,0 +1,179 @@
+use hanekawa_common::types::Cidr;
+
+use axum::{
+    extract::{ConnectInfo, FromRequestParts},
+    http::{header::FORWARDED, request::Parts, HeaderMap},
+};
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
+
+// Proxies whose forwarding headers are believed, as an extension.
+#[derive(Debug, Clone)]
+pub struct TrustedProxies(pub Arc<[Cidr]>);
+
+impl TrustedProxies {
+    fn contains(&self, ip: IpAddr) -> bool {
+        self.0.iter().any(|cidr| cidr.contains(ip))
+    }
+}
+
+// Where a request came from: the connecting address, or through trusted
+// proxies the right-most address they were forwarded for that is not
+// itself one. IPv4-mapped addresses are taken as IPv4.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientIp(pub IpAddr);
+
+impl ClientIp {
+    fn of(peer: IpAddr, headers: &HeaderMap, trusted: Option<&TrustedProxies>) -> Self {
+        let mut ip = peer.to_canonical();
+        let Some(trusted) = trusted else {
+            return Self(ip);
+        };
+
+        for hop in forwarded_for(headers).into_iter().rev() {
+            if !trusted.contains(ip) {
+                break;
+            }
+            match hop {
+                Some(hop) => ip = hop.to_canonical(),
+                None => break,
+            }
+        }
+
+        Self(ip)
+    }
+}
+
+// From the first of the headers that is set, in the order they were
+// forwarded through. None where a hop is not an address.
+fn forwarded_for(headers: &HeaderMap) -> Vec<Option<IpAddr>> {
+    let values = |name| {
+        headers
+            .get_all(name)
+            .iter()
+            .flat_map(|v| v.to_str().ok().unwrap_or_default().split(','))
+            .collect::<Vec<_>>()
+    };
+
+    let forwarded = values(FORWARDED.as_str());
+    if !forwarded.is_empty() {
+        return forwarded
+            .into_iter()
+            .map(|element| {
+                element
+                    .split(';')
+                    .filter_map(|pair| pair.split_once('='))
+                    .find(|(key, _)| key.trim().eq_ignore_ascii_case("for"))
+                    .and_then(|(_, node)| hop(node))
+            })
+            .collect();
+    }
+
+    let forwarded = values("x-forwarded-for");
+    let forwarded = match forwarded.is_empty() {
+        true => values("x-real-ip"),
+        false => forwarded,
+    };
+    forwarded.into_iter().map(hop).collect()
+}
+
+// An address, maybe with a port, quoted, or in brackets.
+fn hop(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+    node.parse()
+        .or_else(|_| node.parse::<SocketAddr>().map(|addr| addr.ip()))
+        .or_else(|_| node.trim_start_matches('[').trim_end_matches(']').parse())
+        .ok()
+}
+
+#[async_trait::async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for ClientIp {
+    type Rejection = <ConnectInfo<SocketAddr> as FromRequestParts<S>>::Rejection;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let ConnectInfo(peer) = ConnectInfo::<SocketAddr>::from_request_parts(parts, state).await?;
+        let trusted = parts.extensions.get::<TrustedProxies>();
+
+        Ok(Self::of(peer.ip(), &parts.headers, trusted))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use axum::http::HeaderValue;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    fn headers(headers: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut map = HeaderMap::new();
+        for (name, value) in headers {
+            map.append(*name, HeaderValue::from_static(value));
+        }
+        map
+    }
+
+    fn client_ip(peer: &str, forwarded: &[(&'static str, &'static str)]) -> IpAddr {
+        let trusted = TrustedProxies(Arc::from(vec![
+            "10.0.0.0/8".parse().unwrap(),
+            "2001:db8:ffff::/48".parse().unwrap(),
+        ]));
+
+        ClientIp::of(ip(peer), &headers(forwarded), Some(&trusted)).0
+    }
+
+    #[test]
+    fn takes_direct_connections_as_they_are() {
+        assert_eq!(ip("192.0.2.1"), client_ip("192.0.2.1", &[]));
+        assert_eq!(ip("192.0.2.1"), client_ip("::ffff:192.0.2.1", &[]));
+        assert_eq!(ip("2001:db8::1"), client_ip("2001:db8::1", &[]));
+
+        // Headers from anyone not trusted are ignored, as are all of them
+        // if no one is.
+        let spoofed = [("x-forwarded-for", "198.51.100.1")];
+        assert_eq!(ip("192.0.2.1"), client_ip("192.0.2.1", &spoofed));
+        assert_eq!(
+            ClientIp(ip("10.0.0.1")),
+            ClientIp::of(ip("10.0.0.1"), &headers(&spoofed), None)
+        );
+    }
+
+    #[test]
+    fn follows_forwarding_through_trusted_proxies() {
+        // Hops left of the first untrusted one are up to the client.
+        let chain = [("x-forwarded-for", "203.0.113.9, 192.0.2.1, 10.0.0.2")];
+        assert_eq!(ip("192.0.2.1"), client_ip("10.0.0.1", &chain));
+        let split = [
+            ("x-forwarded-for", "203.0.113.9"),
+            ("x-forwarded-for", "10.1.0.1, 10.0.0.2"),
+        ];
+        assert_eq!(ip("203.0.113.9"), client_ip("10.0.0.1", &split));
+
+        assert_eq!(
+            ip("192.0.2.1"),
+            client_ip("10.0.0.1", &[("x-real-ip", "192.0.2.1")])
+        );
+        assert_eq!(
+            ip("192.0.2.1"),
+            client_ip("10.0.0.1", &[("x-forwarded-for", "::ffff:192.0.2.1")])
+        );
+        let forwarded = [(
+            "forwarded",
+            "for=\"[2001:db8::1]:4711\";proto=http, for=10.0.0.2",
+        )];
+        assert_eq!(ip("2001:db8::1"), client_ip("2001:db8:ffff::1", &forwarded));
+
+        // Forwarded wins over the others, and a hop that is no address ends
+        // the walk.
+        let obfuscated = [
+            ("forwarded", "for=unknown, for=10.0.0.2"),
+            ("x-forwarded-for", "192.0.2.1"),
+        ];
+        assert_eq!(ip("10.0.0.2"), client_ip("10.0.0.1", &obfuscated));
+    }
+}
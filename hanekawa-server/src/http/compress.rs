@@ -0,0 +1,148 @@
+use hanekawa_common::CompressionConfig;
+
+use axum::{
+    body::{boxed, Full, HttpBody},
+    extract::State,
+    http::{
+        header::{ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_LENGTH, VARY},
+        HeaderMap, HeaderValue, Request, StatusCode,
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use flate2::{
+    write::{GzEncoder, ZlibEncoder},
+    Compression,
+};
+use std::io::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    Gzip,
+    // HTTP's deflate is zlib's format.
+    Deflate,
+}
+
+impl Encoding {
+    fn name(self) -> HeaderValue {
+        match self {
+            Self::Gzip => HeaderValue::from_static("gzip"),
+            Self::Deflate => HeaderValue::from_static("deflate"),
+        }
+    }
+
+    fn compress(self, body: &[u8]) -> std::io::Result<Vec<u8>> {
+        match self {
+            Self::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(body)?;
+                encoder.finish()
+            }
+            Self::Deflate => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(body)?;
+                encoder.finish()
+            }
+        }
+    }
+}
+
+// Gzip over deflate, unless refused with `q=0`. None without the header, as
+// clients that do not ask may not expect anything but the body as is.
+fn accepted(headers: &HeaderMap) -> Option<Encoding> {
+    let accepted = headers
+        .get_all(ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|coding| {
+            let mut params = coding.split(';').map(str::trim);
+            let name = params.next()?.to_ascii_lowercase();
+            let refused = params
+                .filter_map(|p| p.strip_prefix("q="))
+                .any(|q| q.parse::<f32>().is_ok_and(|q| q == 0.0));
+            (!refused).then_some(name)
+        })
+        .collect::<Vec<_>>();
+
+    [("gzip", Encoding::Gzip), ("deflate", Encoding::Deflate)]
+        .into_iter()
+        .find(|(name, _)| accepted.iter().any(|a| a == name || a == "*"))
+        .map(|(_, encoding)| encoding)
+}
+
+// Compact peer lists are a few bytes each, compressing them would only cost
+// time.
+fn is_compact_announce<B>(request: &Request<B>) -> bool {
+    request.uri().path().split('/').any(|s| s == "announce")
+        && !request
+            .uri()
+            .query()
+            .unwrap_or_default()
+            .split('&')
+            .any(|param| param == "compact=0")
+}
+
+pub async fn compress<B>(
+    State(cfg): State<CompressionConfig>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    if is_compact_announce(&request) {
+        return next.run(request).await;
+    }
+
+    let encoding = accepted(request.headers());
+    let mut response = next.run(request).await;
+    response
+        .headers_mut()
+        .append(VARY, HeaderValue::from_static("accept-encoding"));
+
+    let Some(encoding) = encoding else {
+        return response;
+    };
+    let small = response
+        .body()
+        .size_hint()
+        .upper()
+        .is_some_and(|size| size < cfg.min_size as u64);
+    if small || response.headers().contains_key(CONTENT_ENCODING) {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let Ok(body) = hyper::body::to_bytes(body).await else {
+        return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+    };
+    if body.len() < cfg.min_size {
+        return Response::from_parts(parts, boxed(Full::new(body)));
+    }
+    let Ok(compressed) = encoding.compress(&body) else {
+        return Response::from_parts(parts, boxed(Full::new(body)));
+    };
+
+    parts.headers.insert(CONTENT_ENCODING, encoding.name());
+    parts.headers.remove(CONTENT_LENGTH);
+    Response::from_parts(parts, boxed(Full::from(compressed)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn accept(value: &str) -> Option<Encoding> {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT_ENCODING, HeaderValue::from_str(value).unwrap());
+        accepted(&headers)
+    }
+
+    #[test]
+    fn picks_an_accepted_encoding() {
+        assert_eq!(None, accepted(&HeaderMap::new()));
+        assert_eq!(Some(Encoding::Gzip), accept("deflate, gzip;q=0.5"));
+        assert_eq!(Some(Encoding::Deflate), accept("gzip;q=0, deflate"));
+        assert_eq!(Some(Encoding::Gzip), accept("*"));
+        assert_eq!(None, accept("br"));
+        assert_eq!(None, accept("identity"));
+    }
+}
@@ -1,16 +1,28 @@
+use axum::extract::FromRequestParts;
+use axum::http::request::Parts;
 use axum::http::{header, status, HeaderValue};
 use axum::response::IntoResponse;
+use bytes::Bytes;
+use hanekawa_bencode::Encoder;
+use std::{cell::RefCell, convert::Infallible};
 
 pub struct Bencode<T>(pub T);
 
 const APPLICATION_OCTET_STREAM: &'static str = "application/octet-stream";
+const APPLICATION_JSON: &str = "application/json";
+
+thread_local! {
+    // Responses are encoded one after another into the same buffer, which is
+    // what makes most of them not allocate.
+    static ENCODER: RefCell<Encoder> = RefCell::new(Encoder::with_capacity(16 * 1024));
+}
 
 impl<T> IntoResponse for Bencode<T>
 where
     T: serde::Serialize,
 {
     fn into_response(self) -> axum::response::Response {
-        let encoded = hanekawa_bencode::to_bytes(&self.0);
+        let encoded = ENCODER.with_borrow_mut(|encoder| encoder.encode(&self.0));
         match encoded {
             Ok(bs) => (
                 [(
@@ -29,3 +41,191 @@ where
         }
     }
 }
+
+// Already bencoded, as cached responses are.
+pub struct Bencoded(pub Bytes);
+
+impl IntoResponse for Bencoded {
+    fn into_response(self) -> axum::response::Response {
+        (
+            [(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static(APPLICATION_OCTET_STREAM),
+            )],
+            self.0,
+        )
+            .into_response()
+    }
+}
+
+// Set on routers that may answer in JSON.
+#[derive(Debug, Clone, Copy)]
+pub struct JsonResponses;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Bencode,
+    Json,
+}
+
+impl Format {
+    fn of(parts: &Parts) -> Self {
+        if parts.extensions.get::<JsonResponses>().is_none() {
+            return Self::Bencode;
+        }
+
+        let json = parts
+            .headers
+            .get_all(header::ACCEPT)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .any(|media| {
+                let mut params = media.split(';').map(str::trim);
+                let accepted = params
+                    .next()
+                    .is_some_and(|m| m.eq_ignore_ascii_case(APPLICATION_JSON));
+                let refused = params
+                    .filter_map(|p| p.strip_prefix("q="))
+                    .any(|q| q.parse::<f32>().is_ok_and(|q| q == 0.0));
+                accepted && !refused
+            });
+        match json {
+            true => Self::Json,
+            false => Self::Bencode,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<S: Sync> FromRequestParts<S> for Format {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Self::of(parts))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use hanekawa::http_tracker::{
+        encode_peers,
+        proto::{AnnounceResponse, PeerData, Warning},
+    };
+    use hanekawa_common::types::{Peer, PeerId, PeerSource, PeerStatistics};
+
+    use axum::http::Request;
+    use bytes::{BufMut, BytesMut};
+    use std::net::IpAddr;
+
+    fn format(accept: Option<&str>, enabled: bool) -> Format {
+        let mut request = Request::builder();
+        if let Some(accept) = accept {
+            request = request.header(header::ACCEPT, accept);
+        }
+        let (mut parts, _) = request.body(()).unwrap().into_parts();
+        if enabled {
+            parts.extensions.insert(JsonResponses);
+        }
+        Format::of(&parts)
+    }
+
+    #[test]
+    fn answers_in_json_only_if_asked_and_enabled() {
+        assert_eq!(Format::Bencode, format(None, true));
+        assert_eq!(Format::Json, format(Some("application/json"), true));
+        assert_eq!(
+            Format::Json,
+            format(Some("text/html, Application/JSON;q=0.9"), true)
+        );
+        assert_eq!(Format::Bencode, format(Some("application/json;q=0"), true));
+        assert_eq!(Format::Bencode, format(Some("*/*"), true));
+        assert_eq!(Format::Bencode, format(Some("application/json"), false));
+    }
+
+    // How compact peer lists and responses were encoded before they were
+    // pooled, with a buffer of their own each.
+    fn unpooled(peers: Vec<Peer>, is_compact: bool) -> (PeerData, PeerData) {
+        if !is_compact {
+            return encode_peers(peers, false, true);
+        }
+
+        let mut peers_bytes = BytesMut::new();
+        let mut peers6_bytes = BytesMut::new();
+        for peer in peers {
+            match peer.ip {
+                IpAddr::V4(ip) => {
+                    peers_bytes.put_u32(ip.into());
+                    peers_bytes.put_u16(peer.port);
+                }
+                IpAddr::V6(ip) => {
+                    peers6_bytes.put_u128(ip.into());
+                    peers6_bytes.put_u16(peer.port);
+                }
+            }
+        }
+        (
+            PeerData::Compact(peers_bytes.to_vec().into()),
+            PeerData::Compact(peers6_bytes.to_vec().into()),
+        )
+    }
+
+    fn response((peers, peers6): (PeerData, PeerData), n: usize) -> AnnounceResponse {
+        AnnounceResponse {
+            interval: 1800 + n as u32,
+            min_interval: 900,
+            peers,
+            peers6,
+            stats: n.is_multiple_of(2).then(|| PeerStatistics {
+                complete: n as u32,
+                downloaded: 2 * n as u32,
+                incomplete: 3,
+            }),
+            warnings: match n.is_multiple_of(3) {
+                true => vec![Warning::PasskeyExpiring("passkey changed".to_string())],
+                false => vec![],
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn pooled_responses_are_byte_identical() {
+        let mut sent = vec![];
+        for n in 0..200 {
+            let peers = (0..n % 60)
+                .map(|i| Peer {
+                    peer_id: PeerId(vec![i as u8; 20]),
+                    ip: match i % 3 {
+                        0 => IpAddr::from([
+                            0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, i as u8,
+                        ]),
+                        _ => IpAddr::from([192, 0, 2, i as u8]),
+                    },
+                    port: 6881 + i as u16,
+                    connectable: None,
+                    seeding: false,
+                    source: PeerSource::default(),
+                })
+                .collect::<Vec<_>>();
+            let is_compact = n % 5 != 0;
+
+            let expected =
+                hanekawa_bencode::to_bytes(&response(unpooled(peers.clone(), is_compact), n))
+                    .unwrap();
+            let pooled =
+                Bencode(response(encode_peers(peers, is_compact, true), n)).into_response();
+            // Some are still being sent while others are encoded.
+            sent.push((expected, pooled));
+            if n % 7 == 0 {
+                sent.drain(..sent.len() / 2);
+            }
+        }
+
+        for (expected, pooled) in sent {
+            let body = hyper::body::to_bytes(pooled.into_body()).await.unwrap();
+            assert_eq!(expected, body);
+        }
+    }
+}
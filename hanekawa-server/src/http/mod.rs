@@ -1,2 +1,5 @@
+pub mod client_ip;
+pub mod compress;
 pub mod encode;
 pub mod extractor;
+pub mod tls;
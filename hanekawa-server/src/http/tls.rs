@@ -0,0 +1,139 @@
+use crate::Error;
+use hanekawa_common::TlsConfig;
+
+use axum::extract::connect_info::Connected;
+use std::{
+    io,
+    net::SocketAddr,
+    path::Path,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+};
+use tokio_rustls::{server::TlsStream, TlsAcceptor};
+
+// Clients that take longer to say hello are hung up on.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+// Connections past the handshake that the server has yet to take.
+const BACKLOG: usize = 128;
+
+fn read(path: &Path) -> Result<Vec<u8>, Error> {
+    std::fs::read(path).map_err(|e| Error::Tls(format!("cannot read {}: {e}", path.display())))
+}
+
+pub fn acceptor(cfg: &TlsConfig) -> Result<TlsAcceptor, Error> {
+    let invalid = |path: &Path, e: &dyn std::fmt::Display| {
+        Error::Tls(format!("invalid PEM in {}: {e}", path.display()))
+    };
+
+    let certs = rustls_pemfile::certs(&mut &read(&cfg.cert)?[..])
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| invalid(&cfg.cert, &e))?;
+    if certs.is_empty() {
+        return Err(invalid(&cfg.cert, &"no certificates"));
+    }
+    let key = rustls_pemfile::private_key(&mut &read(&cfg.key)?[..])
+        .map_err(|e| invalid(&cfg.key, &e))?
+        .ok_or_else(|| invalid(&cfg.key, &"no private key"))?;
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let mut config = rustls::ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .and_then(|builder| builder.with_no_client_auth().with_single_cert(certs, key))
+        .map_err(|e| Error::Tls(e.to_string()))?;
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+pub struct TlsConn {
+    stream: TlsStream<TcpStream>,
+    remote: SocketAddr,
+}
+
+impl AsyncRead for TlsConn {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for TlsConn {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_shutdown(cx)
+    }
+}
+
+impl Connected<&TlsConn> for SocketAddr {
+    fn connect_info(conn: &TlsConn) -> Self {
+        conn.remote
+    }
+}
+
+// Connections as they finish their handshakes, each apart from the others
+// so a slow one holds up no other.
+pub struct Incoming(mpsc::Receiver<TlsConn>);
+
+impl hyper::server::accept::Accept for Incoming {
+    type Conn = TlsConn;
+    type Error = io::Error;
+
+    fn poll_accept(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<TlsConn, io::Error>>> {
+        self.get_mut().0.poll_recv(cx).map(|conn| conn.map(Ok))
+    }
+}
+
+// Accepts until the server lets go of what it returns.
+pub fn incoming(listener: TcpListener, acceptor: TlsAcceptor) -> Incoming {
+    let (tx, rx) = mpsc::channel(BACKLOG);
+
+    tokio::spawn(async move {
+        loop {
+            let (stream, remote) = tokio::select! {
+                _ = tx.closed() => break,
+                accepted = listener.accept() => match accepted {
+                    Ok(accepted) => accepted,
+                    // Out of file descriptors, most likely.
+                    Err(_) => {
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                        continue;
+                    }
+                },
+            };
+
+            let (acceptor, tx) = (acceptor.clone(), tx.clone());
+            tokio::spawn(async move {
+                let handshake = tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream));
+                if let Ok(Ok(stream)) = handshake.await {
+                    let _ = tx.send(TlsConn { stream, remote }).await;
+                }
+            });
+        }
+    });
+
+    Incoming(rx)
+}
@@ -0,0 +1,249 @@
+use hanekawa_bencode::StreamEncoder;
+use hanekawa_common::{
+    repository::{
+        self,
+        peer::{IterSwarms, PeerRepository},
+    },
+    torrent_policy::TorrentPolicy,
+    types::{InfoHash, PeerStatistics},
+    Config, Services,
+};
+
+use bytes::Bytes;
+use std::sync::Arc;
+use time::{Duration, OffsetDateTime};
+use tokio::sync::Mutex;
+
+type Clock = Arc<dyn Fn() -> OffsetDateTime + Send + Sync>;
+// The snapshot, and when it was taken.
+type Latest = Option<(OffsetDateTime, Arc<Snapshot>)>;
+
+// About how long each file is bencoded, from `20:` to the end of its
+// statistics, so that most snapshots are written in one allocation.
+const FILE_LEN: usize = 96;
+
+// Every swarm as of when it was taken, in info hash order.
+pub struct Snapshot {
+    pub files: Vec<(InfoHash, PeerStatistics)>,
+    pub bencoded: Bytes,
+}
+
+impl Snapshot {
+    fn new(mut files: Vec<(InfoHash, PeerStatistics)>) -> Self {
+        files.sort_by(|(a, _), (b, _)| a.0.cmp(&b.0));
+
+        let mut encoder = StreamEncoder::new(Vec::with_capacity(16 + files.len() * FILE_LEN));
+        let encoded = (|| {
+            encoder.begin_dict()?;
+            encoder.key(b"files")?;
+            encoder.begin_dict()?;
+            for (info_hash, stats) in &files {
+                encoder.key(&info_hash.0)?;
+                encoder.begin_dict()?;
+                encoder.key(b"complete")?;
+                encoder.int(stats.complete as i64)?;
+                encoder.key(b"downloaded")?;
+                encoder.int(stats.downloaded as i64)?;
+                encoder.key(b"incomplete")?;
+                encoder.int(stats.incomplete as i64)?;
+                encoder.end()?;
+            }
+            encoder.end()?;
+            encoder.end()
+        })();
+        // Info hashes are unique and sorted, and a Vec takes any write.
+        encoded.unwrap();
+
+        Self {
+            files,
+            bencoded: encoder.finish().unwrap().into(),
+        }
+    }
+}
+
+// Full scrapes go through every swarm, so they share a snapshot that is
+// taken again once it is too old, by whichever request finds it so while
+// the others wait for it.
+#[derive(Clone)]
+pub struct FullScrapes {
+    peer_repository: Arc<dyn PeerRepository>,
+    torrent_policy: TorrentPolicy,
+    activity_timeout: Duration,
+    refresh: Duration,
+    clock: Clock,
+    latest: Arc<Mutex<Latest>>,
+}
+
+impl FullScrapes {
+    pub fn new(config: &Config, services: &Services) -> Self {
+        Self {
+            peer_repository: services.peer_repository.clone(),
+            torrent_policy: services.torrent_policy.clone(),
+            activity_timeout: Duration::seconds(config.activity_timeout() as i64),
+            refresh: Duration::seconds(config.scrape.full_scrape_refresh as i64),
+            clock: Arc::new(OffsetDateTime::now_utc),
+            latest: Arc::default(),
+        }
+    }
+
+    // Unless the store fails, in which case the next request tries again.
+    pub async fn snapshot(&self) -> Result<Arc<Snapshot>, repository::Error> {
+        let mut latest = self.latest.lock().await;
+        let now = (self.clock)();
+        if let Some((taken, snapshot)) = &*latest {
+            if now - *taken < self.refresh {
+                return Ok(snapshot.clone());
+            }
+        }
+
+        let swarms = self
+            .peer_repository
+            .iter_swarms(IterSwarms {
+                active_after: now - self.activity_timeout,
+            })
+            .await?;
+        let files = swarms
+            .into_iter()
+            .filter(|swarm| self.torrent_policy.allows(&swarm.info_hash))
+            .map(|swarm| (swarm.info_hash, swarm.statistics))
+            .collect();
+
+        let snapshot = Arc::new(Snapshot::new(files));
+        *latest = Some((now, snapshot.clone()));
+        Ok(snapshot)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use hanekawa_common::{
+        repository::{
+            peer::UpdatePeerAnnounce,
+            peer::{GetPeerStatistics, GetPeers, GetSwarmDetail, PurgeSwarm, SetConnectable},
+            Error,
+        },
+        types::{Peer, SwarmMember, SwarmSummary},
+    };
+    use std::{
+        collections::HashMap,
+        sync::atomic::{AtomicBool, AtomicU8, Ordering},
+    };
+
+    // A swarm more each time it is gone through, under `1…`, `2…` and so on,
+    // unless it is down.
+    #[derive(Default)]
+    struct Growing(AtomicU8, AtomicBool);
+
+    #[async_trait::async_trait]
+    impl PeerRepository for Growing {
+        async fn update_peer_announce(&self, _cmd: &UpdatePeerAnnounce) -> Result<(), Error> {
+            Ok(())
+        }
+
+        async fn get_peers(&self, _cmd: GetPeers<'_>) -> Result<Vec<Peer>, Error> {
+            Ok(vec![])
+        }
+
+        async fn get_peer_statistics(
+            &self,
+            _cmd: GetPeerStatistics<'_>,
+        ) -> Result<HashMap<InfoHash, PeerStatistics>, Error> {
+            Ok(HashMap::new())
+        }
+
+        async fn iter_swarms(&self, cmd: IterSwarms) -> Result<Vec<SwarmSummary>, Error> {
+            if self.1.load(Ordering::SeqCst) {
+                return Err(Error::Backend("connection refused".to_string()));
+            }
+            let swarms = self.0.fetch_add(1, Ordering::SeqCst) + 1;
+            Ok((1..=swarms)
+                .map(|n| SwarmSummary {
+                    info_hash: InfoHash(vec![n; 20]),
+                    statistics: PeerStatistics::default(),
+                    last_activity: cmd.active_after,
+                })
+                .collect())
+        }
+
+        async fn get_swarm_detail(
+            &self,
+            _cmd: GetSwarmDetail<'_>,
+        ) -> Result<Vec<SwarmMember>, Error> {
+            Ok(vec![])
+        }
+
+        async fn purge_swarm(&self, _cmd: PurgeSwarm<'_>) -> Result<(), Error> {
+            Ok(())
+        }
+
+        async fn set_connectable(&self, _cmd: SetConnectable) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn answers_from_the_snapshot_until_it_is_too_old() {
+        let now = Arc::new(std::sync::Mutex::new(OffsetDateTime::now_utc()));
+        let clock = now.clone();
+        let full_scrapes = FullScrapes {
+            peer_repository: Arc::new(Growing::default()),
+            torrent_policy: TorrentPolicy::open(),
+            activity_timeout: Duration::hours(1),
+            refresh: Duration::seconds(60),
+            clock: Arc::new(move || *clock.lock().unwrap()),
+            latest: Arc::default(),
+        };
+        let files = |snapshot: Arc<Snapshot>| snapshot.files.len();
+
+        let (a, b) = tokio::join!(full_scrapes.snapshot(), full_scrapes.snapshot());
+        assert_eq!((1, 1), (files(a.unwrap()), files(b.unwrap())));
+        *now.lock().unwrap() += Duration::seconds(59);
+        assert_eq!(1, files(full_scrapes.snapshot().await.unwrap()));
+
+        *now.lock().unwrap() += Duration::seconds(1);
+        assert_eq!(2, files(full_scrapes.snapshot().await.unwrap()));
+        assert_eq!(2, files(full_scrapes.clone().snapshot().await.unwrap()));
+    }
+
+    #[tokio::test]
+    async fn takes_the_snapshot_again_after_the_store_fails() {
+        let store = Arc::new(Growing::default());
+        let full_scrapes = FullScrapes {
+            peer_repository: store.clone(),
+            torrent_policy: TorrentPolicy::open(),
+            activity_timeout: Duration::hours(1),
+            refresh: Duration::seconds(60),
+            clock: Arc::new(OffsetDateTime::now_utc),
+            latest: Arc::default(),
+        };
+
+        store.1.store(true, Ordering::SeqCst);
+        assert!(full_scrapes.snapshot().await.is_err());
+        store.1.store(false, Ordering::SeqCst);
+        assert_eq!(1, full_scrapes.snapshot().await.unwrap().files.len());
+    }
+
+    #[test]
+    fn bencodes_files_in_info_hash_order() {
+        let stats = |complete, downloaded, incomplete| PeerStatistics {
+            complete,
+            downloaded,
+            incomplete,
+        };
+        let snapshot = Snapshot::new(vec![
+            (InfoHash(vec![b'b'; 20]), stats(1, 2, 3)),
+            (InfoHash(vec![b'a'; 20]), stats(10, 0, 300)),
+        ]);
+
+        let expected = format!(
+            "d5:filesd20:{}d8:completei10e10:downloadedi0e10:incompletei300ee\
+             20:{}d8:completei1e10:downloadedi2e10:incompletei3eeee",
+            "a".repeat(20),
+            "b".repeat(20)
+        );
+        assert_eq!(expected.as_bytes(), &snapshot.bencoded[..]);
+        assert_eq!(InfoHash(vec![b'a'; 20]), snapshot.files[0].0);
+    }
+}
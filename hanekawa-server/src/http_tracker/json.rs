@@ -0,0 +1,153 @@
+use super::full_scrape::Snapshot;
+use crate::http::encode::{Bencode, Format};
+
+use hanekawa::http_tracker::proto::{warning_message, AnnounceResponse, PeerData, ScrapeResponse};
+use hanekawa_common::types::PeerStatistics;
+
+use axum::response::{IntoResponse, Response};
+use serde_json::{json, Map, Value};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+// The same response as it is in bencode, but with peers as objects and
+// bytes in hex.
+pub(super) trait ToJson {
+    fn to_json(&self) -> Value;
+}
+
+pub(super) fn respond<T: serde::Serialize + ToJson>(format: Format, response: T) -> Response {
+    match format {
+        Format::Bencode => Bencode(response).into_response(),
+        Format::Json => axum::Json(response.to_json()).into_response(),
+    }
+}
+
+fn peers(data: &PeerData, ip_len: usize) -> Value {
+    match data {
+        PeerData::Compact(bytes) => bytes
+            .chunks_exact(ip_len + 2)
+            .map(|peer| {
+                let (ip, port) = peer.split_at(ip_len);
+                let ip = match <[u8; 4]>::try_from(ip) {
+                    Ok(ip) => IpAddr::V4(Ipv4Addr::from(ip)),
+                    Err(_) => IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(ip).unwrap())),
+                };
+                json!({
+                    "ip": ip,
+                    "port": u16::from_be_bytes([port[0], port[1]]),
+                })
+            })
+            .collect(),
+        PeerData::Long { peers, peer_ids } => peers
+            .iter()
+            .map(|peer| match peer_ids {
+                true => json!({
+                    "peer id": hex::encode(&peer.peer_id.0),
+                    "ip": peer.ip,
+                    "port": peer.port,
+                }),
+                false => json!({
+                    "ip": peer.ip,
+                    "port": peer.port,
+                }),
+            })
+            .collect(),
+    }
+}
+
+fn statistics(stats: &PeerStatistics) -> Map<String, Value> {
+    let mut map = Map::new();
+    map.insert("complete".to_string(), stats.complete.into());
+    map.insert("downloaded".to_string(), stats.downloaded.into());
+    map.insert("incomplete".to_string(), stats.incomplete.into());
+    map
+}
+
+impl ToJson for AnnounceResponse {
+    fn to_json(&self) -> Value {
+        let mut map = Map::new();
+        map.insert("interval".to_string(), self.interval.into());
+        map.insert("min interval".to_string(), self.min_interval.into());
+        map.insert("peers".to_string(), peers(&self.peers, 4));
+        map.insert("peers6".to_string(), peers(&self.peers6, 16));
+        if let Some(stats) = &self.stats {
+            map.extend(statistics(stats));
+        }
+        if !self.warnings.is_empty() {
+            let message = warning_message(&self.warnings);
+            map.insert("warning message".to_string(), message.into());
+        }
+        Value::Object(map)
+    }
+}
+
+impl ToJson for Snapshot {
+    fn to_json(&self) -> Value {
+        let files = self
+            .files
+            .iter()
+            .map(|(info_hash, stats)| (hex::encode(&info_hash.0), statistics(stats).into()))
+            .collect::<Map<_, _>>();
+        json!({ "files": files })
+    }
+}
+
+impl ToJson for ScrapeResponse {
+    fn to_json(&self) -> Value {
+        let files = self
+            .files
+            .iter()
+            .map(|(info_hash, stats)| (hex::encode(&info_hash.0), statistics(stats).into()))
+            .collect::<Map<_, _>>();
+        json!({ "files": files })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use hanekawa_common::types::{Peer, PeerId, PeerSource};
+
+    #[test]
+    fn renders_compact_and_long_peers_alike() {
+        let peer = Peer {
+            peer_id: PeerId(vec![b'a'; 20]),
+            ip: "2001:db8::1".parse().unwrap(),
+            port: 6881,
+            connectable: None,
+            seeding: false,
+            source: PeerSource::default(),
+        };
+
+        assert_eq!(
+            json!([{"ip": "192.0.2.1", "port": 6881}]),
+            peers(&PeerData::Compact(vec![192, 0, 2, 1, 0x1a, 0xe1].into()), 4)
+        );
+        let mut compact6 = "2001:db8::1".parse::<Ipv6Addr>().unwrap().octets().to_vec();
+        compact6.extend_from_slice(&6881u16.to_be_bytes());
+        assert_eq!(
+            json!([{"ip": "2001:db8::1", "port": 6881}]),
+            peers(&PeerData::Compact(compact6.into()), 16)
+        );
+        assert_eq!(
+            json!([{"peer id": hex::encode([b'a'; 20]), "ip": "2001:db8::1", "port": 6881}]),
+            peers(
+                &PeerData::Long {
+                    peers: vec![peer.clone()],
+                    peer_ids: true
+                },
+                16
+            )
+        );
+        assert_eq!(
+            json!([{"ip": "2001:db8::1", "port": 6881}]),
+            peers(
+                &PeerData::Long {
+                    peers: vec![peer],
+                    peer_ids: false
+                },
+                16
+            )
+        );
+    }
+}
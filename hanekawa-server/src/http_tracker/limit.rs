@@ -0,0 +1,246 @@
+use super::response::TrackerError;
+use crate::http::{client_ip::ClientIp, encode::Format};
+
+use hanekawa::http_tracker::proto::Error;
+use hanekawa_common::{BucketConfig, RateLimitConfig};
+
+use axum::{
+    extract::State,
+    http::Request,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+};
+use time::OffsetDateTime;
+
+type Clock = Arc<dyn Fn() -> OffsetDateTime + Send + Sync>;
+
+// Buckets kept at once. Full ones are dropped to make room, as they are the
+// same as none, and new addresses past it go unlimited until there is some.
+const MAX_TRACKED: usize = 1 << 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Kind {
+    Announce,
+    Scrape,
+}
+
+struct Bucket {
+    tokens: f64,
+    at: OffsetDateTime,
+}
+
+// At least one of each, so that no bucket stays empty for good.
+fn burst(config: &BucketConfig) -> f64 {
+    config.burst.max(1) as f64
+}
+
+fn per_minute(config: &BucketConfig) -> f64 {
+    config.per_minute.max(1) as f64
+}
+
+impl Bucket {
+    fn refilled(&self, config: &BucketConfig, now: OffsetDateTime) -> f64 {
+        let elapsed = (now - self.at).as_seconds_f64().max(0.0);
+        (self.tokens + elapsed * per_minute(config) / 60.0).min(burst(config))
+    }
+}
+
+#[derive(Clone)]
+pub struct RateLimiter {
+    config: Arc<RateLimitConfig>,
+    clock: Clock,
+    buckets: Arc<Mutex<HashMap<(IpAddr, Kind), Bucket>>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: &RateLimitConfig) -> Self {
+        Self {
+            config: Arc::new(config.clone()),
+            clock: Arc::new(OffsetDateTime::now_utc),
+            buckets: Arc::default(),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn with_clock(
+        mut self,
+        clock: impl Fn() -> OffsetDateTime + Send + Sync + 'static,
+    ) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    fn bucket(&self, kind: Kind) -> &BucketConfig {
+        match kind {
+            Kind::Announce => &self.config.announce,
+            Kind::Scrape => &self.config.scrape,
+        }
+    }
+
+    // Err with the seconds until the address may send another.
+    pub fn take(&self, ip: IpAddr, kind: Kind) -> Result<(), u32> {
+        if self.config.exempt.iter().any(|cidr| cidr.contains(ip)) {
+            return Ok(());
+        }
+
+        let now = (self.clock)();
+        let config = self.bucket(kind);
+        let key = (ip, kind);
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        if !buckets.contains_key(&key) && buckets.len() >= MAX_TRACKED {
+            buckets.retain(|(_, kind), bucket| {
+                let config = self.bucket(*kind);
+                bucket.refilled(config, now) < burst(config)
+            });
+            if buckets.len() >= MAX_TRACKED {
+                return Ok(());
+            }
+        }
+
+        let bucket = buckets.entry(key).or_insert(Bucket {
+            tokens: burst(config),
+            at: now,
+        });
+        let tokens = bucket.refilled(config, now);
+        bucket.at = now;
+        match tokens >= 1.0 {
+            true => {
+                bucket.tokens = tokens - 1.0;
+                Ok(())
+            }
+            false => {
+                bucket.tokens = tokens;
+                Err(((1.0 - tokens) * 60.0 / per_minute(config)).ceil().max(1.0) as u32)
+            }
+        }
+    }
+}
+
+// Limited requests get a failure reason with when to retry, as clients make
+// nothing of a 429.
+pub async fn limit<B>(
+    State(limiter): State<RateLimiter>,
+    format: Format,
+    ClientIp(ip): ClientIp,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let kind = match request.uri().path().split('/').any(|s| s == "scrape") {
+        true => Kind::Scrape,
+        false => Kind::Announce,
+    };
+
+    match limiter.take(ip, kind) {
+        Ok(()) => next.run(request).await,
+        Err(seconds) => TrackerError::new(Error::RateLimited(seconds), format).into_response(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use axum::{body::Body, extract::ConnectInfo, middleware, routing::get, Router};
+    use std::net::SocketAddr;
+    use time::Duration;
+    use tower::ServiceExt;
+
+    fn config() -> RateLimitConfig {
+        RateLimitConfig {
+            enabled: true,
+            announce: BucketConfig {
+                burst: 3,
+                per_minute: 30,
+            },
+            scrape: BucketConfig {
+                burst: 1,
+                per_minute: 1,
+            },
+            exempt: vec!["127.0.0.0/8".parse().unwrap()],
+        }
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn limits_each_address_until_its_bucket_refills() {
+        let now = Arc::new(Mutex::new(OffsetDateTime::now_utc()));
+        let clock = now.clone();
+        let limiter = RateLimiter::new(&config()).with_clock(move || *clock.lock().unwrap());
+        let advance = |by| *now.lock().unwrap() += by;
+        let (a, b) = (ip("192.0.2.1"), ip("2001:db8::1"));
+
+        for _ in 0..3 {
+            assert_eq!(Ok(()), limiter.take(a, Kind::Announce));
+        }
+        assert_eq!(Err(2), limiter.take(a, Kind::Announce));
+        // Other addresses and the other kind have buckets of their own.
+        assert_eq!(Ok(()), limiter.take(b, Kind::Announce));
+        assert_eq!(Ok(()), limiter.take(a, Kind::Scrape));
+        assert_eq!(Err(60), limiter.take(a, Kind::Scrape));
+
+        advance(Duration::seconds(1));
+        assert_eq!(Err(1), limiter.take(a, Kind::Announce));
+        advance(Duration::seconds(1));
+        assert_eq!(Ok(()), limiter.take(a, Kind::Announce));
+        assert!(limiter.take(a, Kind::Announce).is_err());
+
+        // Refilled, but no further than the burst.
+        advance(Duration::hours(1));
+        for _ in 0..3 {
+            assert_eq!(Ok(()), limiter.take(a, Kind::Announce));
+        }
+        assert!(limiter.take(a, Kind::Announce).is_err());
+
+        for _ in 0..10 {
+            assert_eq!(Ok(()), limiter.take(ip("127.0.0.1"), Kind::Announce));
+        }
+    }
+
+    #[tokio::test]
+    async fn answers_limited_requests_with_when_to_retry() {
+        let router = Router::new()
+            .route("/scrape", get(|| async { "ok" }))
+            .route_layer(middleware::from_fn_with_state(
+                RateLimiter::new(&config()),
+                limit,
+            ));
+        let request = |from: &str| {
+            let mut request = Request::get("/scrape").body(Body::empty()).unwrap();
+            let from: SocketAddr = from.parse().unwrap();
+            request.extensions_mut().insert(ConnectInfo(from));
+            request
+        };
+        let body = |response: Response| async {
+            hyper::body::to_bytes(response.into_body()).await.unwrap()
+        };
+
+        let response = router
+            .clone()
+            .oneshot(request("192.0.2.1:1"))
+            .await
+            .unwrap();
+        assert_eq!(&b"ok"[..], body(response).await);
+
+        let response = router
+            .clone()
+            .oneshot(request("192.0.2.1:2"))
+            .await
+            .unwrap();
+        assert_eq!(200, response.status().as_u16());
+        assert_eq!(
+            &b"d14:failure reason38:too many requests, retry in 60 seconds8:retry ini1ee"[..],
+            body(response).await
+        );
+
+        let response = router.oneshot(request("192.0.2.2:1")).await.unwrap();
+        assert_eq!(&b"ok"[..], body(response).await);
+    }
+}
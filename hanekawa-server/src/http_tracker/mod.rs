@@ -1,47 +1,124 @@
+mod full_scrape;
+mod json;
+mod limit;
 mod response;
+mod screen;
 
 use self::response::OrFailure;
 
-use super::http::encode::Bencode;
+use super::http::client_ip::{ClientIp, TrustedProxies};
+use super::http::compress::compress;
+use super::http::encode::{Bencoded, Format, JsonResponses};
 use super::http::extractor::Query;
 
-use response::Failure;
+use full_scrape::FullScrapes;
+use json::{respond, ToJson};
+use limit::RateLimiter;
+use response::TrackerError;
 
-use hanekawa::http_tracker::proto::{
-    AnnounceRequest, AnnounceResponse, ScrapeRequest, ScrapeResponse,
-};
+use hanekawa::http_tracker::proto::{AnnounceRequest, ScrapeRequest};
 use hanekawa::http_tracker::HttpTrackerService;
 
-use axum::extract::{ConnectInfo, State};
+use axum::extract::{Path, State};
+use axum::middleware;
+use axum::response::{IntoResponse, Response};
 use axum::routing::get;
-use axum::Router;
+use axum::{Extension, Router};
 use hanekawa_common::{Config, Services};
 
+// Private trackers hand out announce URLs with a passkey in the path, before
+// or after the endpoint, and only they serve those.
 async fn announce(
-    OrFailure(Query(announce)): OrFailure<Query<AnnounceRequest>>,
+    format: Format,
+    OrFailure(Query(mut announce)): OrFailure<Query<AnnounceRequest>>,
+    passkey: Option<Path<String>>,
     State(tracker): State<HttpTrackerService>,
-    ConnectInfo(info): ConnectInfo<std::net::SocketAddr>,
-) -> Result<Bencode<AnnounceResponse>, Failure> {
-    // TODO: extract true source IP from potential proxies.
-    let response = tracker.announce(announce, info.ip()).await?;
+    ClientIp(ip): ClientIp,
+) -> Result<Response, TrackerError> {
+    announce.passkey = passkey.map(|Path(p)| p);
+    let response = tracker
+        .announce(announce, ip)
+        .await
+        .map_err(|e| TrackerError::new(e, format))?;
 
-    Ok(Bencode(response))
+    Ok(respond(format, response))
 }
 
 // BEP 48: Tracker Protocol Extension: Scrape
 async fn scrape(
-    OrFailure(Query(scrape)): OrFailure<Query<ScrapeRequest>>,
+    format: Format,
+    OrFailure(Query(mut scrape)): OrFailure<Query<ScrapeRequest>>,
+    passkey: Option<Path<String>>,
     State(tracker): State<HttpTrackerService>,
-) -> Result<Bencode<ScrapeResponse>, Failure> {
-    let response = tracker.scrape(scrape).await?;
-    Ok(Bencode(response))
+    Extension(full_scrapes): Extension<FullScrapes>,
+    ClientIp(ip): ClientIp,
+) -> Result<Response, TrackerError> {
+    scrape.passkey = passkey.map(|Path(p)| p);
+    // Without info hashes, of every torrent.
+    if scrape.info_hash.is_empty() {
+        tracker
+            .admit_full_scrape(scrape.passkey.as_deref(), ip)
+            .map_err(|e| TrackerError::new(e, format))?;
+        let snapshot = full_scrapes
+            .snapshot()
+            .await
+            .map_err(|e| TrackerError::new(e.into(), format))?;
+        return Ok(match format {
+            Format::Bencode => Bencoded(snapshot.bencoded.clone()).into_response(),
+            Format::Json => axum::Json(snapshot.to_json()).into_response(),
+        });
+    }
+
+    let response = tracker
+        .scrape(scrape, ip)
+        .await
+        .map_err(|e| TrackerError::new(e, format))?;
+    Ok(respond(format, response))
 }
 
 pub async fn tracker<S>(cfg: &Config, services: Services) -> Router<S> {
+    let screen = (services.offenders.clone(), services.clock.clone());
+    let full_scrapes = FullScrapes::new(cfg, &services);
     let tracker = HttpTrackerService::new(cfg, services);
 
-    Router::new()
+    let mut router = Router::new()
         .route("/announce", get(announce))
-        .route("/scrape", get(scrape))
-        .with_state(tracker)
+        .route("/scrape", get(scrape));
+    // Only private trackers have passkeys to put in the path, and refuse
+    // announces without one.
+    if cfg.passkeys.required {
+        router = router
+            .route("/:passkey/announce", get(announce))
+            .route("/:passkey/scrape", get(scrape))
+            .route("/announce/:passkey", get(announce))
+            .route("/scrape/:passkey", get(scrape));
+    }
+    let mut router = router
+        .layer(Extension(full_scrapes))
+        .route_layer(middleware::from_fn(response::catch_panic))
+        .route_layer(middleware::from_fn_with_state(screen, screen::screen));
+    // Before the screen, so that repeats count against the limit too.
+    if cfg.rate_limit.enabled {
+        router = router.route_layer(middleware::from_fn_with_state(
+            RateLimiter::new(&cfg.rate_limit),
+            limit::limit,
+        ));
+    }
+    if cfg.compression.enabled {
+        router = router.route_layer(middleware::from_fn_with_state(
+            cfg.compression.clone(),
+            compress,
+        ));
+    }
+    // Requests that cannot be parsed are still answered in bencode, as the
+    // screen replays those answers to whoever sends them again.
+    if cfg.json_responses {
+        router = router.layer(Extension(JsonResponses));
+    }
+    if !cfg.trusted_proxies.is_empty() {
+        let trusted = TrustedProxies(cfg.trusted_proxies.clone().into());
+        router = router.layer(Extension(trusted));
+    }
+
+    router.with_state(tracker)
 }
@@ -1,41 +1,79 @@
-use crate::http::encode::Bencode;
+use crate::http::encode::{Bencode, Format};
 use axum::extract::FromRequestParts;
 use axum::http::request::Parts;
-use hanekawa::http_tracker::proto::Error;
+use hanekawa::http_tracker::proto::{Error, RetryIn};
 
-use axum::http::StatusCode;
-use axum::response::IntoResponse;
+use axum::http::Request;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use futures::FutureExt;
+use std::panic::AssertUnwindSafe;
 
-pub struct Failure(Error);
+// Clients only understand a failure reason, and many of them only read it
+// from a 200, so failures are all sent as one. The rest goes to the
+// screen in an extension.
+pub struct TrackerError(Error, Format);
 
-impl From<Error> for Failure {
-    fn from(value: Error) -> Self {
-        Self(value)
+impl TrackerError {
+    pub fn new(error: Error, format: Format) -> Self {
+        Self(error, format)
     }
 }
 
+#[derive(Clone, Copy)]
+pub(super) struct Failed {
+    // Sent again, it fails again.
+    pub invalid: bool,
+}
+
 #[derive(serde::Serialize)]
-struct FailureResponse {
+pub(super) struct FailureResponse {
     #[serde(rename = "failure reason")]
-    reason: String,
+    pub reason: String,
+    // BEP 31: Tracker Returns HTTP Error Codes.
+    #[serde(rename = "retry in", skip_serializing_if = "Option::is_none")]
+    pub retry_in: Option<RetryIn>,
 }
 
-impl IntoResponse for Failure {
-    fn into_response(self) -> axum::response::Response {
+impl IntoResponse for TrackerError {
+    fn into_response(self) -> Response {
         let failure_reason = FailureResponse {
             reason: self.0.to_string(),
+            retry_in: self.0.retry_in(),
+        };
+
+        let failed = Failed {
+            invalid: matches!(
+                self.0,
+                Error::InvalidRequest(_) | Error::TooManyInfoHashes(_)
+            ),
         };
 
-        let status_code = match self.0 {
-            Error::ServerError(_) => StatusCode::INTERNAL_SERVER_ERROR,
-            Error::InfoHashNotAllowed(_) => StatusCode::FORBIDDEN,
-            Error::Other(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        let mut response = match self.1 {
+            Format::Bencode => Bencode(failure_reason).into_response(),
+            Format::Json => axum::Json(failure_reason).into_response(),
         };
+        response.extensions_mut().insert(failed);
+        response
+    }
+}
 
-        (status_code, Bencode(failure_reason)).into_response()
+// A handler that panics is a server error like any other, rather than a
+// dropped connection. Failures the tracker knows of, such as the store's,
+// are answered before it comes to this.
+pub async fn catch_panic<B>(request: Request<B>, next: Next<B>) -> Response {
+    match AssertUnwindSafe(next.run(request)).catch_unwind().await {
+        Ok(response) => response,
+        Err(_) => {
+            tracing::error!("announce or scrape panicked");
+            let error = Error::ServerError("internal error".to_string());
+            TrackerError::new(error, Format::Bencode).into_response()
+        }
     }
 }
 
+// Any extractor, with its rejection as the failure reason. Requests that
+// cannot be read are answered in bencode.
 pub struct OrFailure<T>(pub T);
 
 #[async_trait::async_trait]
@@ -43,23 +81,48 @@ impl<S: Sync, T> FromRequestParts<S> for OrFailure<T>
 where
     T: FromRequestParts<S> + Send + Sync,
 {
-    type Rejection = axum::response::Response;
+    type Rejection = TrackerError;
 
     async fn from_request_parts(parts: &mut Parts, s: &S) -> Result<Self, Self::Rejection> {
-        use axum::body::HttpBody;
+        let rejection = match T::from_request_parts(parts, s).await {
+            Ok(result) => return Ok(Self(result)),
+            Err(rejection) => rejection.into_response(),
+        };
+        let body = hyper::body::to_bytes(rejection.into_body())
+            .await
+            .unwrap_or_default();
+        let reason = String::from_utf8_lossy(&body).to_string();
+
+        Err(TrackerError::new(
+            Error::InvalidRequest(reason),
+            Format::Bencode,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
 
-        let result = T::from_request_parts(parts, s).await.map_err(|resp| {
-            let resp = resp.into_response();
-            let sc = resp.status();
-            let body = resp.into_body().map_data(|bs| {
-                let body = String::from_utf8_lossy(&bs).to_string();
-                let failure = FailureResponse { reason: body };
-                hanekawa_bencode::to_bytes(&failure).unwrap()
-            });
+    use axum::{body::Body, middleware, routing::get, Router};
+    use tower::ServiceExt;
 
-            (sc, body).into_response()
-        })?;
+    #[tokio::test]
+    async fn answers_panics_with_a_failure() {
+        async fn panics() -> &'static str {
+            panic!("oops")
+        }
+        let router = Router::new()
+            .route("/announce", get(panics))
+            .route_layer(middleware::from_fn(catch_panic));
 
-        Ok(Self(result))
+        let request = Request::get("/announce").body(Body::empty()).unwrap();
+        let response = router.oneshot(request).await.unwrap();
+        assert_eq!(200, response.status().as_u16());
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(
+            &b"d14:failure reason28:server error: internal error8:retry ini1ee"[..],
+            body
+        );
     }
 }
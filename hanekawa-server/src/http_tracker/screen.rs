@@ -0,0 +1,68 @@
+use super::response::{Failed, TrackerError};
+use crate::http::{client_ip::ClientIp, encode::Format};
+
+use hanekawa::http_tracker::proto::Error;
+use hanekawa_common::{
+    offense::{Offenders, Standing},
+    Clock,
+};
+
+use axum::{
+    extract::State,
+    http::{header::CONTENT_TYPE, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+// The same path and query string is the same request, passkey and all.
+fn fingerprint<B>(request: &Request<B>) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    request
+        .uri()
+        .path_and_query()
+        .map(|pq| pq.as_str())
+        .hash(&mut hasher);
+    hasher.finish()
+}
+
+// Keeps clients that send the same broken request over and over from costing
+// more than a lookup. Failures say whether the request itself was at fault.
+// Timed out clients are told when to come back in a failure reason, as they
+// make nothing of a 429.
+pub async fn screen<B>(
+    State((offenders, clock)): State<(Offenders, Clock)>,
+    format: Format,
+    ClientIp(ip): ClientIp,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let fingerprint = fingerprint(&request);
+    match offenders.standing(ip, fingerprint) {
+        Standing::Good => {}
+        Standing::Repeated(body) => {
+            return ([(CONTENT_TYPE, "application/octet-stream")], body.to_vec()).into_response()
+        }
+        Standing::TimedOut { until } => {
+            let seconds = (until - clock()).whole_seconds().clamp(1, u32::MAX as i64);
+            let error = Error::TimedOut(seconds as u32);
+            return TrackerError::new(error, format).into_response();
+        }
+    }
+
+    let response = next.run(request).await;
+    match response.extensions().get::<Failed>() {
+        None if response.status().is_success() => offenders.valid(ip),
+        Some(Failed { invalid: true }) => {
+            let (parts, body) = response.into_parts();
+            let Ok(body) = hyper::body::to_bytes(body).await else {
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            };
+            offenders.invalid(ip, fingerprint, &body[..]);
+            return Response::from_parts(parts, axum::body::boxed(axum::body::Full::new(body)));
+        }
+        _ => {}
+    }
+
+    response
+}
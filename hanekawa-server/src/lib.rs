@@ -1,64 +1,379 @@
 mod admin;
 mod config;
+pub mod federation;
+mod health;
 mod http;
 mod http_tracker;
+pub mod logging;
+mod probe;
+mod retention;
+mod stats;
+mod sweep;
 mod task_queue;
+#[cfg(feature = "testkit")]
+pub mod testkit;
 mod udp_tracker;
 
-use std::sync::Arc;
+use std::{
+    io,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
 
-use hanekawa_common::{Config, Services};
+use hanekawa_common::{
+    repository::{
+        audit::AuditRepository, ban::BanRepository, info_hash::InfoHashRepository,
+        memory::MemoryStore, passkey::PasskeyRepository, peer::PeerRepository,
+    },
+    torrent_policy::{PolicyError, TorrentPolicy},
+    Config, Services, StatsVisibility,
+};
 use http_tracker::tracker;
 
 use axum::Router;
+use tokio::task::JoinHandle;
+use tokio_rustls::TlsAcceptor;
 use tokio_util::sync::CancellationToken;
 
-async fn start_http(cfg: Config, services: Services, kt: CancellationToken) {
-    let tracker = tracker(&cfg, services).await;
-    let admin = admin::admin(&cfg).await;
+#[derive(Debug)]
+pub enum Error {
+    Bind(SocketAddr, io::Error),
+    Tls(String),
+    TorrentPolicy(PolicyError),
+    Storage(String),
+    Snapshot(std::path::PathBuf, String),
+    AdminWithoutToken,
+}
 
-    let app = Router::new().nest("/", tracker).nest("/admin", admin);
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Bind(addr, e) => write!(f, "cannot listen on {addr}: {e}"),
+            Self::Tls(e) => write!(f, "cannot set up TLS: {e}"),
+            Self::TorrentPolicy(e) => write!(f, "cannot load the torrent policy: {e}"),
+            Self::Storage(e) => write!(f, "cannot open storage: {e}"),
+            Self::Snapshot(path, e) => write!(f, "cannot use snapshot {}: {e}", path.display()),
+            Self::AdminWithoutToken => {
+                f.write_str("the admin API needs admin_token or admin_tokens")
+            }
+        }
+    }
+}
 
-    axum::Server::bind(&(cfg.bind_ip, cfg.http_bind_port).into())
-        .serve(app.into_make_service_with_connect_info::<std::net::SocketAddr>())
-        .with_graceful_shutdown(async { kt.cancelled().await })
-        .await
-        .unwrap();
+impl std::error::Error for Error {}
+
+// The trackers' bound addresses, which tell the ports when 0 was configured.
+pub struct Listening {
+    pub http_addr: SocketAddr,
+    pub udp_addr: SocketAddr,
+    // Only with `bind_ipv6`.
+    pub http_addr6: Option<SocketAddr>,
+    pub udp_addr6: Option<SocketAddr>,
+    // Only when the admin API has a port of its own.
+    pub admin_addr: Option<SocketAddr>,
+    tasks: Vec<JoinHandle<()>>,
+    kt: CancellationToken,
 }
 
-async fn start_udp(cfg: Config, _services: Services, kt: CancellationToken) {
-    udp_tracker::start(&cfg, kt).await;
+impl Listening {
+    // Waits for both trackers to shut down.
+    pub async fn join(self) {
+        for task in self.tasks {
+            let _ = task.await;
+        }
+    }
+
+    // Waits for the trackers to be cancelled, after which they take no more
+    // connections and requests under way get `grace` to finish before they
+    // are dropped.
+    pub async fn join_within(mut self, grace: Duration) {
+        self.kt.cancelled().await;
+        let finished = tokio::time::timeout(grace, async {
+            for task in &mut self.tasks {
+                let _ = task.await;
+            }
+        })
+        .await;
+
+        if finished.is_err() {
+            tracing::warn!("Dropping requests still under way after {grace:?}");
+            for task in &self.tasks {
+                task.abort();
+            }
+        }
+    }
+
+    pub async fn shut_down(self, grace: Duration) {
+        self.kt.cancel();
+        self.join_within(grace).await
+    }
 }
 
-pub async fn start() {
-    let _ = dotenvy::dotenv();
-    tracing_subscriber::fmt::init();
+// A server's bound address, and its task.
+type Spawned = (SocketAddr, JoinHandle<()>);
 
-    let cfg = crate::config::load_config();
+// IPv6 listeners take IPv6 only, so that the IPv4 one may share the port.
+fn tcp_listener(addr: SocketAddr) -> io::Result<std::net::TcpListener> {
+    use socket2::{Domain, Socket, Type};
 
-    let kt = tokio_util::sync::CancellationToken::new();
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, None)?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(true)?;
+    }
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
 
-    let storage = hanekawa_storage::Services::start(&cfg).await;
-    let queue_conn = hanekawa_queue::QueueConnection::connect(&cfg).await;
+    Ok(socket.into())
+}
 
-    let queue = hanekawa_queue::AmqpTaskQueue::new(queue_conn.clone()).await;
+fn spawn_server(
+    app: Router,
+    addr: SocketAddr,
+    tls: Option<&TlsAcceptor>,
+    kt: CancellationToken,
+) -> Result<Spawned, Error> {
+    let bind_error = |e| Error::Bind(addr, e);
+    let listener = tcp_listener(addr).map_err(bind_error)?;
+    let addr = listener.local_addr().map_err(bind_error)?;
+    let app = app.into_make_service_with_connect_info::<SocketAddr>();
+    let shutdown = async move { kt.cancelled().await };
 
-    let services = hanekawa_common::Services {
-        peer_repository: Arc::new(storage.peer),
-        info_hash_repository: Arc::new(storage.info_hash),
-        task_queue: Arc::new(queue),
+    let task = match tls {
+        None => {
+            let server = axum::Server::from_tcp(listener)
+                .map_err(|e| bind_error(io::Error::other(e)))?
+                .serve(app);
+            tokio::spawn(async move { server.with_graceful_shutdown(shutdown).await.unwrap() })
+        }
+        Some(acceptor) => {
+            let listener = tokio::net::TcpListener::from_std(listener).map_err(bind_error)?;
+            let server =
+                axum::Server::builder(http::tls::incoming(listener, acceptor.clone())).serve(app);
+            tokio::spawn(async move { server.with_graceful_shutdown(shutdown).await.unwrap() })
+        }
     };
 
-    let hh = tokio::spawn(start_http(cfg.clone(), services.clone(), kt.child_token()));
-    let uh = tokio::spawn(start_udp(cfg.clone(), services.clone(), kt.child_token()));
+    Ok((addr, task))
+}
+
+// The tracker's listeners, IPv4 first, and the admin API's own if it has
+// one.
+async fn start_http(
+    cfg: &Config,
+    services: Services,
+    kt: CancellationToken,
+) -> Result<(Vec<Spawned>, Option<Spawned>), Error> {
+    let tls = cfg.tls.as_ref().map(http::tls::acceptor).transpose()?;
+    // It would let anyone change anything.
+    if cfg.enable_admin_api && cfg.admin_token.is_none() && cfg.admin_tokens.is_empty() {
+        return Err(Error::AdminWithoutToken);
+    }
+
+    let admin = Router::new().nest("/admin", admin::admin(cfg, &services).await);
+    let mut tracker = Router::new()
+        .nest("/", tracker(cfg, services.clone()).await)
+        .merge(health::health(&services));
+    if cfg.torrent_stats == StatsVisibility::Public {
+        tracker = tracker.merge(stats::stats(cfg, services));
+    }
+    let (tracker, admin) = match cfg.admin_bind_port {
+        Some(port) => (tracker, Some((admin, port))),
+        None => (tracker.merge(admin), None),
+    };
+
+    let v4 = (cfg.bind_ip, cfg.http_bind_port).into();
+    let mut trackers = vec![spawn_server(
+        tracker.clone(),
+        v4,
+        tls.as_ref(),
+        kt.child_token(),
+    )?];
+    if let Some(ip) = cfg.bind_ipv6 {
+        let v6 = (ip, trackers[0].0.port()).into();
+        trackers.push(spawn_server(tracker, v6, tls.as_ref(), kt.child_token())?);
+    }
+    let admin = admin
+        .map(|(admin, port)| spawn_server(admin, (cfg.bind_ip, port).into(), tls.as_ref(), kt))
+        .transpose()?;
+
+    Ok((trackers, admin))
+}
+
+async fn listen(
+    cfg: &Config,
+    services: Services,
+    kt: CancellationToken,
+) -> Result<Listening, Error> {
+    let (http, admin) = start_http(cfg, services.clone(), kt.child_token()).await?;
+    let mut udp = vec![];
+    let ips = std::iter::once(IpAddr::V4(cfg.bind_ip)).chain(cfg.bind_ipv6.map(IpAddr::V6));
+    for ip in ips {
+        let port = udp
+            .first()
+            .map_or(cfg.udp_bind_port, |(addr, _): &Spawned| addr.port());
+        let addr = SocketAddr::new(ip, port);
+        let spawned = udp_tracker::start(cfg, addr, services.clone(), kt.child_token())
+            .map_err(|e| Error::Bind(addr, e))?;
+        udp.push(spawned);
+    }
+
+    let addr = |spawned: &[Spawned], i: usize| spawned.get(i).map(|(addr, _)| *addr);
+    let (http_addr, http_addr6) = (addr(&http, 0).unwrap(), addr(&http, 1));
+    let (udp_addr, udp_addr6) = (addr(&udp, 0).unwrap(), addr(&udp, 1));
+    let admin_addr = admin.as_ref().map(|(addr, _)| *addr);
+    let tasks = http
+        .into_iter()
+        .chain(udp)
+        .chain(admin)
+        .map(|(_, task)| task)
+        .collect();
+
+    Ok(Listening {
+        http_addr,
+        udp_addr,
+        http_addr6,
+        udp_addr6,
+        admin_addr,
+        tasks,
+        kt,
+    })
+}
+
+// Serves HTTP and UDP announces with the given services until `kt` is
+// cancelled. Whatever was listening already stops if the rest cannot.
+pub async fn serve(
+    cfg: &Config,
+    services: Services,
+    kt: CancellationToken,
+) -> Result<Listening, Error> {
+    let kt = kt.child_token();
+    let listening = listen(cfg, services, kt.clone()).await;
+    if listening.is_err() {
+        kt.cancel();
+    }
+
+    listening
+}
+
+// Where the tracker keeps its swarms, and the bans, audit records and
+// passkeys it loads on start. Those without a repository are kept in memory
+// only.
+#[derive(Clone)]
+pub struct Storage {
+    pub peers: Arc<dyn PeerRepository>,
+    pub info_hashes: Arc<dyn InfoHashRepository>,
+    pub bans: Option<Arc<dyn BanRepository>>,
+    pub audit: Option<Arc<dyn AuditRepository>>,
+    pub passkeys: Option<Arc<dyn PasskeyRepository>>,
+    // The store behind `peers` if it is in memory, to be snapshotted.
+    pub memory: Option<Arc<MemoryStore>>,
+}
+
+impl Storage {
+    // By the scheme of `database_url`: `memory:` keeps nothing past the
+    // process, `redis://` and `rediss://` need the `redis` feature, and
+    // anything else is Postgres.
+    pub async fn connect(cfg: &Config) -> Result<Self, Error> {
+        match cfg.database_url.split(':').next().unwrap_or_default() {
+            "memory" => Ok(Self::in_memory()),
+            "redis" | "rediss" => Self::redis(cfg).await,
+            _ => Self::postgres(cfg).await,
+        }
+    }
 
-    let background_tasks =
-        hanekawa_queue::BackgroundTaskService::new(queue_conn.clone(), services.clone()).await;
+    // Migrated to the latest schema first.
+    pub async fn postgres(cfg: &Config) -> Result<Self, Error> {
+        let storage = hanekawa_storage::Services::start(cfg)
+            .await
+            .map_err(|e| Error::Storage(e.to_string()))?;
 
-    let tkt = kt.child_token();
-    let bt = tokio::spawn(async move { background_tasks.run(tkt).await });
+        Ok(Self {
+            peers: Arc::new(storage.peer),
+            info_hashes: Arc::new(storage.info_hash),
+            bans: Some(Arc::new(storage.ban)),
+            audit: Some(Arc::new(storage.audit)),
+            passkeys: Some(Arc::new(storage.passkey)),
+            memory: None,
+        })
+    }
+
+    // Swarms and info hashes shared by every tracker on the same Redis. The
+    // rest is each tracker's own, in memory.
+    #[cfg(feature = "redis")]
+    pub async fn redis(cfg: &Config) -> Result<Self, Error> {
+        let store = hanekawa_storage::redis::RedisRepository::connect(cfg)
+            .await
+            .map_err(|e| Error::Storage(e.to_string()))?;
+        let store = Arc::new(store);
+
+        Ok(Self {
+            peers: store.clone(),
+            info_hashes: store,
+            bans: None,
+            audit: None,
+            passkeys: None,
+            memory: None,
+        })
+    }
+
+    #[cfg(not(feature = "redis"))]
+    pub async fn redis(_cfg: &Config) -> Result<Self, Error> {
+        Err(Error::Storage(
+            "built without Redis, which the redis feature adds".into(),
+        ))
+    }
+
+    // Nothing outlives the process.
+    pub fn in_memory() -> Self {
+        let store = Arc::new(MemoryStore::new());
+
+        Self {
+            peers: store.clone(),
+            info_hashes: store.clone(),
+            bans: None,
+            audit: None,
+            passkeys: None,
+            memory: Some(store),
+        }
+    }
+}
 
-    let cancel = tokio::spawn(async move {
+// Rows re-encrypted at a time, each batch in a query of its own.
+const REKEY_BATCH: usize = 1000;
+
+// Re-encrypts stored peers under the first configured key, after which
+// older keys may be removed from the config.
+pub async fn rekey() -> Result<(), Error> {
+    let _ = dotenvy::dotenv();
+    let cfg = crate::config::load_config();
+    logging::install(&cfg.logging);
+    if cfg.encryption.keys.is_empty() {
+        eprintln!("no encryption keys are configured");
+        std::process::exit(1);
+    }
+
+    let storage = hanekawa_storage::Services::start(&cfg)
+        .await
+        .map_err(|e| Error::Storage(e.to_string()))?;
+    let rekeyed = storage.peer.rekey(REKEY_BATCH).await;
+    println!("rekeyed {rekeyed} peers");
+
+    Ok(())
+}
+
+// With the config from hanekawa.toml and HKW_ variables, logging as it says,
+// until interrupted or terminated.
+pub async fn start() -> Result<(), Error> {
+    let _ = dotenvy::dotenv();
+    let cfg = crate::config::load_config();
+    logging::install(&cfg.logging);
+
+    let kt = CancellationToken::new();
+    let shutdown = kt.clone();
+    tokio::spawn(async move {
         use tokio::signal::{
             ctrl_c,
             unix::{signal, SignalKind},
@@ -74,8 +389,231 @@ pub async fn start() {
         }
 
         tracing::info!("Shutting down...");
-        kt.cancel();
+        shutdown.cancel();
+    });
+
+    start_with_config(cfg, kt).await
+}
+
+// Until `kt` is cancelled.
+pub async fn start_with_config(cfg: Config, kt: CancellationToken) -> Result<(), Error> {
+    let storage = Storage::connect(&cfg).await?;
+    start_with_storage(cfg, storage, kt).await
+}
+
+// Peers announced since the snapshot was written, or none if it was not.
+fn restore_snapshot(cfg: &Config, store: &MemoryStore) -> Result<(), Error> {
+    let Some(path) = &cfg.snapshot_path else {
+        return Ok(());
+    };
+    let snapshot_error = |e: String| Error::Snapshot(path.clone(), e);
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(snapshot_error(e.to_string())),
+    };
+
+    let timeout = time::Duration::seconds(cfg.activity_timeout() as i64);
+    let active_after = time::OffsetDateTime::now_utc() - timeout;
+    let restored = store
+        .restore(&bytes, active_after)
+        .map_err(|e| snapshot_error(e.to_string()))?;
+    tracing::info!("Restored {restored} peers from {}", path.display());
+
+    Ok(())
+}
+
+// Written next to the last one and moved over it, so that a failed write
+// leaves that one as it was.
+fn write_snapshot(cfg: &Config, store: &MemoryStore) -> Result<(), Error> {
+    let Some(path) = &cfg.snapshot_path else {
+        return Ok(());
+    };
+    let snapshot_error = |e: io::Error| Error::Snapshot(path.clone(), e.to_string());
+    let mut partial = path.clone().into_os_string();
+    partial.push(".partial");
+
+    std::fs::write(&partial, store.snapshot()).map_err(snapshot_error)?;
+    std::fs::rename(&partial, path).map_err(snapshot_error)?;
+    tracing::info!("Wrote the swarms to {}", path.display());
+
+    Ok(())
+}
+
+// Without a message queue URL, announces are recorded as they are answered.
+// Swarms kept in memory are snapshotted if so configured, once everything
+// else has stopped.
+pub async fn start_with_storage(
+    cfg: Config,
+    storage: Storage,
+    kt: CancellationToken,
+) -> Result<(), Error> {
+    if let Some(memory) = &storage.memory {
+        restore_snapshot(&cfg, memory)?;
+    }
+    let memory = storage.memory.clone();
+    let torrent_policy = TorrentPolicy::load(&cfg.torrent_policy).map_err(Error::TorrentPolicy)?;
+
+    let queue_conn = match cfg.message_queue_url.is_empty() {
+        true => None,
+        false => Some(hanekawa_queue::QueueConnection::connect(&cfg).await),
+    };
+
+    let peer_repository = storage.peers;
+    let (prober, probing) = match cfg.probe.enabled {
+        true => {
+            let (prober, task) = probe::ConnectabilityProber::start(
+                &cfg.probe,
+                peer_repository.clone(),
+                kt.child_token(),
+            );
+            (
+                Some(prober as Arc<dyn hanekawa_common::probe::Prober>),
+                Some(task),
+            )
+        }
+        false => (None, None),
+    };
+    let (federation, forwarding) = match cfg.federation.upstreams.is_empty() {
+        false => {
+            let (federation, task) = federation::UpstreamFederation::start(&cfg, kt.child_token());
+            (
+                Some(federation as Arc<dyn hanekawa_common::federation::Federation>),
+                Some(task),
+            )
+        }
+        true => (None, None),
+    };
+
+    let bans = match storage.bans {
+        Some(bans) => hanekawa_common::ban::BanList::load(bans).await.unwrap(),
+        None => hanekawa_common::ban::BanList::in_memory(),
+    };
+    let audit = match storage.audit {
+        Some(audit) => hanekawa_common::audit::AuditLog::new(audit),
+        None => hanekawa_common::audit::AuditLog::in_memory(),
+    };
+    let passkeys = match storage.passkeys {
+        Some(passkeys) => hanekawa_common::passkey::Passkeys::load(passkeys)
+            .await
+            .unwrap(),
+        None => hanekawa_common::passkey::Passkeys::in_memory(),
+    };
+    let offenders = hanekawa_common::offense::Offenders::new(&cfg.invalid_requests);
+    let maintenance = hanekawa_common::maintenance::Maintenance::new(cfg.maintenance.enabled);
+    let peer_limits = hanekawa_common::peer_limit::PeerLimits::new(&cfg);
+    let services = |task_queue| hanekawa_common::Services {
+        peer_repository: peer_repository.clone(),
+        info_hash_repository: storage.info_hashes.clone(),
+        task_queue,
+        bans: bans.clone(),
+        audit: audit.clone(),
+        prober: prober.clone(),
+        federation: federation.clone(),
+        offenders: offenders.clone(),
+        maintenance: maintenance.clone(),
+        passkeys: passkeys.clone(),
+        torrent_policy: torrent_policy.clone(),
+        peer_limits: peer_limits.clone(),
+        clock: hanekawa_common::system_clock(),
+    };
+    let services = match &queue_conn {
+        Some(queue_conn) => {
+            let queue = hanekawa_queue::AmqpTaskQueue::new(queue_conn.clone()).await;
+            services(Arc::new(queue))
+        }
+        None => services(Arc::new(task_queue::InlineQueue(services(Arc::new(
+            task_queue::Unqueued,
+        ))))),
+    };
+
+    let listening = serve(&cfg, services.clone(), kt.child_token()).await?;
+    let sweeping = sweep::SwarmSweeper::new(&cfg, services.clone()).start(kt.child_token());
+    let cleaning = retention::RetentionCleaner::new(&cfg, services.clone())
+        .map(|cleaner| cleaner.start(kt.child_token()));
+
+    let bt = match queue_conn {
+        Some(queue_conn) => {
+            let background_tasks =
+                hanekawa_queue::BackgroundTaskService::new(queue_conn, services.clone()).await;
+            let tkt = kt.child_token();
+            Some(tokio::spawn(async move { background_tasks.run(tkt).await }))
+        }
+        None => None,
+    };
+
+    let maintenance = services.maintenance.clone();
+    let mkt = kt.child_token();
+    let toggling = tokio::spawn(async move {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut usr2 = signal(SignalKind::user_defined2()).unwrap();
+        loop {
+            tokio::select! {
+                _ = mkt.cancelled() => break,
+                _ = usr2.recv() => match maintenance.toggle() {
+                    true => tracing::info!("Entering maintenance mode"),
+                    false => tracing::info!("Leaving maintenance mode"),
+                },
+            }
+        }
+    });
+
+    // Swarms of torrents still served are left as they are.
+    let torrent_policy = services.torrent_policy.clone();
+    let rkt = kt.child_token();
+    let reloading = tokio::spawn(async move {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut hup = signal(SignalKind::hangup()).unwrap();
+        loop {
+            tokio::select! {
+                _ = rkt.cancelled() => break,
+                _ = hup.recv() => match torrent_policy.reload() {
+                    Ok(count) => tracing::info!("Reloaded the torrent policy, {count} listed"),
+                    Err(e) => tracing::error!("Kept the torrent policy as it was: {e}"),
+                },
+            }
+        }
     });
 
-    let _ = tokio::join!(cancel, hh, uh, bt);
+    let probing = async {
+        if let Some(task) = probing {
+            let _ = task.await;
+        }
+    };
+
+    let forwarding = async {
+        if let Some(task) = forwarding {
+            let _ = task.await;
+        }
+    };
+
+    let cleaning = async {
+        if let Some(task) = cleaning {
+            let _ = task.await;
+        }
+    };
+
+    let bt = async {
+        if let Some(task) = bt {
+            let _ = task.await;
+        }
+    };
+
+    let _ = tokio::join!(
+        toggling,
+        reloading,
+        listening.join_within(cfg.shutdown_grace_period()),
+        bt,
+        probing,
+        forwarding,
+        sweeping,
+        cleaning
+    );
+
+    match memory {
+        Some(memory) => write_snapshot(&cfg, &memory),
+        None => Ok(()),
+    }
 }
@@ -0,0 +1,31 @@
+use hanekawa_common::{LogFormat, LoggingConfig};
+
+use tracing_subscriber::{fmt::format::FmtSpan, EnvFilter};
+
+// Logs as configured, unless a subscriber is installed already. `start`
+// installs it, and `start_with_config` leaves it to those embedding the
+// server, who may call this or install their own.
+pub fn install(config: &LoggingConfig) {
+    let filter = EnvFilter::try_from_default_env()
+        .or_else(|_| EnvFilter::try_new(&config.filter))
+        .unwrap_or_else(|e| {
+            eprintln!("invalid log filter {:?}, {e}", config.filter);
+            EnvFilter::new("info")
+        });
+    let span_events = match config.span_timings {
+        true => FmtSpan::CLOSE,
+        false => FmtSpan::NONE,
+    };
+
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_span_events(span_events);
+    let _ = match config.format {
+        LogFormat::Text => builder.compact().try_init(),
+        LogFormat::Json => builder
+            .json()
+            .flatten_event(true)
+            .with_current_span(true)
+            .try_init(),
+    };
+}
@@ -0,0 +1,309 @@
+use hanekawa_common::{
+    probe::Prober,
+    repository::peer::{PeerRepository, SetConnectable},
+    ProbeConfig,
+};
+
+use std::{
+    collections::HashMap,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+use tokio::{
+    net::{TcpStream, UdpSocket},
+    sync::{mpsc, Semaphore},
+    task::JoinHandle,
+    time::MissedTickBehavior,
+};
+use tokio_util::sync::CancellationToken;
+
+// Endpoints waiting for a probe. New ones are dropped while it is full.
+const QUEUE_SIZE: usize = 1024;
+// Endpoints remembered as probed. Past this, new ones are not probed until
+// old ones are due again.
+const MAX_DESTINATIONS: usize = 1 << 16;
+
+// BEP 29: uTP packet types in the high nibble, version 1 in the low one.
+const ST_SYN: u8 = 0x41;
+const ST_STATE: u8 = 0x21;
+
+// Tries a single TCP connection to each submitted endpoint, and a uTP
+// connection request if that fails and it is enabled. Probes are spread out
+// within a global budget, and an endpoint is not probed again until its
+// interval has passed.
+pub struct ConnectabilityProber {
+    queue: mpsc::Sender<SocketAddr>,
+    interval: Duration,
+    // When each endpoint was last submitted.
+    probed: Mutex<HashMap<SocketAddr, Instant>>,
+}
+
+impl ConnectabilityProber {
+    // Probes until `kt` is cancelled.
+    pub fn start(
+        cfg: &ProbeConfig,
+        peer_repository: Arc<dyn PeerRepository>,
+        kt: CancellationToken,
+    ) -> (Arc<Self>, JoinHandle<()>) {
+        let (queue, probes) = mpsc::channel(QUEUE_SIZE);
+        let prober = Arc::new(Self {
+            queue,
+            interval: Duration::from_secs(cfg.destination_interval),
+            probed: Mutex::default(),
+        });
+
+        let task = tokio::spawn(run(cfg.clone(), probes, peer_repository, kt));
+
+        (prober, task)
+    }
+}
+
+impl Prober for ConnectabilityProber {
+    fn submit(&self, endpoint: SocketAddr) {
+        let now = Instant::now();
+        let mut probed = self.probed.lock().unwrap_or_else(|e| e.into_inner());
+
+        let due = |at: &Instant| now.duration_since(*at) >= self.interval;
+        if probed.get(&endpoint).is_some_and(|at| !due(at)) {
+            return;
+        }
+        if probed.len() >= MAX_DESTINATIONS {
+            probed.retain(|_, at| !due(at));
+            if probed.len() >= MAX_DESTINATIONS {
+                return;
+            }
+        }
+
+        if self.queue.try_send(endpoint).is_ok() {
+            probed.insert(endpoint, now);
+        }
+    }
+}
+
+async fn run(
+    cfg: ProbeConfig,
+    mut probes: mpsc::Receiver<SocketAddr>,
+    peer_repository: Arc<dyn PeerRepository>,
+    kt: CancellationToken,
+) {
+    let workers = Arc::new(Semaphore::new(cfg.workers.max(1)));
+    let timeout = Duration::from_millis(cfg.timeout_ms);
+
+    // One probe starts per tick at most, however many workers are idle.
+    let mut budget = tokio::time::interval(Duration::from_secs(1) / cfg.per_second.max(1));
+    budget.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        let endpoint = tokio::select! {
+            _ = kt.cancelled() => break,
+            endpoint = probes.recv() => match endpoint {
+                Some(endpoint) => endpoint,
+                None => break,
+            },
+        };
+        let permit = tokio::select! {
+            _ = kt.cancelled() => break,
+            _ = budget.tick() => workers.clone().acquire_owned().await.unwrap(),
+        };
+
+        let peer_repository = peer_repository.clone();
+        tokio::spawn(async move {
+            let connectable = probe(endpoint, timeout, cfg.udp).await;
+            peer_repository
+                .set_connectable(SetConnectable {
+                    endpoint,
+                    connectable,
+                })
+                .await
+                .unwrap();
+
+            drop(permit);
+        });
+    }
+}
+
+async fn probe(endpoint: SocketAddr, timeout: Duration, udp: bool) -> bool {
+    let tcp = tokio::time::timeout(timeout, TcpStream::connect(endpoint)).await;
+    if matches!(tcp, Ok(Ok(_))) {
+        return true;
+    }
+
+    udp && probe_utp(endpoint, timeout).await.unwrap_or(false)
+}
+
+// A peer taking uTP answers a connection request with a state packet for
+// the same connection id.
+async fn probe_utp(endpoint: SocketAddr, timeout: Duration) -> std::io::Result<bool> {
+    let local: SocketAddr = match endpoint {
+        SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+    let socket = UdpSocket::bind(local).await?;
+    socket.connect(endpoint).await?;
+
+    let micros = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as u32;
+    let connection_id = (micros as u16).to_be_bytes();
+
+    let mut syn = [0; 20];
+    syn[0] = ST_SYN;
+    syn[2..4].copy_from_slice(&connection_id);
+    syn[4..8].copy_from_slice(&micros.to_be_bytes());
+    syn[12..16].copy_from_slice(&(1u32 << 20).to_be_bytes());
+    syn[16..18].copy_from_slice(&1u16.to_be_bytes());
+    socket.send(&syn).await?;
+
+    let mut reply = [0; 20];
+    let answered = match tokio::time::timeout(timeout, socket.recv(&mut reply)).await {
+        Ok(Ok(n)) => n == reply.len() && reply[0] == ST_STATE && reply[2..4] == connection_id,
+        _ => false,
+    };
+
+    Ok(answered)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use hanekawa_common::{
+        repository::{
+            peer::{
+                GetPeerStatistics, GetPeers, GetSwarmDetail, IterSwarms, PurgeSwarm,
+                UpdatePeerAnnounce,
+            },
+            Error,
+        },
+        types::{InfoHash, Peer, PeerStatistics, SwarmMember, SwarmSummary},
+    };
+    use tokio::net::TcpListener;
+
+    #[derive(Default)]
+    struct Recording(Mutex<HashMap<SocketAddr, Vec<bool>>>);
+
+    #[async_trait::async_trait]
+    impl PeerRepository for Recording {
+        async fn update_peer_announce(&self, _cmd: &UpdatePeerAnnounce) -> Result<(), Error> {
+            Ok(())
+        }
+
+        async fn get_peers(&self, _cmd: GetPeers<'_>) -> Result<Vec<Peer>, Error> {
+            Ok(vec![])
+        }
+
+        async fn get_peer_statistics(
+            &self,
+            _cmd: GetPeerStatistics<'_>,
+        ) -> Result<HashMap<InfoHash, PeerStatistics>, Error> {
+            Ok(HashMap::new())
+        }
+
+        async fn iter_swarms(&self, _cmd: IterSwarms) -> Result<Vec<SwarmSummary>, Error> {
+            Ok(vec![])
+        }
+
+        async fn get_swarm_detail(
+            &self,
+            _cmd: GetSwarmDetail<'_>,
+        ) -> Result<Vec<SwarmMember>, Error> {
+            Ok(vec![])
+        }
+
+        async fn purge_swarm(&self, _cmd: PurgeSwarm<'_>) -> Result<(), Error> {
+            Ok(())
+        }
+
+        async fn set_connectable(&self, cmd: SetConnectable) -> Result<(), Error> {
+            let mut flags = self.0.lock().unwrap();
+            flags.entry(cmd.endpoint).or_default().push(cmd.connectable);
+            Ok(())
+        }
+    }
+
+    fn config(udp: bool) -> ProbeConfig {
+        ProbeConfig {
+            enabled: true,
+            udp,
+            timeout_ms: 500,
+            workers: 2,
+            per_second: 100,
+            destination_interval: 3600,
+            connectable_weight: 0,
+        }
+    }
+
+    // A port nothing listens on, over TCP or UDP.
+    async fn closed_port() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        listener.local_addr().unwrap()
+    }
+
+    async fn flags(recording: &Recording, count: usize) -> HashMap<SocketAddr, Vec<bool>> {
+        for _ in 0..100 {
+            let flags = recording.0.lock().unwrap().clone();
+            if flags.values().map(Vec::len).sum::<usize>() >= count {
+                return flags;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+
+        panic!("probes did not finish");
+    }
+
+    #[tokio::test]
+    async fn marks_peers_taking_connections_as_connectable() {
+        let recording = Arc::new(Recording::default());
+        let kt = CancellationToken::new();
+        let (prober, _) =
+            ConnectabilityProber::start(&config(false), recording.clone(), kt.clone());
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let accepting = listener.local_addr().unwrap();
+        let refusing = closed_port().await;
+
+        prober.submit(accepting);
+        prober.submit(refusing);
+        // Not again within the interval.
+        prober.submit(accepting);
+
+        let flags = flags(&recording, 2).await;
+        assert_eq!(Some(&vec![true]), flags.get(&accepting));
+        assert_eq!(Some(&vec![false]), flags.get(&refusing));
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(2, recording.0.lock().unwrap().values().flatten().count());
+        kt.cancel();
+    }
+
+    #[tokio::test]
+    async fn marks_peers_answering_utp_as_connectable() {
+        let recording = Arc::new(Recording::default());
+        let kt = CancellationToken::new();
+        let (prober, _) = ConnectabilityProber::start(&config(true), recording.clone(), kt.clone());
+
+        let peer = UdpSocket::bind(closed_port().await).await.unwrap();
+        let endpoint = peer.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut syn = [0; 20];
+            let (_, from) = peer.recv_from(&mut syn).await.unwrap();
+            assert_eq!(ST_SYN, syn[0]);
+
+            let mut state = [0; 20];
+            state[0] = ST_STATE;
+            state[2..4].copy_from_slice(&syn[2..4]);
+            peer.send_to(&state, from).await.unwrap();
+        });
+        let silent = closed_port().await;
+
+        prober.submit(endpoint);
+        prober.submit(silent);
+
+        let flags = flags(&recording, 2).await;
+        assert_eq!(Some(&vec![true]), flags.get(&endpoint));
+        assert_eq!(Some(&vec![false]), flags.get(&silent));
+        kt.cancel();
+    }
+}
@@ -0,0 +1,198 @@
+use hanekawa_common::{repository::peer::PurgePeers, Config, Services};
+
+use std::{sync::Arc, time::Duration};
+use time::OffsetDateTime;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+// Deletes audit records and announces once they are older than the
+// configured retention, whatever else would keep them.
+pub struct RetentionCleaner {
+    services: Services,
+    retention: Duration,
+    clock: Arc<dyn Fn() -> OffsetDateTime + Send + Sync>,
+}
+
+impl RetentionCleaner {
+    // None if everything is kept for good.
+    pub fn new(cfg: &Config, services: Services) -> Option<Self> {
+        Some(Self {
+            services,
+            retention: Duration::from_secs(cfg.privacy.retention?),
+            clock: Arc::new(OffsetDateTime::now_utc),
+        })
+    }
+
+    #[cfg(test)]
+    fn with_clock(mut self, clock: impl Fn() -> OffsetDateTime + Send + Sync + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    // Cleans up until `kt` is cancelled.
+    pub fn start(self, kt: CancellationToken) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(CLEANUP_INTERVAL);
+            loop {
+                tokio::select! {
+                    _ = kt.cancelled() => break,
+                    _ = interval.tick() => {
+                        self.clean().await;
+                    }
+                }
+            }
+        })
+    }
+
+    // Returns how many announces and audit records were deleted.
+    pub async fn clean(&self) -> (u64, u64) {
+        let before = (self.clock)() - self.retention;
+        let announces = self
+            .services
+            .peer_repository
+            .purge_peers(PurgePeers { before })
+            .await
+            .unwrap();
+        let records = self.services.audit.purge(before).await.unwrap();
+
+        if announces > 0 || records > 0 {
+            tracing::info!("deleted {announces} announces and {records} audit records");
+        }
+
+        (announces, records)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testkit::config;
+
+    use hanekawa_common::{
+        audit::AuditLog,
+        ban::BanList,
+        maintenance::Maintenance,
+        offense::Offenders,
+        passkey::Passkeys,
+        peer_limit::PeerLimits,
+        repository::{
+            memory::MemoryStore,
+            peer::{
+                GetPeerStatistics, GetPeers, GetSwarmDetail, IterSwarms, PeerRepository,
+                PurgeSwarm, SetConnectable, UpdatePeerAnnounce,
+            },
+            Error,
+        },
+        task::{Task, TaskQueue},
+        torrent_policy::TorrentPolicy,
+        types::{InfoHash, Peer, PeerStatistics, SwarmMember, SwarmSummary},
+        InvalidRequestConfig,
+    };
+    use std::{collections::HashMap, sync::Mutex};
+
+    // When each announce was made, which no store but Postgres keeps.
+    #[derive(Default)]
+    struct Announces(Mutex<Vec<OffsetDateTime>>);
+
+    #[async_trait::async_trait]
+    impl PeerRepository for Announces {
+        async fn update_peer_announce(&self, _cmd: &UpdatePeerAnnounce) -> Result<(), Error> {
+            Ok(())
+        }
+
+        async fn get_peers(&self, _cmd: GetPeers<'_>) -> Result<Vec<Peer>, Error> {
+            Ok(vec![])
+        }
+
+        async fn get_peer_statistics(
+            &self,
+            _cmd: GetPeerStatistics<'_>,
+        ) -> Result<HashMap<InfoHash, PeerStatistics>, Error> {
+            Ok(HashMap::new())
+        }
+
+        async fn iter_swarms(&self, _cmd: IterSwarms) -> Result<Vec<SwarmSummary>, Error> {
+            Ok(vec![])
+        }
+
+        async fn get_swarm_detail(
+            &self,
+            _cmd: GetSwarmDetail<'_>,
+        ) -> Result<Vec<SwarmMember>, Error> {
+            Ok(vec![])
+        }
+
+        async fn purge_swarm(&self, _cmd: PurgeSwarm<'_>) -> Result<(), Error> {
+            Ok(())
+        }
+
+        async fn purge_peers(&self, cmd: PurgePeers) -> Result<u64, Error> {
+            let mut announces = self.0.lock().unwrap();
+            let before = announces.len();
+            announces.retain(|at| *at >= cmd.before);
+            Ok((before - announces.len()) as u64)
+        }
+
+        async fn set_connectable(&self, _cmd: SetConnectable) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    struct DiscardingQueue;
+
+    #[async_trait::async_trait]
+    impl TaskQueue for DiscardingQueue {
+        async fn enqueue(&self, _task: &dyn Task) -> Option<()> {
+            Some(())
+        }
+    }
+
+    #[tokio::test]
+    async fn deletes_what_is_past_its_retention() {
+        let start = OffsetDateTime::now_utc();
+        let announces = Arc::new(Announces::default());
+        announces.0.lock().unwrap().extend([
+            start - time::Duration::days(40),
+            start - time::Duration::days(10),
+            start,
+        ]);
+        let audit = AuditLog::in_memory();
+        audit.record(None, "add_ban", "ip", "ok").await.unwrap();
+
+        let services = Services {
+            peer_repository: announces.clone(),
+            info_hash_repository: Arc::new(MemoryStore::new()),
+            task_queue: Arc::new(DiscardingQueue),
+            bans: BanList::in_memory(),
+            audit: audit.clone(),
+            prober: None,
+            federation: None,
+            offenders: Offenders::new(&InvalidRequestConfig::default()),
+            maintenance: Maintenance::default(),
+            passkeys: Passkeys::in_memory(),
+            torrent_policy: TorrentPolicy::open(),
+            peer_limits: PeerLimits::unlimited(),
+            clock: hanekawa_common::system_clock(),
+        };
+
+        let mut cfg = config();
+        assert!(RetentionCleaner::new(&cfg, services.clone()).is_none());
+
+        // Thirty days.
+        cfg.privacy.retention = Some(30 * 24 * 60 * 60);
+        let now = Arc::new(Mutex::new(start));
+        let clock = now.clone();
+        let cleaner = RetentionCleaner::new(&cfg, services)
+            .unwrap()
+            .with_clock(move || *clock.lock().unwrap());
+
+        assert_eq!((1, 0), cleaner.clean().await);
+        *now.lock().unwrap() += time::Duration::days(25);
+        assert_eq!((1, 0), cleaner.clean().await);
+        assert_eq!(vec![start], *announces.0.lock().unwrap());
+        *now.lock().unwrap() += time::Duration::days(10);
+        assert_eq!((1, 1), cleaner.clean().await);
+    }
+}
@@ -0,0 +1,311 @@
+use hanekawa::stats::{Error, StatsService};
+use hanekawa_common::{Config, Services};
+
+use axum::{
+    extract::{Path, State},
+    http::{
+        header::{ETAG, IF_NONE_MATCH},
+        HeaderMap, StatusCode,
+    },
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+
+fn status(e: &Error) -> StatusCode {
+    match e {
+        Error::InvalidInfoHash(_) => StatusCode::BAD_REQUEST,
+        Error::UnknownTorrent => StatusCode::NOT_FOUND,
+        Error::Storage(_) => StatusCode::SERVICE_UNAVAILABLE,
+    }
+}
+
+// Whether any of the client's tags matches, which spares it the body.
+fn fresh(headers: &HeaderMap, etag: &str) -> bool {
+    let Some(tags) = headers.get(IF_NONE_MATCH).and_then(|v| v.to_str().ok()) else {
+        return false;
+    };
+
+    tags.split(',')
+        .map(|tag| tag.trim())
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+async fn torrent_stats(
+    Path(hex_info_hash): Path<String>,
+    State(stats): State<StatsService>,
+    headers: HeaderMap,
+) -> Response {
+    let stats = match stats.torrent(&hex_info_hash).await {
+        Ok(stats) => stats,
+        Err(e) => {
+            let body = serde_json::json!({ "error": e.to_string() });
+            return (status(&e), Json(body)).into_response();
+        }
+    };
+
+    let etag = stats.etag();
+    if fresh(&headers, &etag) {
+        return (StatusCode::NOT_MODIFIED, [(ETAG, etag)]).into_response();
+    }
+
+    ([(ETAG, etag)], Json(stats)).into_response()
+}
+
+pub fn stats<S>(cfg: &Config, services: Services) -> Router<S> {
+    Router::new()
+        .route("/stats/torrent/:info_hash", get(torrent_stats))
+        .with_state(StatsService::new(cfg, services))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use hanekawa_common::{
+        audit::AuditLog,
+        ban::BanList,
+        maintenance::Maintenance,
+        offense::Offenders,
+        passkey::Passkeys,
+        peer_limit::PeerLimits,
+        repository::{
+            info_hash::{GetInfoHashSummary, InfoHashRepository, RegisterTorrent, UpdateInfoHash},
+            memory::MemoryStore,
+            peer::{PeerRepository, UpdatePeerAnnounce},
+            Error as RepositoryError,
+        },
+        task::{Task, TaskQueue},
+        torrent_policy::TorrentPolicy,
+        types::{Event, InfoHash, InfoHashSummary, PeerId, TorrentMetadata, Transport},
+        InvalidRequestConfig, StatsVisibility,
+    };
+    use std::{net::Ipv4Addr, sync::Arc};
+    use time::OffsetDateTime;
+    use tower::ServiceExt;
+
+    struct Unreachable;
+
+    fn unreachable<T>() -> Result<T, RepositoryError> {
+        Err(RepositoryError::Backend("connection refused".to_string()))
+    }
+
+    #[async_trait::async_trait]
+    impl InfoHashRepository for Unreachable {
+        async fn get_info_hash_summary(
+            &self,
+            _cmd: GetInfoHashSummary<'_>,
+        ) -> Result<InfoHashSummary, RepositoryError> {
+            unreachable()
+        }
+
+        async fn update_info_hash(&self, _cmd: UpdateInfoHash<'_>) -> Result<(), RepositoryError> {
+            unreachable()
+        }
+
+        async fn register_torrent(&self, _cmd: RegisterTorrent<'_>) -> Result<(), RepositoryError> {
+            unreachable()
+        }
+    }
+
+    struct DiscardingQueue;
+
+    #[async_trait::async_trait]
+    impl TaskQueue for DiscardingQueue {
+        async fn enqueue(&self, _task: &dyn Task) -> Option<()> {
+            Some(())
+        }
+    }
+
+    fn config() -> Config {
+        Config {
+            torrent_stats: StatsVisibility::Public,
+            ..crate::testkit::config()
+        }
+    }
+
+    // A swarm of an HTTP seeder that completed and a UDP leecher under
+    // `aa…`, and `bb…` registered from its .torrent file with no peers.
+    async fn app(cfg: &Config, now: OffsetDateTime) -> Router {
+        let store = Arc::new(MemoryStore::new());
+        let announce = |peer_id, n: u8, left, event, age, transport| UpdatePeerAnnounce {
+            info_hash: InfoHash(vec![0xaa; 20]),
+            peer_id,
+            ip: Ipv4Addr::new(192, 0, 2, n).into(),
+            port: 6881,
+            uploaded: 0,
+            downloaded: 0,
+            left,
+            event,
+            update_timestamp: now - time::Duration::seconds(age),
+            other_endpoint: None,
+            transport: Some(transport),
+            user_id: None,
+            key: None,
+        };
+        for cmd in [
+            announce(
+                PeerId(b"-qB4650-abcdefghijkl".to_vec()),
+                1,
+                0,
+                Event::Completed,
+                30,
+                Transport::Http,
+            ),
+            announce(
+                PeerId(vec![2; 20]),
+                2,
+                10,
+                Event::Started,
+                5,
+                Transport::Udp,
+            ),
+        ] {
+            store.update_peer_announce(&cmd).await.unwrap();
+        }
+        let metadata = TorrentMetadata {
+            name: "fixture.txt".to_string(),
+            size: 14,
+        };
+        let cmd = RegisterTorrent {
+            info_hash: &InfoHash(vec![0xbb; 20]),
+            metadata: &metadata,
+        };
+        store.register_torrent(cmd).await.unwrap();
+
+        stats(cfg, services(store.clone(), store))
+    }
+
+    fn services(
+        peer_repository: Arc<dyn PeerRepository>,
+        info_hash_repository: Arc<dyn InfoHashRepository>,
+    ) -> Services {
+        Services {
+            peer_repository,
+            info_hash_repository,
+            task_queue: Arc::new(DiscardingQueue),
+            bans: BanList::in_memory(),
+            audit: AuditLog::in_memory(),
+            prober: None,
+            federation: None,
+            offenders: Offenders::new(&InvalidRequestConfig::default()),
+            maintenance: Maintenance::default(),
+            passkeys: Passkeys::in_memory(),
+            torrent_policy: TorrentPolicy::open(),
+            peer_limits: PeerLimits::unlimited(),
+            clock: hanekawa_common::system_clock(),
+        }
+    }
+
+    async fn get(
+        app: &Router,
+        info_hash: &str,
+        if_none_match: Option<&str>,
+    ) -> (StatusCode, Option<String>, serde_json::Value) {
+        let mut request = axum::http::Request::get(format!("/stats/torrent/{info_hash}"));
+        if let Some(tags) = if_none_match {
+            request = request.header(IF_NONE_MATCH, tags);
+        }
+        let request = request.body(axum::body::Body::empty()).unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+
+        let status = response.status();
+        let etag = response
+            .headers()
+            .get(ETAG)
+            .map(|v| v.to_str().unwrap().to_string());
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (
+            status,
+            etag,
+            serde_json::from_slice(&body).unwrap_or_default(),
+        )
+    }
+
+    #[tokio::test]
+    async fn reports_a_torrents_swarm() {
+        let now = OffsetDateTime::now_utc();
+        let app = app(&config(), now).await;
+
+        let (status, _, body) = get(&app, &"aa".repeat(20), None).await;
+        assert_eq!(StatusCode::OK, status);
+        assert_eq!(
+            serde_json::json!({
+                "info_hash": "aa".repeat(20),
+                "seeders": 1,
+                "leechers": 1,
+                "snatches": 1,
+                "last_activity": (now - time::Duration::seconds(5)).unix_timestamp(),
+                "transports": { "http": 1, "udp": 1, "unknown": 0 },
+                "clients": { "qBittorrent": 1, "unknown": 1 },
+                "name": null,
+                "size": null,
+            }),
+            body
+        );
+
+        let (status, _, body) = get(&app, &"bb".repeat(20), None).await;
+        assert_eq!(StatusCode::OK, status);
+        assert_eq!(
+            serde_json::json!([0, 0, null, "fixture.txt", 14]),
+            serde_json::json!([
+                body["seeders"],
+                body["leechers"],
+                body["last_activity"],
+                body["name"],
+                body["size"]
+            ])
+        );
+
+        let (status, _, body) = get(&app, &"cc".repeat(20), None).await;
+        assert_eq!(StatusCode::NOT_FOUND, status);
+        assert_eq!("unknown torrent", body["error"]);
+
+        let (status, _, _) = get(&app, "abc", None).await;
+        assert_eq!(StatusCode::BAD_REQUEST, status);
+    }
+
+    #[tokio::test]
+    async fn answers_conditional_requests_without_a_body() {
+        let app = app(&config(), OffsetDateTime::now_utc()).await;
+        let info_hash = "aa".repeat(20);
+
+        let (_, etag, _) = get(&app, &info_hash, None).await;
+        let etag = etag.unwrap();
+
+        for tags in [etag.clone(), format!("W/{etag}"), format!("\"x\", {etag}")] {
+            let (status, again, body) = get(&app, &info_hash, Some(&tags)).await;
+            assert_eq!(StatusCode::NOT_MODIFIED, status);
+            assert_eq!(Some(&etag), again.as_ref());
+            assert!(body.is_null());
+        }
+
+        // Another torrent's tag, or one from before the stats changed.
+        let (_, other, _) = get(&app, &"bb".repeat(20), None).await;
+        let (status, _, body) = get(&app, &info_hash, other.as_deref()).await;
+        assert_eq!(StatusCode::OK, status);
+        assert_eq!(1, body["seeders"]);
+    }
+
+    // A torrent its policy refuses is not told apart from one never seen.
+    #[tokio::test]
+    async fn hides_torrents_outside_the_whitelist() {
+        let mut cfg = config();
+        cfg.only_allowed_info_hashes = true;
+        let app = app(&cfg, OffsetDateTime::now_utc()).await;
+
+        let (status, _, body) = get(&app, &"aa".repeat(20), None).await;
+        assert_eq!(StatusCode::NOT_FOUND, status);
+        assert_eq!("unknown torrent", body["error"]);
+    }
+
+    #[tokio::test]
+    async fn answers_a_server_error_when_the_store_fails() {
+        let peers = Arc::new(MemoryStore::new());
+        let app = stats(&config(), services(peers, Arc::new(Unreachable)));
+
+        let (status, _, body) = get(&app, &"aa".repeat(20), None).await;
+        assert_eq!(StatusCode::SERVICE_UNAVAILABLE, status);
+        assert_eq!("storage unavailable", body["error"]);
+    }
+}
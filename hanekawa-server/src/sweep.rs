@@ -0,0 +1,393 @@
+use hanekawa_common::{
+    repository::{
+        info_hash::GetInfoHashSummary,
+        peer::{IterIdleSwarms, PurgeStalePeers, PurgeSwarm},
+        Error,
+    },
+    types::{InfoHash, InfoHashStatus},
+    Config, Services,
+};
+
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use time::OffsetDateTime;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+// Forgets peers that stopped announcing without saying so, and drops swarms
+// a while after their last peer left, so records of every torrent ever
+// announced do not pile up. Snatches are counted apart from the peers, so
+// none are lost with them.
+pub struct SwarmSweeper {
+    services: Services,
+    sweep_interval: Duration,
+    activity_timeout: Duration,
+    grace_period: Duration,
+    clock: Arc<dyn Fn() -> OffsetDateTime + Send + Sync>,
+    // Reported empty, and neither dropped nor joined since.
+    emptied: Mutex<HashSet<InfoHash>>,
+}
+
+impl SwarmSweeper {
+    pub fn new(cfg: &Config, services: Services) -> Self {
+        let clock = services.clock.clone();
+        Self {
+            services,
+            sweep_interval: Duration::from_secs(cfg.peer_sweep_interval as u64),
+            activity_timeout: Duration::from_secs(cfg.activity_timeout() as u64),
+            grace_period: Duration::from_secs(cfg.empty_swarm_grace_period as u64),
+            clock,
+            emptied: Mutex::default(),
+        }
+    }
+
+    // For tests, to get past the grace period without waiting.
+    #[cfg(test)]
+    fn with_clock(mut self, clock: impl Fn() -> OffsetDateTime + Send + Sync + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    // Sweeps until `kt` is cancelled. A sweep the store fails is tried
+    // again on the next tick.
+    pub fn start(self, kt: CancellationToken) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(self.sweep_interval);
+            loop {
+                tokio::select! {
+                    _ = kt.cancelled() => break,
+                    _ = interval.tick() => {
+                        if let Err(e) = self.sweep().await {
+                            tracing::error!("sweep failed: {e}");
+                        }
+                    }
+                }
+            }
+        })
+    }
+
+    // Returns the swarms dropped.
+    pub async fn sweep(&self) -> Result<Vec<InfoHash>, Error> {
+        let empty_since = (self.clock)() - self.activity_timeout;
+        let idle = self
+            .services
+            .peer_repository
+            .iter_idle_swarms(IterIdleSwarms {
+                idle_since: empty_since,
+            })
+            .await?;
+
+        {
+            let mut emptied = self.emptied.lock().unwrap_or_else(|e| e.into_inner());
+            emptied.retain(|info_hash| idle.contains_key(info_hash));
+            for info_hash in idle.keys() {
+                if emptied.insert(info_hash.clone()) {
+                    tracing::info!("swarm {} emptied", info_hash.to_hex());
+                }
+            }
+        }
+
+        let mut dropped = vec![];
+        let drop_before = empty_since - self.grace_period;
+        for (info_hash, last_activity) in idle {
+            if last_activity > drop_before || self.is_registered(&info_hash).await? {
+                continue;
+            }
+
+            self.services
+                .peer_repository
+                .purge_swarm(PurgeSwarm {
+                    info_hash: &info_hash,
+                })
+                .await?;
+            tracing::info!("swarm {} dropped", info_hash.to_hex());
+
+            let mut emptied = self.emptied.lock().unwrap_or_else(|e| e.into_inner());
+            emptied.remove(&info_hash);
+            dropped.push(info_hash);
+        }
+
+        // Swarms of registered torrents are kept however long they idle, but
+        // not their peers.
+        let stale = self
+            .services
+            .peer_repository
+            .purge_stale_peers(PurgeStalePeers {
+                inactive_since: empty_since,
+                cutoff: drop_before,
+            })
+            .await?;
+
+        // Stale peers forgotten, and swarms dropped.
+        let swarms = dropped.len();
+        match stale > 0 || swarms > 0 {
+            true => tracing::info!(peers = stale, swarms, "swept"),
+            false => tracing::debug!(peers = stale, swarms, "swept"),
+        }
+
+        Ok(dropped)
+    }
+
+    // Allowed by the whitelist, or uploaded as a .torrent file.
+    async fn is_registered(&self, info_hash: &InfoHash) -> Result<bool, Error> {
+        let summary = self
+            .services
+            .info_hash_repository
+            .get_info_hash_summary(GetInfoHashSummary { info_hash })
+            .await?;
+
+        Ok(summary.status == InfoHashStatus::ExplicitAllow || summary.metadata.is_some())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testkit::config;
+
+    use hanekawa_common::{
+        audit::AuditLog,
+        ban::BanList,
+        maintenance::Maintenance,
+        offense::Offenders,
+        passkey::Passkeys,
+        peer_limit::PeerLimits,
+        repository::{
+            info_hash::{InfoHashRepository, RegisterTorrent, UpdateInfoHash},
+            memory::MemoryStore,
+            peer::{
+                GetPeerStatistics, GetPeers, GetSwarmDetail, IterSwarms, PeerRepository,
+                SetConnectable, UpdatePeerAnnounce,
+            },
+        },
+        task::{Task, TaskQueue},
+        torrent_policy::TorrentPolicy,
+        types::{
+            Peer, PeerId, PeerSource, PeerStatistics, SwarmMember, SwarmSummary, TorrentMetadata,
+        },
+        InvalidRequestConfig,
+    };
+    use std::{
+        collections::HashMap,
+        net::Ipv4Addr,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    fn info_hash(n: u8) -> InfoHash {
+        InfoHash(vec![n; 20])
+    }
+
+    // `02…` is allowed by the whitelist and `03…` was uploaded, the others
+    // are neither.
+    async fn store() -> Arc<MemoryStore> {
+        let store = Arc::new(MemoryStore::new());
+        let cmd = UpdateInfoHash {
+            info_hash: &info_hash(2),
+            status: InfoHashStatus::ExplicitAllow,
+        };
+        store.update_info_hash(cmd).await.unwrap();
+        let metadata = TorrentMetadata {
+            name: "fixture.txt".to_string(),
+            size: 14,
+        };
+        let cmd = RegisterTorrent {
+            info_hash: &info_hash(3),
+            metadata: &metadata,
+        };
+        store.register_torrent(cmd).await.unwrap();
+
+        store
+    }
+
+    // As if peer `id` had announced to swarm `n` at `at`.
+    fn announce(store: &MemoryStore, n: u8, id: u8, at: OffsetDateTime) {
+        let peer = Peer {
+            peer_id: PeerId(vec![id; 20]),
+            ip: Ipv4Addr::new(10, 0, 0, id).into(),
+            port: 6881,
+            connectable: None,
+            seeding: true,
+            source: PeerSource::Announce,
+        };
+        store.seed(&info_hash(n), peer, 0, at);
+    }
+
+    fn peer_ids(store: &MemoryStore, n: u8) -> Vec<PeerId> {
+        let peers = store.peers(&info_hash(n));
+        peers.into_iter().map(|peer| peer.peer_id).collect()
+    }
+
+    // Fails every call, counting them.
+    #[derive(Default)]
+    struct Unreachable(AtomicUsize);
+
+    impl Unreachable {
+        fn fail<T>(&self) -> Result<T, Error> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Err(Error::Backend("connection refused".to_string()))
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl PeerRepository for Unreachable {
+        async fn update_peer_announce(&self, _cmd: &UpdatePeerAnnounce) -> Result<(), Error> {
+            self.fail()
+        }
+
+        async fn get_peers(&self, _cmd: GetPeers<'_>) -> Result<Vec<Peer>, Error> {
+            self.fail()
+        }
+
+        async fn get_peer_statistics(
+            &self,
+            _cmd: GetPeerStatistics<'_>,
+        ) -> Result<HashMap<InfoHash, PeerStatistics>, Error> {
+            self.fail()
+        }
+
+        async fn iter_swarms(&self, _cmd: IterSwarms) -> Result<Vec<SwarmSummary>, Error> {
+            self.fail()
+        }
+
+        async fn iter_idle_swarms(
+            &self,
+            _cmd: IterIdleSwarms,
+        ) -> Result<HashMap<InfoHash, OffsetDateTime>, Error> {
+            self.fail()
+        }
+
+        async fn get_swarm_detail(
+            &self,
+            _cmd: GetSwarmDetail<'_>,
+        ) -> Result<Vec<SwarmMember>, Error> {
+            self.fail()
+        }
+
+        async fn purge_swarm(&self, _cmd: PurgeSwarm<'_>) -> Result<(), Error> {
+            self.fail()
+        }
+
+        async fn purge_stale_peers(&self, _cmd: PurgeStalePeers) -> Result<u64, Error> {
+            self.fail()
+        }
+
+        async fn set_connectable(&self, _cmd: SetConnectable) -> Result<(), Error> {
+            self.fail()
+        }
+    }
+
+    struct DiscardingQueue;
+
+    #[async_trait::async_trait]
+    impl TaskQueue for DiscardingQueue {
+        async fn enqueue(&self, _task: &dyn Task) -> Option<()> {
+            Some(())
+        }
+    }
+
+    fn services(
+        peer_repository: Arc<dyn PeerRepository>,
+        info_hash_repository: Arc<dyn InfoHashRepository>,
+    ) -> Services {
+        Services {
+            peer_repository,
+            info_hash_repository,
+            task_queue: Arc::new(DiscardingQueue),
+            bans: BanList::in_memory(),
+            audit: AuditLog::in_memory(),
+            prober: None,
+            federation: None,
+            offenders: Offenders::new(&InvalidRequestConfig::default()),
+            maintenance: Maintenance::default(),
+            passkeys: Passkeys::in_memory(),
+            torrent_policy: TorrentPolicy::open(),
+            peer_limits: PeerLimits::unlimited(),
+            clock: hanekawa_common::system_clock(),
+        }
+    }
+
+    fn sweeper(
+        store: Arc<MemoryStore>,
+        start: OffsetDateTime,
+    ) -> (SwarmSweeper, impl Fn(time::Duration)) {
+        let services = services(store.clone(), store);
+        let now = Arc::new(Mutex::new(start));
+        let clock = now.clone();
+        let sweeper =
+            SwarmSweeper::new(&config(), services).with_clock(move || *clock.lock().unwrap());
+
+        (sweeper, move |by| *now.lock().unwrap() += by)
+    }
+
+    #[tokio::test]
+    async fn drops_empty_swarms_after_the_grace_period() {
+        let start = OffsetDateTime::now_utc();
+        let store = store().await;
+        for n in 1..=3 {
+            announce(&store, n, n, start);
+        }
+        announce(&store, 4, 4, start + time::Duration::hours(3));
+        let (sweeper, advance) = sweeper(store.clone(), start);
+
+        // Peers time out after an hour, and their swarms are kept for four
+        // more.
+        advance(time::Duration::minutes(30));
+        assert!(sweeper.sweep().await.unwrap().is_empty());
+        advance(time::Duration::hours(1));
+        assert!(sweeper.sweep().await.unwrap().is_empty());
+        assert_eq!(3, sweeper.emptied.lock().unwrap().len());
+        advance(time::Duration::hours(4));
+        assert_eq!(vec![info_hash(1)], sweeper.sweep().await.unwrap());
+
+        // Registered torrents stay, though not their peers, and so does the
+        // one still active.
+        let left = (2..=4).map(|n| peer_ids(&store, n)).collect::<Vec<_>>();
+        assert_eq!(vec![vec![], vec![], vec![PeerId(vec![4; 20])]], left);
+
+        advance(time::Duration::hours(5));
+        assert_eq!(vec![info_hash(4)], sweeper.sweep().await.unwrap());
+        // With no peers left, the registered ones are not empty swarms
+        // anymore, but no swarms at all.
+        assert!(sweeper.emptied.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn forgets_peers_that_stopped_announcing() {
+        let start = OffsetDateTime::now_utc();
+        let store = store().await;
+        announce(&store, 1, 1, start);
+        announce(&store, 1, 2, start + time::Duration::minutes(50));
+        announce(&store, 4, 3, start);
+        let (sweeper, advance) = sweeper(store.clone(), start);
+
+        advance(time::Duration::minutes(70));
+        assert!(sweeper.sweep().await.unwrap().is_empty());
+
+        // The peer still announcing stays, and the swarm without any keeps
+        // its last until the swarm is dropped.
+        assert_eq!(vec![PeerId(vec![2; 20])], peer_ids(&store, 1));
+        assert_eq!(vec![PeerId(vec![3; 20])], peer_ids(&store, 4));
+        advance(time::Duration::hours(4));
+        assert_eq!(vec![info_hash(4)], sweeper.sweep().await.unwrap());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn sweeps_again_after_the_store_fails() {
+        let unreachable = Arc::new(Unreachable::default());
+        let services = services(unreachable.clone(), Arc::new(MemoryStore::new()));
+        let sweeper = SwarmSweeper::new(&config(), services);
+        let interval = sweeper.sweep_interval;
+
+        let kt = CancellationToken::new();
+        let handle = sweeper.start(kt.clone());
+        tokio::time::sleep(interval * 2 + Duration::from_secs(1)).await;
+
+        assert_eq!(3, unreachable.0.load(Ordering::SeqCst));
+        assert!(!handle.is_finished());
+        kt.cancel();
+        handle.await.unwrap();
+    }
+}
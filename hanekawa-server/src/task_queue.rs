@@ -1 +1,26 @@
 pub use hanekawa_queue::{AmqpMessage, AmqpTaskQueue};
+
+use hanekawa_common::{
+    task::{Task, TaskQueue},
+    Services,
+};
+
+// Runs tasks as they are enqueued, for trackers without a message queue.
+// Tasks enqueued by those go to the services' own queue.
+pub(crate) struct InlineQueue(pub(crate) Services);
+
+#[async_trait::async_trait]
+impl TaskQueue for InlineQueue {
+    async fn enqueue(&self, task: &dyn Task) -> Option<()> {
+        task.execute(&self.0).await
+    }
+}
+
+pub(crate) struct Unqueued;
+
+#[async_trait::async_trait]
+impl TaskQueue for Unqueued {
+    async fn enqueue(&self, _task: &dyn Task) -> Option<()> {
+        None
+    }
+}
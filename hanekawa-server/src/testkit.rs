@@ -0,0 +1,325 @@
+// A whole tracker on ephemeral ports, over an in-memory store and a clock
+// that only moves when told to, for tests here and of clients elsewhere.
+
+use crate::{
+    federation::UpstreamFederation,
+    task_queue::{InlineQueue, Unqueued},
+    Error, Listening,
+};
+
+use hanekawa_client::{
+    proto::{AnnounceParams, AnnounceResponse, ScrapeResponse},
+    ClientError, HttpTrackerClient, UdpTrackerClient,
+};
+pub use hanekawa_common::repository::memory::{MemoryStore, StoredPeer, Swarm};
+use hanekawa_common::{
+    audit::AuditLog,
+    ban::BanList,
+    maintenance::Maintenance,
+    offense::Offenders,
+    passkey::Passkeys,
+    peer_limit::PeerLimits,
+    torrent_policy::TorrentPolicy,
+    types::{Event, InfoHash, Peer, PeerId},
+    Config, Services,
+};
+
+use std::{
+    net::{IpAddr, Ipv4Addr},
+    sync::{Arc, Mutex},
+};
+use time::{Duration, OffsetDateTime};
+use tokio_util::sync::CancellationToken;
+
+// On loopback and ephemeral ports, with peers active for an hour.
+pub fn config() -> Config {
+    Config {
+        database_pool_size: 1,
+        bind_ip: Ipv4Addr::LOCALHOST,
+        http_bind_port: 0,
+        udp_bind_port: 0,
+        interval_jitter_percent: 0,
+        peer_activity_timeout: Some(3600),
+        ..Config::default()
+    }
+}
+
+pub fn random_info_hash() -> InfoHash {
+    InfoHash(rand::random::<[u8; 20]>().to_vec())
+}
+
+pub fn random_peer_id() -> PeerId {
+    PeerId(rand::random::<[u8; 20]>().to_vec())
+}
+
+// A seeder starting on a torrent of its own, unless told otherwise.
+#[derive(Debug, Clone)]
+pub struct AnnounceRequestBuilder {
+    params: AnnounceParams,
+}
+
+impl Default for AnnounceRequestBuilder {
+    fn default() -> Self {
+        Self {
+            params: AnnounceParams {
+                info_hash: random_info_hash(),
+                peer_id: random_peer_id(),
+                port: 6881,
+                uploaded: 0,
+                downloaded: 0,
+                left: 0,
+                event: Event::Started,
+                num_want: None,
+                compact: true,
+                key: None,
+                ip: None,
+            },
+        }
+    }
+}
+
+impl AnnounceRequestBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn info_hash(mut self, info_hash: InfoHash) -> Self {
+        self.params.info_hash = info_hash;
+        self
+    }
+
+    pub fn peer_id(mut self, peer_id: PeerId) -> Self {
+        self.params.peer_id = peer_id;
+        self
+    }
+
+    pub fn port(mut self, port: u16) -> Self {
+        self.params.port = port;
+        self
+    }
+
+    pub fn uploaded(mut self, uploaded: u64) -> Self {
+        self.params.uploaded = uploaded;
+        self
+    }
+
+    pub fn downloaded(mut self, downloaded: u64) -> Self {
+        self.params.downloaded = downloaded;
+        self
+    }
+
+    pub fn left(mut self, left: u64) -> Self {
+        self.params.left = left;
+        self
+    }
+
+    pub fn event(mut self, event: Event) -> Self {
+        self.params.event = event;
+        self
+    }
+
+    pub fn num_want(mut self, num_want: u32) -> Self {
+        self.params.num_want = Some(num_want);
+        self
+    }
+
+    pub fn compact(mut self, compact: bool) -> Self {
+        self.params.compact = compact;
+        self
+    }
+
+    pub fn key(mut self, key: impl Into<String>) -> Self {
+        self.params.key = Some(key.into());
+        self
+    }
+
+    pub fn ip(mut self, ip: IpAddr) -> Self {
+        self.params.ip = Some(ip);
+        self
+    }
+
+    pub fn build(self) -> AnnounceParams {
+        self.params
+    }
+}
+
+pub struct TestTracker {
+    // Where `/announce`, `/scrape` and the rest are, `http://127.0.0.1:…`.
+    pub base_url: String,
+    // The announce URLs.
+    pub http: String,
+    pub udp: String,
+    // Only with `bind_ipv6`.
+    pub http6: Option<String>,
+    pub udp6: Option<String>,
+    // Only served if the config enables it.
+    pub admin: String,
+    pub bans: BanList,
+    pub store: Arc<MemoryStore>,
+    pub torrent_policy: TorrentPolicy,
+    // What the tracker takes to be now.
+    now: Arc<Mutex<OffsetDateTime>>,
+    client: HttpTrackerClient,
+    udp_client: UdpTrackerClient,
+    kt: CancellationToken,
+    // Until shut down.
+    listening: Option<Listening>,
+}
+
+impl TestTracker {
+    // With `overrides` made to the test config.
+    pub async fn spawn(overrides: impl FnOnce(&mut Config)) -> Self {
+        let mut config = config();
+        overrides(&mut config);
+        Self::spawn_with(&config).await
+    }
+
+    pub async fn spawn_with(config: &Config) -> Self {
+        Self::try_spawn_with(config).await.unwrap()
+    }
+
+    pub async fn try_spawn_with(config: &Config) -> Result<Self, Error> {
+        Self::try_spawn_on(config, Arc::default()).await
+    }
+
+    // Keeping swarms in `store`, which outlives the tracker.
+    pub async fn try_spawn_on(config: &Config, store: Arc<MemoryStore>) -> Result<Self, Error> {
+        let now = Arc::new(Mutex::new(OffsetDateTime::now_utc()));
+        let clock = {
+            let now = now.clone();
+            move || *now.lock().unwrap_or_else(|e| e.into_inner())
+        };
+        let bans = BanList::in_memory().with_clock(clock.clone());
+        let kt = CancellationToken::new();
+        let federation = (!config.federation.upstreams.is_empty())
+            .then(|| UpstreamFederation::start(config, kt.child_token()).0 as _);
+        let offenders = Offenders::new(&config.invalid_requests).with_clock(clock.clone());
+        let maintenance = Maintenance::new(config.maintenance.enabled);
+        let passkeys = Passkeys::in_memory().with_clock(clock.clone());
+        let torrent_policy =
+            TorrentPolicy::load(&config.torrent_policy).map_err(Error::TorrentPolicy)?;
+        let peer_limits = PeerLimits::new(config).with_clock(clock.clone());
+        let services = |task_queue| Services {
+            peer_repository: store.clone(),
+            info_hash_repository: store.clone(),
+            task_queue,
+            bans: bans.clone(),
+            audit: AuditLog::in_memory(),
+            prober: None,
+            federation: federation.clone(),
+            offenders: offenders.clone(),
+            maintenance: maintenance.clone(),
+            passkeys: passkeys.clone(),
+            torrent_policy: torrent_policy.clone(),
+            peer_limits: peer_limits.clone(),
+            clock: Arc::new(clock.clone()),
+        };
+        let services = services(Arc::new(InlineQueue(services(Arc::new(Unqueued)))));
+
+        let listening = crate::serve(config, services, kt.child_token()).await?;
+        let scheme = match config.tls {
+            Some(_) => "https",
+            None => "http",
+        };
+        let base_url = format!("{scheme}://{}", listening.http_addr);
+
+        Ok(Self {
+            http: format!("{base_url}/announce"),
+            udp: format!("udp://{}", listening.udp_addr),
+            http6: listening
+                .http_addr6
+                .map(|addr| format!("{scheme}://{addr}/announce")),
+            udp6: listening.udp_addr6.map(|addr| format!("udp://{addr}")),
+            admin: format!("{base_url}/admin"),
+            base_url,
+            bans,
+            store,
+            torrent_policy,
+            now,
+            client: HttpTrackerClient::new().unwrap(),
+            udp_client: UdpTrackerClient::new(),
+            kt,
+            listening: Some(listening),
+        })
+    }
+
+    pub fn now(&self) -> OffsetDateTime {
+        *self.now.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // Moves the tracker's clock on, for intervals, bans and peers to run out
+    // without waiting for them.
+    pub fn advance(&self, by: Duration) {
+        *self.now.lock().unwrap_or_else(|e| e.into_inner()) += by;
+    }
+
+    // Announces over HTTP from 127.0.0.1.
+    pub async fn announce(
+        &self,
+        request: AnnounceRequestBuilder,
+    ) -> Result<AnnounceResponse, ClientError> {
+        self.client.announce(&self.http, request.build()).await
+    }
+
+    // The tracker leaves senders out of their own peer lists, so peers that
+    // are to see each other announce from different loopback addresses.
+    pub async fn announce_from(
+        &self,
+        ip: IpAddr,
+        request: AnnounceRequestBuilder,
+    ) -> Result<AnnounceResponse, ClientError> {
+        let client = HttpTrackerClient::builder().local_address(ip).build()?;
+        client.announce(&self.http, request.build()).await
+    }
+
+    pub async fn announce_udp(
+        &self,
+        request: AnnounceRequestBuilder,
+    ) -> Result<AnnounceResponse, ClientError> {
+        self.udp_client.announce(&self.udp, request.build()).await
+    }
+
+    // The body answering `query`, as it is sent, whatever the status.
+    pub async fn announce_raw(&self, query: &str) -> Vec<u8> {
+        let url = format!("{}?{query}", self.http);
+        let response = reqwest::get(url).await.unwrap();
+        response.bytes().await.unwrap().to_vec()
+    }
+
+    pub async fn scrape(&self, info_hashes: &[InfoHash]) -> Result<ScrapeResponse, ClientError> {
+        self.client.scrape(&self.http, info_hashes).await
+    }
+
+    pub async fn scrape_udp(
+        &self,
+        info_hashes: &[InfoHash],
+    ) -> Result<ScrapeResponse, ClientError> {
+        self.udp_client.scrape(&self.udp, info_hashes).await
+    }
+
+    // As if `peer` had announced just now.
+    pub fn seed(&self, info_hash: &InfoHash, peer: Peer, left: u64) {
+        self.store.seed(info_hash, peer, left, self.now());
+    }
+
+    pub fn peers(&self, info_hash: &InfoHash) -> Vec<Peer> {
+        self.store.peers(info_hash)
+    }
+
+    // Stops the tracker, which cancelling this does too.
+    pub fn shutdown_handle(&self) -> CancellationToken {
+        self.kt.clone()
+    }
+
+    // Waits for the tracker to stop listening.
+    pub async fn shut_down(&mut self) {
+        let listening = self.listening.take().unwrap();
+        listening.shut_down(std::time::Duration::from_secs(5)).await;
+    }
+}
+
+impl Drop for TestTracker {
+    fn drop(&mut self) {
+        self.kt.cancel();
+    }
+}
@@ -1,18 +1,102 @@
 mod codec;
 
 use codec::UdpTrackerCodec;
-use hanekawa_common::Config;
+use hanekawa::udp_tracker::{
+    proto::{ErrorResponse, Request, Response},
+    UdpTrackerService,
+};
+use hanekawa_common::{Config, Services};
 
+use std::{net::SocketAddr, sync::Arc};
+
+use tokio::{net::UdpSocket, task::JoinHandle};
 use tokio_util::sync::CancellationToken;
 
-pub async fn start(cfg: &Config, kt: CancellationToken) {
-    use futures::StreamExt;
-    use tokio::net::UdpSocket;
+async fn handle(
+    tracker: &UdpTrackerService,
+    request: Request,
+    addr: SocketAddr,
+) -> Option<Response> {
+    match request {
+        Request::Announce(announce) => {
+            let transaction_id = announce.transaction_id;
+            let response = match tracker.announce(announce, addr).await {
+                Ok(r) => Response::Announce(r),
+                Err(e) => Response::Error(ErrorResponse {
+                    transaction_id,
+                    message: e.to_string(),
+                }),
+            };
+
+            Some(response)
+        }
+        Request::Connect(connect) => Some(Response::Connect(tracker.connect(connect, addr))),
+        Request::Scrape(scrape) => {
+            let transaction_id = scrape.transaction_id;
+            let response = match tracker.scrape(scrape, addr).await {
+                Ok(r) => Response::Scrape(r),
+                Err(e) => Response::Error(ErrorResponse {
+                    transaction_id,
+                    message: e.to_string(),
+                }),
+            };
+
+            Some(response)
+        }
+    }
+}
+
+fn bind_socket(addr: SocketAddr, reuse_port: bool) -> std::io::Result<UdpSocket> {
+    use socket2::{Domain, Protocol, Socket, Type};
+
+    let socket = Socket::new(Domain::for_address(addr), Type::DGRAM, Some(Protocol::UDP))?;
+    // So that the IPv4 sockets may share the port.
+    if addr.is_ipv6() {
+        socket.set_only_v6(true)?;
+    }
+
+    #[cfg(target_os = "linux")]
+    socket.set_reuse_port(reuse_port)?;
+    #[cfg(not(target_os = "linux"))]
+    let _ = reuse_port;
+
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+
+    UdpSocket::from_std(socket.into())
+}
+
+// Opens `count` sockets sharing the same address with SO_REUSEPORT so the
+// kernel spreads incoming datagrams across them. Platforms without
+// load-balancing SO_REUSEPORT get a single socket.
+fn bind_sockets(addr: SocketAddr, count: usize) -> std::io::Result<Vec<UdpSocket>> {
+    let count = if cfg!(target_os = "linux") {
+        count.max(1)
+    } else {
+        if count > 1 {
+            tracing::warn!("multiple UDP sockets are not supported on this platform, using one");
+        }
+        1
+    };
+
+    let reuse_port = count > 1;
+
+    let first = bind_socket(addr, reuse_port)?;
+    // Resolve the port in case an ephemeral one was requested.
+    let addr = first.local_addr()?;
+
+    let mut sockets = vec![first];
+    for _ in 1..count {
+        sockets.push(bind_socket(addr, reuse_port)?);
+    }
+
+    Ok(sockets)
+}
+
+async fn serve(socket: Arc<UdpSocket>, tracker: UdpTrackerService, kt: CancellationToken) {
+    use futures::{SinkExt, StreamExt};
     use tokio_util::udp::UdpFramed;
 
-    let socket = UdpSocket::bind((cfg.bind_ip, cfg.udp_bind_port))
-        .await
-        .unwrap();
     let mut socket = UdpFramed::new(socket, UdpTrackerCodec {});
 
     loop {
@@ -23,8 +107,12 @@ pub async fn start(cfg: &Config, kt: CancellationToken) {
             request = socket.next() => {
                 if let Some(request) = request {
                     match request {
-                        Ok((request, ip)) => {
-                            eprintln!("received request {:?} from ip {:?}", request, ip);
+                        Ok((request, addr)) => {
+                            if let Some(response) = handle(&tracker, request, addr).await {
+                                if let Err(e) = socket.send((response, addr)).await {
+                                    tracing::warn!(error = %e, %addr, "failed to send response");
+                                }
+                            }
                         }
                         Err(e) => {
                             eprintln!("malformed message, {:?}", e);
@@ -35,3 +123,263 @@ pub async fn start(cfg: &Config, kt: CancellationToken) {
         }
     }
 }
+
+// Serves the socket until `kt` is cancelled, in a task started over should
+// it panic, so that one bad request does not take the socket down with it.
+async fn supervise(socket: Arc<UdpSocket>, tracker: UdpTrackerService, kt: CancellationToken) {
+    loop {
+        let task = tokio::spawn(serve(socket.clone(), tracker.clone(), kt.clone()));
+        match task.await {
+            Ok(()) => break,
+            Err(_) if kt.is_cancelled() => break,
+            Err(e) => tracing::error!("udp socket task failed, restarting it: {e}"),
+        }
+    }
+}
+
+async fn serve_all(sockets: Vec<UdpSocket>, tracker: UdpTrackerService, kt: CancellationToken) {
+    let handles: Vec<_> = sockets
+        .into_iter()
+        .map(|socket| {
+            let socket = Arc::new(socket);
+            tokio::spawn(supervise(socket, tracker.clone(), kt.child_token()))
+        })
+        .collect();
+
+    for handle in handles {
+        if let Err(e) = handle.await {
+            tracing::error!("udp socket supervisor failed: {e}");
+        }
+    }
+}
+
+// Returns the bound address, and the task serving it until `kt` is
+// cancelled.
+pub fn start(
+    cfg: &Config,
+    addr: SocketAddr,
+    services: Services,
+    kt: CancellationToken,
+) -> std::io::Result<(SocketAddr, JoinHandle<()>)> {
+    let tracker = UdpTrackerService::new(cfg, services);
+
+    let sockets = bind_sockets(addr, cfg.udp_socket_count)?;
+    let addr = sockets[0].local_addr()?;
+
+    Ok((addr, tokio::spawn(serve_all(sockets, tracker, kt))))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use hanekawa_common::{
+        audit::AuditLog,
+        ban::BanList,
+        maintenance::Maintenance,
+        offense::Offenders,
+        passkey::Passkeys,
+        peer_limit::PeerLimits,
+        repository::memory::MemoryStore,
+        task::{Task, TaskQueue},
+        torrent_policy::TorrentPolicy,
+        InvalidRequestConfig,
+    };
+
+    use bytes::{Buf, BufMut, BytesMut};
+    use std::{sync::Arc, time::Duration};
+
+    struct DiscardingQueue;
+
+    #[async_trait::async_trait]
+    impl TaskQueue for DiscardingQueue {
+        async fn enqueue(&self, _task: &dyn Task) -> Option<()> {
+            Some(())
+        }
+    }
+
+    // Fails every announce, as a bug in the tracker would.
+    struct CrashingQueue;
+
+    #[async_trait::async_trait]
+    impl TaskQueue for CrashingQueue {
+        async fn enqueue(&self, _task: &dyn Task) -> Option<()> {
+            panic!("queue crashed");
+        }
+    }
+
+    fn config() -> Config {
+        Config {
+            udp_socket_count: 4,
+            peer_announce_interval: 60,
+            peer_min_announce_interval: 30,
+            peer_activity_timeout: Some(120),
+            ..crate::testkit::config()
+        }
+    }
+
+    fn tracker() -> UdpTrackerService {
+        tracker_with(Arc::new(DiscardingQueue))
+    }
+
+    fn tracker_with(task_queue: Arc<dyn TaskQueue>) -> UdpTrackerService {
+        let store = Arc::new(MemoryStore::new());
+        let services = Services {
+            peer_repository: store.clone(),
+            info_hash_repository: store,
+            task_queue,
+            bans: BanList::in_memory(),
+            audit: AuditLog::in_memory(),
+            prober: None,
+            federation: None,
+            offenders: Offenders::new(&InvalidRequestConfig::default()),
+            maintenance: Maintenance::default(),
+            passkeys: Passkeys::in_memory(),
+            torrent_policy: TorrentPolicy::open(),
+            peer_limits: PeerLimits::unlimited(),
+            clock: hanekawa_common::system_clock(),
+        };
+
+        UdpTrackerService::new(&config(), services)
+    }
+
+    async fn exchange(client: &UdpSocket, packet: &[u8]) -> Vec<u8> {
+        client.send(packet).await.unwrap();
+
+        let mut buf = [0; 1500];
+        let len = tokio::time::timeout(Duration::from_secs(5), client.recv(&mut buf))
+            .await
+            .expect("no response from tracker")
+            .unwrap();
+        buf[..len].to_vec()
+    }
+
+    async fn connect(client: &UdpSocket) -> i64 {
+        let mut buf = BytesMut::new();
+        buf.put_i64(0x41727101980);
+        buf.put_i32(0);
+        buf.put_i32(7);
+
+        let reply = exchange(client, &buf).await;
+        let mut reply = &reply[..];
+        assert_eq!(16, reply.len());
+        assert_eq!((0, 7), (reply.get_i32(), reply.get_i32()));
+        reply.get_i64()
+    }
+
+    fn announce_packet(connection_id: i64, transaction_id: i32) -> BytesMut {
+        let mut buf = BytesMut::new();
+
+        buf.put_i64(connection_id);
+        buf.put_i32(1);
+        buf.put_i32(transaction_id);
+        buf.put_slice(&[0xab; 20]);
+        buf.put_slice(&[0xcd; 20]);
+        buf.put_i64(0);
+        buf.put_i64(100);
+        buf.put_i64(0);
+        buf.put_i32(2);
+        buf.put_i32(0);
+        buf.put_i32(0);
+        buf.put_i32(-1);
+        buf.put_u16(6881);
+
+        buf
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn binds_requested_number_of_sockets() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_io()
+            .build()
+            .unwrap();
+        let _guard = rt.enter();
+
+        let sockets = bind_sockets("127.0.0.1:0".parse().unwrap(), 4).unwrap();
+        let addr = sockets[0].local_addr().unwrap();
+
+        assert_eq!(4, sockets.len());
+        assert!(sockets.iter().all(|s| s.local_addr().unwrap() == addr));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn answers_every_client_with_multiple_sockets() {
+        let cfg = config();
+        let sockets = bind_sockets((cfg.bind_ip, 0).into(), cfg.udp_socket_count).unwrap();
+        let addr = sockets[0].local_addr().unwrap();
+
+        let kt = CancellationToken::new();
+        let server = tokio::spawn(serve_all(sockets, tracker(), kt.child_token()));
+
+        let clients: Vec<_> = (0..16)
+            .map(|transaction_id| {
+                tokio::spawn(async move {
+                    let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+                    client.connect(addr).await.unwrap();
+                    let connection_id = connect(&client).await;
+                    let reply =
+                        exchange(&client, &announce_packet(connection_id, transaction_id)).await;
+
+                    let mut buf = &reply[..];
+                    (transaction_id, buf.get_i32(), buf.get_i32(), reply.len())
+                })
+            })
+            .collect();
+
+        for client in clients {
+            let (transaction_id, action, reply_transaction_id, len) = client.await.unwrap();
+            assert_eq!(1, action);
+            assert_eq!(transaction_id, reply_transaction_id);
+            assert_eq!(20, len);
+        }
+
+        kt.cancel();
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn answers_unknown_connection_ids_with_an_error() {
+        let socket = bind_socket("127.0.0.1:0".parse().unwrap(), false).unwrap();
+        let addr = socket.local_addr().unwrap();
+
+        let kt = CancellationToken::new();
+        let server = tokio::spawn(serve(Arc::new(socket), tracker(), kt.child_token()));
+
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client.connect(addr).await.unwrap();
+        let connection_id = connect(&client).await;
+
+        let reply = exchange(&client, &announce_packet(connection_id ^ 1, 9)).await;
+        let mut buf = &reply[..];
+        assert_eq!((3, 9), (buf.get_i32(), buf.get_i32()));
+        assert_eq!(b"invalid connection id, connect again", buf);
+
+        kt.cancel();
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn keeps_serving_after_a_request_panics() {
+        let cfg = config();
+        let socket = bind_socket((cfg.bind_ip, 0).into(), false).unwrap();
+        let addr = socket.local_addr().unwrap();
+
+        let kt = CancellationToken::new();
+        let tracker = tracker_with(Arc::new(CrashingQueue));
+        let server = tokio::spawn(serve_all(vec![socket], tracker, kt.child_token()));
+
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        client.connect(addr).await.unwrap();
+        let connection_id = connect(&client).await;
+        client
+            .send(&announce_packet(connection_id, 9))
+            .await
+            .unwrap();
+
+        connect(&client).await;
+
+        kt.cancel();
+        server.await.unwrap();
+    }
+}
@@ -0,0 +1,2393 @@
+// The whole server, HTTP and UDP, driven only through the client's public API
+// so every step goes over the wire formats both ends implement.
+
+use hanekawa_client::proto::RetryIn;
+use hanekawa_client::{
+    proto::AnnounceParams, AnnounceKey, ClientError, HttpTrackerClient, MultiTrackerAnnouncer,
+    TrackerClient, UdpTrackerClient,
+};
+use hanekawa_common::{
+    magnet::MagnetLink,
+    repository::peer::{PeerRepository, PurgeSwarm},
+    types::{BanTarget, Event, InfoHash, Peer, PeerId, PeerSource},
+    AnnouncedIp, ClientRule, Config, EncryptionKey, IntervalScaleConfig, KeyMismatch,
+    StatsVisibility, SwarmFull, TlsConfig, TorrentPolicyConfig, TorrentPolicyMode, Upstream,
+};
+use hanekawa_server::testkit::{
+    config, random_info_hash, random_peer_id, AnnounceRequestBuilder, TestTracker,
+};
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::{Arc, Mutex},
+};
+use time::{Duration, OffsetDateTime};
+
+// Of the torrent most tests share.
+fn peer(peer_id: u8, port: u16, left: u64, event: Event) -> AnnounceRequestBuilder {
+    AnnounceRequestBuilder::new()
+        .info_hash(InfoHash(vec![0xaa; 20]))
+        .peer_id(PeerId(vec![peer_id; 20]))
+        .port(port)
+        .downloaded(100 - left)
+        .left(left)
+        .event(event)
+}
+
+fn params(peer_id: u8, port: u16, left: u64, event: Event) -> AnnounceParams {
+    peer(peer_id, port, left, event).build()
+}
+
+fn addrs(peers: Vec<hanekawa_client::proto::Peer>) -> Vec<SocketAddr> {
+    peers.into_iter().map(|p| p.addr).collect()
+}
+
+#[tokio::test]
+async fn a_swarm_over_http_and_udp() {
+    let server = TestTracker::spawn(|_| {}).await;
+
+    // B announces over UDP from 127.0.0.1, so A announces from another
+    // address to see it.
+    let a_ip = IpAddr::from([127, 0, 0, 2]);
+    let info_hash = InfoHash(vec![0xaa; 20]);
+
+    // A seeds.
+    let response = server
+        .announce_from(a_ip, peer(b'a', 6881, 0, Event::Started))
+        .await
+        .unwrap();
+    assert_eq!(1800, response.interval);
+    assert_eq!(Some(900), response.min_interval);
+    assert_eq!((Some(1), Some(0)), (response.complete, response.incomplete));
+    assert!(response.peers().unwrap().is_empty());
+
+    // B joins and sees A.
+    let response = server
+        .announce_udp(peer(b'b', 51413, 100, Event::Started))
+        .await
+        .unwrap();
+    assert_eq!((Some(1), Some(1)), (response.complete, response.incomplete));
+    assert_eq!(
+        vec![SocketAddr::from((a_ip, 6881))],
+        addrs(response.peers().unwrap())
+    );
+
+    // B completes, which counts as a snatch.
+    let response = server
+        .announce_udp(peer(b'b', 51413, 0, Event::Completed))
+        .await
+        .unwrap();
+    assert_eq!((Some(2), Some(0)), (response.complete, response.incomplete));
+
+    // Scrapes agree over both protocols.
+    let info_hashes = [info_hash.clone()];
+    let udp = server.scrape_udp(&info_hashes).await.unwrap();
+    let http = server.scrape(&info_hashes).await.unwrap();
+    for scrape in [udp, http] {
+        let file = &scrape.files[&info_hash];
+        assert_eq!((2, 1, 0), (file.complete, file.downloaded, file.incomplete));
+    }
+
+    // A stops and is gone from B's next announce.
+    server
+        .announce_from(a_ip, peer(b'a', 6881, 0, Event::Stopped))
+        .await
+        .unwrap();
+    let response = server
+        .announce_udp(peer(b'b', 51413, 0, Event::Interval))
+        .await
+        .unwrap();
+    assert_eq!((Some(1), Some(0)), (response.complete, response.incomplete));
+    assert!(response.peers().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn announce_events_over_a_peers_lifetime() {
+    let server = TestTracker::spawn(|_| {}).await;
+
+    let a_ip = IpAddr::from([127, 0, 0, 2]);
+    let info_hashes = [InfoHash(vec![0xaa; 20])];
+    let scrape = || async {
+        let scrape = server.scrape(&info_hashes).await.unwrap();
+        let file = &scrape.files[&info_hashes[0]];
+        (file.complete, file.downloaded, file.incomplete)
+    };
+
+    // Completing unannounced still joins the swarm, and saying so twice is
+    // one snatch.
+    for _ in 0..2 {
+        server
+            .announce_from(a_ip, peer(b'a', 6881, 0, Event::Completed))
+            .await
+            .unwrap();
+    }
+    assert_eq!((1, 1, 0), scrape().await);
+
+    // Stopping unannounced is fine, and gets no peers.
+    let response = server
+        .announce_udp(peer(b'b', 51413, 100, Event::Stopped))
+        .await
+        .unwrap();
+    assert!(response.peers().unwrap().is_empty());
+    assert_eq!((1, 1, 0), scrape().await);
+
+    let response = server
+        .announce_udp(peer(b'b', 51413, 100, Event::Started))
+        .await
+        .unwrap();
+    assert_eq!(
+        vec![SocketAddr::from((a_ip, 6881))],
+        addrs(response.peers().unwrap())
+    );
+    server
+        .announce_udp(peer(b'b', 51413, 50, Event::Interval))
+        .await
+        .unwrap();
+    assert_eq!((1, 1, 1), scrape().await);
+
+    // Stopping leaves at once, with no peers handed out on the way.
+    let response = server
+        .announce_udp(peer(b'b', 51413, 50, Event::Stopped))
+        .await
+        .unwrap();
+    assert!(response.peers().unwrap().is_empty());
+    assert_eq!((1, 1, 0), scrape().await);
+
+    // While a seeder that keeps announcing stays one.
+    server
+        .announce_from(a_ip, peer(b'a', 6881, 0, Event::Interval))
+        .await
+        .unwrap();
+    assert_eq!((1, 1, 0), scrape().await);
+
+    // And one that stops announcing is gone after the activity timeout.
+    server.advance(Duration::seconds(3601));
+    assert_eq!((0, 1, 0), scrape().await);
+    let response = server
+        .announce_udp(peer(b'b', 51413, 100, Event::Started))
+        .await
+        .unwrap();
+    assert!(response.peers().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn scrapes_answer_for_each_known_info_hash() {
+    let server = TestTracker::spawn(|config| config.scrape.max_info_hashes = 3).await;
+
+    let (x, y, unknown) = (
+        InfoHash(vec![0xaa; 20]),
+        InfoHash(vec![0xbb; 20]),
+        InfoHash(vec![0xcc; 20]),
+    );
+    for (info_hash, peer_id, left) in [
+        (&x, b'a', 0),
+        (&x, b'b', 0),
+        (&x, b'c', 50),
+        (&y, b'd', 0),
+        (&y, b'e', 100),
+        (&y, b'f', 100),
+    ] {
+        let request = peer(peer_id, 6881, left, Event::Started).info_hash(info_hash.clone());
+        server.announce_udp(request).await.unwrap();
+    }
+    let request = peer(b'e', 6881, 0, Event::Completed).info_hash(y.clone());
+    server.announce_udp(request).await.unwrap();
+
+    // The unknown info hash is left out.
+    let scrape = server
+        .scrape(&[x.clone(), y.clone(), unknown.clone()])
+        .await
+        .unwrap();
+    let files = scrape
+        .files
+        .iter()
+        .map(|(info_hash, f)| (info_hash.clone(), (f.complete, f.downloaded, f.incomplete)))
+        .collect::<HashMap<_, _>>();
+    assert_eq!(
+        HashMap::from([(x.clone(), (2, 0, 1)), (y.clone(), (2, 1, 1))]),
+        files
+    );
+
+    let result = server
+        .scrape(&[x.clone(), y.clone(), unknown.clone(), unknown])
+        .await;
+    assert!(matches!(
+        result,
+        Err(ClientError::Failure { reason, .. }) if reason == "too many info hashes, at most 3"
+    ));
+
+    // Unless configured to answer it with zeros.
+    let server = TestTracker::spawn(|config| config.scrape.include_unknown = true).await;
+    let scrape = server.scrape(std::slice::from_ref(&x)).await.unwrap();
+    let file = &scrape.files[&x];
+    assert_eq!((0, 0, 0), (file.complete, file.downloaded, file.incomplete));
+}
+
+#[tokio::test]
+async fn seeded_peers_are_handed_out_as_announced_ones() {
+    let server = TestTracker::spawn(|_| {}).await;
+    let info_hash = random_info_hash();
+    let seeded = Peer {
+        peer_id: random_peer_id(),
+        ip: IpAddr::from([192, 0, 2, 1]),
+        port: 6881,
+        connectable: None,
+        seeding: true,
+        source: PeerSource::Announce,
+    };
+    server.seed(&info_hash, seeded.clone(), 0);
+
+    let response = server
+        .announce(AnnounceRequestBuilder::new().info_hash(info_hash.clone()))
+        .await
+        .unwrap();
+    assert_eq!((Some(2), Some(0)), (response.complete, response.incomplete));
+    assert_eq!(
+        vec![SocketAddr::from(([192, 0, 2, 1], 6881))],
+        addrs(response.peers().unwrap())
+    );
+    assert_eq!(2, server.peers(&info_hash).len());
+
+    // As sent, for what the client would not make of it.
+    let query = format!(
+        "info_hash={}&peer_id=-qB4650-123456789012&port=6882&uploaded=0&downloaded=0&left=0&compact=1",
+        info_hash.0.iter().map(|b| format!("%{b:02x}")).collect::<String>()
+    );
+    let body = server.announce_raw(&query).await;
+    let response = hanekawa_bencode::parse(&body).unwrap().into_value();
+    assert_eq!(Some(3), response.get("complete").and_then(|v| v.as_int()));
+    let peers = response.get("peers").and_then(|v| v.as_bytes()).unwrap();
+    let mut peers = peers.chunks(6).collect::<Vec<_>>();
+    peers.sort();
+    assert_eq!(
+        vec![&[127, 0, 0, 1, 0x1a, 0xe1][..], &[192, 0, 2, 1, 0x1a, 0xe1]],
+        peers
+    );
+}
+
+#[tokio::test]
+async fn peers_may_say_where_they_are_reached_as_configured() {
+    let announced = |server: &TestTracker, ip: &str| {
+        let http = server.http.clone();
+        let a_params = AnnounceParams {
+            ip: Some(ip.parse().unwrap()),
+            ..params(b'a', 6881, 0, Event::Started)
+        };
+        async move {
+            let a = HttpTrackerClient::builder()
+                .local_address(IpAddr::from([127, 0, 0, 2]))
+                .build()
+                .unwrap();
+            a.announce(&http, a_params).await.unwrap();
+
+            let b = HttpTrackerClient::new().unwrap();
+            let response = b
+                .announce(&http, params(b'b', 51413, 100, Event::Started))
+                .await
+                .unwrap();
+            (addrs(response.peers().unwrap()), response.peers6.is_some())
+        }
+    };
+    let seen = |ip: &str| vec![SocketAddr::new(ip.parse().unwrap(), 6881)];
+
+    // Ignored by default.
+    let server = TestTracker::spawn(|_| {}).await;
+    assert_eq!(
+        seen("127.0.0.2"),
+        announced(&server, "93.184.216.34").await.0
+    );
+
+    // Only public addresses in public mode, else the sender's.
+    let mut config = config();
+    config.announced_ip = AnnouncedIp::Public;
+    let server = TestTracker::spawn_with(&config).await;
+    assert_eq!(seen("127.0.0.2"), announced(&server, "10.1.2.3").await.0);
+    assert_eq!(
+        seen("93.184.216.34"),
+        announced(&server, "93.184.216.34").await.0
+    );
+    let server = TestTracker::spawn_with(&config).await;
+    assert_eq!(
+        (seen("2606:2800:220:1::1"), true),
+        announced(&server, "2606:2800:220:1::1").await
+    );
+
+    config.announced_ip = AnnouncedIp::Any;
+    let server = TestTracker::spawn_with(&config).await;
+    assert_eq!(seen("10.1.2.3"), announced(&server, "10.1.2.3").await.0);
+}
+
+#[tokio::test]
+async fn announces_say_what_was_not_taken_as_asked() {
+    let server = TestTracker::spawn(|_| {}).await;
+    let a = HttpTrackerClient::new().unwrap();
+    let warning = |params| {
+        let (a, http) = (&a, &server.http);
+        async move {
+            let response = a.announce(http, params).await.unwrap();
+            response.warning_message
+        }
+    };
+
+    assert_eq!(None, warning(params(b'a', 6881, 0, Event::Started)).await);
+    let as_asked = AnnounceParams {
+        num_want: Some(200),
+        ip: Some("127.0.0.1".parse().unwrap()),
+        ..params(b'a', 6881, 0, Event::Interval)
+    };
+    assert_eq!(None, warning(as_asked.clone()).await);
+
+    let not_as_asked = AnnounceParams {
+        num_want: Some(500),
+        ip: Some("93.184.216.34".parse().unwrap()),
+        ..as_asked
+    };
+    assert_eq!(
+        Some(
+            "ip parameter ignored, announced from where you are; numwant clamped to 200"
+                .to_string()
+        ),
+        warning(not_as_asked).await
+    );
+
+    // Not an address at all, which is refused rather than ignored.
+    let query = format!(
+        "info_hash={}&peer_id=-qB4650-123456789012&port=6881&uploaded=0&downloaded=0&left=0\
+         &ip=example.com",
+        "%aa".repeat(20)
+    );
+    let body = server.announce_raw(&query).await;
+    let response = hanekawa_bencode::parse(&body).unwrap().into_value();
+    assert_eq!(
+        Some(&b"failed to deserialize query string: ip is not an address: example.com"[..]),
+        response.get("failure reason").and_then(|v| v.as_bytes())
+    );
+}
+
+#[tokio::test]
+async fn each_peer_is_handed_the_rest_of_the_swarm() {
+    let server = TestTracker::spawn(|_| {}).await;
+
+    let mut seen = vec![];
+    for (n, peer) in [b'a', b'b', b'c'].into_iter().enumerate() {
+        let ip = IpAddr::from([127, 0, 0, 2 + n as u8]);
+        let client = HttpTrackerClient::builder()
+            .local_address(ip)
+            .build()
+            .unwrap();
+        let response = client
+            .announce(&server.http, params(peer, 6881, 100, Event::Started))
+            .await
+            .unwrap();
+
+        let mut peers = addrs(response.peers().unwrap());
+        peers.sort();
+        assert_eq!(seen, peers);
+        assert_eq!(
+            (Some(0), Some(n as u32 + 1)),
+            (response.complete, response.incomplete)
+        );
+        seen.push(SocketAddr::from((ip, 6881)));
+    }
+
+    // Or as many of it as asked for.
+    let client = HttpTrackerClient::new().unwrap();
+    let response = client
+        .announce(
+            &server.http,
+            AnnounceParams {
+                num_want: Some(2),
+                ..params(b'd', 6881, 100, Event::Started)
+            },
+        )
+        .await
+        .unwrap();
+    let peers = addrs(response.peers().unwrap());
+    assert_eq!(2, peers.len());
+    assert!(peers.iter().all(|peer| seen.contains(peer)));
+}
+
+#[tokio::test]
+async fn peers_advertise_the_other_address_family() {
+    let server = TestTracker::spawn(|config| config.announced_ip = AnnouncedIp::Any).await;
+
+    let a_ip = IpAddr::from([127, 0, 0, 2]);
+    let a_v6: SocketAddr = "[2001:db8::a]:6882".parse().unwrap();
+    let a = HttpTrackerClient::builder()
+        .local_address(a_ip)
+        .advertise(a_v6)
+        .build()
+        .unwrap();
+    let b = HttpTrackerClient::new().unwrap();
+
+    a.announce(&server.http, params(b'a', 6881, 0, Event::Started))
+        .await
+        .unwrap();
+    let response = b
+        .announce(&server.http, params(b'b', 51413, 100, Event::Started))
+        .await
+        .unwrap();
+
+    // Both of A's endpoints, but it is still a single seeder.
+    assert_eq!(
+        vec![SocketAddr::from((a_ip, 6881)), a_v6],
+        addrs(response.peers().unwrap())
+    );
+    assert_eq!((Some(1), Some(1)), (response.complete, response.incomplete));
+}
+
+#[tokio::test]
+async fn dual_stack_peers_reach_either_family_per_bep_7() {
+    use hanekawa_client::proto::PeerList;
+
+    let mut config = config();
+    config.bind_ipv6 = Some(Ipv6Addr::LOCALHOST);
+    config.announced_ip = AnnouncedIp::Any;
+    let server = TestTracker::spawn_with(&config).await;
+
+    // Announced once, over v6, with its v4 endpoint.
+    let a_v4 = SocketAddr::from(([127, 0, 0, 2], 6882));
+    let a = HttpTrackerClient::builder()
+        .advertise(a_v4)
+        .build()
+        .unwrap();
+    a.announce(
+        server.http6.as_ref().unwrap(),
+        params(b'a', 6881, 0, Event::Started),
+    )
+    .await
+    .unwrap();
+
+    // A v4 only client has it in `peers`, and `peers6` has the other end.
+    let b = HttpTrackerClient::builder()
+        .local_address(IpAddr::from([127, 0, 0, 3]))
+        .build()
+        .unwrap();
+    let response = b
+        .announce(&server.http, params(b'b', 51413, 100, Event::Started))
+        .await
+        .unwrap();
+    assert_eq!(
+        PeerList::Compact(vec![127, 0, 0, 2, 0x1a, 0xe2]),
+        response.peers
+    );
+    let mut v6 = Ipv6Addr::LOCALHOST.octets().to_vec();
+    v6.extend_from_slice(&6881u16.to_be_bytes());
+    assert_eq!(Some(PeerList::Compact(v6)), response.peers6);
+}
+
+#[tokio::test]
+async fn the_client_reads_either_peer_list_model() {
+    let server = TestTracker::spawn(|config| config.announced_ip = AnnouncedIp::Any).await;
+    let a_ip = IpAddr::from([127, 0, 0, 2]);
+    let a = HttpTrackerClient::builder()
+        .local_address(a_ip)
+        .advertise("[2001:db8::a]:6881".parse().unwrap())
+        .build()
+        .unwrap();
+    a.announce(&server.http, params(b'a', 6881, 0, Event::Started))
+        .await
+        .unwrap();
+
+    let b = HttpTrackerClient::new().unwrap();
+    let announce = |compact| AnnounceParams {
+        compact,
+        ..params(b'b', 51413, 100, Event::Started)
+    };
+    let compact = b.announce(&server.http, announce(true)).await.unwrap();
+    let dict = b.announce(&server.http, announce(false)).await.unwrap();
+
+    let (compact, dict) = (compact.peers().unwrap(), dict.peers().unwrap());
+    assert_eq!(addrs(compact.clone()), addrs(dict.clone()));
+    assert_eq!(2, dict.len());
+    assert!(compact.iter().all(|p| p.peer_id.is_none()));
+    assert!(dict
+        .iter()
+        .all(|p| p.peer_id == Some(PeerId(vec![b'a'; 20]))));
+}
+
+#[tokio::test]
+async fn only_the_key_moves_a_peer_or_stops_it() {
+    let from = |ip: [u8; 4]| {
+        HttpTrackerClient::builder()
+            .local_address(IpAddr::from(ip))
+            .build()
+            .unwrap()
+    };
+    let announce = |key: &str, event| AnnounceParams {
+        key: Some(key.to_string()),
+        ..params(b'a', 6881, 0, event)
+    };
+    let seen = |server: &TestTracker| {
+        let observer = HttpTrackerClient::new().unwrap();
+        let http = server.http.clone();
+        async move {
+            let response = observer
+                .announce(&http, params(b'o', 51413, 100, Event::Interval))
+                .await
+                .unwrap();
+            addrs(response.peers().unwrap())
+        }
+    };
+    let at = |ip: [u8; 4]| vec![SocketAddr::from((ip, 6881))];
+
+    let mut config = config();
+    let server = TestTracker::spawn_with(&config).await;
+    from([127, 0, 0, 2])
+        .announce(&server.http, announce("k1", Event::Started))
+        .await
+        .unwrap();
+
+    // With the key, the peer moves.
+    from([127, 0, 0, 3])
+        .announce(&server.http, announce("k1", Event::Interval))
+        .await
+        .unwrap();
+    assert_eq!(at([127, 0, 0, 3]), seen(&server).await);
+
+    // Without it, it stays where it is, however it is asked to leave.
+    let spoofer = from([127, 0, 0, 4]);
+    for event in [Event::Interval, Event::Stopped] {
+        spoofer
+            .announce(&server.http, announce("k2", event))
+            .await
+            .unwrap();
+        assert_eq!(at([127, 0, 0, 3]), seen(&server).await);
+    }
+
+    // Which is said in so many words if configured.
+    config.key_mismatch = KeyMismatch::Reject;
+    let server = TestTracker::spawn_with(&config).await;
+    from([127, 0, 0, 2])
+        .announce(&server.http, announce("k1", Event::Started))
+        .await
+        .unwrap();
+    let result = spoofer
+        .announce(&server.http, announce("k2", Event::Stopped))
+        .await;
+    assert!(matches!(
+        result,
+        Err(ClientError::Failure { reason, .. }) if reason == "key does not match the peer's"
+    ));
+    assert_eq!(at([127, 0, 0, 2]), seen(&server).await);
+}
+
+// The client's saved key outlives it, and a client with another key cannot
+// take its place.
+#[tokio::test]
+async fn knows_a_client_by_its_saved_key() {
+    let path = std::env::temp_dir().join(format!(
+        "hanekawa-announce-key-{}",
+        OffsetDateTime::now_utc().unix_timestamp_nanos()
+    ));
+    let client = |ip: [u8; 4], key: AnnounceKey| {
+        HttpTrackerClient::builder()
+            .local_address(IpAddr::from(ip))
+            .announce_key(key)
+            .build()
+            .unwrap()
+    };
+    let mut config = config();
+    config.key_mismatch = KeyMismatch::Reject;
+    let server = TestTracker::spawn_with(&config).await;
+    let endpoint = || {
+        let info_hash = InfoHash(vec![0xaa; 20]);
+        server.store.with_swarm(&info_hash, |swarm| {
+            swarm.map(|swarm| swarm.peers[&PeerId(vec![b'a'; 20])].endpoints[0].ip)
+        })
+    };
+
+    let saved = || AnnounceKey::load_or_generate(&path).unwrap();
+    client([127, 0, 0, 2], saved())
+        .announce(&server.http, params(b'a', 6881, 0, Event::Started))
+        .await
+        .unwrap();
+    client([127, 0, 0, 3], saved())
+        .announce(&server.http, params(b'a', 6881, 0, Event::Interval))
+        .await
+        .unwrap();
+    assert_eq!(Some(IpAddr::from([127, 0, 0, 3])), endpoint());
+
+    let result = client([127, 0, 0, 4], AnnounceKey::in_memory())
+        .announce(&server.http, params(b'a', 6881, 0, Event::Stopped))
+        .await;
+    assert!(matches!(
+        result,
+        Err(ClientError::Failure { reason, .. }) if reason == "key does not match the peer's"
+    ));
+    assert_eq!(Some(IpAddr::from([127, 0, 0, 3])), endpoint());
+
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+async fn one_address_cannot_fill_the_store() {
+    let mut config = config();
+    config.peer_limits.max_per_torrent = Some(4);
+    config.peer_limits.max_per_ip = Some(10);
+    config.peer_limits.max_new_torrents_per_ip = Some(3);
+    let server = TestTracker::spawn_with(&config).await;
+    let on = |torrent: u8, peer_id: u8| AnnounceParams {
+        info_hash: InfoHash(vec![torrent; 20]),
+        ..params(peer_id, 6881 + peer_id as u16, 100, Event::Started)
+    };
+    let kept = |torrent: u8| {
+        let info_hash = InfoHash(vec![torrent; 20]);
+        server.store.with_swarm(&info_hash, |swarm| {
+            swarm.map_or(0, |swarm| swarm.peers.len())
+        })
+    };
+    let refused = |result: Result<_, ClientError>, expected: &str| matches!(result, Err(ClientError::Failure { reason, .. }) if reason.starts_with(expected));
+    let a = HttpTrackerClient::new().unwrap();
+    let b = HttpTrackerClient::builder()
+        .local_address(IpAddr::from([127, 0, 0, 2]))
+        .build()
+        .unwrap();
+
+    // Past the swarm's limit, peers are answered but not kept.
+    for peer_id in 0..8 {
+        let response = a.announce(&server.http, on(1, peer_id)).await.unwrap();
+        assert_eq!(peer_id.min(4) as usize, response.peers().unwrap().len());
+    }
+    assert_eq!(4, kept(1));
+
+    // Only so many new torrents at a time, over either protocol.
+    a.announce(&server.http, on(2, 0)).await.unwrap();
+    a.announce(&server.http, on(3, 0)).await.unwrap();
+    let result = a.announce(&server.http, on(4, 0)).await;
+    assert!(refused(result, "too many new torrents from your address"));
+    let result = UdpTrackerClient::new()
+        .announce(&server.udp, on(5, 0))
+        .await;
+    assert!(refused(result, "too many new torrents from your address"));
+
+    // Only so many peers, across the torrents it announced to.
+    for peer_id in 1..5 {
+        a.announce(&server.http, on(2, peer_id)).await.unwrap();
+    }
+    assert_eq!(4, kept(2));
+    a.announce(&server.http, on(3, 1)).await.unwrap();
+    let result = a.announce(&server.http, on(3, 2)).await;
+    assert!(refused(
+        result,
+        "too many peers from your address, at most 10"
+    ));
+    // Peers already kept go on announcing.
+    a.announce(&server.http, on(3, 1)).await.unwrap();
+
+    // None of which holds another address back.
+    b.announce(&server.http, on(4, 0)).await.unwrap();
+    b.announce(&server.http, on(3, 2)).await.unwrap();
+    assert_eq!((1, 3), (kept(4), kept(3)));
+
+    // Places free up as peers are swept.
+    server
+        .store
+        .purge_swarm(PurgeSwarm {
+            info_hash: &InfoHash(vec![1; 20]),
+        })
+        .await
+        .unwrap();
+    a.announce(&server.http, on(3, 3)).await.unwrap();
+    assert_eq!(4, kept(3));
+}
+
+#[tokio::test]
+async fn full_swarms_may_make_room_for_new_peers() {
+    let mut config = config();
+    config.peer_limits.max_per_torrent = Some(2);
+    config.peer_limits.swarm_full = SwarmFull::Evict;
+    let server = TestTracker::spawn_with(&config).await;
+    let client = HttpTrackerClient::new().unwrap();
+
+    for peer_id in [b'a', b'b', b'c'] {
+        client
+            .announce(&server.http, params(peer_id, 6881, 100, Event::Started))
+            .await
+            .unwrap();
+        server.advance(Duration::SECOND);
+    }
+
+    let mut kept = server.store.with_swarm(&InfoHash(vec![0xaa; 20]), |swarm| {
+        let peers = swarm.unwrap().peers.keys();
+        peers.map(|peer_id| peer_id.0[0]).collect::<Vec<_>>()
+    });
+    kept.sort();
+    assert_eq!(vec![b'b', b'c'], kept);
+}
+
+#[tokio::test]
+async fn announces_to_every_tracker_of_a_magnet_link() {
+    let (one, two) = (
+        TestTracker::spawn(|_| {}).await,
+        TestTracker::spawn(|_| {}).await,
+    );
+
+    // C is known to both trackers, D only to the second.
+    let c_ip = IpAddr::from([127, 0, 0, 2]);
+    let d_ip = IpAddr::from([127, 0, 0, 3]);
+    let c = HttpTrackerClient::builder()
+        .local_address(c_ip)
+        .build()
+        .unwrap();
+    let d = HttpTrackerClient::builder()
+        .local_address(d_ip)
+        .build()
+        .unwrap();
+    for (client, url, peer_id) in [
+        (&c, &one.http, b'c'),
+        (&c, &two.http, b'c'),
+        (&d, &two.http, b'd'),
+    ] {
+        client
+            .announce(url, params(peer_id, 6881, 0, Event::Started))
+            .await
+            .unwrap();
+    }
+
+    let link: MagnetLink = format!(
+        "magnet:?xt=urn:btih:{}&tr={}&tr={}&tr=wss%3A%2F%2Ftracker.test",
+        "aa".repeat(20),
+        one.http,
+        two.udp
+    )
+    .parse()
+    .unwrap();
+    let client = TrackerClient::new(HttpTrackerClient::new().unwrap(), UdpTrackerClient::new());
+
+    // The link's info hash is the one announced.
+    let mut b = params(b'b', 51413, 100, Event::Started);
+    b.info_hash = InfoHash(vec![0; 20]);
+    let handle = MultiTrackerAnnouncer::from_magnet(client, &link, b).unwrap();
+    let mut peers = handle.peers();
+    peers.changed().await.unwrap();
+
+    let mut seen = addrs(peers.borrow().clone());
+    seen.sort();
+    assert_eq!(
+        vec![
+            SocketAddr::from((c_ip, 6881)),
+            SocketAddr::from((d_ip, 6881))
+        ],
+        seen
+    );
+    handle.shutdown().await.unwrap();
+
+    let link: MagnetLink =
+        "magnet:?xt=urn:btih:aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa&tr=ws%3A%2F%2Ftracker.test"
+            .parse()
+            .unwrap();
+    let b = params(b'b', 51413, 100, Event::Started);
+    assert!(matches!(
+        MultiTrackerAnnouncer::from_magnet(HttpTrackerClient::new().unwrap(), &link, b),
+        Err(ClientError::UnsupportedScheme(url)) if url == "ws://tracker.test"
+    ));
+}
+
+#[tokio::test]
+async fn banned_peers_are_refused_until_the_ban_expires() {
+    let server = TestTracker::spawn(|_| {}).await;
+
+    let a_ip = IpAddr::from([127, 0, 0, 2]);
+    let a = HttpTrackerClient::builder()
+        .local_address(a_ip)
+        .build()
+        .unwrap();
+    let b = UdpTrackerClient::new();
+
+    a.announce(&server.http, params(b'a', 6881, 0, Event::Started))
+        .await
+        .unwrap();
+
+    server
+        .bans
+        .add(
+            BanTarget::Ip(a_ip),
+            "abuse".to_string(),
+            Some(Duration::HOUR),
+        )
+        .await
+        .unwrap();
+
+    // Refused for good, as far as A can tell, while B is unaffected.
+    let result = a
+        .announce(&server.http, params(b'a', 6881, 0, Event::Interval))
+        .await;
+    assert!(matches!(
+        result,
+        Err(ClientError::Failure { reason, retry_in: Some(RetryIn::Never) })
+            if reason == "banned: abuse"
+    ));
+    assert!(a
+        .scrape(&server.http, &[InfoHash(vec![0xaa; 20])])
+        .await
+        .is_err());
+    b.announce(&server.udp, params(b'b', 51413, 100, Event::Started))
+        .await
+        .unwrap();
+
+    // The same goes for a peer id over UDP.
+    server
+        .bans
+        .add(
+            BanTarget::PeerId(PeerId(vec![b'b'; 20])),
+            "leech".to_string(),
+            None,
+        )
+        .await
+        .unwrap();
+    let result = b
+        .announce(&server.udp, params(b'b', 51413, 100, Event::Interval))
+        .await;
+    assert!(matches!(
+        result,
+        Err(ClientError::Failure { reason, .. }) if reason == "banned: leech"
+    ));
+
+    server.advance(Duration::HOUR);
+    a.announce(&server.http, params(b'a', 6881, 0, Event::Interval))
+        .await
+        .unwrap();
+}
+
+// Anyone could change anything otherwise, on the tracker's own port.
+#[tokio::test]
+async fn refuses_to_serve_the_admin_api_without_tokens() {
+    let mut config = config();
+    config.enable_admin_api = true;
+    assert!(matches!(
+        TestTracker::try_spawn_with(&config).await,
+        Err(hanekawa_server::Error::AdminWithoutToken)
+    ));
+}
+
+// Before any connection is made, keys that cannot be used are an error to
+// start with, not a process exit.
+#[tokio::test]
+async fn refuses_to_open_storage_with_unusable_keys() {
+    let mut config = config();
+    config.database_url = "postgres://localhost/hanekawa".to_string();
+    config.encryption.keys = vec![EncryptionKey {
+        id: "k1".to_string(),
+        key: Some("00".repeat(32)),
+        keyfile: None,
+    }];
+    assert!(matches!(
+        hanekawa_server::Storage::connect(&config).await,
+        Err(hanekawa_server::Error::Storage(_))
+    ));
+}
+
+#[tokio::test]
+async fn serves_only_registered_torrents_in_whitelist_mode() {
+    let mut config = config();
+    config.only_allowed_info_hashes = true;
+    config.enable_admin_api = true;
+    config.admin_token = Some("secret".to_string());
+    let server = TestTracker::spawn_with(&config).await;
+
+    let admin = reqwest::Client::new();
+    let tracker = HttpTrackerClient::new().unwrap();
+    let announce = |info_hash: &str| {
+        let mut params = params(b'a', 6881, 0, Event::Started);
+        params.info_hash = InfoHash::from_hex(info_hash);
+        tracker.announce(&server.http, params)
+    };
+
+    // A hybrid torrent, which is announced under both of its info hashes.
+    let response = admin
+        .post(format!("{}/torrents", server.admin))
+        .bearer_auth("secret")
+        .body(&include_bytes!("fixtures/hybrid.torrent")[..])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(201, response.status().as_u16());
+    let registered: serde_json::Value = response.json().await.unwrap();
+    let (v1, v2) = (
+        "5d6cb8efd635463b2746debbc3a6e8d2400e3772",
+        "29603b3eda8a502ca7581f9e2a51a8c234929b68",
+    );
+    assert_eq!(
+        serde_json::json!({ "info_hashes": [v1, v2], "name": "fixture.txt", "size": 14 }),
+        registered
+    );
+    for info_hash in [v1, v2] {
+        announce(info_hash).await.unwrap();
+    }
+
+    // Removed, and refused from the next announce on.
+    let response = admin
+        .delete(format!("{}/torrents/{v1}", server.admin))
+        .bearer_auth("secret")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(200, response.status().as_u16());
+    assert!(matches!(
+        announce(v1).await,
+        Err(ClientError::Failure { reason, .. }) if reason.starts_with("info hash not allowed")
+    ));
+    announce(v2).await.unwrap();
+
+    // Hashes can be registered without a .torrent file too.
+    let other = "bb".repeat(20);
+    assert!(announce(&other).await.is_err());
+    admin
+        .put(format!("{}/torrents/{other}", server.admin))
+        .bearer_auth("secret")
+        .send()
+        .await
+        .unwrap();
+    announce(&other).await.unwrap();
+}
+
+fn federated(upstream: &str, trusted: bool) -> Config {
+    let mut config = config();
+    config.federation.upstreams = vec![Upstream {
+        url: upstream.to_string(),
+        trusted,
+    }];
+    config.federation.timeout_ms = 10_000;
+    config
+}
+
+#[tokio::test]
+async fn merges_peers_from_upstream_trackers() {
+    let mut upstream_config = config();
+    upstream_config.federation.trusted_forwarders = vec![Ipv4Addr::LOCALHOST.into()];
+    let upstream = TestTracker::spawn_with(&upstream_config).await;
+    let local = TestTracker::spawn_with(&federated(&upstream.http, true)).await;
+
+    let (u_ip, l_ip) = (IpAddr::from([127, 0, 0, 3]), IpAddr::from([127, 0, 0, 2]));
+    let u = HttpTrackerClient::builder()
+        .local_address(u_ip)
+        .build()
+        .unwrap();
+    let l = HttpTrackerClient::builder()
+        .local_address(l_ip)
+        .build()
+        .unwrap();
+
+    // U is only known upstream, and L only here until its announce is
+    // forwarded.
+    u.announce(&upstream.http, params(b'u', 7000, 0, Event::Started))
+        .await
+        .unwrap();
+    let response = l
+        .announce(&local.http, params(b'l', 6881, 100, Event::Started))
+        .await
+        .unwrap();
+    assert_eq!((Some(0), Some(1)), (response.complete, response.incomplete));
+
+    let mut merged = vec![];
+    for _ in 0..100 {
+        let response = l
+            .announce(&local.http, params(b'l', 6881, 100, Event::Interval))
+            .await
+            .unwrap();
+        merged = addrs(response.peers().unwrap());
+        if !merged.is_empty() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    assert_eq!(vec![SocketAddr::from((u_ip, 7000))], merged);
+
+    // The upstream took L's own address, since it trusts the local tracker.
+    let response = u
+        .announce(&upstream.http, params(b'u', 7000, 0, Event::Interval))
+        .await
+        .unwrap();
+    assert_eq!(
+        vec![SocketAddr::from((l_ip, 6881))],
+        addrs(response.peers().unwrap())
+    );
+}
+
+#[tokio::test]
+async fn unresponsive_upstreams_do_not_delay_announces() {
+    // Takes connections and never answers.
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let upstream = format!("http://{}/announce", listener.local_addr().unwrap());
+    let accepted = Arc::new(Mutex::new(vec![]));
+    let held = accepted.clone();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            held.lock().unwrap().push(stream);
+        }
+    });
+    let local = TestTracker::spawn_with(&federated(&upstream, false)).await;
+
+    let peer_ip = IpAddr::from([127, 0, 0, 2]);
+    let a = HttpTrackerClient::builder()
+        .local_address(peer_ip)
+        .build()
+        .unwrap();
+    let b = HttpTrackerClient::new().unwrap();
+    a.announce(&local.http, params(b'a', 6881, 0, Event::Started))
+        .await
+        .unwrap();
+
+    for _ in 0..5 {
+        let started = std::time::Instant::now();
+        let response = b
+            .announce(&local.http, params(b'b', 51413, 100, Event::Interval))
+            .await
+            .unwrap();
+        assert!(started.elapsed() < std::time::Duration::from_secs(1));
+        assert_eq!(
+            vec![SocketAddr::from((peer_ip, 6881))],
+            addrs(response.peers().unwrap())
+        );
+    }
+
+    // The announces were forwarded all the same.
+    for _ in 0..100 {
+        if !accepted.lock().unwrap().is_empty() {
+            return;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    panic!("nothing was forwarded");
+}
+
+#[tokio::test]
+async fn torrent_stats_are_served_as_configured() {
+    let client = reqwest::Client::new();
+    let info_hash = "aa".repeat(20);
+    let fetch = |url: String, token: Option<&str>| {
+        let mut request = client.get(url);
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+        async move { request.send().await.unwrap().status().as_u16() }
+    };
+
+    for (visibility, public, admin) in [
+        (StatsVisibility::Off, 404, 404),
+        (StatsVisibility::Public, 200, 404),
+        (StatsVisibility::Admin, 404, 200),
+    ] {
+        let mut config = config();
+        config.enable_admin_api = true;
+        config.admin_token = Some("secret".to_string());
+        config.torrent_stats = visibility;
+        let server = TestTracker::spawn_with(&config).await;
+
+        HttpTrackerClient::new()
+            .unwrap()
+            .announce(&server.http, params(b'a', 6881, 0, Event::Started))
+            .await
+            .unwrap();
+
+        let root = server.http.trim_end_matches("/announce");
+        let public_url = format!("{root}/stats/torrent/{info_hash}");
+        let admin_url = format!("{}/stats/torrent/{info_hash}", server.admin);
+        assert_eq!(public, fetch(public_url, None).await, "{visibility:?}");
+        assert_eq!(admin, fetch(admin_url.clone(), Some("secret")).await);
+
+        if visibility == StatsVisibility::Admin {
+            assert_eq!(401, fetch(admin_url, None).await);
+        }
+    }
+}
+
+#[tokio::test]
+async fn announces_well_before_the_min_interval_may_be_refused() {
+    let server = TestTracker::spawn(|config| config.enforce_min_interval = true).await;
+
+    server
+        .announce(peer(b'a', 6881, 0, Event::Started))
+        .await
+        .unwrap();
+    let result = server.announce(peer(b'a', 6881, 0, Event::Interval)).await;
+    assert!(matches!(
+        result,
+        Err(ClientError::Failure { reason, .. }) if reason == "announced too soon, wait 900 seconds"
+    ));
+
+    // Until it is halfway through the min interval.
+    server.advance(Duration::seconds(300));
+    let result = server.announce(peer(b'a', 6881, 0, Event::Interval)).await;
+    assert!(matches!(
+        result,
+        Err(ClientError::Failure { reason, .. }) if reason == "announced too soon, wait 600 seconds"
+    ));
+    server.advance(Duration::seconds(150));
+    server
+        .announce(peer(b'a', 6881, 0, Event::Interval))
+        .await
+        .unwrap();
+
+    // Stopping is never too soon.
+    server
+        .announce(peer(b'a', 6881, 0, Event::Stopped))
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn listens_where_configured() {
+    // A busy port is an error to report.
+    let busy = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let busy = busy.local_addr().unwrap();
+    let mut on_busy_port = config();
+    on_busy_port.http_bind_port = busy.port();
+    assert!(matches!(
+        TestTracker::try_spawn_with(&on_busy_port).await,
+        Err(hanekawa_server::Error::Bind(addr, _)) if addr == busy
+    ));
+
+    let mut config = config();
+    config.bind_ipv6 = Some(Ipv6Addr::LOCALHOST);
+    let server = TestTracker::spawn_with(&config).await;
+    let http6 = server.http6.clone().unwrap();
+    assert_eq!(
+        server.http.rsplit(':').next(),
+        http6.rsplit(':').next(),
+        "{http6}"
+    );
+
+    let a = HttpTrackerClient::builder()
+        .local_address(IpAddr::from([127, 0, 0, 2]))
+        .build()
+        .unwrap();
+    a.announce(&server.http, params(b'a', 6881, 0, Event::Started))
+        .await
+        .unwrap();
+    let b = HttpTrackerClient::new().unwrap();
+    let response = b
+        .announce(&http6, params(b'b', 51413, 100, Event::Started))
+        .await
+        .unwrap();
+    assert_eq!(
+        vec![SocketAddr::from(([127, 0, 0, 2], 6881))],
+        addrs(response.peers().unwrap())
+    );
+
+    let c = UdpTrackerClient::new();
+    let response = c
+        .announce(
+            server.udp6.as_ref().unwrap(),
+            params(b'c', 6882, 100, Event::Started),
+        )
+        .await
+        .unwrap();
+    assert_eq!((Some(1), Some(2)), (response.complete, response.incomplete));
+}
+
+#[tokio::test]
+async fn serves_https_if_configured() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let fixtures = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+    let mut config = config();
+    config.tls = Some(TlsConfig {
+        cert: fixtures.join("localhost.pem"),
+        key: fixtures.join("localhost.key"),
+    });
+    let server = TestTracker::spawn_with(&config).await;
+
+    let pem = std::fs::read(fixtures.join("localhost.pem")).unwrap();
+    let mut roots = rustls::RootCertStore::empty();
+    for cert in rustls_pemfile::certs(&mut &pem[..]) {
+        roots.add(cert.unwrap()).unwrap();
+    }
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let tls = rustls::ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    let connector = tokio_rustls::TlsConnector::from(Arc::new(tls));
+
+    let url = server.http.strip_prefix("https://").unwrap();
+    let (addr, path) = url.split_once('/').unwrap();
+    let tcp = tokio::net::TcpStream::connect(addr).await.unwrap();
+    let mut stream = connector
+        .connect("localhost".try_into().unwrap(), tcp)
+        .await
+        .unwrap();
+    let query = format!(
+        "info_hash={}&peer_id={}&port=6881&uploaded=0&downloaded=0&left=0",
+        "%aa".repeat(20),
+        "a".repeat(20)
+    );
+    let request =
+        format!("GET /{path}?{query} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n");
+    stream.write_all(request.as_bytes()).await.unwrap();
+
+    let mut response = vec![];
+    stream.read_to_end(&mut response).await.unwrap();
+    let response = String::from_utf8_lossy(&response);
+    assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+    assert!(response.contains("8:intervali1800e"), "{response}");
+
+    // Plain HTTP gets nowhere.
+    let plain = reqwest::get(format!("http://{addr}/health")).await;
+    assert!(plain.is_err());
+}
+
+#[tokio::test]
+async fn failures_are_bencoded_failure_reasons() {
+    #[derive(serde::Deserialize)]
+    struct Failure {
+        #[serde(rename = "failure reason")]
+        reason: String,
+    }
+
+    let mut config = config();
+    config.only_allowed_info_hashes = true;
+    let server = TestTracker::spawn_with(&config).await;
+
+    let client = reqwest::Client::new();
+    let info_hash = "%aa".repeat(20);
+    let peer_id = "-qB4650-123456789012";
+    for (query, expected) in [
+        (
+            format!("info_hash={info_hash}&peer_id={peer_id}&uploaded=0&downloaded=0&left=0"),
+            "port",
+        ),
+        (
+            format!("info_hash=%aa&peer_id={peer_id}&port=6881&uploaded=0&downloaded=0&left=0"),
+            "info_hash is 1 bytes, not 20",
+        ),
+        (
+            format!("info_hash={info_hash}&peer_id={peer_id}&port=0&uploaded=0&downloaded=0&left=0"),
+            "port is 0",
+        ),
+        (
+            format!("info_hash={info_hash}&peer_id={peer_id}&port=6881&uploaded=0&downloaded=0&left=lots"),
+            "\"lots\" is not a u64",
+        ),
+        (
+            format!("info_hash={info_hash}&peer_id={peer_id}&port=6881&uploaded=0&downloaded=0&left=0&numwant=99999999999"),
+            "\"99999999999\" is not a u32",
+        ),
+        (
+            format!("info_hash={info_hash}&peer_id={peer_id}&port=6881&uploaded=0&downloaded=0&left=0"),
+            "info hash not allowed",
+        ),
+    ] {
+        let response = client
+            .get(format!("{}?{query}", server.http))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(200, response.status().as_u16(), "{query}");
+
+        let body = response.bytes().await.unwrap();
+        let failure: Failure = hanekawa_bencode::from_bytes(&body).unwrap();
+        assert!(failure.reason.contains(expected), "{}", failure.reason);
+    }
+}
+
+#[tokio::test]
+async fn clients_sending_invalid_requests_are_answered_and_then_timed_out() {
+    let mut config = config();
+    config.enable_admin_api = true;
+    config.admin_token = Some("secret".to_string());
+    config.invalid_requests.cache_after = 2;
+    config.invalid_requests.timeout_after = 5;
+    let server = TestTracker::spawn_with(&config).await;
+
+    let client = reqwest::Client::new();
+    let broken = || async {
+        let response = client
+            .get(format!("{}?info_hash=abc", server.http))
+            .send()
+            .await
+            .unwrap();
+        (response.status().as_u16(), response.bytes().await.unwrap())
+    };
+    let counts = || async {
+        let timeouts: serde_json::Value = client
+            .get(format!("{}/timeouts", server.admin))
+            .bearer_auth("secret")
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        (
+            timeouts["counts"]["repeats_answered"].as_u64().unwrap(),
+            timeouts["timeouts"].as_array().unwrap().len(),
+        )
+    };
+    let tracker = HttpTrackerClient::new().unwrap();
+
+    // The third time, the response is the same but comes from the cache.
+    let (status, failure) = broken().await;
+    assert_eq!(200, status);
+    assert_eq!((200, failure.clone()), broken().await);
+    assert_eq!((200, failure.clone()), broken().await);
+    assert_eq!((1, 0), counts().await);
+
+    // Fixing the client forgives it most of that.
+    tracker
+        .announce(&server.http, params(b'a', 6881, 0, Event::Started))
+        .await
+        .unwrap();
+    assert_eq!((200, failure.clone()), broken().await);
+    assert_eq!((1, 0), counts().await);
+
+    for _ in 0..3 {
+        assert_eq!((200, failure.clone()), broken().await);
+    }
+    assert_eq!((4, 1), counts().await);
+
+    // Timed out, valid requests or not, and told when to come back.
+    assert_eq!(200, broken().await.0);
+    let result = tracker
+        .announce(&server.http, params(b'a', 6881, 0, Event::Interval))
+        .await;
+    assert!(matches!(
+        result,
+        Err(ClientError::Failure { reason, retry_in: Some(RetryIn::After(_)) })
+            if reason.starts_with("too many invalid requests, retry in")
+    ));
+}
+
+#[tokio::test]
+async fn announces_and_scrapes_are_rate_limited_by_address() {
+    #[derive(serde::Deserialize)]
+    struct Reply {
+        #[serde(rename = "failure reason")]
+        reason: Option<String>,
+    }
+
+    let mut config = config();
+    config.trusted_proxies = vec!["127.0.0.1/32".parse().unwrap()];
+    config.rate_limit.enabled = true;
+    config.rate_limit.exempt = vec!["192.0.2.100/32".parse().unwrap()];
+    config.rate_limit.announce.burst = 2;
+    config.rate_limit.scrape.burst = 1;
+    let server = TestTracker::spawn_with(&config).await;
+    let scrape_url = server.http.replace("/announce", "/scrape");
+
+    let client = reqwest::Client::new();
+    let request = |url: &str, from: &'static str| {
+        let query = format!(
+            "info_hash={}&peer_id=-qB4650-123456789012&port=6881&uploaded=0&downloaded=0&left=0",
+            "%aa".repeat(20)
+        );
+        let request = client
+            .get(format!("{url}?{query}"))
+            .header("x-forwarded-for", from);
+        async move {
+            let response = request.send().await.unwrap();
+            assert_eq!(200, response.status().as_u16());
+            let body = response.bytes().await.unwrap();
+            let reply: Reply = hanekawa_bencode::from_bytes(&body).unwrap();
+            match reply.reason {
+                None => true,
+                Some(reason) => {
+                    assert!(reason.starts_with("too many requests"), "{reason}");
+                    false
+                }
+            }
+        }
+    };
+
+    assert!(request(&server.http, "192.0.2.1").await);
+    assert!(request(&server.http, "192.0.2.1").await);
+    assert!(!request(&server.http, "192.0.2.1").await);
+    // Scrapes and other addresses have limits of their own.
+    assert!(request(&scrape_url, "192.0.2.1").await);
+    assert!(!request(&scrape_url, "192.0.2.1").await);
+    assert!(request(&server.http, "192.0.2.2").await);
+    for _ in 0..5 {
+        assert!(request(&server.http, "192.0.2.100").await);
+    }
+}
+
+#[tokio::test]
+async fn maintenance_mode_drains_the_tracker() {
+    let mut config = config();
+    config.enable_admin_api = true;
+    config.admin_token = Some("secret".to_string());
+    let server = TestTracker::spawn_with(&config).await;
+
+    let client = reqwest::Client::new();
+    let root = server.http.trim_end_matches("/announce").to_string();
+    let health = || async {
+        let response = client.get(format!("{root}/health")).send().await.unwrap();
+        let status = response.status().as_u16();
+        let body: serde_json::Value = response.json().await.unwrap();
+        (status, body["status"].as_str().unwrap().to_string())
+    };
+    let admin = server.admin.clone();
+    let maintenance = |enabled: bool| {
+        let request = client
+            .put(format!("{admin}/maintenance"))
+            .bearer_auth("secret")
+            .json(&serde_json::json!({ "enabled": enabled }));
+        async move { assert_eq!(200, request.send().await.unwrap().status().as_u16()) }
+    };
+
+    let a_ip = IpAddr::from([127, 0, 0, 2]);
+    let a = HttpTrackerClient::builder()
+        .local_address(a_ip)
+        .build()
+        .unwrap();
+    let b = UdpTrackerClient::new();
+    a.announce(&server.http, params(b'a', 6881, 0, Event::Started))
+        .await
+        .unwrap();
+    b.announce(&server.udp, params(b'b', 51413, 100, Event::Started))
+        .await
+        .unwrap();
+    assert_eq!((200, "ok".to_string()), health().await);
+
+    maintenance(true).await;
+    assert_eq!((503, "maintenance".to_string()), health().await);
+
+    // Clients are told to come back later, over either protocol.
+    let result = a
+        .announce(&server.http, params(b'a', 6881, 0, Event::Interval))
+        .await;
+    assert!(matches!(
+        result,
+        Err(ClientError::Failure { reason, retry_in: Some(RetryIn::After(after)) })
+            if reason == "down for maintenance" && after == std::time::Duration::from_secs(30 * 60)
+    ));
+    assert!(a
+        .scrape(&server.http, &[InfoHash(vec![0xaa; 20])])
+        .await
+        .is_err());
+    let result = b
+        .announce(&server.udp, params(b'b', 51413, 100, Event::Interval))
+        .await;
+    assert!(matches!(
+        result,
+        Err(ClientError::Failure { reason, .. }) if reason == "down for maintenance"
+    ));
+
+    // But peers may still leave.
+    a.announce(&server.http, params(b'a', 6881, 0, Event::Stopped))
+        .await
+        .unwrap();
+
+    maintenance(false).await;
+    assert_eq!((200, "ok".to_string()), health().await);
+    let response = b
+        .announce(&server.udp, params(b'b', 51413, 100, Event::Interval))
+        .await
+        .unwrap();
+    assert!(response.peers().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn large_responses_are_compressed_for_clients_that_accept_it() {
+    let mut config = config();
+    config.compression.enabled = true;
+    config.compression.min_size = 1024;
+    let server = TestTracker::spawn_with(&config).await;
+
+    let tracker = HttpTrackerClient::new().unwrap();
+    let mut scrape = format!("{}?", server.http.replace("/announce", "/scrape"));
+    for n in 0..40 {
+        let mut params = params(b'a', 6881, 0, Event::Started);
+        params.info_hash = InfoHash(vec![n; 20]);
+        tracker.announce(&server.http, params).await.unwrap();
+        scrape.push_str(&format!("info_hash={}&", format!("%{n:02x}").repeat(20)));
+    }
+
+    // Decompressing is left to the test.
+    let client = reqwest::Client::builder()
+        .no_gzip()
+        .no_deflate()
+        .build()
+        .unwrap();
+    let fetch = |url: String, accept: Option<&'static str>| {
+        let mut request = client.get(url);
+        if let Some(accept) = accept {
+            request = request.header("accept-encoding", accept);
+        }
+        async move {
+            let response = request.send().await.unwrap();
+            let encoding = response
+                .headers()
+                .get("content-encoding")
+                .map(|v| v.to_str().unwrap().to_string());
+            (encoding, response.bytes().await.unwrap())
+        }
+    };
+
+    // As is for clients that do not ask.
+    let (encoding, plain) = fetch(scrape.clone(), None).await;
+    assert_eq!(None, encoding);
+    assert!(plain.len() >= 1024);
+
+    let (encoding, gzipped) = fetch(scrape, Some("gzip, deflate")).await;
+    assert_eq!(Some("gzip"), encoding.as_deref());
+    assert!(gzipped.len() < plain.len());
+    let mut unzipped = vec![];
+    std::io::Read::read_to_end(
+        &mut flate2::read::GzDecoder::new(&gzipped[..]),
+        &mut unzipped,
+    )
+    .unwrap();
+    // The files come in no particular order.
+    assert_eq!(plain.len(), unzipped.len());
+    assert!(unzipped.starts_with(b"d5:filesd20:"));
+
+    // A compact announce never is.
+    let announce = format!(
+        "{}?info_hash={}&peer_id={}&port=6882&uploaded=0&downloaded=0&left=0",
+        server.http,
+        "%00".repeat(20),
+        "b".repeat(20)
+    );
+    let (encoding, _) = fetch(announce, Some("gzip")).await;
+    assert_eq!(None, encoding);
+}
+
+#[tokio::test]
+async fn privacy_settings_leave_peer_lists_alone() {
+    let mut config = config();
+    config.enable_admin_api = true;
+    config.admin_token = Some("secret".to_string());
+    config.privacy.truncate_ips = true;
+    config.privacy.pseudonymize = true;
+    let server = TestTracker::spawn_with(&config).await;
+
+    let a_ip = IpAddr::from([127, 0, 0, 2]);
+    let a = HttpTrackerClient::builder()
+        .local_address(a_ip)
+        .build()
+        .unwrap();
+    let b = UdpTrackerClient::new();
+    a.announce(&server.http, params(b'a', 6881, 0, Event::Started))
+        .await
+        .unwrap();
+
+    // Peers need real addresses to connect to each other.
+    let response = b
+        .announce(&server.udp, params(b'b', 51413, 100, Event::Started))
+        .await
+        .unwrap();
+    assert_eq!(
+        vec![SocketAddr::from((a_ip, 6881))],
+        addrs(response.peers().unwrap())
+    );
+
+    // And bans need them to be enforced, though the audit log never sees it.
+    let client = reqwest::Client::new();
+    let response = client
+        .post(format!("{}/bans", server.admin))
+        .bearer_auth("secret")
+        .json(&serde_json::json!({ "kind": "ip", "value": "127.0.0.2", "reason": "abuse" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(201, response.status().as_u16());
+    let audit: serde_json::Value = client
+        .get(format!("{}/audit", server.admin))
+        .bearer_auth("secret")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert!(!audit.to_string().contains("127.0.0.2"));
+
+    let result = a
+        .announce(&server.http, params(b'a', 6881, 0, Event::Interval))
+        .await;
+    assert!(matches!(
+        result,
+        Err(ClientError::Failure { reason, .. }) if reason == "banned: abuse"
+    ));
+}
+
+#[tokio::test]
+async fn new_swarms_are_told_to_come_back_sooner() {
+    let mut config = config();
+    config.interval_ramp.enabled = true;
+    let server = TestTracker::spawn_with(&config).await;
+
+    let a_ip = IpAddr::from([127, 0, 0, 2]);
+    let a = HttpTrackerClient::builder()
+        .local_address(a_ip)
+        .build()
+        .unwrap();
+    let b = UdpTrackerClient::new();
+
+    let mut intervals = vec![];
+    for event in [Event::Started, Event::Interval] {
+        let response = a
+            .announce(&server.http, params(b'a', 6881, 0, event))
+            .await
+            .unwrap();
+        intervals.push(response.interval);
+    }
+    let response = b
+        .announce(&server.udp, params(b'b', 51413, 100, Event::Started))
+        .await
+        .unwrap();
+    intervals.push(response.interval);
+
+    assert_eq!(vec![60, 120, 60], intervals);
+}
+
+#[tokio::test]
+async fn refuses_clients_the_policy_denies() {
+    let mut config = config();
+    config.clients.deny = vec![ClientRule {
+        client: "utorrent".to_string(),
+        below: Some("3".parse().unwrap()),
+    }];
+    let server = TestTracker::spawn_with(&config).await;
+    let from = |prefix: &str, port| AnnounceParams {
+        peer_id: PeerId(format!("{prefix}abcdefghijkl").into_bytes()),
+        ..params(0, port, 100, Event::Started)
+    };
+    let refused = |result: Result<_, ClientError>| matches!(result, Err(ClientError::Failure { reason, .. }) if reason == "client not allowed: uTorrent 2.2.1");
+    let http = HttpTrackerClient::new().unwrap();
+
+    let result = http.announce(&server.http, from("-UT2210-", 6881)).await;
+    assert!(refused(result));
+    let result = UdpTrackerClient::new()
+        .announce(&server.udp, from("-UT2210-", 6882))
+        .await;
+    assert!(refused(result));
+
+    http.announce(&server.http, from("-UT3550-", 6883))
+        .await
+        .unwrap();
+    let response = http
+        .announce(&server.http, from("-qB4650-", 6884))
+        .await
+        .unwrap();
+    assert_eq!(1, response.peers().unwrap().len());
+}
+
+#[tokio::test]
+async fn large_swarms_are_told_to_come_back_later() {
+    let mut config = config();
+    config.interval_scale = IntervalScaleConfig {
+        enabled: true,
+        threshold: 2,
+        per_peer: 600.0,
+        max_interval: 3000,
+    };
+    let server = TestTracker::spawn_with(&config).await;
+    let http = HttpTrackerClient::new().unwrap();
+    let udp = UdpTrackerClient::new();
+
+    let mut intervals = vec![];
+    for peer_id in 0..6 {
+        let announce = params(peer_id, 6881 + peer_id as u16, 100, Event::Started);
+        let response = match peer_id % 2 {
+            0 => http.announce(&server.http, announce).await.unwrap(),
+            _ => udp.announce(&server.udp, announce).await.unwrap(),
+        };
+        intervals.push(response.interval);
+    }
+
+    assert_eq!(vec![1800, 1800, 2400, 3000, 3000, 3000], intervals);
+}
+
+#[tokio::test]
+async fn passkeys_are_rotated_with_a_grace_period_and_revoked_at_once() {
+    let mut config = config();
+    config.enable_admin_api = true;
+    config.admin_token = Some("secret".to_string());
+    config.passkeys.required = true;
+    let server = TestTracker::spawn_with(&config).await;
+
+    let client = reqwest::Client::new();
+    let admin = server.admin.clone();
+    let replace = |action: &'static str| {
+        let request = client
+            .post(format!("{admin}/users/1/passkey/{action}"))
+            .bearer_auth("secret");
+        async move {
+            let body: serde_json::Value = request.send().await.unwrap().json().await.unwrap();
+            body["passkey"].as_str().unwrap().to_string()
+        }
+    };
+    let tracker = server.http.trim_end_matches("/announce").to_string();
+    let url = |passkey: &str| format!("{tracker}/{passkey}/announce");
+
+    let a = HttpTrackerClient::builder()
+        .local_address(IpAddr::from([127, 0, 0, 2]))
+        .build()
+        .unwrap();
+    let announce = |passkey: String| {
+        let url = url(&passkey);
+        let a = &a;
+        async move {
+            a.announce(&url, params(b'a', 6881, 0, Event::Interval))
+                .await
+        }
+    };
+
+    let first = replace("rotate").await;
+    let response = announce(first.clone()).await.unwrap();
+    assert_eq!(None, response.warning_message);
+
+    // The old passkey still works, but the user is told to update.
+    let second = replace("rotate").await;
+    let response = announce(first.clone()).await.unwrap();
+    assert_eq!(
+        Some(config.passkeys.warning.clone()),
+        response.warning_message
+    );
+    let response = announce(second.clone()).await.unwrap();
+    assert_eq!(None, response.warning_message);
+
+    // Revoking leaves only the new one.
+    let third = replace("revoke").await;
+    for passkey in [first, second, "guess".to_string()] {
+        let result = announce(passkey).await;
+        assert!(matches!(
+            result,
+            Err(ClientError::Failure { reason, retry_in: Some(RetryIn::Never) })
+                if reason == "unregistered passkey"
+        ));
+    }
+    announce(third).await.unwrap();
+
+    let result = a
+        .announce(&server.http, params(b'a', 6881, 0, Event::Interval))
+        .await;
+    assert!(matches!(
+        result,
+        Err(ClientError::Failure { reason, .. }) if reason == "unregistered passkey"
+    ));
+
+    let result = UdpTrackerClient::new()
+        .announce(&server.udp, params(b'b', 51413, 100, Event::Started))
+        .await;
+    assert!(matches!(result, Err(ClientError::Failure { .. })));
+}
+
+// Nor do they take announces there as if the passkey was not in the way.
+#[tokio::test]
+async fn open_trackers_serve_no_passkey_urls() {
+    let server = TestTracker::spawn(|_| {}).await;
+    let tracker = server.http.trim_end_matches("/announce").to_string();
+    let a = HttpTrackerClient::new().unwrap();
+
+    for url in [
+        format!("{tracker}/0123456789abcdef0123456789abcdef/announce"),
+        format!("{tracker}/announce/0123456789abcdef0123456789abcdef"),
+    ] {
+        let result = a
+            .announce(&url, params(b'a', 6881, 0, Event::Started))
+            .await;
+        assert!(matches!(result, Err(ClientError::Status(404))));
+    }
+    a.announce(&server.http, params(b'a', 6881, 0, Event::Started))
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn private_trackers_tie_announces_to_users_by_passkey() {
+    let mut config = config();
+    config.enable_admin_api = true;
+    config.admin_token = Some("secret".to_string());
+    config.passkeys.required = true;
+    let server = TestTracker::spawn_with(&config).await;
+
+    let passkey: serde_json::Value = reqwest::Client::new()
+        .post(format!("{}/users/7/passkey/rotate", server.admin))
+        .bearer_auth("secret")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let passkey = passkey["passkey"].as_str().unwrap();
+    assert_eq!(32, passkey.len());
+    let tracker = server.http.trim_end_matches("/announce").to_string();
+
+    let a = HttpTrackerClient::builder()
+        .local_address(IpAddr::from([127, 0, 0, 2]))
+        .build()
+        .unwrap();
+    let b = HttpTrackerClient::builder()
+        .local_address(IpAddr::from([127, 0, 0, 3]))
+        .build()
+        .unwrap();
+    a.announce(
+        &format!("{tracker}/announce/{passkey}"),
+        params(b'a', 6881, 0, Event::Started),
+    )
+    .await
+    .unwrap();
+    let response = b
+        .announce(
+            &format!("{tracker}/{passkey}/announce"),
+            params(b'b', 6882, 100, Event::Started),
+        )
+        .await
+        .unwrap();
+    assert_eq!(1, response.peers().unwrap().len());
+
+    let user_id = server.store.with_swarm(&InfoHash(vec![0xaa; 20]), |swarm| {
+        swarm.unwrap().peers[&PeerId(vec![b'a'; 20])]
+            .user_id
+            .clone()
+    });
+    assert_eq!(Some("7".to_string()), user_id);
+
+    let scrape = reqwest::get(format!(
+        "{tracker}/scrape/{passkey}?info_hash={}",
+        "%aa".repeat(20)
+    ))
+    .await
+    .unwrap()
+    .bytes()
+    .await
+    .unwrap();
+    assert!(scrape.starts_with(b"d5:files"));
+
+    let unregistered = "0123456789abcdef0123456789abcdef";
+    for url in [
+        server.http.clone(),
+        format!("{tracker}/announce/{unregistered}"),
+        format!("{tracker}/announce/{}", &passkey[..31]),
+        format!("{tracker}/announce/{}", passkey.to_uppercase()),
+    ] {
+        let result = a
+            .announce(&url, params(b'a', 6881, 0, Event::Interval))
+            .await;
+        assert!(
+            matches!(
+                &result,
+                Err(ClientError::Failure { reason, .. }) if reason == "unregistered passkey"
+            ),
+            "{url}: {result:?}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn only_torrents_the_policy_allows_are_served() {
+    let list = std::env::temp_dir().join(format!(
+        "hanekawa-allowlist-{}",
+        OffsetDateTime::now_utc().unix_timestamp_nanos()
+    ));
+    let (listed, unlisted) = (InfoHash(vec![0xaa; 20]), InfoHash(vec![0xbb; 20]));
+    std::fs::write(&list, format!("{}\n", listed.to_hex())).unwrap();
+    let mut config = config();
+    config.torrent_policy = TorrentPolicyConfig {
+        mode: TorrentPolicyMode::Allowlist,
+        file: Some(list.clone()),
+        torrents_dir: None,
+    };
+    let server = TestTracker::spawn_with(&config).await;
+
+    let a = HttpTrackerClient::builder()
+        .local_address(IpAddr::from([127, 0, 0, 2]))
+        .build()
+        .unwrap();
+    let unlisted_params = |peer_id| AnnounceParams {
+        info_hash: unlisted.clone(),
+        ..params(peer_id, 6881, 0, Event::Started)
+    };
+    a.announce(&server.http, params(b'a', 6881, 0, Event::Started))
+        .await
+        .unwrap();
+    for result in [
+        a.announce(&server.http, unlisted_params(b'a')).await,
+        UdpTrackerClient::new()
+            .announce(&server.udp, unlisted_params(b'b'))
+            .await,
+    ] {
+        assert!(matches!(
+            result,
+            Err(ClientError::Failure { reason, .. })
+                if reason == "torrent not registered with this tracker"
+        ));
+    }
+    assert!(server.store.with_swarm(&unlisted, |swarm| swarm.is_none()));
+
+    let scrape = a
+        .scrape(&server.http, &[listed.clone(), unlisted.clone()])
+        .await
+        .unwrap();
+    assert_eq!(vec![&listed], scrape.files.keys().collect::<Vec<_>>());
+
+    // Listed after a reload, with the swarms already served kept.
+    std::fs::write(
+        &list,
+        format!("{}\n{}\n", listed.to_hex(), unlisted.to_hex()),
+    )
+    .unwrap();
+    assert_eq!(2, server.torrent_policy.reload().unwrap());
+    let response = a
+        .announce(&server.http, unlisted_params(b'a'))
+        .await
+        .unwrap();
+    assert!(response.peers().unwrap().is_empty());
+    let response = HttpTrackerClient::new()
+        .unwrap()
+        .announce(&server.http, params(b'c', 6882, 100, Event::Started))
+        .await
+        .unwrap();
+    assert_eq!(1, response.peers().unwrap().len());
+
+    let _ = std::fs::remove_file(&list);
+}
+
+#[tokio::test]
+async fn json_responses_say_what_bencode_does() {
+    let mut config = config();
+    config.json_responses = true;
+    let server = TestTracker::spawn_with(&config).await;
+
+    let a_ip = IpAddr::from([127, 0, 0, 2]);
+    let a = HttpTrackerClient::builder()
+        .local_address(a_ip)
+        .build()
+        .unwrap();
+    let b = HttpTrackerClient::new().unwrap();
+    a.announce(&server.http, params(b'a', 6881, 0, Event::Started))
+        .await
+        .unwrap();
+    let bencoded = b
+        .announce(&server.http, params(b'b', 51413, 100, Event::Started))
+        .await
+        .unwrap();
+
+    let client = reqwest::Client::new();
+    let fetch = |url: String, accept: &'static str| {
+        let request = client.get(url).header("accept", accept);
+        async move {
+            let response = request.send().await.unwrap();
+            let content_type = response.headers()["content-type"]
+                .to_str()
+                .unwrap()
+                .to_string();
+            (content_type, response.bytes().await.unwrap())
+        }
+    };
+    let query = format!(
+        "info_hash={}&peer_id={}&port=51413&uploaded=0&downloaded=0&left=100",
+        "%aa".repeat(20),
+        "b".repeat(20)
+    );
+
+    let (content_type, body) = fetch(
+        format!("{}?{query}&compact=1", server.http),
+        "application/json",
+    )
+    .await;
+    assert_eq!("application/json", content_type);
+    let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(serde_json::json!(bencoded.interval), json["interval"]);
+    assert_eq!(serde_json::json!(bencoded.complete), json["complete"]);
+    assert_eq!(serde_json::json!(bencoded.incomplete), json["incomplete"]);
+    let json_addrs = json["peers"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|p| {
+            SocketAddr::new(
+                p["ip"].as_str().unwrap().parse().unwrap(),
+                p["port"].as_u64().unwrap() as u16,
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(addrs(bencoded.peers().unwrap()), json_addrs);
+    assert_eq!(serde_json::json!([]), json["peers6"]);
+
+    // Peer ids, where they are sent, are in hex.
+    let (_, body) = fetch(
+        format!("{}?{query}&compact=0", server.http),
+        "application/json",
+    )
+    .await;
+    let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(
+        serde_json::json!([{ "peer id": "61".repeat(20), "ip": "127.0.0.2", "port": 6881 }]),
+        json["peers"]
+    );
+
+    let info_hash = InfoHash(vec![0xaa; 20]);
+    let scrape = b
+        .scrape(&server.http, std::slice::from_ref(&info_hash))
+        .await
+        .unwrap();
+    let file = &scrape.files[&info_hash];
+    let (_, body) = fetch(
+        format!(
+            "{}?info_hash={}",
+            server.http.replace("/announce", "/scrape"),
+            "%aa".repeat(20)
+        ),
+        "application/json",
+    )
+    .await;
+    let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(
+        serde_json::json!({ "files": { "aa".repeat(20): {
+            "complete": file.complete,
+            "downloaded": file.downloaded,
+            "incomplete": file.incomplete,
+        }}}),
+        json
+    );
+
+    // Off by default.
+    let server = TestTracker::spawn(|_| {}).await;
+    let (content_type, body) = fetch(format!("{}?{query}", server.http), "application/json").await;
+    assert_eq!("application/octet-stream", content_type);
+    assert!(body.starts_with(b"d"));
+}
+
+#[tokio::test]
+async fn peer_lists_come_as_clients_ask_per_bep_23() {
+    use hanekawa_bencode::Value;
+
+    let fetch = |server: &TestTracker, params: &str| {
+        let url = format!(
+            "{}?info_hash={}&peer_id={}&port=51413&uploaded=0&downloaded=0&left=100{params}",
+            server.http,
+            "%aa".repeat(20),
+            "b".repeat(20)
+        );
+        async move { reqwest::get(url).await.unwrap().bytes().await.unwrap() }
+    };
+    // Each peer's keys for dictionary model peer lists, or none for compact
+    // ones.
+    let peer_keys = |body: &[u8]| -> Option<Vec<String>> {
+        let Value::Dict(response) = hanekawa_bencode::parse(body).unwrap().into_value() else {
+            panic!("not a dictionary");
+        };
+        match response
+            .into_iter()
+            .find(|(k, _)| *k == b"peers")
+            .unwrap()
+            .1
+        {
+            Value::Bytes(peers) => {
+                assert_eq!(6, peers.len());
+                None
+            }
+            Value::List(mut peers) => match peers.pop() {
+                Some(Value::Dict(peer)) if peers.is_empty() => Some(
+                    peer.into_iter()
+                        .map(|(k, _)| String::from_utf8(k.to_vec()).unwrap())
+                        .collect(),
+                ),
+                peers => panic!("{peers:?}"),
+            },
+            peers => panic!("{peers:?}"),
+        }
+    };
+    let a = HttpTrackerClient::builder()
+        .local_address(IpAddr::from([127, 0, 0, 2]))
+        .build()
+        .unwrap();
+
+    let server = TestTracker::spawn(|_| {}).await;
+    a.announce(&server.http, params(b'a', 6881, 0, Event::Started))
+        .await
+        .unwrap();
+    let with_ids = Some(vec!["ip".into(), "peer id".into(), "port".into()]);
+    let without_ids = Some(vec!["ip".into(), "port".into()]);
+    for (params, expected) in [
+        ("&compact=1", None),
+        ("&compact=1&no_peer_id=1", None),
+        ("&compact=0", with_ids.clone()),
+        ("&compact=0&no_peer_id=1", without_ids.clone()),
+        ("", None),
+    ] {
+        assert_eq!(
+            expected,
+            peer_keys(&fetch(&server, params).await),
+            "{params}"
+        );
+    }
+
+    let mut lists = config();
+    lists.compact.default = false;
+    let server = TestTracker::spawn_with(&lists).await;
+    a.announce(&server.http, params(b'a', 6881, 0, Event::Started))
+        .await
+        .unwrap();
+    assert_eq!(with_ids, peer_keys(&fetch(&server, "").await));
+    assert_eq!(
+        without_ids,
+        peer_keys(&fetch(&server, "&no_peer_id=1").await)
+    );
+
+    let mut required = config();
+    required.compact.required = true;
+    let server = TestTracker::spawn_with(&required).await;
+    a.announce(&server.http, params(b'a', 6881, 0, Event::Started))
+        .await
+        .unwrap();
+    assert_eq!(
+        &b"d14:failure reason43:compact peer lists required, send compact=18:retry in5:nevere"[..],
+        fetch(&server, "&compact=0").await
+    );
+    assert_eq!(None, peer_keys(&fetch(&server, "").await));
+}
+
+#[tokio::test]
+async fn full_scrapes_are_served_from_a_snapshot_if_allowed() {
+    use hanekawa_bencode::Value;
+
+    let announce = |server: &TestTracker, info_hash: u8| {
+        let client = HttpTrackerClient::new().unwrap();
+        let url = server.http.clone();
+        async move {
+            let params = AnnounceParams {
+                info_hash: InfoHash(vec![info_hash; 20]),
+                ..params(b'a', 6881, 0, Event::Started)
+            };
+            client.announce(&url, params).await.unwrap();
+        }
+    };
+    let full_scrape = |server: &TestTracker| {
+        let url = server.http.replace("/announce", "/scrape");
+        async move {
+            let body = reqwest::get(url).await.unwrap().bytes().await.unwrap();
+            let Value::Dict(response) = hanekawa_bencode::parse(&body).unwrap().into_value() else {
+                panic!("not a dictionary");
+            };
+            let (mut files, mut reason) = (vec![], None);
+            for (key, value) in response {
+                match (key, value) {
+                    (b"files", Value::Dict(f)) => files.extend(f.into_iter().map(|(k, _)| k[0])),
+                    (b"failure reason", Value::Bytes(r)) => {
+                        reason = Some(String::from_utf8(r.to_vec()).unwrap())
+                    }
+                    _ => {}
+                }
+            }
+            (files, reason)
+        }
+    };
+
+    let server = TestTracker::spawn(|_| {}).await;
+    announce(&server, 0xaa).await;
+    assert_eq!(
+        (
+            vec![],
+            Some("full scrapes are not served, ask for info hashes".to_string())
+        ),
+        full_scrape(&server).await
+    );
+
+    let mut config = config();
+    config.scrape.full_scrape = hanekawa_common::FullScrape::Allow;
+    let server = TestTracker::spawn_with(&config).await;
+    announce(&server, 0xaa).await;
+    announce(&server, 0xbb).await;
+    assert_eq!((vec![0xaa, 0xbb], None), full_scrape(&server).await);
+    // Until the snapshot is taken again.
+    announce(&server, 0xcc).await;
+    assert_eq!((vec![0xaa, 0xbb], None), full_scrape(&server).await);
+
+    config.scrape.full_scrape_refresh = 0;
+    let server = TestTracker::spawn_with(&config).await;
+    announce(&server, 0xaa).await;
+    assert_eq!((vec![0xaa], None), full_scrape(&server).await);
+    announce(&server, 0xbb).await;
+    assert_eq!((vec![0xaa, 0xbb], None), full_scrape(&server).await);
+}
+
+#[tokio::test]
+async fn the_admin_api_inspects_and_manages_swarms() {
+    let mut config = config();
+    config.enable_admin_api = true;
+    config.admin_token = Some("secret".to_string());
+    let server = TestTracker::spawn_with(&config).await;
+
+    let a = HttpTrackerClient::builder()
+        .local_address(IpAddr::from([127, 0, 0, 2]))
+        .build()
+        .unwrap();
+    let b = UdpTrackerClient::new();
+    for (info_hash, left) in [(0xaa, 0), (0xbb, 0), (0xcc, 100)] {
+        let params = AnnounceParams {
+            info_hash: InfoHash(vec![info_hash; 20]),
+            ..params(b'a', 6881, left, Event::Started)
+        };
+        a.announce(&server.http, params).await.unwrap();
+    }
+    b.announce(&server.udp, params(b'b', 51413, 100, Event::Started))
+        .await
+        .unwrap();
+
+    let client = reqwest::Client::new();
+    let get = |path: &str| {
+        let request = client
+            .get(format!("{}{path}", server.admin))
+            .bearer_auth("secret");
+        async move {
+            let response = request.send().await.unwrap();
+            assert_eq!(200, response.status().as_u16());
+            response.json::<serde_json::Value>().await.unwrap()
+        }
+    };
+    let counts = |list: serde_json::Value| {
+        let mut torrents = list["torrents"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|t| {
+                (
+                    t["info_hash"].as_str().unwrap().to_string(),
+                    t["seeders"].as_u64().unwrap(),
+                    t["leechers"].as_u64().unwrap(),
+                )
+            })
+            .collect::<Vec<_>>();
+        torrents.sort();
+        torrents
+    };
+
+    let (aa, bb, cc) = ("aa".repeat(20), "bb".repeat(20), "cc".repeat(20));
+    assert_eq!(
+        vec![(aa.clone(), 1, 1), (bb.clone(), 1, 0), (cc.clone(), 0, 1)],
+        counts(get("/torrents").await)
+    );
+    let peers = get(&format!("/torrents/{aa}/peers")).await;
+    let mut peer_ids = peers
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|p| p["peer_id"].as_str().unwrap().to_string())
+        .collect::<Vec<_>>();
+    peer_ids.sort();
+    assert_eq!(vec!["61".repeat(20), "62".repeat(20)], peer_ids);
+
+    let response = client
+        .delete(format!("{}/torrents/{bb}", server.admin))
+        .bearer_auth("secret")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(vec![(aa, 1, 1), (cc, 0, 1)], counts(get("/torrents").await));
+
+    let response = client
+        .post(format!("{}/bans", server.admin))
+        .bearer_auth("secret")
+        .json(&serde_json::json!({
+            "kind": "peer_id",
+            "value": "62".repeat(20),
+            "reason": "leech",
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(201, response.status().as_u16());
+    let result = b
+        .announce(&server.udp, params(b'b', 51413, 100, Event::Interval))
+        .await;
+    assert!(matches!(
+        result,
+        Err(ClientError::Failure { reason, .. }) if reason == "banned: leech"
+    ));
+
+    // Nothing without the token.
+    let response = client
+        .get(format!("{}/torrents", server.admin))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn swarms_outlive_a_restart() {
+    let mut server = TestTracker::spawn(|_| {}).await;
+    let a = HttpTrackerClient::builder()
+        .local_address(IpAddr::from([127, 0, 0, 2]))
+        .build()
+        .unwrap();
+    a.announce(&server.http, params(b'a', 6881, 0, Event::Started))
+        .await
+        .unwrap();
+
+    server.shut_down().await;
+    let b = HttpTrackerClient::new().unwrap();
+    assert!(b
+        .announce(&server.http, params(b'b', 51413, 100, Event::Started))
+        .await
+        .is_err());
+
+    let server = TestTracker::try_spawn_on(&config(), server.store.clone())
+        .await
+        .unwrap();
+    let response = b
+        .announce(&server.http, params(b'b', 51413, 100, Event::Started))
+        .await
+        .unwrap();
+    assert_eq!(
+        vec![SocketAddr::from(([127, 0, 0, 2], 6881))],
+        addrs(response.peers().unwrap())
+    );
+}
+
+#[tokio::test]
+async fn swarms_in_memory_are_snapshotted_across_a_restart() {
+    let path = std::env::temp_dir().join(format!("hanekawa-{}.snapshot", std::process::id()));
+    let mut config = config();
+    config.database_url = "memory:".to_string();
+    config.snapshot_path = Some(path.clone());
+    // Picked up front, as `start_with_config` does not say which port it
+    // bound.
+    let port = std::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    config.http_bind_port = port;
+    let http = format!("http://127.0.0.1:{port}/announce");
+
+    let start = |config: Config| {
+        let kt = tokio_util::sync::CancellationToken::new();
+        let task = tokio::spawn(hanekawa_server::start_with_config(config, kt.clone()));
+        (kt, task)
+    };
+    // Once it is listening.
+    let announce = |client: HttpTrackerClient, params: AnnounceParams| {
+        let http = http.clone();
+        async move {
+            for _ in 0..100 {
+                if let Ok(response) = client.announce(&http, params.clone()).await {
+                    return response;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            }
+            panic!("the tracker never answered");
+        }
+    };
+    let a = HttpTrackerClient::builder()
+        .local_address(IpAddr::from([127, 0, 0, 2]))
+        .build()
+        .unwrap();
+    let b = HttpTrackerClient::new().unwrap();
+
+    let (kt, tracker) = start(config.clone());
+    announce(a, params(b'a', 6881, 0, Event::Started)).await;
+    kt.cancel();
+    tracker.await.unwrap().unwrap();
+    assert!(path.exists());
+
+    let (kt, tracker) = start(config);
+    let response = announce(b, params(b'b', 51413, 100, Event::Started)).await;
+    assert_eq!(
+        vec![SocketAddr::from(([127, 0, 0, 2], 6881))],
+        addrs(response.peers().unwrap())
+    );
+    kt.cancel();
+    tracker.await.unwrap().unwrap();
+    std::fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,3 @@
+ALTER TABLE peer_announces
+      ADD COLUMN other_ip inet,
+      ADD COLUMN other_port integer;
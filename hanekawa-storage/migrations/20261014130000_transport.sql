@@ -0,0 +1,2 @@
+ALTER TABLE peer_announces
+      ADD COLUMN transport text;
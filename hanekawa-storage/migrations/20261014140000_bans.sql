@@ -0,0 +1,8 @@
+CREATE TABLE bans(
+       id bigserial PRIMARY KEY,
+       kind text NOT NULL,
+       value text NOT NULL,
+       reason text NOT NULL,
+       created_ts timestamptz NOT NULL,
+       expires_ts timestamptz
+);
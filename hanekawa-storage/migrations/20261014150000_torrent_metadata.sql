@@ -0,0 +1,3 @@
+ALTER TABLE info_hashes
+      ADD COLUMN name text,
+      ADD COLUMN size bigint;
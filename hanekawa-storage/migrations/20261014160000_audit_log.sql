@@ -0,0 +1,8 @@
+CREATE TABLE audit_log(
+       id bigserial PRIMARY KEY,
+       ts timestamptz NOT NULL,
+       token_id text,
+       action text NOT NULL,
+       target text NOT NULL,
+       outcome text NOT NULL
+);
@@ -0,0 +1,9 @@
+-- When encrypted, peer_id holds an HMAC of the peer id, ip and other_ip are
+-- left empty, and all three are in sealed under the key named by key_id.
+ALTER TABLE peer_announces
+      ALTER COLUMN ip DROP NOT NULL,
+      ADD COLUMN ip_lookup bytea,
+      ADD COLUMN sealed bytea,
+      ADD COLUMN key_id text;
+
+CREATE INDEX peer_announces_ip_lookup ON peer_announces(ip_lookup) WHERE ip_lookup IS NOT NULL;
@@ -0,0 +1,8 @@
+CREATE TABLE passkeys(
+       passkey text PRIMARY KEY,
+       user_id text NOT NULL,
+       created_ts timestamptz NOT NULL,
+       expires_ts timestamptz
+);
+
+CREATE INDEX passkeys_user_id ON passkeys(user_id);
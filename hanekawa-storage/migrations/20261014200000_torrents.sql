@@ -0,0 +1,4 @@
+CREATE TABLE torrents(
+       info_hash bytea PRIMARY KEY,
+       completed bigint NOT NULL DEFAULT 0
+);
@@ -0,0 +1 @@
+ALTER TABLE peer_announces ADD COLUMN completed boolean NOT NULL DEFAULT false;
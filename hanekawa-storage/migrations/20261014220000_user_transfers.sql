@@ -0,0 +1,11 @@
+ALTER TABLE peer_announces ADD COLUMN user_id text;
+
+-- What each user uploaded and downloaded of each torrent, summed from how
+-- much the counters they announced went up.
+CREATE TABLE user_transfers(
+       user_id text NOT NULL,
+       info_hash bytea NOT NULL,
+       uploaded bigint NOT NULL DEFAULT 0,
+       downloaded bigint NOT NULL DEFAULT 0,
+       PRIMARY KEY (user_id, info_hash)
+);
@@ -0,0 +1,2 @@
+-- A digest of the key the peer announced with, if it sent one.
+ALTER TABLE peer_announces ADD COLUMN announce_key bytea;
@@ -0,0 +1,3 @@
+-- Peers are counted by address for the per-address limit, as sealed ones
+-- already are by ip_lookup.
+CREATE INDEX peer_announces_ip ON peer_announces(ip) WHERE ip IS NOT NULL;
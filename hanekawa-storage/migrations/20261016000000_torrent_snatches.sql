@@ -0,0 +1,11 @@
+-- Which peers completed each torrent, kept apart from their announces so
+-- that a peer that stopped and came back is not counted again.
+CREATE TABLE torrent_snatches(
+       info_hash bytea NOT NULL,
+       peer_id bytea NOT NULL,
+       PRIMARY KEY (info_hash, peer_id)
+);
+
+INSERT INTO torrent_snatches(info_hash, peer_id)
+SELECT info_hash, peer_id FROM peer_announces WHERE completed
+ON CONFLICT DO NOTHING;
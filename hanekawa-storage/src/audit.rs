@@ -0,0 +1,86 @@
+use crate::failed;
+use hanekawa_common::repository::{
+    audit::{AppendAudit, AuditRepository as Repository, GetAudit, PurgeAudit},
+    Error,
+};
+use hanekawa_common::types::AuditRecord;
+
+use sqlx::postgres::PgPool;
+
+#[derive(Clone)]
+pub struct AuditRepository {
+    pool: PgPool,
+}
+
+impl AuditRepository {
+    pub(super) fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl Repository for AuditRepository {
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn append_audit(&self, cmd: AppendAudit<'_>) -> Result<(), Error> {
+        sqlx::query!(
+            "
+INSERT INTO audit_log(ts, token_id, action, target, outcome)
+VALUES($1, $2, $3, $4, $5)
+",
+            cmd.timestamp,
+            cmd.token_id,
+            cmd.action,
+            cmd.target,
+            cmd.outcome
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(failed)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn get_audit(&self, cmd: GetAudit) -> Result<Vec<AuditRecord>, Error> {
+        let result = sqlx::query!(
+            "
+SELECT id, ts, token_id, action, target, outcome
+FROM audit_log
+WHERE $1::bigint IS NULL OR id < $1
+ORDER BY id DESC
+LIMIT $2
+",
+            cmd.before.map(|id| id as i64),
+            cmd.limit as i64
+        )
+        .map(|r| AuditRecord {
+            id: r.id as u64,
+            timestamp: r.ts,
+            token_id: r.token_id,
+            action: r.action,
+            target: r.target,
+            outcome: r.outcome,
+        })
+        .fetch_all(&self.pool)
+        .await
+        .map_err(failed)?;
+
+        Ok(result)
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn purge_audit(&self, cmd: PurgeAudit) -> Result<u64, Error> {
+        let result = sqlx::query!(
+            "
+DELETE FROM audit_log
+WHERE ts < $1
+",
+            cmd.before
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(failed)?;
+
+        Ok(result.rows_affected())
+    }
+}
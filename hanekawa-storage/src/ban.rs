@@ -0,0 +1,97 @@
+use crate::failed;
+use hanekawa_common::repository::{
+    ban::{AddBan, BanRepository as Repository, GetBans, RemoveBan},
+    Error,
+};
+use hanekawa_common::types::{Ban, BanTarget};
+
+use sqlx::postgres::PgPool;
+
+#[derive(Clone)]
+pub struct BanRepository {
+    pool: PgPool,
+}
+
+impl BanRepository {
+    pub(super) fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl Repository for BanRepository {
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn add_ban(&self, cmd: AddBan<'_>) -> Result<u64, Error> {
+        let id = sqlx::query!(
+            "
+INSERT INTO bans(kind, value, reason, created_ts, expires_ts)
+VALUES($1, $2, $3, $4, $5)
+RETURNING id
+",
+            cmd.target.kind(),
+            cmd.target.value(),
+            cmd.reason,
+            cmd.created,
+            cmd.expires
+        )
+        .map(|r| r.id as u64)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(failed)?;
+
+        Ok(id)
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn remove_ban(&self, cmd: RemoveBan) -> Result<bool, Error> {
+        let result = sqlx::query!(
+            "
+DELETE FROM bans
+WHERE id = $1
+",
+            cmd.id as i64
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(failed)?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn get_bans(&self, cmd: GetBans) -> Result<Vec<Ban>, Error> {
+        let rows = sqlx::query!(
+            "
+SELECT id, kind, value, reason, created_ts, expires_ts
+FROM bans
+WHERE expires_ts IS NULL OR expires_ts > $1
+ORDER BY id
+",
+            cmd.active_at
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(failed)?;
+
+        // Targets are written by `BanTarget::kind` and `value`, so only a
+        // hand-edited row can fail to parse.
+        let bans = rows
+            .into_iter()
+            .filter_map(|r| match BanTarget::parse(&r.kind, &r.value) {
+                Ok(target) => Some(Ban {
+                    id: r.id as u64,
+                    target,
+                    reason: r.reason,
+                    created: r.created_ts,
+                    expires: r.expires_ts,
+                }),
+                Err(e) => {
+                    log::warn!("skipping ban {}, {e}", r.id);
+                    None
+                }
+            })
+            .collect();
+
+        Ok(bans)
+    }
+}
@@ -1,8 +1,11 @@
+use crate::failed;
 use hanekawa_common::repository::{
-    info_hash::{GetInfoHashSummary, InfoHashRepository as Repository, UpdateInfoHash},
+    info_hash::{
+        GetInfoHashSummary, InfoHashRepository as Repository, RegisterTorrent, UpdateInfoHash,
+    },
     Error,
 };
-use hanekawa_common::types::{InfoHashStatus, InfoHashSummary};
+use hanekawa_common::types::{InfoHashStatus, InfoHashSummary, TorrentMetadata};
 
 use sqlx::postgres::PgPool;
 
@@ -19,13 +22,14 @@ impl InfoHashRepository {
 
 #[async_trait::async_trait]
 impl Repository for InfoHashRepository {
+    #[tracing::instrument(level = "debug", skip_all)]
     async fn get_info_hash_summary(
         &self,
         cmd: GetInfoHashSummary<'_>,
     ) -> Result<InfoHashSummary, Error> {
         let result = sqlx::query!(
             "
-SELECT info_hash, is_allowed
+SELECT info_hash, is_allowed, name, size
 FROM info_hashes
 WHERE info_hash = $1
 ",
@@ -38,17 +42,23 @@ WHERE info_hash = $1
             } else {
                 InfoHashStatus::ExplicitDeny
             },
+            metadata: r.name.zip(r.size).map(|(name, size)| TorrentMetadata {
+                name,
+                size: size as u64,
+            }),
         })
         .fetch_optional(&self.pool)
         .await
-        .unwrap();
+        .map_err(failed)?;
 
         Ok(result.unwrap_or(InfoHashSummary {
             info_hash: cmd.info_hash.clone(),
             status: InfoHashStatus::Unknown,
+            metadata: None,
         }))
     }
 
+    #[tracing::instrument(level = "debug", skip_all)]
     async fn update_info_hash(&self, cmd: UpdateInfoHash<'_>) -> Result<(), Error> {
         if let InfoHashStatus::Unknown = cmd.status {
             sqlx::query!(
@@ -60,7 +70,7 @@ WHERE info_hash = $1
             )
             .execute(&self.pool)
             .await
-            .unwrap();
+            .map_err(failed)?;
         } else {
             let is_allowed = cmd.status == InfoHashStatus::ExplicitAllow;
 
@@ -76,9 +86,29 @@ SET is_allowed = $2
             )
             .execute(&self.pool)
             .await
-            .unwrap();
+            .map_err(failed)?;
         }
 
         Ok(())
     }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn register_torrent(&self, cmd: RegisterTorrent<'_>) -> Result<(), Error> {
+        sqlx::query!(
+            "
+INSERT INTO info_hashes(info_hash, is_allowed, name, size)
+VALUES($1, true, $2, $3)
+ON CONFLICT (info_hash) DO UPDATE
+SET is_allowed = true, name = $2, size = $3
+",
+            &cmd.info_hash.0,
+            cmd.metadata.name,
+            cmd.metadata.size as i64
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(failed)?;
+
+        Ok(())
+    }
 }
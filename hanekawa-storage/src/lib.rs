@@ -1,33 +1,85 @@
-use hanekawa_common::Config;
+use hanekawa_common::{repository::Error, Config};
 
 use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
 use sqlx::ConnectOptions;
+use std::sync::Arc;
 
+pub mod audit;
+pub mod ban;
 pub mod info_hash;
+pub mod passkey;
 pub mod peer;
+#[cfg(feature = "redis")]
+pub mod redis;
+pub mod seal;
+
+// Queries that fail are the caller's to answer, not the tracker's to die of.
+pub(crate) fn failed(e: sqlx::Error) -> Error {
+    log::error!("storage error: {e}");
+    Error::Backend(e.to_string())
+}
+
+// The database at HANEKAWA_TEST_DATABASE_URL, migrated. Tests taking it
+// are skipped without one.
+#[cfg(test)]
+pub(crate) async fn test_pool() -> Option<sqlx::PgPool> {
+    let url = std::env::var("HANEKAWA_TEST_DATABASE_URL").ok()?;
+    let pool = PgPoolOptions::new()
+        .max_connections(2)
+        .connect(&url)
+        .await
+        .unwrap();
+    sqlx::migrate!().run(&pool).await.unwrap();
+
+    Some(pool)
+}
+
+// Not taken by any other run against the same database.
+#[cfg(test)]
+pub(crate) fn unique_info_hash() -> hanekawa_common::types::InfoHash {
+    let nanos = sqlx::types::time::OffsetDateTime::now_utc().unix_timestamp_nanos() as u128;
+    let mut info_hash = nanos.to_be_bytes().to_vec();
+    info_hash.extend(std::process::id().to_be_bytes());
+    hanekawa_common::types::InfoHash(info_hash)
+}
 
 pub struct Services {
     pub peer: peer::PeerRepository,
     pub info_hash: info_hash::InfoHashRepository,
+    pub ban: ban::BanRepository,
+    pub audit: audit::AuditRepository,
+    pub passkey: passkey::PasskeyRepository,
 }
 
 impl Services {
-    pub async fn start(cfg: &Config) -> Self {
+    pub async fn start(cfg: &Config) -> Result<Self, seal::Error> {
+        let sealer = seal::Sealer::new(&cfg.encryption)?
+            .or_else(|| cfg.privacy.pseudonymize.then(seal::Sealer::ephemeral))
+            .map(Arc::new);
         let mut connect_options: PgConnectOptions = cfg.database_url.parse().unwrap();
 
         connect_options.log_statements(log::LevelFilter::Trace);
 
         let pool = PgPoolOptions::new()
-            .max_connections(80)
+            .max_connections(cfg.database_pool_size)
             .connect_with(connect_options)
             .await
             .unwrap();
 
         sqlx::migrate!().run(&pool).await.unwrap();
 
-        let peer = peer::PeerRepository::new(pool.clone(), cfg);
-        let info_hash = info_hash::InfoHashRepository::new(pool);
+        let peer = peer::PeerRepository::new(pool.clone(), cfg, sealer);
+        let info_hash = info_hash::InfoHashRepository::new(pool.clone());
+        let ban = ban::BanRepository::new(pool.clone());
+        let audit = audit::AuditRepository::new(pool.clone());
+        let passkey = passkey::PasskeyRepository::new(pool);
 
-        Self { peer, info_hash }
+        Ok(Self {
+            peer,
+            info_hash,
+            ban,
+            audit,
+            passkey,
+        })
     }
 }
@@ -0,0 +1,81 @@
+use crate::failed;
+use hanekawa_common::repository::{
+    passkey::{AddPasskey, GetPasskeys, PasskeyRepository as Repository},
+    Error,
+};
+use hanekawa_common::types::Passkey;
+
+use sqlx::postgres::PgPool;
+
+#[derive(Clone)]
+pub struct PasskeyRepository {
+    pool: PgPool,
+}
+
+impl PasskeyRepository {
+    pub(super) fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl Repository for PasskeyRepository {
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn add_passkey(&self, cmd: AddPasskey<'_>) -> Result<(), Error> {
+        let mut tx = self.pool.begin().await.map_err(failed)?;
+
+        sqlx::query!(
+            "
+UPDATE passkeys
+SET expires_ts = $2
+WHERE user_id = $1 AND (expires_ts IS NULL OR expires_ts > $2)
+",
+            &cmd.passkey.user_id,
+            cmd.retire_others_at
+        )
+        .execute(&mut tx)
+        .await
+        .map_err(failed)?;
+
+        sqlx::query!(
+            "
+INSERT INTO passkeys(passkey, user_id, created_ts, expires_ts)
+VALUES($1, $2, $3, $4)
+",
+            &cmd.passkey.passkey,
+            &cmd.passkey.user_id,
+            cmd.passkey.created,
+            cmd.passkey.expires
+        )
+        .execute(&mut tx)
+        .await
+        .map_err(failed)?;
+
+        tx.commit().await.map_err(failed)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn get_passkeys(&self, cmd: GetPasskeys) -> Result<Vec<Passkey>, Error> {
+        let passkeys = sqlx::query!(
+            "
+SELECT passkey, user_id, created_ts, expires_ts
+FROM passkeys
+WHERE expires_ts IS NULL OR expires_ts > $1
+",
+            cmd.active_at
+        )
+        .map(|r| Passkey {
+            passkey: r.passkey,
+            user_id: r.user_id,
+            created: r.created_ts,
+            expires: r.expires_ts,
+        })
+        .fetch_all(&self.pool)
+        .await
+        .map_err(failed)?;
+
+        Ok(passkeys)
+    }
+}
@@ -1,27 +1,155 @@
 use hanekawa_common::{
     repository::{
-        peer::{GetPeerStatistics, GetPeers, PeerRepository as Repository, UpdatePeerAnnounce},
+        peer::{
+            CheckPeerKey, CountPeers, EvictStalestPeer, GetPeerStatistics, GetPeers,
+            GetSwarmDetail, IterIdleSwarms, IterSwarms, PageSwarms, PeerCounts,
+            PeerRepository as Repository, PurgePeers, PurgeStalePeers, PurgeSwarm, SetConnectable,
+            SwarmOrder, UpdatePeerAnnounce,
+        },
         Error,
     },
-    types::{InfoHash, Peer, PeerId, PeerStatistics},
+    types::{
+        Event, InfoHash, InfoHashStatus, Peer, PeerId, PeerKey, PeerSource, PeerStatistics,
+        SwarmMember, SwarmSummary, Transport,
+    },
     Config,
 };
 
+use crate::{
+    failed,
+    seal::{Identity, Sealer},
+};
+
 use sqlx::postgres::PgPool;
 use sqlx::types::ipnetwork::IpNetwork;
 use sqlx::types::time::OffsetDateTime;
-use std::collections::HashMap;
+use std::{collections::HashMap, net::SocketAddr, sync::Arc};
 
 #[derive(Clone)]
 pub struct PeerRepository {
     pool: PgPool,
     cfg: Config,
+    sealer: Option<Arc<Sealer>>,
+}
+
+// The columns that say who a peer is, as written.
+struct Stored {
+    peer_id: Vec<u8>,
+    ip: Option<IpNetwork>,
+    other_ip: Option<IpNetwork>,
+    ip_lookup: Option<Vec<u8>>,
+    sealed: Option<Vec<u8>>,
+    key_id: Option<String>,
 }
 
 #[async_trait::async_trait]
 impl Repository for PeerRepository {
+    #[tracing::instrument(level = "debug", skip_all)]
     async fn update_peer_announce(&self, cmd: &UpdatePeerAnnounce) -> Result<(), Error> {
-        let inet: IpNetwork = cmd.ip.clone().into();
+        let identity = Identity {
+            peer_id: cmd.peer_id.0.clone(),
+            ip: cmd.ip,
+            other_ip: cmd.other_endpoint.map(|e| e.ip()),
+        };
+        let stored = self.store(&cmd.info_hash, identity);
+
+        // All or nothing, and one announce of the peer at a time, so that
+        // its transfers are not summed twice from the same counters.
+        let mut tx = self.pool.begin().await.map_err(failed)?;
+        sqlx::query!(
+            "
+SELECT 1 AS locked
+FROM peer_announces
+WHERE info_hash = $1 AND peer_id = $2
+FOR UPDATE
+",
+            &cmd.info_hash.0,
+            &stored.peer_id
+        )
+        .fetch_optional(&mut tx)
+        .await
+        .map_err(failed)?;
+
+        // Counted once per peer, however often it says it completed, and
+        // whether or not it stopped in between.
+        if cmd.event == Event::Completed {
+            sqlx::query!(
+                "
+WITH snatch AS (
+  INSERT INTO torrent_snatches(info_hash, peer_id)
+  VALUES ($1, $2)
+  ON CONFLICT DO NOTHING
+  RETURNING info_hash
+)
+INSERT INTO torrents(info_hash, completed)
+SELECT info_hash, 1 FROM snatch
+ON CONFLICT (info_hash) DO UPDATE
+  SET completed = torrents.completed + 1;
+",
+                &cmd.info_hash.0,
+                &stored.peer_id
+            )
+            .execute(&mut tx)
+            .await
+            .map_err(failed)?;
+        }
+
+        // Counters lower than last time were reset, so all of them is new.
+        if let Some(user_id) = &cmd.user_id {
+            sqlx::query!(
+                "
+INSERT INTO user_transfers(user_id, info_hash, uploaded, downloaded)
+SELECT
+  $1,
+  $2,
+  CASE WHEN p.uploaded <= $4 THEN $4 - p.uploaded ELSE $4 END,
+  CASE WHEN p.downloaded <= $5 THEN $5 - p.downloaded ELSE $5 END
+FROM (SELECT 1) AS announce
+LEFT JOIN peer_announces p ON p.info_hash = $2 AND p.peer_id = $3
+ON CONFLICT (user_id, info_hash) DO UPDATE
+  SET
+    uploaded = user_transfers.uploaded + EXCLUDED.uploaded,
+    downloaded = user_transfers.downloaded + EXCLUDED.downloaded;
+",
+                user_id,
+                &cmd.info_hash.0,
+                &stored.peer_id,
+                cmd.uploaded as i64,
+                cmd.downloaded as i64
+            )
+            .execute(&mut tx)
+            .await
+            .map_err(failed)?;
+        }
+
+        // Gone at once, whether it was here or not, unless it is not the
+        // peer's as far as its key goes.
+        let announce_key = cmd.key.as_ref().map(announce_key);
+        if cmd.event == Event::Stopped {
+            sqlx::query!(
+                "
+DELETE FROM peer_announces
+WHERE
+  info_hash = $1
+  AND peer_id = $2
+  AND (
+    announce_key IS NULL
+    OR announce_key = $3
+    OR (ip IS NOT DISTINCT FROM $4 AND ip_lookup IS NOT DISTINCT FROM $5)
+  )
+",
+                &cmd.info_hash.0,
+                &stored.peer_id,
+                announce_key,
+                stored.ip,
+                stored.ip_lookup
+            )
+            .execute(&mut tx)
+            .await
+            .map_err(failed)?;
+
+            return tx.commit().await.map_err(failed);
+        }
 
         sqlx::query!(
             "
@@ -34,9 +162,21 @@ INSERT INTO peer_announces(
   downloaded,
   remaining,
   event,
-  last_update_ts
+  last_update_ts,
+  other_ip,
+  other_port,
+  transport,
+  ip_lookup,
+  sealed,
+  key_id,
+  completed,
+  user_id,
+  announce_key
+)
+VALUES (
+  $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15,
+  $8 = 'completed', $16, $17
 )
-VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
 ON CONFLICT (info_hash, peer_id) DO UPDATE
   SET
     ip = $3,
@@ -45,32 +185,156 @@ ON CONFLICT (info_hash, peer_id) DO UPDATE
     downloaded = $6,
     remaining = $7,
     event = $8,
-    last_update_ts = $9;
+    last_update_ts = $9,
+    other_ip = $10,
+    other_port = $11,
+    transport = $12,
+    ip_lookup = $13,
+    sealed = $14,
+    key_id = $15,
+    completed = peer_announces.completed OR EXCLUDED.completed,
+    user_id = $16,
+    connectable = CASE
+      WHEN peer_announces.ip IS NOT DISTINCT FROM $3
+        AND peer_announces.ip_lookup IS NOT DISTINCT FROM $13
+        AND peer_announces.port = $4
+      THEN peer_announces.connectable
+    END,
+    announce_key = COALESCE($17, peer_announces.announce_key)
+  WHERE
+    peer_announces.announce_key IS NULL
+    OR peer_announces.announce_key = $17
+    OR (
+      peer_announces.ip IS NOT DISTINCT FROM $3
+      AND peer_announces.ip_lookup IS NOT DISTINCT FROM $13
+    );
 ",
             &cmd.info_hash.0,
-            &cmd.peer_id.0,
-            &inet,
+            &stored.peer_id,
+            stored.ip,
             cmd.port as i32,
             cmd.uploaded as i64,
             cmd.downloaded as i64,
             cmd.left as i64,
             cmd.event.to_string(),
-            OffsetDateTime::now_utc()
+            OffsetDateTime::now_utc(),
+            stored.other_ip,
+            cmd.other_endpoint.map(|e| e.port() as i32),
+            cmd.transport.map(|t| t.as_str()),
+            stored.ip_lookup,
+            stored.sealed,
+            stored.key_id,
+            cmd.user_id,
+            announce_key
+        )
+        .execute(&mut tx)
+        .await
+        .map_err(failed)?;
+
+        tx.commit().await.map_err(failed)
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn check_peer_key(&self, cmd: CheckPeerKey<'_>) -> Result<bool, Error> {
+        let (peer_id, ip, ip_lookup) = match &self.sealer {
+            Some(sealer) => (
+                sealer.lookup(&cmd.peer_id.0),
+                None,
+                Some(sealer.lookup_ip(cmd.ip)),
+            ),
+            None => (cmd.peer_id.0.clone(), Some(cmd.ip), None),
+        };
+
+        let stored = sqlx::query!(
+            "
+SELECT announce_key, ip, ip_lookup
+FROM peer_announces
+WHERE info_hash = $1 AND peer_id = $2
+",
+            &cmd.info_hash.0,
+            &peer_id
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(failed)?;
+
+        Ok(stored.is_none_or(|r| {
+            r.announce_key.is_none()
+                || r.announce_key == cmd.key.map(announce_key)
+                || (r.ip.map(|n| n.ip()) == ip && r.ip_lookup == ip_lookup)
+        }))
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn count_peers(&self, cmd: CountPeers<'_>) -> Result<Option<PeerCounts>, Error> {
+        let (peer_id, ip, ip_lookup): (_, Option<IpNetwork>, _) = match &self.sealer {
+            Some(sealer) => (
+                sealer.lookup(&cmd.peer_id.0),
+                None,
+                cmd.ip.map(|ip| sealer.lookup_ip(ip)),
+            ),
+            None => (cmd.peer_id.0.clone(), cmd.ip.map(IpNetwork::from), None),
+        };
+
+        let counts = sqlx::query!(
+            "
+SELECT
+  COUNT(*) FILTER (WHERE info_hash = $1 AND peer_id = $2) AS known,
+  COUNT(*) FILTER (WHERE info_hash = $1) AS in_swarm,
+  COUNT(*) FILTER (WHERE ip = $3 OR ip_lookup = $4) AS from_ip
+FROM peer_announces
+WHERE
+  (info_hash = $1 OR ip = $3 OR ip_lookup = $4)
+  AND last_update_ts > $5
+",
+            &cmd.info_hash.0,
+            &peer_id,
+            ip,
+            ip_lookup,
+            cmd.active_after
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(failed)?;
+
+        Ok(Some(PeerCounts {
+            known: counts.known.unwrap_or(0) > 0,
+            in_swarm: counts.in_swarm.unwrap_or(0) as u32,
+            from_ip: counts.from_ip.unwrap_or(0) as u32,
+        }))
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn evict_stalest_peer(&self, cmd: EvictStalestPeer<'_>) -> Result<(), Error> {
+        sqlx::query!(
+            "
+DELETE FROM peer_announces
+WHERE (info_hash, peer_id) IN (
+  SELECT info_hash, peer_id
+  FROM peer_announces
+  WHERE info_hash = $1 AND last_update_ts > $2
+  ORDER BY last_update_ts
+  LIMIT 1
+)
+",
+            &cmd.info_hash.0,
+            cmd.active_after
         )
         .execute(&self.pool)
         .await
-        .unwrap();
+        .map_err(failed)?;
 
         Ok(())
     }
 
+    #[tracing::instrument(level = "debug", skip_all)]
     async fn get_peers(&self, cmd: GetPeers<'_>) -> Result<Vec<Peer>, Error> {
         let active_peer_window_start = OffsetDateTime::now_utc()
-            - std::time::Duration::from_secs(self.cfg.peer_activity_timeout as u64);
+            - std::time::Duration::from_secs(self.cfg.activity_timeout() as u64);
 
         let peers = sqlx::query!(
             "
-SELECT peer_id, ip, port
+SELECT peer_id, ip, port, other_ip, other_port, connectable, remaining, sealed, key_id
 FROM peer_announces
 WHERE
   info_hash = $1
@@ -79,34 +343,71 @@ WHERE
             &cmd.info_hash.0,
             active_peer_window_start
         )
-        .map(|r| Peer {
-            peer_id: PeerId(r.peer_id),
-            ip: r.ip.ip(),
-            port: r.port as u16,
-        })
         .fetch_all(&self.pool)
         .await
-        .unwrap();
+        .map_err(failed)?;
+
+        // Peers advertising an endpoint in the other family appear once per
+        // family.
+        Ok(peers
+            .into_iter()
+            .filter_map(|r| {
+                let identity = self.identity(
+                    cmd.info_hash,
+                    r.peer_id,
+                    r.ip,
+                    r.other_ip,
+                    r.sealed,
+                    r.key_id,
+                )?;
+                Some((identity, r.port, r.other_port, r.connectable, r.remaining))
+            })
+            .flat_map(|(identity, port, other_port, connectable, remaining)| {
+                let peer = Peer {
+                    peer_id: PeerId(identity.peer_id),
+                    ip: identity.ip,
+                    port: port as u16,
+                    connectable,
+                    seeding: remaining == 0,
+                    source: PeerSource::Announce,
+                };
+                // Only the announcing endpoint is probed.
+                let other = identity.other_ip.zip(other_port).map(|(ip, port)| Peer {
+                    peer_id: peer.peer_id.clone(),
+                    ip,
+                    port: port as u16,
+                    connectable: None,
+                    seeding: peer.seeding,
+                    source: PeerSource::Announce,
+                });
 
-        Ok(peers)
+                std::iter::once(peer).chain(other)
+            })
+            .collect())
     }
 
+    #[tracing::instrument(level = "debug", skip_all)]
     async fn get_peer_statistics(
         &self,
         cmd: GetPeerStatistics<'_>,
     ) -> Result<HashMap<InfoHash, PeerStatistics>, Error> {
         let ih_bs: Vec<Vec<u8>> = cmd.info_hashes.iter().cloned().map(|ih| ih.0).collect();
 
+        // From the hashes asked for, so that torrents with no peers left still
+        // have their completed count.
         let result = sqlx::query!(
             "
 SELECT
-  info_hash,
-  COUNT(*) FILTER (WHERE remaining =  0 AND last_update_ts > $2) AS complete,
-  COUNT(*) FILTER (WHERE remaining <> 0 AND last_update_ts > $2) AS incomplete
+  r.info_hash AS \"info_hash!\",
+  COUNT(p.info_hash) FILTER (WHERE p.remaining =  0 AND p.last_update_ts > $2) AS complete,
+  MAX(t.completed) AS \"downloaded?\",
+  COUNT(p.info_hash) FILTER (WHERE p.remaining <> 0 AND p.last_update_ts > $2) AS incomplete
 FROM
-  peer_announces
-WHERE info_hash = ANY($1)
-GROUP BY info_hash
+  (SELECT DISTINCT UNNEST($1::bytea[]) AS info_hash) r
+LEFT JOIN torrents t ON t.info_hash = r.info_hash
+LEFT JOIN peer_announces p ON p.info_hash = r.info_hash
+GROUP BY r.info_hash
+HAVING COUNT(p.info_hash) > 0 OR MAX(t.completed) IS NOT NULL
 ",
             &ih_bs,
             &cmd.active_after
@@ -116,22 +417,717 @@ GROUP BY info_hash
                 InfoHash(r.info_hash),
                 PeerStatistics {
                     complete: r.complete.unwrap_or(0) as u32,
-                    downloaded: r.complete.unwrap_or(0) as u32,
+                    downloaded: r.downloaded.unwrap_or(0) as u32,
                     incomplete: r.incomplete.unwrap_or(0) as u32,
                 },
             )
         })
         .fetch_all(&self.pool)
         .await
-        .unwrap();
+        .map_err(failed)?;
 
         Ok(result.into_iter().collect())
     }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn iter_swarms(&self, cmd: IterSwarms) -> Result<Vec<SwarmSummary>, Error> {
+        let result = sqlx::query!(
+            "
+SELECT
+  p.info_hash,
+  COUNT(*) FILTER (WHERE remaining =  0) AS complete,
+  MAX(t.completed) AS downloaded,
+  COUNT(*) FILTER (WHERE remaining <> 0) AS incomplete,
+  MAX(last_update_ts) AS last_activity
+FROM
+  peer_announces p
+LEFT JOIN torrents t ON t.info_hash = p.info_hash
+WHERE last_update_ts > $1
+GROUP BY p.info_hash
+",
+            &cmd.active_after
+        )
+        .map(|r| SwarmSummary {
+            info_hash: InfoHash(r.info_hash),
+            statistics: PeerStatistics {
+                complete: r.complete.unwrap_or(0) as u32,
+                downloaded: r.downloaded.unwrap_or(0) as u32,
+                incomplete: r.incomplete.unwrap_or(0) as u32,
+            },
+            last_activity: r.last_activity.unwrap_or(cmd.active_after),
+        })
+        .fetch_all(&self.pool)
+        .await
+        .map_err(failed)?;
+
+        Ok(result)
+    }
+
+    // Filtered, ordered and paged by the database, status too.
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn page_swarms(&self, cmd: PageSwarms<'_>) -> Result<Vec<SwarmSummary>, Error> {
+        let min_peers = cmd.filter.min_peers.map(i64::from);
+        let status = cmd.filter.status.as_ref().map(|status| match status {
+            InfoHashStatus::Unknown => "unknown",
+            InfoHashStatus::ExplicitAllow => "allowed",
+            InfoHashStatus::ExplicitDeny => "denied",
+        });
+        let limit = cmd.limit as i64;
+
+        let swarm = |info_hash: Vec<u8>,
+                     complete: Option<i64>,
+                     downloaded: Option<i64>,
+                     incomplete: Option<i64>,
+                     last_activity: Option<OffsetDateTime>| SwarmSummary {
+            info_hash: InfoHash(info_hash),
+            statistics: PeerStatistics {
+                complete: complete.unwrap_or(0) as u32,
+                downloaded: downloaded.unwrap_or(0) as u32,
+                incomplete: incomplete.unwrap_or(0) as u32,
+            },
+            last_activity: last_activity.unwrap_or(cmd.active_after),
+        };
+
+        // Keyset pagination, so later pages cost no more than the first.
+        let result = match cmd.order {
+            SwarmOrder::Peers => sqlx::query!(
+                "
+SELECT
+  s.info_hash,
+  s.complete,
+  t.completed AS \"downloaded?\",
+  s.incomplete,
+  s.last_activity
+FROM (
+  SELECT
+    info_hash,
+    COUNT(*) FILTER (WHERE remaining =  0) AS complete,
+    COUNT(*) FILTER (WHERE remaining <> 0) AS incomplete,
+    COUNT(*) AS peers,
+    MAX(last_update_ts) AS last_activity
+  FROM
+    peer_announces
+  WHERE
+    last_update_ts > $1
+    AND ($2::text IS NULL OR encode(info_hash, 'hex') LIKE $2 || '%')
+  GROUP BY info_hash
+) s
+LEFT JOIN info_hashes i ON i.info_hash = s.info_hash
+LEFT JOIN torrents t ON t.info_hash = s.info_hash
+WHERE
+  ($3::bigint IS NULL OR s.peers >= $3)
+  AND ($4::timestamptz IS NULL OR s.last_activity >= $4)
+  AND ($5::text IS NULL OR $5 = CASE
+    WHEN i.is_allowed IS NULL THEN 'unknown'
+    WHEN i.is_allowed THEN 'allowed'
+    ELSE 'denied'
+  END)
+  AND ($6::bigint IS NULL
+    OR s.peers < $6
+    OR (s.peers = $6 AND s.last_activity < $7)
+    OR (s.peers = $6 AND s.last_activity = $7 AND s.info_hash > $8))
+ORDER BY s.peers DESC, s.last_activity DESC, s.info_hash
+LIMIT $9
+",
+                &cmd.active_after,
+                cmd.filter.info_hash_prefix.as_deref(),
+                min_peers,
+                cmd.filter.active_since,
+                status,
+                cmd.after.map(|after| after.peers as i64),
+                cmd.after.map(|after| after.last_activity),
+                cmd.after.map(|after| &after.info_hash.0[..]),
+                limit
+            )
+            .map(|r| {
+                swarm(
+                    r.info_hash,
+                    r.complete,
+                    r.downloaded,
+                    r.incomplete,
+                    r.last_activity,
+                )
+            })
+            .fetch_all(&self.pool)
+            .await
+            .map_err(failed)?,
+            SwarmOrder::Activity => sqlx::query!(
+                "
+SELECT
+  s.info_hash,
+  s.complete,
+  t.completed AS \"downloaded?\",
+  s.incomplete,
+  s.last_activity
+FROM (
+  SELECT
+    info_hash,
+    COUNT(*) FILTER (WHERE remaining =  0) AS complete,
+    COUNT(*) FILTER (WHERE remaining <> 0) AS incomplete,
+    COUNT(*) AS peers,
+    MAX(last_update_ts) AS last_activity
+  FROM
+    peer_announces
+  WHERE
+    last_update_ts > $1
+    AND ($2::text IS NULL OR encode(info_hash, 'hex') LIKE $2 || '%')
+  GROUP BY info_hash
+) s
+LEFT JOIN info_hashes i ON i.info_hash = s.info_hash
+LEFT JOIN torrents t ON t.info_hash = s.info_hash
+WHERE
+  ($3::bigint IS NULL OR s.peers >= $3)
+  AND ($4::timestamptz IS NULL OR s.last_activity >= $4)
+  AND ($5::text IS NULL OR $5 = CASE
+    WHEN i.is_allowed IS NULL THEN 'unknown'
+    WHEN i.is_allowed THEN 'allowed'
+    ELSE 'denied'
+  END)
+  AND ($6::timestamptz IS NULL
+    OR s.last_activity < $6
+    OR (s.last_activity = $6 AND s.info_hash > $7))
+ORDER BY s.last_activity DESC, s.info_hash
+LIMIT $8
+",
+                &cmd.active_after,
+                cmd.filter.info_hash_prefix.as_deref(),
+                min_peers,
+                cmd.filter.active_since,
+                status,
+                cmd.after.map(|after| after.last_activity),
+                cmd.after.map(|after| &after.info_hash.0[..]),
+                limit
+            )
+            .map(|r| {
+                swarm(
+                    r.info_hash,
+                    r.complete,
+                    r.downloaded,
+                    r.incomplete,
+                    r.last_activity,
+                )
+            })
+            .fetch_all(&self.pool)
+            .await
+            .map_err(failed)?,
+        };
+
+        Ok(result)
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn get_swarm_detail(&self, cmd: GetSwarmDetail<'_>) -> Result<Vec<SwarmMember>, Error> {
+        let result = sqlx::query!(
+            "
+SELECT
+  peer_id,
+  ip,
+  port,
+  other_ip,
+  other_port,
+  uploaded,
+  downloaded,
+  remaining,
+  event,
+  last_update_ts,
+  transport,
+  connectable,
+  sealed,
+  key_id
+FROM peer_announces
+WHERE
+  info_hash = $1
+  AND last_update_ts > $2
+",
+            &cmd.info_hash.0,
+            &cmd.active_after
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(failed)?;
+
+        Ok(result
+            .into_iter()
+            .filter_map(|r| {
+                let identity = self.identity(
+                    cmd.info_hash,
+                    r.peer_id,
+                    r.ip,
+                    r.other_ip,
+                    r.sealed,
+                    r.key_id,
+                )?;
+                Some(SwarmMember {
+                    peer_id: PeerId(identity.peer_id),
+                    ip: identity.ip,
+                    port: r.port as u16,
+                    other_endpoint: identity
+                        .other_ip
+                        .zip(r.other_port)
+                        .map(|(ip, port)| SocketAddr::new(ip, port as u16)),
+                    uploaded: r.uploaded as u64,
+                    downloaded: r.downloaded as u64,
+                    left: r.remaining as u64,
+                    event: parse_event(r.event.as_deref()),
+                    last_announce: r.last_update_ts.unwrap_or(cmd.active_after),
+                    transport: match r.transport.as_deref() {
+                        Some("http") => Some(Transport::Http),
+                        Some("udp") => Some(Transport::Udp),
+                        _ => None,
+                    },
+                    connectable: r.connectable,
+                })
+            })
+            .collect())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn iter_idle_swarms(
+        &self,
+        cmd: IterIdleSwarms,
+    ) -> Result<HashMap<InfoHash, OffsetDateTime>, Error> {
+        let result = sqlx::query!(
+            "
+SELECT
+  info_hash,
+  MAX(last_update_ts) AS last_activity
+FROM
+  peer_announces
+GROUP BY info_hash
+HAVING MAX(last_update_ts) <= $1
+",
+            &cmd.idle_since
+        )
+        .map(|r| {
+            (
+                InfoHash(r.info_hash),
+                r.last_activity.unwrap_or(cmd.idle_since),
+            )
+        })
+        .fetch_all(&self.pool)
+        .await
+        .map_err(failed)?;
+
+        Ok(result.into_iter().collect())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn purge_swarm(&self, cmd: PurgeSwarm<'_>) -> Result<(), Error> {
+        sqlx::query!(
+            "
+DELETE FROM peer_announces
+WHERE info_hash = $1
+",
+            &cmd.info_hash.0
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(failed)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn purge_peers(&self, cmd: PurgePeers) -> Result<u64, Error> {
+        let result = sqlx::query!(
+            "
+DELETE FROM peer_announces
+WHERE last_update_ts < $1
+",
+            cmd.before
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(failed)?;
+
+        Ok(result.rows_affected())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn purge_stale_peers(&self, cmd: PurgeStalePeers) -> Result<u64, Error> {
+        let result = sqlx::query!(
+            "
+DELETE FROM peer_announces p
+WHERE
+  last_update_ts < $2
+  OR (
+    last_update_ts < $1
+    AND EXISTS (
+      SELECT 1
+      FROM peer_announces q
+      WHERE q.info_hash = p.info_hash AND q.last_update_ts >= $1
+    )
+  )
+",
+            cmd.inactive_since,
+            cmd.cutoff
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(failed)?;
+
+        Ok(result.rows_affected())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn set_connectable(&self, cmd: SetConnectable) -> Result<(), Error> {
+        let inet: IpNetwork = cmd.endpoint.ip().into();
+        let lookup = self
+            .sealer
+            .as_ref()
+            .map(|sealer| sealer.lookup_ip(cmd.endpoint.ip()));
+
+        sqlx::query!(
+            "
+UPDATE peer_announces
+SET connectable = $3
+WHERE (ip = $1 OR ip_lookup = $4) AND port = $2
+",
+            &inet,
+            cmd.endpoint.port() as i32,
+            cmd.connectable,
+            lookup
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(failed)?;
+
+        Ok(())
+    }
+}
+
+// Only a digest is kept, so that keys cannot be read back.
+fn announce_key(key: &PeerKey) -> Vec<u8> {
+    ring::digest::digest(&ring::digest::SHA256, key.0.as_bytes())
+        .as_ref()
+        .to_vec()
+}
+
+// As written by `Event::to_string`.
+fn parse_event(event: Option<&str>) -> Event {
+    match event {
+        Some("started") => Event::Started,
+        Some("completed") => Event::Completed,
+        Some("stopped") => Event::Stopped,
+        _ => Event::Interval,
+    }
 }
 
 impl PeerRepository {
-    pub(super) fn new(pool: PgPool, cfg: &Config) -> Self {
+    pub(super) fn new(pool: PgPool, cfg: &Config, sealer: Option<Arc<Sealer>>) -> Self {
         let cfg = cfg.clone();
-        Self { pool, cfg }
+        Self { pool, cfg, sealer }
+    }
+
+    // Sealed to the row's info hash if encrypting, with the peer id replaced
+    // by its lookup hash so announces still land on the same row.
+    fn store(&self, info_hash: &InfoHash, identity: Identity) -> Stored {
+        match &self.sealer {
+            Some(sealer) => Stored {
+                peer_id: sealer.lookup(&identity.peer_id),
+                ip: None,
+                other_ip: None,
+                ip_lookup: Some(sealer.lookup_ip(identity.ip)),
+                sealed: Some(sealer.seal(&info_hash.0, &identity)),
+                key_id: Some(sealer.key_id().to_string()),
+            },
+            None => Stored {
+                peer_id: identity.peer_id,
+                ip: Some(identity.ip.into()),
+                other_ip: identity.other_ip.map(IpNetwork::from),
+                ip_lookup: None,
+                sealed: None,
+                key_id: None,
+            },
+        }
+    }
+
+    // None for rows that cannot be opened, under a key that is gone, which
+    // are left out rather than failing the whole swarm.
+    fn identity(
+        &self,
+        info_hash: &InfoHash,
+        peer_id: Vec<u8>,
+        ip: Option<IpNetwork>,
+        other_ip: Option<IpNetwork>,
+        sealed: Option<Vec<u8>>,
+        key_id: Option<String>,
+    ) -> Option<Identity> {
+        let Some(sealed) = sealed else {
+            return Some(Identity {
+                peer_id,
+                ip: ip?.ip(),
+                other_ip: other_ip.map(|ip| ip.ip()),
+            });
+        };
+
+        let opened = self.sealer.as_ref().and_then(|sealer| {
+            sealer.open(key_id.as_deref().unwrap_or_default(), &info_hash.0, &sealed)
+        });
+        if opened.is_none() {
+            log::warn!("cannot decrypt a peer under key {key_id:?}");
+        }
+        opened
+    }
+
+    // Moves every row to the first key, `batch` rows at a time, sealing those
+    // written before encryption was enabled too. Returns how many were.
+    pub async fn rekey(&self, batch: usize) -> u64 {
+        let Some(sealer) = &self.sealer else {
+            return 0;
+        };
+
+        let mut rekeyed = 0;
+        let mut after: Option<(Vec<u8>, Vec<u8>)> = None;
+        loop {
+            let rows = sqlx::query!(
+                "
+SELECT info_hash, peer_id, ip, other_ip, sealed, key_id
+FROM peer_announces
+WHERE
+  key_id IS DISTINCT FROM $1
+  AND ($2::bytea IS NULL OR (info_hash, peer_id) > ($2, $3))
+ORDER BY info_hash, peer_id
+LIMIT $4
+",
+                sealer.key_id(),
+                after.as_ref().map(|(info_hash, _)| &info_hash[..]),
+                after.as_ref().map(|(_, peer_id)| &peer_id[..]),
+                batch as i64
+            )
+            .fetch_all(&self.pool)
+            .await
+            .unwrap();
+            let Some(last) = rows.last() else {
+                break;
+            };
+            after = Some((last.info_hash.clone(), last.peer_id.clone()));
+
+            for r in rows {
+                let info_hash = InfoHash(r.info_hash);
+                let Some(identity) = self.identity(
+                    &info_hash,
+                    r.peer_id.clone(),
+                    r.ip,
+                    r.other_ip,
+                    r.sealed,
+                    r.key_id,
+                ) else {
+                    continue;
+                };
+                let stored = self.store(&info_hash, identity);
+
+                // A peer that announced since encryption was enabled has a
+                // sealed row already, and its cleartext one is left to expire.
+                let result = sqlx::query!(
+                    "
+UPDATE peer_announces
+SET
+  peer_id = $3,
+  ip = NULL,
+  other_ip = NULL,
+  ip_lookup = $4,
+  sealed = $5,
+  key_id = $6
+WHERE
+  info_hash = $1
+  AND peer_id = $2
+  AND ($2 = $3 OR NOT EXISTS (
+    SELECT 1 FROM peer_announces p WHERE p.info_hash = $1 AND p.peer_id = $3
+  ))
+",
+                    &info_hash.0,
+                    &r.peer_id,
+                    &stored.peer_id,
+                    stored.ip_lookup,
+                    stored.sealed,
+                    stored.key_id
+                )
+                .execute(&self.pool)
+                .await
+                .unwrap();
+                rekeyed += result.rows_affected();
+            }
+            log::info!("rekeyed {rekeyed} peers");
+        }
+
+        rekeyed
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::{test_pool, unique_info_hash};
+
+    fn announce(info_hash: &InfoHash, peer: u8, left: u64, event: Event) -> UpdatePeerAnnounce {
+        UpdatePeerAnnounce {
+            info_hash: info_hash.clone(),
+            peer_id: PeerId(vec![peer; 20]),
+            ip: std::net::Ipv4Addr::new(10, 0, 0, peer).into(),
+            port: 6881,
+            uploaded: 0,
+            downloaded: 0,
+            left,
+            event,
+            update_timestamp: OffsetDateTime::now_utc(),
+            other_endpoint: None,
+            transport: None,
+            user_id: None,
+            key: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn scrapes_torrents_whose_peers_are_gone() {
+        let Some(pool) = test_pool().await else {
+            return;
+        };
+        let repository = PeerRepository::new(pool, &Config::default(), None);
+        let (gone, leeched, unknown) = (unique_info_hash(), unique_info_hash(), unique_info_hash());
+        for cmd in [
+            announce(&gone, 1, 0, Event::Completed),
+            announce(&gone, 1, 0, Event::Stopped),
+            announce(&leeched, 2, 100, Event::Started),
+        ] {
+            repository.update_peer_announce(&cmd).await.unwrap();
+        }
+
+        let scraped = repository
+            .get_peer_statistics(GetPeerStatistics {
+                info_hashes: &[gone.clone(), gone.clone(), leeched.clone(), unknown.clone()],
+                active_after: OffsetDateTime::now_utc() - std::time::Duration::from_secs(60),
+            })
+            .await
+            .unwrap();
+
+        let counts = |info_hash| {
+            let stats: &PeerStatistics = &scraped[info_hash];
+            (stats.complete, stats.downloaded, stats.incomplete)
+        };
+        assert_eq!((0, 1, 0), counts(&gone));
+        assert_eq!((0, 0, 1), counts(&leeched));
+        assert!(!scraped.contains_key(&unknown));
+    }
+
+    // Stale peers of a swarm still announcing go, and those of an idle one
+    // only once past the cutoff.
+    #[tokio::test]
+    async fn purges_stale_peers() {
+        let Some(pool) = test_pool().await else {
+            return;
+        };
+        let repository = PeerRepository::new(pool.clone(), &Config::default(), None);
+        let (announcing, idle) = (unique_info_hash(), unique_info_hash());
+        let now = OffsetDateTime::now_utc();
+        let minutes = |n: u64| now - std::time::Duration::from_secs(n * 60);
+        for (info_hash, peer, at) in [
+            (&announcing, 1, minutes(10)),
+            (&announcing, 2, now),
+            (&idle, 3, minutes(10)),
+            (&idle, 4, minutes(120)),
+        ] {
+            let cmd = announce(info_hash, peer, 100, Event::Started);
+            repository.update_peer_announce(&cmd).await.unwrap();
+            // Announces are stamped as they are written.
+            sqlx::query!(
+                "UPDATE peer_announces SET last_update_ts = $1 WHERE info_hash = $2 AND peer_id = $3",
+                at,
+                &info_hash.0,
+                &cmd.peer_id.0
+            )
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+
+        repository
+            .purge_stale_peers(PurgeStalePeers {
+                inactive_since: minutes(1),
+                cutoff: minutes(60),
+            })
+            .await
+            .unwrap();
+
+        for (info_hash, left) in [(&announcing, 2), (&idle, 3)] {
+            let cmd = GetPeers {
+                info_hash,
+                active_after: None,
+            };
+            let peers = repository.get_peers(cmd).await.unwrap();
+            let peer_ids: Vec<_> = peers.into_iter().map(|p| p.peer_id.0[0]).collect();
+            assert_eq!(vec![left], peer_ids);
+        }
+    }
+
+    #[tokio::test]
+    async fn counts_each_peer_that_completed_once() {
+        let Some(pool) = test_pool().await else {
+            return;
+        };
+        let repository = PeerRepository::new(pool, &Config::default(), None);
+        let info_hash = unique_info_hash();
+        for cmd in [
+            announce(&info_hash, 1, 0, Event::Completed),
+            announce(&info_hash, 1, 0, Event::Stopped),
+            announce(&info_hash, 1, 0, Event::Completed),
+        ] {
+            repository.update_peer_announce(&cmd).await.unwrap();
+        }
+        // Both at once.
+        let cmd = announce(&info_hash, 2, 0, Event::Completed);
+        let (a, b) = tokio::join!(
+            repository.update_peer_announce(&cmd),
+            repository.update_peer_announce(&cmd)
+        );
+        a.unwrap();
+        b.unwrap();
+
+        let scraped = repository
+            .get_peer_statistics(GetPeerStatistics {
+                info_hashes: std::slice::from_ref(&info_hash),
+                active_after: OffsetDateTime::now_utc() - std::time::Duration::from_secs(60),
+            })
+            .await
+            .unwrap();
+        assert_eq!(2, scraped[&info_hash].downloaded);
+    }
+
+    // Without any keys configured, as the ones it makes up will do.
+    #[tokio::test]
+    async fn writes_no_addresses_or_peer_ids_when_pseudonymizing() {
+        let Some(pool) = test_pool().await else {
+            return;
+        };
+        let sealer = Arc::new(Sealer::ephemeral());
+        let repository = PeerRepository::new(pool.clone(), &Config::default(), Some(sealer));
+        let info_hash = unique_info_hash();
+        let cmd = announce(&info_hash, 1, 0, Event::Started);
+        repository.update_peer_announce(&cmd).await.unwrap();
+
+        let row = sqlx::query!(
+            "SELECT peer_id, ip, sealed FROM peer_announces WHERE info_hash = $1",
+            &info_hash.0
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        let contains =
+            |haystack: &[u8], needle: &[u8]| haystack.windows(needle.len()).any(|w| w == needle);
+        assert_ne!(cmd.peer_id.0, row.peer_id);
+        assert_eq!(None, row.ip);
+        assert!(!contains(&row.sealed.clone().unwrap(), &cmd.peer_id.0));
+        assert!(!contains(&row.sealed.unwrap(), &[10, 0, 0, 1]));
+
+        let peers = repository
+            .get_peers(GetPeers {
+                info_hash: &info_hash,
+                active_after: None,
+            })
+            .await
+            .unwrap();
+        let peers: Vec<_> = peers.into_iter().map(|p| (p.peer_id, p.ip)).collect();
+        assert_eq!(vec![(cmd.peer_id, cmd.ip)], peers);
     }
 }
@@ -0,0 +1,768 @@
+// Swarms and info hashes in Redis, shared by every tracker pointed at it.
+// Each swarm is a hash of its peers, with sorted sets of when each peer and
+// each seeder last announced. Its keys expire once nothing announces to it
+// for the activity timeout and the grace period. Completed counts are kept
+// apart, and do not expire.
+
+use hanekawa_common::{
+    repository::{
+        info_hash::{GetInfoHashSummary, InfoHashRepository, RegisterTorrent, UpdateInfoHash},
+        peer::{
+            CheckPeerKey, CountPeers, EvictStalestPeer, GetPeerStatistics, GetPeers,
+            GetSwarmDetail, IterIdleSwarms, IterSwarms, PeerCounts, PeerRepository,
+            PurgeStalePeers, PurgeSwarm, SetConnectable, UpdatePeerAnnounce,
+        },
+        Error,
+    },
+    types::{
+        Event, InfoHash, InfoHashStatus, InfoHashSummary, Peer, PeerId, PeerSource, PeerStatistics,
+        SwarmMember, SwarmSummary, TorrentMetadata, Transport,
+    },
+    Config,
+};
+
+use redis::{aio::ConnectionManager, AsyncCommands, Script};
+use sqlx::types::time::OffsetDateTime;
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+};
+
+// When every swarm last had an announce, stopped ones too.
+const SWARMS: &str = "hanekawa:swarms";
+// Each torrent's completed count.
+const DOWNLOADED: &str = "hanekawa:downloaded";
+
+// Records the announce unless another peer's key is on it. Stopped peers
+// are forgotten, but not that they completed.
+const ANNOUNCE: &str = r"
+local peers, seen, seeders, completed, keys, downloaded, swarms, ip_index =
+  KEYS[1], KEYS[2], KEYS[3], KEYS[4], KEYS[5], KEYS[6], KEYS[7], KEYS[8]
+local peer_id, record, at, ttl, event, info_hash, key, ip, seeding =
+  ARGV[1], ARGV[2], ARGV[3], ARGV[4], ARGV[5], ARGV[6], ARGV[7], ARGV[8], ARGV[9]
+local member = info_hash .. ':' .. peer_id
+
+local old = redis.call('HGET', peers, peer_id)
+local old_ip = old and cjson.decode(old).ip
+local stored_key = redis.call('HGET', keys, peer_id)
+if stored_key and stored_key ~= key and old_ip ~= ip then
+  return 0
+end
+if old_ip and old_ip ~= ip then
+  redis.call('ZREM', 'hanekawa:ip:' .. old_ip, member)
+end
+
+redis.call('ZADD', swarms, at, info_hash)
+if event == 'stopped' then
+  redis.call('HDEL', peers, peer_id)
+  redis.call('HDEL', keys, peer_id)
+  redis.call('ZREM', seen, peer_id)
+  redis.call('ZREM', seeders, peer_id)
+  redis.call('ZREM', ip_index, member)
+  return 1
+end
+if event == 'completed' and redis.call('SADD', completed, peer_id) == 1 then
+  redis.call('HINCRBY', downloaded, info_hash, 1)
+end
+
+redis.call('HSET', peers, peer_id, record)
+if key ~= '' then
+  redis.call('HSET', keys, peer_id, key)
+end
+redis.call('ZADD', seen, at, peer_id)
+if seeding == '1' then
+  redis.call('ZADD', seeders, at, peer_id)
+else
+  redis.call('ZREM', seeders, peer_id)
+end
+redis.call('ZADD', ip_index, at, member)
+for _, k in ipairs({peers, seen, seeders, completed, keys, ip_index}) do
+  redis.call('EXPIRE', k, ttl)
+end
+return 1
+";
+
+// A peer as of its latest announce.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Record {
+    ip: IpAddr,
+    port: u16,
+    other_endpoint: Option<SocketAddr>,
+    uploaded: u64,
+    downloaded: u64,
+    left: u64,
+    event: Event,
+    transport: Option<Transport>,
+    // In microseconds since the epoch, as scored in the sorted sets.
+    announced: i64,
+    user_id: Option<String>,
+}
+
+impl Record {
+    fn endpoints(&self) -> impl Iterator<Item = SocketAddr> {
+        std::iter::once(SocketAddr::new(self.ip, self.port)).chain(self.other_endpoint)
+    }
+}
+
+fn failed(e: redis::RedisError) -> Error {
+    log::error!("storage error: {e}");
+    Error::Backend(e.to_string())
+}
+
+fn score(at: OffsetDateTime) -> i64 {
+    (at.unix_timestamp_nanos() / 1000) as i64
+}
+
+fn at(score: i64) -> OffsetDateTime {
+    OffsetDateTime::from_unix_timestamp_nanos(score as i128 * 1000)
+        .unwrap_or(OffsetDateTime::UNIX_EPOCH)
+}
+
+// Scores after `at`, not including it.
+fn after(at: OffsetDateTime) -> String {
+    format!("({}", score(at))
+}
+
+fn swarm_key(info_hash: &str, part: &str) -> String {
+    format!("hanekawa:swarm:{info_hash}:{part}")
+}
+
+fn ip_key(ip: IpAddr) -> String {
+    format!("hanekawa:ip:{ip}")
+}
+
+#[derive(Clone)]
+pub struct RedisRepository {
+    conn: ConnectionManager,
+    // How long a swarm outlives its last announce, in seconds.
+    ttl: u64,
+    announce: Script,
+}
+
+impl RedisRepository {
+    pub async fn connect(cfg: &Config) -> Result<Self, Error> {
+        let ttl = cfg.activity_timeout() as u64 + cfg.empty_swarm_grace_period as u64;
+        Self::open(&cfg.database_url, ttl).await
+    }
+
+    async fn open(url: &str, ttl: u64) -> Result<Self, Error> {
+        let client = redis::Client::open(url).map_err(failed)?;
+        let conn = client.get_connection_manager().await.map_err(failed)?;
+
+        Ok(Self {
+            conn,
+            ttl,
+            announce: Script::new(ANNOUNCE),
+        })
+    }
+
+    async fn records(&self, info_hash: &str) -> Result<Vec<(PeerId, Record)>, Error> {
+        let records: HashMap<String, String> = self
+            .conn
+            .clone()
+            .hgetall(swarm_key(info_hash, "peers"))
+            .await
+            .map_err(failed)?;
+
+        Ok(records
+            .into_iter()
+            .filter_map(|(peer_id, record)| {
+                let peer_id = PeerId(hex::decode(peer_id).ok()?);
+                Some((peer_id, serde_json::from_str(&record).ok()?))
+            })
+            .collect())
+    }
+
+    // Whether each endpoint answered the prober, as far as it is known.
+    async fn connectable(&self, info_hash: &str) -> Result<HashMap<String, bool>, Error> {
+        self.conn
+            .clone()
+            .hgetall(swarm_key(info_hash, "connectable"))
+            .await
+            .map_err(failed)
+    }
+
+    async fn forget(&self, info_hash: &str, peer_ids: &[String]) -> Result<(), Error> {
+        if peer_ids.is_empty() {
+            return Ok(());
+        }
+
+        let mut conn = self.conn.clone();
+        let records: Vec<Option<String>> = redis::cmd("HMGET")
+            .arg(swarm_key(info_hash, "peers"))
+            .arg(peer_ids)
+            .query_async(&mut conn)
+            .await
+            .map_err(failed)?;
+
+        let mut pipe = redis::pipe();
+        pipe.atomic()
+            .hdel(swarm_key(info_hash, "peers"), peer_ids)
+            .hdel(swarm_key(info_hash, "keys"), peer_ids)
+            .zrem(swarm_key(info_hash, "seen"), peer_ids)
+            .zrem(swarm_key(info_hash, "seeders"), peer_ids);
+        for (peer_id, record) in peer_ids.iter().zip(records) {
+            let Some(record) = record.and_then(|r| serde_json::from_str::<Record>(&r).ok()) else {
+                continue;
+            };
+            pipe.zrem(ip_key(record.ip), format!("{info_hash}:{peer_id}"));
+        }
+        pipe.query_async::<()>(&mut conn).await.map_err(failed)
+    }
+
+    async fn peers_before(&self, info_hash: &str, before: OffsetDateTime) -> Result<u64, Error> {
+        let stale: Vec<String> = self
+            .conn
+            .clone()
+            .zrangebyscore(
+                swarm_key(info_hash, "seen"),
+                "-inf",
+                format!("({}", score(before)),
+            )
+            .await
+            .map_err(failed)?;
+        self.forget(info_hash, &stale).await?;
+
+        Ok(stale.len() as u64)
+    }
+
+    async fn statistics(
+        &self,
+        info_hash: &str,
+        active_after: OffsetDateTime,
+    ) -> Result<Option<PeerStatistics>, Error> {
+        let (peers, active, complete, downloaded): (u32, u32, u32, Option<u32>) = redis::pipe()
+            .zcard(swarm_key(info_hash, "seen"))
+            .zcount(swarm_key(info_hash, "seen"), after(active_after), "+inf")
+            .zcount(swarm_key(info_hash, "seeders"), after(active_after), "+inf")
+            .hget(DOWNLOADED, info_hash)
+            .query_async(&mut self.conn.clone())
+            .await
+            .map_err(failed)?;
+
+        if peers == 0 && downloaded.is_none() {
+            return Ok(None);
+        }
+
+        Ok(Some(PeerStatistics {
+            complete,
+            downloaded: downloaded.unwrap_or(0),
+            incomplete: active.saturating_sub(complete),
+        }))
+    }
+}
+
+#[async_trait::async_trait]
+impl PeerRepository for RedisRepository {
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn update_peer_announce(&self, cmd: &UpdatePeerAnnounce) -> Result<(), Error> {
+        let info_hash = cmd.info_hash.to_hex();
+        let peer_id = hex::encode(&cmd.peer_id.0);
+        let record = Record {
+            ip: cmd.ip,
+            port: cmd.port,
+            other_endpoint: cmd.other_endpoint,
+            uploaded: cmd.uploaded,
+            downloaded: cmd.downloaded,
+            left: cmd.left,
+            event: cmd.event.clone(),
+            transport: cmd.transport,
+            announced: score(cmd.update_timestamp),
+            user_id: cmd.user_id.clone(),
+        };
+        let record = serde_json::to_string(&record).map_err(|e| Error::Backend(e.to_string()))?;
+
+        self.announce
+            .key(swarm_key(&info_hash, "peers"))
+            .key(swarm_key(&info_hash, "seen"))
+            .key(swarm_key(&info_hash, "seeders"))
+            .key(swarm_key(&info_hash, "completed"))
+            .key(swarm_key(&info_hash, "keys"))
+            .key(DOWNLOADED)
+            .key(SWARMS)
+            .key(ip_key(cmd.ip))
+            .arg(&peer_id)
+            .arg(record)
+            .arg(score(cmd.update_timestamp))
+            .arg(self.ttl)
+            .arg(match cmd.event {
+                Event::Stopped => "stopped",
+                Event::Completed => "completed",
+                _ => "",
+            })
+            .arg(&info_hash)
+            .arg(cmd.key.as_ref().map_or("", |key| key.0.as_str()))
+            .arg(cmd.ip.to_string())
+            .arg(if cmd.left == 0 { "1" } else { "0" })
+            .invoke_async::<i64>(&mut self.conn.clone())
+            .await
+            .map_err(failed)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn check_peer_key(&self, cmd: CheckPeerKey<'_>) -> Result<bool, Error> {
+        let info_hash = cmd.info_hash.to_hex();
+        let peer_id = hex::encode(&cmd.peer_id.0);
+        let (key, record): (Option<String>, Option<String>) = redis::pipe()
+            .hget(swarm_key(&info_hash, "keys"), &peer_id)
+            .hget(swarm_key(&info_hash, "peers"), &peer_id)
+            .query_async(&mut self.conn.clone())
+            .await
+            .map_err(failed)?;
+
+        let at = record.and_then(|r| serde_json::from_str::<Record>(&r).ok());
+        Ok(key.is_none_or(|stored| {
+            cmd.key.is_some_and(|key| key.0 == stored) || at.is_some_and(|r| r.ip == cmd.ip)
+        }))
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn count_peers(&self, cmd: CountPeers<'_>) -> Result<Option<PeerCounts>, Error> {
+        let info_hash = cmd.info_hash.to_hex();
+        let mut pipe = redis::pipe();
+        pipe.hexists(swarm_key(&info_hash, "peers"), hex::encode(&cmd.peer_id.0))
+            .zcount(
+                swarm_key(&info_hash, "seen"),
+                after(cmd.active_after),
+                "+inf",
+            );
+        let mut conn = self.conn.clone();
+        let (known, in_swarm, from_ip) = match cmd.ip {
+            Some(ip) => pipe
+                .zcount(ip_key(ip), after(cmd.active_after), "+inf")
+                .query_async(&mut conn)
+                .await
+                .map_err(failed)?,
+            None => {
+                let (known, in_swarm) = pipe.query_async(&mut conn).await.map_err(failed)?;
+                (known, in_swarm, 0)
+            }
+        };
+
+        Ok(Some(PeerCounts {
+            known,
+            in_swarm,
+            from_ip,
+        }))
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn evict_stalest_peer(&self, cmd: EvictStalestPeer<'_>) -> Result<(), Error> {
+        let info_hash = cmd.info_hash.to_hex();
+        let stalest: Vec<String> = self
+            .conn
+            .clone()
+            .zrangebyscore_limit(
+                swarm_key(&info_hash, "seen"),
+                after(cmd.active_after),
+                "+inf",
+                0,
+                1,
+            )
+            .await
+            .map_err(failed)?;
+
+        self.forget(&info_hash, &stalest).await
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn get_peers(&self, cmd: GetPeers<'_>) -> Result<Vec<Peer>, Error> {
+        let info_hash = cmd.info_hash.to_hex();
+        let connectable = self.connectable(&info_hash).await?;
+        let active_after = cmd.active_after.map(score);
+
+        Ok(self
+            .records(&info_hash)
+            .await?
+            .into_iter()
+            .filter(|(_, r)| active_after.is_none_or(|after| r.announced > after))
+            .flat_map(|(peer_id, r)| {
+                let connectable = &connectable;
+                r.endpoints().map(move |endpoint| Peer {
+                    peer_id: peer_id.clone(),
+                    ip: endpoint.ip(),
+                    port: endpoint.port(),
+                    connectable: connectable.get(&endpoint.to_string()).copied(),
+                    seeding: r.left == 0,
+                    source: PeerSource::Announce,
+                })
+            })
+            .collect())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn get_peer_statistics(
+        &self,
+        cmd: GetPeerStatistics<'_>,
+    ) -> Result<HashMap<InfoHash, PeerStatistics>, Error> {
+        let mut result = HashMap::new();
+        for info_hash in cmd.info_hashes {
+            if let Some(stats) = self
+                .statistics(&info_hash.to_hex(), cmd.active_after)
+                .await?
+            {
+                result.insert(info_hash.clone(), stats);
+            }
+        }
+
+        Ok(result)
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn iter_swarms(&self, cmd: IterSwarms) -> Result<Vec<SwarmSummary>, Error> {
+        let info_hashes: Vec<String> = self
+            .conn
+            .clone()
+            .zrangebyscore(SWARMS, after(cmd.active_after), "+inf")
+            .await
+            .map_err(failed)?;
+
+        let mut swarms = vec![];
+        for info_hash in info_hashes {
+            let latest: Vec<(String, i64)> = self
+                .conn
+                .clone()
+                .zrevrange_withscores(swarm_key(&info_hash, "seen"), 0, 0)
+                .await
+                .map_err(failed)?;
+            let Some((_, last_activity)) = latest.into_iter().next() else {
+                continue;
+            };
+            if last_activity <= score(cmd.active_after) {
+                continue;
+            }
+            let Some(statistics) = self.statistics(&info_hash, cmd.active_after).await? else {
+                continue;
+            };
+            swarms.push(SwarmSummary {
+                info_hash: InfoHash::from_hex(&info_hash),
+                statistics,
+                last_activity: at(last_activity),
+            });
+        }
+
+        Ok(swarms)
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn iter_idle_swarms(
+        &self,
+        cmd: IterIdleSwarms,
+    ) -> Result<HashMap<InfoHash, OffsetDateTime>, Error> {
+        let idle: Vec<(String, i64)> = self
+            .conn
+            .clone()
+            .zrangebyscore_withscores(SWARMS, "-inf", score(cmd.idle_since))
+            .await
+            .map_err(failed)?;
+
+        Ok(idle
+            .into_iter()
+            .map(|(info_hash, last_activity)| (InfoHash::from_hex(info_hash), at(last_activity)))
+            .collect())
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn get_swarm_detail(&self, cmd: GetSwarmDetail<'_>) -> Result<Vec<SwarmMember>, Error> {
+        let info_hash = cmd.info_hash.to_hex();
+        let connectable = self.connectable(&info_hash).await?;
+        let active_after = score(cmd.active_after);
+
+        Ok(self
+            .records(&info_hash)
+            .await?
+            .into_iter()
+            .filter(|(_, r)| r.announced > active_after)
+            .map(|(peer_id, r)| SwarmMember {
+                connectable: connectable
+                    .get(&SocketAddr::new(r.ip, r.port).to_string())
+                    .copied(),
+                peer_id,
+                ip: r.ip,
+                port: r.port,
+                other_endpoint: r.other_endpoint,
+                uploaded: r.uploaded,
+                downloaded: r.downloaded,
+                left: r.left,
+                event: r.event,
+                last_announce: at(r.announced),
+                transport: r.transport,
+            })
+            .collect())
+    }
+
+    // The swarm's completed count outlives it.
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn purge_swarm(&self, cmd: PurgeSwarm<'_>) -> Result<(), Error> {
+        let info_hash = cmd.info_hash.to_hex();
+        let peer_ids = self
+            .records(&info_hash)
+            .await?
+            .into_iter()
+            .map(|(peer_id, _)| hex::encode(peer_id.0))
+            .collect::<Vec<_>>();
+        self.forget(&info_hash, &peer_ids).await?;
+
+        let parts = [
+            "peers",
+            "seen",
+            "seeders",
+            "completed",
+            "keys",
+            "connectable",
+        ];
+        redis::pipe()
+            .atomic()
+            .del(parts.map(|part| swarm_key(&info_hash, part)).to_vec())
+            .zrem(SWARMS, &info_hash)
+            .query_async::<()>(&mut self.conn.clone())
+            .await
+            .map_err(failed)
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn purge_stale_peers(&self, cmd: PurgeStalePeers) -> Result<u64, Error> {
+        let info_hashes: Vec<String> = self
+            .conn
+            .clone()
+            .zrange(SWARMS, 0, -1)
+            .await
+            .map_err(failed)?;
+
+        let mut purged = 0;
+        for info_hash in info_hashes {
+            let announcing: u32 = self
+                .conn
+                .clone()
+                .zcount(
+                    swarm_key(&info_hash, "seen"),
+                    score(cmd.inactive_since),
+                    "+inf",
+                )
+                .await
+                .map_err(failed)?;
+            let before = match announcing {
+                0 => cmd.cutoff,
+                _ => cmd.inactive_since,
+            };
+            purged += self.peers_before(&info_hash, before).await?;
+        }
+        // Nothing is left of those, once their keys expire.
+        self.conn
+            .clone()
+            .zrembyscore::<_, _, _, ()>(SWARMS, "-inf", format!("({}", score(cmd.cutoff)))
+            .await
+            .map_err(failed)?;
+
+        Ok(purged)
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn set_connectable(&self, cmd: SetConnectable) -> Result<(), Error> {
+        let members: Vec<String> = self
+            .conn
+            .clone()
+            .zrange(ip_key(cmd.endpoint.ip()), 0, -1)
+            .await
+            .map_err(failed)?;
+
+        let mut pipe = redis::pipe();
+        for info_hash in members.iter().filter_map(|m| m.split(':').next()) {
+            let key = swarm_key(info_hash, "connectable");
+            pipe.hset(&key, cmd.endpoint.to_string(), cmd.connectable)
+                .expire(&key, self.ttl as i64);
+        }
+        pipe.query_async::<()>(&mut self.conn.clone())
+            .await
+            .map_err(failed)
+    }
+}
+
+#[async_trait::async_trait]
+impl InfoHashRepository for RedisRepository {
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn get_info_hash_summary(
+        &self,
+        cmd: GetInfoHashSummary<'_>,
+    ) -> Result<InfoHashSummary, Error> {
+        let fields: HashMap<String, String> = self
+            .conn
+            .clone()
+            .hgetall(format!("hanekawa:info_hash:{}", cmd.info_hash.to_hex()))
+            .await
+            .map_err(failed)?;
+
+        let status = match fields.get("status").map(String::as_str) {
+            Some("allowed") => InfoHashStatus::ExplicitAllow,
+            Some("denied") => InfoHashStatus::ExplicitDeny,
+            _ => InfoHashStatus::Unknown,
+        };
+        let size = fields.get("size").and_then(|size| size.parse().ok());
+        let metadata = fields
+            .get("name")
+            .cloned()
+            .zip(size)
+            .map(|(name, size)| TorrentMetadata { name, size });
+
+        Ok(InfoHashSummary {
+            info_hash: cmd.info_hash.clone(),
+            status,
+            metadata,
+        })
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn update_info_hash(&self, cmd: UpdateInfoHash<'_>) -> Result<(), Error> {
+        let key = format!("hanekawa:info_hash:{}", cmd.info_hash.to_hex());
+        let mut conn = self.conn.clone();
+        match cmd.status {
+            InfoHashStatus::Unknown => conn.del::<_, ()>(key).await,
+            InfoHashStatus::ExplicitAllow => {
+                conn.hset::<_, _, _, ()>(key, "status", "allowed").await
+            }
+            InfoHashStatus::ExplicitDeny => conn.hset::<_, _, _, ()>(key, "status", "denied").await,
+        }
+        .map_err(failed)
+    }
+
+    #[tracing::instrument(level = "debug", skip_all)]
+    async fn register_torrent(&self, cmd: RegisterTorrent<'_>) -> Result<(), Error> {
+        let key = format!("hanekawa:info_hash:{}", cmd.info_hash.to_hex());
+        let size = cmd.metadata.size.to_string();
+        let fields = [
+            ("status", "allowed"),
+            ("name", cmd.metadata.name.as_str()),
+            ("size", size.as_str()),
+        ];
+        self.conn
+            .clone()
+            .hset_multiple::<_, _, _, ()>(key, &fields)
+            .await
+            .map_err(failed)
+    }
+}
+
+// Against the Redis at HANEKAWA_TEST_REDIS_URL, which is flushed. Skipped
+// without one.
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::net::Ipv4Addr;
+    use std::time::Duration;
+
+    async fn repository() -> Option<RedisRepository> {
+        let url = std::env::var("HANEKAWA_TEST_REDIS_URL").ok()?;
+        let repository = RedisRepository::open(&url, 3600).await.unwrap();
+        redis::cmd("FLUSHDB")
+            .query_async::<()>(&mut repository.conn.clone())
+            .await
+            .unwrap();
+
+        Some(repository)
+    }
+
+    fn announce(peer_id: u8, left: u64, event: Event, at: OffsetDateTime) -> UpdatePeerAnnounce {
+        UpdatePeerAnnounce {
+            info_hash: InfoHash(vec![0xaa; 20]),
+            peer_id: PeerId(vec![peer_id; 20]),
+            ip: IpAddr::V4(Ipv4Addr::new(10, 0, 0, peer_id)),
+            port: 6881,
+            uploaded: 0,
+            downloaded: 0,
+            left,
+            event,
+            update_timestamp: at,
+            other_endpoint: None,
+            transport: Some(Transport::Http),
+            user_id: None,
+            key: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn keeps_swarms() {
+        let Some(repository) = repository().await else {
+            return;
+        };
+        let now = OffsetDateTime::now_utc();
+        let info_hash = InfoHash(vec![0xaa; 20]);
+        for cmd in [
+            announce(1, 0, Event::Started, now),
+            announce(2, 100, Event::Started, now),
+            announce(3, 0, Event::Completed, now),
+            announce(3, 0, Event::Completed, now),
+        ] {
+            repository.update_peer_announce(&cmd).await.unwrap();
+        }
+
+        let peers = repository
+            .get_peers(GetPeers {
+                info_hash: &info_hash,
+                active_after: Some(now - Duration::from_secs(60)),
+            })
+            .await
+            .unwrap();
+        assert_eq!(3, peers.len());
+        let stats = repository
+            .get_peer_statistics(GetPeerStatistics {
+                info_hashes: std::slice::from_ref(&info_hash),
+                active_after: now - Duration::from_secs(60),
+            })
+            .await
+            .unwrap();
+        assert_eq!((2, 1, 1), {
+            let s = &stats[&info_hash];
+            (s.complete, s.downloaded, s.incomplete)
+        });
+
+        let cmd = announce(3, 0, Event::Stopped, now);
+        repository.update_peer_announce(&cmd).await.unwrap();
+        let stats = repository
+            .get_peer_statistics(GetPeerStatistics {
+                info_hashes: std::slice::from_ref(&info_hash),
+                active_after: now - Duration::from_secs(60),
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            (1, 1),
+            (stats[&info_hash].complete, stats[&info_hash].downloaded)
+        );
+    }
+
+    // With none of the swarm announcing, only those past the cutoff go.
+    #[tokio::test]
+    async fn purges_peers_of_idle_swarms_past_the_cutoff() {
+        let Some(repository) = repository().await else {
+            return;
+        };
+        let now = OffsetDateTime::now_utc();
+        let cmd = announce(1, 100, Event::Started, now - Duration::from_secs(3600));
+        repository.update_peer_announce(&cmd).await.unwrap();
+        let cmd = announce(2, 100, Event::Started, now - Duration::from_secs(600));
+        repository.update_peer_announce(&cmd).await.unwrap();
+
+        let expired = repository
+            .purge_stale_peers(PurgeStalePeers {
+                inactive_since: now - Duration::from_secs(60),
+                cutoff: now - Duration::from_secs(1800),
+            })
+            .await
+            .unwrap();
+        assert_eq!(1, expired);
+        let peers = repository
+            .get_peers(GetPeers {
+                info_hash: &InfoHash(vec![0xaa; 20]),
+                active_after: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            vec![PeerId(vec![2; 20])],
+            peers.into_iter().map(|p| p.peer_id).collect::<Vec<_>>()
+        );
+    }
+}
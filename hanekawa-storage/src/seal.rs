@@ -0,0 +1,386 @@
+use hanekawa_common::EncryptionConfig;
+
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN},
+    hmac,
+    rand::{SecureRandom, SystemRandom},
+};
+use std::{net::IpAddr, path::Path};
+
+#[derive(Debug)]
+pub enum Error {
+    NoLookupKey,
+    Unreadable(String, std::io::Error),
+    Invalid(String),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoLookupKey => write!(f, "encryption keys need a lookup key"),
+            Self::Unreadable(path, e) => write!(f, "cannot read key file {path}: {e}"),
+            Self::Invalid(id) => write!(f, "key {id} is not 32 bytes in hex"),
+        }
+    }
+}
+
+// What is encrypted of an announce. The rest is left as is, as it says
+// little about who the peer is and is needed to count and order swarms.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Identity {
+    pub peer_id: Vec<u8>,
+    pub ip: IpAddr,
+    pub other_ip: Option<IpAddr>,
+}
+
+impl Identity {
+    fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(64);
+        let mut field = |bytes: &[u8]| {
+            out.push(bytes.len() as u8);
+            out.extend_from_slice(bytes);
+        };
+        field(&self.peer_id);
+        field(&octets(self.ip));
+        field(&self.other_ip.map(octets).unwrap_or_default());
+        out
+    }
+
+    fn decode(mut bytes: &[u8]) -> Option<Self> {
+        let mut field = || {
+            let (len, rest) = bytes.split_first()?;
+            let (field, rest) = rest.split_at_checked(*len as usize)?;
+            bytes = rest;
+            Some(field.to_vec())
+        };
+        let peer_id = field()?;
+        let ip = ip_from(&field()?)?;
+        let other_ip = field()?;
+        let other_ip = match other_ip.is_empty() {
+            true => None,
+            false => Some(ip_from(&other_ip)?),
+        };
+
+        Some(Self {
+            peer_id,
+            ip,
+            other_ip,
+        })
+    }
+}
+
+fn octets(ip: IpAddr) -> Vec<u8> {
+    match ip {
+        IpAddr::V4(ip) => ip.octets().to_vec(),
+        IpAddr::V6(ip) => ip.octets().to_vec(),
+    }
+}
+
+fn ip_from(bytes: &[u8]) -> Option<IpAddr> {
+    match bytes.len() {
+        4 => Some(IpAddr::from(<[u8; 4]>::try_from(bytes).ok()?)),
+        16 => Some(IpAddr::from(<[u8; 16]>::try_from(bytes).ok()?)),
+        _ => None,
+    }
+}
+
+// Encrypts peer ids and addresses before they are written, with AES-256-GCM
+// under a random nonce, so the same peer never looks the same twice. Where
+// rows are looked up by them, an HMAC stands in instead.
+pub struct Sealer {
+    // The first encrypts.
+    keys: Vec<(String, LessSafeKey)>,
+    lookup: hmac::Key,
+    rng: SystemRandom,
+}
+
+impl Sealer {
+    // None if no keys are configured.
+    pub fn new(cfg: &EncryptionConfig) -> Result<Option<Self>, Error> {
+        if cfg.keys.is_empty() {
+            return Ok(None);
+        }
+
+        let keys = cfg
+            .keys
+            .iter()
+            .map(|key| {
+                let bytes = key_bytes(&key.id, key.key.as_deref(), key.keyfile.as_deref())?;
+                let unbound = UnboundKey::new(&AES_256_GCM, &bytes)
+                    .map_err(|_| Error::Invalid(key.id.clone()))?;
+                Ok((key.id.clone(), LessSafeKey::new(unbound)))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        if cfg.lookup_key.is_none() && cfg.lookup_keyfile.is_none() {
+            return Err(Error::NoLookupKey);
+        }
+        let lookup = key_bytes(
+            "lookup",
+            cfg.lookup_key.as_deref(),
+            cfg.lookup_keyfile.as_deref(),
+        )?;
+
+        Ok(Some(Self {
+            keys,
+            lookup: hmac::Key::new(hmac::HMAC_SHA256, &lookup),
+            rng: SystemRandom::new(),
+        }))
+    }
+
+    // Under keys made up at start and never written anywhere, for when
+    // peers are to be pseudonymized but no keys are configured. What it
+    // wrote cannot be read once the tracker restarts, and is swept then.
+    pub fn ephemeral() -> Self {
+        let rng = SystemRandom::new();
+        let mut key = [0; 32];
+        rng.fill(&mut key).unwrap();
+        let mut lookup = [0; 32];
+        rng.fill(&mut lookup).unwrap();
+
+        let unbound = UnboundKey::new(&AES_256_GCM, &key).unwrap();
+        Self {
+            keys: vec![("ephemeral".to_string(), LessSafeKey::new(unbound))],
+            lookup: hmac::Key::new(hmac::HMAC_SHA256, &lookup),
+            rng,
+        }
+    }
+
+    pub fn key_id(&self) -> &str {
+        &self.keys[0].0
+    }
+
+    // The same for the same value, and nothing else.
+    pub fn lookup(&self, value: &[u8]) -> Vec<u8> {
+        hmac::sign(&self.lookup, value).as_ref().to_vec()
+    }
+
+    pub fn lookup_ip(&self, ip: IpAddr) -> Vec<u8> {
+        self.lookup(&octets(ip))
+    }
+
+    // Bound to `context`, so rows cannot be swapped around undetected. The
+    // nonce comes first.
+    pub fn seal(&self, context: &[u8], identity: &Identity) -> Vec<u8> {
+        let mut nonce = [0; NONCE_LEN];
+        self.rng.fill(&mut nonce).unwrap();
+
+        let mut sealed = identity.encode();
+        self.keys[0]
+            .1
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(context),
+                &mut sealed,
+            )
+            .unwrap();
+
+        [&nonce[..], &sealed].concat()
+    }
+
+    // None under a key that is not configured, or if it was tampered with.
+    pub fn open(&self, key_id: &str, context: &[u8], sealed: &[u8]) -> Option<Identity> {
+        let (_, key) = self.keys.iter().find(|(id, _)| id == key_id)?;
+        let (nonce, sealed) = sealed.split_at_checked(NONCE_LEN)?;
+        let nonce = Nonce::try_assume_unique_for_key(nonce).ok()?;
+
+        let mut sealed = sealed.to_vec();
+        let opened = key
+            .open_in_place(nonce, Aad::from(context), &mut sealed)
+            .ok()?;
+        Identity::decode(opened)
+    }
+}
+
+fn key_bytes(id: &str, key: Option<&str>, keyfile: Option<&Path>) -> Result<Vec<u8>, Error> {
+    let hex = match (key, keyfile) {
+        (Some(key), _) => key.to_string(),
+        (None, Some(path)) => std::fs::read_to_string(path)
+            .map_err(|e| Error::Unreadable(path.display().to_string(), e))?,
+        (None, None) => return Err(Error::Invalid(id.to_string())),
+    };
+
+    match hex::decode(hex.trim()) {
+        Ok(bytes) if bytes.len() == 32 => Ok(bytes),
+        _ => Err(Error::Invalid(id.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::{peer::PeerRepository, test_pool, unique_info_hash};
+
+    use hanekawa_common::{
+        repository::peer::{GetPeers, GetSwarmDetail, PeerRepository as _, UpdatePeerAnnounce},
+        types::{Event, InfoHash, PeerId},
+        Config, EncryptionKey,
+    };
+    use sqlx::types::time::OffsetDateTime;
+    use std::sync::Arc;
+
+    fn key(id: &str, byte: u8) -> EncryptionKey {
+        EncryptionKey {
+            id: id.to_string(),
+            key: Some(hex::encode([byte; 32])),
+            keyfile: None,
+        }
+    }
+
+    fn sealer(keys: Vec<EncryptionKey>) -> Sealer {
+        let cfg = EncryptionConfig {
+            keys,
+            lookup_key: Some(hex::encode([0xff; 32])),
+            lookup_keyfile: None,
+        };
+        Sealer::new(&cfg).unwrap().unwrap()
+    }
+
+    fn identity() -> Identity {
+        Identity {
+            peer_id: b"-qB4650-123456789012".to_vec(),
+            ip: IpAddr::from([192, 0, 2, 1]),
+            other_ip: Some("2001:db8::1".parse().unwrap()),
+        }
+    }
+
+    fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+        haystack.windows(needle.len()).any(|w| w == needle)
+    }
+
+    #[test]
+    fn round_trips_without_plaintext() {
+        let sealer = sealer(vec![key("a", 1)]);
+        let identity = identity();
+
+        let sealed = sealer.seal(b"info hash", &identity);
+        assert_eq!(
+            Some(identity.clone()),
+            sealer.open("a", b"info hash", &sealed)
+        );
+        assert!(!contains(&sealed, &identity.peer_id));
+        assert!(!contains(&sealed, &[192, 0, 2, 1]));
+        assert_ne!(sealed, sealer.seal(b"info hash", &identity));
+
+        // Nor does it open for another row.
+        assert_eq!(None, sealer.open("a", b"other info hash", &sealed));
+
+        let lookup = sealer.lookup(&identity.peer_id);
+        assert_eq!(lookup, sealer.lookup(&identity.peer_id));
+        assert!(!contains(&lookup, &identity.peer_id));
+    }
+
+    #[test]
+    fn opens_under_older_keys() {
+        let old = sealer(vec![key("a", 1)]);
+        let sealed = old.seal(b"info hash", &identity());
+
+        let rotated = sealer(vec![key("b", 2), key("a", 1)]);
+        assert_eq!("b", rotated.key_id());
+        assert_eq!(Some(identity()), rotated.open("a", b"info hash", &sealed));
+        assert_eq!(old.lookup(b"peer"), rotated.lookup(b"peer"));
+
+        let retired = sealer(vec![key("b", 2)]);
+        assert_eq!(None, retired.open("a", b"info hash", &sealed));
+    }
+
+    #[test]
+    fn checks_the_keys() {
+        let mut cfg = EncryptionConfig::default();
+        assert!(Sealer::new(&cfg).unwrap().is_none());
+
+        cfg.keys = vec![key("a", 1)];
+        assert!(matches!(Sealer::new(&cfg), Err(Error::NoLookupKey)));
+
+        cfg.lookup_key = Some("abcd".to_string());
+        assert!(matches!(Sealer::new(&cfg), Err(Error::Invalid(id)) if id == "lookup"));
+    }
+
+    // As announced by `identity()`, through a store sealing under `a`.
+    async fn stored() -> Option<(sqlx::PgPool, PeerRepository, InfoHash)> {
+        let pool = test_pool().await?;
+        let sealer = Arc::new(sealer(vec![key("a", 1)]));
+        let repository = PeerRepository::new(pool.clone(), &Config::default(), Some(sealer));
+        let info_hash = unique_info_hash();
+        let identity = identity();
+        let cmd = UpdatePeerAnnounce {
+            info_hash: info_hash.clone(),
+            peer_id: PeerId(identity.peer_id),
+            ip: identity.ip,
+            port: 6881,
+            uploaded: 0,
+            downloaded: 0,
+            left: 100,
+            event: Event::Started,
+            update_timestamp: OffsetDateTime::now_utc(),
+            other_endpoint: identity.other_ip.map(|ip| (ip, 6882).into()),
+            transport: None,
+            user_id: None,
+            key: None,
+        };
+        repository.update_peer_announce(&cmd).await.unwrap();
+
+        Some((pool, repository, info_hash))
+    }
+
+    #[tokio::test]
+    async fn reads_back_what_it_wrote() {
+        let Some((_, repository, info_hash)) = stored().await else {
+            return;
+        };
+
+        let peers = repository
+            .get_peers(GetPeers {
+                info_hash: &info_hash,
+                active_after: None,
+            })
+            .await
+            .unwrap();
+        let identity = identity();
+        let peers: Vec<_> = peers.into_iter().map(|p| (p.peer_id.0, p.ip)).collect();
+        // Both of its addresses.
+        let expected = [identity.ip, identity.other_ip.unwrap()]
+            .map(|ip| (identity.peer_id.clone(), ip))
+            .to_vec();
+        assert_eq!(expected, peers);
+
+        let members = repository
+            .get_swarm_detail(GetSwarmDetail {
+                info_hash: &info_hash,
+                active_after: OffsetDateTime::now_utc() - std::time::Duration::from_secs(60),
+            })
+            .await
+            .unwrap();
+        assert_eq!(identity.other_ip, members[0].other_endpoint.map(|e| e.ip()));
+    }
+
+    #[tokio::test]
+    async fn writes_no_plaintext() {
+        let Some((pool, _, info_hash)) = stored().await else {
+            return;
+        };
+
+        let row = sqlx::query!(
+            "
+SELECT peer_id, ip, other_ip, ip_lookup, sealed, key_id
+FROM peer_announces
+WHERE info_hash = $1
+",
+            &info_hash.0
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+
+        let identity = identity();
+        assert_eq!((None, None), (row.ip, row.other_ip));
+        assert_eq!(Some("a"), row.key_id.as_deref());
+        let v6 = octets(identity.other_ip.unwrap());
+        for column in [row.peer_id, row.ip_lookup.unwrap(), row.sealed.unwrap()] {
+            assert!(!contains(&column, &identity.peer_id));
+            assert!(!contains(&column, &[192, 0, 2, 1]));
+            assert!(!contains(&column, &v6));
+        }
+    }
+}
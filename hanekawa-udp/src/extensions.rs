@@ -16,7 +16,8 @@ fn parse_three_part_option(input: &[u8]) -> IResult<&[u8], (u8, String)> {
     let (input, id) = be_u8(input)?;
     let (input, len) = be_u8(input)?;
     let (input, bs) = take(len)(input)?;
-    let s = std::str::from_utf8(bs).unwrap().to_string();
+    // From anyone on the network, so bytes that are not UTF-8 are replaced.
+    let s = String::from_utf8_lossy(bs).into_owned();
 
     Ok((input, (id, s)))
 }
@@ -97,6 +98,24 @@ mod test {
         )
     }
 
+    #[test]
+    fn parses_urldata_that_is_not_utf8() {
+        let mut buf = BytesMut::new();
+
+        buf.put_u8(2);
+        buf.put_u8(4);
+        buf.put_slice(b"/a\xffb");
+        buf.put_u8(0);
+
+        assert_eq!(
+            Ok((
+                &[] as &[u8],
+                vec![Extension::UrlData("/a\u{fffd}b".to_string())]
+            )),
+            parse_extensions(&buf)
+        )
+    }
+
     #[test]
     fn parses_unknown_data() {
         let mut buf = BytesMut::new();
@@ -1,7 +1,7 @@
 mod extensions;
 
 use hanekawa::udp_tracker::proto::*;
-use hanekawa_common::types::Event;
+use hanekawa_common::types::{Event, InfoHash, PeerId};
 
 use extensions::parse_extensions;
 
@@ -11,11 +11,13 @@ use nom::{
     bytes::complete::{tag, take},
     combinator::{all_consuming, map},
     multi::many1,
-    number::complete::{be_i16, be_i32, be_i64},
+    number::complete::{be_i32, be_i64, be_u16},
     sequence::tuple,
     IResult,
 };
 
+use std::net::SocketAddr;
+
 const PROTOCOL_ID: u64 = 0x41727101980;
 
 fn parse_connect_request(input: &[u8]) -> IResult<&[u8], ConnectRequest> {
@@ -39,11 +41,8 @@ fn encode_connect_response(resp: &ConnectResponse, buf: &mut BytesMut) {
     buf.put_i64(resp.connection_id);
 }
 
-fn parse_20_bit_string(input: &[u8]) -> IResult<&[u8], String> {
-    let (input, bts) = take(20_usize)(input)?;
-    let s = std::str::from_utf8(bts).unwrap();
-
-    Ok((input, s.to_string()))
+fn parse_20_bytes(input: &[u8]) -> IResult<&[u8], Vec<u8>> {
+    map(take(20_usize), |bs: &[u8]| bs.to_vec())(input)
 }
 
 fn parse_event(input: &[u8]) -> IResult<&[u8], Option<Event>> {
@@ -92,8 +91,8 @@ fn parse_announce_request(input: &[u8]) -> IResult<&[u8], AnnounceRequest> {
         be_i64,
         tag(1_u32.to_be_bytes()),
         be_i32,
-        parse_20_bit_string,
-        parse_20_bit_string,
+        map(parse_20_bytes, InfoHash),
+        map(parse_20_bytes, PeerId),
         be_i64,
         be_i64,
         be_i64,
@@ -101,7 +100,7 @@ fn parse_announce_request(input: &[u8]) -> IResult<&[u8], AnnounceRequest> {
         parse_ip,
         be_i32,
         parse_num_want,
-        be_i16,
+        be_u16,
         parse_extensions,
     ))(input)?;
 
@@ -132,9 +131,12 @@ fn encode_announce_response(resp: &AnnounceResponse, buf: &mut BytesMut) {
     buf.put_i32(resp.leechers);
     buf.put_i32(resp.seeders);
 
-    for (ip, port) in &resp.peers {
-        buf.put_i32(*ip);
-        buf.put_i16(*port);
+    for peer in &resp.peers {
+        match peer {
+            SocketAddr::V4(addr) => buf.put_slice(&addr.ip().octets()),
+            SocketAddr::V6(addr) => buf.put_slice(&addr.ip().octets()),
+        }
+        buf.put_u16(peer.port());
     }
 }
 
@@ -143,7 +145,7 @@ fn parse_scrape_request(input: &[u8]) -> IResult<&[u8], ScrapeRequest> {
         be_i64,
         tag(2_u32.to_be_bytes()),
         be_i32,
-        many1(parse_20_bit_string),
+        many1(map(parse_20_bytes, InfoHash)),
     ))(input)?;
 
     Ok((
@@ -233,7 +235,7 @@ mod test {
         buf.put_i32(0);
         buf.put_i32(17);
         buf.put_i32(-1);
-        buf.put_i16(3001);
+        buf.put_u16(3001);
 
         assert_eq!(
             Ok((
@@ -241,8 +243,8 @@ mod test {
                 AnnounceRequest {
                     connection_id: 42,
                     transaction_id: 32,
-                    info_hash: info_hash.to_string(),
-                    peer_id: peer_id.to_string(),
+                    info_hash: InfoHash(info_hash.as_bytes().to_vec()),
+                    peer_id: PeerId(peer_id.as_bytes().to_vec()),
                     downloaded: 3,
                     left: 4,
                     uploaded: 5,
@@ -262,7 +264,7 @@ mod test {
     fn parses_scrape_request() {
         let mut buf = BytesMut::new();
 
-        let info_hash = "01234567890123456789".to_string();
+        let info_hash = InfoHash(b"01234567890123456789".to_vec());
         let num_hashes = 6;
 
         let mut hashes = Vec::new();
@@ -274,7 +276,7 @@ mod test {
         buf.put_i32(2);
         buf.put_i32(32);
         for _ in 0..num_hashes {
-            buf.put_slice(info_hash.as_bytes())
+            buf.put_slice(&info_hash.0)
         }
 
         assert_eq!(
@@ -289,4 +291,67 @@ mod test {
             parse_scrape_request(&buf)
         )
     }
+
+    #[test]
+    fn encodes_announce_response() {
+        let mut buf = BytesMut::new();
+
+        let response = AnnounceResponse {
+            transaction_id: 32,
+            interval: 60,
+            leechers: 1,
+            seeders: 2,
+            peers: vec![
+                "127.0.0.1:6881".parse().unwrap(),
+                "10.0.0.1:51413".parse().unwrap(),
+            ],
+        };
+
+        encode_announce_response(&response, &mut buf);
+
+        let mut exp = BytesMut::new();
+        exp.put_i32(1);
+        exp.put_i32(32);
+        exp.put_i32(60);
+        exp.put_i32(1);
+        exp.put_i32(2);
+        exp.put_slice(&[127, 0, 0, 1, 0x1a, 0xe1]);
+        exp.put_slice(&[10, 0, 0, 1, 0xc8, 0xd5]);
+
+        assert_eq!(exp, buf)
+    }
+
+    #[test]
+    fn encodes_ipv6_announce_response() {
+        let mut buf = BytesMut::new();
+
+        let response = AnnounceResponse {
+            transaction_id: 32,
+            interval: 60,
+            leechers: 0,
+            seeders: 1,
+            peers: vec!["[::1]:6881".parse().unwrap()],
+        };
+
+        encode_announce_response(&response, &mut buf);
+
+        assert_eq!(20 + 18, buf.len());
+        assert_eq!(&[0, 1, 0x1a, 0xe1], &buf[34..]);
+    }
+
+    #[test]
+    fn parses_binary_info_hash() {
+        let mut buf = BytesMut::new();
+
+        let info_hash = [0xff_u8; 20];
+
+        buf.put_i64(42);
+        buf.put_i32(2);
+        buf.put_i32(32);
+        buf.put_slice(&info_hash);
+
+        let (_, request) = parse_scrape_request(&buf).unwrap();
+
+        assert_eq!(vec![InfoHash(info_hash.to_vec())], request.info_hashes)
+    }
 }
@@ -0,0 +1,143 @@
+// Encoding announce responses, with compact peer lists and the response
+// buffer pooled as the tracker does and with a buffer of their own each as
+// it did before. Allocations per announce are printed before the timings.
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+
+use hanekawa::http_tracker::{
+    encode_peers,
+    proto::{AnnounceResponse, PeerData},
+};
+use hanekawa_bencode::Encoder;
+use hanekawa_common::types::{Peer, PeerId, PeerSource, PeerStatistics};
+
+use bytes::{BufMut, BytesMut};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    hint::black_box,
+    net::IpAddr,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+fn peers(n: u8) -> Vec<Peer> {
+    (0..n)
+        .map(|i| Peer {
+            peer_id: PeerId(vec![i; 20]),
+            ip: match i % 4 {
+                0 => IpAddr::from([0x2001, 0xdb8, 0, 0, 0, 0, 0, i as u16]),
+                _ => IpAddr::from([192, 0, 2, i]),
+            },
+            port: 6881,
+            connectable: None,
+            seeding: false,
+            source: PeerSource::default(),
+        })
+        .collect()
+}
+
+fn response((peers, peers6): (PeerData, PeerData)) -> AnnounceResponse {
+    AnnounceResponse {
+        interval: 1800,
+        min_interval: 900,
+        peers,
+        peers6,
+        stats: Some(PeerStatistics {
+            complete: 30,
+            downloaded: 100,
+            incomplete: 20,
+        }),
+        warnings: vec![],
+    }
+}
+
+fn unpooled(peers: Vec<Peer>) -> bytes::Bytes {
+    let mut peers_bytes = BytesMut::new();
+    let mut peers6_bytes = BytesMut::new();
+    for peer in peers {
+        match peer.ip {
+            IpAddr::V4(ip) => {
+                peers_bytes.put_u32(ip.into());
+                peers_bytes.put_u16(peer.port);
+            }
+            IpAddr::V6(ip) => {
+                peers6_bytes.put_u128(ip.into());
+                peers6_bytes.put_u16(peer.port);
+            }
+        }
+    }
+    let data = (
+        PeerData::Compact(peers_bytes.to_vec().into()),
+        PeerData::Compact(peers6_bytes.to_vec().into()),
+    );
+    hanekawa_bencode::to_bytes(&response(data)).unwrap()
+}
+
+fn pooled(encoder: &mut Encoder, peers: Vec<Peer>) -> bytes::Bytes {
+    encoder
+        .encode(&response(encode_peers(peers, true, true)))
+        .unwrap()
+}
+
+fn allocations(mut encode: impl FnMut(Vec<Peer>) -> bytes::Bytes) -> f64 {
+    const ROUNDS: usize = 1000;
+    let mut count = 0;
+    for _ in 0..ROUNDS {
+        let peers = peers(50);
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+        // Drops the peers along with the response, as sending it does.
+        drop(encode(peers));
+        count += ALLOCATIONS.load(Ordering::Relaxed) - before;
+    }
+    count as f64 / ROUNDS as f64
+}
+
+pub fn encode_announces(c: &mut Criterion) {
+    let mut encoder = Encoder::with_capacity(16 * 1024);
+    eprintln!(
+        "allocations per announce of 50 peers: {} unpooled, {} pooled",
+        allocations(unpooled),
+        allocations(|peers| pooled(&mut encoder, peers)),
+    );
+
+    let mut group = c.benchmark_group("announce");
+    group.bench_function("unpooled", |b| {
+        b.iter_batched(
+            || peers(50),
+            |p| black_box(unpooled(p)),
+            BatchSize::SmallInput,
+        )
+    });
+    group.bench_function("pooled", |b| {
+        b.iter_batched(
+            || peers(50),
+            |p| black_box(pooled(&mut encoder, p)),
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+criterion_group!(benches, encode_announces);
+criterion_main!(benches);
@@ -0,0 +1,93 @@
+// Encoding the responses the HTTP tracker sends most: a compact announce,
+// a non-compact one, and a scrape of many torrents. Each is also encoded
+// into an empty buffer, as `to_bytes` did before it started out with room.
+//
+// When that changed, a compact announce took 224ns rather than 592ns, a
+// non-compact one 5.5µs rather than 6.1µs, and the scrape about 100µs
+// either way. Sizing buffers exactly by encoding twice, once to count, was
+// slower for all but the compact announce, as most of the time goes into
+// formatting addresses.
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+
+use hanekawa::http_tracker::{
+    encode_peers,
+    proto::{AnnounceResponse, ScrapeResponse},
+};
+use hanekawa_common::types::{InfoHash, Peer, PeerId, PeerSource, PeerStatistics};
+
+use bytes::BytesMut;
+use std::{hint::black_box, net::IpAddr};
+
+fn peers(n: u16) -> Vec<Peer> {
+    (0..n)
+        .map(|i| Peer {
+            peer_id: PeerId(format!("-qB4650-{i:012}").into_bytes()),
+            ip: match i % 4 {
+                0 => IpAddr::from([0x2001, 0xdb8, 0, 0, 0, 0, 0, i]),
+                _ => IpAddr::from([192, 0, (i >> 8) as u8, i as u8]),
+            },
+            port: 6881 + i,
+            connectable: None,
+            seeding: false,
+            source: PeerSource::default(),
+        })
+        .collect()
+}
+
+fn announce(is_compact: bool) -> AnnounceResponse {
+    let (peers, peers6) = encode_peers(peers(50), is_compact, true);
+    AnnounceResponse {
+        interval: 1800,
+        min_interval: 900,
+        peers,
+        peers6,
+        stats: Some(PeerStatistics {
+            complete: 30,
+            downloaded: 1200,
+            incomplete: 20,
+        }),
+        warnings: vec![],
+    }
+}
+
+fn scrape(n: u32) -> ScrapeResponse {
+    let files = (0..n)
+        .map(|i| {
+            let mut info_hash = vec![0xaa; 20];
+            info_hash[..4].copy_from_slice(&i.to_be_bytes());
+            let stats = PeerStatistics {
+                complete: i % 100,
+                downloaded: i * 7,
+                incomplete: i % 13,
+            };
+            (InfoHash(info_hash), stats)
+        })
+        .collect();
+    ScrapeResponse { files }
+}
+
+fn bench<T: serde::Serialize>(c: &mut Criterion, name: &str, response: T) {
+    let len = hanekawa_bencode::to_bytes(&response).unwrap().len();
+    let mut group = c.benchmark_group(name);
+    group.throughput(Throughput::Bytes(len as u64));
+    group.bench_function("to_bytes", |b| {
+        b.iter(|| hanekawa_bencode::to_bytes(black_box(&response)).unwrap())
+    });
+    group.bench_function("empty buffer", |b| {
+        b.iter(|| {
+            let mut buf = BytesMut::new();
+            hanekawa_bencode::to_writer(black_box(&response), &mut buf).unwrap();
+            buf.freeze()
+        })
+    });
+}
+
+pub fn encode_responses(c: &mut Criterion) {
+    bench(c, "compact announce", announce(true));
+    bench(c, "non-compact announce", announce(false));
+    bench(c, "scrape of 1000", scrape(1000));
+}
+
+criterion_group!(benches, encode_responses);
+criterion_main!(benches);
@@ -1,20 +1,81 @@
-use std::sync::Arc;
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
 
 use hanekawa_common::{
-    repository::info_hash::{InfoHashRepository, UpdateInfoHash},
-    types::{InfoHash, InfoHashStatus},
-    Config,
+    audit::AuditLog,
+    ban::BanList,
+    client::{ClientFingerprint, Family},
+    maintenance::Maintenance,
+    metainfo::Metainfo,
+    offense::{Offenders, OffenseCounts},
+    passkey::Passkeys,
+    privacy::{self, Pseudonymizer},
+    repository::{
+        audit::GetAudit,
+        info_hash::{GetInfoHashSummary, InfoHashRepository, RegisterTorrent, UpdateInfoHash},
+        peer::{
+            GetSwarmDetail, PageSwarms, PeerRepository, PurgeSwarm, SwarmFilter, SwarmOrder,
+            SwarmPosition,
+        },
+    },
+    types::{
+        AuditRecord, Ban, BanTarget, InfoHash, InfoHashStatus, Passkey, SwarmMember,
+        TorrentMetadata, Transport,
+    },
+    Clock, Config, Services,
 };
+use time::OffsetDateTime;
+
+const DEFAULT_PAGE_SIZE: usize = 50;
+const MAX_PAGE_SIZE: usize = 500;
 
 #[derive(Debug)]
 pub enum Error {
     NotAllowed,
+    InvalidInfoHash(String),
+    InvalidTorrent(String),
+    InvalidBan(String),
+    UnknownBan(u64),
+    InvalidCursor,
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotAllowed => f.write_str("admin API disabled"),
+            Self::InvalidInfoHash(s) => f.write_fmt(format_args!("invalid info hash: {s}")),
+            Self::InvalidTorrent(s) => f.write_fmt(format_args!("invalid torrent: {s}")),
+            Self::InvalidBan(s) => f.write_fmt(format_args!("invalid ban: {s}")),
+            Self::UnknownBan(id) => f.write_fmt(format_args!("no ban {id}")),
+            Self::InvalidCursor => f.write_str("invalid cursor"),
+        }
+    }
+}
+
+// Who made a request, by the id of their token.
+#[derive(Debug, Clone, Default)]
+pub struct Caller {
+    // Anonymous if the admin API takes no tokens.
+    pub token_id: Option<String>,
 }
 
 #[derive(Clone)]
 pub struct AdminService {
     config: Config,
+    peer_repository: Arc<dyn PeerRepository>,
     info_hash_repository: Arc<dyn InfoHashRepository>,
+    bans: BanList,
+    audit: AuditLog,
+    offenders: Offenders,
+    maintenance: Maintenance,
+    passkeys: Passkeys,
+    // Stands in for ban targets in the audit log, if set.
+    pseudonymizer: Option<Pseudonymizer>,
+    clock: Clock,
 }
 
 pub struct KnownInfoHashRequest {
@@ -22,25 +83,376 @@ pub struct KnownInfoHashRequest {
     pub action: InfoHashStatus,
 }
 
+#[derive(Debug, Clone, Default)]
+pub struct ListTorrentsRequest {
+    pub order: SwarmOrder,
+    pub filter: SwarmFilter,
+    // From the previous page, which must have had the same order.
+    pub cursor: Option<String>,
+    // The default page size if not set.
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct TorrentList {
+    pub torrents: Vec<TorrentEntry>,
+    // Where the next page starts, if there is one.
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct TorrentEntry {
+    pub info_hash: String,
+    // From the .torrent file, if it was uploaded.
+    pub name: Option<String>,
+    pub seeders: u32,
+    pub leechers: u32,
+    pub snatches: u32,
+    // Unix time of the latest announce.
+    pub last_activity: i64,
+    pub policy: Policy,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct RegisteredTorrent {
+    // Both of a hybrid's, v1 first.
+    pub info_hashes: Vec<String>,
+    pub name: String,
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Policy {
+    Default,
+    Allowed,
+    Denied,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct PeerEntry {
+    // Left out when peers are redacted, like the addresses.
+    pub peer_id: Option<String>,
+    pub ip: Option<IpAddr>,
+    pub port: Option<u16>,
+    pub other_endpoint: Option<SocketAddr>,
+    // As its peer id fingerprints it, e.g. `qBittorrent 4.6.5`, unless it
+    // names no client.
+    pub client: Option<String>,
+    pub uploaded: u64,
+    pub downloaded: u64,
+    pub left: u64,
+    pub event: String,
+    // Unix time of the peer's latest announce.
+    pub last_announce: i64,
+    pub transport: Option<Transport>,
+    // Unknown until probed.
+    pub connectable: Option<bool>,
+}
+
+// Active peers of a swarm by client and version.
+#[derive(Debug, serde::Serialize)]
+pub struct ClientCount {
+    pub client: String,
+    pub version: Option<String>,
+    pub peers: u32,
+}
+
+pub struct AddBanRequest {
+    // `ip`, `cidr`, `peer_id` (in hex) or `passkey`.
+    pub kind: String,
+    pub value: String,
+    // Sent to the banned peer as the failure reason.
+    pub reason: String,
+    // Never, if not set.
+    pub expires_in: Option<std::time::Duration>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct BanEntry {
+    pub id: u64,
+    pub kind: &'static str,
+    pub value: String,
+    pub reason: String,
+    // Unix times.
+    pub created: i64,
+    pub expires: Option<i64>,
+}
+
+// Addresses refused for a while for sending invalid requests, apart from the
+// bans.
+#[derive(Debug, serde::Serialize)]
+pub struct TimeoutList {
+    pub timeouts: Vec<TimeoutEntry>,
+    pub counts: OffenseCounts,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct TimeoutEntry {
+    pub ip: IpAddr,
+    // Unix time.
+    pub until: i64,
+}
+
+// The passkey a user should announce with from now on.
+#[derive(Debug, serde::Serialize)]
+pub struct PasskeyEntry {
+    pub user_id: String,
+    pub passkey: String,
+    // When the ones before it stop working, as a Unix time.
+    pub previous_expire: i64,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct MaintenanceStatus {
+    pub enabled: bool,
+    // What clients are told while it is.
+    pub reason: String,
+    // In minutes.
+    pub retry_in: u32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ListAuditRequest {
+    // The default page size if not set.
+    pub limit: Option<usize>,
+    // Only entries older than this id, for the next page.
+    pub before: Option<u64>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct AuditEntry {
+    pub id: u64,
+    // Unix time.
+    pub timestamp: i64,
+    pub token_id: Option<String>,
+    pub action: String,
+    pub target: String,
+    pub outcome: String,
+}
+
+impl From<AuditRecord> for AuditEntry {
+    fn from(record: AuditRecord) -> Self {
+        Self {
+            id: record.id,
+            timestamp: record.timestamp.unix_timestamp(),
+            token_id: record.token_id,
+            action: record.action,
+            target: record.target,
+            outcome: record.outcome,
+        }
+    }
+}
+
+impl From<Ban> for BanEntry {
+    fn from(ban: Ban) -> Self {
+        Self {
+            id: ban.id,
+            kind: ban.target.kind(),
+            value: ban.target.value(),
+            reason: ban.reason,
+            created: ban.created.unix_timestamp(),
+            expires: ban.expires.map(|e| e.unix_timestamp()),
+        }
+    }
+}
+
 impl AdminService {
-    pub fn new(config: &Config, info_hash_repository: Arc<dyn InfoHashRepository>) -> Self {
+    pub fn new(config: &Config, services: &Services) -> Self {
         let config = config.clone();
+        let clock = services.clock.clone();
+        let pseudonymizer = config.privacy.pseudonymize.then(|| {
+            Pseudonymizer::new(std::time::Duration::from_secs(config.privacy.salt_rotation))
+                .with_clock(move || clock())
+        });
 
         Self {
+            pseudonymizer,
             config,
-            info_hash_repository,
+            peer_repository: services.peer_repository.clone(),
+            info_hash_repository: services.info_hash_repository.clone(),
+            bans: services.bans.clone(),
+            audit: services.audit.clone(),
+            offenders: services.offenders.clone(),
+            maintenance: services.maintenance.clone(),
+            passkeys: services.passkeys.clone(),
+            clock: services.clock.clone(),
+        }
+    }
+
+    fn active_after(&self) -> OffsetDateTime {
+        (self.clock)() - std::time::Duration::from_secs(self.config.activity_timeout() as u64)
+    }
+
+    pub async fn list_torrents(&self, request: ListTorrentsRequest) -> Result<TorrentList, Error> {
+        if !self.config.enable_admin_api {
+            return Err(Error::NotAllowed);
+        }
+
+        let mut filter = request.filter;
+        if let Some(prefix) = &mut filter.info_hash_prefix {
+            prefix.make_ascii_lowercase();
+            if prefix.len() > 40 || !prefix.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return Err(Error::InvalidInfoHash(prefix.clone()));
+            }
+        }
+        let mut after = request
+            .cursor
+            .as_deref()
+            .map(|cursor| decode_cursor(request.order, cursor))
+            .transpose()?;
+        let limit = request
+            .limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE);
+        let active_after = self.active_after();
+
+        // Backends that cannot filter by status leave it to be done here, so
+        // a page of theirs may not fill one of ours.
+        let mut torrents = vec![];
+        let mut more = false;
+        while !more {
+            let page = self
+                .peer_repository
+                .page_swarms(PageSwarms {
+                    active_after,
+                    filter: &filter,
+                    order: request.order,
+                    after: after.as_ref(),
+                    limit,
+                })
+                .await
+                .unwrap();
+            let last_page = page.len() < limit;
+
+            for swarm in page {
+                if torrents.len() == limit {
+                    more = true;
+                    break;
+                }
+                after = Some(SwarmPosition::of(&swarm));
+
+                let summary = self
+                    .info_hash_repository
+                    .get_info_hash_summary(GetInfoHashSummary {
+                        info_hash: &swarm.info_hash,
+                    })
+                    .await
+                    .unwrap();
+                if filter.status.as_ref().is_some_and(|s| *s != summary.status) {
+                    continue;
+                }
+
+                torrents.push(TorrentEntry {
+                    info_hash: swarm.info_hash.to_hex(),
+                    seeders: swarm.statistics.complete,
+                    leechers: swarm.statistics.incomplete,
+                    name: summary.metadata.map(|m| m.name),
+                    snatches: swarm.statistics.downloaded,
+                    last_activity: swarm.last_activity.unix_timestamp(),
+                    policy: match summary.status {
+                        InfoHashStatus::Unknown => Policy::Default,
+                        InfoHashStatus::ExplicitAllow => Policy::Allowed,
+                        InfoHashStatus::ExplicitDeny => Policy::Denied,
+                    },
+                });
+            }
+
+            if last_page {
+                break;
+            }
+        }
+
+        let next_cursor = after
+            .filter(|_| more)
+            .map(|after| encode_cursor(request.order, &after));
+
+        Ok(TorrentList {
+            torrents,
+            next_cursor,
+        })
+    }
+
+    // Most recently announced first.
+    pub async fn list_peers(&self, hex_info_hash: &str) -> Result<Vec<PeerEntry>, Error> {
+        if !self.config.enable_admin_api {
+            return Err(Error::NotAllowed);
+        }
+
+        let info_hash = parse_info_hash(hex_info_hash)?;
+
+        let mut members = self
+            .peer_repository
+            .get_swarm_detail(GetSwarmDetail {
+                info_hash: &info_hash,
+                active_after: self.active_after(),
+            })
+            .await
+            .unwrap();
+        members.sort_by_key(|m| Reverse(m.last_announce));
+
+        let redact = self.config.admin_redact_peers;
+        let truncate = self.config.privacy.truncate_ips;
+        Ok(members
+            .into_iter()
+            .map(|m| peer_entry(m, redact, truncate))
+            .collect())
+    }
+
+    // Most peers first.
+    pub async fn list_clients(&self, hex_info_hash: &str) -> Result<Vec<ClientCount>, Error> {
+        if !self.config.enable_admin_api {
+            return Err(Error::NotAllowed);
+        }
+
+        let info_hash = parse_info_hash(hex_info_hash)?;
+
+        let members = self
+            .peer_repository
+            .get_swarm_detail(GetSwarmDetail {
+                info_hash: &info_hash,
+                active_after: self.active_after(),
+            })
+            .await
+            .unwrap();
+        let mut counts = HashMap::<_, u32>::new();
+        for member in members {
+            let mut client = ClientFingerprint::of(&member.peer_id);
+            // Counted together, as the prefixes of random ids are random.
+            if let Family::Unknown(_) = client.family {
+                client.family = Family::Unknown(String::new());
+            }
+            *counts.entry(client).or_default() += 1;
         }
+
+        let mut clients = counts
+            .into_iter()
+            .map(|(client, peers)| ClientCount {
+                client: client.family.to_string(),
+                version: client.version.map(|v| v.to_string()),
+                peers,
+            })
+            .collect::<Vec<_>>();
+        clients.sort_by(|a, b| {
+            (Reverse(a.peers), &a.client, &a.version).cmp(&(
+                Reverse(b.peers),
+                &b.client,
+                &b.version,
+            ))
+        });
+        Ok(clients)
     }
 
     pub async fn known_info_hash_command(
         &self,
         command: KnownInfoHashRequest,
+        caller: &Caller,
     ) -> Result<(), Error> {
         if !self.config.enable_admin_api {
             return Err(Error::NotAllowed);
         }
 
-        let info_hash = InfoHash::from_hex(command.hex_info_hash);
+        let info_hash = InfoHash::from_hex(&command.hex_info_hash);
 
         self.info_hash_repository
             .update_info_hash(UpdateInfoHash {
@@ -50,6 +462,396 @@ impl AdminService {
             .await
             .unwrap();
 
-        Ok(())
+        self.audited(caller, "update_info_hash", &command.hex_info_hash, Ok(()))
+            .await
+    }
+
+    // Allows announces for the torrent, in whitelist mode too.
+    pub async fn register_torrent(
+        &self,
+        hex_info_hash: &str,
+        caller: &Caller,
+    ) -> Result<(), Error> {
+        if !self.config.enable_admin_api {
+            return Err(Error::NotAllowed);
+        }
+
+        let result = match parse_info_hash(hex_info_hash) {
+            Ok(info_hash) => {
+                self.info_hash_repository
+                    .update_info_hash(UpdateInfoHash {
+                        info_hash: &info_hash,
+                        status: InfoHashStatus::ExplicitAllow,
+                    })
+                    .await
+                    .unwrap();
+                Ok(())
+            }
+            Err(e) => Err(e),
+        };
+
+        self.audited(caller, "register_torrent", hex_info_hash, result)
+            .await
+    }
+
+    // Registers every info hash of a .torrent file with its name and size.
+    pub async fn upload_torrent(
+        &self,
+        torrent: &[u8],
+        caller: &Caller,
+    ) -> Result<RegisteredTorrent, Error> {
+        if !self.config.enable_admin_api {
+            return Err(Error::NotAllowed);
+        }
+
+        let metainfo = match Metainfo::from_bytes(torrent) {
+            Ok(metainfo) => metainfo,
+            Err(e) => {
+                let result = Err(Error::InvalidTorrent(e.to_string()));
+                return self.audited(caller, "upload_torrent", "", result).await;
+            }
+        };
+        let metadata = TorrentMetadata {
+            name: metainfo.info.name.clone(),
+            size: metainfo.info.size(),
+        };
+
+        for info_hash in metainfo.info_hashes() {
+            self.info_hash_repository
+                .register_torrent(RegisterTorrent {
+                    info_hash,
+                    metadata: &metadata,
+                })
+                .await
+                .unwrap();
+        }
+
+        let registered = RegisteredTorrent {
+            info_hashes: metainfo.info_hashes().map(InfoHash::to_hex).collect(),
+            name: metadata.name,
+            size: metadata.size,
+        };
+        let target = registered.info_hashes.join(",");
+
+        self.audited(caller, "upload_torrent", &target, Ok(registered))
+            .await
+    }
+
+    // Forgets the torrent and its peers, so in whitelist mode announces for
+    // it are refused again.
+    pub async fn remove_torrent(&self, hex_info_hash: &str, caller: &Caller) -> Result<(), Error> {
+        if !self.config.enable_admin_api {
+            return Err(Error::NotAllowed);
+        }
+
+        let result = match parse_info_hash(hex_info_hash) {
+            Ok(info_hash) => {
+                self.info_hash_repository
+                    .update_info_hash(UpdateInfoHash {
+                        info_hash: &info_hash,
+                        status: InfoHashStatus::Unknown,
+                    })
+                    .await
+                    .unwrap();
+                self.peer_repository
+                    .purge_swarm(PurgeSwarm {
+                        info_hash: &info_hash,
+                    })
+                    .await
+                    .unwrap();
+                Ok(())
+            }
+            Err(e) => Err(e),
+        };
+
+        self.audited(caller, "remove_torrent", hex_info_hash, result)
+            .await
+    }
+
+    // Takes effect on the next announce or scrape.
+    pub async fn add_ban(
+        &self,
+        request: AddBanRequest,
+        caller: &Caller,
+    ) -> Result<BanEntry, Error> {
+        if !self.config.enable_admin_api {
+            return Err(Error::NotAllowed);
+        }
+
+        let target = match &self.pseudonymizer {
+            Some(pseudonymizer) => {
+                let pseudonym = pseudonymizer.pseudonym(request.value.as_bytes());
+                format!("{} {pseudonym}", request.kind)
+            }
+            None => format!("{} {}", request.kind, request.value),
+        };
+        let parsed = BanTarget::parse(&request.kind, &request.value)
+            .map_err(Error::InvalidBan)
+            .and_then(|target| {
+                let expires_in = request
+                    .expires_in
+                    .map(|d| {
+                        time::Duration::try_from(d).map_err(|e| Error::InvalidBan(e.to_string()))
+                    })
+                    .transpose()?;
+                Ok((target, expires_in))
+            });
+
+        let result = match parsed {
+            Ok((target, expires_in)) => {
+                let ban = self
+                    .bans
+                    .add(target, request.reason, expires_in)
+                    .await
+                    .unwrap();
+                Ok(BanEntry::from(ban))
+            }
+            Err(e) => Err(e),
+        };
+
+        self.audited(caller, "add_ban", &target, result).await
+    }
+
+    pub async fn remove_ban(&self, id: u64, caller: &Caller) -> Result<(), Error> {
+        if !self.config.enable_admin_api {
+            return Err(Error::NotAllowed);
+        }
+
+        let result = match self.bans.remove(id).await.unwrap() {
+            true => Ok(()),
+            false => Err(Error::UnknownBan(id)),
+        };
+
+        self.audited(caller, "remove_ban", &format!("ban {id}"), result)
+            .await
+    }
+
+    // Oldest first, without the expired ones.
+    pub fn list_bans(&self) -> Result<Vec<BanEntry>, Error> {
+        if !self.config.enable_admin_api {
+            return Err(Error::NotAllowed);
+        }
+
+        Ok(self.bans.active().into_iter().map(BanEntry::from).collect())
+    }
+
+    // Those lifting soonest first.
+    pub fn list_timeouts(&self) -> Result<TimeoutList, Error> {
+        if !self.config.enable_admin_api {
+            return Err(Error::NotAllowed);
+        }
+
+        let timeouts = self
+            .offenders
+            .timed_out()
+            .into_iter()
+            .map(|(ip, until)| TimeoutEntry {
+                ip: match self.config.privacy.truncate_ips {
+                    true => privacy::truncate(ip),
+                    false => ip,
+                },
+                until: until.unix_timestamp(),
+            })
+            .collect();
+
+        Ok(TimeoutList {
+            timeouts,
+            counts: self.offenders.counts(),
+        })
+    }
+
+    pub fn maintenance(&self) -> Result<MaintenanceStatus, Error> {
+        if !self.config.enable_admin_api {
+            return Err(Error::NotAllowed);
+        }
+
+        Ok(MaintenanceStatus {
+            enabled: self.maintenance.is_enabled(),
+            reason: self.config.maintenance.reason.clone(),
+            retry_in: self.config.maintenance.retry_in,
+        })
+    }
+
+    // Takes effect on the next announce or scrape, on every transport.
+    pub async fn set_maintenance(
+        &self,
+        enabled: bool,
+        caller: &Caller,
+    ) -> Result<MaintenanceStatus, Error> {
+        if !self.config.enable_admin_api {
+            return Err(Error::NotAllowed);
+        }
+
+        self.maintenance.set(enabled);
+        let target = match enabled {
+            true => "on",
+            false => "off",
+        };
+
+        self.audited(caller, "set_maintenance", target, self.maintenance())
+            .await
+    }
+
+    // The old passkeys keep working for the configured grace period, with a
+    // warning. Issues the user's first passkey too.
+    pub async fn rotate_passkey(
+        &self,
+        user_id: &str,
+        caller: &Caller,
+    ) -> Result<PasskeyEntry, Error> {
+        let grace = time::Duration::seconds(self.config.passkeys.grace_period as i64);
+        self.replace_passkey(user_id, grace, "rotate_passkey", caller)
+            .await
+    }
+
+    // For leaked passkeys, which stop working at once.
+    pub async fn revoke_passkey(
+        &self,
+        user_id: &str,
+        caller: &Caller,
+    ) -> Result<PasskeyEntry, Error> {
+        self.replace_passkey(user_id, time::Duration::ZERO, "revoke_passkey", caller)
+            .await
+    }
+
+    async fn replace_passkey(
+        &self,
+        user_id: &str,
+        grace: time::Duration,
+        action: &str,
+        caller: &Caller,
+    ) -> Result<PasskeyEntry, Error> {
+        if !self.config.enable_admin_api {
+            return Err(Error::NotAllowed);
+        }
+
+        let Passkey {
+            user_id,
+            passkey,
+            created,
+            ..
+        } = self.passkeys.rotate(user_id, grace).await.unwrap();
+        let target = format!("user {user_id}");
+        let entry = PasskeyEntry {
+            user_id,
+            passkey,
+            previous_expire: (created + grace).unix_timestamp(),
+        };
+
+        self.audited(caller, action, &target, Ok(entry)).await
+    }
+
+    // Newest first.
+    pub async fn list_audit(&self, request: ListAuditRequest) -> Result<Vec<AuditEntry>, Error> {
+        if !self.config.enable_admin_api {
+            return Err(Error::NotAllowed);
+        }
+
+        let limit = request
+            .limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE);
+        let records = self
+            .audit
+            .records(GetAudit {
+                limit,
+                before: request.before,
+            })
+            .await
+            .unwrap();
+
+        Ok(records.into_iter().map(AuditEntry::from).collect())
+    }
+
+    // Whether or not the change went through, it is on the record.
+    async fn audited<T>(
+        &self,
+        caller: &Caller,
+        action: &str,
+        target: &str,
+        result: Result<T, Error>,
+    ) -> Result<T, Error> {
+        let outcome = match &result {
+            Ok(_) => "ok".to_string(),
+            Err(e) => e.to_string(),
+        };
+        self.audit
+            .record(caller.token_id.as_deref(), action, target, &outcome)
+            .await
+            .unwrap();
+
+        result
+    }
+}
+
+fn parse_info_hash(hex_info_hash: &str) -> Result<InfoHash, Error> {
+    match hex::decode(hex_info_hash) {
+        Ok(bytes) if bytes.len() == 20 => Ok(InfoHash(bytes)),
+        _ => Err(Error::InvalidInfoHash(hex_info_hash.to_string())),
+    }
+}
+
+// Opaque to clients: the order, then the position of the last swarm listed.
+fn encode_cursor(order: SwarmOrder, after: &SwarmPosition) -> String {
+    let mut cursor = vec![order as u8];
+    cursor.extend(after.peers.to_be_bytes());
+    cursor.extend(after.last_activity.unix_timestamp_nanos().to_be_bytes());
+    cursor.extend(&after.info_hash.0);
+
+    hex::encode(cursor)
+}
+
+fn decode_cursor(order: SwarmOrder, cursor: &str) -> Result<SwarmPosition, Error> {
+    let cursor = hex::decode(cursor).map_err(|_| Error::InvalidCursor)?;
+
+    if cursor.len() != 41 || cursor[0] != order as u8 {
+        return Err(Error::InvalidCursor);
+    }
+    let peers = u32::from_be_bytes(cursor[1..5].try_into().unwrap());
+    let nanos = i128::from_be_bytes(cursor[5..21].try_into().unwrap());
+    let last_activity =
+        OffsetDateTime::from_unix_timestamp_nanos(nanos).map_err(|_| Error::InvalidCursor)?;
+
+    Ok(SwarmPosition {
+        peers,
+        last_activity,
+        info_hash: InfoHash(cursor[21..].to_vec()),
+    })
+}
+
+// Addresses are left out when redacted, or only show their network when
+// truncated.
+fn peer_entry(member: SwarmMember, redact: bool, truncate: bool) -> PeerEntry {
+    let client = match ClientFingerprint::of(&member.peer_id) {
+        ClientFingerprint {
+            family: Family::Unknown(_),
+            ..
+        } => None,
+        client => Some(client.to_string()),
+    };
+    fn visible<T>(value: T, redact: bool) -> Option<T> {
+        (!redact).then_some(value)
+    }
+    let shown = |ip| match truncate {
+        true => privacy::truncate(ip),
+        false => ip,
+    };
+
+    PeerEntry {
+        peer_id: visible(hex::encode(&member.peer_id.0), redact),
+        ip: visible(shown(member.ip), redact),
+        port: visible(member.port, redact),
+        other_endpoint: member
+            .other_endpoint
+            .filter(|_| !redact)
+            .map(|e| SocketAddr::new(shown(e.ip()), e.port())),
+        client,
+        uploaded: member.uploaded,
+        downloaded: member.downloaded,
+        left: member.left,
+        event: member.event.to_string(),
+        last_announce: member.last_announce.unix_timestamp(),
+        transport: member.transport,
+        connectable: member.connectable,
     }
 }
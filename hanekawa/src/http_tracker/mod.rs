@@ -1,4 +1,4 @@
 pub mod proto;
 mod service;
 
-pub use service::HttpTrackerService;
+pub use service::{encode_peers, HttpTrackerService};
@@ -1,60 +1,432 @@
-use hanekawa_common::types::{Event, InfoHash, Peer, PeerId, PeerStatistics};
+use hanekawa_common::{
+    repository,
+    types::{Event, InfoHash, Peer, PeerId, PeerKey, PeerStatistics},
+};
 
-use std::{collections::HashMap, fmt::Display};
+use bytes::Bytes;
+use serde::{Deserialize, Deserializer};
+use serde_bytes::ByteBuf;
+use std::{collections::HashMap, fmt::Display, net::IpAddr};
 
 #[derive(Debug)]
 pub enum Error {
+    // The query could not be read, and why.
+    InvalidRequest(String),
     ServerError(String),
     InfoHashNotAllowed(String),
+    // Not served under the torrent policy.
+    NotRegistered,
+    Banned(String),
+    // The reason, and when to come back in minutes.
+    Maintenance { reason: String, retry_in: u32 },
+    UnknownPasskey,
+    // At most this many info hashes may be scraped at once.
+    TooManyInfoHashes(usize),
+    // Seconds left before the peer may announce again.
+    TooSoon(u32),
+    // Seconds until the address may send another.
+    RateLimited(u32),
+    // Seconds until the address is heard again after too many invalid
+    // requests.
+    TimedOut(u32),
+    // Asked for a dictionary model peer list where only compact ones are
+    // sent.
+    CompactRequired,
+    FullScrapeDenied,
+    // From elsewhere than the peer, without its key.
+    KeyMismatch,
+    // Refused by the client policy, as its peer id fingerprints it.
+    ClientNotAllowed(String),
+    // At most this many peers are kept for one address.
+    TooManyPeers(u32),
+    // Seconds until the address may announce to another new torrent.
+    TooManyTorrents(u32),
     Other(String),
 }
 
 impl Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            Self::InvalidRequest(s) => f.write_str(s),
             Self::ServerError(s) => f.write_fmt(format_args!("server error: {s}")),
             Self::InfoHashNotAllowed(s) => f.write_fmt(format_args!("info hash not allowed: {s}")),
+            Self::NotRegistered => f.write_str("torrent not registered with this tracker"),
+            Self::Banned(s) => f.write_fmt(format_args!("banned: {s}")),
+            Self::Maintenance { reason, .. } => f.write_str(reason),
+            Self::UnknownPasskey => f.write_str("unregistered passkey"),
+            Self::TooManyInfoHashes(n) => {
+                f.write_fmt(format_args!("too many info hashes, at most {n}"))
+            }
+            Self::TooSoon(n) => f.write_fmt(format_args!("announced too soon, wait {n} seconds")),
+            Self::RateLimited(n) => {
+                f.write_fmt(format_args!("too many requests, retry in {n} seconds"))
+            }
+            Self::TimedOut(n) => f.write_fmt(format_args!(
+                "too many invalid requests, retry in {n} seconds"
+            )),
+            Self::CompactRequired => f.write_str("compact peer lists required, send compact=1"),
+            Self::FullScrapeDenied => {
+                f.write_str("full scrapes are not served, ask for info hashes")
+            }
+            Self::KeyMismatch => f.write_str("key does not match the peer's"),
+            Self::ClientNotAllowed(s) => f.write_fmt(format_args!("client not allowed: {s}")),
+            Self::TooManyPeers(n) => f.write_fmt(format_args!(
+                "too many peers from your address, at most {n}"
+            )),
+            Self::TooManyTorrents(n) => f.write_fmt(format_args!(
+                "too many new torrents from your address, retry in {n} seconds"
+            )),
             Self::Other(s) => f.write_fmt(format_args!("error: {s}")),
         }
     }
 }
 
+// The store logs what went wrong, which is none of the client's business.
+impl From<repository::Error> for Error {
+    fn from(_: repository::Error) -> Self {
+        Self::ServerError("storage unavailable".to_string())
+    }
+}
+
+impl Error {
+    // BEP 31: when asking again may get another answer, per kind of failure.
+    pub fn retry_in(&self) -> Option<RetryIn> {
+        match self {
+            Self::InvalidRequest(_)
+            | Self::InfoHashNotAllowed(_)
+            | Self::NotRegistered
+            | Self::Banned(_)
+            | Self::UnknownPasskey
+            | Self::TooManyInfoHashes(_)
+            | Self::CompactRequired
+            | Self::FullScrapeDenied
+            | Self::KeyMismatch
+            | Self::ClientNotAllowed(_) => Some(RetryIn::Never),
+            // Most often the store, which is soon back.
+            Self::ServerError(_) => Some(RetryIn::Minutes(1)),
+            Self::Maintenance { retry_in, .. } => Some(RetryIn::Minutes(*retry_in)),
+            Self::TooSoon(seconds)
+            | Self::RateLimited(seconds)
+            | Self::TimedOut(seconds)
+            | Self::TooManyTorrents(seconds) => Some(RetryIn::Minutes(seconds.div_ceil(60))),
+            // Once some of the address's other peers stop or go quiet,
+            // which is up to them.
+            Self::TooManyPeers(_) | Self::Other(_) => None,
+        }
+    }
+
+    // What kind of failure it is, for logs.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::InvalidRequest(_) => "invalid_request",
+            Self::ServerError(_) => "server_error",
+            Self::InfoHashNotAllowed(_) => "info_hash_not_allowed",
+            Self::NotRegistered => "not_registered",
+            Self::Banned(_) => "banned",
+            Self::Maintenance { .. } => "maintenance",
+            Self::UnknownPasskey => "unknown_passkey",
+            Self::TooManyInfoHashes(_) => "too_many_info_hashes",
+            Self::TooSoon(_) => "too_soon",
+            Self::RateLimited(_) => "rate_limited",
+            Self::TimedOut(_) => "timed_out",
+            Self::CompactRequired => "compact_required",
+            Self::FullScrapeDenied => "full_scrape_denied",
+            Self::KeyMismatch => "key_mismatch",
+            Self::ClientNotAllowed(_) => "client_not_allowed",
+            Self::TooManyPeers(_) => "too_many_peers",
+            Self::TooManyTorrents(_) => "too_many_torrents",
+            Self::Other(_) => "other",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryIn {
+    Minutes(u32),
+    // For failures that will not go away by asking again.
+    Never,
+}
+
+impl serde::Serialize for RetryIn {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Minutes(minutes) => serializer.serialize_u32(*minutes),
+            Self::Never => serializer.serialize_str("never"),
+        }
+    }
+}
+
+// Said with an announce that still went through, for clients to show.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Warning {
+    // As configured, for passkeys rotated away from.
+    PasskeyExpiring(String),
+    // The `ip` parameter, which the peer's address was not taken from.
+    IpIgnored,
+    // More peers were asked for than are handed out at once.
+    NumWantClamped(usize),
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::PasskeyExpiring(s) => f.write_str(s),
+            Self::IpIgnored => f.write_str("ip parameter ignored, announced from where you are"),
+            Self::NumWantClamped(n) => f.write_fmt(format_args!("numwant clamped to {n}")),
+        }
+    }
+}
+
+// Clients show a single message, so several are put in one.
+pub fn warning_message(warnings: &[Warning]) -> String {
+    let warnings = warnings.iter().map(Warning::to_string);
+    warnings.collect::<Vec<_>>().join("; ")
+}
+
+fn warnings<S: serde::Serializer>(warnings: &[Warning], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&warning_message(warnings))
+}
+
 #[derive(Debug, serde::Deserialize)]
 pub struct AnnounceRequest {
+    #[serde(deserialize_with = "info_hash")]
     pub info_hash: InfoHash,
+    #[serde(deserialize_with = "peer_id")]
     pub peer_id: PeerId,
+    #[serde(deserialize_with = "port")]
     pub port: u16,
     pub uploaded: u64,
     pub downloaded: u64,
     pub left: u64,
     #[serde(default)]
     pub event: Event,
+    // Both as in BEP 23, `compact` defaulting as configured.
     pub compact: Option<u8>,
+    pub no_peer_id: Option<u8>,
+    pub numwant: Option<u32>,
+    // BEP 7: IPv6 Tracker Extension, as an address or address:port.
+    pub ipv4: Option<String>,
+    pub ipv6: Option<String>,
+    // The peer's address, taken from trusted forwarders, and from others as
+    // `announced_ip` allows.
+    #[serde(default, deserialize_with = "ip")]
+    pub ip: Option<IpAddr>,
+    // Proves it is the same peer after its address changes.
+    pub key: Option<PeerKey>,
+    // From the path of `/<passkey>/announce` or `/announce/<passkey>`, not
+    // the query.
+    #[serde(skip)]
+    pub passkey: Option<String>,
+}
+
+// Info hashes and peer ids are sent as their raw 20 bytes, whatever else
+// they may be elsewhere.
+fn twenty_bytes<E: serde::de::Error>(name: &str, bytes: ByteBuf) -> Result<Vec<u8>, E> {
+    match bytes.len() {
+        20 => Ok(bytes.into_vec()),
+        n => Err(E::custom(format_args!("{name} is {n} bytes, not 20"))),
+    }
+}
+
+fn info_hash<'de, D: Deserializer<'de>>(d: D) -> Result<InfoHash, D::Error> {
+    twenty_bytes("info_hash", ByteBuf::deserialize(d)?).map(InfoHash)
 }
 
-#[derive(serde::Serialize, Debug, PartialEq)]
-#[serde(untagged)]
+fn info_hashes<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<InfoHash>, D::Error> {
+    Vec::<ByteBuf>::deserialize(d)?
+        .into_iter()
+        .map(|bytes| twenty_bytes("info_hash", bytes).map(InfoHash))
+        .collect()
+}
+
+fn peer_id<'de, D: Deserializer<'de>>(d: D) -> Result<PeerId, D::Error> {
+    twenty_bytes("peer_id", ByteBuf::deserialize(d)?).map(PeerId)
+}
+
+// Refused rather than ignored, so the client is told why.
+fn ip<'de, D: Deserializer<'de>>(d: D) -> Result<Option<IpAddr>, D::Error> {
+    let ip = String::deserialize(d)?;
+    match ip.parse() {
+        Ok(ip) => Ok(Some(ip)),
+        Err(_) => Err(serde::de::Error::custom(format_args!(
+            "ip is not an address: {ip}"
+        ))),
+    }
+}
+
+// Nobody can be reached on it.
+fn port<'de, D: Deserializer<'de>>(d: D) -> Result<u16, D::Error> {
+    match u16::deserialize(d)? {
+        0 => Err(serde::de::Error::custom("port is 0")),
+        port => Ok(port),
+    }
+}
+
+#[derive(Debug, PartialEq)]
 pub enum PeerData {
-    Compact(#[serde(with = "serde_bytes")] Vec<u8>),
-    Long(Vec<Peer>),
+    Compact(Bytes),
+    // Dictionaries of each peer, without `peer id` if the client sent
+    // `no_peer_id`.
+    Long { peers: Vec<Peer>, peer_ids: bool },
+}
+
+impl serde::Serialize for PeerData {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        // v6 addresses are bracketed, as they would be in a URL.
+        #[derive(serde::Serialize)]
+        struct Dict<'a> {
+            #[serde(rename = "peer id", skip_serializing_if = "Option::is_none")]
+            peer_id: Option<&'a PeerId>,
+            ip: String,
+            port: u16,
+        }
+
+        match self {
+            Self::Compact(bytes) => serializer.serialize_bytes(bytes),
+            Self::Long { peers, peer_ids } => serializer.collect_seq(peers.iter().map(|p| Dict {
+                peer_id: peer_ids.then_some(&p.peer_id),
+                ip: match p.ip {
+                    std::net::IpAddr::V4(ip) => ip.to_string(),
+                    std::net::IpAddr::V6(ip) => format!("[{ip}]"),
+                },
+                port: p.port,
+            })),
+        }
+    }
 }
 
 #[derive(serde::Serialize)]
 pub struct AnnounceResponse {
     pub interval: u32,
+    #[serde(rename = "min interval")]
+    pub min_interval: u32,
     pub peers: PeerData,
     pub peers6: PeerData,
     #[serde(flatten)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub stats: Option<PeerStatistics>,
+    #[serde(
+        rename = "warning message",
+        skip_serializing_if = "Vec::is_empty",
+        serialize_with = "warnings"
+    )]
+    pub warnings: Vec<Warning>,
 }
 
 #[derive(Debug, serde::Deserialize)]
 pub struct ScrapeRequest {
+    // None for a full scrape.
+    #[serde(default, deserialize_with = "info_hashes")]
     pub info_hash: Vec<InfoHash>,
+    #[serde(skip)]
+    pub passkey: Option<String>,
 }
 
 #[derive(Debug, serde::Serialize)]
 pub struct ScrapeResponse {
     pub files: HashMap<InfoHash, PeerStatistics>,
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use hanekawa_percent_encode::from_query_string;
+
+    #[test]
+    fn reads_raw_info_hashes_and_peer_ids() {
+        let info_hash = "%00%ff%20+%2b".to_string() + &"%aa".repeat(15);
+        let announce: AnnounceRequest = from_query_string(&format!(
+            "info_hash={info_hash}&peer_id=-qB4650-+23456789012\
+             &port=6881&uploaded=0&downloaded=0&left=0"
+        ))
+        .unwrap();
+
+        let mut expected = vec![0x00, 0xff, b' ', b'+', b'+'];
+        expected.extend_from_slice(&[0xaa; 15]);
+        assert_eq!(InfoHash(expected.clone()), announce.info_hash);
+        assert_eq!(PeerId(b"-qB4650-+23456789012".to_vec()), announce.peer_id);
+
+        let scrape: ScrapeRequest = from_query_string(&format!(
+            "info_hash={info_hash}&info_hash={}",
+            "%bb".repeat(20)
+        ))
+        .unwrap();
+        assert_eq!(
+            vec![InfoHash(expected), InfoHash(vec![0xbb; 20])],
+            scrape.info_hash
+        );
+    }
+
+    #[test]
+    fn refuses_malformed_fields() {
+        let error = from_query_string::<AnnounceRequest>(
+            "info_hash=%aa%aa&peer_id=-qB4650-123456789012&port=6881&uploaded=0&downloaded=0&left=0",
+        )
+        .unwrap_err();
+        assert_eq!("info_hash is 2 bytes, not 20", error.to_string());
+
+        let query = format!(
+            "info_hash={}&info_hash={}",
+            "%bb".repeat(20),
+            "%bb".repeat(21)
+        );
+        let error = from_query_string::<ScrapeRequest>(&query).unwrap_err();
+        assert_eq!("info_hash is 21 bytes, not 20", error.to_string());
+
+        let error = from_query_string::<AnnounceRequest>(&format!(
+            "info_hash={}&peer_id=-qB4650-123456789012&port=0&uploaded=0&downloaded=0&left=0",
+            "%aa".repeat(20)
+        ))
+        .unwrap_err();
+        assert_eq!("port is 0", error.to_string());
+
+        let error = from_query_string::<AnnounceRequest>(&format!(
+            "info_hash={}&peer_id=-qB4650-123456789012&port=6881&uploaded=0&downloaded=0&left=0\
+             &ip=example.com",
+            "%aa".repeat(20)
+        ))
+        .unwrap_err();
+        assert_eq!("ip is not an address: example.com", error.to_string());
+    }
+
+    #[test]
+    fn bencodes_warnings_as_one_message() {
+        let response = |warnings| AnnounceResponse {
+            interval: 1800,
+            min_interval: 900,
+            peers: PeerData::Compact(Bytes::new()),
+            peers6: PeerData::Compact(Bytes::new()),
+            stats: None,
+            warnings,
+        };
+        let bencoded = |warnings| hanekawa_bencode::to_bytes(&response(warnings)).unwrap();
+
+        assert_eq!(
+            &b"d8:intervali1800e12:min intervali900e5:peers0:6:peers60:e"[..],
+            bencoded(vec![])
+        );
+        assert_eq!(
+            &b"d8:intervali1800e12:min intervali900e5:peers0:6:peers60:\
+               15:warning message73:ip parameter ignored, announced from where you are; \
+               numwant clamped to 50e"[..],
+            bencoded(vec![Warning::IpIgnored, Warning::NumWantClamped(50)])
+        );
+    }
+
+    #[test]
+    fn says_when_to_retry_per_kind_of_failure() {
+        assert_eq!(Some(RetryIn::Never), Error::NotRegistered.retry_in());
+        assert_eq!(Some(RetryIn::Minutes(2)), Error::TooSoon(61).retry_in());
+        assert_eq!(Some(RetryIn::Minutes(1)), Error::RateLimited(1).retry_in());
+        assert_eq!(Some(RetryIn::Minutes(5)), Error::TimedOut(300).retry_in());
+        assert_eq!(
+            Some(RetryIn::Minutes(1)),
+            Error::ServerError("store down".to_string()).retry_in()
+        );
+
+        let bencoded = |retry_in| hanekawa_bencode::to_bytes(&retry_in).unwrap();
+        assert_eq!(&b"5:never"[..], bencoded(RetryIn::Never));
+        assert_eq!(&b"i30e"[..], bencoded(RetryIn::Minutes(30)));
+    }
+}
@@ -1,48 +1,56 @@
 use super::proto::{
-    AnnounceRequest, AnnounceResponse, Error, PeerData, ScrapeRequest, ScrapeResponse,
+    AnnounceRequest, AnnounceResponse, Error, PeerData, ScrapeRequest, ScrapeResponse, Warning,
+};
+
+use crate::{
+    interval::IntervalPolicy,
+    peer_selector::{PeerSelector, Requester},
+    task::UpdatePeerAnnounceTask,
+    trace,
 };
 
 use hanekawa_common::{
+    ban::BanCheck,
+    client::ClientFingerprint,
+    federation,
+    peer_limit::Refusal,
     repository::{
         info_hash::GetInfoHashSummary,
-        peer::{GetPeerStatistics, GetPeers, UpdatePeerAnnounce},
+        peer::{CheckPeerKey, GetPeerStatistics, GetPeers, UpdatePeerAnnounce},
     },
-    task::Task,
-    types::{InfoHashStatus, Peer},
-    Config, Services,
+    types::{Event, InfoHash, InfoHashStatus, Passkey, Peer, PeerId, Transport},
+    Config, FullScrape, KeyMismatch, Services,
 };
 
-use std::net::IpAddr;
-
-#[derive(Debug, serde::Serialize, serde::Deserialize)]
-struct UpdatePeerAnnounceTask {
-    cmd: UpdatePeerAnnounce,
-}
-
-#[typetag::serde]
-#[async_trait::async_trait]
-impl Task for UpdatePeerAnnounceTask {
-    async fn execute(&self, ctx: &Services) -> Option<()> {
-        ctx.peer_repository
-            .update_peer_announce(&self.cmd)
-            .await
-            .unwrap();
+use bytes::{BufMut, BytesMut};
+use std::{
+    cell::RefCell,
+    net::{IpAddr, SocketAddr},
+};
+use tracing::{field::Empty, Instrument};
 
-        Some(())
-    }
+thread_local! {
+    // Compact peer lists are split off of it, and the space reused once the
+    // responses they are in have been sent.
+    static COMPACT_PEERS: RefCell<BytesMut> = RefCell::new(BytesMut::with_capacity(16 * 1024));
 }
 
 #[derive(Clone)]
 pub struct HttpTrackerService {
     config: Config,
     services: Services,
+    selector: PeerSelector,
+    intervals: IntervalPolicy,
 }
 
 impl HttpTrackerService {
     pub fn new(config: &Config, services: Services) -> Self {
+        let clock = services.clock.clone();
         Self {
             config: config.clone(),
             services,
+            selector: PeerSelector::new(config),
+            intervals: IntervalPolicy::new(config).with_clock(move || clock()),
         }
     }
 
@@ -51,14 +59,68 @@ impl HttpTrackerService {
         announce: AnnounceRequest,
         sender_ip: IpAddr,
     ) -> Result<AnnounceResponse, Error> {
+        let span = tracing::info_span!(
+            "announce",
+            transport = "http",
+            info_hash = %announce.info_hash.to_hex(),
+            peer_id = trace::client(&announce.peer_id),
+            event = %announce.event.to_string(),
+            ip = %trace::ip(&self.config, sender_ip),
+            passkey = trace::redacted(announce.passkey.as_ref()),
+            key = trace::redacted(announce.key.as_ref()),
+            peers = Empty,
+            failure = Empty,
+        );
+
+        async {
+            let response = self.answer_announce(announce, sender_ip).await;
+            if let Err(e) = &response {
+                trace::failed(e.kind(), e);
+            }
+            response
+        }
+        .instrument(span)
+        .await
+    }
+
+    async fn answer_announce(
+        &self,
+        announce: AnnounceRequest,
+        sender_ip: IpAddr,
+    ) -> Result<AnnounceResponse, Error> {
+        let peer_ip = self.peer_ip(&announce, sender_ip);
+        // Bans of the address it sends from hold whatever address it gives.
+        for ip in [sender_ip, peer_ip] {
+            self.check_bans(BanCheck {
+                ip,
+                peer_id: Some(&announce.peer_id),
+                passkey: announce.passkey.as_deref(),
+            })?;
+        }
+        self.check_client(&announce.peer_id)?;
+        let passkey = self.check_passkey(announce.passkey.as_deref())?;
+        self.check_maintenance(Some(&announce.event))?;
+        let is_compact = announce
+            .compact
+            .map_or(self.config.compact.default, |c| c == 1);
+        if !is_compact && self.config.compact.required {
+            return Err(Error::CompactRequired);
+        }
+        self.intervals
+            .admit(&announce.info_hash, &announce.peer_id, &announce.event)
+            .map_err(Error::TooSoon)?;
+
+        if !self.services.torrent_policy.allows(&announce.info_hash) {
+            return Err(Error::NotRegistered);
+        }
+
         let info_hash_summary = self
             .services
             .info_hash_repository
             .get_info_hash_summary(GetInfoHashSummary {
                 info_hash: &announce.info_hash,
             })
-            .await
-            .unwrap();
+            .await?;
 
         if info_hash_summary.status == InfoHashStatus::ExplicitDeny
             || (self.config.only_allowed_info_hashes
@@ -68,40 +130,60 @@ impl HttpTrackerService {
             return Err(Error::InfoHashNotAllowed(st));
         }
 
+        let other_endpoint = self.other_endpoint(&announce, peer_ip, sender_ip);
         let cmd = UpdatePeerAnnounce {
             info_hash: announce.info_hash.clone(),
             peer_id: announce.peer_id.clone(),
-            ip: sender_ip,
+            ip: peer_ip,
             port: announce.port,
             uploaded: announce.uploaded,
             downloaded: announce.downloaded,
             left: announce.left,
-            event: announce.event,
-            update_timestamp: time::OffsetDateTime::now_utc(),
+            event: announce.event.clone(),
+            update_timestamp: (self.services.clock)(),
+            other_endpoint,
+            transport: Some(Transport::Http),
+            user_id: passkey.as_ref().map(|p| p.user_id.clone()),
+            key: announce.key.clone(),
         };
 
-        self.services
-            .task_queue
-            .enqueue(&UpdatePeerAnnounceTask { cmd })
-            .await;
+        if self.check_key(&cmd).await? && self.check_limits(&cmd).await? {
+            // Probed while the peer is around, which the prober limits to
+            // once in a while.
+            if let Some(prober) = &self.services.prober {
+                if cmd.event != Event::Stopped {
+                    prober.submit(SocketAddr::new(cmd.ip, cmd.port));
+                }
+            }
+            if let Some(federation) = &self.services.federation {
+                federation.forward(&cmd);
+            }
 
-        let active_after = time::OffsetDateTime::now_utc()
-            - std::time::Duration::from_secs(self.config.peer_activity_timeout as u64);
+            self.services
+                .task_queue
+                .enqueue(&UpdatePeerAnnounceTask { cmd })
+                .await;
+        }
 
-        let peers = self
-            .services
-            .peer_repository
-            .get_peers(GetPeers {
-                info_hash: &announce.info_hash,
-                active_after: Some(active_after),
-            })
-            .await
-            .unwrap();
+        let active_after = (self.services.clock)()
+            - std::time::Duration::from_secs(self.config.activity_timeout() as u64);
 
-        let peers = peers.into_iter().filter(|p| p.ip != sender_ip).collect();
+        // A peer on its way out has no use for others.
+        let num_want = match announce.event {
+            Event::Stopped => 0,
+            _ => self.selector.num_want(announce.numwant),
+        };
+        let peers = match num_want {
+            0 => vec![],
+            _ => {
+                self.peers(&announce, peer_ip, active_after, num_want)
+                    .await?
+            }
+        };
+        trace::answered(peers.len());
 
-        let is_compact = announce.compact.unwrap_or(1) == 1;
-        let (peers, peers6) = encode_peers(peers, is_compact);
+        let peer_ids = announce.no_peer_id.unwrap_or(0) == 0;
+        let (peers, peers6) = encode_peers(peers, is_compact, peer_ids);
 
         let stats = self
             .services
@@ -110,84 +192,490 @@ impl HttpTrackerService {
                 info_hashes: &[announce.info_hash.clone()],
                 active_after,
             })
-            .await
-            .unwrap()
+            .await?
             .get(&announce.info_hash)
             .cloned();
+        let intervals = self.intervals.interval(
+            &announce.info_hash,
+            &announce.peer_id,
+            &announce.event,
+            stats.as_ref().map_or(0, |s| s.complete + s.incomplete),
+        );
+
+        // Passkeys rotated away from still work for a while, with a warning
+        // to pass on to the user.
+        let mut warnings = vec![];
+        if passkey.is_some_and(|p| p.expires.is_some()) {
+            let warning = self.config.passkeys.warning.clone();
+            warnings.push(Warning::PasskeyExpiring(warning));
+        }
+        if announce.ip.is_some_and(|ip| ip.to_canonical() != peer_ip) {
+            warnings.push(Warning::IpIgnored);
+        }
+        if announce.numwant.is_some_and(|n| n as usize > num_want) && num_want > 0 {
+            warnings.push(Warning::NumWantClamped(num_want));
+        }
 
         Ok(AnnounceResponse {
-            interval: self.config.peer_announce_interval,
+            interval: intervals.interval,
+            min_interval: intervals.min_interval,
             peers,
             peers6,
             stats,
+            warnings,
         })
     }
 
-    pub async fn scrape(&self, request: ScrapeRequest) -> Result<ScrapeResponse, Error> {
-        let active_after = time::OffsetDateTime::now_utc()
-            - std::time::Duration::from_secs(self.config.peer_activity_timeout as u64);
+    pub async fn scrape(
+        &self,
+        request: ScrapeRequest,
+        sender_ip: IpAddr,
+    ) -> Result<ScrapeResponse, Error> {
+        let span = tracing::info_span!(
+            "scrape",
+            transport = "http",
+            info_hash = request.info_hash.first().map(InfoHash::to_hex),
+            info_hashes = request.info_hash.len(),
+            ip = %trace::ip(&self.config, sender_ip),
+            passkey = trace::redacted(request.passkey.as_ref()),
+            failure = Empty,
+        );
+
+        async {
+            let response = self.answer_scrape(request, sender_ip).await;
+            if let Err(e) = &response {
+                trace::failed(e.kind(), e);
+            }
+            response
+        }
+        .instrument(span)
+        .await
+    }
+
+    async fn answer_scrape(
+        &self,
+        mut request: ScrapeRequest,
+        sender_ip: IpAddr,
+    ) -> Result<ScrapeResponse, Error> {
+        self.check_scraper(request.passkey.as_deref(), sender_ip)?;
+        let max = self.config.scrape.max_info_hashes;
+        if request.info_hash.len() > max {
+            return Err(Error::TooManyInfoHashes(max));
+        }
+        // As if they were not asked for.
+        let policy = &self.services.torrent_policy;
+        request
+            .info_hash
+            .retain(|info_hash| policy.allows(info_hash));
+
+        let active_after = (self.services.clock)()
+            - std::time::Duration::from_secs(self.config.activity_timeout() as u64);
 
         let cmd = GetPeerStatistics {
             info_hashes: &request.info_hash,
             active_after,
         };
 
-        let files = self
+        let mut files = self
             .services
             .peer_repository
             .get_peer_statistics(cmd)
-            .await
-            .unwrap();
+            .await?;
+        if self.config.scrape.include_unknown {
+            for info_hash in request.info_hash {
+                files.entry(info_hash).or_default();
+            }
+        }
 
         Ok(ScrapeResponse { files })
     }
+
+    // Whether a scrape without info hashes may be answered, which is up to
+    // the caller as it takes a snapshot of every swarm.
+    pub fn admit_full_scrape(&self, passkey: Option<&str>, sender_ip: IpAddr) -> Result<(), Error> {
+        self.check_scraper(passkey, sender_ip)?;
+        match self.config.scrape.full_scrape {
+            FullScrape::Allow => Ok(()),
+            FullScrape::Deny => Err(Error::FullScrapeDenied),
+        }
+    }
+
+    fn check_scraper(&self, passkey: Option<&str>, sender_ip: IpAddr) -> Result<(), Error> {
+        self.check_bans(BanCheck {
+            ip: sender_ip,
+            peer_id: None,
+            passkey,
+        })?;
+        self.check_passkey(passkey)?;
+        self.check_maintenance(None)
+    }
+
+    async fn peers(
+        &self,
+        announce: &AnnounceRequest,
+        peer_ip: IpAddr,
+        active_after: time::OffsetDateTime,
+        num_want: usize,
+    ) -> Result<Vec<Peer>, Error> {
+        let peers = self
+            .services
+            .peer_repository
+            .get_peers(GetPeers {
+                info_hash: &announce.info_hash,
+                active_after: Some(active_after),
+            })
+            .await?;
+        let peers = match &self.services.federation {
+            Some(federation) => federation::merge(peers, federation.peers(&announce.info_hash)),
+            None => peers,
+        };
+
+        let requester = Requester {
+            peer_id: &announce.peer_id,
+            endpoint: SocketAddr::new(peer_ip, announce.port),
+            seeding: announce.left == 0,
+        };
+        Ok(self.selector.select(peers, &requester, num_want))
+    }
+
+    // The `ip` parameter, or failing it the BEP 7 one in the sender's own
+    // family, stands for the peer's address when another tracker forwards
+    // its announce, or as configured. Otherwise, or if it is no address or
+    // none that can be stored, the sender's is taken.
+    fn peer_ip(&self, announce: &AnnounceRequest, sender_ip: IpAddr) -> IpAddr {
+        let trusted = &self.config.federation.trusted_forwarders;
+        let own_family = match sender_ip.is_ipv4() {
+            true => announce.ipv4.as_deref(),
+            false => announce.ipv6.as_deref(),
+        };
+        let announced = announce.ip.or_else(|| {
+            own_family
+                .and_then(|value| endpoint(value, announce.port))
+                .filter(|e| e.is_ipv4() == sender_ip.is_ipv4())
+                .map(|e| e.ip())
+        });
+
+        match announced.map(|ip| ip.to_canonical()) {
+            Some(ip)
+                if storable(ip)
+                    && (trusted.contains(&sender_ip) || self.config.announced_ip.accepts(ip)) =>
+            {
+                ip
+            }
+            _ => sender_ip,
+        }
+    }
+
+    // The advertised endpoint in the family the request did not come over,
+    // as BEP 7 has it, trusted as the `ip` parameter is. Values in the
+    // peer's own family are left to `peer_ip`.
+    fn other_endpoint(
+        &self,
+        announce: &AnnounceRequest,
+        peer_ip: IpAddr,
+        sender_ip: IpAddr,
+    ) -> Option<SocketAddr> {
+        let value = if peer_ip.is_ipv4() {
+            announce.ipv6.as_deref()?
+        } else {
+            announce.ipv4.as_deref()?
+        };
+
+        let endpoint = endpoint(value, announce.port)?;
+        let trusted = &self.config.federation.trusted_forwarders;
+        let accepted =
+            trusted.contains(&sender_ip) || self.config.announced_ip.accepts(endpoint.ip());
+        (endpoint.is_ipv4() != peer_ip.is_ipv4() && storable(endpoint.ip()) && accepted)
+            .then_some(endpoint)
+    }
+
+    // Whether the announce is the peer's to record. Those from elsewhere
+    // without its key are not, and are rejected if so configured.
+    async fn check_key(&self, cmd: &UpdatePeerAnnounce) -> Result<bool, Error> {
+        let matches = self
+            .services
+            .peer_repository
+            .check_peer_key(CheckPeerKey {
+                info_hash: &cmd.info_hash,
+                peer_id: &cmd.peer_id,
+                ip: cmd.ip,
+                key: cmd.key.as_ref(),
+            })
+            .await?;
+
+        match (matches, self.config.key_mismatch) {
+            (true, _) => Ok(true),
+            (false, KeyMismatch::Ignore) => Ok(false),
+            (false, KeyMismatch::Reject) => Err(Error::KeyMismatch),
+        }
+    }
+
+    // Whether the announce is to be recorded under the peer limits. Those
+    // that would go past the swarm's are answered all the same.
+    async fn check_limits(&self, cmd: &UpdatePeerAnnounce) -> Result<bool, Error> {
+        let repository = &*self.services.peer_repository;
+        match self.services.peer_limits.admit(repository, cmd).await {
+            Ok(keep) => Ok(keep),
+            Err(Refusal::TooManyPeers(n)) => Err(Error::TooManyPeers(n)),
+            Err(Refusal::TooManyTorrents(seconds)) => Err(Error::TooManyTorrents(seconds)),
+            Err(Refusal::Storage(e)) => Err(e.into()),
+        }
+    }
+
+    fn check_client(&self, peer_id: &PeerId) -> Result<(), Error> {
+        let policy = &self.config.clients;
+        if policy.allow.is_empty() && policy.deny.is_empty() {
+            return Ok(());
+        }
+
+        let client = ClientFingerprint::of(peer_id);
+        match policy.admits(&client) {
+            true => Ok(()),
+            false => Err(Error::ClientNotAllowed(client.to_string())),
+        }
+    }
+
+    fn check_bans(&self, check: BanCheck<'_>) -> Result<(), Error> {
+        match self.services.bans.find(check) {
+            Some(ban) => Err(Error::Banned(ban.reason)),
+            None => Ok(()),
+        }
+    }
+
+    // Whose passkey it is, if they are required. Open trackers take no
+    // notice of them.
+    fn check_passkey(&self, passkey: Option<&str>) -> Result<Option<Passkey>, Error> {
+        if !self.config.passkeys.required {
+            return Ok(None);
+        }
+
+        match passkey.and_then(|p| self.services.passkeys.find(p)) {
+            Some(passkey) => Ok(Some(passkey)),
+            None => Err(Error::UnknownPasskey),
+        }
+    }
+
+    // Only peers leaving get through while the tracker is drained.
+    fn check_maintenance(&self, event: Option<&Event>) -> Result<(), Error> {
+        if !self.services.maintenance.is_enabled() || event == Some(&Event::Stopped) {
+            return Ok(());
+        }
+
+        Err(Error::Maintenance {
+            reason: self.config.maintenance.reason.clone(),
+            retry_in: self.config.maintenance.retry_in,
+        })
+    }
 }
 
-fn encode_peers(peers: Vec<Peer>, is_compact: bool) -> (PeerData, PeerData) {
-    if is_compact {
-        use bytes::{BufMut, BytesMut};
+// `addr` or `addr:port`, with the announced port unless given. Scoped v6
+// addresses only mean something on the peer's own link.
+fn endpoint(value: &str, port: u16) -> Option<SocketAddr> {
+    let endpoint = match value.parse::<SocketAddr>() {
+        Ok(SocketAddr::V6(e)) if e.scope_id() != 0 => return None,
+        Ok(e) => e,
+        Err(_) => (value.parse::<IpAddr>().ok()?, port).into(),
+    };
+
+    Some(SocketAddr::new(
+        endpoint.ip().to_canonical(),
+        endpoint.port(),
+    ))
+}
 
-        let mut peers_bytes = BytesMut::new();
-        let mut peers6_bytes = BytesMut::new();
+// Addresses nobody else could reach the peer at.
+fn storable(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => !(ip.is_unspecified() || ip.is_broadcast() || ip.is_multicast()),
+        IpAddr::V6(ip) => {
+            let link_local = ip.segments()[0] & 0xffc0 == 0xfe80;
+            !(ip.is_unspecified() || ip.is_multicast() || link_local)
+        }
+    }
+}
 
-        for peer in peers {
-            match peer.ip {
-                IpAddr::V4(ip) => {
-                    let ip_bytes: u32 = ip.into();
-                    peers_bytes.put_u32(ip_bytes);
-                    peers_bytes.put_u16(peer.port);
+// Peer ids are only ever in dictionary model peer lists.
+pub fn encode_peers(peers: Vec<Peer>, is_compact: bool, peer_ids: bool) -> (PeerData, PeerData) {
+    if is_compact {
+        COMPACT_PEERS.with_borrow_mut(|buf| {
+            let v4 = peers.iter().filter(|p| p.ip.is_ipv4()).count();
+            buf.reserve(v4 * 6 + (peers.len() - v4) * 18);
+
+            for peer in &peers {
+                if let IpAddr::V4(ip) = peer.ip {
+                    buf.put_u32(ip.into());
+                    buf.put_u16(peer.port);
                 }
-                IpAddr::V6(ip) => {
-                    let ip_bytes: u128 = ip.into();
-                    peers6_bytes.put_u128(ip_bytes);
-                    peers6_bytes.put_u16(peer.port);
+            }
+            let peers_bytes = buf.split().freeze();
+            for peer in &peers {
+                if let IpAddr::V6(ip) = peer.ip {
+                    buf.put_u128(ip.into());
+                    buf.put_u16(peer.port);
                 }
             }
-        }
+            let peers6_bytes = buf.split().freeze();
 
-        (
-            PeerData::Compact(peers_bytes.to_vec()),
-            PeerData::Compact(peers6_bytes.to_vec()),
-        )
+            (
+                PeerData::Compact(peers_bytes),
+                PeerData::Compact(peers6_bytes),
+            )
+        })
     } else {
         let (peers, peers6) = peers.into_iter().partition::<Vec<_>, _>(|p| p.ip.is_ipv4());
 
-        (PeerData::Long(peers), PeerData::Long(peers6))
+        (
+            PeerData::Long { peers, peer_ids },
+            PeerData::Long {
+                peers: peers6,
+                peer_ids,
+            },
+        )
     }
 }
 
 #[cfg(test)]
 mod test {
-    use hanekawa_common::types::PeerId;
+    use hanekawa_common::{
+        audit::AuditLog,
+        ban::BanList,
+        maintenance::Maintenance,
+        offense::Offenders,
+        passkey::Passkeys,
+        peer_limit::PeerLimits,
+        repository::{
+            info_hash::{InfoHashRepository, RegisterTorrent, UpdateInfoHash},
+            memory::MemoryStore,
+            peer::{GetSwarmDetail, IterSwarms, PeerRepository, PurgeSwarm, SetConnectable},
+            Error as RepositoryError,
+        },
+        task::{Task, TaskQueue},
+        torrent_policy::TorrentPolicy,
+        types::{
+            InfoHash, InfoHashSummary, PeerId, PeerSource, PeerStatistics, SwarmMember,
+            SwarmSummary,
+        },
+        AnnouncedIp, InvalidRequestConfig,
+    };
+
+    use super::{super::proto::RetryIn, *};
+    use std::{
+        collections::HashMap,
+        net::{Ipv4Addr, Ipv6Addr},
+        sync::Arc,
+    };
+
+    struct Unreachable;
+
+    fn unreachable<T>() -> Result<T, RepositoryError> {
+        Err(RepositoryError::Backend("connection refused".to_string()))
+    }
+
+    #[async_trait::async_trait]
+    impl InfoHashRepository for Unreachable {
+        async fn get_info_hash_summary(
+            &self,
+            _cmd: GetInfoHashSummary<'_>,
+        ) -> Result<InfoHashSummary, RepositoryError> {
+            unreachable()
+        }
+
+        async fn update_info_hash(&self, _cmd: UpdateInfoHash<'_>) -> Result<(), RepositoryError> {
+            unreachable()
+        }
+
+        async fn register_torrent(&self, _cmd: RegisterTorrent<'_>) -> Result<(), RepositoryError> {
+            unreachable()
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl PeerRepository for Unreachable {
+        async fn update_peer_announce(
+            &self,
+            _cmd: &UpdatePeerAnnounce,
+        ) -> Result<(), RepositoryError> {
+            unreachable()
+        }
+
+        async fn check_peer_key(&self, _cmd: CheckPeerKey<'_>) -> Result<bool, RepositoryError> {
+            unreachable()
+        }
 
-    use super::*;
-    use std::net::{Ipv4Addr, Ipv6Addr};
+        async fn get_peers(&self, _cmd: GetPeers<'_>) -> Result<Vec<Peer>, RepositoryError> {
+            unreachable()
+        }
+
+        async fn get_peer_statistics(
+            &self,
+            _cmd: GetPeerStatistics<'_>,
+        ) -> Result<HashMap<InfoHash, PeerStatistics>, RepositoryError> {
+            unreachable()
+        }
+
+        async fn iter_swarms(
+            &self,
+            _cmd: IterSwarms,
+        ) -> Result<Vec<SwarmSummary>, RepositoryError> {
+            unreachable()
+        }
+
+        async fn get_swarm_detail(
+            &self,
+            _cmd: GetSwarmDetail<'_>,
+        ) -> Result<Vec<SwarmMember>, RepositoryError> {
+            unreachable()
+        }
+
+        async fn purge_swarm(&self, _cmd: PurgeSwarm<'_>) -> Result<(), RepositoryError> {
+            unreachable()
+        }
+
+        async fn set_connectable(&self, _cmd: SetConnectable) -> Result<(), RepositoryError> {
+            unreachable()
+        }
+    }
+
+    struct DiscardingQueue;
+
+    #[async_trait::async_trait]
+    impl TaskQueue for DiscardingQueue {
+        async fn enqueue(&self, _task: &dyn Task) -> Option<()> {
+            Some(())
+        }
+    }
+
+    fn service(
+        peer_repository: Arc<dyn PeerRepository>,
+        info_hash_repository: Arc<dyn InfoHashRepository>,
+    ) -> HttpTrackerService {
+        let services = Services {
+            peer_repository,
+            info_hash_repository,
+            task_queue: Arc::new(DiscardingQueue),
+            bans: BanList::in_memory(),
+            audit: AuditLog::in_memory(),
+            prober: None,
+            federation: None,
+            offenders: Offenders::new(&InvalidRequestConfig::default()),
+            maintenance: Maintenance::default(),
+            passkeys: Passkeys::in_memory(),
+            torrent_policy: TorrentPolicy::open(),
+            peer_limits: PeerLimits::unlimited(),
+            clock: hanekawa_common::system_clock(),
+        };
+
+        HttpTrackerService::new(&Config::default(), services)
+    }
 
     fn ipv4_peer() -> Peer {
         Peer {
             peer_id: PeerId("012345678901234567890".as_bytes().to_vec()),
             ip: IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
             port: 5005,
+            connectable: None,
+            seeding: false,
+            source: PeerSource::Announce,
         }
     }
 
@@ -196,6 +684,9 @@ mod test {
             peer_id: PeerId("09876543210987654321".as_bytes().to_vec()),
             ip: IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 1)),
             port: 5005,
+            connectable: None,
+            seeding: false,
+            source: PeerSource::Announce,
         }
     }
 
@@ -203,25 +694,177 @@ mod test {
     fn encodes_compact_peers_if_compact() {
         let peers = vec![ipv4_peer(), ipv6_peer()];
 
-        let result = encode_peers(peers, true);
+        let result = encode_peers(peers, true, true);
 
         let bs4 = vec![127, 0, 0, 1, 19, 141];
         let bs6 = vec![0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 19, 141];
-        assert_eq!((PeerData::Compact(bs4), PeerData::Compact(bs6)), result);
+        assert_eq!(
+            (PeerData::Compact(bs4.into()), PeerData::Compact(bs6.into())),
+            result
+        );
+    }
+
+    fn announce(ipv4: Option<&str>, ipv6: Option<&str>) -> AnnounceRequest {
+        AnnounceRequest {
+            info_hash: InfoHash(vec![0; 20]),
+            peer_id: ipv4_peer().peer_id,
+            port: 6881,
+            uploaded: 0,
+            downloaded: 0,
+            left: 0,
+            event: Default::default(),
+            compact: None,
+            no_peer_id: None,
+            numwant: None,
+            ipv4: ipv4.map(String::from),
+            ipv6: ipv6.map(String::from),
+            ip: None,
+            key: None,
+            passkey: None,
+        }
+    }
+
+    // Told to come back in a while, whichever store is down.
+    #[tokio::test]
+    async fn answers_storage_errors_as_server_errors() {
+        let store = Arc::new(MemoryStore::new());
+        let sender = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+
+        for service in [
+            service(store.clone(), Arc::new(Unreachable)),
+            service(Arc::new(Unreachable), store),
+        ] {
+            let Err(error) = service.announce(announce(None, None), sender).await else {
+                panic!("announce answered without the store");
+            };
+            assert_eq!("server error: storage unavailable", error.to_string());
+            assert_eq!(Some(RetryIn::Minutes(1)), error.retry_in());
+        }
+
+        let scrape = ScrapeRequest {
+            info_hash: vec![InfoHash(vec![0; 20])],
+            passkey: None,
+        };
+        let service = service(Arc::new(Unreachable), Arc::new(Unreachable));
+        assert!(matches!(
+            service.scrape(scrape, sender).await,
+            Err(Error::ServerError(_))
+        ));
+    }
+
+    #[test]
+    fn takes_the_other_family_from_bep7_parameters() {
+        let v4: IpAddr = "192.0.2.1".parse().unwrap();
+        let v6: IpAddr = "2001:db8::1".parse().unwrap();
+        let store = Arc::new(MemoryStore::new());
+        let mut service = service(store.clone(), store);
+        service.config.announced_ip = AnnouncedIp::Any;
+
+        for (request, sender, expected) in [
+            (
+                announce(None, Some("2001:db8::2")),
+                v4,
+                Some("[2001:db8::2]:6881"),
+            ),
+            (
+                announce(None, Some("[2001:db8::2]:51413")),
+                v4,
+                Some("[2001:db8::2]:51413"),
+            ),
+            (
+                announce(Some("192.0.2.2:51413"), None),
+                v6,
+                Some("192.0.2.2:51413"),
+            ),
+            // Only the sender's own family, or garbage.
+            (announce(Some("192.0.2.2"), None), v4, None),
+            (announce(None, Some("192.0.2.2")), v4, None),
+            (announce(None, Some("[2001:db8::2")), v4, None),
+            // Or none anybody could reach.
+            (announce(None, Some("fe80::2")), v4, None),
+            (announce(None, Some("[2001:db8::2%1]:51413")), v4, None),
+            (announce(None, Some("::")), v4, None),
+            (announce(Some("224.0.0.1"), None), v6, None),
+            // A mapped v4 address is one.
+            (
+                announce(Some("[::ffff:192.0.2.2]:51413"), None),
+                v6,
+                Some("192.0.2.2:51413"),
+            ),
+        ] {
+            assert_eq!(
+                expected.map(|e| e.parse().unwrap()),
+                service.other_endpoint(&request, sender, sender),
+                "{request:?}"
+            );
+        }
+    }
+
+    // Only trusted forwarders may say where else a peer is, unless anyone
+    // may say where it is.
+    #[test]
+    fn ignores_bep7_parameters_from_untrusted_senders() {
+        let sender: IpAddr = "192.0.2.1".parse().unwrap();
+        let forwarder: IpAddr = "198.51.100.1".parse().unwrap();
+        let store = Arc::new(MemoryStore::new());
+        let mut service = service(store.clone(), store);
+        service.config.federation.trusted_forwarders = vec![forwarder];
+        let request = announce(None, Some("[2001:db8::2]:51413"));
+
+        assert_eq!(None, service.other_endpoint(&request, sender, sender));
+        assert_eq!(
+            Some("[2001:db8::2]:51413".parse().unwrap()),
+            service.other_endpoint(&request, sender, forwarder)
+        );
     }
 
     #[test]
     fn encodes_noncompact_peers_if_noncompact() {
         let peers = vec![ipv4_peer(), ipv6_peer()];
 
-        let result = encode_peers(peers, false);
+        let result = encode_peers(peers, false, true);
 
         assert_eq!(
             (
-                PeerData::Long(vec![ipv4_peer()]),
-                PeerData::Long(vec![ipv6_peer()])
+                PeerData::Long {
+                    peers: vec![ipv4_peer()],
+                    peer_ids: true
+                },
+                PeerData::Long {
+                    peers: vec![ipv6_peer()],
+                    peer_ids: true
+                }
             ),
             result
         );
     }
+
+    #[test]
+    fn leaves_peer_ids_out_if_asked() {
+        let bencoded = |peer_ids| {
+            let (peers, _) = encode_peers(vec![ipv4_peer()], false, peer_ids);
+            hanekawa_bencode::to_bytes(&peers).unwrap()
+        };
+        let peer_id = String::from_utf8(ipv4_peer().peer_id.0).unwrap();
+
+        assert_eq!(
+            format!(
+                "ld2:ip9:127.0.0.17:peer id{}:{peer_id}4:porti5005eee",
+                peer_id.len()
+            )
+            .into_bytes(),
+            bencoded(true)
+        );
+        assert_eq!(b"ld2:ip9:127.0.0.14:porti5005eee".to_vec(), bencoded(false));
+    }
+
+    #[test]
+    fn brackets_v6_addresses_in_dictionaries() {
+        let (_, peers6) = encode_peers(vec![ipv6_peer()], false, false);
+
+        assert_eq!(
+            b"ld2:ip5:[::1]4:porti5005eee".to_vec(),
+            hanekawa_bencode::to_bytes(&peers6).unwrap()
+        );
+    }
 }
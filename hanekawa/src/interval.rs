@@ -0,0 +1,381 @@
+use hanekawa_common::{
+    types::{Event, InfoHash, PeerId},
+    Config, IntervalRampConfig, IntervalScaleConfig,
+};
+
+use rand::Rng;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use time::{Duration, OffsetDateTime};
+
+type Clock = Arc<dyn Fn() -> OffsetDateTime + Send + Sync>;
+
+struct Announce {
+    // Of the peer to the swarm since it joined.
+    count: u32,
+    last: OffsetDateTime,
+    // What it was last sent.
+    min_interval: u32,
+}
+
+type Announces = HashMap<(InfoHash, PeerId), Announce>;
+
+// Peers counted at once. New ones past it get the interval of their swarm's
+// size until others leave.
+const MAX_TRACKED: usize = 1 << 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Intervals {
+    pub interval: u32,
+    pub min_interval: u32,
+}
+
+// The interval each announce is answered with.
+#[derive(Clone)]
+pub struct IntervalPolicy {
+    normal: u32,
+    min_interval: u32,
+    jitter_percent: u32,
+    enforce_min_interval: bool,
+    ramp: IntervalRampConfig,
+    scale: IntervalScaleConfig,
+    activity_timeout: Duration,
+    clock: Clock,
+    announces: Arc<Mutex<Announces>>,
+}
+
+impl IntervalPolicy {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            normal: config.peer_announce_interval,
+            min_interval: config.peer_min_announce_interval,
+            jitter_percent: config.interval_jitter_percent,
+            enforce_min_interval: config.enforce_min_interval,
+            ramp: config.interval_ramp.clone(),
+            scale: config.interval_scale.clone(),
+            activity_timeout: Duration::seconds(config.activity_timeout() as i64),
+            clock: Arc::new(OffsetDateTime::now_utc),
+            announces: Arc::default(),
+        }
+    }
+
+    pub fn with_clock(
+        mut self,
+        clock: impl Fn() -> OffsetDateTime + Send + Sync + 'static,
+    ) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    // Err with the seconds left to wait, if the announce comes too soon.
+    // Only regular announces are held to it, as the others come when
+    // something happens.
+    pub fn admit(&self, info_hash: &InfoHash, peer_id: &PeerId, event: &Event) -> Result<(), u32> {
+        if !self.enforce_min_interval || *event != Event::Interval {
+            return Ok(());
+        }
+
+        let now = (self.clock)();
+        let announces = self.announces.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(last) = announces.get(&(info_hash.clone(), peer_id.clone())) else {
+            return Ok(());
+        };
+        let elapsed = (now - last.last).whole_seconds().max(0) as u64;
+        match elapsed < last.min_interval as u64 / 2 {
+            true => Err((last.min_interval as u64 - elapsed) as u32),
+            false => Ok(()),
+        }
+    }
+
+    // `swarm_size` counts seeders and leechers both.
+    pub fn interval(
+        &self,
+        info_hash: &InfoHash,
+        peer_id: &PeerId,
+        event: &Event,
+        swarm_size: u32,
+    ) -> Intervals {
+        let mut announces = self.announces.lock().unwrap_or_else(|e| e.into_inner());
+        let mut announce = match self.ramp.enabled || self.enforce_min_interval {
+            true => self.count(&mut announces, info_hash, peer_id, event),
+            false => None,
+        };
+
+        let interval = self.jitter(self.ramped(announce.as_ref().map(|a| a.count), swarm_size));
+        // Longer for as long as the interval is scaled.
+        let scaled = self.scaled(swarm_size) as u64 * self.min_interval as u64;
+        let min_interval = (scaled / self.normal.max(1) as u64).min(interval as u64) as u32;
+        if let Some(announce) = &mut announce {
+            announce.min_interval = min_interval;
+        }
+
+        Intervals {
+            interval,
+            min_interval,
+        }
+    }
+
+    fn ramped(&self, announces: Option<u32>, swarm_size: u32) -> u32 {
+        if !self.ramp.enabled || swarm_size >= self.ramp.small_swarm {
+            return self.scaled(swarm_size);
+        }
+
+        let initial = self.ramp.initial_interval.min(self.normal);
+        let by_size = initial + (self.normal - initial) * swarm_size / self.ramp.small_swarm;
+        let by_announces = match announces {
+            Some(n) if n <= self.ramp.first_announces => {
+                initial.saturating_mul(1 << (n - 1).min(31))
+            }
+            _ => u32::MAX,
+        };
+
+        by_size.min(by_announces)
+    }
+
+    // The normal interval, or past the threshold longer by the peers over
+    // it, up to the max.
+    fn scaled(&self, swarm_size: u32) -> u32 {
+        let over = swarm_size.saturating_sub(self.scale.threshold);
+        if !self.scale.enabled || over == 0 {
+            return self.normal;
+        }
+
+        let max = self.scale.max_interval.max(self.normal);
+        (self.normal as f64 + over as f64 * self.scale.per_peer.max(0.0)).min(max as f64) as u32
+    }
+
+    fn jitter(&self, interval: u32) -> u32 {
+        let spread =
+            (interval as u64 * self.jitter_percent as u64 / 100).min(interval as u64) as u32;
+        match spread {
+            0 => interval,
+            _ => rand::thread_rng()
+                .gen_range(interval - spread..=interval.saturating_add(spread))
+                .max(1),
+        }
+    }
+
+    // Including this one, or None if there is no room to count it.
+    fn count<'a>(
+        &self,
+        announces: &'a mut Announces,
+        info_hash: &InfoHash,
+        peer_id: &PeerId,
+        event: &Event,
+    ) -> Option<&'a mut Announce> {
+        let now = (self.clock)();
+        let key = (info_hash.clone(), peer_id.clone());
+        if *event == Event::Stopped {
+            announces.remove(&key);
+            return None;
+        }
+
+        if !announces.contains_key(&key) && announces.len() >= MAX_TRACKED {
+            let active_after = now - self.activity_timeout;
+            announces.retain(|_, announce| announce.last > active_after);
+            if announces.len() >= MAX_TRACKED {
+                return None;
+            }
+        }
+
+        // Peers gone for longer than the activity timeout have left the
+        // swarm, and start over when they are back.
+        let announce = announces.entry(key).or_insert(Announce {
+            count: 0,
+            last: now,
+            min_interval: 0,
+        });
+        if now - announce.last > self.activity_timeout {
+            announce.count = 0;
+        }
+        announce.count = announce.count.saturating_add(1);
+        announce.last = now;
+
+        Some(announce)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::net::Ipv4Addr;
+
+    fn config() -> Config {
+        Config {
+            database_pool_size: 1,
+            bind_ip: Ipv4Addr::LOCALHOST,
+            http_bind_port: 0,
+            udp_bind_port: 0,
+            interval_jitter_percent: 0,
+            peer_activity_timeout: Some(3600),
+            interval_ramp: IntervalRampConfig {
+                enabled: true,
+                ..IntervalRampConfig::default()
+            },
+            ..Config::default()
+        }
+    }
+
+    fn policy() -> (IntervalPolicy, impl Fn(Duration)) {
+        let now = Arc::new(Mutex::new(OffsetDateTime::now_utc()));
+        let clock = now.clone();
+        let policy = IntervalPolicy::new(&config()).with_clock(move || *clock.lock().unwrap());
+
+        (policy, move |by| *now.lock().unwrap() += by)
+    }
+
+    #[test]
+    fn ramps_up_over_a_peers_first_announces() {
+        let (policy, advance) = policy();
+        let info_hash = InfoHash(vec![0xaa; 20]);
+        let peer_id = PeerId(vec![b'a'; 20]);
+        let interval = |event| policy.interval(&info_hash, &peer_id, event, 1).interval;
+
+        // Doubling, until the interval for a swarm of one is shorter.
+        assert_eq!(60, interval(&Event::Started));
+        assert_eq!(120, interval(&Event::Interval));
+        assert_eq!(234, interval(&Event::Interval));
+        assert_eq!(234, interval(&Event::Interval));
+
+        // Another peer in the same swarm starts over.
+        assert_eq!(
+            60,
+            policy
+                .interval(&info_hash, &PeerId(vec![b'b'; 20]), &Event::Started, 2)
+                .interval
+        );
+
+        advance(Duration::hours(2));
+        assert_eq!(60, interval(&Event::Started));
+        interval(&Event::Stopped);
+        assert_eq!(60, interval(&Event::Started));
+    }
+
+    #[test]
+    fn large_swarms_get_the_normal_interval() {
+        let (policy, _) = policy();
+        let info_hash = InfoHash(vec![0xaa; 20]);
+        let peer_id = PeerId(vec![b'a'; 20]);
+
+        assert_eq!(
+            1800,
+            policy
+                .interval(&info_hash, &peer_id, &Event::Started, 10)
+                .interval
+        );
+        assert_eq!(
+            1800,
+            policy
+                .interval(&info_hash, &peer_id, &Event::Interval, 500)
+                .interval
+        );
+
+        let mut cfg = config();
+        cfg.interval_ramp.enabled = false;
+        let policy = IntervalPolicy::new(&cfg);
+        assert_eq!(
+            1800,
+            policy
+                .interval(&info_hash, &peer_id, &Event::Started, 1)
+                .interval
+        );
+    }
+
+    #[test]
+    fn scales_large_swarms_up_to_the_max() {
+        let mut cfg = config();
+        cfg.interval_ramp.enabled = false;
+        cfg.interval_scale = IntervalScaleConfig {
+            enabled: true,
+            threshold: 1000,
+            per_peer: 0.5,
+            max_interval: 3600,
+        };
+        let policy = IntervalPolicy::new(&cfg);
+        let info_hash = InfoHash(vec![0xaa; 20]);
+        let intervals = |swarm_size| {
+            let i = policy.interval(
+                &info_hash,
+                &PeerId(vec![b'a'; 20]),
+                &Event::Started,
+                swarm_size,
+            );
+            (i.interval, i.min_interval)
+        };
+
+        assert_eq!((1800, 900), intervals(0));
+        assert_eq!((1800, 900), intervals(1000));
+        assert_eq!((1801, 900), intervals(1002));
+        assert_eq!((2300, 1150), intervals(2000));
+        assert_eq!((3600, 1800), intervals(4600));
+        assert_eq!((3600, 1800), intervals(u32::MAX));
+
+        // Peers of the largest swarms are not taken to have left early.
+        cfg.peer_activity_timeout = None;
+        assert_eq!(7200, cfg.activity_timeout());
+        cfg.interval_scale.enabled = false;
+        assert_eq!(3600, cfg.activity_timeout());
+    }
+
+    #[test]
+    fn spreads_intervals_out() {
+        let mut cfg = config();
+        cfg.interval_ramp.enabled = false;
+        cfg.interval_jitter_percent = 10;
+        let jittered = IntervalPolicy::new(&cfg);
+        let info_hash = InfoHash(vec![0xaa; 20]);
+
+        let intervals = (0..200u8)
+            .map(|i| jittered.interval(&info_hash, &PeerId(vec![i; 20]), &Event::Started, 10))
+            .collect::<Vec<_>>();
+        assert!(intervals
+            .iter()
+            .all(|i| (1620..=1980).contains(&i.interval) && i.min_interval == 900));
+        assert!(intervals
+            .iter()
+            .any(|i| i.interval != intervals[0].interval));
+
+        // The ramp's short intervals are not held to the min interval.
+        let (policy, _) = policy();
+        assert_eq!(
+            Intervals {
+                interval: 60,
+                min_interval: 60
+            },
+            policy.interval(&info_hash, &PeerId(vec![b'a'; 20]), &Event::Started, 1)
+        );
+    }
+
+    #[test]
+    fn refuses_announces_well_before_the_min_interval() {
+        let mut cfg = config();
+        cfg.interval_ramp.enabled = false;
+        cfg.enforce_min_interval = true;
+        let now = Arc::new(Mutex::new(OffsetDateTime::now_utc()));
+        let clock = now.clone();
+        let policy = IntervalPolicy::new(&cfg).with_clock(move || *clock.lock().unwrap());
+        let advance = |by| *now.lock().unwrap() += by;
+        let info_hash = InfoHash(vec![0xaa; 20]);
+        let peer_id = PeerId(vec![b'a'; 20]);
+
+        assert_eq!(Ok(()), policy.admit(&info_hash, &peer_id, &Event::Started));
+        policy.interval(&info_hash, &peer_id, &Event::Started, 10);
+
+        advance(Duration::seconds(100));
+        assert_eq!(
+            Err(800),
+            policy.admit(&info_hash, &peer_id, &Event::Interval)
+        );
+        // Events are not held to it.
+        assert_eq!(
+            Ok(()),
+            policy.admit(&info_hash, &peer_id, &Event::Completed)
+        );
+
+        advance(Duration::seconds(350));
+        assert_eq!(Ok(()), policy.admit(&info_hash, &peer_id, &Event::Interval));
+    }
+}
@@ -1,3 +1,8 @@
 pub mod admin;
 pub mod http_tracker;
+pub mod interval;
+pub mod peer_selector;
+pub mod stats;
+mod task;
+mod trace;
 pub mod udp_tracker;
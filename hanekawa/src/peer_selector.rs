@@ -0,0 +1,295 @@
+use hanekawa_common::{
+    types::{Peer, PeerId},
+    Config,
+};
+
+use rand::seq::SliceRandom;
+use std::net::SocketAddr;
+
+// The peer announcing, which is never handed itself.
+pub struct Requester<'a> {
+    pub peer_id: &'a PeerId,
+    pub endpoint: SocketAddr,
+    pub seeding: bool,
+}
+
+impl Requester<'_> {
+    // By endpoint too, as clients restarted with a new peer id still
+    // announce from the old one until it times out.
+    fn is(&self, peer: &Peer) -> bool {
+        peer.peer_id == *self.peer_id
+            || (peer.ip.to_canonical(), peer.port)
+                == (self.endpoint.ip().to_canonical(), self.endpoint.port())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PeerSelector {
+    default_num_want: usize,
+    max_num_want: usize,
+    connectable_weight: usize,
+    // Off only for tests, to hand peers out in the order given.
+    shuffle: bool,
+}
+
+impl PeerSelector {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            default_num_want: config.default_num_want as usize,
+            max_num_want: config.max_num_want as usize,
+            connectable_weight: config.probe.connectable_weight as usize,
+            shuffle: true,
+        }
+    }
+
+    // Resolve the number of peers to hand out, falling back to the default
+    // when the client did not ask for a specific amount.
+    pub fn num_want(&self, requested: Option<u32>) -> usize {
+        requested
+            .map(|n| n as usize)
+            .unwrap_or(self.default_num_want)
+            .min(self.max_num_want)
+    }
+
+    // At random, so peers joining a swarm do not all get the same others.
+    // Seeders have no use for other seeders, so they get leechers while
+    // there are enough, and leechers get seeders for half of what they want
+    // with other leechers for the rest. A swarm no bigger than that is
+    // handed out whole.
+    pub fn select(
+        &self,
+        mut peers: Vec<Peer>,
+        requester: &Requester,
+        num_want: usize,
+    ) -> Vec<Peer> {
+        peers.retain(|p| !requester.is(p));
+        if self.shuffle {
+            peers.shuffle(&mut rand::thread_rng());
+        }
+
+        let (seeders, leechers): (Vec<_>, Vec<_>) = peers.into_iter().partition(|p| p.seeding);
+        let seeders_wanted = match requester.seeding {
+            true => 0,
+            false => num_want.div_ceil(2),
+        };
+        let from_leechers = leechers
+            .len()
+            .min(num_want - seeders_wanted.min(seeders.len()));
+        let from_seeders = seeders.len().min(num_want - from_leechers);
+
+        let mut selected = self.by_connectable(seeders, from_seeders);
+        selected.extend(self.by_connectable(leechers, from_leechers));
+        selected
+    }
+
+    // Hands out `connectable_weight` connectable peers for every other one
+    // while there are both, and of the others those not probed yet before
+    // the unconnectable.
+    fn by_connectable(&self, mut peers: Vec<Peer>, num_want: usize) -> Vec<Peer> {
+        if self.connectable_weight == 0 {
+            peers.truncate(num_want);
+            return peers;
+        }
+
+        let (connectable, others): (Vec<_>, Vec<_>) =
+            peers.into_iter().partition(|p| p.connectable == Some(true));
+        let (unknown, unconnectable): (Vec<_>, Vec<_>) =
+            others.into_iter().partition(|p| p.connectable.is_none());
+        let mut connectable = connectable.into_iter();
+        let mut others = unknown.into_iter().chain(unconnectable);
+
+        let mut selected = Vec::with_capacity(num_want);
+        while selected.len() < num_want {
+            let before = selected.len();
+            let room = num_want - selected.len();
+            selected.extend(connectable.by_ref().take(self.connectable_weight.min(room)));
+            if selected.len() < num_want {
+                selected.extend(others.next());
+            }
+
+            if selected.len() == before {
+                break;
+            }
+        }
+
+        selected
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use hanekawa_common::types::PeerSource;
+    use std::net::Ipv4Addr;
+
+    fn selector() -> PeerSelector {
+        PeerSelector {
+            default_num_want: 50,
+            max_num_want: 200,
+            connectable_weight: 0,
+            shuffle: false,
+        }
+    }
+
+    fn peer(n: u8, connectable: Option<bool>) -> Peer {
+        Peer {
+            peer_id: PeerId(vec![n; 20]),
+            ip: Ipv4Addr::new(192, 0, 2, n).into(),
+            port: 6881,
+            connectable,
+            seeding: false,
+            source: PeerSource::Announce,
+        }
+    }
+
+    fn seeder(n: u8) -> Peer {
+        Peer {
+            seeding: true,
+            ..peer(n, None)
+        }
+    }
+
+    // Of none of the peers made by `peer`.
+    fn requester(seeding: bool) -> Requester<'static> {
+        static PEER_ID: PeerId = PeerId(Vec::new());
+        Requester {
+            peer_id: &PEER_ID,
+            endpoint: "198.51.100.1:6881".parse().unwrap(),
+            seeding,
+        }
+    }
+
+    fn selected(selector: &PeerSelector, peers: Vec<Peer>, num_want: usize) -> Vec<u8> {
+        let selected = selector.select(peers, &requester(false), num_want);
+        selected.iter().map(|p| p.peer_id.0[0]).collect()
+    }
+
+    #[test]
+    fn uses_default_num_want_if_unspecified() {
+        assert_eq!(50, selector().num_want(None));
+    }
+
+    #[test]
+    fn clamps_num_want_to_maximum() {
+        assert_eq!(200, selector().num_want(Some(1000)));
+        assert_eq!(0, selector().num_want(Some(0)));
+    }
+
+    #[test]
+    fn prefers_connectable_peers_by_weight() {
+        let peers = vec![
+            peer(1, Some(false)),
+            peer(2, None),
+            peer(3, Some(true)),
+            peer(4, None),
+            peer(5, Some(true)),
+            peer(6, Some(true)),
+        ];
+
+        assert_eq!(vec![1, 2, 3, 4], selected(&selector(), peers.clone(), 4));
+
+        let weighted = PeerSelector {
+            connectable_weight: 2,
+            ..selector()
+        };
+        assert_eq!(vec![3, 5, 2, 6], selected(&weighted, peers.clone(), 4));
+        assert_eq!(vec![3, 5, 2, 6, 4, 1], selected(&weighted, peers, 10));
+    }
+
+    #[test]
+    fn samples_the_swarm_at_random() {
+        let shuffled = PeerSelector {
+            shuffle: true,
+            ..selector()
+        };
+        let peers = (0..200).map(|n| peer(n, None)).collect::<Vec<_>>();
+
+        let samples = (0..4)
+            .map(|_| {
+                let mut sample = selected(&shuffled, peers.clone(), 50);
+                sample.sort();
+                sample.dedup();
+                assert_eq!(50, sample.len());
+                sample
+            })
+            .collect::<Vec<_>>();
+        assert!(samples.windows(2).any(|pair| pair[0] != pair[1]));
+        assert_eq!(200, selected(&shuffled, peers, 300).len());
+    }
+
+    #[test]
+    fn never_hands_out_the_requester() {
+        let shuffled = PeerSelector {
+            shuffle: true,
+            ..selector()
+        };
+        let mut peers = (1..=20).map(|n| peer(n, None)).collect::<Vec<_>>();
+        // The requester's other endpoint, and another peer behind its
+        // address.
+        peers.push(Peer {
+            port: 6882,
+            ..peer(1, None)
+        });
+        peers.push(Peer {
+            ip: Ipv4Addr::new(192, 0, 2, 2).into(),
+            port: 6882,
+            ..peer(21, None)
+        });
+
+        // Restarted with a new peer id from where peer 2 announced.
+        let requester = Requester {
+            peer_id: &PeerId(vec![1; 20]),
+            endpoint: "192.0.2.2:6881".parse().unwrap(),
+            seeding: false,
+        };
+        for num_want in [5, 50] {
+            let mut selected = shuffled
+                .select(peers.clone(), &requester, num_want)
+                .iter()
+                .map(|p| p.peer_id.0[0])
+                .collect::<Vec<_>>();
+            assert!(!selected.contains(&1) && !selected.contains(&2));
+            selected.sort();
+            selected.dedup();
+            assert_eq!(num_want.min(19), selected.len());
+        }
+        let selected = shuffled.select(peers, &requester, 50);
+        assert!(selected.iter().any(|p| p.peer_id.0[0] == 21));
+    }
+
+    #[test]
+    fn hands_out_complementary_peers() {
+        let shuffled = PeerSelector {
+            shuffle: true,
+            ..selector()
+        };
+        let peers = (0..100)
+            .map(|n| match n < 70 {
+                true => seeder(n),
+                false => peer(n, None),
+            })
+            .collect::<Vec<_>>();
+        let seeders = |selected: &[Peer]| selected.iter().filter(|p| p.seeding).count();
+
+        // A seeder gets no seeders while there are leechers enough.
+        let selected = shuffled.select(peers.clone(), &requester(true), 30);
+        assert_eq!((30, 0), (selected.len(), seeders(&selected)));
+        let selected = shuffled.select(peers.clone(), &requester(true), 50);
+        assert_eq!((50, 20), (selected.len(), seeders(&selected)));
+
+        // A leecher gets half of each.
+        let selected = shuffled.select(peers.clone(), &requester(false), 30);
+        assert_eq!((30, 15), (selected.len(), seeders(&selected)));
+        // Topped up with seeders when short of leechers.
+        let selected = shuffled.select(peers.clone(), &requester(false), 80);
+        assert_eq!((80, 50), (selected.len(), seeders(&selected)));
+
+        // Small swarms are handed out whole either way.
+        assert_eq!(
+            100,
+            shuffled.select(peers.clone(), &requester(true), 200).len()
+        );
+        assert_eq!(100, shuffled.select(peers, &requester(false), 200).len());
+    }
+}
@@ -0,0 +1,169 @@
+use hanekawa_common::{
+    client::ClientFingerprint,
+    repository::{
+        self,
+        info_hash::GetInfoHashSummary,
+        peer::{GetPeerStatistics, GetSwarmDetail},
+    },
+    types::{InfoHash, InfoHashStatus, Transport},
+    Config, Services,
+};
+
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap},
+    hash::{Hash, Hasher},
+};
+
+#[derive(Debug)]
+pub enum Error {
+    InvalidInfoHash(String),
+    // Torrents the tracker refuses are unknown too, so that which ones are
+    // cannot be told.
+    UnknownTorrent,
+    Storage(repository::Error),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidInfoHash(s) => f.write_fmt(format_args!("invalid info hash: {s}")),
+            Self::UnknownTorrent => f.write_str("unknown torrent"),
+            Self::Storage(_) => f.write_str("storage unavailable"),
+        }
+    }
+}
+
+impl From<repository::Error> for Error {
+    fn from(e: repository::Error) -> Self {
+        Self::Storage(e)
+    }
+}
+
+#[derive(Debug, Hash, serde::Serialize)]
+pub struct TorrentStats {
+    pub info_hash: String,
+    pub seeders: u32,
+    pub leechers: u32,
+    pub snatches: u32,
+    // Unix time of the latest announce, unless no peer is active.
+    pub last_activity: Option<i64>,
+    pub transports: Transports,
+    // Active peers by the client their peer ids name, `unknown` for those
+    // that name none.
+    pub clients: BTreeMap<String, u32>,
+    // From the .torrent file, if it was registered with one.
+    pub name: Option<String>,
+    pub size: Option<u64>,
+}
+
+// Active peers by how they last announced.
+#[derive(Debug, Default, Hash, serde::Serialize)]
+pub struct Transports {
+    pub http: u32,
+    pub udp: u32,
+    // Announced before transports were recorded.
+    pub unknown: u32,
+}
+
+impl TorrentStats {
+    // The same for as long as the stats are.
+    pub fn etag(&self) -> String {
+        let mut hasher = DefaultHasher::new();
+        self.hash(&mut hasher);
+        format!("\"{:016x}\"", hasher.finish())
+    }
+}
+
+#[derive(Clone)]
+pub struct StatsService {
+    config: Config,
+    services: Services,
+}
+
+impl StatsService {
+    pub fn new(config: &Config, services: Services) -> Self {
+        Self {
+            config: config.clone(),
+            services,
+        }
+    }
+
+    // Torrents are known while they have peers, or if registered.
+    pub async fn torrent(&self, hex_info_hash: &str) -> Result<TorrentStats, Error> {
+        let info_hash = match hex::decode(hex_info_hash) {
+            Ok(bytes) if bytes.len() == 20 => InfoHash(bytes),
+            _ => return Err(Error::InvalidInfoHash(hex_info_hash.to_string())),
+        };
+
+        let summary = self
+            .services
+            .info_hash_repository
+            .get_info_hash_summary(GetInfoHashSummary {
+                info_hash: &info_hash,
+            })
+            .await?;
+        if summary.status == InfoHashStatus::ExplicitDeny
+            || (self.config.only_allowed_info_hashes
+                && summary.status != InfoHashStatus::ExplicitAllow)
+        {
+            return Err(Error::UnknownTorrent);
+        }
+
+        let active_after = (self.services.clock)()
+            - std::time::Duration::from_secs(self.config.activity_timeout() as u64);
+
+        let statistics = self
+            .services
+            .peer_repository
+            .get_peer_statistics(GetPeerStatistics {
+                info_hashes: std::slice::from_ref(&info_hash),
+                active_after,
+            })
+            .await?
+            .remove(&info_hash);
+        let registered =
+            summary.status == InfoHashStatus::ExplicitAllow || summary.metadata.is_some();
+        let Some(statistics) = statistics.or_else(|| registered.then(Default::default)) else {
+            return Err(Error::UnknownTorrent);
+        };
+
+        let members = self
+            .services
+            .peer_repository
+            .get_swarm_detail(GetSwarmDetail {
+                info_hash: &info_hash,
+                active_after,
+            })
+            .await?;
+        let mut transports = Transports::default();
+        let mut clients = BTreeMap::new();
+        for member in &members {
+            match member.transport {
+                Some(Transport::Http) => transports.http += 1,
+                Some(Transport::Udp) => transports.udp += 1,
+                None => transports.unknown += 1,
+            }
+            let family = ClientFingerprint::of(&member.peer_id).family;
+            *clients.entry(family.to_string()).or_default() += 1;
+        }
+
+        let (name, size) = summary
+            .metadata
+            .map_or((None, None), |m| (Some(m.name), Some(m.size)));
+
+        Ok(TorrentStats {
+            info_hash: info_hash.to_hex(),
+            seeders: statistics.complete,
+            leechers: statistics.incomplete,
+            snatches: statistics.downloaded,
+            last_activity: members
+                .iter()
+                .map(|m| m.last_announce.unix_timestamp())
+                .max(),
+            transports,
+            clients,
+            name,
+            size,
+        })
+    }
+}
@@ -0,0 +1,20 @@
+use hanekawa_common::{repository::peer::UpdatePeerAnnounce, task::Task, Services};
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub(crate) struct UpdatePeerAnnounceTask {
+    pub(crate) cmd: UpdatePeerAnnounce,
+}
+
+#[typetag::serde]
+#[async_trait::async_trait]
+impl Task for UpdatePeerAnnounceTask {
+    // The peer announces again soon enough, so a failed update is dropped.
+    async fn execute(&self, ctx: &Services) -> Option<()> {
+        if let Err(e) = ctx.peer_repository.update_peer_announce(&self.cmd).await {
+            tracing::warn!(error = %e, "dropped a peer announce update");
+            return None;
+        }
+
+        Some(())
+    }
+}
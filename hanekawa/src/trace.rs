@@ -0,0 +1,37 @@
+use hanekawa_common::{privacy, types::PeerId, Config};
+
+use std::{fmt::Display, net::IpAddr};
+use tracing::Span;
+
+// Only the client's prefix, as the rest would tell one peer from another
+// across logs.
+pub fn client(peer_id: &PeerId) -> &str {
+    peer_id.client_prefix().unwrap_or("unknown")
+}
+
+// Truncated as the admin API has them, if so configured.
+pub fn ip(config: &Config, ip: IpAddr) -> IpAddr {
+    match config.privacy.truncate_ips {
+        true => privacy::truncate(ip),
+        false => ip,
+    }
+}
+
+// Whether there was one, as anyone reading it could announce as its owner.
+pub fn redacted<T>(secret: Option<T>) -> &'static str {
+    match secret {
+        Some(_) => "[redacted]",
+        None => "none",
+    }
+}
+
+// On the span of the request being answered.
+pub fn answered(peers: usize) {
+    Span::current().record("peers", peers);
+    tracing::debug!(peers, "answered");
+}
+
+pub fn failed(failure: &'static str, reason: &dyn Display) {
+    Span::current().record("failure", failure);
+    tracing::info!(failure, %reason, "refused");
+}
@@ -0,0 +1,90 @@
+use ring::{hmac, rand::SystemRandom};
+use std::{net::IpAddr, sync::Arc};
+use time::OffsetDateTime;
+
+type Clock = Arc<dyn Fn() -> OffsetDateTime + Send + Sync>;
+
+// Ids are taken in the window they were issued in and the one after, so
+// for one to two minutes.
+const WINDOW_SECONDS: i64 = 60;
+
+// Connection ids as an HMAC of the client's address and when they were
+// issued, so that none have to be kept. A client cannot announce from an
+// address it does not receive at.
+#[derive(Clone)]
+pub struct ConnectionIds {
+    key: hmac::Key,
+    clock: Clock,
+}
+
+impl ConnectionIds {
+    pub fn new() -> Self {
+        Self {
+            key: hmac::Key::generate(hmac::HMAC_SHA256, &SystemRandom::new()).unwrap(),
+            clock: Arc::new(OffsetDateTime::now_utc),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn with_clock(
+        mut self,
+        clock: impl Fn() -> OffsetDateTime + Send + Sync + 'static,
+    ) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    pub fn issue(&self, ip: IpAddr) -> i64 {
+        self.id(ip, self.window())
+    }
+
+    pub fn check(&self, ip: IpAddr, id: i64) -> bool {
+        let window = self.window();
+        [window, window - 1]
+            .into_iter()
+            .any(|w| self.id(ip, w) == id)
+    }
+
+    fn window(&self) -> i64 {
+        (self.clock)().unix_timestamp().div_euclid(WINDOW_SECONDS)
+    }
+
+    fn id(&self, ip: IpAddr, window: i64) -> i64 {
+        let mut context = hmac::Context::with_key(&self.key);
+        match ip.to_canonical() {
+            IpAddr::V4(ip) => context.update(&ip.octets()),
+            IpAddr::V6(ip) => context.update(&ip.octets()),
+        }
+        context.update(&window.to_be_bytes());
+
+        let tag = context.sign();
+        i64::from_be_bytes(tag.as_ref()[..8].try_into().unwrap())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::sync::Mutex;
+    use time::Duration;
+
+    #[test]
+    fn ids_expire_and_stay_with_their_address() {
+        let now = Arc::new(Mutex::new(OffsetDateTime::now_utc()));
+        let clock = now.clone();
+        let ids = ConnectionIds::new().with_clock(move || *clock.lock().unwrap());
+        let ip = IpAddr::from([192, 0, 2, 1]);
+
+        let id = ids.issue(ip);
+        assert!(ids.check(ip, id));
+        assert!(!ids.check(IpAddr::from([192, 0, 2, 2]), id));
+        assert!(!ids.check(ip, id ^ 1));
+        assert!(!ConnectionIds::new().check(ip, id));
+
+        *now.lock().unwrap() += Duration::seconds(WINDOW_SECONDS);
+        assert!(ids.check(ip, id));
+        *now.lock().unwrap() += Duration::seconds(WINDOW_SECONDS);
+        assert!(!ids.check(ip, id));
+    }
+}
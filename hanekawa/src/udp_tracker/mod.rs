@@ -1,4 +1,8 @@
 // BEP 15 and BEP 41
 
+mod connection;
 mod extensions;
 pub mod proto;
+mod service;
+
+pub use service::UdpTrackerService;
@@ -1,5 +1,10 @@
 pub use super::extensions::Extension;
-use hanekawa_common::types::Event;
+use hanekawa_common::{
+    repository,
+    types::{Event, InfoHash, PeerId},
+};
+
+use std::net::SocketAddr;
 
 #[derive(Debug, Eq, PartialEq)]
 pub struct ConnectRequest {
@@ -15,8 +20,8 @@ pub struct ConnectResponse {
 pub struct AnnounceRequest {
     pub connection_id: i64,
     pub transaction_id: i32,
-    pub info_hash: String,
-    pub peer_id: String,
+    pub info_hash: InfoHash,
+    pub peer_id: PeerId,
     pub downloaded: i64,
     pub left: i64,
     pub uploaded: i64,
@@ -24,7 +29,7 @@ pub struct AnnounceRequest {
     pub ip_address: Option<i32>,
     pub key: i32,
     pub num_want: Option<i32>,
-    pub port: i16,
+    pub port: u16,
     pub extensions: Vec<Extension>,
 }
 
@@ -33,14 +38,15 @@ pub struct AnnounceResponse {
     pub interval: i32,
     pub leechers: i32,
     pub seeders: i32,
-    pub peers: Vec<(i32, i16)>,
+    // All peers are of the same address family as the announcing peer.
+    pub peers: Vec<SocketAddr>,
 }
 
 #[derive(Debug, Eq, PartialEq)]
 pub struct ScrapeRequest {
     pub connection_id: i64,
     pub transaction_id: i32,
-    pub info_hashes: Vec<String>,
+    pub info_hashes: Vec<InfoHash>,
 }
 
 pub struct InfoHashScrapeData {
@@ -63,17 +69,89 @@ pub enum Request {
 
 #[derive(Debug)]
 pub enum Error {
+    // Not from a connect by the same address in the last minute or two.
+    InvalidConnectionId,
+    InfoHashNotAllowed(String),
+    // Not served under the torrent policy.
+    NotRegistered,
+    Banned(String),
+    Maintenance(String),
+    // Passkeys only come with HTTP announce URLs.
+    PasskeyRequired,
+    // Seconds left before the peer may announce again.
+    TooSoon(u32),
+    // At most this many info hashes may be scraped at once.
+    TooManyInfoHashes(usize),
+    // From elsewhere than the peer, without its key.
+    KeyMismatch,
+    // Refused by the client policy, as its peer id fingerprints it.
+    ClientNotAllowed(String),
+    // At most this many peers are kept for one address.
+    TooManyPeers(u32),
+    // Seconds until the address may announce to another new torrent.
+    TooManyTorrents(u32),
+    // The store failed, which the peer may retry.
+    Storage(repository::Error),
     Other(()),
 }
 
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str("Other error")
+        match self {
+            Self::InvalidConnectionId => f.write_str("invalid connection id, connect again"),
+            Self::InfoHashNotAllowed(s) => f.write_fmt(format_args!("info hash not allowed: {s}")),
+            Self::NotRegistered => f.write_str("torrent not registered with this tracker"),
+            Self::Banned(s) => f.write_fmt(format_args!("banned: {s}")),
+            Self::Maintenance(s) => f.write_str(s),
+            Self::PasskeyRequired => f.write_str("passkey required, announce over HTTP"),
+            Self::TooSoon(n) => f.write_fmt(format_args!("announced too soon, wait {n} seconds")),
+            Self::TooManyInfoHashes(n) => {
+                f.write_fmt(format_args!("too many info hashes, at most {n}"))
+            }
+            Self::KeyMismatch => f.write_str("key does not match the peer's"),
+            Self::ClientNotAllowed(s) => f.write_fmt(format_args!("client not allowed: {s}")),
+            Self::TooManyPeers(n) => f.write_fmt(format_args!(
+                "too many peers from your address, at most {n}"
+            )),
+            Self::TooManyTorrents(n) => f.write_fmt(format_args!(
+                "too many new torrents from your address, retry in {n} seconds"
+            )),
+            Self::Storage(_) => f.write_str("tracker unavailable, try again later"),
+            Self::Other(_) => f.write_str("Other error"),
+        }
     }
 }
 
 impl std::error::Error for Error {}
 
+impl From<repository::Error> for Error {
+    fn from(e: repository::Error) -> Self {
+        Self::Storage(e)
+    }
+}
+
+impl Error {
+    // What kind of failure it is, for logs.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::InvalidConnectionId => "invalid_connection_id",
+            Self::InfoHashNotAllowed(_) => "info_hash_not_allowed",
+            Self::NotRegistered => "not_registered",
+            Self::Banned(_) => "banned",
+            Self::Maintenance(_) => "maintenance",
+            Self::PasskeyRequired => "passkey_required",
+            Self::TooSoon(_) => "too_soon",
+            Self::TooManyInfoHashes(_) => "too_many_info_hashes",
+            Self::KeyMismatch => "key_mismatch",
+            Self::ClientNotAllowed(_) => "client_not_allowed",
+            Self::TooManyPeers(_) => "too_many_peers",
+            Self::TooManyTorrents(_) => "too_many_torrents",
+            Self::Storage(_) => "storage",
+            Self::Other(_) => "other",
+        }
+    }
+}
+
 pub struct ErrorResponse {
     pub transaction_id: i32,
     pub message: String,
@@ -0,0 +1,780 @@
+use super::connection::ConnectionIds;
+use super::proto::{
+    AnnounceRequest, AnnounceResponse, ConnectRequest, ConnectResponse, Error, InfoHashScrapeData,
+    ScrapeRequest, ScrapeResponse,
+};
+use crate::{
+    interval::IntervalPolicy,
+    peer_selector::{PeerSelector, Requester},
+    task::UpdatePeerAnnounceTask,
+    trace,
+};
+
+use hanekawa_common::{
+    ban::BanCheck,
+    client::ClientFingerprint,
+    federation,
+    peer_limit::Refusal,
+    repository::{
+        info_hash::GetInfoHashSummary,
+        peer::{CheckPeerKey, GetPeerStatistics, GetPeers, UpdatePeerAnnounce},
+    },
+    types::{Event, InfoHash, InfoHashStatus, PeerId, PeerKey, Transport},
+    Config, KeyMismatch, Services,
+};
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+use tracing::{field::Empty, Instrument};
+
+// action, transaction_id, interval, leechers, seeders
+const ANNOUNCE_HEADER_SIZE: usize = 20;
+
+const IPV4_PEER_SIZE: usize = 6;
+const IPV6_PEER_SIZE: usize = 18;
+
+#[derive(Clone)]
+pub struct UdpTrackerService {
+    config: Config,
+    services: Services,
+    selector: PeerSelector,
+    intervals: IntervalPolicy,
+    connection_ids: ConnectionIds,
+}
+
+impl UdpTrackerService {
+    pub fn new(config: &Config, services: Services) -> Self {
+        let clock = services.clock.clone();
+        Self {
+            config: config.clone(),
+            services,
+            selector: PeerSelector::new(config),
+            intervals: IntervalPolicy::new(config).with_clock(move || clock()),
+            connection_ids: ConnectionIds::new(),
+        }
+    }
+
+    // The number of peers that fit in a single reply without exceeding the
+    // configured packet size, so replies are trimmed rather than fragmented.
+    fn max_peers_per_packet(&self, sender: &SocketAddr) -> usize {
+        let peer_size = match sender {
+            SocketAddr::V4(_) => IPV4_PEER_SIZE,
+            SocketAddr::V6(_) => IPV6_PEER_SIZE,
+        };
+
+        self.config
+            .udp_max_packet_size
+            .saturating_sub(ANNOUNCE_HEADER_SIZE)
+            / peer_size
+    }
+
+    pub fn connect(&self, connect: ConnectRequest, sender: SocketAddr) -> ConnectResponse {
+        ConnectResponse {
+            transaction_id: connect.transaction_id,
+            connection_id: self.connection_ids.issue(sender.ip()),
+        }
+    }
+
+    pub async fn announce(
+        &self,
+        announce: AnnounceRequest,
+        sender: SocketAddr,
+    ) -> Result<AnnounceResponse, Error> {
+        let span = tracing::info_span!(
+            "announce",
+            transport = "udp",
+            info_hash = %announce.info_hash.to_hex(),
+            peer_id = trace::client(&announce.peer_id),
+            event = %announce.event.clone().unwrap_or_default().to_string(),
+            ip = %trace::ip(&self.config, sender.ip()),
+            key = trace::redacted(Some(announce.key)),
+            peers = Empty,
+            failure = Empty,
+        );
+
+        async {
+            let response = self.answer_announce(announce, sender).await;
+            if let Err(e) = &response {
+                trace::failed(e.kind(), e);
+            }
+            response
+        }
+        .instrument(span)
+        .await
+    }
+
+    async fn answer_announce(
+        &self,
+        announce: AnnounceRequest,
+        sender: SocketAddr,
+    ) -> Result<AnnounceResponse, Error> {
+        self.check_connection(announce.connection_id, sender)?;
+        let peer_ip = self.peer_ip(&announce, sender);
+        // Bans of the address it sends from hold whatever address it gives.
+        for ip in [sender.ip(), peer_ip] {
+            self.check_bans(BanCheck {
+                ip,
+                peer_id: Some(&announce.peer_id),
+                passkey: None,
+            })?;
+        }
+        self.check_client(&announce.peer_id)?;
+        self.check_passkey()?;
+        self.check_maintenance(announce.event.as_ref())?;
+        self.intervals
+            .admit(
+                &announce.info_hash,
+                &announce.peer_id,
+                &announce.event.clone().unwrap_or_default(),
+            )
+            .map_err(Error::TooSoon)?;
+
+        if !self.services.torrent_policy.allows(&announce.info_hash) {
+            return Err(Error::NotRegistered);
+        }
+
+        let info_hash_summary = self
+            .services
+            .info_hash_repository
+            .get_info_hash_summary(GetInfoHashSummary {
+                info_hash: &announce.info_hash,
+            })
+            .await?;
+
+        if info_hash_summary.status == InfoHashStatus::ExplicitDeny
+            || (self.config.only_allowed_info_hashes
+                && info_hash_summary.status != InfoHashStatus::ExplicitAllow)
+        {
+            let st = info_hash_summary.info_hash.to_hex();
+            return Err(Error::InfoHashNotAllowed(st));
+        }
+
+        let cmd = UpdatePeerAnnounce {
+            info_hash: announce.info_hash.clone(),
+            peer_id: announce.peer_id.clone(),
+            ip: peer_ip,
+            port: announce.port,
+            uploaded: announce.uploaded as u64,
+            downloaded: announce.downloaded as u64,
+            left: announce.left as u64,
+            event: announce.event.clone().unwrap_or_default(),
+            update_timestamp: (self.services.clock)(),
+            other_endpoint: None,
+            transport: Some(Transport::Udp),
+            user_id: None,
+            // As HTTP clients send theirs, in hex.
+            key: Some(PeerKey(format!("{:08x}", announce.key as u32))),
+        };
+
+        if self.check_key(&cmd).await? && self.check_limits(&cmd).await? {
+            // Probed while the peer is around, which the prober limits to
+            // once in a while.
+            if let Some(prober) = &self.services.prober {
+                if cmd.event != Event::Stopped {
+                    prober.submit(SocketAddr::new(cmd.ip, cmd.port));
+                }
+            }
+            if let Some(federation) = &self.services.federation {
+                federation.forward(&cmd);
+            }
+
+            self.services
+                .task_queue
+                .enqueue(&UpdatePeerAnnounceTask { cmd })
+                .await;
+        }
+
+        let active_after = (self.services.clock)()
+            - std::time::Duration::from_secs(self.config.activity_timeout() as u64);
+
+        // A peer on its way out has no use for others.
+        let requested = match announce.event {
+            Some(Event::Stopped) => Some(0),
+            _ => announce.num_want.map(|n| n.max(0) as u32),
+        };
+        let num_want = self
+            .selector
+            .num_want(requested)
+            .min(self.max_peers_per_packet(&sender));
+        let peers = match num_want {
+            0 => vec![],
+            _ => {
+                self.peers(&announce, peer_ip, sender, active_after, num_want)
+                    .await?
+            }
+        };
+        trace::answered(peers.len());
+
+        let stats = self
+            .services
+            .peer_repository
+            .get_peer_statistics(GetPeerStatistics {
+                info_hashes: std::slice::from_ref(&announce.info_hash),
+                active_after,
+            })
+            .await?
+            .remove(&announce.info_hash)
+            .unwrap_or_default();
+        // There is no min interval in BEP 15.
+        let intervals = self.intervals.interval(
+            &announce.info_hash,
+            &announce.peer_id,
+            &announce.event.unwrap_or_default(),
+            stats.complete + stats.incomplete,
+        );
+
+        Ok(AnnounceResponse {
+            transaction_id: announce.transaction_id,
+            interval: intervals.interval as i32,
+            leechers: stats.incomplete as i32,
+            seeders: stats.complete as i32,
+            peers,
+        })
+    }
+
+    // Only of the sender's address family, as no other can be represented
+    // in the reply.
+    async fn peers(
+        &self,
+        announce: &AnnounceRequest,
+        peer_ip: IpAddr,
+        sender: SocketAddr,
+        active_after: time::OffsetDateTime,
+        num_want: usize,
+    ) -> Result<Vec<SocketAddr>, Error> {
+        let peers = self
+            .services
+            .peer_repository
+            .get_peers(GetPeers {
+                info_hash: &announce.info_hash,
+                active_after: Some(active_after),
+            })
+            .await?;
+        let peers = match &self.services.federation {
+            Some(federation) => federation::merge(peers, federation.peers(&announce.info_hash)),
+            None => peers,
+        };
+
+        let peers = peers
+            .into_iter()
+            .filter(|p| p.ip.is_ipv4() == sender.is_ipv4())
+            .collect();
+        let requester = Requester {
+            peer_id: &announce.peer_id,
+            endpoint: SocketAddr::new(peer_ip, announce.port),
+            seeding: announce.left == 0,
+        };
+
+        Ok(self
+            .selector
+            .select(peers, &requester, num_want)
+            .into_iter()
+            .map(|p| match p.ip {
+                IpAddr::V4(ip) => SocketAddr::V4(SocketAddrV4::new(ip, p.port)),
+                IpAddr::V6(ip) => SocketAddr::V6(SocketAddrV6::new(ip, p.port, 0, 0)),
+            })
+            .collect())
+    }
+
+    pub async fn scrape(
+        &self,
+        scrape: ScrapeRequest,
+        sender: SocketAddr,
+    ) -> Result<ScrapeResponse, Error> {
+        let span = tracing::info_span!(
+            "scrape",
+            transport = "udp",
+            info_hash = scrape.info_hashes.first().map(InfoHash::to_hex),
+            info_hashes = scrape.info_hashes.len(),
+            ip = %trace::ip(&self.config, sender.ip()),
+            failure = Empty,
+        );
+
+        async {
+            let response = self.answer_scrape(scrape, sender).await;
+            if let Err(e) = &response {
+                trace::failed(e.kind(), e);
+            }
+            response
+        }
+        .instrument(span)
+        .await
+    }
+
+    async fn answer_scrape(
+        &self,
+        scrape: ScrapeRequest,
+        sender: SocketAddr,
+    ) -> Result<ScrapeResponse, Error> {
+        self.check_connection(scrape.connection_id, sender)?;
+        self.check_bans(BanCheck {
+            ip: sender.ip(),
+            peer_id: None,
+            passkey: None,
+        })?;
+        self.check_passkey()?;
+        self.check_maintenance(None)?;
+
+        let max = self.config.scrape.max_info_hashes;
+        if scrape.info_hashes.len() > max {
+            return Err(Error::TooManyInfoHashes(max));
+        }
+
+        let active_after = (self.services.clock)()
+            - std::time::Duration::from_secs(self.config.activity_timeout() as u64);
+        let policy = &self.services.torrent_policy;
+        let allowed = scrape
+            .info_hashes
+            .iter()
+            .filter(|info_hash| policy.allows(info_hash))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let mut stats = self
+            .services
+            .peer_repository
+            .get_peer_statistics(GetPeerStatistics {
+                info_hashes: &allowed,
+                active_after,
+            })
+            .await?;
+
+        // Entries are matched to the request by position, so torrents not
+        // served are left in, with zeros.
+        let data = scrape
+            .info_hashes
+            .iter()
+            .map(|info_hash| {
+                let stats = stats.remove(info_hash).unwrap_or_default();
+
+                InfoHashScrapeData {
+                    seeders: stats.complete as i32,
+                    completed: stats.downloaded as i32,
+                    leechers: stats.incomplete as i32,
+                }
+            })
+            .collect();
+
+        Ok(ScrapeResponse {
+            transaction_id: scrape.transaction_id,
+            data,
+        })
+    }
+
+    // The address field stands for the peer's when another tracker forwards
+    // its announce, or as configured.
+    fn peer_ip(&self, announce: &AnnounceRequest, sender: SocketAddr) -> IpAddr {
+        let trusted = &self.config.federation.trusted_forwarders;
+        match announce
+            .ip_address
+            .map(|ip| IpAddr::V4(Ipv4Addr::from(ip as u32)))
+        {
+            Some(ip) if trusted.contains(&sender.ip()) || self.config.announced_ip.accepts(ip) => {
+                ip
+            }
+            _ => sender.ip(),
+        }
+    }
+
+    fn check_connection(&self, connection_id: i64, sender: SocketAddr) -> Result<(), Error> {
+        match self.connection_ids.check(sender.ip(), connection_id) {
+            true => Ok(()),
+            false => Err(Error::InvalidConnectionId),
+        }
+    }
+
+    // Whether the announce is the peer's to record. Those from elsewhere
+    // without its key are not, and are rejected if so configured.
+    async fn check_key(&self, cmd: &UpdatePeerAnnounce) -> Result<bool, Error> {
+        let matches = self
+            .services
+            .peer_repository
+            .check_peer_key(CheckPeerKey {
+                info_hash: &cmd.info_hash,
+                peer_id: &cmd.peer_id,
+                ip: cmd.ip,
+                key: cmd.key.as_ref(),
+            })
+            .await?;
+
+        match (matches, self.config.key_mismatch) {
+            (true, _) => Ok(true),
+            (false, KeyMismatch::Ignore) => Ok(false),
+            (false, KeyMismatch::Reject) => Err(Error::KeyMismatch),
+        }
+    }
+
+    // Whether the announce is to be recorded under the peer limits. Those
+    // that would go past the swarm's are answered all the same.
+    async fn check_limits(&self, cmd: &UpdatePeerAnnounce) -> Result<bool, Error> {
+        let repository = &*self.services.peer_repository;
+        match self.services.peer_limits.admit(repository, cmd).await {
+            Ok(keep) => Ok(keep),
+            Err(Refusal::TooManyPeers(n)) => Err(Error::TooManyPeers(n)),
+            Err(Refusal::TooManyTorrents(seconds)) => Err(Error::TooManyTorrents(seconds)),
+            Err(Refusal::Storage(e)) => Err(Error::Storage(e)),
+        }
+    }
+
+    fn check_client(&self, peer_id: &PeerId) -> Result<(), Error> {
+        let policy = &self.config.clients;
+        if policy.allow.is_empty() && policy.deny.is_empty() {
+            return Ok(());
+        }
+
+        let client = ClientFingerprint::of(peer_id);
+        match policy.admits(&client) {
+            true => Ok(()),
+            false => Err(Error::ClientNotAllowed(client.to_string())),
+        }
+    }
+
+    fn check_bans(&self, check: BanCheck<'_>) -> Result<(), Error> {
+        match self.services.bans.find(check) {
+            Some(ban) => Err(Error::Banned(ban.reason)),
+            None => Ok(()),
+        }
+    }
+
+    fn check_passkey(&self) -> Result<(), Error> {
+        match self.config.passkeys.required {
+            true => Err(Error::PasskeyRequired),
+            false => Ok(()),
+        }
+    }
+
+    // Only peers leaving get through while the tracker is drained. UDP has
+    // no way to say when to come back.
+    fn check_maintenance(&self, event: Option<&Event>) -> Result<(), Error> {
+        match self.services.maintenance.is_enabled() && event != Some(&Event::Stopped) {
+            true => Err(Error::Maintenance(self.config.maintenance.reason.clone())),
+            false => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use hanekawa_common::{
+        audit::AuditLog,
+        ban::BanList,
+        maintenance::Maintenance,
+        offense::Offenders,
+        passkey::Passkeys,
+        peer_limit::PeerLimits,
+        repository::{
+            info_hash::{InfoHashRepository, RegisterTorrent, UpdateInfoHash},
+            memory::MemoryStore,
+            Error as RepositoryError,
+        },
+        task::{Task, TaskQueue},
+        torrent_policy::TorrentPolicy,
+        types::{InfoHash, InfoHashSummary, Peer, PeerId, PeerSource},
+        InvalidRequestConfig,
+    };
+    use std::{
+        net::{IpAddr, Ipv4Addr},
+        sync::{Arc, Mutex},
+    };
+    use tracing_subscriber::fmt::MakeWriter;
+
+    struct Unreachable;
+
+    #[async_trait::async_trait]
+    impl InfoHashRepository for Unreachable {
+        async fn get_info_hash_summary(
+            &self,
+            _cmd: GetInfoHashSummary<'_>,
+        ) -> Result<InfoHashSummary, RepositoryError> {
+            Err(RepositoryError::Backend("connection refused".to_string()))
+        }
+
+        async fn update_info_hash(&self, _cmd: UpdateInfoHash<'_>) -> Result<(), RepositoryError> {
+            Err(RepositoryError::Backend("connection refused".to_string()))
+        }
+
+        async fn register_torrent(&self, _cmd: RegisterTorrent<'_>) -> Result<(), RepositoryError> {
+            Err(RepositoryError::Backend("connection refused".to_string()))
+        }
+    }
+
+    // What is logged, once the subscriber writing to it is installed.
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'a> MakeWriter<'a> for Captured {
+        type Writer = Self;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    struct DiscardingQueue;
+
+    #[async_trait::async_trait]
+    impl TaskQueue for DiscardingQueue {
+        async fn enqueue(&self, _task: &dyn Task) -> Option<()> {
+            Some(())
+        }
+    }
+
+    fn config() -> Config {
+        Config {
+            database_pool_size: 1,
+            http_bind_port: 0,
+            udp_bind_port: 0,
+            peer_announce_interval: 60,
+            peer_min_announce_interval: 30,
+            interval_jitter_percent: 0,
+            peer_activity_timeout: Some(120),
+            max_num_want: 1000,
+            ..Config::default()
+        }
+    }
+
+    fn service(swarm_size: u32) -> UdpTrackerService {
+        let peers: Vec<_> = (0..swarm_size)
+            .map(|i| Peer {
+                peer_id: PeerId(format!("{:020}", i).into_bytes()),
+                ip: IpAddr::V4(Ipv4Addr::from(0x0a00_0000 + i)),
+                port: 6881,
+                connectable: None,
+                seeding: false,
+                source: PeerSource::Announce,
+            })
+            .collect();
+
+        // Leeching every torrent scraped below.
+        let store = Arc::new(MemoryStore::new());
+        let now = time::OffsetDateTime::now_utc();
+        for info_hash in [0, 1, 2].map(|b| InfoHash(vec![b; 20])) {
+            for peer in &peers {
+                store.seed(&info_hash, peer.clone(), 100, now);
+            }
+        }
+
+        let services = Services {
+            peer_repository: store.clone(),
+            info_hash_repository: store,
+            task_queue: Arc::new(DiscardingQueue),
+            bans: BanList::in_memory(),
+            audit: AuditLog::in_memory(),
+            prober: None,
+            federation: None,
+            offenders: Offenders::new(&InvalidRequestConfig::default()),
+            maintenance: Maintenance::default(),
+            passkeys: Passkeys::in_memory(),
+            torrent_policy: TorrentPolicy::open(),
+            peer_limits: PeerLimits::unlimited(),
+            clock: hanekawa_common::system_clock(),
+        };
+
+        UdpTrackerService::new(&config(), services)
+    }
+
+    fn connection_id(service: &UdpTrackerService, sender: SocketAddr) -> i64 {
+        service
+            .connect(ConnectRequest { transaction_id: 0 }, sender)
+            .connection_id
+    }
+
+    fn announce(service: &UdpTrackerService, num_want: Option<i32>) -> AnnounceRequest {
+        AnnounceRequest {
+            connection_id: connection_id(service, sender()),
+            transaction_id: 42,
+            info_hash: InfoHash(vec![0; 20]),
+            peer_id: PeerId(vec![1; 20]),
+            downloaded: 0,
+            left: 100,
+            uploaded: 0,
+            event: None,
+            ip_address: None,
+            key: 0,
+            num_want,
+            port: 6881,
+            extensions: vec![],
+        }
+    }
+
+    fn sender() -> SocketAddr {
+        "192.168.0.1:6881".parse().unwrap()
+    }
+
+    #[tokio::test]
+    async fn caps_peers_to_fit_packet_size() {
+        let service = service(500);
+        let response = service
+            .announce(announce(&service, Some(1000)), sender())
+            .await
+            .unwrap();
+
+        let reply_len = ANNOUNCE_HEADER_SIZE + response.peers.len() * IPV4_PEER_SIZE;
+        assert!(reply_len <= 1200, "reply of {reply_len} bytes is too large");
+        assert_eq!(
+            (1200 - ANNOUNCE_HEADER_SIZE) / IPV4_PEER_SIZE,
+            response.peers.len()
+        );
+        assert_eq!(42, response.transaction_id);
+        assert_eq!(500, response.leechers + response.seeders);
+    }
+
+    #[tokio::test]
+    async fn honors_num_want() {
+        let service = service(500);
+        let response = service
+            .announce(announce(&service, Some(10)), sender())
+            .await
+            .unwrap();
+
+        assert_eq!(10, response.peers.len());
+    }
+
+    #[tokio::test]
+    async fn uses_default_num_want_if_unspecified() {
+        let service = service(500);
+        let response = service
+            .announce(announce(&service, None), sender())
+            .await
+            .unwrap();
+
+        assert_eq!(50, response.peers.len());
+    }
+
+    #[tokio::test]
+    async fn excludes_peers_of_other_address_families() {
+        let sender = "[2001:db8::1]:6881".parse().unwrap();
+        let service = service(500);
+        let request = AnnounceRequest {
+            connection_id: connection_id(&service, sender),
+            ..announce(&service, None)
+        };
+        let response = service.announce(request, sender).await.unwrap();
+
+        assert!(response.peers.is_empty());
+    }
+
+    #[tokio::test]
+    async fn scrapes_in_request_order() {
+        let service = service(3);
+        let response = service
+            .scrape(
+                ScrapeRequest {
+                    connection_id: connection_id(&service, sender()),
+                    transaction_id: 7,
+                    info_hashes: vec![InfoHash(vec![1; 20]), InfoHash(vec![2; 20])],
+                },
+                sender(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(7, response.transaction_id);
+        assert_eq!(2, response.data.len());
+        assert!(response
+            .data
+            .iter()
+            .all(|d| d.leechers == 3 && d.seeders == 0));
+    }
+
+    #[tokio::test]
+    async fn wants_a_connection_id_from_the_same_address() {
+        let service = service(3);
+        let request = AnnounceRequest {
+            connection_id: connection_id(&service, "192.168.0.2:6881".parse().unwrap()),
+            ..announce(&service, None)
+        };
+        assert!(matches!(
+            service.announce(request, sender()).await,
+            Err(Error::InvalidConnectionId)
+        ));
+
+        let scrape = ScrapeRequest {
+            connection_id: 0,
+            transaction_id: 7,
+            info_hashes: vec![InfoHash(vec![1; 20])],
+        };
+        assert!(matches!(
+            service.scrape(scrape, sender()).await,
+            Err(Error::InvalidConnectionId)
+        ));
+    }
+
+    #[tokio::test]
+    async fn caps_info_hashes_per_scrape() {
+        let service = service(3);
+        let max = service.config.scrape.max_info_hashes;
+        let scrape = ScrapeRequest {
+            connection_id: connection_id(&service, sender()),
+            transaction_id: 7,
+            info_hashes: vec![InfoHash(vec![1; 20]); max + 1],
+        };
+        assert!(matches!(
+            service.scrape(scrape, sender()).await,
+            Err(Error::TooManyInfoHashes(n)) if n == max
+        ));
+    }
+
+    #[tokio::test]
+    async fn answers_storage_errors() {
+        let mut service = service(3);
+        service.services.info_hash_repository = Arc::new(Unreachable);
+        let request = announce(&service, None);
+        let Err(error) = service.announce(request, sender()).await else {
+            panic!("announce answered without the store");
+        };
+
+        assert!(matches!(error, Error::Storage(_)));
+        assert_eq!("tracker unavailable, try again later", error.to_string());
+    }
+
+    #[tokio::test]
+    async fn logs_refused_announces_without_their_keys() {
+        let captured = Captured::default();
+        let subscriber = tracing_subscriber::fmt()
+            .json()
+            .flatten_event(true)
+            .with_current_span(true)
+            .with_writer(captured.clone())
+            .finish();
+        let _default = tracing::subscriber::set_default(subscriber);
+
+        let service = service(3);
+        let request = AnnounceRequest {
+            connection_id: 0,
+            peer_id: PeerId(b"-qB4650-abcdefghijkl".to_vec()),
+            key: 0x5ec7e7,
+            event: Some(Event::Started),
+            ..announce(&service, None)
+        };
+        assert!(service.announce(request, sender()).await.is_err());
+
+        let logged = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        let event: serde_json::Value =
+            serde_json::from_str(logged.lines().next().unwrap()).unwrap();
+        assert_eq!("INFO", event["level"]);
+        assert_eq!("invalid_connection_id", event["failure"]);
+        assert_eq!("invalid connection id, connect again", event["reason"]);
+        let span = &event["span"];
+        assert_eq!("announce", span["name"]);
+        assert_eq!("udp", span["transport"]);
+        assert_eq!("00".repeat(20), span["info_hash"]);
+        assert_eq!("-qB4650-", span["peer_id"]);
+        assert_eq!("started", span["event"]);
+        assert_eq!("192.168.0.1", span["ip"]);
+        assert_eq!("[redacted]", span["key"]);
+        assert!(!logged.contains("abcdefghijkl") && !logged.contains("5ec7e7"));
+    }
+}